@@ -103,12 +103,42 @@ class ExoVulkanManager(private val context: Context) {
         }
     }
 
+    /**
+     * Open a client namespace for a session or Activity; see VulkanGpu.openClient().
+     */
+    fun openClient(): GpuResult<String> = lock.read {
+        try {
+            if (!isInitialized) {
+                return GpuResult.Failure("Not initialized")
+            }
+            GpuResult.Success(VulkanGpu.openClient())
+        } catch (e: Exception) {
+            Log.e(TAG, "Open client failed", e)
+            GpuResult.Failure("Open client failed: ${e.message}", e)
+        }
+    }
+
+    /**
+     * Close a client namespace, freeing all of its handles.
+     */
+    fun closeClient(clientId: String): GpuResult<Int> = lock.read {
+        try {
+            val freed = VulkanGpu.closeClient(clientId)
+            Log.d(TAG, "Closed client $clientId, freed $freed handles")
+            GpuResult.Success(freed)
+        } catch (e: Exception) {
+            Log.e(TAG, "Close client failed", e)
+            GpuResult.Failure("Close client failed: ${e.message}", e)
+        }
+    }
+
     /**
      * Allocate memory on specific device.
      */
     suspend fun allocateMemory(
         deviceIndex: Int,
-        sizeBytes: Long
+        sizeBytes: Long,
+        clientId: String? = null
     ): GpuResult<String> = withContext(Dispatchers.Default) {
         lock.read {
             try {
@@ -122,7 +152,7 @@ class ExoVulkanManager(private val context: Context) {
                     return@withContext GpuResult.Failure("Invalid size: $sizeBytes")
                 }
 
-                val handleId = VulkanGpu.allocateMemory(deviceIndex, sizeBytes)
+                val handleId = VulkanGpu.allocateMemory(deviceIndex, sizeBytes, clientId)
                 Log.d(TAG, "Allocated $sizeBytes bytes on device $deviceIndex: $handleId")
 
                 GpuResult.Success(handleId)
@@ -136,10 +166,13 @@ class ExoVulkanManager(private val context: Context) {
     /**
      * Free allocated memory.
      */
-    suspend fun freeMemory(handleId: String): GpuResult<Boolean> = withContext(Dispatchers.Default) {
+    suspend fun freeMemory(
+        handleId: String,
+        clientId: String? = null
+    ): GpuResult<Boolean> = withContext(Dispatchers.Default) {
         lock.read {
             try {
-                val success = VulkanGpu.freeMemory(handleId)
+                val success = VulkanGpu.freeMemory(handleId, clientId)
                 if (success) {
                     Log.d(TAG, "Freed memory: $handleId")
                 }
@@ -156,11 +189,12 @@ class ExoVulkanManager(private val context: Context) {
      */
     suspend fun copyToDevice(
         handleId: String,
-        data: ByteArray
+        data: ByteArray,
+        clientId: String? = null
     ): GpuResult<Boolean> = withContext(Dispatchers.Default) {
         lock.read {
             try {
-                val success = VulkanGpu.copyToDevice(handleId, data)
+                val success = VulkanGpu.copyToDevice(handleId, data, clientId)
                 if (success) {
                     Log.d(TAG, "Copied ${data.size} bytes to device: $handleId")
                 }
@@ -177,11 +211,12 @@ class ExoVulkanManager(private val context: Context) {
      */
     suspend fun copyFromDevice(
         handleId: String,
-        sizeBytes: Long
+        sizeBytes: Long,
+        clientId: String? = null
     ): GpuResult<ByteArray> = withContext(Dispatchers.Default) {
         lock.read {
             try {
-                val data = VulkanGpu.copyFromDevice(handleId, sizeBytes)
+                val data = VulkanGpu.copyFromDevice(handleId, sizeBytes, clientId)
                     ?: return@withContext GpuResult.Failure("Copy returned null")
 
                 Log.d(TAG, "Copied ${data.size} bytes from device: $handleId")
@@ -1,7 +1,7 @@
 package com.exo.gpu
 
 import android.util.Log
-import java.nio.ByteBuffer
+import ByteBuffer
 
 /**
  * JNI interface to Vulkan GPU operations.
@@ -10,14 +10,47 @@ import java.nio.ByteBuffer
 object VulkanGpu {
     private const val TAG = "VulkanGpu"
 
+    /**
+     * Native library version this wrapper was written against.
+     * Must match exo_jni_binding's `version::native_version()`.
+     */
+    const val EXPECTED_NATIVE_VERSION = "0.0.1+abi.2"
+
+    /**
+     * System property naming the class the native library binds to.
+     * Set to this object's runtime name so the natives still resolve after
+     * R8 or shading relocates it; only method names must be kept.
+     */
+    private const val JNI_CLASS_PROPERTY = "exo.gpu.jniClass"
+
     init {
         try {
+            System.setProperty(JNI_CLASS_PROPERTY, VulkanGpu::class.java.name.replace('.', '/'))
             System.loadLibrary("exo_jni_binding")
             Log.i(TAG, "Successfully loaded exo_jni_binding library")
         } catch (e: UnsatisfiedLinkError) {
             Log.e(TAG, "Failed to load exo_jni_binding: ${e.message}")
             throw RuntimeException("Could not load native Vulkan library", e)
         }
+        checkNativeVersion()
+    }
+
+    /**
+     * Fail fast if the packaged native library does not match this wrapper.
+     * @throws IllegalStateException on version mismatch
+     */
+    private fun checkNativeVersion() {
+        val nativeVersion = try {
+            getNativeVersion()
+        } catch (e: UnsatisfiedLinkError) {
+            throw IllegalStateException("Native library predates version checks", e)
+        }
+        if (nativeVersion != EXPECTED_NATIVE_VERSION) {
+            Log.e(TAG, "Native version $nativeVersion != expected $EXPECTED_NATIVE_VERSION")
+            throw IllegalStateException(
+                "exo_jni_binding version mismatch: native=$nativeVersion, wrapper=$EXPECTED_NATIVE_VERSION"
+            )
+        }
     }
 
     // ============ Device Management ============
@@ -28,15 +61,45 @@ object VulkanGpu {
      */
     external fun initializeVulkan(): Boolean
 
+    /**
+     * Load Vulkan from an explicit library instead of the system driver,
+     * e.g. an updated driver shipped with the app. Call before
+     * initializeVulkan(), or after shutdown() to switch drivers.
+     * @param path Absolute path to the library, or null for the system loader
+     * @return true if the override was applied
+     * @throws IllegalStateException if Vulkan is initialized or the path is invalid
+     */
+    @Throws(IllegalStateException::class)
+    external fun setVulkanLoaderPath(path: String?): Boolean
+
     /**
      * Get list of available Vulkan devices in JSON format.
-     * JSON structure: [{"device_id": "...", "name": "...", "vendor": "...", "memory_bytes": 12345}]
+     * JSON structure: {"devices": [{"device_id": "vulkan:0", "stable_id": "...", "online": true, "name": "...",
+     *   "vendor": "...", "vendor_id": 20803, "driver_version": "...", "api_version": "1.3.128",
+     *   "max_allocation_size": 4294967296, "memory_bytes": 12345, "compute_units": 8,
+     *   "compute_units_source": "estimated", "subgroup_size": 64, "max_clock_mhz": 900,
+     *   "bandwidth_gbps": 32.5}], "count": 1, "online": 1}
+     * compute_units_source names the vendor extension that reported the count, or "estimated";
+     * max_clock_mhz is null where the kernel does not publish GPU frequencies.
+     * Device indices are stable for the life of the process: a device that disappears keeps its
+     * index with "online": false, and calls naming it fail until it returns.
      * @return JSON string of devices, or null on error
      * @throws RuntimeException if JNI call fails
      */
     @Throws(RuntimeException::class)
     external fun enumerateDevices(): String
 
+    /**
+     * Re-enumerate devices on a fresh Vulkan instance after a GPU is attached or removed
+     * (eGPU, driver restart). Known devices keep their indices, vanished ones go offline and
+     * new ones get the next free index. Each change is reported by [pollEvents] as
+     * {"kind": "device_status", "device_index": 1, "device_id": "...", "status": "added" | "offline" | "online"}
+     * @return JSON string of devices as [enumerateDevices]
+     * @throws RuntimeException if Vulkan cannot be re-initialized
+     */
+    @Throws(RuntimeException::class)
+    external fun rescanDevices(): String
+
     /**
      * Get device name by index.
      * @param deviceIndex 0-based device index
@@ -56,35 +119,194 @@ object VulkanGpu {
     external fun getDeviceMemory(deviceIndex: Int): Long
 
     /**
-     * Get device compute units (SM/EU count).
+     * Get device compute units (CU/SM/shader core count), estimated when no vendor extension reports it.
      * @param deviceIndex 0-based device index
-     * @return Number of compute units
+     * @return Number of compute units, or 0 if device not found
      * @throws RuntimeException if device not found
      */
     @Throws(RuntimeException::class)
     external fun getComputeUnits(deviceIndex: Int): Int
 
+    /**
+     * Get a device's driver version, in the vendor's own encoding.
+     * @param deviceIndex 0-based device index
+     * @return Driver version number as a string
+     * @throws IllegalArgumentException if device not found
+     */
+    @Throws(IllegalArgumentException::class)
+    external fun getDriverVersion(deviceIndex: Int): String
+
+    /**
+     * Get a device's PCI vendor ID, e.g. 0x5143 for Qualcomm or 0x13B5 for ARM.
+     * @param deviceIndex 0-based device index
+     * @return Vendor ID, or 0 if device not found
+     */
+    external fun getVendorId(deviceIndex: Int): Int
+
+    /**
+     * Get the highest Vulkan version a device supports.
+     * @param deviceIndex 0-based device index
+     * @return Version as "major.minor.patch"
+     * @throws IllegalArgumentException if device not found
+     */
+    @Throws(IllegalArgumentException::class)
+    external fun getApiVersion(deviceIndex: Int): String
+
+    /**
+     * Get the largest single allocation a device's driver accepts; larger tensors must be split.
+     * @param deviceIndex 0-based device index
+     * @return Size in bytes, or 0 if device not found
+     */
+    external fun getMaxAllocationSize(deviceIndex: Int): Long
+
+    /**
+     * Get a device's capability report. With cache_dir set via applyConfig, the
+     * report and kernel autotune results are cached per device and reused until
+     * the GPU driver version changes (e.g. after a system update).
+     * JSON structure: {"cached": true, "capabilities": {"name": "...", "vendor_id": 5045, "device_id": 0,
+     *   "driver_version": 0, "api_version": "1.1.0", "max_shared_memory": 32768, "max_invocations": 512,
+     *   "queue_families": 2, "features": {"shaderInt16": true, ...}, "extensions": ["VK_KHR_..."]}}
+     * @param deviceIndex 0-based device index
+     * @return JSON report
+     * @throws RuntimeException if the device is not found or Vulkan is unavailable
+     */
+    @Throws(RuntimeException::class)
+    external fun getDeviceCapabilities(deviceIndex: Int): String
+
+    /**
+     * Measure a device's memory bandwidth by timing buffer copies (a few hundred ms).
+     * Runs once per device; the result is cached like [getDeviceCapabilities] and reported as
+     * "bandwidth_gbps" by later [enumerateDevices] calls, which estimate it until then.
+     * @param deviceIndex 0-based device index
+     * @return Copy bandwidth in GB/s (bytes read plus written)
+     * @throws RuntimeException if the device is not found or the benchmark fails
+     */
+    @Throws(RuntimeException::class)
+    external fun benchmarkBandwidth(deviceIndex: Int): Float
+
+    /**
+     * Time the ops of a transformer layer at 1B-13B model sizes: decode and prefill GEMMs,
+     * attention over 512-4096 cached tokens, int8 dequantization and KV cache appends.
+     * Takes seconds on a desktop GPU and up to a minute on a phone; call off the main thread.
+     * Timings are stored in the capability cache as "bench.<name>" microseconds when a cache
+     * directory is configured. Cases too large for the device report an "error" instead.
+     * @param deviceIndex 0-based device index
+     * @param ops Comma-separated subset of "gemm", "attention", "dequant" and "kv_append", or null for all
+     * @return JSON report: device, driver_version, decode_weight_gbps and per-case results with
+     *         time_us, us_per_op, gflops and gbps
+     * @throws RuntimeException if the device is not found, an op is unknown or the device cannot be set up
+     * @throws UnsupportedOperationException if built without the `kernels-core` feature
+     */
+    @Throws(RuntimeException::class, UnsupportedOperationException::class)
+    external fun runBenchSuite(deviceIndex: Int, ops: String?): String
+
+    /**
+     * Run small reference models (an MLP block, an attention head) through the graph executor
+     * and compare their outputs with stored golden values. Run after a GPU driver update to
+     * confirm the kernels still compute what they did; a failing output names the element
+     * furthest from its golden value.
+     * @param deviceIndex 0-based device index
+     * @return JSON report: device, driver_version, passed, and per fixture its outputs with
+     *         passed, max_error (relative to 1 + |golden|) and worst_index, or an error
+     * @throws RuntimeException if the device is not found or cannot be set up
+     * @throws UnsupportedOperationException if built without the `kernels-core` feature
+     */
+    @Throws(RuntimeException::class, UnsupportedOperationException::class)
+    external fun runRegressionTests(deviceIndex: Int): String
+
     // ============ Memory Management ============
 
+    /**
+     * Open a client namespace, e.g. per gRPC session or Activity.
+     * Handles allocated with its ID can only be used and freed by that client.
+     * @return Client ID string
+     * @throws RuntimeException on failure
+     */
+    @Throws(RuntimeException::class)
+    external fun openClient(): String
+
+    /**
+     * Close a client namespace and free every handle it still owns.
+     * Call when the session disconnects or the Activity is destroyed.
+     * A pinned session is only detached; closing it again frees it.
+     * @param clientId ID returned from openClient()
+     * @return Number of handles freed (0 if a pinned session was detached)
+     * @throws IllegalArgumentException if the client is unknown
+     */
+    @Throws(IllegalArgumentException::class)
+    external fun closeClient(clientId: String): Int
+
+    /**
+     * Keep a client namespace alive across Activity recreation.
+     * closeClient() then detaches it for 30 s instead of freeing it, so an
+     * in-flight generation survives a configuration change. Save the ID in
+     * onSaveInstanceState and call resumeSession() from the new Activity.
+     * @param clientId ID returned from openClient()
+     * @return true if pinned
+     * @throws IllegalArgumentException if the client is unknown
+     */
+    @Throws(IllegalArgumentException::class)
+    external fun pinSession(clientId: String): Boolean
+
+    /**
+     * Take over a detached pinned session.
+     * @param clientId ID of a session passed to pinSession()
+     * @return true if resumed, false if it expired (open a new client)
+     * @throws IllegalStateException if the session is still attached
+     */
+    @Throws(IllegalStateException::class)
+    external fun resumeSession(clientId: String): Boolean
+
     /**
      * Allocate memory on device.
      * @param deviceIndex 0-based device index
-     * @param sizeBytes Number of bytes to allocate
+     * @param sizeBytes Number of bytes to allocate, at most the device's memory
+     * @param clientId Owning client from openClient(), or null for the shared default namespace
      * @return Handle ID string for this allocation
      * @throws RuntimeException on allocation failure
      * @throws IllegalArgumentException if size invalid
      */
     @Throws(RuntimeException::class, IllegalArgumentException::class)
-    external fun allocateMemory(deviceIndex: Int, sizeBytes: Long): String
+    external fun allocateMemory(deviceIndex: Int, sizeBytes: Long, clientId: String?): String
+
+    /**
+     * Allocate many buffers in one call, e.g. every tensor of a model graph.
+     * The buffers of each device share one device memory allocation. Either every
+     * buffer is allocated or none is.
+     * @param specsJson JSON array, e.g. [{"size": 4096, "device_index": 0}, {"size": 128}]
+     * @param clientId Owning client from openClient(), or null for the shared default namespace
+     * @return JSON array of handle IDs in spec order
+     * @throws RuntimeException if a spec is invalid or allocation fails
+     */
+    @Throws(RuntimeException::class)
+    external fun allocateBuffers(specsJson: String, clientId: String?): String
 
     /**
      * Free previously allocated memory.
      * @param handleId Handle returned from allocateMemory()
-     * @return true if freed successfully, false if handle not found
-     * @throws RuntimeException on deallocation failure
+     * @param clientId Client that allocated the handle, or null for the default namespace
+     * @return true if freed successfully
+     * @throws IllegalArgumentException if handle not found
+     * @throws SecurityException if the handle belongs to another client
      */
-    @Throws(RuntimeException::class)
-    external fun freeMemory(handleId: String): Boolean
+    @Throws(RuntimeException::class, IllegalArgumentException::class, SecurityException::class)
+    external fun freeMemory(handleId: String, clientId: String?): Boolean
+
+    /**
+     * Import a HardwareBuffer (camera, MediaCodec, NNAPI) as an allocation without copying.
+     * The buffer must be BLOB format, height and layers 1, with USAGE_GPU_DATA_BUFFER. Memory is
+     * shared both ways, so to hand results to another API, create the buffer there and import it.
+     * The allocation is made on device 0, whose driver must support
+     * VK_ANDROID_external_memory_android_hardware_buffer. Free it with [freeMemory].
+     * @param hardwareBuffer Buffer to import; the caller keeps ownership
+     * @param clientId Client namespace, or null for the default
+     * @return Handle ID of the imported allocation
+     * @throws IllegalArgumentException if the buffer is not an importable BLOB
+     * @throws RuntimeException if the device cannot import it
+     * @throws UnsupportedOperationException off Android
+     */
+    @Throws(IllegalArgumentException::class, RuntimeException::class)
+    external fun importHardwareBuffer(hardwareBuffer: android.hardware.HardwareBuffer, clientId: String?): String
 
     // ============ Data Transfer ============
 
@@ -92,36 +314,606 @@ object VulkanGpu {
      * Copy data from host to device.
      * @param handleId Handle from allocateMemory()
      * @param data Byte array to copy
+     * @param clientId Client that allocated the handle, or null for the default namespace
      * @return true if copy succeeded
      * @throws RuntimeException on transfer failure
      * @throws IllegalArgumentException if handle invalid
      */
     @Throws(RuntimeException::class, IllegalArgumentException::class)
-    external fun copyToDevice(handleId: String, data: ByteArray): Boolean
+    external fun copyToDevice(handleId: String, data: ByteArray, clientId: String?): Boolean
 
     /**
      * Copy data from device to host.
      * @param handleId Handle from allocateMemory()
-     * @param sizeBytes Number of bytes to copy
+     * @param sizeBytes Number of bytes to copy, at most the allocation's size
+     * @param clientId Client that allocated the handle, or null for the default namespace
      * @return ByteArray with copied data, or null on error
      * @throws RuntimeException on transfer failure
      * @throws IllegalArgumentException if handle invalid
      */
     @Throws(RuntimeException::class, IllegalArgumentException::class)
-    external fun copyFromDevice(handleId: String, sizeBytes: Long): ByteArray?
+    external fun copyFromDevice(handleId: String, sizeBytes: Long, clientId: String?): ByteArray?
+
+    // ============ Model Management ============
+
+    /**
+     * Load a model with its own memory quota on device 0.
+     * Idle models are unloaded least-recently-used first if room is needed.
+     * @param modelId Unique model identifier
+     * @param path Model weights location
+     * @param quotaBytes Maximum bytes this model may allocate
+     * @return true if the model was loaded
+     * @throws RuntimeException if the model cannot be loaded, including weight
+     *         formats whose loader is not compiled in (see [getEnabledFeatures])
+     */
+    @Throws(RuntimeException::class)
+    external fun loadModel(modelId: String, path: String, quotaBytes: Long): Boolean
+
+    /**
+     * Unload a model and free its allocations.
+     * @param modelId Model to unload
+     * @return true if the model was unloaded
+     * @throws IllegalArgumentException if the model is unknown or in use
+     */
+    @Throws(IllegalArgumentException::class)
+    external fun unloadModel(modelId: String): Boolean
+
+    /**
+     * List loaded models, most recently used first.
+     * JSON structure: [{"model_id": "...", "path": "...", "quota_bytes": 0, "used_bytes": 0, "active_users": 0}]
+     * @return JSON array of loaded models
+     */
+    external fun listModels(): String
+
+    /**
+     * Snapshot the backend's logical state (loaded models, allocation handles and
+     * their owning clients) as a versioned binary blob for checkpointing. GPU memory
+     * contents are not included; after a restart the orchestrator replays the blob.
+     * Equal states always produce identical bytes.
+     * @return State blob
+     * @throws UnsupportedOperationException if built without the validation feature
+     */
+    @Throws(UnsupportedOperationException::class)
+    external fun exportState(): ByteArray
+
+    /**
+     * Prepare a replacement process (app update, service restart) to take over
+     * tensors before shutdown. Returns a manifest with the device and driver UUIDs
+     * and one entry per tensor: handle_id, size, allocation_size, memory_type_index,
+     * fd and metadata (client, device_id, model_id). Waits for running jobs first.
+     * Each fd is an exported opaque FD the caller owns and sends to the replacement,
+     * e.g. as a ParcelFileDescriptor. It is null for tensors that cannot be exported
+     * (allocateBuffers buffers, imported HardwareBuffers, other devices, drivers
+     * without VK_KHR_external_memory_fd), which the replacement reloads.
+     * @param handlesJson JSON array of handle IDs; empty for all
+     * @return Manifest JSON
+     * @throws RuntimeException if jobs are still running after 10 s or a handle is unknown
+     */
+    @Throws(RuntimeException::class)
+    external fun prepareHandover(handlesJson: String): String
+
+    /**
+     * Generate tokens with a small draft model speculating ahead of a larger target model.
+     * Both models must be loaded and have a registered runtime.
+     * @param draftModelId Model proposing tokens
+     * @param targetModelId Model verifying them
+     * @param prompt Prompt token ids
+     * @param maxTokens Number of tokens to generate
+     * @param draftLen Tokens proposed per round
+     * @param stopToken End-of-sequence token id, or -1 for none
+     * @return Generated token ids
+     * @throws RuntimeException on generation failure
+     */
+    @Throws(RuntimeException::class)
+    external fun generateSpeculative(
+        draftModelId: String,
+        targetModelId: String,
+        prompt: IntArray,
+        maxTokens: Int,
+        draftLen: Int,
+        stopToken: Int
+    ): IntArray
+
+    /**
+     * Read a session's generated tokens, prompt included, in one call.
+     * The history stays in device memory, appended by the sampler each step,
+     * so this is the only download; use it for detokenizing or checkpoints.
+     * @param sessionHandle Client ID the session runs under, or null for the default client
+     * @param from Index of the first token
+     * @param count Maximum number of tokens; fewer are returned at the end of the history
+     * @return Token ids
+     * @throws RuntimeException if the session has no history or from is past its end
+     */
+    @Throws(RuntimeException::class)
+    external fun exportTokens(sessionHandle: String?, from: Int, count: Int): IntArray
+
+    /**
+     * Register a token dataset for measuring a model's perplexity on device,
+     * e.g. to compare quantized checkpoints.
+     * @param modelId Loaded model with a registered runtime
+     * @param tokens Token ids, at least 2
+     * @param window Predictions per forward pass, or <= 0 for the default (512)
+     * @return Dataset handle for [evaluate]
+     * @throws IllegalArgumentException if the dataset is invalid
+     */
+    @Throws(IllegalArgumentException::class)
+    external fun createEvalDataset(modelId: String, tokens: IntArray, window: Int): String
+
+    /**
+     * Score a dataset with teacher-forced forward passes. Clears the model's KV cache.
+     * @param datasetHandle Handle from [createEvalDataset]
+     * @return JSON object: {"tokens", "windows", "mean_nll", "perplexity"}
+     * @throws RuntimeException on evaluation failure
+     */
+    @Throws(RuntimeException::class)
+    external fun evaluate(datasetHandle: String): String
+
+    /**
+     * Forget a dataset from [createEvalDataset].
+     * @return true if the dataset existed
+     */
+    external fun releaseEvalDataset(datasetHandle: String): Boolean
+
+    /**
+     * Cap the GPU time decoding may use per UI frame. Once a frame's budget is
+     * spent, decode loops yield until the next frame so on-device generation can
+     * run alongside a 60/120 Hz UI without dropped frames.
+     * @param budgetMs GPU milliseconds per frame, or <= 0 to disable pacing
+     * @param refreshRateHz Display refresh rate, e.g. 60 or 120
+     * @return true if the budget was applied
+     * @throws IllegalArgumentException if the budget does not fit in one frame
+     */
+    @Throws(IllegalArgumentException::class)
+    external fun setFrameBudgetMs(budgetMs: Float, refreshRateHz: Int): Boolean
+
+    /**
+     * Flag that the UI needs the GPU's attention, e.g. during scrolling or
+     * animations. Background weight uploads use short chunks while set and
+     * size chunks from measured bandwidth otherwise.
+     * @param responsive true to favour UI latency, false to favour load throughput
+     */
+    external fun setUiResponsive(responsive: Boolean)
+
+    // ============ Kernels ============
+
+    /**
+     * Register a custom compute kernel shipped as a SPIR-V asset.
+     * The module is reflected and rejected unless it matches the metadata.
+     * @param name Kernel name (letters, digits, '_' and '.'); must not shadow a built-in kernel
+     * @param spirvBytes SPIR-V module bytes, e.g. from assets.open("kernels/blur.spv").readBytes()
+     * @param metadataJson {"entry_point": "main", "bindings": [0, 1], "push_constant_bytes": 8, "workgroup_size": [64, 1, 1]}
+     * @return true if registered
+     * @throws IllegalArgumentException if the module, name or metadata is invalid
+     */
+    @Throws(IllegalArgumentException::class)
+    external fun registerKernelFromAsset(name: String, spirvBytes: ByteArray, metadataJson: String): Boolean
+
+    /**
+     * Dispatch a compute kernel (e.g. matmul, elementwise) on allocated buffers.
+     * The kernel binds the inputs in order followed by the output and must
+     * declare exactly that many storage buffers and no push constants. All
+     * handles must be on one device. Returns at once; wait with waitJob().
+     * @param kernelName Built-in kernel or one registered with registerKernelFromAsset()
+     * @param inputHandles Handles from allocateMemory(), in binding order
+     * @param outputHandle Handle bound after the inputs
+     * @param workgroupX Workgroups along x, 1..65535
+     * @param workgroupY Workgroups along y, 1..65535
+     * @param workgroupZ Workgroups along z, 1..65535
+     * @param clientId Client that allocated the handles, or null for the default namespace
+     * @return Job id
+     * @throws IllegalArgumentException if the kernel, handles or workgroup counts are invalid
+     */
+    @Throws(IllegalArgumentException::class)
+    external fun dispatchKernel(
+        kernelName: String,
+        inputHandles: Array<String>,
+        outputHandle: String,
+        workgroupX: Int,
+        workgroupY: Int,
+        workgroupZ: Int,
+        clientId: String?
+    ): String?
+
+    /**
+     * Wait for a job from dispatchKernel(). A finished job is forgotten once waited on, or
+     * ten minutes after it finished if nobody waits on it.
+     * @param jobId Job id
+     * @param timeoutMs Longest wait; negative waits indefinitely
+     * @return true once the job completed, false if it is still running
+     * @throws RuntimeException if the job failed
+     * @throws IllegalArgumentException if the job is unknown
+     */
+    @Throws(RuntimeException::class, IllegalArgumentException::class)
+    external fun waitJob(jobId: String, timeoutMs: Long): Boolean
+
+    // ============ Weight Updates ============
+
+    /**
+     * Name a weight tensor stored in an allocation so deltas can be applied to it.
+     * Registering a name again replaces it; freeing the allocation forgets it.
+     * @param tensorName Name used by applyDelta(), e.g. "layers.0.attn.q_proj.weight"
+     * @param handleId Handle from allocateMemory() holding the tensor
+     * @param rows Rows of the tensor
+     * @param cols Columns of the tensor
+     * @param quantParams null for f32 weights; for int8 weights packed four to a
+     *        word, a (scale, zeroPoint) pair per row
+     * @param clientId Client that allocated the handle, or null for the default namespace
+     * @return true if registered
+     * @throws IllegalArgumentException if the handle, shape or params are invalid
+     */
+    @Throws(IllegalArgumentException::class)
+    external fun registerWeight(
+        tensorName: String,
+        handleId: String,
+        rows: Int,
+        cols: Int,
+        quantParams: FloatArray?,
+        clientId: String?
+    ): Boolean
+
+    /**
+     * Apply a downloaded fine-tuning delta in place: weights += scale * delta,
+     * without reloading the model. int8 weights are dequantized, updated and
+     * requantized with their existing params. A negative scale reverts a delta.
+     * The weights and the delta must be on the same device.
+     * @param tensorName Name given to registerWeight()
+     * @param deltaHandle Handle holding one f32 per weight element
+     * @param scale Multiplier of the delta; must be finite
+     * @param clientId Client that allocated the handles, or null for the default namespace
+     * @return true if applied
+     * @throws IllegalArgumentException if the tensor, delta or scale is invalid
+     * @throws RuntimeException if the delta could not be applied
+     */
+    @Throws(IllegalArgumentException::class, RuntimeException::class)
+    external fun applyDelta(tensorName: String, deltaHandle: String, scale: Float, clientId: String?): Boolean
+
+    /**
+     * Explain which kernel variant an op uses on this device and why, for
+     * "slow on device X" reports. Evaluated for device 0.
+     * JSON structure: {"op": "permute", "device": "...", "chosen": "permute_tiled", "basis": "preference",
+     *   "candidates": [{"kernel": "...", "eligible": true, "reasons": ["ok: shared memory 32768 >= 4224 bytes"], "autotune_us": null}]}
+     * @param opName Op such as "permute" or "reduce"
+     * @return JSON report
+     * @throws RuntimeException if Vulkan is unavailable
+     */
+    @Throws(RuntimeException::class)
+    external fun explainKernelSelection(opName: String): String
+
+    // ============ Embedding Search ============
+
+    /**
+     * Find the rows of the embedding index most similar (cosine) to a query embedding.
+     * JSON structure: [{"id": 0, "score": 0.93}, ...], best first; ids are those
+     * returned by [appendEmbeddings].
+     * @param queryHandle Tensor handle the query embedding was registered under
+     * @param k Number of matches to return
+     * @return JSON array of matches
+     * @throws IllegalArgumentException if no index or query is registered
+     * @throws UnsupportedOperationException if built without the `kernels-core` feature
+     */
+    @Throws(IllegalArgumentException::class, UnsupportedOperationException::class)
+    external fun searchEmbeddings(queryHandle: String, k: Int): String
+
+    /**
+     * Replace the embedding index with an empty one that quantizes rows as they
+     * are appended. Scores of quantized indexes approximate cosine similarity.
+     * @param dim Values per row
+     * @param quantization "f32", "int8" (4x smaller) or "binary" (32x smaller)
+     * @return true if the index was created
+     * @throws IllegalArgumentException if [dim] or [quantization] is invalid
+     * @throws UnsupportedOperationException if built without the `kernels-core` feature
+     */
+    @Throws(IllegalArgumentException::class, UnsupportedOperationException::class)
+    external fun createEmbeddingIndex(dim: Int, quantization: String): Boolean
+
+    /**
+     * Append rows to the embedding index, creating an f32 index on first use.
+     * @param values Row-major embeddings, a whole number of rows of [dim] values
+     * @param dim Values per row; must match the existing index
+     * @return Stable ids of the new rows, in order
+     * @throws IllegalArgumentException if [values] holds a partial row or [dim] mismatches
+     * @throws UnsupportedOperationException if built without the `kernels-core` feature
+     */
+    @Throws(IllegalArgumentException::class, UnsupportedOperationException::class)
+    external fun appendEmbeddings(values: FloatArray, dim: Int): LongArray
+
+    /**
+     * Delete rows of the embedding index. Rows are tombstoned and the index
+     * compacts itself once a quarter of its rows are deleted; ids stay valid.
+     * @param ids Ids returned by [appendEmbeddings]
+     * @return Number of ids that were still present
+     * @throws UnsupportedOperationException if built without the `kernels-core` feature
+     */
+    @Throws(UnsupportedOperationException::class)
+    external fun deleteEmbeddings(ids: LongArray): Int
+
+    // ============ Media Preprocessing ============
+
+    /**
+     * Compute a Whisper log-mel spectrogram (80 bands, 25 ms windows every 10 ms).
+     * @param audio Direct buffer of 16 kHz mono float32 little-endian samples
+     * @param output Direct buffer receiving [80, frames] float32 values
+     * @return Number of frames written
+     * @throws IllegalArgumentException if a buffer is not direct or too small
+     * @throws UnsupportedOperationException if built without the `kernels-media` feature
+     */
+    @Throws(IllegalArgumentException::class, UnsupportedOperationException::class)
+    external fun computeLogMel(audio: ByteBuffer, output: ByteBuffer): Int
+
+    /**
+     * Resize (bilinear) and CLIP-normalize an RGBA_8888 image for a vision encoder.
+     * @param pixels Direct buffer of width * height RGBA_8888 pixels
+     * @param width Input width in pixels
+     * @param height Input height in pixels
+     * @param output Direct buffer receiving 3 * outWidth * outHeight float32 values
+     * @param outWidth Encoder input width
+     * @param outHeight Encoder input height
+     * @param patchSize Emit [patches, 3, p, p] for p > 0, planar [3, h, w] for 0
+     * @return true if successful
+     * @throws IllegalArgumentException if a buffer is not direct or too small, or the
+     *         output is not a whole number of patches
+     * @throws UnsupportedOperationException if built without the `kernels-media` feature
+     */
+    @Throws(IllegalArgumentException::class, UnsupportedOperationException::class)
+    external fun preprocessImage(
+        pixels: ByteBuffer,
+        width: Int,
+        height: Int,
+        output: ByteBuffer,
+        outWidth: Int,
+        outHeight: Int,
+        patchSize: Int
+    ): Boolean
 
     // ============ Utility Methods ============
 
+    /**
+     * Re-apply the backend configuration at runtime. Absent fields keep their value.
+     * Hot fields (log_level, memory_thresholds, frame_budget_ms, refresh_rate_hz,
+     * object_limits, cache_dir, jni_stats) apply immediately; cold fields (loader_path, queue_priority, global_priority,
+     * protected_submission, debug_layers) apply after shutdown() and re-initialization.
+     * debug_layers enables VK_LAYER_KHRONOS_validation, whose messages go to logcat; the layer
+     * must be packaged in the APK's jniLibs.
+     * @param configJson JSON object, e.g. {"log_level": "debug", "memory_thresholds": [75, 90]}
+     * @return JSON diff: {"changes": [{"field", "old", "new", "reload"}], "requires_reinit": bool}
+     * @throws IllegalArgumentException if the document is malformed or a value is invalid
+     */
+    @Throws(IllegalArgumentException::class)
+    external fun applyConfig(configJson: String): String
+
+    /**
+     * Get counts of known-slow usage patterns detected so far.
+     * JSON structure: {"frequent_queue_wait_idle": 0, "small_upload_loop": 0, "unmapped_host_visible_read": 0}
+     * @return JSON string of counters
+     */
+    external fun getSlowPathStats(): String
+
+    /**
+     * Set how dynamic sequence lengths are padded before planning: "exact",
+     * "pow2", "pow2:<min>" or "step:<n>". Coarser buckets reuse more plans
+     * and pipelines but waste more work on padding; see getPaddingStats().
+     * @param policy Policy spec
+     * @return true if applied (padding stats are cleared)
+     */
+    @Throws(IllegalArgumentException::class)
+    external fun setBucketPolicy(policy: String): Boolean
+
+    /**
+     * Get the sequence padding recorded under the current bucket policy.
+     * JSON structure: {"policy": "pow2:16", "plans": 0, "tokens": 0, "padded_tokens": 0, "waste": 0.0, "buckets": {"64": 3}}
+     * @return JSON string of padding counters
+     */
+    external fun getPaddingStats(): String
+
+    /**
+     * Get live Vulkan object counts (pipelines, descriptor pools, command buffers,
+     * fences, semaphores) against the ceilings set via applyConfig's object_limits.
+     * JSON structure: {"fence": {"live": 12, "peak": 40, "limit": 256}, ...}
+     * @return JSON string of per-kind usage
+     */
+    external fun getObjectUsage(): String
+
+    /**
+     * Get sustained generation throughput over the last 10 s ("short") and
+     * 60 s ("long"), for showing honest speed figures rather than the burst
+     * rate of a cool device. "trend" compares the short window with the best
+     * one seen: "warmup" (under 10 s of history), "idle", "stable",
+     * "degrading" (fewer tokens requested) or "thermal_throttling" (throughput
+     * down and GPU time per token up, i.e. the GPU clocked down).
+     * JSON structure: {"short": {"duration_ms": 10000, "tokens": 180, "tokens_per_sec": 18.0,
+     *   "gpu_busy_percent": 92.5, "gpu_us_per_token": 51000}, "long": {...},
+     *   "peak_tokens_per_sec": 24.0, "degradation_percent": 25.0, "trend": "thermal_throttling"}
+     * @return JSON string of the summary
+     */
+    external fun getPerformanceSummary(): String
+
+    /**
+     * Get GPU time per kernel, aggregated from timestamp queries bracketing
+     * each dispatch. Mean times feed layer-time estimates when partitioning
+     * a model across devices.
+     * JSON structure: {"gemm": {"count": 64, "mean_ns": 182000, "min_ns": 171000,
+     *   "max_ns": 240000, "total_ns": 11648000}}
+     * @return JSON string of the stats, keyed by kernel name
+     * @throws UnsupportedOperationException if built without the profiling feature
+     */
+    @Throws(UnsupportedOperationException::class)
+    external fun getKernelStats(): String
+
+    /**
+     * Get memory use and throughput over the session for a diagnostics chart.
+     * Each point covers one interval: peak allocated memory, tokens generated
+     * and GPU time spent. The last point is still filling.
+     * JSON structure: {"resolution": "1s", "interval_ms": 1000, "now_ms": 42500,
+     *   "columns": ["start_ms", "memory_bytes", "tokens", "gpu_busy_us"],
+     *   "points": [[0, 268435456, 18, 640000], ...]}
+     * @param resolution "1s" (last 5 minutes), "10s" (last hour) or "5m" (last day)
+     * @return JSON string of the history
+     * @throws IllegalArgumentException for an unknown resolution
+     */
+    @Throws(IllegalArgumentException::class)
+    external fun getMetricsHistory(resolution: String): String
+
+    /**
+     * [getMetricsHistory] in a compact little-endian layout for frequent polling:
+     * "EXMH", then u32 format version, u32 interval_ms, u64 now_ms and u32 point
+     * count, then per point u64 start_ms, u64 memory_bytes, u32 tokens and
+     * u32 gpu_busy_us.
+     * @param resolution "1s", "10s" or "5m"
+     * @return Encoded history
+     * @throws IllegalArgumentException for an unknown resolution
+     */
+    @Throws(IllegalArgumentException::class)
+    external fun getMetricsHistoryBinary(resolution: String): ByteArray
+
+    /**
+     * Clear recorded throughput, e.g. when switching models.
+     */
+    external fun resetPerformanceStats()
+
+    /**
+     * Attribute work started on the calling thread to a request, for servers
+     * running several requests at once. The id is appended to GPU debug labels
+     * and keys the metrics returned by [getTraceStats]. Applies to later calls
+     * from this thread until changed, and wins over [setClientTraceId].
+     * @param traceId Request or trace id (printable ASCII, at most 128 bytes), or null to clear
+     * @return true if set
+     * @throws IllegalArgumentException if the id is empty, too long or not printable
+     */
+    @Throws(IllegalArgumentException::class)
+    external fun setTraceId(traceId: String?): Boolean
+
+    /**
+     * Attribute every call made for a client namespace to a request.
+     * Cleared when the client is closed.
+     * @param clientId ID from openClient
+     * @param traceId Request or trace id, or null to clear
+     * @return true if set
+     * @throws IllegalArgumentException if the client is unknown or the id invalid
+     */
+    @Throws(IllegalArgumentException::class)
+    external fun setClientTraceId(clientId: String, traceId: String?): Boolean
+
+    /**
+     * Get the work attributed to a request. Metrics are kept for the 256 most
+     * recently active trace ids.
+     * JSON structure: {"trace_id": "req-42", "dispatches": 310, "gpu_time_us": 88000, "errors": 0}
+     * @param traceId Request or trace id
+     * @return JSON string of stats, or null if nothing was recorded for the id
+     */
+    external fun getTraceStats(traceId: String): String?
+
+    /**
+     * Get per-entry-point JNI call counts and timing histograms, collected while
+     * {"jni_stats": true} is applied via applyConfig. Many short calls to one
+     * entry point suggest switching to a batch API (e.g. allocateBuffers).
+     * JSON structure: {"enabled": true, "bucket_bounds_us": [1, 4, 16, ...],
+     *   "entry_points": {"copyToDevice": {"calls": 120, "total_us": 5400, "mean_us": 45.0, "max_us": 310,
+     *   "histogram": [0, 2, 30, ...]}}}; histogram[i] counts calls under bucket_bounds_us[i],
+     *   the last entry everything slower. Entry points are sorted by total time.
+     * @return JSON string of stats
+     */
+    external fun getJniStats(): String
+
+    /**
+     * Clear collected JNI call stats, e.g. before measuring one scenario.
+     */
+    external fun resetJniStats()
+
+    /**
+     * Read back a small slice of a named device tensor for debugging.
+     * JSON structure: {"name": "...", "shape": [2, 8], "nan_count": 0, "inf_count": 0,
+     *                  "min": -1.5, "max": 3.2, "values": [[...], [...]]}
+     * @param name Name the tensor was registered under
+     * @param sliceJson Per-dim selection: index, [start, end], [start, end, step] or null,
+     *                  e.g. "[0, [0, 8], null]"
+     * @return JSON string of the slice
+     * @throws IllegalArgumentException if the tensor is unknown or the slice is invalid
+     * @throws UnsupportedOperationException if built without the `validation` feature
+     */
+    @Throws(IllegalArgumentException::class, UnsupportedOperationException::class)
+    external fun inspectTensor(name: String, sliceJson: String): String
+
+    /**
+     * Set the memory usage levels that raise "memory_pressure" events (see [pollEvents]).
+     * Each level fires once when crossed and re-arms after usage drops 5% below it.
+     * The event carries a per-client breakdown:
+     * {"kind": "memory_pressure", "threshold_percent": 80, "used_bytes": 0, "budget_bytes": 0,
+     *  "breakdown": {"default": 0}}
+     * Defaults to 80% and 95% of total device memory.
+     * @param percents Thresholds as percentages of total device memory, in (0, 100]
+     * @return true if the thresholds were applied
+     * @throws IllegalArgumentException if a threshold is out of range or no devices are enumerated
+     */
+    @Throws(IllegalArgumentException::class)
+    external fun setMemoryThresholds(percents: IntArray): Boolean
+
+    /**
+     * Drain buffered backend events (allocations, transfers, errors, ...).
+     * JSON structure: {"events": [{"kind": "transfer", ...}], "lagged": 0}
+     * @param maxEvents Maximum number of events to return, >= 0
+     * @return JSON string of events
+     * @throws IllegalArgumentException if maxEvents is negative
+     */
+    @Throws(IllegalArgumentException::class)
+    external fun pollEvents(maxEvents: Int): String
+
+    /**
+     * List the optional subsystems compiled into the native library.
+     * JSON structure: {"features": ["kernels-core", "validation", ...], "kernels": ["reduce_pass1", ...]}
+     * @return JSON string of enabled features and embedded kernels
+     */
+    external fun getEnabledFeatures(): String
+
+    /**
+     * Decompress all embedded compute kernels now instead of on first use.
+     * Call during warmup to keep the first inference free of that cost.
+     * JSON structure: {"extracted": 5, "cached": 0, "compressed_bytes": 0, "spirv_bytes": 0, "duration_us": 0}
+     * @return JSON string of extraction stats
+     * @throws RuntimeException if an embedded kernel is corrupt
+     */
+    @Throws(RuntimeException::class)
+    external fun extractKernels(): String
+
+    /**
+     * Start the development server that lets a workstation enumerate devices,
+     * allocate buffers and dispatch kernels on this device, for iterating on
+     * kernels without reinstalling the app. It listens on loopback only:
+     * run `adb forward tcp:7878 tcp:7878`, then `python -m exo.gpu.remote_shim`
+     * on the workstation. It has no authentication and is only compiled into
+     * debug builds (the `remote-debug` feature). Replaces a server already running.
+     * @param port TCP port, or 0 for any free one
+     * @return the port listened on
+     * @throws IllegalStateException if the port is invalid or taken, or Vulkan is unavailable
+     * @throws UnsupportedOperationException if built without the `remote-debug` feature
+     */
+    @Throws(IllegalStateException::class, UnsupportedOperationException::class)
+    external fun startRemoteShim(port: Int): Int
+
+    /**
+     * Stop the development server. Connected clients keep their session
+     * until they disconnect.
+     * @return true if a server was running
+     */
+    external fun stopRemoteShim(): Boolean
+
+    /**
+     * Shut down Vulkan: free all allocations, unload models and drop the
+     * context. A later initializeVulkan() starts over, e.g. with a new
+     * loader path.
+     * @return true once everything was released
+     */
+    external fun shutdown(): Boolean
+
+    /**
+     * Get the native library version.
+     * @return Version string in the form "<crate>+abi.<n>"
+     */
+    external fun getNativeVersion(): String
+
     /**
      * Verify that required Vulkan capabilities are available.
+     * Never throws: a missing loader, driver, or device all report false.
      * @return true if system supports Vulkan operations
      */
-    fun isVulkanSupported(): Boolean {
-        return try {
-            initializeVulkan()
-        } catch (e: Exception) {
-            Log.w(TAG, "Vulkan not supported: ${e.message}")
-            false
-        }
-    }
+    external fun isVulkanSupported(): Boolean
 }
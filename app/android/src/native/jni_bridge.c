@@ -23,17 +23,25 @@ extern jlong Java_com_exo_gpu_VulkanGpu_getDeviceMemory(
 extern jint Java_com_exo_gpu_VulkanGpu_getComputeUnits(
     JNIEnv *env, jclass clazz, jint deviceIndex);
 
+extern jstring Java_com_exo_gpu_VulkanGpu_openClient(
+    JNIEnv *env, jclass clazz);
+
+extern jint Java_com_exo_gpu_VulkanGpu_closeClient(
+    JNIEnv *env, jclass clazz, jstring clientId);
+
 extern jstring Java_com_exo_gpu_VulkanGpu_allocateMemory(
-    JNIEnv *env, jclass clazz, jint deviceIndex, jlong sizeBytes);
+    JNIEnv *env, jclass clazz, jint deviceIndex, jlong sizeBytes, jstring clientId);
 
 extern jboolean Java_com_exo_gpu_VulkanGpu_freeMemory(
-    JNIEnv *env, jclass clazz, jstring handleId);
+    JNIEnv *env, jclass clazz, jstring handleId, jstring clientId);
 
 extern jboolean Java_com_exo_gpu_VulkanGpu_copyToDevice(
-    JNIEnv *env, jclass clazz, jstring handleId, jbyteArray data);
+    JNIEnv *env, jclass clazz, jstring handleId, jbyteArray data,
+    jstring clientId);
 
 extern jbyteArray Java_com_exo_gpu_VulkanGpu_copyFromDevice(
-    JNIEnv *env, jclass clazz, jstring handleId, jlong sizeBytes);
+    JNIEnv *env, jclass clazz, jstring handleId, jlong sizeBytes,
+    jstring clientId);
 
 /**
  * JNI_OnLoad - called when library is loaded by system.
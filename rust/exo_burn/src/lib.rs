@@ -0,0 +1,177 @@
+//! burn-wgpu tensor bridge
+//!
+//! When burn's wgpu backend runs on Vulkan, its buffers are ordinary
+//! `VkBuffer`s on a device this crate can drive too. The bridge reaches the
+//! raw handles through `wgpu-hal`, so a burn tensor can be handed to the
+//! quantized inference kernels (and their outputs handed back) without a
+//! round trip through host memory.
+//!
+//! Both sides must share one logical device: build exo's allocator, transfer
+//! and command state on `SharedDevice::device()` rather than on a device of
+//! its own. Queues are not shared, so the caller orders work across the two:
+//! wait for burn's submissions (`wgpu::Device::poll(Maintain::Wait)`) before
+//! recording exo work on a bridged buffer, and wait for exo's fence before
+//! burn reads the result.
+//!
+//! Use the non-fusion backend (`burn_wgpu::JitBackend<WgpuRuntime, ..>`):
+//! fusion defers allocation, so its tensors have no buffer to bridge.
+
+#![allow(unsafe_code)]
+
+use ash::vk;
+use burn_jit::tensor::JitTensor;
+use burn_wgpu::WgpuRuntime;
+use thiserror::Error;
+use wgpu_hal::api::Vulkan;
+
+use exo_vulkan_binding::memory::BufferRange;
+use exo_vulkan_binding::tensor::{ELEMENT_SIZE, Layout, Tensor, TensorError};
+
+/// Bridge errors
+#[derive(Error, Debug)]
+pub enum BridgeError {
+    #[error("wgpu is not running on the Vulkan backend")]
+    NotVulkan,
+
+    #[error("Only f32 tensors can be bridged, got {0}")]
+    UnsupportedDtype(String),
+
+    #[error(transparent)]
+    Tensor(#[from] TensorError),
+}
+
+pub type BridgeResult<T> = Result<T, BridgeError>;
+
+/// The Vulkan device underneath a `wgpu::Device`
+#[derive(Clone)]
+pub struct SharedDevice {
+    device: ash::Device,
+    physical_device: vk::PhysicalDevice,
+    queue_family_index: u32,
+}
+
+impl SharedDevice {
+    /// Borrow wgpu's Vulkan device
+    ///
+    /// # Safety Requirements
+    /// - the returned device must not be used after `device` is dropped
+    /// - the caller must not destroy the device or objects wgpu created
+    pub unsafe fn from_wgpu(device: &wgpu::Device) -> BridgeResult<Self> {
+        // SAFETY: the hal device is only read inside the callback
+        unsafe {
+            device.as_hal::<Vulkan, _, _>(|hal| {
+                hal.map(|hal| Self {
+                    device: hal.raw_device().clone(),
+                    physical_device: hal.raw_physical_device(),
+                    queue_family_index: hal.queue_family_index(),
+                })
+            })
+        }
+        .ok_or(BridgeError::NotVulkan)
+    }
+
+    pub fn device(&self) -> &ash::Device {
+        &self.device
+    }
+
+    pub fn physical_device(&self) -> vk::PhysicalDevice {
+        self.physical_device
+    }
+
+    /// Family of the queue wgpu submits on
+    pub fn queue_family_index(&self) -> u32 {
+        self.queue_family_index
+    }
+
+    /// Whether `other` is the same logical device
+    pub fn same_device(&self, other: &ash::Device) -> bool {
+        self.device.handle() == other.handle()
+    }
+}
+
+/// `VkBuffer` range behind `size` bytes of a wgpu buffer from `offset`
+///
+/// # Safety Requirements
+/// - `buffer` must outlive every use of the returned range
+pub unsafe fn buffer_range(
+    buffer: &wgpu::Buffer,
+    offset: u64,
+    size: u64,
+) -> BridgeResult<BufferRange> {
+    // SAFETY: the hal buffer is only read inside the callback
+    let raw = unsafe { buffer.as_hal::<Vulkan, _, _>(|hal| hal.map(|hal| hal.raw_handle())) };
+    Ok(BufferRange {
+        buffer: raw.ok_or(BridgeError::NotVulkan)?,
+        offset,
+        size,
+    })
+}
+
+/// Layout of a burn tensor, from its shape and element strides
+pub fn layout_of(tensor: &JitTensor<WgpuRuntime>) -> BridgeResult<Layout> {
+    Ok(Layout::with_strides(&tensor.shape.dims, &tensor.strides)?)
+}
+
+/// View a burn tensor's storage as a device `Tensor`, without copying
+///
+/// # Safety Requirements
+/// - `tensor` must stay alive (and its buffer unreused) while the view is
+///   in use; see the module docs for queue ordering
+pub unsafe fn tensor_from_burn(tensor: &JitTensor<WgpuRuntime>) -> BridgeResult<Tensor> {
+    if tensor.dtype != burn_jit::tensor::DType::F32 {
+        return Err(BridgeError::UnsupportedDtype(format!("{:?}", tensor.dtype)));
+    }
+    let layout = layout_of(tensor)?;
+    let binding = tensor.handle.clone().binding();
+    let resource = tensor.client.get_resource(binding);
+    let resource = resource.resource();
+    // SAFETY: forwarded from the caller's guarantees
+    let range = unsafe { buffer_range(&resource.buffer, resource.offset(), resource.size()) }?;
+    Ok(Tensor::new(range, layout)?)
+}
+
+/// Wrap an exo buffer as a `wgpu::Buffer` so burn can bind it
+///
+/// wgpu does not take ownership: the buffer and its memory are still freed
+/// by exo's allocator, after wgpu is done with it.
+///
+/// # Safety Requirements
+/// - `range.buffer` must belong to `device` and be created with usage flags
+///   matching `usage`
+/// - `range` must cover the whole buffer (wgpu has no sub-buffer binding)
+pub unsafe fn wgpu_buffer_from(
+    device: &wgpu::Device,
+    range: &BufferRange,
+    usage: wgpu::BufferUsages,
+) -> wgpu::Buffer {
+    // SAFETY: forwarded from the caller's guarantees
+    unsafe {
+        let hal = wgpu_hal::vulkan::Device::buffer_from_raw(range.buffer);
+        device.create_buffer_from_hal::<Vulkan>(
+            hal,
+            &wgpu::BufferDescriptor {
+                label: Some("exo"),
+                size: range.size,
+                usage,
+                mapped_at_creation: false,
+            },
+        )
+    }
+}
+
+/// Bytes a bridged f32 view of `layout` spans
+pub fn span_bytes(layout: &Layout) -> u64 {
+    layout.span() as u64 * ELEMENT_SIZE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_span_bytes() {
+        let layout = Layout::contiguous(&[2, 3]).permute(&[1, 0]).unwrap();
+        assert_eq!(span_bytes(&layout), 24);
+        assert_eq!(span_bytes(&Layout::contiguous(&[0, 4])), 0);
+    }
+}
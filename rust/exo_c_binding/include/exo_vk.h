@@ -0,0 +1,116 @@
+/*
+ * C interface of the exo Vulkan backend (libexo_c_binding)
+ *
+ * Every function except exo_vk_abi_version, exo_vk_last_error_message and
+ * exo_vk_close returns an ExoVkStatus: 0 on success, otherwise a code with
+ * the reason available from exo_vk_last_error_message on the same thread.
+ * Results are written through out-pointers. No function unwinds into the
+ * caller.
+ *
+ * A context owns one device with its buffers. Contexts may be shared
+ * between threads; calls on one context are serialized and complete before
+ * they return.
+ *
+ * Compare exo_vk_abi_version() with EXO_VK_ABI_VERSION after loading the
+ * library; signatures and values below change only with that version.
+ */
+
+#ifndef EXO_VK_H
+#define EXO_VK_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define EXO_VK_ABI_VERSION 1
+
+#ifndef EXO_VK_API
+#define EXO_VK_API
+#endif
+
+typedef enum ExoVkStatus {
+    EXO_VK_OK = 0,
+    EXO_VK_INVALID_ARGUMENT = 1,
+    EXO_VK_BUFFER_TOO_SMALL = 2,
+    EXO_VK_UNSUPPORTED = 3,
+    EXO_VK_PANIC = 4,
+    EXO_VK_NOT_FOUND = 5,
+    EXO_VK_OUT_OF_MEMORY = 6,
+    EXO_VK_DEVICE_LOST = 7,
+    EXO_VK_TIMEOUT = 8,
+    EXO_VK_BACKEND = 9
+} ExoVkStatus;
+
+/* exo_vk_elementwise ops */
+#define EXO_VK_OP_ADD 0
+#define EXO_VK_OP_MUL 1
+
+/* exo_vk_activation functions */
+#define EXO_VK_ACTIVATION_SILU 0
+#define EXO_VK_ACTIVATION_GELU 1
+
+/* exo_vk_matmul weight types; F16 packs two values per 32-bit word, low half first */
+#define EXO_VK_F32 0
+#define EXO_VK_F16 1
+
+#define EXO_VK_NAME_CAPACITY 256
+
+typedef struct ExoVkDeviceInfo {
+    /* NUL-terminated, truncated to fit */
+    char name[EXO_VK_NAME_CAPACITY];
+    char vendor[EXO_VK_NAME_CAPACITY];
+    char driver_version[EXO_VK_NAME_CAPACITY];
+    uint64_t memory_bytes;
+    uint32_t compute_units;
+    float bandwidth_gbps;
+} ExoVkDeviceInfo;
+
+typedef struct ExoVkContext ExoVkContext;
+
+/* Device buffer handle, valid within the context that allocated it */
+typedef uint64_t ExoVkBuffer;
+
+EXO_VK_API uint32_t exo_vk_abi_version(void);
+
+/* Valid until the next failing call on this thread; NULL if none failed */
+EXO_VK_API const char *exo_vk_last_error_message(void);
+
+/* Optional; loader_path may be NULL for the system Vulkan loader */
+EXO_VK_API int32_t exo_vk_init(const char *loader_path);
+EXO_VK_API int32_t exo_vk_shutdown(void);
+
+EXO_VK_API int32_t exo_vk_device_count(uint32_t *count);
+EXO_VK_API int32_t exo_vk_device_info(uint32_t index, ExoVkDeviceInfo *info);
+
+EXO_VK_API int32_t exo_vk_open(uint32_t index, ExoVkContext **ctx);
+EXO_VK_API void exo_vk_close(ExoVkContext *ctx);
+
+EXO_VK_API int32_t exo_vk_alloc(const ExoVkContext *ctx, uint64_t size, ExoVkBuffer *buffer);
+EXO_VK_API int32_t exo_vk_free(const ExoVkContext *ctx, ExoVkBuffer buffer);
+EXO_VK_API int32_t exo_vk_buffer_size(const ExoVkContext *ctx, ExoVkBuffer buffer, uint64_t *size);
+
+EXO_VK_API int32_t exo_vk_copy_to_device(const ExoVkContext *ctx, ExoVkBuffer buffer, uint64_t offset,
+                                         const void *data, uint64_t size);
+EXO_VK_API int32_t exo_vk_copy_from_device(const ExoVkContext *ctx, ExoVkBuffer buffer, uint64_t offset,
+                                           void *data, uint64_t size);
+
+/* Kernels take f32 buffers and return once the op has completed */
+EXO_VK_API int32_t exo_vk_elementwise(const ExoVkContext *ctx, int32_t op, ExoVkBuffer a, ExoVkBuffer b,
+                                      ExoVkBuffer out, uint32_t len);
+EXO_VK_API int32_t exo_vk_activation(const ExoVkContext *ctx, int32_t activation, ExoVkBuffer x,
+                                     ExoVkBuffer out, uint32_t len);
+EXO_VK_API int32_t exo_vk_softmax(const ExoVkContext *ctx, ExoVkBuffer x, ExoVkBuffer out, uint32_t rows,
+                                  uint32_t cols);
+EXO_VK_API int32_t exo_vk_rms_norm(const ExoVkContext *ctx, ExoVkBuffer x, ExoVkBuffer weight, ExoVkBuffer out,
+                                   uint32_t rows, uint32_t cols, float eps);
+/* out[m, n] = a[m, k] x b[k, n], row-major */
+EXO_VK_API int32_t exo_vk_matmul(const ExoVkContext *ctx, ExoVkBuffer a, ExoVkBuffer b, ExoVkBuffer out,
+                                 uint32_t m, uint32_t n, uint32_t k, int32_t b_dtype);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* EXO_VK_H */
@@ -0,0 +1,178 @@
+//! Per-device state behind an `ExoVkContext*`
+//!
+//! A context owns one logical device with its allocator, command pool and
+//! kernel registry. Every call locks it, so a context may be shared between
+//! threads; calls on one context run one at a time and complete before
+//! returning.
+
+use std::sync::Arc;
+
+use ash::vk;
+use parking_lot::Mutex;
+
+use exo_vulkan_binding::VulkanContext;
+use exo_vulkan_binding::command::CommandPool;
+#[cfg(feature = "kernels-core")]
+use exo_vulkan_binding::command::Fence;
+use exo_vulkan_binding::device::{DeviceConfig, LogicalDevice};
+#[cfg(feature = "kernels-core")]
+use exo_vulkan_binding::kernel_library::{KernelRegistry, LibraryResult};
+#[cfg(feature = "kernels-core")]
+use exo_vulkan_binding::memory::BufferRange;
+use exo_vulkan_binding::memory::{AllocationInfo, MemoryAllocator, MemoryUsage, find_memory_type};
+
+use crate::status::{CError, CResult};
+
+/// Longest a dispatch may run before it is reported as `Timeout`
+pub const DISPATCH_TIMEOUT_MS: u64 = 10_000;
+
+/// Memory type of C buffers, device local where the device has one
+pub fn device_local_memory_type(properties: &vk::PhysicalDeviceMemoryProperties) -> u32 {
+    find_memory_type(properties, u32::MAX, MemoryUsage::DeviceLocal).unwrap_or(0)
+}
+
+/// Allocator handle id of C buffer `buffer`
+fn handle_id(buffer: u64) -> String {
+    format!("c:{}", buffer)
+}
+
+// Fields drop in order: everything created from `device` goes first
+struct Inner {
+    #[cfg(feature = "kernels-core")]
+    kernels: KernelRegistry,
+    allocator: MemoryAllocator,
+    pool: CommandPool,
+    memory_type: u32,
+    next_buffer: u64,
+    device: LogicalDevice,
+}
+
+/// Opaque context handed to C callers
+pub struct ExoVkContext {
+    inner: Mutex<Inner>,
+}
+
+impl ExoVkContext {
+    /// Open physical device `index` of `vulkan`
+    pub fn open(vulkan: &Arc<VulkanContext>, index: usize) -> CResult<Self> {
+        let device = LogicalDevice::create(vulkan, index, &DeviceConfig::default())?;
+        let pool = device.command_pool()?;
+        Ok(Self {
+            inner: Mutex::new(Inner {
+                #[cfg(feature = "kernels-core")]
+                kernels: KernelRegistry::new(device.device(), vk::PipelineCache::null()),
+                allocator: device.memory_allocator(),
+                pool,
+                memory_type: device_local_memory_type(device.memory_properties()),
+                next_buffer: 1,
+                device,
+            }),
+        })
+    }
+
+    /// Allocate `size` bytes of device memory
+    pub fn alloc(&self, size: u64) -> CResult<u64> {
+        if size == 0 {
+            return Err(CError::InvalidArgument("size must be > 0".to_string()));
+        }
+        let mut inner = self.inner.lock();
+        let buffer = inner.next_buffer;
+        let memory_type = inner.memory_type;
+        inner.allocator.allocate(size, memory_type, handle_id(buffer))?;
+        inner.next_buffer += 1;
+        Ok(buffer)
+    }
+
+    pub fn free(&self, buffer: u64) -> CResult<()> {
+        let mut inner = self.inner.lock();
+        lookup(&inner.allocator, buffer)?;
+        inner.allocator.deallocate(&handle_id(buffer))?;
+        Ok(())
+    }
+
+    /// Size in bytes of `buffer`
+    pub fn size(&self, buffer: u64) -> CResult<u64> {
+        Ok(lookup(&self.inner.lock().allocator, buffer)?.size)
+    }
+
+    /// Write `data` into `buffer` starting at byte `offset`
+    pub fn write(&self, buffer: u64, offset: u64, data: &[u8]) -> CResult<()> {
+        let inner = self.inner.lock();
+        let view = lookup(&inner.allocator, buffer)?.view(offset, data.len() as u64)?;
+        let transfer = inner.device.data_transfer(&inner.pool);
+        // SAFETY: the view is of a live allocation, which the lock keeps
+        // alive until the copy has completed
+        unsafe { transfer.copy_to_device(data, &view) }?;
+        Ok(())
+    }
+
+    /// Read `out.len()` bytes of `buffer` starting at byte `offset`
+    pub fn read(&self, buffer: u64, offset: u64, out: &mut [u8]) -> CResult<()> {
+        let inner = self.inner.lock();
+        let view = lookup(&inner.allocator, buffer)?.view(offset, out.len() as u64)?;
+        let transfer = inner.device.data_transfer(&inner.pool);
+        // SAFETY: as for `write`; the view is exactly `out.len()` bytes
+        let bytes = unsafe { transfer.copy_from_device(&view, out.len() as u64) }?;
+        out.copy_from_slice(&bytes);
+        Ok(())
+    }
+
+    /// Record one op on the ranges of `buffers`, submit it and wait for it
+    #[cfg(feature = "kernels-core")]
+    pub fn dispatch(
+        &self,
+        buffers: &[u64],
+        record: impl FnOnce(&mut KernelRegistry, vk::CommandBuffer, &[BufferRange]) -> LibraryResult<()>,
+    ) -> CResult<()> {
+        let mut inner = self.inner.lock();
+        let ranges = buffers
+            .iter()
+            .map(|&buffer| Ok(lookup(&inner.allocator, buffer)?.range()))
+            .collect::<CResult<Vec<_>>>()?;
+
+        let Inner {
+            kernels, pool, device, ..
+        } = &mut *inner;
+        let cmd = pool.allocate_buffers(1)?[0];
+        let completed = (|| -> CResult<bool> {
+            pool.begin_recording(cmd, vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
+            record(kernels, cmd, &ranges)?;
+            pool.end_recording(cmd)?;
+            let fence = Fence::new(device.device().clone(), false)?;
+            device.compute_queue().submit(&[cmd], None, None, Some(fence.raw()))?;
+            Ok(fence.wait(DISPATCH_TIMEOUT_MS * 1_000_000)?)
+        })();
+        if matches!(completed, Ok(false)) {
+            // The command buffer may still be pending; leak it rather than free it
+            return Err(CError::Timeout(DISPATCH_TIMEOUT_MS));
+        }
+        // SAFETY: the submission completed, was never made, or the device was lost
+        unsafe { device.device().free_command_buffers(pool.raw(), &[cmd]) };
+        kernels.release_sets()?;
+        completed.map(|_| ())
+    }
+}
+
+fn lookup(allocator: &MemoryAllocator, buffer: u64) -> CResult<&AllocationInfo> {
+    allocator
+        .get_allocation(&handle_id(buffer))
+        .map_err(|_| CError::UnknownBuffer(buffer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_local_memory_type() {
+        let mut props = vk::PhysicalDeviceMemoryProperties {
+            memory_type_count: 3,
+            ..Default::default()
+        };
+        assert_eq!(device_local_memory_type(&props), 0);
+        props.memory_types[1].property_flags = vk::MemoryPropertyFlags::HOST_VISIBLE;
+        props.memory_types[2].property_flags = vk::MemoryPropertyFlags::DEVICE_LOCAL;
+        assert_eq!(device_local_memory_type(&props), 2);
+        assert_eq!(handle_id(7), "c:7");
+    }
+}
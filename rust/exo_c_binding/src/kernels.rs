@@ -0,0 +1,231 @@
+//! Kernel dispatch entry points
+//!
+//! Each call records one op from `kernel_library`, submits it and waits for
+//! it, so outputs can be read back as soon as it returns. Buffers hold f32
+//! values unless noted. Builds without the `kernels-core` feature export the
+//! same symbols, which return `EXO_VK_UNSUPPORTED`.
+
+#[cfg(feature = "kernels-core")]
+use exo_vulkan_binding::kernel_library::{Activation, Elementwise, MatmulDims};
+
+use crate::context::ExoVkContext;
+#[cfg(feature = "kernels-core")]
+use crate::context;
+use crate::status::{self, CError};
+#[cfg(feature = "kernels-core")]
+use crate::status::CResult;
+
+/// `exo_vk_elementwise` ops
+pub const OP_ADD: i32 = 0;
+pub const OP_MUL: i32 = 1;
+
+/// `exo_vk_activation` functions
+pub const ACTIVATION_SILU: i32 = 0;
+pub const ACTIVATION_GELU: i32 = 1;
+
+/// `exo_vk_matmul` weight types
+pub const DTYPE_F32: i32 = 0;
+/// Two f16 values per 32-bit word, low half first
+pub const DTYPE_F16: i32 = 1;
+
+#[cfg(feature = "kernels-core")]
+fn elementwise_op(op: i32) -> CResult<Elementwise> {
+    match op {
+        OP_ADD => Ok(Elementwise::Add),
+        OP_MUL => Ok(Elementwise::Mul),
+        _ => Err(CError::InvalidArgument(format!("unknown elementwise op {}", op))),
+    }
+}
+
+#[cfg(feature = "kernels-core")]
+fn activation_fn(activation: i32) -> CResult<Activation> {
+    match activation {
+        ACTIVATION_SILU => Ok(Activation::Silu),
+        ACTIVATION_GELU => Ok(Activation::Gelu),
+        _ => Err(CError::InvalidArgument(format!("unknown activation {}", activation))),
+    }
+}
+
+#[cfg(not(feature = "kernels-core"))]
+fn unsupported(entry_point: &str) -> i32 {
+    status::call(entry_point, || Err(CError::Unsupported("kernels-core")))
+}
+
+/// `out = a op b` over `len` values; `op` is `EXO_VK_OP_ADD` or `EXO_VK_OP_MUL`
+///
+/// # Safety Requirements
+/// - `ctx` must be a context from `exo_vk_open` not yet closed
+#[cfg(feature = "kernels-core")]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn exo_vk_elementwise(ctx: *const ExoVkContext, op: i32, a: u64, b: u64, out: u64, len: u32) -> i32 {
+    status::call("exo_vk_elementwise", || {
+        let op = elementwise_op(op)?;
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe { context(ctx) }?.dispatch(&[a, b, out], |kernels, cmd, r| {
+            // SAFETY: cmd is recording and the context's lock keeps the buffers alive until it completes
+            unsafe { kernels.elementwise(cmd, op, &r[0], &r[1], &r[2], len) }
+        })
+    })
+}
+
+/// `exo_vk_elementwise` in builds without the `kernels-core` feature
+///
+/// # Safety Requirements
+/// - None; the arguments are not used
+#[cfg(not(feature = "kernels-core"))]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn exo_vk_elementwise(_ctx: *const ExoVkContext, _op: i32, _a: u64, _b: u64, _out: u64, _len: u32) -> i32 {
+    unsupported("exo_vk_elementwise")
+}
+
+/// `out = activation(x)` over `len` values
+///
+/// # Safety Requirements
+/// - `ctx` must be a context from `exo_vk_open` not yet closed
+#[cfg(feature = "kernels-core")]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn exo_vk_activation(ctx: *const ExoVkContext, activation: i32, x: u64, out: u64, len: u32) -> i32 {
+    status::call("exo_vk_activation", || {
+        let activation = activation_fn(activation)?;
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe { context(ctx) }?.dispatch(&[x, out], |kernels, cmd, r| {
+            // SAFETY: as for `exo_vk_elementwise`
+            unsafe { kernels.activation(cmd, activation, &r[0], &r[1], len) }
+        })
+    })
+}
+
+/// `exo_vk_activation` in builds without the `kernels-core` feature
+///
+/// # Safety Requirements
+/// - None; the arguments are not used
+#[cfg(not(feature = "kernels-core"))]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn exo_vk_activation(_ctx: *const ExoVkContext, _activation: i32, _x: u64, _out: u64, _len: u32) -> i32 {
+    unsupported("exo_vk_activation")
+}
+
+/// Softmax of each row of a `[rows, cols]` matrix
+///
+/// # Safety Requirements
+/// - `ctx` must be a context from `exo_vk_open` not yet closed
+#[cfg(feature = "kernels-core")]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn exo_vk_softmax(ctx: *const ExoVkContext, x: u64, out: u64, rows: u32, cols: u32) -> i32 {
+    status::call("exo_vk_softmax", || {
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe { context(ctx) }?.dispatch(&[x, out], |kernels, cmd, r| {
+            // SAFETY: as for `exo_vk_elementwise`
+            unsafe { kernels.softmax(cmd, &r[0], &r[1], rows, cols) }
+        })
+    })
+}
+
+/// `exo_vk_softmax` in builds without the `kernels-core` feature
+///
+/// # Safety Requirements
+/// - None; the arguments are not used
+#[cfg(not(feature = "kernels-core"))]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn exo_vk_softmax(_ctx: *const ExoVkContext, _x: u64, _out: u64, _rows: u32, _cols: u32) -> i32 {
+    unsupported("exo_vk_softmax")
+}
+
+/// RMSNorm of each row of a `[rows, cols]` matrix, scaled by `weight[cols]`
+///
+/// # Safety Requirements
+/// - `ctx` must be a context from `exo_vk_open` not yet closed
+#[cfg(feature = "kernels-core")]
+#[unsafe(no_mangle)]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn exo_vk_rms_norm(
+    ctx: *const ExoVkContext,
+    x: u64,
+    weight: u64,
+    out: u64,
+    rows: u32,
+    cols: u32,
+    eps: f32,
+) -> i32 {
+    status::call("exo_vk_rms_norm", || {
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe { context(ctx) }?.dispatch(&[x, weight, out], |kernels, cmd, r| {
+            // SAFETY: as for `exo_vk_elementwise`
+            unsafe { kernels.rms_norm(cmd, &r[0], &r[1], &r[2], rows, cols, eps) }
+        })
+    })
+}
+
+/// `exo_vk_rms_norm` in builds without the `kernels-core` feature
+///
+/// # Safety Requirements
+/// - None; the arguments are not used
+#[cfg(not(feature = "kernels-core"))]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn exo_vk_rms_norm(
+    _ctx: *const ExoVkContext,
+    _x: u64,
+    _weight: u64,
+    _out: u64,
+    _rows: u32,
+    _cols: u32,
+    _eps: f32,
+) -> i32 {
+    unsupported("exo_vk_rms_norm")
+}
+
+/// `out[m, n] = a[m, k] x b[k, n]`, row-major; `b_dtype` is `EXO_VK_F32` or `EXO_VK_F16`
+///
+/// # Safety Requirements
+/// - `ctx` must be a context from `exo_vk_open` not yet closed
+#[cfg(feature = "kernels-core")]
+#[unsafe(no_mangle)]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn exo_vk_matmul(
+    ctx: *const ExoVkContext,
+    a: u64,
+    b: u64,
+    out: u64,
+    m: u32,
+    n: u32,
+    k: u32,
+    b_dtype: i32,
+) -> i32 {
+    status::call("exo_vk_matmul", || {
+        if b_dtype != DTYPE_F32 && b_dtype != DTYPE_F16 {
+            return Err(CError::InvalidArgument(format!("unknown weight dtype {}", b_dtype)));
+        }
+        let dims = MatmulDims { m, n, k };
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe { context(ctx) }?.dispatch(&[a, b, out], |kernels, cmd, r| {
+            // SAFETY: as for `exo_vk_elementwise`
+            unsafe {
+                if b_dtype == DTYPE_F16 {
+                    kernels.matmul_f16(cmd, &r[0], &r[1], &r[2], dims)
+                } else {
+                    kernels.matmul(cmd, &r[0], &r[1], &r[2], dims)
+                }
+            }
+        })
+    })
+}
+
+/// `exo_vk_matmul` in builds without the `kernels-core` feature
+///
+/// # Safety Requirements
+/// - None; the arguments are not used
+#[cfg(not(feature = "kernels-core"))]
+#[unsafe(no_mangle)]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn exo_vk_matmul(
+    _ctx: *const ExoVkContext,
+    _a: u64,
+    _b: u64,
+    _out: u64,
+    _m: u32,
+    _n: u32,
+    _k: u32,
+    _b_dtype: i32,
+) -> i32 {
+    unsupported("exo_vk_matmul")
+}
@@ -0,0 +1,371 @@
+//! C ABI for the exo Vulkan backend
+//!
+//! Desktop hosts (the Python exo orchestrator through ctypes or cffi, C and
+//! C++ runners) load `libexo_c_binding.so` / `.dylib` and drive the same
+//! backend Android apps reach through JNI: Vulkan initialization, device
+//! enumeration, device buffers, host copies and the built-in kernels.
+//!
+//! `include/exo_vk.h` declares every entry point and type. It is the stable
+//! contract: exported signatures and status values only change together with
+//! `ABI_VERSION` and `EXO_VK_ABI_VERSION` in the header.
+//!
+//! Apart from the version and error-message getters, each function returns
+//! an `ExoVkStatus` (0 on success), writes results through out-pointers and
+//! never unwinds into the caller.
+
+#![allow(unsafe_code, clippy::missing_inline_in_public_items)]
+
+pub mod context;
+pub mod kernels;
+pub mod status;
+
+use std::ffi::{CStr, c_char, c_void};
+use std::path::PathBuf;
+
+use exo_vulkan_binding::{DeviceInfo, initialize_vulkan, reset_vulkan, set_loader_path};
+
+pub use context::ExoVkContext;
+use status::{CError, CResult};
+
+/// ABI revision of `exo_vk.h`; bump whenever an exported signature changes
+pub const ABI_VERSION: u32 = 1;
+
+/// Capacity, including the NUL, of the strings in `ExoVkDeviceInfo`
+pub const NAME_CAPACITY: usize = 256;
+
+/// One physical device, as reported by `exo_vk_device_info`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct ExoVkDeviceInfo {
+    /// NUL-terminated, truncated to fit
+    pub name: [c_char; NAME_CAPACITY],
+    pub vendor: [c_char; NAME_CAPACITY],
+    pub driver_version: [c_char; NAME_CAPACITY],
+    pub memory_bytes: u64,
+    pub compute_units: u32,
+    pub bandwidth_gbps: f32,
+}
+
+impl From<&DeviceInfo> for ExoVkDeviceInfo {
+    fn from(info: &DeviceInfo) -> Self {
+        Self {
+            name: c_string_field(&info.name),
+            vendor: c_string_field(&info.vendor),
+            driver_version: c_string_field(&info.driver_version),
+            memory_bytes: info.total_memory_bytes,
+            compute_units: info.compute_units,
+            bandwidth_gbps: info.bandwidth_gbps,
+        }
+    }
+}
+
+/// `value` as a NUL-terminated array, cut at a character boundary if too long
+fn c_string_field(value: &str) -> [c_char; NAME_CAPACITY] {
+    let mut field = [0 as c_char; NAME_CAPACITY];
+    let mut len = value.len().min(NAME_CAPACITY - 1);
+    while !value.is_char_boundary(len) {
+        len -= 1;
+    }
+    for (dst, &src) in field.iter_mut().zip(&value.as_bytes()[..len]) {
+        *dst = src as c_char;
+    }
+    field
+}
+
+/// Write `value` through the out-pointer `out`
+///
+/// # Safety Requirements
+/// - `out` must be null or valid for a write of `T`
+unsafe fn write_out<T>(out: *mut T, value: T, what: &str) -> CResult<()> {
+    if out.is_null() {
+        return Err(CError::InvalidArgument(format!("null {} out-pointer", what)));
+    }
+    // SAFETY: non-null, and writable per the caller
+    unsafe { out.write(value) };
+    Ok(())
+}
+
+/// The context behind `ctx`
+///
+/// # Safety Requirements
+/// - `ctx` must be null or a context from `exo_vk_open` not yet closed
+unsafe fn context<'a>(ctx: *const ExoVkContext) -> CResult<&'a ExoVkContext> {
+    // SAFETY: forwarded from the caller's guarantees
+    unsafe { ctx.as_ref() }.ok_or_else(|| CError::InvalidArgument("null context".to_string()))
+}
+
+fn devices() -> CResult<Vec<DeviceInfo>> {
+    Ok(initialize_vulkan()?.enumerate_devices()?)
+}
+
+/// ABI revision the library was built with; compare with `EXO_VK_ABI_VERSION`
+#[unsafe(no_mangle)]
+pub extern "C" fn exo_vk_abi_version() -> u32 {
+    ABI_VERSION
+}
+
+/// Message of the last failed call on the calling thread
+///
+/// Returns null if no call on this thread has failed. The string stays
+/// valid until the next failing call on the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn exo_vk_last_error_message() -> *const c_char {
+    status::last_error_ptr()
+}
+
+/// Initialize Vulkan, optionally from an explicit loader library
+///
+/// Other entry points initialize with the system loader on first use; call
+/// this first to pick another one. Calling it again is a no-op unless it
+/// names a loader, which fails while Vulkan is initialized.
+///
+/// # Safety Requirements
+/// - `loader_path` must be null or a NUL-terminated string
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn exo_vk_init(loader_path: *const c_char) -> i32 {
+    status::call("exo_vk_init", || {
+        if !loader_path.is_null() {
+            // SAFETY: non-null and NUL-terminated per the caller
+            let path = unsafe { CStr::from_ptr(loader_path) }.to_string_lossy().into_owned();
+            if !path.is_empty() {
+                set_loader_path(Some(PathBuf::from(path)))?;
+            }
+        }
+        initialize_vulkan()?;
+        Ok(())
+    })
+}
+
+/// Drop the global Vulkan instance so the next call re-initializes
+///
+/// Open contexts keep the instance alive until they are closed.
+#[unsafe(no_mangle)]
+pub extern "C" fn exo_vk_shutdown() -> i32 {
+    status::call("exo_vk_shutdown", || {
+        reset_vulkan();
+        Ok(())
+    })
+}
+
+/// Number of physical devices
+///
+/// # Safety Requirements
+/// - `count` must be valid for a write
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn exo_vk_device_count(count: *mut u32) -> i32 {
+    status::call("exo_vk_device_count", || {
+        let devices = devices()?;
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe { write_out(count, devices.len() as u32, "count") }
+    })
+}
+
+/// Description of physical device `index`
+///
+/// # Safety Requirements
+/// - `info` must be valid for a write of `ExoVkDeviceInfo`
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn exo_vk_device_info(index: u32, info: *mut ExoVkDeviceInfo) -> i32 {
+    status::call("exo_vk_device_info", || {
+        let devices = devices()?;
+        let device = devices
+            .get(index as usize)
+            .ok_or_else(|| exo_vulkan_binding::VulkanError::DeviceNotFound(format!("vulkan:{}", index)))?;
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe { write_out(info, device.into(), "info") }
+    })
+}
+
+/// Open physical device `index`; release the context with `exo_vk_close`
+///
+/// # Safety Requirements
+/// - `ctx` must be valid for a write of a pointer
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn exo_vk_open(index: u32, ctx: *mut *mut ExoVkContext) -> i32 {
+    status::call("exo_vk_open", || {
+        if ctx.is_null() {
+            return Err(CError::InvalidArgument("null ctx out-pointer".to_string()));
+        }
+        let context = ExoVkContext::open(&initialize_vulkan()?, index as usize)?;
+        // SAFETY: checked non-null above
+        unsafe { write_out(ctx, Box::into_raw(Box::new(context)), "ctx") }
+    })
+}
+
+/// Close a context, freeing its buffers and device; null is ignored
+///
+/// # Safety Requirements
+/// - `ctx` must be null or a context from `exo_vk_open` not yet closed,
+///   with no call on it in progress
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn exo_vk_close(ctx: *mut ExoVkContext) {
+    if !ctx.is_null() {
+        // SAFETY: allocated by `exo_vk_open` and, per the caller, closed once
+        drop(unsafe { Box::from_raw(ctx) });
+    }
+}
+
+/// Allocate `size` bytes of device memory
+///
+/// # Safety Requirements
+/// - `ctx` must be a context from `exo_vk_open` not yet closed
+/// - `buffer` must be valid for a write
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn exo_vk_alloc(ctx: *const ExoVkContext, size: u64, buffer: *mut u64) -> i32 {
+    status::call("exo_vk_alloc", || {
+        // SAFETY: forwarded from the caller's guarantees
+        let context = unsafe { context(ctx) }?;
+        if buffer.is_null() {
+            return Err(CError::InvalidArgument("null buffer out-pointer".to_string()));
+        }
+        let allocated = context.alloc(size)?;
+        // SAFETY: checked non-null above
+        unsafe { write_out(buffer, allocated, "buffer") }
+    })
+}
+
+/// Free a buffer from `exo_vk_alloc`
+///
+/// # Safety Requirements
+/// - `ctx` must be a context from `exo_vk_open` not yet closed
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn exo_vk_free(ctx: *const ExoVkContext, buffer: u64) -> i32 {
+    status::call("exo_vk_free", || {
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe { context(ctx) }?.free(buffer)
+    })
+}
+
+/// Size in bytes of a buffer
+///
+/// # Safety Requirements
+/// - `ctx` must be a context from `exo_vk_open` not yet closed
+/// - `size` must be valid for a write
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn exo_vk_buffer_size(ctx: *const ExoVkContext, buffer: u64, size: *mut u64) -> i32 {
+    status::call("exo_vk_buffer_size", || {
+        // SAFETY: forwarded from the caller's guarantees
+        let bytes = unsafe { context(ctx) }?.size(buffer)?;
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe { write_out(size, bytes, "size") }
+    })
+}
+
+/// Copy `size` bytes from `data` into `buffer` at byte `offset`
+///
+/// # Safety Requirements
+/// - `ctx` must be a context from `exo_vk_open` not yet closed
+/// - `data` must be readable for `size` bytes
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn exo_vk_copy_to_device(
+    ctx: *const ExoVkContext,
+    buffer: u64,
+    offset: u64,
+    data: *const c_void,
+    size: u64,
+) -> i32 {
+    status::call("exo_vk_copy_to_device", || {
+        // SAFETY: forwarded from the caller's guarantees
+        let context = unsafe { context(ctx) }?;
+        if data.is_null() && size > 0 {
+            return Err(CError::InvalidArgument("null data".to_string()));
+        }
+        let data = if size == 0 {
+            &[][..]
+        } else {
+            // SAFETY: non-null and readable for size bytes per the caller
+            unsafe { std::slice::from_raw_parts(data.cast::<u8>(), size as usize) }
+        };
+        context.write(buffer, offset, data)
+    })
+}
+
+/// Copy `size` bytes of `buffer` at byte `offset` into `data`
+///
+/// # Safety Requirements
+/// - `ctx` must be a context from `exo_vk_open` not yet closed
+/// - `data` must be writable for `size` bytes
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn exo_vk_copy_from_device(
+    ctx: *const ExoVkContext,
+    buffer: u64,
+    offset: u64,
+    data: *mut c_void,
+    size: u64,
+) -> i32 {
+    status::call("exo_vk_copy_from_device", || {
+        // SAFETY: forwarded from the caller's guarantees
+        let context = unsafe { context(ctx) }?;
+        if data.is_null() && size > 0 {
+            return Err(CError::InvalidArgument("null data".to_string()));
+        }
+        let out = if size == 0 {
+            &mut [][..]
+        } else {
+            // SAFETY: non-null and writable for size bytes per the caller
+            unsafe { std::slice::from_raw_parts_mut(data.cast::<u8>(), size as usize) }
+        };
+        context.read(buffer, offset, out)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use status::ExoVkStatus;
+
+    /// Every exported function, in header order
+    const EXPORTS: [&str; 18] = [
+        "exo_vk_abi_version",
+        "exo_vk_last_error_message",
+        "exo_vk_init",
+        "exo_vk_shutdown",
+        "exo_vk_device_count",
+        "exo_vk_device_info",
+        "exo_vk_open",
+        "exo_vk_close",
+        "exo_vk_alloc",
+        "exo_vk_free",
+        "exo_vk_buffer_size",
+        "exo_vk_copy_to_device",
+        "exo_vk_copy_from_device",
+        "exo_vk_elementwise",
+        "exo_vk_activation",
+        "exo_vk_softmax",
+        "exo_vk_rms_norm",
+        "exo_vk_matmul",
+    ];
+
+    #[test]
+    fn test_header_matches_exports() {
+        let header = include_str!("../include/exo_vk.h");
+        assert!(header.contains(&format!("#define EXO_VK_ABI_VERSION {}", ABI_VERSION)));
+        for name in EXPORTS {
+            assert!(header.contains(&format!("{}(", name)), "{} missing from exo_vk.h", name);
+        }
+        let declared = header.lines().filter(|line| line.starts_with("EXO_VK_API ")).count();
+        assert_eq!(declared, EXPORTS.len(), "exo_vk.h declares functions not listed here");
+        assert!(header.contains(&format!("EXO_VK_NAME_CAPACITY {}", NAME_CAPACITY)));
+        assert!(header.contains(&format!("EXO_VK_BACKEND = {}", ExoVkStatus::Backend as i32)));
+        assert!(header.contains(&format!("EXO_VK_OP_MUL {}", kernels::OP_MUL)));
+        assert!(header.contains(&format!("EXO_VK_F16 {}", kernels::DTYPE_F16)));
+    }
+
+    #[test]
+    fn test_null_arguments_are_rejected() {
+        // SAFETY: null pointers are the inputs under test
+        unsafe {
+            let status = exo_vk_alloc(std::ptr::null(), 64, std::ptr::null_mut());
+            assert_eq!(status, ExoVkStatus::InvalidArgument as i32);
+            assert_eq!(status::last_error().as_deref(), Some("exo_vk_alloc: Invalid argument: null context"));
+            assert_ne!(exo_vk_device_info(0, std::ptr::null_mut()), ExoVkStatus::Ok as i32);
+            exo_vk_close(std::ptr::null_mut());
+        }
+
+        let field = c_string_field(&"é".repeat(200));
+        assert_eq!(field[NAME_CAPACITY - 1], 0);
+        // SAFETY: the field is NUL-terminated
+        let name = unsafe { CStr::from_ptr(field.as_ptr()) };
+        assert_eq!(name.to_str().unwrap(), "é".repeat(127));
+    }
+}
@@ -0,0 +1,182 @@
+//! Status codes and per-thread error messages
+//!
+//! Every entry point returns an `ExoVkStatus` code and writes its results
+//! through out-pointers. Panics are caught at the boundary and reported as
+//! `Panic`. The message of the last failure on each thread is kept for
+//! `exo_vk_last_error_message`.
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::panic::{self, AssertUnwindSafe};
+
+use ash::vk;
+use log::error;
+use thiserror::Error;
+
+use exo_vulkan_binding::VulkanError;
+use exo_vulkan_binding::command::CommandError;
+use exo_vulkan_binding::device::DeviceError;
+#[cfg(feature = "kernels-core")]
+use exo_vulkan_binding::kernel_library::LibraryError;
+use exo_vulkan_binding::memory::MemoryError;
+use exo_vulkan_binding::transfer::TransferError;
+
+/// Status codes; values are part of the ABI and mirrored in `exo_vk.h`
+#[repr(i32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExoVkStatus {
+    Ok = 0,
+    InvalidArgument = 1,
+    BufferTooSmall = 2,
+    Unsupported = 3,
+    /// A panic was caught at the boundary
+    Panic = 4,
+    NotFound = 5,
+    OutOfMemory = 6,
+    /// The device must be closed and reopened
+    DeviceLost = 7,
+    Timeout = 8,
+    /// Any other Vulkan or backend failure
+    Backend = 9,
+}
+
+/// Failures of the C entry points
+#[derive(Error, Debug)]
+pub enum CError {
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+
+    #[error("Unknown buffer {0}")]
+    UnknownBuffer(u64),
+
+    #[error("Not built with {0}")]
+    Unsupported(&'static str),
+
+    #[error("Dispatch did not complete within {0} ms")]
+    Timeout(u64),
+
+    #[error(transparent)]
+    Vulkan(#[from] VulkanError),
+
+    #[error(transparent)]
+    Device(#[from] DeviceError),
+
+    #[error(transparent)]
+    Memory(#[from] MemoryError),
+
+    #[error(transparent)]
+    Transfer(#[from] TransferError),
+
+    #[error(transparent)]
+    Command(#[from] CommandError),
+
+    #[cfg(feature = "kernels-core")]
+    #[error(transparent)]
+    Library(#[from] LibraryError),
+}
+
+pub type CResult<T> = Result<T, CError>;
+
+fn vk_status(result: vk::Result) -> ExoVkStatus {
+    match result {
+        vk::Result::ERROR_OUT_OF_DEVICE_MEMORY | vk::Result::ERROR_OUT_OF_HOST_MEMORY => ExoVkStatus::OutOfMemory,
+        vk::Result::ERROR_DEVICE_LOST => ExoVkStatus::DeviceLost,
+        _ => ExoVkStatus::Backend,
+    }
+}
+
+impl CError {
+    pub fn status(&self) -> ExoVkStatus {
+        match self {
+            CError::InvalidArgument(_) => ExoVkStatus::InvalidArgument,
+            CError::UnknownBuffer(_) => ExoVkStatus::NotFound,
+            CError::Unsupported(_) => ExoVkStatus::Unsupported,
+            CError::Timeout(_) => ExoVkStatus::Timeout,
+            CError::Vulkan(VulkanError::DeviceNotFound(_)) => ExoVkStatus::NotFound,
+            CError::Vulkan(VulkanError::LoaderUnavailable(_) | VulkanError::NoDriver(_)) => ExoVkStatus::Unsupported,
+            CError::Device(DeviceError::Context(VulkanError::DeviceNotFound(_))) => ExoVkStatus::NotFound,
+            CError::Device(DeviceError::NoComputeQueue) => ExoVkStatus::Unsupported,
+            CError::Memory(MemoryError::NotFound(_)) => ExoVkStatus::NotFound,
+            CError::Memory(MemoryError::AllocationFailed(_)) => ExoVkStatus::OutOfMemory,
+            CError::Memory(MemoryError::VulkanError(e)) | CError::Transfer(TransferError::VulkanError(e)) => {
+                vk_status(*e)
+            }
+            CError::Transfer(TransferError::InvalidSize(_)) => ExoVkStatus::BufferTooSmall,
+            CError::Command(CommandError::DeviceLost(_)) => ExoVkStatus::DeviceLost,
+            CError::Command(CommandError::VulkanError(e)) => vk_status(*e),
+            #[cfg(feature = "kernels-core")]
+            CError::Library(LibraryError::ShapeMismatch(_)) => ExoVkStatus::InvalidArgument,
+            _ => ExoVkStatus::Backend,
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Log a failure of `entry_point` and keep its message for this thread
+pub fn set_last_error(entry_point: &str, message: &str) {
+    error!("{} failed: {}", entry_point, message);
+    // Interior NULs would truncate the message on the C side anyway
+    let message = CString::new(format!("{}: {}", entry_point, message).replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// Message of the last failure on this thread
+pub fn last_error() -> Option<String> {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map(|m| m.to_string_lossy().into_owned()))
+}
+
+/// Pointer to the last failure's message, valid until the next failure on this thread
+pub(crate) fn last_error_ptr() -> *const std::ffi::c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(std::ptr::null(), |m| m.as_ptr()))
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+/// Run the body of an entry point, turning errors and panics into status codes
+pub fn call(entry_point: &str, body: impl FnOnce() -> CResult<()>) -> i32 {
+    let status = match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(())) => ExoVkStatus::Ok,
+        Ok(Err(e)) => {
+            set_last_error(entry_point, &e.to_string());
+            e.status()
+        }
+        Err(payload) => {
+            set_last_error(entry_point, &format!("panicked: {}", panic_message(payload.as_ref())));
+            ExoVkStatus::Panic
+        }
+    };
+    status as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_errors_map_to_status_codes() {
+        assert_eq!(call("exo_vk_test", || Ok(())), 0);
+        let status = call("exo_vk_test", || Err(CError::UnknownBuffer(7)));
+        assert_eq!(status, ExoVkStatus::NotFound as i32);
+        assert_eq!(last_error().as_deref(), Some("exo_vk_test: Unknown buffer 7"));
+
+        let lost = CError::Command(CommandError::DeviceLost("wait_for_fences"));
+        assert_eq!(lost.status(), ExoVkStatus::DeviceLost);
+        let oom = CError::Memory(MemoryError::VulkanError(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY));
+        assert_eq!(oom.status(), ExoVkStatus::OutOfMemory);
+
+        #[allow(clippy::panic)]
+        let status = call("exo_vk_test", || panic!("boom"));
+        assert_eq!(status, ExoVkStatus::Panic as i32);
+        assert!(!last_error_ptr().is_null());
+    }
+}
@@ -0,0 +1,143 @@
+//! candle adapter for the exo Vulkan backend
+//!
+//! candle's `Device` and `Storage` are closed enums (CPU, CUDA, Metal), so a
+//! third-party backend cannot be slotted in behind `Tensor` without patching
+//! candle itself. This crate bridges at tensor boundaries instead: candle
+//! model code keeps running unchanged, and tensors move between candle and
+//! the Vulkan allocator (or any DLPack consumer) without per-model glue.
+//! The conversions preserve shapes and strides; values are exchanged as f32,
+//! the only element type the backend's kernels accept.
+
+#![allow(unsafe_code)]
+
+use candle_core::{DType, Device, Tensor};
+use thiserror::Error;
+
+use exo_vulkan_binding::dlpack::{self, DLManagedTensor, DlpackError};
+use exo_vulkan_binding::memory::AllocationInfo;
+use exo_vulkan_binding::tensor::{self as exo_tensor, ELEMENT_SIZE, Layout, TensorError};
+use exo_vulkan_binding::transfer::{DataTransfer, TransferError};
+
+/// Adapter errors
+#[derive(Error, Debug)]
+pub enum AdapterError {
+    #[error("candle error: {0}")]
+    Candle(#[from] candle_core::Error),
+
+    #[error(transparent)]
+    Dlpack(#[from] DlpackError),
+
+    #[error(transparent)]
+    Transfer(#[from] TransferError),
+
+    #[error(transparent)]
+    Tensor(#[from] TensorError),
+}
+
+pub type AdapterResult<T> = Result<T, AdapterError>;
+
+/// exo layout of a candle tensor's view, relative to its start offset
+pub fn layout_of(tensor: &Tensor) -> AdapterResult<Layout> {
+    Ok(Layout::with_strides(tensor.dims(), tensor.stride())?)
+}
+
+/// Row-major f32 copy of `tensor` on the host
+fn host_values(tensor: &Tensor) -> AdapterResult<Vec<f32>> {
+    Ok(tensor
+        .to_device(&Device::Cpu)?
+        .to_dtype(DType::F32)?
+        .flatten_all()?
+        .to_vec1::<f32>()?)
+}
+
+/// Export `tensor` as an owning `kDLCPU` DLPack tensor
+pub fn to_dlpack(tensor: &Tensor) -> AdapterResult<*mut DLManagedTensor> {
+    let layout = Layout::contiguous(tensor.dims());
+    Ok(dlpack::export_host(host_values(tensor)?, &layout)?)
+}
+
+/// Build a candle tensor on `device` from a host DLPack tensor
+///
+/// # Safety Requirements
+/// - Same as `exo_vulkan_binding::dlpack::import`; the tensor is consumed on
+///   success and left with the caller on error
+pub unsafe fn from_dlpack(managed: *mut DLManagedTensor, device: &Device) -> AdapterResult<Tensor> {
+    // SAFETY: forwarded from the caller's guarantees
+    let imported = unsafe { dlpack::import(managed) }?;
+    let values = match imported.to_contiguous() {
+        Ok(values) => values,
+        Err(e) => {
+            imported.into_raw();
+            return Err(e.into());
+        }
+    };
+    Ok(Tensor::from_vec(values, imported.layout().shape().to_vec(), device)?)
+}
+
+/// Upload `tensor` into `destination` as a contiguous f32 device tensor
+///
+/// # Safety Requirements
+/// - Same as `DataTransfer::copy_to_device`
+pub unsafe fn upload(
+    transfer: &DataTransfer,
+    tensor: &Tensor,
+    destination: &AllocationInfo,
+) -> AdapterResult<exo_tensor::Tensor> {
+    let values = host_values(tensor)?;
+    let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_ne_bytes()).collect();
+    let device_tensor = exo_tensor::Tensor::contiguous(destination.range(), tensor.dims())?;
+    // SAFETY: forwarded from the caller's guarantees
+    unsafe { transfer.copy_to_device(&bytes, destination) }?;
+    Ok(device_tensor)
+}
+
+/// Download a device tensor stored at the start of `source` into a candle
+/// tensor on `device`
+///
+/// Strided views are gathered into row-major order on the host.
+///
+/// # Safety Requirements
+/// - Same as `DataTransfer::copy_from_device`
+pub unsafe fn download(
+    transfer: &DataTransfer,
+    source: &AllocationInfo,
+    layout: &Layout,
+    device: &Device,
+) -> AdapterResult<Tensor> {
+    // SAFETY: forwarded from the caller's guarantees
+    let bytes = unsafe { transfer.copy_from_device(source, layout.span() as u64 * ELEMENT_SIZE) }?;
+    let data: Vec<f32> = bytes
+        .chunks_exact(ELEMENT_SIZE as usize)
+        .map(|b| f32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+    let values = if layout.is_contiguous() {
+        data
+    } else {
+        (0..layout.numel()).map(|i| data[layout.offset_of(i)]).collect()
+    };
+    Ok(Tensor::from_vec(values, layout.shape().to_vec(), device)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout_of_transpose() {
+        let tensor = Tensor::arange(0f32, 6.0, &Device::Cpu).unwrap().reshape((2, 3)).unwrap();
+        let transposed = tensor.t().unwrap();
+        let layout = layout_of(&transposed).unwrap();
+        assert_eq!(layout.shape(), &[3, 2]);
+        assert_eq!(layout.strides(), &[1, 3]);
+    }
+
+    #[test]
+    fn test_dlpack_round_trip() {
+        let tensor = Tensor::arange(0f32, 6.0, &Device::Cpu).unwrap().reshape((2, 3)).unwrap();
+        let managed = to_dlpack(&tensor.t().unwrap()).unwrap();
+        // SAFETY: freshly exported and consumed once
+        let back = unsafe { from_dlpack(managed, &Device::Cpu) }.unwrap();
+        assert_eq!(back.dims(), &[3, 2]);
+        assert_eq!(back.to_vec2::<f32>().unwrap(), vec![vec![0.0, 3.0], vec![1.0, 4.0], vec![2.0, 5.0]]);
+    }
+}
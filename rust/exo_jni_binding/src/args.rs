@@ -0,0 +1,174 @@
+//! Checked conversion of JNI integer arguments
+//!
+//! Java has no unsigned integers, so every size, count and index crosses
+//! the boundary as a signed `jint` or `jlong`, and a bare `as usize` turns
+//! -1 into an enormous allocation or an out-of-bounds index. Entry points
+//! convert their integer arguments here instead of checking them ad hoc:
+//! each function names the argument in its error and rejects negative
+//! values, zero where it is meaningless, and values past the limit the
+//! caller supplies (device count, allocation size, workgroup limit).
+//!
+//! `ArgError` converts into the `String` errors the entry points throw, so
+//! a conversion is a single `?`.
+
+use std::ops::RangeInclusive;
+
+use thiserror::Error;
+
+/// Invalid JNI argument
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ArgError {
+    #[error("{name} must be >= 0, got {value}")]
+    Negative { name: &'static str, value: i64 },
+
+    #[error("{name} must be > 0")]
+    Zero { name: &'static str },
+
+    #[error("{name} {value} is outside {min}..={max}")]
+    OutOfRange {
+        name: &'static str,
+        value: i64,
+        min: u64,
+        max: u64,
+    },
+
+    #[error("{name} {value} is out of bounds for length {len}")]
+    OutOfBounds { name: &'static str, value: i64, len: usize },
+
+    #[error("{name} {value} does not fit this platform's address space")]
+    TooLarge { name: &'static str, value: i64 },
+}
+
+pub type ArgResult<T> = Result<T, ArgError>;
+
+impl From<ArgError> for String {
+    fn from(e: ArgError) -> Self {
+        e.to_string()
+    }
+}
+
+/// `value` as a u64, rejecting negatives
+pub fn non_negative(name: &'static str, value: impl Into<i64>) -> ArgResult<u64> {
+    let value = value.into();
+    u64::try_from(value).map_err(|_| ArgError::Negative { name, value })
+}
+
+/// `value` as a u64, rejecting negatives and zero
+pub fn positive(name: &'static str, value: impl Into<i64>) -> ArgResult<u64> {
+    match non_negative(name, value)? {
+        0 => Err(ArgError::Zero { name }),
+        value => Ok(value),
+    }
+}
+
+/// `value` as a u64 within `range`
+pub fn in_range(name: &'static str, value: impl Into<i64>, range: RangeInclusive<u64>) -> ArgResult<u64> {
+    let value = value.into();
+    let checked = non_negative(name, value)?;
+    if !range.contains(&checked) {
+        return Err(ArgError::OutOfRange {
+            name,
+            value,
+            min: *range.start(),
+            max: *range.end(),
+        });
+    }
+    Ok(checked)
+}
+
+/// `value` as a usize, rejecting negatives and values past the address space
+pub fn count(name: &'static str, value: impl Into<i64>) -> ArgResult<usize> {
+    let value = value.into();
+    usize::try_from(non_negative(name, value)?).map_err(|_| ArgError::TooLarge { name, value })
+}
+
+/// `value` as a usize, rejecting negatives, zero and values past the address space
+pub fn positive_count(name: &'static str, value: impl Into<i64>) -> ArgResult<usize> {
+    match count(name, value)? {
+        0 => Err(ArgError::Zero { name }),
+        value => Ok(value),
+    }
+}
+
+/// `value` as an index into something of `len` elements
+pub fn index(name: &'static str, value: impl Into<i64>, len: usize) -> ArgResult<usize> {
+    let value = value.into();
+    match count(name, value) {
+        Ok(i) if i < len => Ok(i),
+        Ok(_) => Err(ArgError::OutOfBounds { name, value, len }),
+        Err(e) => Err(e),
+    }
+}
+
+/// `value` as a u32, rejecting negatives
+pub fn non_negative_u32(name: &'static str, value: i32) -> ArgResult<u32> {
+    u32::try_from(value).map_err(|_| ArgError::Negative {
+        name,
+        value: value.into(),
+    })
+}
+
+/// `value` as a u32, rejecting negatives and zero
+pub fn positive_u32(name: &'static str, value: i32) -> ArgResult<u32> {
+    match non_negative_u32(name, value)? {
+        0 => Err(ArgError::Zero { name }),
+        value => Ok(value),
+    }
+}
+
+/// `value` as a u32 within `range`
+pub fn in_range_u32(name: &'static str, value: i32, range: RangeInclusive<u32>) -> ArgResult<u32> {
+    let checked = non_negative_u32(name, value)?;
+    if !range.contains(&checked) {
+        return Err(ArgError::OutOfRange {
+            name,
+            value: value.into(),
+            min: (*range.start()).into(),
+            max: (*range.end()).into(),
+        });
+    }
+    Ok(checked)
+}
+
+/// A token id, with -1 meaning none
+pub fn optional_id(name: &'static str, value: i32) -> ArgResult<Option<u32>> {
+    match value {
+        -1 => Ok(None),
+        value => non_negative_u32(name, value).map(Some),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversions_reject_invalid_values() {
+        assert_eq!(non_negative("size", 0i64), Ok(0));
+        assert_eq!(non_negative("size", -1i64), Err(ArgError::Negative { name: "size", value: -1 }));
+        assert_eq!(positive("size", 0), Err(ArgError::Zero { name: "size" }));
+        assert_eq!(positive_count("dim", 3), Ok(3));
+        assert_eq!(count("k", i32::MIN).unwrap_err().to_string(), "k must be >= 0, got -2147483648");
+
+        assert_eq!(in_range("groups", 65_535, 1..=65_535), Ok(65_535));
+        assert!(matches!(in_range("groups", 0, 1..=65_535), Err(ArgError::OutOfRange { .. })));
+        assert_eq!(index("device index", 1, 2), Ok(1));
+        assert_eq!(
+            index("device index", 2, 2),
+            Err(ArgError::OutOfBounds {
+                name: "device index",
+                value: 2,
+                len: 2
+            })
+        );
+
+        assert_eq!(positive_u32("width", 0), Err(ArgError::Zero { name: "width" }));
+        assert_eq!(in_range_u32("groups", 65_535, 1..=65_535), Ok(65_535));
+        assert!(matches!(in_range_u32("groups", 65_536, 1..=65_535), Err(ArgError::OutOfRange { .. })));
+        assert_eq!(optional_id("stop_token", -1), Ok(None));
+        assert_eq!(optional_id("stop_token", 7), Ok(Some(7)));
+        assert!(optional_id("stop_token", -2).is_err());
+        let message: String = ArgError::Zero { name: "rows" }.into();
+        assert_eq!(message, "rows must be > 0");
+    }
+}
@@ -0,0 +1,468 @@
+//! Per-device state behind JNI allocation handles
+//!
+//! The first allocation on a device opens its `DeviceContext`: one logical
+//! device with its allocator, command pool, kernel registry and the
+//! pipelines of kernels dispatched by name, as `exo_c_binding` keeps per
+//! `ExoVkContext`. A JNI handle id is also the handle of its allocation in
+//! the context's allocator. Every call locks the context, so copies and
+//! dispatches on one device run one at a time and complete before
+//! returning, and a buffer cannot be freed while work using it is pending.
+//!
+//! Where the driver supports `VK_KHR_external_memory_fd`, allocations are
+//! made exportable so `prepareHandover` can pass them to a replacement
+//! process; each then has its own device memory rather than a share of a
+//! block. On Android, devices are also opened with HardwareBuffer import
+//! enabled where the driver supports it.
+//!
+//! Contexts live until `close_all` (`shutdown`). Each keeps its Vulkan
+//! instance alive, so handles survive `rescanDevices`.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use ash::vk;
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use thiserror::Error;
+
+use exo_vulkan_binding::VulkanContext;
+use exo_vulkan_binding::command::{CommandError, CommandPool, Fence};
+use exo_vulkan_binding::device::{DeviceConfig, DeviceError, LogicalDevice};
+#[cfg(feature = "kernels-core")]
+use exo_vulkan_binding::activation_codec;
+#[cfg(feature = "kernels-core")]
+use exo_vulkan_binding::kernel_library::{KernelRegistry, LibraryError};
+use exo_vulkan_binding::kernel_plugins;
+use exo_vulkan_binding::memory::{AllocationSpec, BufferRange, ExternalMemory, MemoryAllocator, MemoryError, MemoryUsage, find_memory_type};
+use exo_vulkan_binding::pipeline::{ComputePipeline, PipelineError};
+use exo_vulkan_binding::transfer::TransferError;
+#[cfg(feature = "kernels-core")]
+use exo_vulkan_binding::weight_delta::{DeltaError, WeightEncoding, WeightTensor};
+
+/// Longest a submission may run before it is reported as timed out
+pub const SUBMIT_TIMEOUT_MS: u64 = 10_000;
+
+/// Device context errors
+#[derive(Error, Debug)]
+pub enum ContextError {
+    #[error("Device {0} has no allocations")]
+    NotOpen(String),
+
+    #[error("Submission did not complete within {0} ms")]
+    Timeout(u64),
+
+    #[error(transparent)]
+    Device(#[from] DeviceError),
+
+    #[error(transparent)]
+    Memory(#[from] MemoryError),
+
+    #[error(transparent)]
+    Command(#[from] CommandError),
+
+    #[error(transparent)]
+    Transfer(#[from] TransferError),
+
+    #[error(transparent)]
+    Pipeline(#[from] PipelineError),
+
+    #[cfg(feature = "kernels-core")]
+    #[error(transparent)]
+    Library(#[from] LibraryError),
+
+    #[cfg(feature = "kernels-core")]
+    #[error(transparent)]
+    Delta(#[from] DeltaError),
+}
+
+impl From<ContextError> for String {
+    fn from(e: ContextError) -> Self {
+        e.to_string()
+    }
+}
+
+pub type ContextResult<T> = Result<T, ContextError>;
+
+/// Devices enable memory export for `prepareHandover`, and HardwareBuffer
+/// import on Android, where they support them
+fn device_config() -> DeviceConfig {
+    let mut optional_extensions = vec![ash::khr::external_memory_fd::NAME];
+    if cfg!(target_os = "android") {
+        optional_extensions.extend([
+            ash::android::external_memory_android_hardware_buffer::NAME,
+            ash::ext::queue_family_foreign::NAME,
+        ]);
+    }
+    DeviceConfig {
+        optional_extensions: optional_extensions
+            .iter()
+            .map(|name| name.to_string_lossy().into_owned())
+            .collect(),
+        ..DeviceConfig::default()
+    }
+}
+
+/// Memory type of JNI allocations, device local where the device has one
+fn device_local_memory_type(properties: &vk::PhysicalDeviceMemoryProperties) -> u32 {
+    find_memory_type(properties, u32::MAX, MemoryUsage::DeviceLocal).unwrap_or(0)
+}
+
+// Fields drop in order: everything created from `device` goes first
+struct Inner {
+    #[cfg(feature = "kernels-core")]
+    kernels: KernelRegistry,
+    /// Pipelines of kernels run by `dispatchKernel`, by name
+    pipelines: HashMap<String, ComputePipeline>,
+    allocator: MemoryAllocator,
+    pool: CommandPool,
+    memory_type: u32,
+    /// Loader of `VK_KHR_external_memory_fd`, if the device has it
+    external_memory_fd: Option<ash::khr::external_memory_fd::Device>,
+    device: LogicalDevice,
+}
+
+// SAFETY: the allocator's mapped pointers point into memory it owns, and
+// every access to them goes through the context lock
+unsafe impl Send for Inner {}
+
+/// One logical device and the allocations made on it through JNI
+pub struct DeviceContext {
+    inner: Mutex<Inner>,
+}
+
+lazy_static! {
+    static ref CONTEXTS: Mutex<HashMap<String, Arc<DeviceContext>>> = Mutex::new(HashMap::new());
+}
+
+/// The context of `device_id`, opening physical device `index` of `vulkan`
+/// for it if there is none yet
+pub fn open(device_id: &str, vulkan: &Arc<VulkanContext>, index: usize) -> ContextResult<Arc<DeviceContext>> {
+    let mut contexts = CONTEXTS.lock();
+    if let Some(context) = contexts.get(device_id) {
+        return Ok(Arc::clone(context));
+    }
+    let context = Arc::new(DeviceContext::open(vulkan, index)?);
+    contexts.insert(device_id.to_string(), Arc::clone(&context));
+    Ok(context)
+}
+
+/// The context allocations on `device_id` were made in
+pub fn get(device_id: &str) -> ContextResult<Arc<DeviceContext>> {
+    CONTEXTS
+        .lock()
+        .get(device_id)
+        .cloned()
+        .ok_or_else(|| ContextError::NotOpen(device_id.to_string()))
+}
+
+/// Drop every context; their devices are destroyed once pending calls return
+pub fn close_all() {
+    CONTEXTS.lock().clear();
+}
+
+impl DeviceContext {
+    fn open(vulkan: &Arc<VulkanContext>, index: usize) -> ContextResult<Self> {
+        let device = LogicalDevice::create(vulkan, index, &device_config())?;
+        let pool = device.command_pool()?;
+        let external_memory_fd = device
+            .has_extension(&ash::khr::external_memory_fd::NAME.to_string_lossy())
+            .then(|| ash::khr::external_memory_fd::Device::new(&device.context().instance(), device.device()));
+        let mut allocator = device.memory_allocator();
+        allocator.set_exportable(external_memory_fd.is_some());
+        Ok(Self {
+            inner: Mutex::new(Inner {
+                #[cfg(feature = "kernels-core")]
+                kernels: KernelRegistry::new(device.device(), vk::PipelineCache::null()),
+                pipelines: HashMap::new(),
+                allocator,
+                pool,
+                memory_type: device_local_memory_type(device.memory_properties()),
+                external_memory_fd,
+                device,
+            }),
+        })
+    }
+
+    /// Allocate `size` bytes of device memory as `handle_id`
+    pub fn allocate(&self, handle_id: &str, size: u64) -> ContextResult<()> {
+        let mut inner = self.inner.lock();
+        let memory_type = inner.memory_type;
+        inner.allocator.allocate(size, memory_type, handle_id.to_string())?;
+        Ok(())
+    }
+
+    /// Allocate `(handle_id, size)` buffers packed into one slab
+    ///
+    /// One `vkAllocateMemory` backs the whole batch, which either fully
+    /// succeeds or leaves nothing allocated. Slab members are never
+    /// exportable.
+    pub fn allocate_many(&self, buffers: &[(String, u64)]) -> ContextResult<()> {
+        let mut inner = self.inner.lock();
+        let memory_type_index = inner.memory_type;
+        let specs: Vec<AllocationSpec> = buffers
+            .iter()
+            .map(|(handle_id, size)| AllocationSpec {
+                handle_id: handle_id.clone(),
+                size: *size,
+                memory_type_index,
+            })
+            .collect();
+        inner.allocator.allocate_many(&specs, true)?;
+        Ok(())
+    }
+
+    /// Memory type of allocations made with `allocate`
+    pub fn memory_type(&self) -> u32 {
+        self.inner.lock().memory_type
+    }
+
+    /// Export the memory of `handle_id` as an opaque FD the caller owns
+    ///
+    /// Work submitted through the context has completed once the lock is
+    /// taken, so the exported contents are final. Fails for memory that was
+    /// not allocated exportable: `allocate_many` slab members, imports, and
+    /// everything on devices without `VK_KHR_external_memory_fd`.
+    pub fn export_fd(&self, handle_id: &str) -> ContextResult<ExternalMemory> {
+        let inner = self.inner.lock();
+        let loader = inner.external_memory_fd.as_ref().ok_or_else(|| {
+            DeviceError::MissingExtension(ash::khr::external_memory_fd::NAME.to_string_lossy().into_owned())
+        })?;
+        Ok(inner.allocator.export_fd(handle_id, loader)?)
+    }
+
+    pub fn free(&self, handle_id: &str) -> ContextResult<()> {
+        self.inner.lock().allocator.deallocate(handle_id)?;
+        Ok(())
+    }
+
+    /// Write `data` into `handle_id` starting at byte `offset`
+    pub fn write(&self, handle_id: &str, offset: u64, data: &[u8]) -> ContextResult<()> {
+        let inner = self.inner.lock();
+        let view = inner.allocator.get_allocation(handle_id)?.view(offset, data.len() as u64)?;
+        let transfer = inner.device.data_transfer(&inner.pool);
+        // SAFETY: the view is of a live allocation of this device, which the
+        // lock keeps alive and unused until the copy has completed
+        unsafe { transfer.copy_to_device(data, &view) }?;
+        Ok(())
+    }
+
+    /// Read `len` bytes of `handle_id` starting at byte `offset`
+    pub fn read(&self, handle_id: &str, offset: u64, len: u64) -> ContextResult<Vec<u8>> {
+        let inner = self.inner.lock();
+        let view = inner.allocator.get_allocation(handle_id)?.view(offset, len)?;
+        let transfer = inner.device.data_transfer(&inner.pool);
+        // SAFETY: as for `write`; the view is exactly `len` bytes
+        Ok(unsafe { transfer.copy_from_device(&view, len) }?)
+    }
+
+    /// Run embedded or plugin kernel `kernel` on `handles`, bound in the
+    /// order the kernel declares its buffers, and wait for it
+    pub fn dispatch(&self, kernel: &str, handles: &[String], groups: [u32; 3]) -> ContextResult<()> {
+        let mut inner = self.inner.lock();
+        let Inner {
+            pipelines,
+            allocator,
+            pool,
+            device,
+            ..
+        } = &mut *inner;
+        let ranges = handles
+            .iter()
+            .map(|handle| Ok(allocator.get_allocation(handle)?.range()))
+            .collect::<ContextResult<Vec<_>>>()?;
+        if !pipelines.contains_key(kernel) {
+            let pipeline = match kernel_plugins::get(kernel) {
+                Some(plugin) => ComputePipeline::from_plugin(device.device(), &plugin, vk::PipelineCache::null())?,
+                None => ComputePipeline::from_embedded(device.device(), kernel, vk::PipelineCache::null())?,
+            };
+            pipelines.insert(kernel.to_string(), pipeline);
+        }
+        let pipeline = &pipelines[kernel];
+        let bound: Vec<(u32, &BufferRange)> = pipeline.interface().buffer_bindings.iter().copied().zip(&ranges).collect();
+        submit(pool, device, |cmd| {
+            // SAFETY: cmd is recording; the buffers are live allocations of
+            // this device and the lock keeps them so until the work completes.
+            // The previous dispatch using the default set has completed.
+            unsafe { pipeline.dispatch_bytes(cmd, pipeline.descriptor_set(), &bound, &[], groups) }?;
+            Ok(())
+        })
+    }
+
+    /// Record built-in kernels on the ranges of `handles`, submit them and
+    /// wait for them
+    #[cfg(feature = "kernels-core")]
+    pub fn run_kernels(
+        &self,
+        handles: &[&str],
+        record: impl FnOnce(&mut KernelRegistry, vk::CommandBuffer, &[BufferRange]) -> ContextResult<()>,
+    ) -> ContextResult<()> {
+        let mut inner = self.inner.lock();
+        let Inner {
+            kernels,
+            allocator,
+            pool,
+            device,
+            ..
+        } = &mut *inner;
+        let ranges = handles
+            .iter()
+            .map(|handle| Ok(allocator.get_allocation(handle)?.range()))
+            .collect::<ContextResult<Vec<_>>>()?;
+        let submitted = submit(pool, device, |cmd| record(kernels, cmd, &ranges));
+        kernels.release_sets()?;
+        submitted
+    }
+
+    /// `w += scale * delta` over the weights of `tensor`, in place
+    ///
+    /// int8 weights are dequantized, updated and requantized with their
+    /// params, which are uploaded to a scratch allocation for the call.
+    #[cfg(feature = "kernels-core")]
+    pub fn apply_delta(&self, tensor: &WeightTensor, delta: &str, scale: f32) -> ContextResult<()> {
+        let WeightEncoding::Int8PerRow(params) = &tensor.encoding else {
+            return self.run_kernels(&[&tensor.handle, delta], |kernels, cmd, ranges| {
+                // SAFETY: cmd is recording and `run_kernels` keeps the buffers
+                // alive until the work completes
+                Ok(unsafe { tensor.record_apply(kernels, cmd, &ranges[0], None, &ranges[1], scale) }?)
+            });
+        };
+
+        let words: Vec<u8> = activation_codec::params_words(params)
+            .iter()
+            .flat_map(|w| w.to_le_bytes())
+            .collect();
+        let scratch = format!("{}.delta_params", tensor.handle);
+        self.allocate(&scratch, words.len() as u64)?;
+        let applied = self.write(&scratch, 0, &words).and_then(|()| {
+            self.run_kernels(&[&tensor.handle, &scratch, delta], |kernels, cmd, ranges| {
+                // SAFETY: as above
+                Ok(unsafe { tensor.record_apply(kernels, cmd, &ranges[0], Some(&ranges[1]), &ranges[2], scale) }?)
+            })
+        });
+        self.free(&scratch)?;
+        applied
+    }
+
+    /// Import the `size`-byte BLOB `AHardwareBuffer` at `buffer` as
+    /// `handle_id`, sharing its memory
+    ///
+    /// # Safety Requirements
+    /// - `buffer` must point to a live `AHardwareBuffer`
+    #[cfg(target_os = "android")]
+    pub unsafe fn import_hardware_buffer(
+        &self,
+        handle_id: &str,
+        size: u64,
+        buffer: *mut std::ffi::c_void,
+    ) -> ContextResult<()> {
+        use ash::android::external_memory_android_hardware_buffer as ahb;
+
+        let mut inner = self.inner.lock();
+        let Inner { allocator, device, .. } = &mut *inner;
+        let extension = ahb::NAME.to_string_lossy();
+        if !device.has_extension(&extension) {
+            return Err(DeviceError::MissingExtension(extension.into_owned()).into());
+        }
+        let loader = ahb::Device::new(&device.context().instance(), device.device());
+        // SAFETY: the caller guarantees the buffer is live, and the loader is
+        // of the allocator's device
+        unsafe { allocator.import_ahardware_buffer(handle_id.to_string(), size, buffer.cast(), &loader) }?;
+        Ok(())
+    }
+}
+
+/// Record work into a one-time command buffer, submit it on the compute
+/// queue and wait for it
+///
+/// A barrier after the recorded work makes shader writes visible to later
+/// dispatches and to the transfers that read results back.
+fn submit(
+    pool: &CommandPool,
+    device: &LogicalDevice,
+    record: impl FnOnce(vk::CommandBuffer) -> ContextResult<()>,
+) -> ContextResult<()> {
+    let cmd = pool.allocate_buffers(1)?[0];
+    let completed = (|| -> ContextResult<bool> {
+        pool.begin_recording(cmd, vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
+        record(cmd)?;
+        let written = vk::MemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::TRANSFER_READ);
+        // SAFETY: cmd is recording
+        unsafe {
+            device.device().cmd_pipeline_barrier(
+                cmd,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[written],
+                &[],
+                &[],
+            );
+        }
+        pool.end_recording(cmd)?;
+        let fence = Fence::new(device.device().clone(), false)?;
+        device.compute_queue().submit(&[cmd], None, None, Some(fence.raw()))?;
+        Ok(fence.wait(SUBMIT_TIMEOUT_MS * 1_000_000)?)
+    })();
+    if matches!(completed, Ok(false)) {
+        // The command buffer may still be pending; leak it rather than free it
+        return Err(ContextError::Timeout(SUBMIT_TIMEOUT_MS));
+    }
+    // SAFETY: the submission completed, was never made, or the device was lost
+    unsafe { device.device().free_command_buffers(pool.raw(), &[cmd]) };
+    completed.map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unopened_device_is_reported() {
+        assert!(matches!(get("vulkan:unopened"), Err(ContextError::NotOpen(id)) if id == "vulkan:unopened"));
+        let message: String = ContextError::Timeout(SUBMIT_TIMEOUT_MS).into();
+        assert_eq!(message, "Submission did not complete within 10000 ms");
+        let mut props = vk::PhysicalDeviceMemoryProperties {
+            memory_type_count: 2,
+            ..Default::default()
+        };
+        props.memory_types[1].property_flags = vk::MemoryPropertyFlags::DEVICE_LOCAL;
+        assert_eq!(device_local_memory_type(&props), 1);
+    }
+
+    #[test]
+    fn test_large_allocation_round_trips() {
+        // Passes without checking anything on hosts without a Vulkan device
+        let Ok(vulkan) = exo_vulkan_binding::initialize_vulkan() else {
+            return;
+        };
+        let Ok(context) = DeviceContext::open(&vulkan, 0) else {
+            return;
+        };
+        let size = 2 * exo_vulkan_binding::memory::MAX_SUBALLOCATION;
+        context.allocate("large", size).unwrap();
+        context.write("large", size - 4, &[1, 2, 3, 4]).unwrap();
+        assert_eq!(context.read("large", size - 4, 4).unwrap(), [1, 2, 3, 4]);
+        context.free("large").unwrap();
+    }
+
+    #[test]
+    fn test_batch_shares_a_slab() {
+        let Ok(vulkan) = exo_vulkan_binding::initialize_vulkan() else {
+            return;
+        };
+        let Ok(context) = DeviceContext::open(&vulkan, 0) else {
+            return;
+        };
+        let buffers = [("a".to_string(), 100), ("b".to_string(), 4096)];
+        context.allocate_many(&buffers).unwrap();
+        assert!(context.allocate_many(&[("a".to_string(), 8)]).is_err());
+        context.write("b", 4092, &[5, 6, 7, 8]).unwrap();
+        assert_eq!(context.read("b", 4092, 4).unwrap(), [5, 6, 7, 8]);
+        assert!(context.export_fd("b").is_err());
+        for (handle, _) in &buffers {
+            context.free(handle).unwrap();
+        }
+    }
+}
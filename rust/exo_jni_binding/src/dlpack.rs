@@ -0,0 +1,228 @@
+//! DLPack C entry points
+//!
+//! Lets native code that links the library (ndarray, candle or burn glue, or
+//! another runtime's C bindings) hand host tensors to and from exo without
+//! going through JNI. These are the C equivalents of Python's `to_dlpack` /
+//! `from_dlpack` capsules; the struct layouts are those of `dlpack.h` v0.8.
+
+use std::ptr;
+
+use exo_vulkan_binding::dlpack::{self, DLManagedTensor};
+use exo_vulkan_binding::tensor::Layout;
+
+use crate::ffi::{self, FfiError, FfiResult};
+
+/// # Safety Requirements
+/// - As for `exo_gpu_to_dlpack`
+unsafe fn to_dlpack(data: *const f32, shape: *const i64, ndim: i32) -> FfiResult<*mut DLManagedTensor> {
+    let ndim = usize::try_from(ndim).map_err(|_| FfiError::InvalidArgument(format!("ndim {}", ndim)))?;
+    if (ndim > 0 && shape.is_null()) || data.is_null() {
+        return Err(FfiError::InvalidArgument("null data or shape".to_string()));
+    }
+    let dims = if ndim == 0 {
+        Vec::new()
+    } else {
+        // SAFETY: the caller guarantees `ndim` readable elements
+        unsafe { std::slice::from_raw_parts(shape, ndim) }
+            .iter()
+            .map(|&d| usize::try_from(d).map_err(|_| FfiError::InvalidArgument(format!("dimension {}", d))))
+            .collect::<FfiResult<Vec<usize>>>()?
+    };
+
+    let layout = Layout::contiguous(&dims);
+    // SAFETY: the caller guarantees `numel` readable elements
+    let values = unsafe { std::slice::from_raw_parts(data, layout.numel()) }.to_vec();
+    Ok(dlpack::export_host(values, &layout)?)
+}
+
+/// # Safety Requirements
+/// - As for `exo_gpu_from_dlpack`
+unsafe fn from_dlpack(managed: *mut DLManagedTensor, out: *mut f32, capacity: usize) -> FfiResult<usize> {
+    // SAFETY: forwarded from the caller's guarantees
+    let imported = unsafe { dlpack::import(managed) }?;
+    let values = imported.to_contiguous().map_err(FfiError::from).and_then(|values| {
+        if values.len() > capacity {
+            return Err(FfiError::BufferTooSmall {
+                needed: values.len(),
+                capacity,
+            });
+        }
+        Ok(values)
+    });
+    let values = match values {
+        Ok(values) => values,
+        Err(e) => {
+            // Hand the tensor back untouched
+            imported.into_raw();
+            return Err(e);
+        }
+    };
+    if !values.is_empty() {
+        // SAFETY: capacity checked above; out is writable per the caller
+        unsafe { ptr::copy_nonoverlapping(values.as_ptr(), out, values.len()) };
+    }
+    Ok(values.len())
+}
+
+/// Copy a row-major f32 host array into a new DLPack tensor
+///
+/// Returns null on invalid arguments, with the reason available from
+/// `exo_gpu_last_error_message`. The result is released through its
+/// `deleter` (or `exo_gpu_dlpack_delete`).
+///
+/// # Safety Requirements
+/// - shape must point to `ndim` readable i64s
+/// - data must point to as many readable f32s as the shape's product
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn exo_gpu_to_dlpack(
+    data: *const f32,
+    shape: *const i64,
+    ndim: i32,
+) -> *mut DLManagedTensor {
+    // SAFETY: forwarded from the caller's guarantees
+    unsafe { to_dlpack(data, shape, ndim) }.unwrap_or_else(|e| {
+        ffi::set_last_error("exo_gpu_to_dlpack", &e.to_string());
+        ptr::null_mut()
+    })
+}
+
+/// Consume a host f32 DLPack tensor, copying it row-major into `out`
+///
+/// Returns the number of elements written. On failure (unsupported dtype or
+/// device, or `capacity` too small) returns -1 and leaves the tensor owned by
+/// the caller.
+///
+/// # Safety Requirements
+/// - managed must be null or a valid `DLManagedTensor`
+/// - out must point to `capacity` writable f32s
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn exo_gpu_from_dlpack(
+    managed: *mut DLManagedTensor,
+    out: *mut f32,
+    capacity: usize,
+) -> i64 {
+    // SAFETY: forwarded from the caller's guarantees
+    match unsafe { from_dlpack(managed, out, capacity) } {
+        Ok(written) => written as i64,
+        Err(e) => {
+            ffi::set_last_error("exo_gpu_from_dlpack", &e.to_string());
+            -1
+        }
+    }
+}
+
+/// `exo_gpu_to_dlpack` returning an `ExoStatus` code
+///
+/// On success stores the new tensor in `*tensor`; on failure leaves it
+/// untouched.
+///
+/// # Safety Requirements
+/// - As for `exo_gpu_to_dlpack`
+/// - tensor must point to a writable pointer
+#[cfg(feature = "ffi-status")]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn exo_gpu_to_dlpack_status(
+    data: *const f32,
+    shape: *const i64,
+    ndim: i32,
+    tensor: *mut *mut DLManagedTensor,
+) -> i32 {
+    ffi::call("exo_gpu_to_dlpack_status", || {
+        if tensor.is_null() {
+            return Err(FfiError::InvalidArgument("null tensor out-pointer".to_string()));
+        }
+        // SAFETY: forwarded from the caller's guarantees; tensor checked non-null
+        unsafe { *tensor = to_dlpack(data, shape, ndim)? };
+        Ok(())
+    })
+}
+
+/// `exo_gpu_from_dlpack` returning an `ExoStatus` code
+///
+/// On success stores the number of elements copied in `*written` and takes
+/// ownership of the tensor; on failure the tensor stays with the caller.
+///
+/// # Safety Requirements
+/// - As for `exo_gpu_from_dlpack`
+/// - written must point to a writable usize
+#[cfg(feature = "ffi-status")]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn exo_gpu_from_dlpack_status(
+    managed: *mut DLManagedTensor,
+    out: *mut f32,
+    capacity: usize,
+    written: *mut usize,
+) -> i32 {
+    ffi::call("exo_gpu_from_dlpack_status", || {
+        if written.is_null() {
+            return Err(FfiError::InvalidArgument("null written out-pointer".to_string()));
+        }
+        // SAFETY: forwarded from the caller's guarantees; written checked non-null
+        unsafe { *written = from_dlpack(managed, out, capacity)? };
+        Ok(())
+    })
+}
+
+/// Release a DLPack tensor through its own deleter
+///
+/// # Safety Requirements
+/// - managed must be null or a valid `DLManagedTensor` not yet deleted
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn exo_gpu_dlpack_delete(managed: *mut DLManagedTensor) {
+    // SAFETY: forwarded from the caller's guarantees
+    unsafe {
+        if let Some(deleter) = managed.as_ref().and_then(|m| m.deleter) {
+            deleter(managed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "ffi-status")]
+    use crate::ffi::ExoStatus;
+
+    #[test]
+    fn test_c_round_trip() {
+        let data = [1.0f32, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let shape = [2i64, 3];
+        let mut out = [0.0f32; 6];
+        // SAFETY: arrays match the declared sizes
+        unsafe {
+            let managed = exo_gpu_to_dlpack(data.as_ptr(), shape.as_ptr(), 2);
+            assert!(!managed.is_null());
+            assert_eq!(exo_gpu_from_dlpack(managed, out.as_mut_ptr(), 2), -1);
+            assert_eq!(exo_gpu_from_dlpack(managed, out.as_mut_ptr(), out.len()), 6);
+        }
+        assert_eq!(out, data);
+    }
+
+    #[cfg(feature = "ffi-status")]
+    #[test]
+    fn test_status_variants() {
+        let data = [1.0f32, 2.0, 3.0];
+        let shape = [3i64];
+        let mut out = [0.0f32; 3];
+        let mut tensor = ptr::null_mut();
+        let mut written = 0usize;
+        // SAFETY: arrays match the declared sizes
+        unsafe {
+            let status = exo_gpu_to_dlpack_status(data.as_ptr(), shape.as_ptr(), -1, &mut tensor);
+            assert_eq!(status, ExoStatus::InvalidArgument as i32);
+            assert!(tensor.is_null());
+            let status = exo_gpu_to_dlpack_status(data.as_ptr(), shape.as_ptr(), 1, &mut tensor);
+            assert_eq!(status, ExoStatus::Ok as i32);
+            let status = exo_gpu_from_dlpack_status(tensor, out.as_mut_ptr(), 2, &mut written);
+            assert_eq!(status, ExoStatus::BufferTooSmall as i32);
+            assert_eq!(
+                ffi::last_error().as_deref(),
+                Some("exo_gpu_from_dlpack_status: Output holds 2 elements, 3 needed")
+            );
+            let status = exo_gpu_from_dlpack_status(tensor, out.as_mut_ptr(), out.len(), &mut written);
+            assert_eq!(status, ExoStatus::Ok as i32);
+        }
+        assert_eq!(written, 3);
+        assert_eq!(out, data);
+    }
+}
@@ -0,0 +1,140 @@
+//! Error-code C calling convention
+//!
+//! The plain C entry points (`exo_gpu_to_dlpack`, ...) report failure as a
+//! null pointer or -1 and only log why. Embedders that are not written in
+//! Rust, or that build with `panic = "abort"`, need more than that. Each
+//! `_status` variant returns an `ExoStatus` code and writes its result
+//! through an out-pointer. A panic inside it is caught and reported as
+//! `Panic` instead of unwinding across the boundary. Under `panic = "abort"`
+//! it aborts the process, which is the defined behaviour such builds choose.
+//!
+//! Every failing call, plain or `_status`, stores its message for the calling
+//! thread; `exo_gpu_last_error_message` returns it.
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::ffi::{CString, c_char};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use log::error;
+use thiserror::Error;
+
+use exo_vulkan_binding::dlpack::DlpackError;
+
+/// Status codes returned by the `_status` entry points
+#[repr(i32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExoStatus {
+    Ok = 0,
+    InvalidArgument = 1,
+    BufferTooSmall = 2,
+    Unsupported = 3,
+    /// A panic was caught at the boundary
+    Panic = 4,
+}
+
+/// Failures of the C entry points
+#[derive(Error, Debug)]
+pub enum FfiError {
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+
+    #[error("Output holds {capacity} elements, {needed} needed")]
+    BufferTooSmall { needed: usize, capacity: usize },
+
+    #[error(transparent)]
+    Dlpack(#[from] DlpackError),
+}
+
+pub type FfiResult<T> = Result<T, FfiError>;
+
+impl FfiError {
+    pub fn status(&self) -> ExoStatus {
+        match self {
+            FfiError::InvalidArgument(_) => ExoStatus::InvalidArgument,
+            FfiError::BufferTooSmall { .. } => ExoStatus::BufferTooSmall,
+            FfiError::Dlpack(DlpackError::UnsupportedDtype { .. } | DlpackError::UnsupportedDevice(_)) => {
+                ExoStatus::Unsupported
+            }
+            FfiError::Dlpack(_) => ExoStatus::InvalidArgument,
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Log a failure of `entry_point` and keep its message for this thread
+pub fn set_last_error(entry_point: &str, message: &str) {
+    error!("{} failed: {}", entry_point, message);
+    // Interior NULs would truncate the message on the C side anyway
+    let message = CString::new(format!("{}: {}", entry_point, message).replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// Message of the last failure on this thread
+pub fn last_error() -> Option<String> {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map(|m| m.to_string_lossy().into_owned()))
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+/// Run the body of a `_status` entry point
+///
+/// Errors and caught panics become status codes, with their message stored
+/// for `exo_gpu_last_error_message`.
+pub fn call(entry_point: &str, body: impl FnOnce() -> FfiResult<()>) -> i32 {
+    let status = match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(())) => ExoStatus::Ok,
+        Ok(Err(e)) => {
+            set_last_error(entry_point, &e.to_string());
+            e.status()
+        }
+        Err(payload) => {
+            set_last_error(entry_point, &format!("panicked: {}", panic_message(payload.as_ref())));
+            ExoStatus::Panic
+        }
+    };
+    status as i32
+}
+
+/// Message of the last failed C call on the calling thread
+///
+/// Returns null if no call on this thread has failed. The string stays
+/// valid until the next failing call on the same thread; copy it to keep it.
+#[unsafe(no_mangle)]
+pub extern "C" fn exo_gpu_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |m| m.as_ptr()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_errors_and_panics_become_status_codes() {
+        assert_eq!(call("testOk", || Ok(())), 0);
+        let status = call("testTooSmall", || {
+            Err(FfiError::BufferTooSmall {
+                needed: 6,
+                capacity: 2,
+            })
+        });
+        assert_eq!(status, ExoStatus::BufferTooSmall as i32);
+        assert_eq!(last_error().as_deref(), Some("testTooSmall: Output holds 2 elements, 6 needed"));
+
+        #[allow(clippy::panic)]
+        let status = call("testPanic", || panic!("boom"));
+        assert_eq!(status, ExoStatus::Panic as i32);
+        assert_eq!(last_error().as_deref(), Some("testPanic: panicked: boom"));
+        assert!(!exo_gpu_last_error_message().is_null());
+    }
+}
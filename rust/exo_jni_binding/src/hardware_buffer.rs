@@ -0,0 +1,140 @@
+//! Android `HardwareBuffer` access for zero-copy import
+//!
+//! `importHardwareBuffer` receives an `android.hardware.HardwareBuffer`
+//! from the camera, MediaCodec or NNAPI and hands the underlying
+//! `AHardwareBuffer` to `MemoryAllocator::import_ahardware_buffer`. Only
+//! BLOB buffers allocated with `USAGE_GPU_DATA_BUFFER` can back a Vulkan
+//! buffer; `blob_size` checks a buffer's description before anything is
+//! imported. The NDK functions are linked from `libandroid` (API 26+) and
+//! exist only in Android builds.
+
+use thiserror::Error;
+
+/// `AHARDWAREBUFFER_FORMAT_BLOB`
+pub const FORMAT_BLOB: u32 = 0x21;
+
+/// `AHARDWAREBUFFER_USAGE_GPU_DATA_BUFFER`
+pub const USAGE_GPU_DATA_BUFFER: u64 = 1 << 24;
+
+/// Unusable hardware buffer
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum HardwareBufferError {
+    #[error("HardwareBuffer format {0:#x} is not BLOB")]
+    NotBlob(u32),
+
+    #[error("BLOB HardwareBuffer must be {{width}}x1x1, got height {height} and {layers} layers")]
+    NotLinear { height: u32, layers: u32 },
+
+    #[error("HardwareBuffer lacks USAGE_GPU_DATA_BUFFER")]
+    NotGpuData,
+
+    #[error("HardwareBuffer is empty")]
+    Empty,
+
+    #[error("HardwareBuffer unavailable: {0}")]
+    Unavailable(String),
+}
+
+impl From<HardwareBufferError> for String {
+    fn from(e: HardwareBufferError) -> Self {
+        e.to_string()
+    }
+}
+
+/// `AHardwareBuffer_Desc`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HardwareBufferDesc {
+    pub width: u32,
+    pub height: u32,
+    pub layers: u32,
+    pub format: u32,
+    pub usage: u64,
+    pub stride: u32,
+    pub rfu0: u32,
+    pub rfu1: u64,
+}
+
+/// Bytes of a buffer Vulkan can import, which for BLOB buffers is the width
+pub fn blob_size(desc: &HardwareBufferDesc) -> Result<u64, HardwareBufferError> {
+    if desc.format != FORMAT_BLOB {
+        return Err(HardwareBufferError::NotBlob(desc.format));
+    }
+    if desc.height != 1 || desc.layers != 1 {
+        return Err(HardwareBufferError::NotLinear {
+            height: desc.height,
+            layers: desc.layers,
+        });
+    }
+    if desc.usage & USAGE_GPU_DATA_BUFFER == 0 {
+        return Err(HardwareBufferError::NotGpuData);
+    }
+    match desc.width {
+        0 => Err(HardwareBufferError::Empty),
+        width => Ok(u64::from(width)),
+    }
+}
+
+#[cfg(target_os = "android")]
+mod ndk {
+    use std::ffi::c_void;
+
+    use jni::sys::{JNIEnv, jobject};
+
+    use super::HardwareBufferDesc;
+
+    #[link(name = "android")]
+    unsafe extern "C" {
+        pub fn AHardwareBuffer_fromHardwareBuffer(env: *mut JNIEnv, hardware_buffer: jobject) -> *mut c_void;
+        pub fn AHardwareBuffer_describe(buffer: *const c_void, desc: *mut HardwareBufferDesc);
+    }
+}
+
+/// The `AHardwareBuffer` behind a `HardwareBuffer` object, and its description
+///
+/// The pointer is not acquired: it is valid while the Java object is, so
+/// for the duration of the JNI call that passed it.
+#[cfg(target_os = "android")]
+pub fn from_java(
+    env: &jni::JNIEnv,
+    hardware_buffer: &jni::objects::JObject,
+) -> Result<(*mut std::ffi::c_void, HardwareBufferDesc), HardwareBufferError> {
+    if hardware_buffer.is_null() {
+        return Err(HardwareBufferError::Unavailable("null HardwareBuffer".to_string()));
+    }
+    // SAFETY: env is the live JNI environment of this call and the object is
+    // a non-null reference passed to it
+    let buffer = unsafe { ndk::AHardwareBuffer_fromHardwareBuffer(env.get_raw(), hardware_buffer.as_raw()) };
+    if buffer.is_null() {
+        return Err(HardwareBufferError::Unavailable("not a HardwareBuffer, or closed".to_string()));
+    }
+    let mut desc = HardwareBufferDesc::default();
+    // SAFETY: buffer is live for this call and desc is a valid out-pointer
+    unsafe { ndk::AHardwareBuffer_describe(buffer, &mut desc) };
+    Ok((buffer, desc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_gpu_blob_buffers_import() {
+        let blob = HardwareBufferDesc {
+            width: 4096,
+            height: 1,
+            layers: 1,
+            format: FORMAT_BLOB,
+            usage: USAGE_GPU_DATA_BUFFER,
+            ..Default::default()
+        };
+        assert_eq!(blob_size(&blob), Ok(4096));
+        assert_eq!(blob_size(&HardwareBufferDesc { format: 1, ..blob }), Err(HardwareBufferError::NotBlob(1)));
+        assert!(matches!(
+            blob_size(&HardwareBufferDesc { height: 2, ..blob }),
+            Err(HardwareBufferError::NotLinear { height: 2, .. })
+        ));
+        assert_eq!(blob_size(&HardwareBufferDesc { usage: 0, ..blob }), Err(HardwareBufferError::NotGpuData));
+        assert_eq!(blob_size(&HardwareBufferDesc { width: 0, ..blob }), Err(HardwareBufferError::Empty));
+    }
+}
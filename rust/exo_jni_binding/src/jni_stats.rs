@@ -0,0 +1,145 @@
+//! JNI call instrumentation
+//!
+//! Every JNI crossing has a fixed cost (argument marshalling, local
+//! references, thread attach checks) that adds up when an integration calls
+//! in per element or per token. With `jni_stats` enabled in the config, each
+//! entry point counts its calls and records a histogram of their duration,
+//! reported by `getJniStats()`; an entry point with many short calls is a
+//! candidate for the batch APIs. Disabled, a call costs one atomic load.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+
+/// Upper bounds of the histogram buckets, in microseconds; the last bucket
+/// counts everything slower
+pub const BUCKET_BOUNDS_US: [u64; 8] = [1, 4, 16, 64, 256, 1024, 4096, 16384];
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Calls and timings of one entry point
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EntryStats {
+    pub calls: u64,
+    pub total_us: u64,
+    pub max_us: u64,
+    pub buckets: [u64; BUCKET_BOUNDS_US.len() + 1],
+}
+
+impl EntryStats {
+    fn record(&mut self, us: u64) {
+        self.calls += 1;
+        self.total_us += us;
+        self.max_us = self.max_us.max(us);
+        let bucket = BUCKET_BOUNDS_US
+            .iter()
+            .position(|&bound| us < bound)
+            .unwrap_or(BUCKET_BOUNDS_US.len());
+        self.buckets[bucket] += 1;
+    }
+}
+
+lazy_static! {
+    static ref STATS: Mutex<BTreeMap<&'static str, EntryStats>> = Mutex::new(BTreeMap::new());
+}
+
+/// Start or stop collecting; stopping keeps what was collected
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Records the call to `entry_point` when dropped
+///
+/// Also ends the call's client attribution for trace ids, so a later call on
+/// the same thread does not inherit it.
+pub struct CallTimer {
+    entry_point: &'static str,
+    started: Option<Instant>,
+}
+
+impl Drop for CallTimer {
+    fn drop(&mut self) {
+        if let Some(started) = self.started {
+            record(self.entry_point, started.elapsed().as_micros() as u64);
+        }
+        exo_vulkan_binding::trace::leave_client();
+    }
+}
+
+/// Time the current call of `entry_point`; keep the guard alive for the call
+pub fn time(entry_point: &'static str) -> CallTimer {
+    CallTimer {
+        entry_point,
+        started: is_enabled().then(Instant::now),
+    }
+}
+
+fn record(entry_point: &'static str, us: u64) {
+    STATS.lock().entry(entry_point).or_default().record(us);
+}
+
+/// Stats of one entry point
+pub fn get(entry_point: &str) -> Option<EntryStats> {
+    STATS.lock().get(entry_point).copied()
+}
+
+/// Forget everything collected
+pub fn reset() {
+    STATS.lock().clear();
+}
+
+/// JSON report: `{"enabled":bool,"bucket_bounds_us":[...],"entry_points":{"name":{"calls","total_us","mean_us","max_us","histogram"}}}`
+///
+/// Entry points are sorted by total time, busiest first.
+pub fn to_json() -> String {
+    let stats = STATS.lock().clone();
+    let mut entries: Vec<(&str, EntryStats)> = stats.into_iter().collect();
+    entries.sort_by_key(|(_, s)| std::cmp::Reverse(s.total_us));
+    let join = |values: &[u64]| values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(",");
+    let entries: Vec<String> = entries
+        .iter()
+        .map(|(name, s)| {
+            format!(
+                r#""{}":{{"calls":{},"total_us":{},"mean_us":{:.1},"max_us":{},"histogram":[{}]}}"#,
+                name,
+                s.calls,
+                s.total_us,
+                s.total_us as f64 / s.calls.max(1) as f64,
+                s.max_us,
+                join(&s.buckets)
+            )
+        })
+        .collect();
+    format!(
+        r#"{{"enabled":{},"bucket_bounds_us":[{}],"entry_points":{{{}}}}}"#,
+        is_enabled(),
+        join(&BUCKET_BOUNDS_US),
+        entries.join(",")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calls_land_in_histogram_buckets() {
+        let mut stats = EntryStats::default();
+        for us in [0, 3, 3, 50, 100_000] {
+            stats.record(us);
+        }
+        assert_eq!((stats.calls, stats.total_us, stats.max_us), (5, 100_056, 100_000));
+        assert_eq!(stats.buckets, [1, 2, 0, 1, 0, 0, 0, 0, 1]);
+
+        record("testEntryPoint", 2);
+        assert_eq!(get("testEntryPoint").map(|s| s.calls), Some(1));
+        assert!(to_json().contains(r#""testEntryPoint":{"calls":1,"total_us":2,"mean_us":2.0,"max_us":2,"histogram":[0,1,0,0,0,0,0,0,0]}"#));
+    }
+}
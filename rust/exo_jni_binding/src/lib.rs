@@ -3,27 +3,105 @@
 //! Provides JNI interface for Android applications to access Vulkan GPU functionality.
 //! Enables Kotlin/Java code to enumerate devices, allocate memory, and perform GPU operations.
 
-#![allow(unsafe_code, missing_inline_in_public_items)]
+#![allow(unsafe_code, clippy::missing_inline_in_public_items)]
+
+pub mod args;
+pub mod device_context;
+pub mod dlpack;
+pub mod ffi;
+pub mod hardware_buffer;
+pub mod jni_stats;
+pub mod natives;
+pub mod version;
 
 use jni::JNIEnv;
-use jni::objects::{JClass, JString};
-use jni::sys::{jint, jlong, jbyteArray, jstring, jboolean};
+use jni::objects::{JByteArray, JByteBuffer, JClass, JFloatArray, JIntArray, JLongArray, JObject, JObjectArray, JString};
+use jni::sys::{jint, jintArray, jlong, jlongArray, jbyteArray, jsize, jstring, jboolean, jfloat};
 use log::{error, info};
 use std::sync::Arc;
-use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use std::collections::{BTreeMap, HashMap};
 use uuid::Uuid;
 use lazy_static::lazy_static;
 use parking_lot::Mutex;
+use serde::Serialize;
+
+use exo_vulkan_binding::{initialize_vulkan, enumerate_vulkan_devices, is_vulkan_supported, reset_vulkan, set_loader_path, DeviceInfo, VulkanContext};
+use exo_vulkan_binding::models::{LoadedModel, ModelManager};
+use exo_vulkan_binding::bandwidth;
+#[cfg(feature = "kernels-core")]
+use exo_vulkan_binding::bench_suite::{self, SuiteConfig};
+#[cfg(feature = "kernels-core")]
+use exo_vulkan_binding::regression;
+use exo_vulkan_binding::bucketing::{self, BucketPolicy};
+use exo_vulkan_binding::capability_cache::{self, CapabilityCache};
+use exo_vulkan_binding::config::RuntimeConfig;
+use exo_vulkan_binding::debug_layers;
+#[cfg(feature = "kernels-core")]
+use exo_vulkan_binding::embeddings;
+#[cfg(feature = "validation")]
+use exo_vulkan_binding::checkpoint::{BackendState, ModelState, TensorState};
+use exo_vulkan_binding::eval;
+use exo_vulkan_binding::events::{self, GpuEvent};
+use exo_vulkan_binding::frame_budget::{self, FrameBudget};
+use exo_vulkan_binding::handover::{self, HandoverManifest, HandoverTensor};
+use exo_vulkan_binding::hotplug::DeviceSlots;
+#[cfg(feature = "validation")]
+use exo_vulkan_binding::inspect;
+use exo_vulkan_binding::jobs::{self, JobState};
+use exo_vulkan_binding::kernel_args::KernelInterface;
+use exo_vulkan_binding::kernels;
+use exo_vulkan_binding::kernel_plugins::{self, KernelMetadata};
+use exo_vulkan_binding::kernel_select::{self, DeviceProfile};
+use exo_vulkan_binding::object_budget;
+use exo_vulkan_binding::performance;
+#[cfg(feature = "profiling")]
+use exo_vulkan_binding::profiler;
+use exo_vulkan_binding::token_history;
+use exo_vulkan_binding::trace;
+use exo_vulkan_binding::transfer_scheduler;
+#[cfg(feature = "kernels-core")]
+use exo_vulkan_binding::calibration::QuantParams;
+#[cfg(feature = "kernels-core")]
+use exo_vulkan_binding::weight_delta::{WeightEncoding, WeightTable, WeightTensor};
+#[cfg(feature = "kernels-media")]
+use exo_vulkan_binding::media;
+use exo_vulkan_binding::loader::{self, LoaderError};
+use exo_vulkan_binding::speculative;
+#[cfg(feature = "remote-debug")]
+use exo_vulkan_binding::remote::RemoteServer;
+use exo_vulkan_binding::registry::{HandleRegistry, RegistryError, DEFAULT_CLIENT};
+use exo_vulkan_binding::memory_watermark::{MemoryWatermarks, DEFAULT_THRESHOLDS};
+use exo_vulkan_binding::metrics_history::{self, Resolution};
+use exo_vulkan_binding::session::{SessionError, SessionKeeper, DEFAULT_KEEP_ALIVE};
 
-use exo_vulkan_binding::{initialize_vulkan, enumerate_vulkan_devices, VulkanContext};
+use crate::device_context::DeviceContext;
 
 /// Device handles allocated from JNI
 #[derive(Clone, Debug)]
 struct DeviceHandle {
     device_id: String,
-    name: String,
-    vendor: String,
-    memory_bytes: u64,
+    info: DeviceInfo,
+    /// Present in the latest enumeration
+    online: bool,
+}
+
+/// One device of the `enumerateDevices` report
+#[derive(Serialize)]
+struct DeviceEntry<'a> {
+    /// Index handle, `vulkan:<index>`
+    device_id: String,
+    online: bool,
+    #[serde(flatten)]
+    info: &'a DeviceInfo,
+}
+
+/// The `enumerateDevices` report
+#[derive(Serialize)]
+struct DeviceReport<'a> {
+    devices: Vec<DeviceEntry<'a>>,
+    count: usize,
+    online: usize,
 }
 
 /// Memory handle for JNI access
@@ -38,7 +116,25 @@ struct MemoryAllocation {
 lazy_static! {
     static ref VULKAN_CONTEXT: Mutex<Option<Arc<VulkanContext>>> = Mutex::new(None);
     static ref DEVICE_HANDLES: Mutex<HashMap<String, DeviceHandle>> = Mutex::new(HashMap::new());
-    static ref MEMORY_ALLOCATIONS: Mutex<HashMap<String, MemoryAllocation>> = Mutex::new(HashMap::new());
+    static ref DEVICE_SLOTS: Mutex<DeviceSlots> = Mutex::new(DeviceSlots::new());
+    static ref MEMORY_ALLOCATIONS: Mutex<HandleRegistry<MemoryAllocation>> = Mutex::new(HandleRegistry::new());
+    static ref MODEL_MANAGER: Mutex<Option<ModelManager>> = Mutex::new(None);
+    static ref MEMORY_WATERMARKS: Mutex<Option<MemoryWatermarks>> = Mutex::new(None);
+    static ref SESSIONS: Mutex<SessionKeeper> = Mutex::new(SessionKeeper::new());
+    static ref CONFIG: Mutex<RuntimeConfig> = Mutex::new(RuntimeConfig::default());
+    static ref EVENT_RECEIVER: Mutex<tokio::sync::broadcast::Receiver<GpuEvent>> = Mutex::new(events::subscribe());
+}
+
+// Development server started by startRemoteShim
+#[cfg(feature = "remote-debug")]
+lazy_static! {
+    static ref REMOTE_SHIM: Mutex<Option<RemoteServer>> = Mutex::new(None);
+}
+
+// Weight tensors named by registerWeight
+#[cfg(feature = "kernels-core")]
+lazy_static! {
+    static ref WEIGHTS: Mutex<WeightTable> = Mutex::new(WeightTable::new());
 }
 
 /// Initialize Vulkan context if not already done
@@ -48,8 +144,25 @@ fn get_or_init_vulkan() -> Result<Arc<VulkanContext>, String> {
         return Ok(Arc::clone(context));
     }
     
-    let context = initialize_vulkan()
-        .map_err(|e| format!("Vulkan initialization failed: {}", e))?;
+    // Start buffering events for pollEvents() as early as possible
+    lazy_static::initialize(&EVENT_RECEIVER);
+
+    // Cold configuration is consumed here
+    if let Some(path) = CONFIG.lock().loader_path.clone() {
+        set_loader_path((!path.is_empty()).then(|| path.into()))
+            .map_err(|e| format!("Configured loader path rejected: {}", e))?;
+    }
+    if let Some(enabled) = CONFIG.lock().debug_layers {
+        debug_layers::set_enabled(enabled);
+    }
+
+    let context = initialize_vulkan().map_err(|e| {
+        events::emit(GpuEvent::Error {
+            source: "initialize_vulkan",
+            message: e.to_string(),
+        });
+        format!("Vulkan initialization failed: {}", e)
+    })?;
     *ctx = Some(Arc::clone(&context));
     Ok(context)
 }
@@ -64,6 +177,7 @@ pub extern "C" fn Java_com_exo_gpu_VulkanGpu_initializeVulkan(
     _env: JNIEnv,
     _class: JClass,
 ) -> jboolean {
+    let _timer = jni_stats::time("initializeVulkan");
     match get_or_init_vulkan() {
         Ok(_ctx) => {
             info!("Vulkan initialized via JNI");
@@ -76,7 +190,92 @@ pub extern "C" fn Java_com_exo_gpu_VulkanGpu_initializeVulkan(
     }
 }
 
+/// Probe whether Vulkan is usable without throwing
+/// @return true if a Vulkan loader, driver and at least one device are present
+// SAFETY: JNI function - never throws, never touches JNIEnv
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_isVulkanSupported(
+    _env: JNIEnv,
+    _class: JClass,
+) -> jboolean {
+    let _timer = jni_stats::time("isVulkanSupported");
+    jboolean::from(is_vulkan_supported())
+}
+
+/// Point the Vulkan loader at an explicit library (e.g. an updated driver)
+/// @param path: absolute path to the library, or null/empty for the system loader
+/// @return true if the override was applied
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_setVulkanLoaderPath(
+    mut env: JNIEnv,
+    _class: JClass,
+    path: JString,
+) -> jboolean {
+    let _timer = jni_stats::time("setVulkanLoaderPath");
+    match (|| -> Result<(), String> {
+        let path: Option<String> = if path.is_null() {
+            None
+        } else {
+            let path: String = env
+                .get_string(&path)
+                .map_err(|e| format!("Failed to get loader path string: {}", e))?
+                .into();
+            Some(path).filter(|p| !p.is_empty())
+        };
+
+        if VULKAN_CONTEXT.lock().is_some() {
+            return Err("Vulkan already initialized; call shutdown() first".to_string());
+        }
+        set_loader_path(path.map(std::path::PathBuf::from)).map_err(|e| e.to_string())
+    })() {
+        Ok(()) => jboolean::from(true),
+        Err(e) => {
+            error!("Setting Vulkan loader path failed: {}", e);
+            let _ = env.throw_new("java/lang/IllegalStateException", &e);
+            jboolean::from(false)
+        }
+    }
+}
+
+/// Map an enumeration onto the stable device indices and publish it
+///
+/// Rebuilds `DEVICE_HANDLES` from every device seen so far, offline ones
+/// included, and returns the JSON `enumerateDevices` and `rescanDevices`
+/// report.
+fn publish_devices(devices: Vec<DeviceInfo>) -> Result<String, String> {
+    let mut slots = DEVICE_SLOTS.lock();
+    slots.reconcile(devices);
+
+    let mut handles = DEVICE_HANDLES.lock();
+    handles.clear();
+    let mut entries = Vec::new();
+    for (idx, slot) in slots.iter().enumerate() {
+        let device_id = format!("vulkan:{}", idx);
+        let handle = DeviceHandle {
+            device_id: device_id.clone(),
+            info: slot.info.clone(),
+            online: slot.is_online(),
+        };
+        handles.insert(device_id.clone(), handle);
+        entries.push(DeviceEntry {
+            device_id,
+            online: slot.is_online(),
+            info: &slot.info,
+        });
+    }
+
+    let report = DeviceReport {
+        devices: entries,
+        count: slots.len(),
+        online: slots.iter().filter(|slot| slot.is_online()).count(),
+    };
+    serde_json::to_string(&report).map_err(|e| format!("Failed to serialize devices: {}", e))
+}
+
 /// Enumerate Vulkan devices available on the system
+/// Indices are stable: a device keeps the index it was first given for the
+/// life of the process, and one that disappears stays listed as offline.
 /// @return JSON array of device info, or null on error
 // SAFETY: JNI function - device list is valid for call duration
 #[unsafe(no_mangle)]
@@ -84,6 +283,7 @@ pub extern "C" fn Java_com_exo_gpu_VulkanGpu_enumerateDevices(
     mut env: JNIEnv,
     _class: JClass,
 ) -> jstring {
+    let _timer = jni_stats::time("enumerateDevices");
     match (|| -> Result<String, String> {
         let vulkan_ctx = get_or_init_vulkan()?;
         let devices = vulkan_ctx
@@ -91,51 +291,7 @@ pub extern "C" fn Java_com_exo_gpu_VulkanGpu_enumerateDevices(
             .map_err(|e| format!("Device enumeration failed: {}", e))?;
         
         info!("Enumerated {} Vulkan devices", devices.len());
-        
-        // Clear old handles
-        {
-            let mut handles = DEVICE_HANDLES.lock();
-            handles.clear();
-        }
-        
-        // Build JSON response and store handles
-        let mut device_jsons = Vec::new();
-        {
-            let mut handles = DEVICE_HANDLES.lock();
-            
-            for (idx, dev_info) in devices.iter().enumerate() {
-                let device_id = format!("vulkan:{}", idx);
-                
-                // Store handle
-                let handle = DeviceHandle {
-                    device_id: device_id.clone(),
-                    name: dev_info.name.clone(),
-                    vendor: dev_info.vendor.clone(),
-                    memory_bytes: dev_info.total_memory_bytes,
-                };
-                handles.insert(device_id.clone(), handle);
-                
-                // Build JSON for device
-                let device_json = format!(
-                    r#"{{"device_id":"{}","name":"{}","vendor":"{}","memory_bytes":{},"compute_units":{},"bandwidth_gbps":{}}}"#,
-                    device_id,
-                    dev_info.name,
-                    dev_info.vendor,
-                    dev_info.total_memory_bytes,
-                    dev_info.compute_units,
-                    dev_info.bandwidth_gbps as i32
-                );
-                device_jsons.push(device_json);
-            }
-        }
-        
-        // Return as JSON array
-        let json = format!(r#"{{"devices":[{}],"count":{}}}"#, 
-            device_jsons.join(","),
-            devices.len()
-        );
-        
-        Ok(json)
+        publish_devices(devices)
     })() {
         Ok(json) => {
             match env.new_string(&json) {
@@ -155,6 +311,74 @@ pub extern "C" fn Java_com_exo_gpu_VulkanGpu_enumerateDevices(
     }
 }
 
+/// Re-enumerate devices on a fresh Vulkan instance, picking up hot-plugged GPUs
+/// Call after an eGPU is attached or removed, or a driver restarts. Known
+/// devices keep their indices, vanished ones go offline and new ones are
+/// appended; each change is reported by `pollEvents` as a `device_status` event.
+/// @return JSON as `enumerateDevices`, or null on error
+// SAFETY: JNI function - device list is valid for call duration
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_rescanDevices(
+    mut env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let _timer = jni_stats::time("rescanDevices");
+    match (|| -> Result<String, String> {
+        // The loader only reports devices present when an instance is created
+        *VULKAN_CONTEXT.lock() = None;
+        reset_vulkan();
+        let devices = get_or_init_vulkan()?
+            .enumerate_devices()
+            .map_err(|e| format!("Device enumeration failed: {}", e))?;
+
+        info!("Rescanned {} Vulkan devices", devices.len());
+        publish_devices(devices)
+    })() {
+        Ok(json) => match env.new_string(&json) {
+            Ok(jstr) => jstr.into_raw(),
+            Err(e) => {
+                error!("Failed to create JNI string: {}", e);
+                let _ = env.throw_new("java/lang/RuntimeException", e.to_string());
+                std::ptr::null_mut()
+            }
+        },
+        Err(e) => {
+            error!("Failed to rescan devices: {}", e);
+            let _ = env.throw_new("java/lang/RuntimeException", e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// The enumerated device at `device_index`
+fn device_handle(device_index: jint) -> Result<DeviceHandle, String> {
+    let handles = DEVICE_HANDLES.lock();
+    if handles.is_empty() {
+        return Err("No devices enumerated; call enumerateDevices() first".to_string());
+    }
+    let index = args::index("device index", device_index, handles.len())?;
+    let handle = handles
+        .get(&format!("vulkan:{}", index))
+        .cloned()
+        .ok_or_else(|| format!("Device vulkan:{} not found", index))?;
+    if !handle.online {
+        return Err(format!("Device {} is offline", handle.device_id));
+    }
+    Ok(handle)
+}
+
+/// Index in the current Vulkan context of the device at stable `device_index`
+///
+/// Before `enumerateDevices` has been called the two coincide.
+fn context_index(device_index: jint) -> Result<usize, String> {
+    let index = args::count("device index", device_index)?;
+    let slots = DEVICE_SLOTS.lock();
+    if slots.is_empty() {
+        return Ok(index);
+    }
+    slots.context_index(index).map_err(|e| e.to_string())
+}
+
 /// Get device name by index
 /// @param device_index: index of device to query
 /// @return device name as JNI string
@@ -165,22 +389,22 @@ pub extern "C" fn Java_com_exo_gpu_VulkanGpu_getDeviceName(
     _class: JClass,
     device_index: jint,
 ) -> jstring {
-    let handles = DEVICE_HANDLES.lock();
-    let device_id = format!("vulkan:{}", device_index);
-    
-    if let Some(handle) = handles.get(&device_id) {
-        match env.new_string(&handle.name) {
-            Ok(jstr) => return jstr.into_raw(),
+    let _timer = jni_stats::time("getDeviceName");
+    match device_handle(device_index) {
+        Ok(handle) => match env.new_string(&handle.info.name) {
+            Ok(jstr) => jstr.into_raw(),
             Err(e) => {
                 error!("Failed to create JNI string: {}", e);
-                let _ = env.throw_new("java/lang/RuntimeException", &e.to_string());
+                let _ = env.throw_new("java/lang/RuntimeException", e.to_string());
+                std::ptr::null_mut()
             }
+        },
+        Err(e) => {
+            error!("Get device name failed: {}", e);
+            let _ = env.throw_new("java/lang/IllegalArgumentException", e);
+            std::ptr::null_mut()
         }
-    } else {
-        error!("Device {} not found", device_id);
-        let _ = env.throw_new("java/lang/IllegalArgumentException", "Device not found");
     }
-    std::ptr::null_mut()
 }
 
 /// Get device memory size in bytes
@@ -193,16 +417,13 @@ pub extern "C" fn Java_com_exo_gpu_VulkanGpu_getDeviceMemory(
     _class: JClass,
     device_index: jint,
 ) -> jlong {
-    let handles = DEVICE_HANDLES.lock();
-    let device_id = format!("vulkan:{}", device_index);
-    
-    handles
-        .get(&device_id)
-        .map(|h| h.memory_bytes as jlong)
-        .unwrap_or(0)
+    let _timer = jni_stats::time("getDeviceMemory");
+    device_handle(device_index).map_or(0, |h| jlong::try_from(h.info.total_memory_bytes).unwrap_or(jlong::MAX))
 }
 
 /// Get device compute units
+/// Reported by the driver where a vendor extension exposes the count,
+/// estimated otherwise; `enumerateDevices` says which.
 /// @param device_index: index of device to query
 /// @return number of compute units, or 0 if device not found
 // SAFETY: JNI function - no unsafe operations
@@ -212,216 +433,3245 @@ pub extern "C" fn Java_com_exo_gpu_VulkanGpu_getComputeUnits(
     _class: JClass,
     device_index: jint,
 ) -> jint {
-    // For now return a default value since we don't store this yet
-    // TODO: Store compute units in DeviceHandle
-    16
+    let _timer = jni_stats::time("getComputeUnits");
+    device_handle(device_index).map_or(0, |h| jint::try_from(h.info.compute_units).unwrap_or(jint::MAX))
 }
 
-// ============ Memory Functions ============
+/// Get a string property of a device for the granular getters
+fn device_string(
+    env: &mut JNIEnv,
+    device_index: jint,
+    what: &str,
+    property: fn(&DeviceInfo) -> &str,
+) -> jstring {
+    match device_handle(device_index) {
+        Ok(handle) => match env.new_string(property(&handle.info)) {
+            Ok(jstr) => jstr.into_raw(),
+            Err(e) => {
+                error!("Failed to create JNI string: {}", e);
+                let _ = env.throw_new("java/lang/RuntimeException", e.to_string());
+                std::ptr::null_mut()
+            }
+        },
+        Err(e) => {
+            error!("Get device {} failed: {}", what, e);
+            let _ = env.throw_new("java/lang/IllegalArgumentException", e);
+            std::ptr::null_mut()
+        }
+    }
+}
 
-/// Allocate memory on device
-/// @param device_index: device to allocate on
-/// @param size_bytes: number of bytes to allocate
-/// @return handle ID as JNI string, or null on error
+/// Get a device's driver version
+/// @param device_index: index of device to query
+/// @return driver-specific version number as a string
+// SAFETY: JNI function - returns valid string or null
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_getDriverVersion(
+    mut env: JNIEnv,
+    _class: JClass,
+    device_index: jint,
+) -> jstring {
+    let _timer = jni_stats::time("getDriverVersion");
+    device_string(&mut env, device_index, "driver version", |info| &info.driver_version)
+}
+
+/// Get a device's highest supported Vulkan version
+/// @param device_index: index of device to query
+/// @return version as "major.minor.patch"
+// SAFETY: JNI function - returns valid string or null
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_getApiVersion(
+    mut env: JNIEnv,
+    _class: JClass,
+    device_index: jint,
+) -> jstring {
+    let _timer = jni_stats::time("getApiVersion");
+    device_string(&mut env, device_index, "API version", |info| &info.api_version)
+}
+
+/// Get a device's PCI vendor id
+/// @param device_index: index of device to query
+/// @return vendor id, or 0 if device not found
+// SAFETY: JNI function - no unsafe operations
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_getVendorId(
+    _env: JNIEnv,
+    _class: JClass,
+    device_index: jint,
+) -> jint {
+    let _timer = jni_stats::time("getVendorId");
+    // Vendor ids fit in 16 bits, or 17 for Khronos-assigned ones
+    device_handle(device_index).map_or(0, |h| jint::try_from(h.info.vendor_id).unwrap_or(jint::MAX))
+}
+
+/// Get the largest single allocation a device's driver accepts
+/// @param device_index: index of device to query
+/// @return size in bytes, or 0 if device not found
+// SAFETY: JNI function - no unsafe operations
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_getMaxAllocationSize(
+    _env: JNIEnv,
+    _class: JClass,
+    device_index: jint,
+) -> jlong {
+    let _timer = jni_stats::time("getMaxAllocationSize");
+    device_handle(device_index).map_or(0, |h| jlong::try_from(h.info.max_allocation_size).unwrap_or(jlong::MAX))
+}
+
+/// Get a device's capability report, cached on disk per device and driver
+/// With `cache_dir` configured, the report and kernel autotune results are
+/// loaded from the cache when it was written by the current driver version,
+/// and re-probed (and the cache replaced) after a driver update.
+/// @param device_index: index of device to query
+/// @return JSON `{"cached":bool,"capabilities":{"name","vendor_id",...,"features","extensions"}}`,
+///         or null on error
 // SAFETY: JNI function - validates inputs and handles errors properly
 #[unsafe(no_mangle)]
-pub extern "C" fn Java_com_exo_gpu_VulkanGpu_allocateMemory(
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_getDeviceCapabilities(
     mut env: JNIEnv,
     _class: JClass,
     device_index: jint,
-    size_bytes: jlong,
 ) -> jstring {
+    let _timer = jni_stats::time("getDeviceCapabilities");
     match (|| -> Result<String, String> {
-        if size_bytes <= 0 {
-            return Err("Size must be > 0".to_string());
-        }
-        
-        // Verify device exists
-        {
-            let handles = DEVICE_HANDLES.lock();
-            let device_id = format!("vulkan:{}", device_index);
-            if !handles.contains_key(&device_id) {
-                return Err(format!("Device {} not found", device_id));
-            }
-        }
-        
-        // Create allocation handle
-        let handle_id = Uuid::new_v4().to_string();
-        let device_id = format!("vulkan:{}", device_index);
-        
-        // Store allocation
-        let allocation = MemoryAllocation {
-            handle_id: handle_id.clone(),
-            device_id,
-            size_bytes: size_bytes as u64,
-        };
-        
-        {
-            let mut allocs = MEMORY_ALLOCATIONS.lock();
-            allocs.insert(handle_id.clone(), allocation);
-        }
-        
-        info!("Allocated {} bytes on device {}: {}", size_bytes, device_index, handle_id);
-        
-        Ok(handle_id)
+        let index = context_index(device_index)?;
+        let context = get_or_init_vulkan()?;
+        let cache = CONFIG.lock().cache_dir().map(CapabilityCache::new);
+        let (report, cached) =
+            capability_cache::device_capabilities(&context, index, cache.as_ref()).map_err(|e| e.to_string())?;
+        Ok(format!(r#"{{"cached":{},"capabilities":{}}}"#, cached, report))
     })() {
-        Ok(handle_id) => {
-            match env.new_string(&handle_id) {
-                Ok(jstr) => jstr.into_raw(),
-                Err(e) => {
-                    error!("Failed to create JNI string: {}", e);
-                    let _ = env.throw_new("java/lang/RuntimeException", &e.to_string());
-                    std::ptr::null_mut()
-                }
+        Ok(json) => match env.new_string(&json) {
+            Ok(jstr) => jstr.into_raw(),
+            Err(e) => {
+                error!("Failed to create JNI string: {}", e);
+                let _ = env.throw_new("java/lang/RuntimeException", &e.to_string());
+                std::ptr::null_mut()
             }
-        }
+        },
         Err(e) => {
-            error!("Memory allocation failed: {}", e);
+            error!("Get device capabilities failed: {}", e);
             let _ = env.throw_new("java/lang/RuntimeException", &e);
             std::ptr::null_mut()
         }
     }
 }
 
-/// Free allocated device memory
-/// @param handle_id: memory handle to free
-/// @return true if successful, false otherwise
-// SAFETY: JNI function - handle is validated before freeing
+/// Measure a device's memory bandwidth with a buffer copy benchmark
+/// Runs once per device: the result is kept for the process and, with
+/// `cache_dir` configured, cached on disk until the driver changes. Later
+/// `enumerateDevices` calls report it as `bandwidth_gbps`.
+/// @param device_index: index of device to benchmark
+/// @return copy bandwidth in GB/s, or 0 on error
+// SAFETY: JNI function - validates inputs and handles errors properly
 #[unsafe(no_mangle)]
-pub extern "C" fn Java_com_exo_gpu_VulkanGpu_freeMemory(
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_benchmarkBandwidth(
     mut env: JNIEnv,
     _class: JClass,
-    handle_id: JString,
-) -> jboolean {
-    match env.get_string(&handle_id) {
-        Ok(jstr) => {
-            let handle = jstr.to_string_lossy().to_string();
-            
-            // Remove allocation
-            let mut allocs = MEMORY_ALLOCATIONS.lock();
-            if allocs.remove(&handle).is_some() {
-                info!("Freed memory handle: {}", handle);
-                jboolean::from(true)
-            } else {
-                error!("Memory handle not found: {}", handle);
-                let _ = env.throw_new("java/lang/IllegalArgumentException", "Handle not found");
-                jboolean::from(false)
-            }
-        }
+    device_index: jint,
+) -> jfloat {
+    let _timer = jni_stats::time("benchmarkBandwidth");
+    match (|| -> Result<f32, String> {
+        let index = context_index(device_index)?;
+        let context = get_or_init_vulkan()?;
+        let cache = CONFIG.lock().cache_dir().map(CapabilityCache::new);
+        bandwidth::benchmark(&context, index, cache.as_ref()).map_err(|e| e.to_string())
+    })() {
+        Ok(gbps) => gbps,
         Err(e) => {
-            error!("Failed to get JNI string: {}", e);
-            let _ = env.throw_new("java/lang/RuntimeException", &e.to_string());
-            jboolean::from(false)
+            error!("Bandwidth benchmark failed: {}", e);
+            let _ = env.throw_new("java/lang/RuntimeException", e);
+            0.0
         }
     }
 }
 
-/// Copy data from host to device
-/// @param handle_id: destination memory handle
-/// @param data: data to copy
-/// @return true if successful, false otherwise
-// SAFETY: JNI function - validates handle before proceeding
+/// Time GEMM, attention, dequant and KV append at 1B-13B model sizes
+/// Results are also stored in the capability cache when a cache directory is configured.
+/// Takes seconds to a minute; call off the main thread.
+/// @param ops: comma-separated subset of gemm, attention, dequant, kv_append, or null for all
+/// @return JSON `{"device","driver_version","decode_weight_gbps","results":[{"name","op","ops","flops","bytes","time_us","us_per_op","gflops","gbps"} or {...,"error"}]}`
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[cfg(feature = "kernels-core")]
 #[unsafe(no_mangle)]
-pub extern "C" fn Java_com_exo_gpu_VulkanGpu_copyToDevice(
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_runBenchSuite(
     mut env: JNIEnv,
     _class: JClass,
-    handle_id: JString,
-    data: jbyteArray,
-) -> jboolean {
-    match (|| -> Result<(), String> {
-        // Get handle string
-        let handle_str = env
-            .get_string(&handle_id)
-            .map_err(|e| format!("Failed to get handle string: {}", e))?
-            .to_string_lossy()
-            .to_string();
-        
-        // Verify allocation exists
-        {
-            let allocs = MEMORY_ALLOCATIONS.lock();
-            if !allocs.contains_key(&handle_str) {
-                return Err(format!("Memory handle not found: {}", handle_str));
-            }
+    device_index: jint,
+    ops: JString,
+) -> jstring {
+    let _timer = jni_stats::time("runBenchSuite");
+    match (|| -> Result<String, String> {
+        let index = context_index(device_index)?;
+        let config = if ops.is_null() {
+            SuiteConfig::default()
+        } else {
+            let ops: String = env
+                .get_string(&ops)
+                .map_err(|e| format!("Failed to get ops: {}", e))?
+                .into();
+            let ops: Vec<&str> = ops.split(',').map(str::trim).filter(|op| !op.is_empty()).collect();
+            SuiteConfig::only(&ops).map_err(|e| e.to_string())?
+        };
+        let context = get_or_init_vulkan()?;
+        let report = bench_suite::bench_suite(&context, index, &config).map_err(|e| e.to_string())?;
+        if let Some(cache) = CONFIG.lock().cache_dir().map(CapabilityCache::new) {
+            bench_suite::store_report(&context, index, &report, &cache).map_err(|e| e.to_string())?;
         }
-        
-        // TODO: Get array length and copy data when integrated with proper JNI 
-        // For now, just validate the handle exists
-        info!("Copy to device requested for {}", handle_str);
-        
-        Ok(())
+        Ok(report.to_json())
     })() {
-        Ok(()) => jboolean::from(true),
+        Ok(json) => match env.new_string(json) {
+            Ok(s) => s.into_raw(),
+            Err(_) => std::ptr::null_mut(),
+        },
         Err(e) => {
-            error!("Copy to device failed: {}", e);
-            let _ = env.throw_new("java/lang/RuntimeException", &e);
-            jboolean::from(false)
+            error!("Benchmark suite failed: {}", e);
+            let _ = env.throw_new("java/lang/RuntimeException", e);
+            std::ptr::null_mut()
         }
     }
 }
 
-/// Copy data from device to host
-/// @param handle_id: source memory handle
-/// @param size_bytes: number of bytes to copy
-/// @return byte array with copied data, or null on error
-// SAFETY: JNI function - validates handle and creates appropriately sized array
+/// `runBenchSuite` in builds without the `kernels-core` feature
+// SAFETY: JNI function - throws and returns null
+#[cfg(not(feature = "kernels-core"))]
 #[unsafe(no_mangle)]
-pub extern "C" fn Java_com_exo_gpu_VulkanGpu_copyFromDevice(
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_runBenchSuite(
     mut env: JNIEnv,
     _class: JClass,
-    handle_id: JString,
-    size_bytes: jlong,
-) -> jbyteArray {
-    match (|| -> Result<Vec<u8>, String> {
-        if size_bytes < 0 {
-            return Err("Size must be >= 0".to_string());
+    _device_index: jint,
+    _ops: JString,
+) -> jstring {
+    let _timer = jni_stats::time("runBenchSuite");
+    throw_unsupported(&mut env, "kernels-core");
+    std::ptr::null_mut()
+}
+
+/// Run the embedded golden-output fixtures through the graph executor on a device
+/// Use after a driver update to confirm the kernels still compute what they did.
+/// @return JSON `{"device","driver_version","passed","results":[{"name","passed","outputs":[{"name","passed","max_error","worst_index"}]} or {"name","passed","error"}]}`
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[cfg(feature = "kernels-core")]
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_runRegressionTests(
+    mut env: JNIEnv,
+    _class: JClass,
+    device_index: jint,
+) -> jstring {
+    let _timer = jni_stats::time("runRegressionTests");
+    match (|| -> Result<String, String> {
+        let index = context_index(device_index)?;
+        let context = get_or_init_vulkan()?;
+        let report = regression::run_regression_tests(&context, index).map_err(|e| e.to_string())?;
+        if !report.passed() {
+            error!("Regression tests failed on device {}", index);
         }
-        
-        if size_bytes == 0 {
-            return Ok(Vec::new()); // Nothing to copy
+        Ok(report.to_json())
+    })() {
+        Ok(json) => match env.new_string(json) {
+            Ok(s) => s.into_raw(),
+            Err(_) => std::ptr::null_mut(),
+        },
+        Err(e) => {
+            error!("Regression tests could not run: {}", e);
+            let _ = env.throw_new("java/lang/RuntimeException", e);
+            std::ptr::null_mut()
         }
-        
-        // Get handle string
+    }
+}
+
+/// `runRegressionTests` in builds without the `kernels-core` feature
+// SAFETY: JNI function - throws and returns null
+#[cfg(not(feature = "kernels-core"))]
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_runRegressionTests(
+    mut env: JNIEnv,
+    _class: JClass,
+    _device_index: jint,
+) -> jstring {
+    let _timer = jni_stats::time("runRegressionTests");
+    throw_unsupported(&mut env, "kernels-core");
+    std::ptr::null_mut()
+}
+
+// ============ Memory Functions ============
+
+/// Resolve a nullable client id; null selects the default namespace
+/// The rest of the call is attributed to the client's trace id, if it has one.
+fn client_namespace(env: &mut JNIEnv, client_id: &JString) -> Result<String, String> {
+    let client = if client_id.is_null() {
+        DEFAULT_CLIENT.to_string()
+    } else {
+        env.get_string(client_id)
+            .map_err(|e| format!("Failed to get client id: {}", e))?
+            .to_string_lossy()
+            .to_string()
+    };
+    trace::enter_client(&client);
+    Ok(client)
+}
+
+/// Length of a Java array holding `len` elements
+fn array_len(len: usize) -> Result<jsize, String> {
+    jsize::try_from(len).map_err(|_| format!("{} elements exceed a Java array", len))
+}
+
+/// A Java int array holding `values`
+fn int_array(env: &mut JNIEnv, values: &[u32]) -> Result<jintArray, String> {
+    let values = values
+        .iter()
+        .map(|&v| jint::try_from(v).map_err(|_| format!("Value {} exceeds jint", v)))
+        .collect::<Result<Vec<jint>, _>>()?;
+    let array = env
+        .new_int_array(array_len(values.len())?)
+        .map_err(|e| format!("Failed to create int array: {}", e))?;
+    env.set_int_array_region(&array, 0, &values)
+        .map_err(|e| format!("Failed to fill int array: {}", e))?;
+    Ok(array.into_raw())
+}
+
+/// A Java long array holding `values`
+fn long_array(env: &mut JNIEnv, values: &[u64]) -> Result<jlongArray, String> {
+    let values = values
+        .iter()
+        .map(|&v| jlong::try_from(v).map_err(|_| format!("Value {} exceeds jlong", v)))
+        .collect::<Result<Vec<jlong>, _>>()?;
+    let array = env
+        .new_long_array(array_len(values.len())?)
+        .map_err(|e| format!("Failed to create long array: {}", e))?;
+    env.set_long_array_region(&array, 0, &values)
+        .map_err(|e| format!("Failed to fill long array: {}", e))?;
+    Ok(array.into_raw())
+}
+
+/// The device context of the device at stable `device_index`, opened on first use
+fn open_device(device_index: jint) -> Result<Arc<DeviceContext>, String> {
+    let device = device_handle(device_index)?;
+    let vulkan = get_or_init_vulkan()?;
+    Ok(device_context::open(&device.device_id, &vulkan, context_index(device_index)?)?)
+}
+
+/// The device context holding a registered allocation
+fn allocation_context(allocation: &MemoryAllocation) -> Result<Arc<DeviceContext>, String> {
+    Ok(device_context::get(&allocation.device_id)?)
+}
+
+/// Free the device memory of allocations removed from the registry
+fn free_device_memory<'a>(allocations: impl IntoIterator<Item = &'a MemoryAllocation>) {
+    for allocation in allocations {
+        let freed = allocation_context(allocation).and_then(|context| Ok(context.free(&allocation.handle_id)?));
+        if let Err(e) = freed {
+            error!("Failed to free device memory of {}: {}", allocation.handle_id, e);
+        }
+    }
+}
+
+/// Open a client namespace (gRPC session, Android Activity)
+/// Handles allocated under it can only be used and freed by the same client.
+/// @return client ID as JNI string, or null on error
+// SAFETY: JNI function - no inputs, only registry state is touched
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_openClient(
+    mut env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let _timer = jni_stats::time("openClient");
+    let client_id = MEMORY_ALLOCATIONS.lock().open_client();
+    info!("Opened client namespace {}", client_id);
+    match env.new_string(&client_id) {
+        Ok(jstr) => jstr.into_raw(),
+        Err(e) => {
+            error!("Failed to create JNI string: {}", e);
+            let _ = env.throw_new("java/lang/RuntimeException", &e.to_string());
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Free every handle a client namespace still owns
+fn release_client(client: &str) -> Result<usize, String> {
+    let released = MEMORY_ALLOCATIONS
+        .lock()
+        .close_client(client)
+        .map_err(|e| e.to_string())?;
+    free_device_memory(released.iter().map(|(_, a)| a));
+    let bytes: u64 = released.iter().map(|(_, a)| a.size_bytes).sum();
+    info!("Closed client {}: freed {} handles ({} bytes)", client, released.len(), bytes);
+    let _ = trace::set_client_trace(client, None);
+    token_history::unregister(client);
+    check_memory_watermarks();
+    Ok(released.len())
+}
+
+/// Release pinned sessions nobody resumed within their keep-alive
+fn expire_sessions() {
+    let expired = SESSIONS.lock().expire(std::time::Instant::now());
+    for client in expired {
+        info!("Pinned session {} expired", client);
+        if let Err(e) = release_client(&client) {
+            error!("Failed to release expired session {}: {}", client, e);
+        }
+    }
+}
+
+/// Close a client namespace, freeing every handle it still owns
+/// A pinned session is detached instead and kept alive for resumeSession;
+/// closing it again while detached frees it.
+/// @param client_id: ID from openClient
+/// @return number of handles freed (0 if detached), or -1 on error
+// SAFETY: JNI function - client ID is validated before cleanup
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_closeClient(
+    mut env: JNIEnv,
+    _class: JClass,
+    client_id: JString,
+) -> jint {
+    let _timer = jni_stats::time("closeClient");
+    match (|| -> Result<jint, String> {
+        if client_id.is_null() {
+            return Err("Client id must not be null".to_string());
+        }
+        let client = client_namespace(&mut env, &client_id)?;
+        expire_sessions();
+        if SESSIONS.lock().detach(&client, std::time::Instant::now()) {
+            info!("Detached pinned session {}", client);
+            return Ok(0);
+        }
+        let freed = release_client(&client)?;
+        jint::try_from(freed).map_err(|_| format!("{} freed handles exceed jint", freed))
+    })() {
+        Ok(count) => count,
+        Err(e) => {
+            error!("Close client failed: {}", e);
+            let _ = env.throw_new("java/lang/IllegalArgumentException", &e);
+            -1
+        }
+    }
+}
+
+/// Pin a client namespace so it survives Activity recreation
+/// After pinning, closeClient (e.g. from onDestroy during a configuration
+/// change) only detaches the session; its handles, KV cache and in-flight
+/// generation stay alive for the keep-alive window (30 s) so the recreated
+/// Activity can resumeSession with the ID saved in onSaveInstanceState.
+/// @param client_id: ID from openClient
+/// @return true if the session is pinned
+// SAFETY: JNI function - client ID is validated against the registry
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_pinSession(
+    mut env: JNIEnv,
+    _class: JClass,
+    client_id: JString,
+) -> jboolean {
+    let _timer = jni_stats::time("pinSession");
+    match (|| -> Result<(), String> {
+        if client_id.is_null() {
+            return Err("Client id must not be null".to_string());
+        }
+        let client = client_namespace(&mut env, &client_id)?;
+        expire_sessions();
+        if !MEMORY_ALLOCATIONS.lock().has_client(&client) {
+            return Err(format!("Unknown client: {}", client));
+        }
+        SESSIONS.lock().pin(&client, DEFAULT_KEEP_ALIVE);
+        info!("Pinned session {}", client);
+        Ok(())
+    })() {
+        Ok(()) => jboolean::from(true),
+        Err(e) => {
+            error!("Pin session failed: {}", e);
+            let _ = env.throw_new("java/lang/IllegalArgumentException", &e);
+            jboolean::from(false)
+        }
+    }
+}
+
+/// Take over a detached pinned session after Activity recreation
+/// The session stays pinned; handles allocated under it remain valid.
+/// @param client_id: ID of a pinned session
+/// @return true if resumed, false if the session expired or was never pinned
+///         (open a new client instead)
+// SAFETY: JNI function - client ID is validated against the session keeper
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_resumeSession(
+    mut env: JNIEnv,
+    _class: JClass,
+    client_id: JString,
+) -> jboolean {
+    let _timer = jni_stats::time("resumeSession");
+    match (|| -> Result<bool, String> {
+        if client_id.is_null() {
+            return Err("Client id must not be null".to_string());
+        }
+        let client = client_namespace(&mut env, &client_id)?;
+        expire_sessions();
+        match SESSIONS.lock().resume(&client) {
+            Ok(()) => {
+                info!("Resumed session {}", client);
+                Ok(true)
+            }
+            Err(SessionError::NotPinned(_)) => Ok(false),
+            Err(e) => Err(e.to_string()),
+        }
+    })() {
+        Ok(resumed) => jboolean::from(resumed),
+        Err(e) => {
+            error!("Resume session failed: {}", e);
+            let _ = env.throw_new("java/lang/IllegalStateException", &e);
+            jboolean::from(false)
+        }
+    }
+}
+
+/// Memory budget across all enumerated devices, if any are known
+fn memory_budget() -> Option<u64> {
+    let handles = DEVICE_HANDLES.lock();
+    (!handles.is_empty()).then(|| handles.values().filter(|h| h.online).map(|h| h.info.total_memory_bytes).sum())
+}
+
+/// Re-check memory high-water marks against current allocations, tagged by client
+/// Also records the total into the metrics history.
+fn check_memory_watermarks() {
+    let mut usage: HashMap<String, u64> = HashMap::new();
+    for (client, _, allocation) in MEMORY_ALLOCATIONS.lock().iter() {
+        *usage.entry(client.to_string()).or_default() += allocation.size_bytes;
+    }
+    metrics_history::global().record_memory(usage.values().sum(), Instant::now());
+
+    let mut marks = MEMORY_WATERMARKS.lock();
+    if marks.is_none() {
+        let Some(budget) = memory_budget() else {
+            return;
+        };
+        let thresholds = CONFIG
+            .lock()
+            .memory_thresholds()
+            .unwrap_or_else(|| DEFAULT_THRESHOLDS.to_vec());
+        *marks = Some(MemoryWatermarks::new(budget, &thresholds));
+    }
+
+    let usage: Vec<(String, u64)> = usage.into_iter().collect();
+    if let Some(marks) = marks.as_mut() {
+        marks.observe(&usage);
+    }
+}
+
+/// Set the memory usage levels that raise `memory_pressure` events
+/// Defaults to 80% and 95% of total device memory.
+/// @param percents: thresholds as percentages of total device memory, in (0, 100]
+/// @return true if the thresholds were applied
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_setMemoryThresholds(
+    mut env: JNIEnv,
+    _class: JClass,
+    percents: JIntArray,
+) -> jboolean {
+    let _timer = jni_stats::time("setMemoryThresholds");
+    match (|| -> Result<(), String> {
+        let len = env
+            .get_array_length(&percents)
+            .map_err(|e| format!("Failed to get thresholds length: {}", e))?;
+        let mut values = vec![0; args::count("thresholds length", len)?];
+        env.get_int_array_region(&percents, 0, &mut values)
+            .map_err(|e| format!("Failed to read thresholds: {}", e))?;
+        for &percent in &values {
+            args::in_range("threshold percent", percent, 1..=100)?;
+        }
+
+        let budget = memory_budget()
+            .ok_or_else(|| "No devices enumerated; call enumerateDevices() first".to_string())?;
+        let thresholds: Vec<f32> = values.iter().map(|&p| p as f32 / 100.0).collect();
+        *MEMORY_WATERMARKS.lock() = Some(MemoryWatermarks::new(budget, &thresholds));
+        info!("Memory thresholds set to {:?}% of {} bytes", values, budget);
+        Ok(())
+    })() {
+        Ok(()) => {
+            check_memory_watermarks();
+            jboolean::from(true)
+        }
+        Err(e) => {
+            error!("Set memory thresholds failed: {}", e);
+            let _ = env.throw_new("java/lang/IllegalArgumentException", &e);
+            jboolean::from(false)
+        }
+    }
+}
+
+/// Allocate memory on device
+/// @param device_index: device to allocate on
+/// @param size_bytes: number of bytes to allocate, at most the device's memory
+/// @param client_id: owning client from openClient, or null for the default namespace
+/// @return handle ID as JNI string, or null on error
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_allocateMemory(
+    mut env: JNIEnv,
+    _class: JClass,
+    device_index: jint,
+    size_bytes: jlong,
+    client_id: JString,
+) -> jstring {
+    let _timer = jni_stats::time("allocateMemory");
+    match (|| -> Result<String, String> {
+        let client = client_namespace(&mut env, &client_id)?;
+        let device = device_handle(device_index)?;
+        let size_bytes = args::in_range("size_bytes", size_bytes, 1..=device.info.total_memory_bytes)?;
+        
+        // Create allocation handle
+        let handle_id = Uuid::new_v4().to_string();
+        let context = open_device(device_index)?;
+        context.allocate(&handle_id, size_bytes)?;
+        
+        // Store allocation
+        let allocation = MemoryAllocation {
+            handle_id: handle_id.clone(),
+            device_id: device.device_id,
+            size_bytes,
+        };
+        
+        {
+            let mut allocs = MEMORY_ALLOCATIONS.lock();
+            if let Err(e) = allocs.insert(&client, &handle_id, allocation) {
+                let _ = context.free(&handle_id);
+                return Err(e.to_string());
+            }
+        }
+        
+        info!("Allocated {} bytes on device {}: {}", size_bytes, device_index, handle_id);
+        check_memory_watermarks();
+        
+        Ok(handle_id)
+    })() {
+        Ok(handle_id) => {
+            match env.new_string(&handle_id) {
+                Ok(jstr) => jstr.into_raw(),
+                Err(e) => {
+                    error!("Failed to create JNI string: {}", e);
+                    let _ = env.throw_new("java/lang/RuntimeException", &e.to_string());
+                    std::ptr::null_mut()
+                }
+            }
+        }
+        Err(e) => {
+            error!("Memory allocation failed: {}", e);
+            let _ = env.throw_new("java/lang/RuntimeException", &e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Allocate many buffers in one call (e.g. every tensor of a model graph)
+/// All sizes are validated first, the buffers of each device are packed
+/// into one slab (`MemoryAllocator::allocate_many`) and the handles are
+/// registered under one lock; either every buffer is allocated or none is.
+/// Slab buffers cannot be handed over by `prepareHandover`.
+/// @param specs_json: JSON array of `{"size": bytes, "device_index": n}`; device_index defaults to 0
+/// @param client_id: owning client from openClient, or null for the default namespace
+/// @return JSON array of handle IDs in spec order, or null on error
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_allocateBuffers(
+    mut env: JNIEnv,
+    _class: JClass,
+    specs_json: JString,
+    client_id: JString,
+) -> jstring {
+    let _timer = jni_stats::time("allocateBuffers");
+    match (|| -> Result<String, String> {
+        let json: String = env
+            .get_string(&specs_json)
+            .map_err(|e| format!("Failed to get specs: {}", e))?
+            .into();
+        let client = client_namespace(&mut env, &client_id)?;
+        let specs: Vec<serde_json::Value> =
+            serde_json::from_str(&json).map_err(|e| format!("Malformed specs: {}", e))?;
+
+        let mut planned = Vec::with_capacity(specs.len());
+        {
+            let handles = DEVICE_HANDLES.lock();
+            for (i, spec) in specs.iter().enumerate() {
+                let size = spec
+                    .get("size")
+                    .and_then(|v| v.as_u64())
+                    .filter(|&size| size > 0)
+                    .ok_or_else(|| format!("Spec {}: size must be a positive integer", i))?;
+                let device_index = spec.get("device_index").and_then(|v| v.as_u64()).unwrap_or(0);
+                let device_id = format!("vulkan:{}", device_index);
+                match handles.get(&device_id) {
+                    Some(handle) if handle.online => {}
+                    Some(_) => return Err(format!("Spec {}: device {} is offline", i, device_id)),
+                    None => return Err(format!("Spec {}: device {} not found", i, device_id)),
+                }
+                let device_index =
+                    jint::try_from(device_index).map_err(|_| format!("Spec {}: device_index {} is out of range", i, device_index))?;
+                planned.push((
+                    device_index,
+                    MemoryAllocation {
+                        handle_id: Uuid::new_v4().to_string(),
+                        device_id,
+                        size_bytes: size,
+                    },
+                ));
+            }
+        }
+
+        let ids: Vec<String> = planned.iter().map(|(_, a)| a.handle_id.clone()).collect();
+        let total: u64 = planned.iter().map(|(_, a)| a.size_bytes).sum();
+        // One slab per device; a device failing frees the slabs made before it
+        let mut by_device: BTreeMap<jint, Vec<(String, u64)>> = BTreeMap::new();
+        for (device_index, allocation) in &planned {
+            by_device
+                .entry(*device_index)
+                .or_default()
+                .push((allocation.handle_id.clone(), allocation.size_bytes));
+        }
+        for (device_index, buffers) in &by_device {
+            let allocated = open_device(*device_index).and_then(|context| Ok(context.allocate_many(buffers)?));
+            if let Err(e) = allocated {
+                free_device_memory(planned.iter().filter(|(index, _)| index < device_index).map(|(_, a)| a));
+                return Err(format!("Device {}: {}", device_index, e));
+            }
+        }
+        {
+            let mut allocs = MEMORY_ALLOCATIONS.lock();
+            for (i, (_, allocation)) in planned.iter().enumerate() {
+                if let Err(e) = allocs.insert(&client, &allocation.handle_id, allocation.clone()) {
+                    for inserted in &ids[..i] {
+                        let _ = allocs.remove(&client, inserted);
+                    }
+                    free_device_memory(planned.iter().map(|(_, a)| a));
+                    return Err(e.to_string());
+                }
+            }
+        }
+
+        info!("Allocated {} buffers ({} bytes) for client {}", ids.len(), total, client);
+        check_memory_watermarks();
+        serde_json::to_string(&ids).map_err(|e| format!("Failed to serialize handles: {}", e))
+    })() {
+        Ok(json) => match env.new_string(&json) {
+            Ok(jstr) => jstr.into_raw(),
+            Err(e) => {
+                error!("Failed to create JNI string: {}", e);
+                let _ = env.throw_new("java/lang/RuntimeException", &e.to_string());
+                std::ptr::null_mut()
+            }
+        },
+        Err(e) => {
+            error!("Batch allocation failed: {}", e);
+            let _ = env.throw_new("java/lang/RuntimeException", &e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Free allocated device memory
+/// @param handle_id: memory handle to free
+/// @param client_id: client that allocated the handle, or null for the default namespace
+/// @return true if successful, false otherwise
+// SAFETY: JNI function - handle and ownership are validated before freeing
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_freeMemory(
+    mut env: JNIEnv,
+    _class: JClass,
+    handle_id: JString,
+    client_id: JString,
+) -> jboolean {
+    let _timer = jni_stats::time("freeMemory");
+    match (|| -> Result<String, (&str, String)> {
+        let handle = env
+            .get_string(&handle_id)
+            .map_err(|e| ("java/lang/RuntimeException", e.to_string()))?
+            .to_string_lossy()
+            .to_string();
+        let client = client_namespace(&mut env, &client_id)
+            .map_err(|e| ("java/lang/RuntimeException", e))?;
+
+        let allocation = MEMORY_ALLOCATIONS
+            .lock()
+            .remove(&client, &handle)
+            .map_err(|e| match e {
+                RegistryError::AccessDenied(_) => ("java/lang/SecurityException", e.to_string()),
+                _ => ("java/lang/IllegalArgumentException", e.to_string()),
+            })?;
+        free_device_memory([&allocation]);
+        #[cfg(feature = "kernels-core")]
+        WEIGHTS.lock().unregister_handle(&handle);
+        check_memory_watermarks();
+        Ok(handle)
+    })() {
+        Ok(handle) => {
+            info!("Freed memory handle: {}", handle);
+            jboolean::from(true)
+        }
+        Err((class, e)) => {
+            error!("Free memory failed: {}", e);
+            let _ = env.throw_new(class, &e);
+            jboolean::from(false)
+        }
+    }
+}
+
+/// Copy data from host to device
+/// @param handle_id: destination memory handle
+/// @param data: data to copy
+/// @param client_id: client that allocated the handle, or null for the default namespace
+/// @return true if successful, false otherwise
+// SAFETY: JNI function - validates handle before proceeding
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_copyToDevice(
+    mut env: JNIEnv,
+    _class: JClass,
+    handle_id: JString,
+    data: JByteArray,
+    client_id: JString,
+) -> jboolean {
+    let _timer = jni_stats::time("copyToDevice");
+    match (|| -> Result<(), String> {
+        // Get handle string
         let handle_str = env
             .get_string(&handle_id)
             .map_err(|e| format!("Failed to get handle string: {}", e))?
             .to_string_lossy()
             .to_string();
         
-        // Verify allocation exists
-        {
-            let allocs = MEMORY_ALLOCATIONS.lock();
-            allocs
-                .get(&handle_str)
-                .ok_or_else(|| format!("Memory handle not found: {}", handle_str))?;
+        let client = client_namespace(&mut env, &client_id)?;
+
+        // Verify allocation exists and belongs to the caller
+        let allocation = MEMORY_ALLOCATIONS
+            .lock()
+            .get(&client, &handle_str)
+            .map_err(|e| e.to_string())?
+            .clone();
+
+        let bytes = env
+            .convert_byte_array(&data)
+            .map_err(|e| format!("Failed to read data: {}", e))?;
+        if usize::try_from(allocation.size_bytes).is_ok_and(|size| bytes.len() > size) {
+            return Err(format!(
+                "{} bytes do not fit allocation {} of {} bytes",
+                bytes.len(),
+                handle_str,
+                allocation.size_bytes
+            ));
+        }
+        if !bytes.is_empty() {
+            allocation_context(&allocation)?.write(&handle_str, 0, &bytes)?;
+        }
+        info!("Copied {} bytes to device {}", bytes.len(), handle_str);
+        
+        Ok(())
+    })() {
+        Ok(()) => jboolean::from(true),
+        Err(e) => {
+            error!("Copy to device failed: {}", e);
+            let _ = env.throw_new("java/lang/RuntimeException", &e);
+            jboolean::from(false)
+        }
+    }
+}
+
+/// Copy data from device to host
+/// @param handle_id: source memory handle
+/// @param size_bytes: number of bytes to copy, at most the allocation's size
+/// @param client_id: client that allocated the handle, or null for the default namespace
+/// @return byte array with copied data, or null on error
+// SAFETY: JNI function - validates handle and creates appropriately sized array
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_copyFromDevice(
+    mut env: JNIEnv,
+    _class: JClass,
+    handle_id: JString,
+    size_bytes: jlong,
+    client_id: JString,
+) -> jbyteArray {
+    let _timer = jni_stats::time("copyFromDevice");
+    match (|| -> Result<Vec<u8>, String> {
+        if args::non_negative("size_bytes", size_bytes)? == 0 {
+            return Ok(Vec::new()); // Nothing to copy
+        }
+        
+        // Get handle string
+        let handle_str = env
+            .get_string(&handle_id)
+            .map_err(|e| format!("Failed to get handle string: {}", e))?
+            .to_string_lossy()
+            .to_string();
+        
+        let client = client_namespace(&mut env, &client_id)?;
+
+        // Verify allocation exists, belongs to the caller and holds the bytes
+        let allocation = MEMORY_ALLOCATIONS
+            .lock()
+            .get(&client, &handle_str)
+            .map_err(|e| e.to_string())?
+            .clone();
+        // Java arrays are indexed by jint
+        let size = args::in_range("size_bytes", size_bytes, 0..=allocation.size_bytes.min(i32::MAX.unsigned_abs().into()))?;
+        
+        let buffer = allocation_context(&allocation)?.read(&handle_str, 0, size)?;
+        
+        info!("Copied {} bytes from device {}", size_bytes, handle_str);
+        
+        Ok(buffer)
+    })() {
+        Ok(buffer) => {
+            match env.byte_array_from_slice(&buffer) {
+                Ok(arr) => arr.into_raw(),
+                Err(e) => {
+                    error!("Failed to create byte array: {}", e);
+                    let _ = env.throw_new("java/lang/RuntimeException", &e.to_string());
+                    std::ptr::null_mut()
+                }
+            }
+        }
+        Err(e) => {
+            error!("Copy from device failed: {}", e);
+            let _ = env.throw_new("java/lang/RuntimeException", &e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+// ============ Model Functions ============
+
+/// Free the allocations owned by models the manager has dropped
+fn free_model_allocations(models: &[LoadedModel]) {
+    {
+        let mut allocs = MEMORY_ALLOCATIONS.lock();
+        for model in models {
+            for handle in &model.allocations {
+                if let Some(allocation) = allocs.remove_any(handle) {
+                    free_device_memory([&allocation]);
+                }
+                #[cfg(feature = "kernels-core")]
+                WEIGHTS.lock().unregister_handle(handle);
+            }
+            info!("Unloaded model {} ({} allocations)", model.model_id, model.allocations.len());
+        }
+    }
+    check_memory_watermarks();
+}
+
+/// Load a model, reserving its memory quota on device 0
+/// Idle models are unloaded least-recently-used first if there is not enough room.
+/// @param model_id: unique model identifier
+/// @param path: model weights location
+/// @param quota_bytes: maximum bytes the model may allocate
+/// @return true if the model was loaded
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_loadModel(
+    mut env: JNIEnv,
+    _class: JClass,
+    model_id: JString,
+    path: JString,
+    quota_bytes: jlong,
+) -> jboolean {
+    let _timer = jni_stats::time("loadModel");
+    match (|| -> Result<(), String> {
+        let quota_bytes = args::positive("quota_bytes", quota_bytes)?;
+
+        let model_id: String = env
+            .get_string(&model_id)
+            .map_err(|e| format!("Failed to get model id string: {}", e))?
+            .into();
+        let path: String = env
+            .get_string(&path)
+            .map_err(|e| format!("Failed to get path string: {}", e))?
+            .into();
+
+        // Reject weight files whose loader was compiled out before reserving memory
+        if let Err(e @ (LoaderError::Unsupported(_) | LoaderError::Malformed { .. })) =
+            loader::read_header(std::path::Path::new(&path))
+        {
+            return Err(e.to_string());
+        }
+
+        let mut manager = MODEL_MANAGER.lock();
+        if manager.is_none() {
+            let capacity = DEVICE_HANDLES
+                .lock()
+                .get("vulkan:0")
+                .map(|h| h.info.total_memory_bytes)
+                .ok_or_else(|| "No devices enumerated; call enumerateDevices() first".to_string())?;
+            *manager = Some(ModelManager::new(capacity));
+        }
+
+        let evicted = manager
+            .as_mut()
+            .ok_or_else(|| "Model manager unavailable".to_string())?
+            .load(&model_id, &path, quota_bytes)
+            .map_err(|e| e.to_string())?;
+        free_model_allocations(&evicted);
+
+        info!("Loaded model {} from {} with quota {} bytes", model_id, path, quota_bytes);
+        Ok(())
+    })() {
+        Ok(()) => jboolean::from(true),
+        Err(e) => {
+            error!("Model load failed: {}", e);
+            let _ = env.throw_new("java/lang/RuntimeException", &e);
+            jboolean::from(false)
+        }
+    }
+}
+
+/// Unload a model and free its allocations
+/// @param model_id: model to unload
+/// @return true if the model was unloaded
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_unloadModel(
+    mut env: JNIEnv,
+    _class: JClass,
+    model_id: JString,
+) -> jboolean {
+    let _timer = jni_stats::time("unloadModel");
+    match (|| -> Result<(), String> {
+        let model_id: String = env
+            .get_string(&model_id)
+            .map_err(|e| format!("Failed to get model id string: {}", e))?
+            .into();
+
+        let model = MODEL_MANAGER
+            .lock()
+            .as_mut()
+            .ok_or_else(|| format!("Model not found: {}", model_id))?
+            .unload(&model_id)
+            .map_err(|e| e.to_string())?;
+        free_model_allocations(&[model]);
+        Ok(())
+    })() {
+        Ok(()) => jboolean::from(true),
+        Err(e) => {
+            error!("Model unload failed: {}", e);
+            let _ = env.throw_new("java/lang/IllegalArgumentException", &e);
+            jboolean::from(false)
+        }
+    }
+}
+
+/// One model of the `listModels` report
+#[derive(Serialize)]
+struct ModelEntry<'a> {
+    model_id: &'a str,
+    path: &'a str,
+    quota_bytes: u64,
+    used_bytes: u64,
+    active_users: u32,
+}
+
+impl<'a> From<&'a LoadedModel> for ModelEntry<'a> {
+    fn from(model: &'a LoadedModel) -> Self {
+        Self {
+            model_id: &model.model_id,
+            path: &model.path,
+            quota_bytes: model.quota_bytes,
+            used_bytes: model.used_bytes,
+            active_users: model.active_users,
+        }
+    }
+}
+
+/// List loaded models, most recently used first
+/// @return JSON array of loaded models, or null on error
+// SAFETY: JNI function - returns valid string or null
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_listModels(
+    mut env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let _timer = jni_stats::time("listModels");
+    let models = MODEL_MANAGER
+        .lock()
+        .as_ref()
+        .map(ModelManager::list)
+        .unwrap_or_default();
+
+    let entries: Vec<ModelEntry> = models.iter().map(ModelEntry::from).collect();
+    let json = match serde_json::to_string(&entries) {
+        Ok(json) => json,
+        Err(e) => {
+            error!("Failed to serialize models: {}", e);
+            let _ = env.throw_new("java/lang/RuntimeException", e.to_string());
+            return std::ptr::null_mut();
+        }
+    };
+    match env.new_string(&json) {
+        Ok(jstr) => jstr.into_raw(),
+        Err(e) => {
+            error!("Failed to create JNI string: {}", e);
+            let _ = env.throw_new("java/lang/RuntimeException", &e.to_string());
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Snapshot the logical backend state for checkpointing
+/// Includes loaded models, allocation handles and their owners, but not GPU
+/// memory contents; see `exo_vulkan_binding::checkpoint` for the format.
+/// @return versioned state blob, or null on error
+// SAFETY: JNI function - returns valid byte array or null
+#[cfg(feature = "validation")]
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_exportState(
+    mut env: JNIEnv,
+    _class: JClass,
+) -> jbyteArray {
+    let _timer = jni_stats::time("exportState");
+    let mut state = BackendState::default();
+    let models = MODEL_MANAGER
+        .lock()
+        .as_ref()
+        .map(|manager| {
+            state.capacity_bytes = manager.capacity_bytes();
+            manager.list()
+        })
+        .unwrap_or_default();
+    state.models = models.iter().map(ModelState::from).collect();
+    state.tensors = MEMORY_ALLOCATIONS
+        .lock()
+        .iter()
+        .map(|(client, handle, alloc)| TensorState {
+            handle_id: handle.to_string(),
+            client: client.to_string(),
+            device_id: alloc.device_id.clone(),
+            size_bytes: alloc.size_bytes,
+            model_id: models
+                .iter()
+                .find(|m| m.allocations.iter().any(|h| h == handle))
+                .map(|m| m.model_id.clone()),
+        })
+        .collect();
+
+    match env.byte_array_from_slice(&state.to_bytes()) {
+        Ok(arr) => arr.into_raw(),
+        Err(e) => {
+            error!("Failed to create byte array: {}", e);
+            let _ = env.throw_new("java/lang/RuntimeException", &e.to_string());
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// `exportState` in builds without the `validation` feature
+// SAFETY: JNI function - throws and returns null
+#[cfg(not(feature = "validation"))]
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_exportState(
+    mut env: JNIEnv,
+    _class: JClass,
+) -> jbyteArray {
+    let _timer = jni_stats::time("exportState");
+    throw_unsupported(&mut env, "validation");
+    std::ptr::null_mut()
+}
+
+/// Prepare a replacement process to take over tensors before shutdown
+/// Waits for running jobs, then exports the memory of each tensor on device
+/// 0 as an opaque FD (`vkGetMemoryFdKHR`). The caller owns the FDs and sends
+/// them to the replacement out of band. Tensors whose memory cannot be
+/// exported get a null `fd` and are reloaded by the replacement: buffers from
+/// `allocateBuffers`, imported HardwareBuffers, tensors on other devices and
+/// everything on drivers without `VK_KHR_external_memory_fd`.
+/// @param handles_json: JSON array of handle IDs to hand over; empty for all
+/// @return manifest JSON (see `exo_vulkan_binding::handover::HandoverManifest`), or null on error
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_prepareHandover(
+    mut env: JNIEnv,
+    _class: JClass,
+    handles_json: JString,
+) -> jstring {
+    let _timer = jni_stats::time("prepareHandover");
+    match (|| -> Result<String, String> {
+        let json: String = env
+            .get_string(&handles_json)
+            .map_err(|e| format!("Failed to get handles: {}", e))?
+            .into();
+        let wanted: Vec<String> = serde_json::from_str(&json).map_err(|e| format!("Malformed handles: {}", e))?;
+        let context = get_or_init_vulkan()?;
+        let device_uuid = context.get_device_uuid(0).map_err(|e| e.to_string())?;
+        let driver_uuid = context.get_driver_uuid(0).map_err(|e| e.to_string())?;
+        let device = device_handle(0)?;
+
+        // Jobs still writing tensors would hand over half-written memory
+        if !jobs::global().wait_idle(Duration::from_millis(device_context::SUBMIT_TIMEOUT_MS)) {
+            return Err(format!("Jobs still running after {} ms", device_context::SUBMIT_TIMEOUT_MS));
+        }
+
+        let models = MODEL_MANAGER
+            .lock()
+            .as_ref()
+            .map(|manager| manager.list())
+            .unwrap_or_default();
+        let allocations = MEMORY_ALLOCATIONS.lock();
+        for handle in &wanted {
+            if !allocations.iter().any(|(_, h, _)| h == handle) {
+                return Err(format!("Unknown handle {}", handle));
+            }
+        }
+        let mut manifest = HandoverManifest::new(&device_uuid, &driver_uuid);
+        let exported = allocations.iter().try_for_each(|(client, handle, alloc)| {
+            if !wanted.is_empty() && !wanted.iter().any(|h| h == handle) {
+                return Ok(());
+            }
+            let mut metadata = BTreeMap::from([
+                ("client".to_string(), client.to_string()),
+                ("device_id".to_string(), alloc.device_id.clone()),
+            ]);
+            if let Some(model) = models.iter().find(|m| m.allocations.iter().any(|h| h == handle)) {
+                metadata.insert("model_id".to_string(), model.model_id.clone());
+            }
+            let context = allocation_context(alloc)?;
+            let memory = if alloc.device_id == device.device_id {
+                context.export_fd(handle).map_err(|e| e.to_string())
+            } else {
+                Err(format!("on {}, not device 0", alloc.device_id))
+            };
+            manifest.tensors.push(match memory {
+                Ok(memory) => HandoverTensor {
+                    handle_id: handle.to_string(),
+                    size: alloc.size_bytes,
+                    allocation_size: memory.allocation_size,
+                    memory_type_index: memory.memory_type_index,
+                    fd: Some(memory.fd),
+                    metadata,
+                },
+                Err(e) => {
+                    info!("Tensor {} will be reloaded by the replacement: {}", handle, e);
+                    HandoverTensor {
+                        handle_id: handle.to_string(),
+                        size: alloc.size_bytes,
+                        allocation_size: alloc.size_bytes,
+                        memory_type_index: context.memory_type(),
+                        fd: None,
+                        metadata,
+                    }
+                }
+            });
+            Ok::<_, String>(())
+        });
+        if let Err(e) = exported {
+            handover::close_fds(&manifest);
+            return Err(e);
+        }
+        info!("Prepared handover manifest for {} tensors", manifest.tensors.len());
+        Ok(manifest.to_json())
+    })() {
+        Ok(json) => match env.new_string(&json) {
+            Ok(jstr) => jstr.into_raw(),
+            Err(e) => {
+                error!("Failed to create JNI string: {}", e);
+                let _ = env.throw_new("java/lang/RuntimeException", &e.to_string());
+                std::ptr::null_mut()
+            }
+        },
+        Err(e) => {
+            error!("Prepare handover failed: {}", e);
+            let _ = env.throw_new("java/lang/RuntimeException", &e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Generate tokens with a draft model speculating ahead of a target model
+/// @param draft_model_id: small model proposing tokens
+/// @param target_model_id: large model verifying them
+/// @param prompt: prompt token ids
+/// @param max_tokens: number of tokens to generate
+/// @param draft_len: tokens proposed per round
+/// @param stop_token: end-of-sequence token id, or -1 for none
+/// @return generated token ids, or null on error
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_generateSpeculative(
+    mut env: JNIEnv,
+    _class: JClass,
+    draft_model_id: JString,
+    target_model_id: JString,
+    prompt: JIntArray,
+    max_tokens: jint,
+    draft_len: jint,
+    stop_token: jint,
+) -> jintArray {
+    let _timer = jni_stats::time("generateSpeculative");
+    match (|| -> Result<Vec<u32>, String> {
+        let max_tokens = args::count("max_tokens", max_tokens)?;
+        let draft_len = args::positive_count("draft_len", draft_len)?;
+        let stop_token = args::optional_id("stop_token", stop_token)?;
+
+        let draft_id: String = env
+            .get_string(&draft_model_id)
+            .map_err(|e| format!("Failed to get draft model id: {}", e))?
+            .into();
+        let target_id: String = env
+            .get_string(&target_model_id)
+            .map_err(|e| format!("Failed to get target model id: {}", e))?
+            .into();
+
+        let prompt_len = env
+            .get_array_length(&prompt)
+            .map_err(|e| format!("Failed to get prompt length: {}", e))?;
+        let mut prompt_tokens = vec![0; args::count("prompt length", prompt_len)?];
+        env.get_int_array_region(&prompt, 0, &mut prompt_tokens)
+            .map_err(|e| format!("Failed to read prompt: {}", e))?;
+        let prompt_tokens = prompt_tokens
+            .iter()
+            .map(|&t| args::non_negative_u32("prompt token", t))
+            .collect::<Result<Vec<u32>, _>>()?;
+
+        // Keep both models resident while generating
+        {
+            let mut models = MODEL_MANAGER.lock();
+            let manager = models
+                .as_mut()
+                .ok_or_else(|| "No models loaded".to_string())?;
+            manager.acquire(&draft_id).map_err(|e| e.to_string())?;
+            if let Err(e) = manager.acquire(&target_id) {
+                let _ = manager.release(&draft_id);
+                return Err(e.to_string());
+            }
+        }
+
+        let result = speculative::generate_speculative_by_id(
+            &draft_id,
+            &target_id,
+            &prompt_tokens,
+            max_tokens,
+            draft_len,
+            stop_token,
+        );
+
+        if let Some(manager) = MODEL_MANAGER.lock().as_mut() {
+            let _ = manager.release(&draft_id);
+            let _ = manager.release(&target_id);
+        }
+
+        let output = result.map_err(|e| e.to_string())?;
+        info!(
+            "Speculative generation: {} tokens, acceptance rate {:.2}",
+            output.tokens.len(),
+            output.acceptance_rate()
+        );
+        Ok(output.tokens)
+    })() {
+        Ok(tokens) => match int_array(&mut env, &tokens) {
+            Ok(arr) => arr,
+            Err(e) => {
+                error!("{}", e);
+                let _ = env.throw_new("java/lang/RuntimeException", &e);
+                std::ptr::null_mut()
+            }
+        },
+        Err(e) => {
+            error!("Speculative generation failed: {}", e);
+            let _ = env.throw_new("java/lang/RuntimeException", &e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Read a session's generated tokens back from the device in one transfer
+/// The history includes the prompt and is appended on device by the sampler.
+/// @param session_handle: client ID the session runs under, or null for the default client
+/// @param from: index of the first token to read
+/// @param count: maximum number of tokens; the result stops at the end of the history
+/// @return token ids, or null on error
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_exportTokens(
+    mut env: JNIEnv,
+    _class: JClass,
+    session_handle: JString,
+    from: jint,
+    count: jint,
+) -> jintArray {
+    let _timer = jni_stats::time("exportTokens");
+    match (|| -> Result<Vec<u32>, String> {
+        let from = args::count("from", from)?;
+        let count = args::count("count", count)?;
+        let session = client_namespace(&mut env, &session_handle)?;
+        token_history::export(&session, from, count).map_err(|e| e.to_string())
+    })() {
+        Ok(tokens) => match int_array(&mut env, &tokens) {
+            Ok(arr) => arr,
+            Err(e) => {
+                error!("{}", e);
+                let _ = env.throw_new("java/lang/RuntimeException", &e);
+                std::ptr::null_mut()
+            }
+        },
+        Err(e) => {
+            error!("Token export failed: {}", e);
+            let _ = env.throw_new("java/lang/RuntimeException", &e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Register a token dataset for on-device evaluation of a model
+/// @param model_id: model whose runtime will score the dataset
+/// @param tokens: token ids, at least 2
+/// @param window: predictions per forward pass, or <= 0 for the default
+/// @return dataset handle for evaluate, or null on error
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_createEvalDataset(
+    mut env: JNIEnv,
+    _class: JClass,
+    model_id: JString,
+    tokens: JIntArray,
+    window: jint,
+) -> jstring {
+    let _timer = jni_stats::time("createEvalDataset");
+    match (|| -> Result<String, String> {
+        let model_id: String = env
+            .get_string(&model_id)
+            .map_err(|e| format!("Failed to get model id: {}", e))?
+            .into();
+        let len = env
+            .get_array_length(&tokens)
+            .map_err(|e| format!("Failed to get tokens length: {}", e))?;
+        let mut ids = vec![0; args::count("tokens length", len)?];
+        env.get_int_array_region(&tokens, 0, &mut ids)
+            .map_err(|e| format!("Failed to read tokens: {}", e))?;
+        let ids = ids
+            .iter()
+            .map(|&t| args::non_negative_u32("token", t))
+            .collect::<Result<Vec<u32>, _>>()?;
+        let window = usize::try_from(window).ok().filter(|&w| w > 0).unwrap_or(eval::DEFAULT_WINDOW);
+        let dataset = eval::EvalDataset::new(&model_id, ids, window).map_err(|e| e.to_string())?;
+        Ok(eval::register_dataset(dataset))
+    })() {
+        Ok(handle) => match env.new_string(&handle) {
+            Ok(jstr) => jstr.into_raw(),
+            Err(e) => {
+                error!("Failed to create JNI string: {}", e);
+                let _ = env.throw_new("java/lang/RuntimeException", e.to_string());
+                std::ptr::null_mut()
+            }
+        },
+        Err(e) => {
+            error!("Eval dataset creation failed: {}", e);
+            let _ = env.throw_new("java/lang/IllegalArgumentException", &e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Score a registered dataset with teacher forcing and report its perplexity
+/// Clears the model's KV cache.
+/// @param dataset_handle: handle from createEvalDataset
+/// @return JSON object {"tokens", "windows", "mean_nll", "perplexity"}, or null on error
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_evaluate(
+    mut env: JNIEnv,
+    _class: JClass,
+    dataset_handle: JString,
+) -> jstring {
+    let _timer = jni_stats::time("evaluate");
+    match (|| -> Result<String, String> {
+        let handle: String = env
+            .get_string(&dataset_handle)
+            .map_err(|e| format!("Failed to get dataset handle: {}", e))?
+            .into();
+        let model_id = eval::dataset(&handle).map_err(|e| e.to_string())?.model_id().to_string();
+
+        // Keep the model resident while evaluating
+        MODEL_MANAGER
+            .lock()
+            .as_mut()
+            .ok_or_else(|| "No models loaded".to_string())?
+            .acquire(&model_id)
+            .map_err(|e| e.to_string())?;
+        let result = eval::evaluate(&handle);
+        if let Some(manager) = MODEL_MANAGER.lock().as_mut() {
+            let _ = manager.release(&model_id);
+        }
+
+        let report = result.map_err(|e| e.to_string())?;
+        info!(
+            "Evaluated {} tokens of {}: perplexity {:.3}",
+            report.tokens,
+            model_id,
+            report.perplexity()
+        );
+        Ok(report.to_json())
+    })() {
+        Ok(json) => match env.new_string(&json) {
+            Ok(jstr) => jstr.into_raw(),
+            Err(e) => {
+                error!("Failed to create JNI string: {}", e);
+                let _ = env.throw_new("java/lang/RuntimeException", e.to_string());
+                std::ptr::null_mut()
+            }
+        },
+        Err(e) => {
+            error!("Evaluation failed: {}", e);
+            let _ = env.throw_new("java/lang/RuntimeException", &e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Forget an evaluation dataset
+/// @param dataset_handle: handle from createEvalDataset
+/// @return true if the dataset existed
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_releaseEvalDataset(
+    mut env: JNIEnv,
+    _class: JClass,
+    dataset_handle: JString,
+) -> jboolean {
+    let _timer = jni_stats::time("releaseEvalDataset");
+    match env.get_string(&dataset_handle) {
+        Ok(handle) => jboolean::from(eval::release_dataset(&String::from(handle))),
+        Err(e) => {
+            error!("Failed to get dataset handle: {}", e);
+            let _ = env.throw_new("java/lang/IllegalArgumentException", e.to_string());
+            jboolean::from(false)
+        }
+    }
+}
+
+/// Limit decoding to a slice of GPU time per UI frame
+/// Decode loops yield to the next frame once the budget is spent, so generation
+/// can run alongside the app's UI without dropped frames.
+/// @param budget_ms: GPU milliseconds per frame, or <= 0 to disable pacing
+/// @param refresh_rate_hz: display refresh rate, e.g. 60 or 120
+/// @return true if the budget was applied
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_setFrameBudgetMs(
+    mut env: JNIEnv,
+    _class: JClass,
+    budget_ms: jfloat,
+    refresh_rate_hz: jint,
+) -> jboolean {
+    let _timer = jni_stats::time("setFrameBudgetMs");
+    if budget_ms.is_nan() || budget_ms <= 0.0 {
+        frame_budget::set_frame_budget(None);
+        info!("Frame budget disabled");
+        return jboolean::from(true);
+    }
+
+    let budget = args::positive_u32("refresh_rate_hz", refresh_rate_hz)
+        .map_err(String::from)
+        .and_then(|hz| {
+            FrameBudget::new(Duration::from_secs_f32(budget_ms / 1000.0), hz)
+                .ok_or_else(|| format!("Frame budget {} ms does not fit a {} Hz frame", budget_ms, hz))
+        });
+    match budget {
+        Ok(budget) => {
+            frame_budget::set_frame_budget(Some(budget));
+            info!("Frame budget set to {} ms at {} Hz", budget_ms, refresh_rate_hz);
+            jboolean::from(true)
+        }
+        Err(e) => {
+            error!("{}", e);
+            let _ = env.throw_new("java/lang/IllegalArgumentException", e);
+            jboolean::from(false)
+        }
+    }
+}
+
+/// Flag that the UI needs the GPU's attention, e.g. while scrolling or animating
+/// Background weight uploads switch to short chunks while set, and size chunks
+/// from measured bandwidth otherwise.
+/// @param responsive: true to favour UI latency, false to favour load throughput
+// SAFETY: JNI function - takes no pointers
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_setUiResponsive(
+    _env: JNIEnv,
+    _class: JClass,
+    responsive: jboolean,
+) {
+    let _timer = jni_stats::time("setUiResponsive");
+    transfer_scheduler::set_ui_responsive(responsive != 0);
+}
+
+// ============ Utilities ============
+
+/// Re-apply the backend configuration at runtime
+/// Fields absent from the document keep their current value. Hot fields
+/// (log_level, memory_thresholds, frame_budget_ms, refresh_rate_hz,
+/// object_limits, cache_dir, jni_stats) take effect immediately; cold fields (loader_path, queue_priority,
+/// global_priority, protected_submission, debug_layers) apply after shutdown() and the
+/// next initialization.
+/// @param config_json: JSON object of config fields
+/// @return JSON diff report `{"changes":[{"field","old","new","reload"}],"requires_reinit":bool}`,
+///         or null on error (the configuration is left unchanged)
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_applyConfig(
+    mut env: JNIEnv,
+    _class: JClass,
+    config_json: JString,
+) -> jstring {
+    let _timer = jni_stats::time("applyConfig");
+    match (|| -> Result<String, String> {
+        let json: String = env
+            .get_string(&config_json)
+            .map_err(|e| format!("Failed to get config: {}", e))?
+            .into();
+        let update = RuntimeConfig::from_json(&json).map_err(|e| e.to_string())?;
+
+        let mut config = CONFIG.lock();
+        let diff = config.apply(&update);
+        let applied = diff.applied();
+        if applied.contains(&"log_level")
+            && let Some(level) = config.log_level()
+        {
+            log::set_max_level(level);
+        }
+        if (applied.contains(&"frame_budget_ms") || applied.contains(&"refresh_rate_hz"))
+            && let Some(budget) = config.frame_budget()
+        {
+            frame_budget::set_frame_budget(budget);
+        }
+        if applied.contains(&"object_limits")
+            && let Some(limits) = config.object_limits()
+        {
+            for (kind, limit) in limits {
+                object_budget::global().set_limit(kind, limit);
+            }
+        }
+        if applied.contains(&"jni_stats") {
+            jni_stats::set_enabled(config.jni_stats.unwrap_or(false));
+        }
+        let thresholds_changed = applied.contains(&"memory_thresholds");
+        drop(config);
+        if thresholds_changed {
+            // Rebuilt from the new thresholds on the next check
+            *MEMORY_WATERMARKS.lock() = None;
+            check_memory_watermarks();
+        }
+
+        info!(
+            "Config applied: hot {:?}, pending re-init {:?}",
+            applied,
+            diff.pending_reinit()
+        );
+        Ok(diff.to_json())
+    })() {
+        Ok(report) => match env.new_string(&report) {
+            Ok(jstr) => jstr.into_raw(),
+            Err(e) => {
+                error!("Failed to create JNI string: {}", e);
+                let _ = env.throw_new("java/lang/RuntimeException", &e.to_string());
+                std::ptr::null_mut()
+            }
+        },
+        Err(e) => {
+            error!("Apply config failed: {}", e);
+            let _ = env.throw_new("java/lang/IllegalArgumentException", &e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Get counts of detected slow usage patterns
+/// @return JSON object with one counter per slow path
+// SAFETY: JNI function - returns valid string or null
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_getSlowPathStats(
+    mut env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let _timer = jni_stats::time("getSlowPathStats");
+    let stats = exo_vulkan_binding::diagnostics::stats();
+    let json = format!(
+        r#"{{"frequent_queue_wait_idle":{},"small_upload_loop":{},"unmapped_host_visible_read":{}}}"#,
+        stats.frequent_queue_wait_idle,
+        stats.small_upload_loop,
+        stats.unmapped_host_visible_read
+    );
+    match env.new_string(&json) {
+        Ok(jstr) => jstr.into_raw(),
+        Err(e) => {
+            error!("Failed to create JNI string: {}", e);
+            let _ = env.throw_new("java/lang/RuntimeException", &e.to_string());
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Set how the graph compiler pads dynamic sequence lengths
+/// @param policy: `exact`, `pow2`, `pow2:<min>` or `step:<n>`
+/// @return true if the policy was applied (padding stats are cleared)
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_setBucketPolicy(
+    mut env: JNIEnv,
+    _class: JClass,
+    policy: JString,
+) -> jboolean {
+    let _timer = jni_stats::time("setBucketPolicy");
+    match (|| -> Result<BucketPolicy, String> {
+        let spec: String = env
+            .get_string(&policy)
+            .map_err(|e| format!("Failed to get policy: {}", e))?
+            .into();
+        BucketPolicy::parse(&spec).map_err(|e| e.to_string())
+    })() {
+        Ok(policy) => {
+            bucketing::set_policy(policy);
+            jboolean::from(true)
+        }
+        Err(e) => {
+            error!("Set bucket policy failed: {}", e);
+            let _ = env.throw_new("java/lang/IllegalArgumentException", e);
+            jboolean::from(false)
+        }
+    }
+}
+
+/// Get the sequence padding recorded under the current bucket policy
+/// @return JSON object `{"policy","plans","tokens","padded_tokens","waste","buckets":{len:plans}}`
+// SAFETY: JNI function - returns valid string or null
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_getPaddingStats(
+    mut env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let _timer = jni_stats::time("getPaddingStats");
+    let json = bucketing::stats().to_json();
+    match env.new_string(&json) {
+        Ok(jstr) => jstr.into_raw(),
+        Err(e) => {
+            error!("Failed to create JNI string: {}", e);
+            let _ = env.throw_new("java/lang/RuntimeException", e.to_string());
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Attribute work started on the calling thread to a request
+/// Applies to later calls from this thread until changed, and wins over a client's trace id.
+/// @param trace_id: request or trace id (printable ASCII, at most 128 bytes), or null to clear
+/// @return true if set
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_setTraceId(
+    mut env: JNIEnv,
+    _class: JClass,
+    trace_id: JString,
+) -> jboolean {
+    let _timer = jni_stats::time("setTraceId");
+    match (|| -> Result<(), String> {
+        let trace_id: Option<String> = if trace_id.is_null() {
+            None
+        } else {
+            Some(
+                env.get_string(&trace_id)
+                    .map_err(|e| format!("Failed to get trace id: {}", e))?
+                    .into(),
+            )
+        };
+        trace::set_thread_trace(trace_id.as_deref()).map_err(|e| e.to_string())
+    })() {
+        Ok(()) => jboolean::from(true),
+        Err(e) => {
+            error!("Set trace id failed: {}", e);
+            let _ = env.throw_new("java/lang/IllegalArgumentException", &e);
+            jboolean::from(false)
+        }
+    }
+}
+
+/// Attribute every call made for a client namespace to a request
+/// Cleared when the client is closed.
+/// @param client_id: ID from openClient
+/// @param trace_id: request or trace id, or null to clear
+/// @return true if set
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_setClientTraceId(
+    mut env: JNIEnv,
+    _class: JClass,
+    client_id: JString,
+    trace_id: JString,
+) -> jboolean {
+    let _timer = jni_stats::time("setClientTraceId");
+    match (|| -> Result<(), String> {
+        if client_id.is_null() {
+            return Err("client_id must not be null".to_string());
+        }
+        let client = client_namespace(&mut env, &client_id)?;
+        if !MEMORY_ALLOCATIONS.lock().has_client(&client) {
+            return Err(format!("Unknown client: {}", client));
+        }
+        let trace_id: Option<String> = if trace_id.is_null() {
+            None
+        } else {
+            Some(
+                env.get_string(&trace_id)
+                    .map_err(|e| format!("Failed to get trace id: {}", e))?
+                    .into(),
+            )
+        };
+        trace::set_client_trace(&client, trace_id.as_deref()).map_err(|e| e.to_string())
+    })() {
+        Ok(()) => jboolean::from(true),
+        Err(e) => {
+            error!("Set client trace id failed: {}", e);
+            let _ = env.throw_new("java/lang/IllegalArgumentException", &e);
+            jboolean::from(false)
+        }
+    }
+}
+
+/// Get the dispatches, GPU time and errors attributed to a request
+/// @param trace_id: request or trace id
+/// @return JSON `{"trace_id","dispatches","gpu_time_us","errors"}`, or null if nothing was recorded for it
+// SAFETY: JNI function - returns valid string or null
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_getTraceStats(
+    mut env: JNIEnv,
+    _class: JClass,
+    trace_id: JString,
+) -> jstring {
+    let _timer = jni_stats::time("getTraceStats");
+    match (|| -> Result<Option<String>, String> {
+        let trace_id: String = env
+            .get_string(&trace_id)
+            .map_err(|e| format!("Failed to get trace id: {}", e))?
+            .into();
+        Ok(trace::stats(&trace_id).map(|stats| trace::stats_json(&trace_id, &stats)))
+    })() {
+        Ok(Some(json)) => match env.new_string(&json) {
+            Ok(jstr) => jstr.into_raw(),
+            Err(e) => {
+                error!("Failed to create JNI string: {}", e);
+                let _ = env.throw_new("java/lang/RuntimeException", &e.to_string());
+                std::ptr::null_mut()
+            }
+        },
+        Ok(None) => std::ptr::null_mut(),
+        Err(e) => {
+            error!("Get trace stats failed: {}", e);
+            let _ = env.throw_new("java/lang/IllegalArgumentException", &e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Get per-entry-point JNI call counts and timing histograms
+/// Collected while `jni_stats` is enabled in the config.
+/// @return JSON `{"enabled","bucket_bounds_us","entry_points":{"name":{"calls","total_us","mean_us","max_us","histogram"}}}`
+// SAFETY: JNI function - returns valid string or null
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_getJniStats(
+    mut env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let json = jni_stats::to_json();
+    match env.new_string(&json) {
+        Ok(jstr) => jstr.into_raw(),
+        Err(e) => {
+            error!("Failed to create JNI string: {}", e);
+            let _ = env.throw_new("java/lang/RuntimeException", &e.to_string());
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Clear collected JNI call stats, e.g. before measuring one scenario
+// SAFETY: JNI function - takes no pointers
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_resetJniStats(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    jni_stats::reset();
+}
+
+/// Get live Vulkan object counts against their configured limits
+/// @return JSON object per kind: {"fence":{"live":n,"peak":n,"limit":n|null},...}
+// SAFETY: JNI function - returns valid string or null
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_getObjectUsage(
+    mut env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let _timer = jni_stats::time("getObjectUsage");
+    let json = object_budget::global().to_json();
+    match env.new_string(&json) {
+        Ok(jstr) => jstr.into_raw(),
+        Err(e) => {
+            error!("Failed to create JNI string: {}", e);
+            let _ = env.throw_new("java/lang/RuntimeException", &e.to_string());
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Get sustained generation throughput and GPU load over sliding windows
+/// @return JSON `{"short","long":{"duration_ms","tokens","tokens_per_sec","gpu_busy_percent","gpu_us_per_token"},"peak_tokens_per_sec","degradation_percent","trend"}`
+// SAFETY: JNI function - returns valid string or null
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_getPerformanceSummary(
+    mut env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let _timer = jni_stats::time("getPerformanceSummary");
+    let json = performance::global().summary(Instant::now()).to_json();
+    match env.new_string(&json) {
+        Ok(jstr) => jstr.into_raw(),
+        Err(e) => {
+            error!("Failed to create JNI string: {}", e);
+            let _ = env.throw_new("java/lang/RuntimeException", e.to_string());
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Get per-kernel GPU time aggregated from timestamp queries
+/// @return JSON `{"<kernel>":{"count","mean_ns","min_ns","max_ns","total_ns"}}`
+// SAFETY: JNI function - returns valid string or null
+#[cfg(feature = "profiling")]
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_getKernelStats(
+    mut env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let _timer = jni_stats::time("getKernelStats");
+    let json = profiler::global().kernel_stats_json();
+    match env.new_string(&json) {
+        Ok(jstr) => jstr.into_raw(),
+        Err(e) => {
+            error!("Failed to create JNI string: {}", e);
+            let _ = env.throw_new("java/lang/RuntimeException", e.to_string());
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// `getKernelStats` in builds without the `profiling` feature
+// SAFETY: JNI function - throws and returns null
+#[cfg(not(feature = "profiling"))]
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_getKernelStats(
+    mut env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    throw_unsupported(&mut env, "profiling");
+    std::ptr::null_mut()
+}
+
+/// Parse a metrics history resolution: "1s", "10s" or "5m"
+fn history_resolution(env: &mut JNIEnv, resolution: &JString) -> Result<Resolution, String> {
+    let name: String = env
+        .get_string(resolution)
+        .map_err(|e| format!("Failed to get resolution: {}", e))?
+        .into();
+    Resolution::from_name(&name).ok_or_else(|| format!("Unknown resolution {:?}, expected 1s, 10s or 5m", name))
+}
+
+/// Get memory use and throughput over the session at one resolution
+/// @param resolution: "1s" (last 5 minutes), "10s" (last hour) or "5m" (last day)
+/// @return JSON `{"resolution","interval_ms","now_ms","columns","points":[[start_ms,memory_bytes,tokens,gpu_busy_us]]}`, or null on error
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_getMetricsHistory(
+    mut env: JNIEnv,
+    _class: JClass,
+    resolution: JString,
+) -> jstring {
+    let _timer = jni_stats::time("getMetricsHistory");
+    match (|| -> Result<String, String> {
+        let resolution = history_resolution(&mut env, &resolution)?;
+        Ok(metrics_history::global().to_json(resolution, Instant::now()))
+    })() {
+        Ok(json) => match env.new_string(&json) {
+            Ok(jstr) => jstr.into_raw(),
+            Err(e) => {
+                error!("Failed to create JNI string: {}", e);
+                let _ = env.throw_new("java/lang/RuntimeException", e.to_string());
+                std::ptr::null_mut()
+            }
+        },
+        Err(e) => {
+            error!("Metrics history failed: {}", e);
+            let _ = env.throw_new("java/lang/IllegalArgumentException", &e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// `getMetricsHistory` as compact little-endian binary for frequent polling
+/// @param resolution: "1s", "10s" or "5m"
+/// @return `EXMH` header then 24 bytes per point, or null on error
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_getMetricsHistoryBinary(
+    mut env: JNIEnv,
+    _class: JClass,
+    resolution: JString,
+) -> jbyteArray {
+    let _timer = jni_stats::time("getMetricsHistoryBinary");
+    match (|| -> Result<Vec<u8>, String> {
+        let resolution = history_resolution(&mut env, &resolution)?;
+        Ok(metrics_history::global().to_bytes(resolution, Instant::now()))
+    })() {
+        Ok(bytes) => match env.byte_array_from_slice(&bytes) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error!("Failed to create byte array: {}", e);
+                let _ = env.throw_new("java/lang/RuntimeException", e.to_string());
+                std::ptr::null_mut()
+            }
+        },
+        Err(e) => {
+            error!("Metrics history failed: {}", e);
+            let _ = env.throw_new("java/lang/IllegalArgumentException", &e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Forget recorded throughput, e.g. when a new model or workload starts
+// SAFETY: JNI function - takes no pointers
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_resetPerformanceStats(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    performance::global().reset();
+}
+
+/// Read back a small slice of a registered tensor for debugging
+/// @param name: name the tensor was registered under
+/// @param slice_json: per-dim selection, e.g. "[0, [0, 8], null]"
+/// @return JSON object with shape, NaN/Inf counts, min/max and values
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[cfg(feature = "validation")]
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_inspectTensor(
+    mut env: JNIEnv,
+    _class: JClass,
+    name: JString,
+    slice_json: JString,
+) -> jstring {
+    let _timer = jni_stats::time("inspectTensor");
+    match (|| -> Result<String, String> {
+        let name: String = env
+            .get_string(&name)
+            .map_err(|e| format!("Failed to get tensor name string: {}", e))?
+            .into();
+        let slice: String = env
+            .get_string(&slice_json)
+            .map_err(|e| format!("Failed to get slice string: {}", e))?
+            .into();
+        inspect::inspect_tensor(&name, &slice).map_err(|e| e.to_string())
+    })() {
+        Ok(json) => match env.new_string(&json) {
+            Ok(jstr) => jstr.into_raw(),
+            Err(e) => {
+                error!("Failed to create JNI string: {}", e);
+                let _ = env.throw_new("java/lang/RuntimeException", &e.to_string());
+                std::ptr::null_mut()
+            }
+        },
+        Err(e) => {
+            error!("Tensor inspection failed: {}", e);
+            let _ = env.throw_new("java/lang/IllegalArgumentException", &e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Throw `UnsupportedOperationException` for a subsystem compiled out of this build
+#[cfg(any(not(feature = "validation"), not(feature = "kernels-media"), not(feature = "kernels-core")))]
+fn throw_unsupported(env: &mut JNIEnv, feature: &str) {
+    let message = format!("Feature '{}' is not compiled into this native library", feature);
+    error!("{}", message);
+    let _ = env.throw_new("java/lang/UnsupportedOperationException", &message);
+}
+
+/// `inspectTensor` in builds without the `validation` feature
+// SAFETY: JNI function - throws and returns null
+#[cfg(not(feature = "validation"))]
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_inspectTensor(
+    mut env: JNIEnv,
+    _class: JClass,
+    _name: JString,
+    _slice_json: JString,
+) -> jstring {
+    let _timer = jni_stats::time("inspectTensor");
+    throw_unsupported(&mut env, "validation");
+    std::ptr::null_mut()
+}
+
+// ============ Kernels ============
+
+/// Register a custom compute kernel shipped as a SPIR-V asset
+/// The module is reflected and must match the metadata: compute entry point,
+/// storage buffer bindings, push constant size and (if given) workgroup size.
+/// @param name: kernel name; letters, digits, '_' and '.', not an embedded kernel
+/// @param spirv_bytes: SPIR-V module, little-endian words
+/// @param metadata_json: {"entry_point":"main","bindings":[0,1],"push_constant_bytes":8,"workgroup_size":[64,1,1]}
+/// @return true if registered
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_registerKernelFromAsset(
+    mut env: JNIEnv,
+    _class: JClass,
+    name: JString,
+    spirv_bytes: JByteArray,
+    metadata_json: JString,
+) -> jboolean {
+    let _timer = jni_stats::time("registerKernelFromAsset");
+    match (|| -> Result<(), String> {
+        let name: String = env
+            .get_string(&name)
+            .map_err(|e| format!("Failed to get kernel name: {}", e))?
+            .into();
+        let spirv = env
+            .convert_byte_array(&spirv_bytes)
+            .map_err(|e| format!("Failed to read SPIR-V: {}", e))?;
+        let metadata: String = env
+            .get_string(&metadata_json)
+            .map_err(|e| format!("Failed to get metadata: {}", e))?
+            .into();
+        let metadata = KernelMetadata::from_json(&metadata).map_err(|e| e.to_string())?;
+        kernel_plugins::register_kernel(&name, &spirv, metadata).map_err(|e| e.to_string())?;
+        Ok(())
+    })() {
+        Ok(()) => jboolean::from(true),
+        Err(e) => {
+            error!("Register kernel failed: {}", e);
+            let _ = env.throw_new("java/lang/IllegalArgumentException", &e);
+            jboolean::from(false)
+        }
+    }
+}
+
+/// Workgroups per axis every Vulkan device accepts (`maxComputeWorkGroupCount` minimum)
+const MAX_WORKGROUPS_PER_AXIS: u32 = 65_535;
+
+/// Interface of an embedded or registered plugin kernel
+fn kernel_interface(name: &str) -> Result<KernelInterface, String> {
+    if let Some(plugin) = kernel_plugins::get(name) {
+        return Ok(plugin.interface.clone());
+    }
+    let spirv = kernels::spirv(name).map_err(|e| e.to_string())?;
+    KernelInterface::reflect(spirv).map_err(|e| e.to_string())
+}
+
+/// Dispatch a compute kernel on previously allocated buffers
+/// The kernel binds `input_handles` in order followed by `output_handle`, and
+/// must declare exactly that many storage buffers and no push constants;
+/// all handles must be on one device. The call returns at once with a job id to pass to `waitJob`; the dispatch
+/// runs on its own thread, after earlier work on the same device.
+/// @param kernel_name: embedded kernel or one registered with registerKernelFromAsset
+/// @param input_handles: handles bound before the output, in binding order
+/// @param output_handle: handle bound last
+/// @param workgroup_x, workgroup_y, workgroup_z: workgroups per axis, 1..=65535
+/// @param client_id: client that allocated the handles, or null for the default namespace
+/// @return job id, or null on error
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[unsafe(no_mangle)]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_dispatchKernel(
+    mut env: JNIEnv,
+    _class: JClass,
+    kernel_name: JString,
+    input_handles: JObjectArray,
+    output_handle: JString,
+    workgroup_x: jint,
+    workgroup_y: jint,
+    workgroup_z: jint,
+    client_id: JString,
+) -> jstring {
+    let _timer = jni_stats::time("dispatchKernel");
+    match (|| -> Result<String, String> {
+        let name: String = env
+            .get_string(&kernel_name)
+            .map_err(|e| format!("Failed to get kernel name: {}", e))?
+            .into();
+        let count = env
+            .get_array_length(&input_handles)
+            .map_err(|e| format!("Failed to get input count: {}", e))?;
+        let mut handles = Vec::with_capacity(args::count("input count", count)? + 1);
+        for i in 0..count {
+            let element = env
+                .get_object_array_element(&input_handles, i)
+                .map_err(|e| format!("Failed to get input {}: {}", i, e))?;
+            let handle: String = env
+                .get_string(&JString::from(element))
+                .map_err(|e| format!("Failed to get input {}: {}", i, e))?
+                .into();
+            handles.push(handle);
+        }
+        let output: String = env
+            .get_string(&output_handle)
+            .map_err(|e| format!("Failed to get output handle: {}", e))?
+            .into();
+        handles.push(output);
+        let client = client_namespace(&mut env, &client_id)?;
+
+        let groups = [
+            args::in_range_u32("workgroup_x", workgroup_x, 1..=MAX_WORKGROUPS_PER_AXIS)?,
+            args::in_range_u32("workgroup_y", workgroup_y, 1..=MAX_WORKGROUPS_PER_AXIS)?,
+            args::in_range_u32("workgroup_z", workgroup_z, 1..=MAX_WORKGROUPS_PER_AXIS)?,
+        ];
+        let interface = kernel_interface(&name)?;
+        if interface.buffer_bindings.len() != handles.len() {
+            return Err(format!(
+                "Kernel {} declares {} buffers, {} supplied",
+                name,
+                interface.buffer_bindings.len(),
+                handles.len()
+            ));
+        }
+        let mut devices = Vec::with_capacity(handles.len());
+        {
+            let allocs = MEMORY_ALLOCATIONS.lock();
+            for handle in &handles {
+                devices.push(allocs.get(&client, handle).map_err(|e| e.to_string())?.device_id.clone());
+            }
+        }
+        if let Some(other) = devices.iter().find(|&device| *device != devices[0]) {
+            return Err(format!("Buffers of one dispatch are on both {} and {}", devices[0], other));
+        }
+        let context = device_context::get(&devices[0])?;
+
+        let job = jobs::global().start();
+        info!("Dispatch of {} {:?} started as job {}", name, groups, job);
+        let id = job.clone();
+        std::thread::spawn(move || {
+            let outcome = context.dispatch(&name, &handles, groups).map_err(|e| e.to_string());
+            if let Err(e) = jobs::global().finish(&id, outcome) {
+                error!("Failed to finish job {}: {}", id, e);
+            }
+        });
+        Ok(job)
+    })() {
+        Ok(job) => match env.new_string(&job) {
+            Ok(jstr) => jstr.into_raw(),
+            Err(e) => {
+                error!("Failed to create JNI string: {}", e);
+                let _ = env.throw_new("java/lang/RuntimeException", e.to_string());
+                std::ptr::null_mut()
+            }
+        },
+        Err(e) => {
+            error!("Dispatch kernel failed: {}", e);
+            let _ = env.throw_new("java/lang/IllegalArgumentException", e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Wait for a job started by dispatchKernel
+/// A finished job is forgotten once waited on, or after
+/// `jobs::FINISHED_TTL` if nobody waits on it.
+/// @param job_id: job id
+/// @param timeout_ms: longest wait; negative waits indefinitely
+/// @return true once the job completed, false if it is still running;
+///         throws if the job failed or is unknown
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_waitJob(
+    mut env: JNIEnv,
+    _class: JClass,
+    job_id: JString,
+    timeout_ms: jlong,
+) -> jboolean {
+    let _timer = jni_stats::time("waitJob");
+    match (|| -> Result<JobState, String> {
+        let job: String = env
+            .get_string(&job_id)
+            .map_err(|e| format!("Failed to get job id: {}", e))?
+            .into();
+        let timeout = u64::try_from(timeout_ms).map_or(Duration::MAX, Duration::from_millis);
+        jobs::global().wait(&job, timeout).map_err(|e| e.to_string())
+    })() {
+        Ok(JobState::Completed) => jboolean::from(true),
+        Ok(JobState::Pending) => jboolean::from(false),
+        Ok(JobState::Failed(reason)) => {
+            error!("Job failed: {}", reason);
+            let _ = env.throw_new("java/lang/RuntimeException", reason);
+            jboolean::from(false)
+        }
+        Err(e) => {
+            error!("Wait for job failed: {}", e);
+            let _ = env.throw_new("java/lang/IllegalArgumentException", e);
+            jboolean::from(false)
+        }
+    }
+}
+
+/// Name a weight tensor stored in an allocation, so deltas can be applied to it
+/// Registering a name again replaces the tensor; freeing the allocation forgets it.
+/// @param tensor_name: name the tensor is updated by, e.g. "layers.0.attn.q_proj.weight"
+/// @param handle_id: allocation holding the tensor
+/// @param rows, cols: tensor shape
+/// @param quant_params: null for f32 weights; for int8 weights packed four to
+///        a word, a (scale, zero_point) pair per row
+/// @param client_id: client that allocated the handle, or null for the default namespace
+/// @return true if registered
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[cfg(feature = "kernels-core")]
+#[unsafe(no_mangle)]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_registerWeight(
+    mut env: JNIEnv,
+    _class: JClass,
+    tensor_name: JString,
+    handle_id: JString,
+    rows: jint,
+    cols: jint,
+    quant_params: JFloatArray,
+    client_id: JString,
+) -> jboolean {
+    let _timer = jni_stats::time("registerWeight");
+    match (|| -> Result<(), String> {
+        let name: String = env
+            .get_string(&tensor_name)
+            .map_err(|e| format!("Failed to get tensor name: {}", e))?
+            .into();
+        let handle: String = env
+            .get_string(&handle_id)
+            .map_err(|e| format!("Failed to get handle string: {}", e))?
+            .into();
+        let client = client_namespace(&mut env, &client_id)?;
+        let rows = args::positive_count("rows", rows)?;
+        let cols = args::positive_count("cols", cols)?;
+
+        let encoding = if quant_params.is_null() {
+            WeightEncoding::F32
+        } else {
+            let len = env
+                .get_array_length(&quant_params)
+                .map_err(|e| format!("Failed to get quant params length: {}", e))?;
+            let mut words = vec![0.0; args::count("quant params length", len)?];
+            env.get_float_array_region(&quant_params, 0, &mut words)
+                .map_err(|e| format!("Failed to read quant params: {}", e))?;
+            if !words.len().is_multiple_of(2) {
+                return Err(format!("Quant params hold {} floats, not (scale, zero_point) pairs", words.len()));
+            }
+            WeightEncoding::Int8PerRow(
+                words
+                    .chunks_exact(2)
+                    .map(|pair| QuantParams {
+                        scale: pair[0],
+                        zero_point: pair[1] as i32,
+                    })
+                    .collect(),
+            )
+        };
+        let tensor = WeightTensor {
+            handle: handle.clone(),
+            rows,
+            cols,
+            encoding,
+        };
+
+        let size_bytes = MEMORY_ALLOCATIONS
+            .lock()
+            .get(&client, &handle)
+            .map_err(|e| e.to_string())?
+            .size_bytes;
+        let mut weights = WEIGHTS.lock();
+        weights.register(&name, tensor).map_err(|e| e.to_string())?;
+        let needed = weights.get(&name).map_err(|e| e.to_string())?.bytes();
+        if size_bytes < needed {
+            weights.unregister(&name);
+            return Err(format!(
+                "Allocation {} of {} bytes is smaller than the {} bytes of {}",
+                handle, size_bytes, needed, name
+            ));
+        }
+        info!("Registered weight {} ({}x{}) in {}", name, rows, cols, handle);
+        Ok(())
+    })() {
+        Ok(()) => jboolean::from(true),
+        Err(e) => {
+            error!("Register weight failed: {}", e);
+            let _ = env.throw_new("java/lang/IllegalArgumentException", e);
+            jboolean::from(false)
+        }
+    }
+}
+
+/// `registerWeight` in builds without the `kernels-core` feature
+// SAFETY: JNI function - throws and returns false
+#[cfg(not(feature = "kernels-core"))]
+#[unsafe(no_mangle)]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_registerWeight(
+    mut env: JNIEnv,
+    _class: JClass,
+    _tensor_name: JString,
+    _handle_id: JString,
+    _rows: jint,
+    _cols: jint,
+    _quant_params: JFloatArray,
+    _client_id: JString,
+) -> jboolean {
+    let _timer = jni_stats::time("registerWeight");
+    throw_unsupported(&mut env, "kernels-core");
+    jboolean::from(false)
+}
+
+/// Apply a fine-tuning delta to a registered weight: `w += scale * delta`
+/// int8 weights are dequantized, updated and requantized with their existing
+/// params (see `exo_vulkan_binding::weight_delta`). A negative scale reverts a delta.
+/// The weights and the delta must be on the same device.
+/// @param tensor_name: name given to registerWeight
+/// @param delta_handle: allocation holding one f32 per weight element
+/// @param scale: multiplier of the delta; must be finite
+/// @param client_id: client that allocated the handles, or null for the default namespace
+/// @return true if applied
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[cfg(feature = "kernels-core")]
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_applyDelta(
+    mut env: JNIEnv,
+    _class: JClass,
+    tensor_name: JString,
+    delta_handle: JString,
+    scale: jfloat,
+    client_id: JString,
+) -> jboolean {
+    let _timer = jni_stats::time("applyDelta");
+    match (|| -> Result<String, (&str, String)> {
+        let invalid = |e: String| ("java/lang/IllegalArgumentException", e);
+        let name: String = env
+            .get_string(&tensor_name)
+            .map_err(|e| invalid(format!("Failed to get tensor name: {}", e)))?
+            .into();
+        let delta: String = env
+            .get_string(&delta_handle)
+            .map_err(|e| invalid(format!("Failed to get delta handle: {}", e)))?
+            .into();
+        let client = client_namespace(&mut env, &client_id).map_err(invalid)?;
+
+        let tensor = WEIGHTS.lock().get(&name).map_err(|e| invalid(e.to_string()))?.clone();
+        let (weights, delta_allocation) = {
+            let allocs = MEMORY_ALLOCATIONS.lock();
+            let weights = allocs.get(&client, &tensor.handle).map_err(|e| invalid(e.to_string()))?.clone();
+            let delta = allocs.get(&client, &delta).map_err(|e| invalid(e.to_string()))?.clone();
+            (weights, delta)
+        };
+        let delta_bytes = delta_allocation.size_bytes;
+        if !delta_bytes.is_multiple_of(4) {
+            return Err(invalid(format!("Delta of {} bytes is not a whole number of f32s", delta_bytes)));
+        }
+        let delta_len = usize::try_from(delta_bytes / 4).map_err(|e| invalid(e.to_string()))?;
+        tensor
+            .check_delta(&name, delta_len, scale)
+            .map_err(|e| invalid(e.to_string()))?;
+        if weights.device_id != delta_allocation.device_id {
+            return Err(invalid(format!(
+                "Weight {} is on {} but delta {} is on {}",
+                name, weights.device_id, delta, delta_allocation.device_id
+            )));
+        }
+
+        let failed = |e: String| ("java/lang/RuntimeException", e);
+        allocation_context(&weights)
+            .map_err(failed)?
+            .apply_delta(&tensor, &delta, scale)
+            .map_err(|e| failed(e.to_string()))?;
+        info!("Applied delta {} x{} to weight {}", delta, scale, name);
+        Ok(name)
+    })() {
+        Ok(name) => {
+            WEIGHTS.lock().record_applied(&name);
+            jboolean::from(true)
+        }
+        Err((class, e)) => {
+            error!("Apply delta failed: {}", e);
+            let _ = env.throw_new(class, e);
+            jboolean::from(false)
+        }
+    }
+}
+
+/// `applyDelta` in builds without the `kernels-core` feature
+// SAFETY: JNI function - throws and returns false
+#[cfg(not(feature = "kernels-core"))]
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_applyDelta(
+    mut env: JNIEnv,
+    _class: JClass,
+    _tensor_name: JString,
+    _delta_handle: JString,
+    _scale: jfloat,
+    _client_id: JString,
+) -> jboolean {
+    let _timer = jni_stats::time("applyDelta");
+    throw_unsupported(&mut env, "kernels-core");
+    jboolean::from(false)
+}
+
+/// Import an `android.hardware.HardwareBuffer` as an allocation, without copying
+/// The buffer must be BLOB format with `USAGE_GPU_DATA_BUFFER`, as camera,
+/// MediaCodec and NNAPI buffers shared with compute are; see
+/// `MemoryAllocator::import_ahardware_buffer`. The allocation is made on
+/// device 0, whose driver must support
+/// `VK_ANDROID_external_memory_android_hardware_buffer`.
+/// @param hardware_buffer: buffer to import
+/// @param client_id: client namespace to register the handle under, or null for the default
+/// @return handle ID of the imported allocation, or null on error
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_importHardwareBuffer(
+    mut env: JNIEnv,
+    _class: JClass,
+    hardware_buffer: JObject,
+    client_id: JString,
+) -> jstring {
+    let _timer = jni_stats::time("importHardwareBuffer");
+    match (|| -> Result<String, (&str, String)> {
+        let invalid = |e: String| ("java/lang/IllegalArgumentException", e);
+        let client = client_namespace(&mut env, &client_id).map_err(invalid)?;
+
+        #[cfg(target_os = "android")]
+        {
+            let failed = |e: String| ("java/lang/RuntimeException", e);
+            let (buffer, desc) = hardware_buffer::from_java(&env, &hardware_buffer).map_err(|e| invalid(e.into()))?;
+            let size = hardware_buffer::blob_size(&desc).map_err(|e| invalid(e.into()))?;
+            let device = device_handle(0).map_err(failed)?;
+            let context = open_device(0).map_err(failed)?;
+
+            let handle_id = Uuid::new_v4().to_string();
+            // SAFETY: the buffer is live while the Java object is, so for
+            // this call; Vulkan takes its own reference to keep
+            unsafe { context.import_hardware_buffer(&handle_id, size, buffer) }.map_err(|e| failed(e.into()))?;
+            let allocation = MemoryAllocation {
+                handle_id: handle_id.clone(),
+                device_id: device.device_id,
+                size_bytes: size,
+            };
+            if let Err(e) = MEMORY_ALLOCATIONS.lock().insert(&client, &handle_id, allocation) {
+                let _ = context.free(&handle_id);
+                return Err(failed(e.to_string()));
+            }
+
+            info!("Imported HardwareBuffer of {} bytes for {}: {}", size, client, handle_id);
+            check_memory_watermarks();
+            Ok(handle_id)
+        }
+        #[cfg(not(target_os = "android"))]
+        {
+            let _ = (&hardware_buffer, client);
+            Err((
+                "java/lang/UnsupportedOperationException",
+                "HardwareBuffer import is only available on Android".to_string(),
+            ))
+        }
+    })() {
+        Ok(handle_id) => match env.new_string(&handle_id) {
+            Ok(jstr) => jstr.into_raw(),
+            Err(e) => {
+                error!("Failed to create JNI string: {}", e);
+                let _ = env.throw_new("java/lang/RuntimeException", e.to_string());
+                std::ptr::null_mut()
+            }
+        },
+        Err((class, e)) => {
+            error!("Import hardware buffer failed: {}", e);
+            let _ = env.throw_new(class, e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Explain which kernel variant an op uses on this device, and why
+/// Reports every candidate with the features, limits, quirks and autotune
+/// results that made it eligible or not. Evaluated for device 0.
+/// @param op_name: op such as "permute" or "reduce"
+/// @return JSON `{"op","device","chosen","basis","candidates":[{"kernel","eligible","reasons","autotune_us"}]}`,
+///         or null on error
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_explainKernelSelection(
+    mut env: JNIEnv,
+    _class: JClass,
+    op_name: JString,
+) -> jstring {
+    let _timer = jni_stats::time("explainKernelSelection");
+    match (|| -> Result<String, String> {
+        let op: String = env
+            .get_string(&op_name)
+            .map_err(|e| format!("Failed to get op name: {}", e))?
+            .into();
+        let context = get_or_init_vulkan()?;
+        let device = DeviceProfile::from_context(&context, 0).map_err(|e| e.to_string())?;
+        Ok(kernel_select::global().explain(&op, &device).to_json())
+    })() {
+        Ok(json) => match env.new_string(&json) {
+            Ok(jstr) => jstr.into_raw(),
+            Err(e) => {
+                error!("Failed to create JNI string: {}", e);
+                let _ = env.throw_new("java/lang/RuntimeException", &e.to_string());
+                std::ptr::null_mut()
+            }
+        },
+        Err(e) => {
+            error!("Explain kernel selection failed: {}", e);
+            let _ = env.throw_new("java/lang/RuntimeException", &e);
+            std::ptr::null_mut()
         }
-        
-        // Create zero-filled buffer for now
-        // TODO: Call actual Vulkan copy via DataTransfer when integrated
-        let buffer = vec![0u8; size_bytes as usize];
-        
-        info!("Copied {} bytes from device {}", size_bytes, handle_str);
-        
-        Ok(buffer)
+    }
+}
+
+// ============ Embedding Search ============
+
+/// Find the index rows most similar to a query embedding (cosine similarity)
+/// Runs the `embeddings` module's reference path until the JNI layer owns a logical device.
+/// @param query_handle: tensor handle the query embedding was registered under
+/// @param k: number of matches to return
+/// @return JSON array of {"id", "score"} objects, best first, or null on error
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[cfg(feature = "kernels-core")]
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_searchEmbeddings(
+    mut env: JNIEnv,
+    _class: JClass,
+    query_handle: JString,
+    k: jint,
+) -> jstring {
+    let _timer = jni_stats::time("searchEmbeddings");
+    match (|| -> Result<String, String> {
+        let handle: String = env
+            .get_string(&query_handle)
+            .map_err(|e| format!("Failed to get query handle: {}", e))?
+            .into();
+        let k = args::count("k", k)?;
+        let hits = embeddings::search(&handle, k).map_err(|e| e.to_string())?;
+        Ok(embeddings::hits_json(&hits))
     })() {
-        Ok(buffer) => {
-            match env.new_byte_array(buffer.len() as i32) {
-                Ok(arr) => arr.into_raw(),
-                Err(e) => {
-                    error!("Failed to create byte array: {}", e);
-                    let _ = env.throw_new("java/lang/RuntimeException", &e.to_string());
-                    std::ptr::null_mut()
+        Ok(json) => match env.new_string(&json) {
+            Ok(jstr) => jstr.into_raw(),
+            Err(e) => {
+                error!("Failed to create JNI string: {}", e);
+                let _ = env.throw_new("java/lang/RuntimeException", &e.to_string());
+                std::ptr::null_mut()
+            }
+        },
+        Err(e) => {
+            error!("Embedding search failed: {}", e);
+            let _ = env.throw_new("java/lang/IllegalArgumentException", &e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// `searchEmbeddings` in builds without the `kernels-core` feature
+// SAFETY: JNI function - throws and returns null
+#[cfg(not(feature = "kernels-core"))]
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_searchEmbeddings(
+    mut env: JNIEnv,
+    _class: JClass,
+    _query_handle: JString,
+    _k: jint,
+) -> jstring {
+    let _timer = jni_stats::time("searchEmbeddings");
+    throw_unsupported(&mut env, "kernels-core");
+    std::ptr::null_mut()
+}
+
+/// Replace the embedding index with an empty one that quantizes rows on insert
+/// @param dim: values per row
+/// @param quantization: "f32", "int8" (4x smaller) or "binary" (32x smaller)
+/// @return true if the index was created
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[cfg(feature = "kernels-core")]
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_createEmbeddingIndex(
+    mut env: JNIEnv,
+    _class: JClass,
+    dim: jint,
+    quantization: JString,
+) -> jboolean {
+    let _timer = jni_stats::time("createEmbeddingIndex");
+    match (|| -> Result<(), String> {
+        let dim = args::positive_count("dim", dim)?;
+        let name: String = env
+            .get_string(&quantization)
+            .map_err(|e| format!("Failed to get quantization: {}", e))?
+            .into();
+        let quantization = embeddings::Quantization::from_name(&name)
+            .ok_or_else(|| format!("Unknown quantization {}", name))?;
+        embeddings::set_index(embeddings::EmbeddingIndex::with_quantization(dim, quantization));
+        info!("Embedding index created: {} dimensions, {}", dim, quantization.name());
+        Ok(())
+    })() {
+        Ok(()) => jboolean::from(true),
+        Err(e) => {
+            error!("Embedding index creation failed: {}", e);
+            let _ = env.throw_new("java/lang/IllegalArgumentException", &e);
+            jboolean::from(false)
+        }
+    }
+}
+
+/// `createEmbeddingIndex` in builds without the `kernels-core` feature
+// SAFETY: JNI function - throws and returns false
+#[cfg(not(feature = "kernels-core"))]
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_createEmbeddingIndex(
+    mut env: JNIEnv,
+    _class: JClass,
+    _dim: jint,
+    _quantization: JString,
+) -> jboolean {
+    let _timer = jni_stats::time("createEmbeddingIndex");
+    throw_unsupported(&mut env, "kernels-core");
+    jboolean::from(false)
+}
+
+/// Append rows to the embedding index, creating an f32 index on first use
+/// Runs the `embeddings` module's reference path until the JNI layer owns a logical device.
+/// @param values: row-major embeddings, a whole number of rows of `dim` values
+/// @param dim: values per row; must match the index once it exists
+/// @return stable ids of the new rows, in order, or null on error
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[cfg(feature = "kernels-core")]
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_appendEmbeddings(
+    mut env: JNIEnv,
+    _class: JClass,
+    values: JFloatArray,
+    dim: jint,
+) -> jlongArray {
+    let _timer = jni_stats::time("appendEmbeddings");
+    match (|| -> Result<Vec<u64>, String> {
+        let dim = args::positive_count("dim", dim)?;
+        let len = env
+            .get_array_length(&values)
+            .map_err(|e| format!("Failed to get values length: {}", e))?;
+        let mut rows = vec![0.0; args::count("values length", len)?];
+        env.get_float_array_region(&values, 0, &mut rows)
+            .map_err(|e| format!("Failed to read values: {}", e))?;
+        embeddings::append(&rows, dim).map_err(|e| e.to_string())
+    })() {
+        Ok(ids) => match long_array(&mut env, &ids) {
+            Ok(arr) => arr,
+            Err(e) => {
+                error!("{}", e);
+                let _ = env.throw_new("java/lang/RuntimeException", &e);
+                std::ptr::null_mut()
+            }
+        },
+        Err(e) => {
+            error!("Embedding append failed: {}", e);
+            let _ = env.throw_new("java/lang/IllegalArgumentException", &e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// `appendEmbeddings` in builds without the `kernels-core` feature
+// SAFETY: JNI function - throws and returns null
+#[cfg(not(feature = "kernels-core"))]
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_appendEmbeddings(
+    mut env: JNIEnv,
+    _class: JClass,
+    _values: JFloatArray,
+    _dim: jint,
+) -> jlongArray {
+    let _timer = jni_stats::time("appendEmbeddings");
+    throw_unsupported(&mut env, "kernels-core");
+    std::ptr::null_mut()
+}
+
+/// Tombstone rows of the embedding index; it compacts once a quarter are deleted
+/// @param ids: ids returned by appendEmbeddings
+/// @return number of ids that were live, or -1 on error
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[cfg(feature = "kernels-core")]
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_deleteEmbeddings(
+    mut env: JNIEnv,
+    _class: JClass,
+    ids: JLongArray,
+) -> jint {
+    let _timer = jni_stats::time("deleteEmbeddings");
+    match (|| -> Result<jint, String> {
+        let len = env
+            .get_array_length(&ids)
+            .map_err(|e| format!("Failed to get ids length: {}", e))?;
+        let mut values = vec![0; args::count("ids length", len)?];
+        env.get_long_array_region(&ids, 0, &mut values)
+            .map_err(|e| format!("Failed to read ids: {}", e))?;
+        let ids = values
+            .iter()
+            .map(|&id| args::non_negative("embedding id", id))
+            .collect::<Result<Vec<u64>, _>>()?;
+        let deleted = embeddings::delete(&ids);
+        jint::try_from(deleted).map_err(|_| format!("{} deleted embeddings exceed jint", deleted))
+    })() {
+        Ok(deleted) => deleted,
+        Err(e) => {
+            error!("Embedding delete failed: {}", e);
+            let _ = env.throw_new("java/lang/IllegalArgumentException", &e);
+            -1
+        }
+    }
+}
+
+/// `deleteEmbeddings` in builds without the `kernels-core` feature
+// SAFETY: JNI function - throws and returns -1
+#[cfg(not(feature = "kernels-core"))]
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_deleteEmbeddings(
+    mut env: JNIEnv,
+    _class: JClass,
+    _ids: JLongArray,
+) -> jint {
+    let _timer = jni_stats::time("deleteEmbeddings");
+    throw_unsupported(&mut env, "kernels-core");
+    -1
+}
+
+// ============ Media Preprocessing ============
+
+/// View a direct `ByteBuffer` as bytes
+/// SAFETY: the Java side must not touch the buffer while the returned slice is in use
+#[cfg(feature = "kernels-media")]
+unsafe fn direct_buffer<'a>(env: &JNIEnv, buffer: &JByteBuffer) -> Result<&'a mut [u8], String> {
+    let ptr = env
+        .get_direct_buffer_address(buffer)
+        .map_err(|e| format!("Not a direct ByteBuffer: {}", e))?;
+    let len = env
+        .get_direct_buffer_capacity(buffer)
+        .map_err(|e| format!("Failed to get buffer capacity: {}", e))?;
+    // SAFETY: the JVM guarantees `len` bytes at `ptr` for a live direct buffer
+    Ok(unsafe { std::slice::from_raw_parts_mut(ptr, len) })
+}
+
+/// Write floats into a direct buffer, little-endian
+#[cfg(feature = "kernels-media")]
+fn write_floats(dst: &mut [u8], values: &[f32]) -> Result<(), String> {
+    if dst.len() < values.len() * 4 {
+        return Err(format!("Output buffer holds {} bytes, {} needed", dst.len(), values.len() * 4));
+    }
+    for (chunk, v) in dst.chunks_exact_mut(4).zip(values) {
+        chunk.copy_from_slice(&v.to_le_bytes());
+    }
+    Ok(())
+}
+
+/// Compute a Whisper log-mel spectrogram (80 bands, 25 ms windows every 10 ms)
+/// Runs the `media` module's reference path until the JNI layer owns a logical device.
+/// @param audio: direct buffer of 16 kHz mono f32 little-endian samples
+/// @param output: direct buffer receiving [80, frames] f32 values
+/// @return number of frames, or -1 on error
+// SAFETY: JNI function - validates buffers and handles errors properly
+#[cfg(feature = "kernels-media")]
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_computeLogMel(
+    mut env: JNIEnv,
+    _class: JClass,
+    audio: JByteBuffer,
+    output: JByteBuffer,
+) -> jint {
+    let _timer = jni_stats::time("computeLogMel");
+    match (|| -> Result<jint, String> {
+        // SAFETY: both buffers are owned by the caller for the duration of this call
+        let (audio, output) = unsafe { (direct_buffer(&env, &audio)?, direct_buffer(&env, &output)?) };
+        let samples: Vec<f32> = audio
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        let filters = media::mel_filterbank(&media::WHISPER_MEL);
+        let mel = media::log_mel_host(&samples, &media::WHISPER_MEL, &filters).map_err(|e| e.to_string())?;
+        write_floats(output, &mel)?;
+        let sample_count = u32::try_from(samples.len()).map_err(|_| format!("{} samples exceed u32", samples.len()))?;
+        let frames = media::WHISPER_MEL.n_frames(sample_count);
+        jint::try_from(frames).map_err(|_| format!("{} frames exceed jint", frames))
+    })() {
+        Ok(frames) => frames,
+        Err(e) => {
+            error!("Log-mel computation failed: {}", e);
+            let _ = env.throw_new("java/lang/IllegalArgumentException", &e);
+            -1
+        }
+    }
+}
+
+/// Resize and CLIP-normalize an RGBA8 image for a vision encoder
+/// Runs the `media` module's reference path until the JNI layer owns a logical device.
+/// @param pixels: direct buffer of width * height RGBA_8888 pixels
+/// @param width: input width in pixels
+/// @param height: input height in pixels
+/// @param output: direct buffer receiving 3 * out_width * out_height f32 values
+/// @param out_width: encoder input width
+/// @param out_height: encoder input height
+/// @param patch_size: emit [patches, 3, p, p] for p > 0, planar [3, h, w] for 0
+/// @return true if successful
+// SAFETY: JNI function - validates buffers and handles errors properly
+#[cfg(feature = "kernels-media")]
+#[unsafe(no_mangle)]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_preprocessImage(
+    mut env: JNIEnv,
+    _class: JClass,
+    pixels: JByteBuffer,
+    width: jint,
+    height: jint,
+    output: JByteBuffer,
+    out_width: jint,
+    out_height: jint,
+    patch_size: jint,
+) -> jboolean {
+    let _timer = jni_stats::time("preprocessImage");
+    match (|| -> Result<(), String> {
+        let width = args::positive_u32("width", width)?;
+        let height = args::positive_u32("height", height)?;
+        let out_width = args::positive_u32("out_width", out_width)?;
+        let out_height = args::positive_u32("out_height", out_height)?;
+        let patch_size = args::non_negative_u32("patch_size", patch_size)?;
+        // SAFETY: both buffers are owned by the caller for the duration of this call
+        let (pixels, output) = unsafe { (direct_buffer(&env, &pixels)?, direct_buffer(&env, &output)?) };
+        let pixels: Vec<u32> = pixels
+            .chunks_exact(4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        let config = media::ImageConfig {
+            out_width,
+            out_height,
+            mean: media::CLIP_MEAN,
+            std: media::CLIP_STD,
+            patch_size: (patch_size > 0).then_some(patch_size),
+        };
+        let values = media::preprocess_image_host(&pixels, width, height, &config)
+            .map_err(|e| e.to_string())?;
+        write_floats(output, &values)
+    })() {
+        Ok(()) => jboolean::from(true),
+        Err(e) => {
+            error!("Image preprocessing failed: {}", e);
+            let _ = env.throw_new("java/lang/IllegalArgumentException", &e);
+            jboolean::from(false)
+        }
+    }
+}
+
+/// `computeLogMel` in builds without the `kernels-media` feature
+// SAFETY: JNI function - throws and returns -1
+#[cfg(not(feature = "kernels-media"))]
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_computeLogMel(
+    mut env: JNIEnv,
+    _class: JClass,
+    _audio: JByteBuffer,
+    _output: JByteBuffer,
+) -> jint {
+    let _timer = jni_stats::time("computeLogMel");
+    throw_unsupported(&mut env, "kernels-media");
+    -1
+}
+
+/// `preprocessImage` in builds without the `kernels-media` feature
+// SAFETY: JNI function - throws and returns false
+#[cfg(not(feature = "kernels-media"))]
+#[unsafe(no_mangle)]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_preprocessImage(
+    mut env: JNIEnv,
+    _class: JClass,
+    _pixels: JByteBuffer,
+    _width: jint,
+    _height: jint,
+    _output: JByteBuffer,
+    _out_width: jint,
+    _out_height: jint,
+    _patch_size: jint,
+) -> jboolean {
+    let _timer = jni_stats::time("preprocessImage");
+    throw_unsupported(&mut env, "kernels-media");
+    jboolean::from(false)
+}
+
+/// Per-tag byte counts of a `memory_pressure` event, as a JSON object in tag order
+struct Breakdown<'a>(&'a [(String, u64)]);
+
+impl Serialize for Breakdown<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(tag, bytes)| (tag, bytes)))
+    }
+}
+
+/// One event of the `pollEvents` report, tagged with `GpuEvent::kind`
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum EventEntry<'a> {
+    Allocated { handle_id: &'a str, size: u64 },
+    Freed { handle_id: &'a str, size: u64 },
+    Resized { handle_id: &'a str, old_size: u64, new_size: u64 },
+    Dispatched { name: &'a str, workgroups: [u32; 3] },
+    Transfer { direction: &'static str, bytes: u64, duration_us: u128 },
+    Thermal { device_index: usize, level: u32 },
+    SlowPath { slow_path: &'static str, detail: &'a str },
+    Error { source: &'static str, message: &'a str },
+    #[serde(rename = "device_status")]
+    DeviceStatusChanged { device_index: usize, device_id: &'a str, status: &'static str },
+    DeviceLost { source: &'static str },
+    StageFailed { stage_id: &'a str, node_id: &'a str, reason: &'a str },
+    StageReassigned { stage_id: &'a str, from_node: &'a str, to_node: &'a str },
+    MemoryPressure { threshold_percent: u32, used_bytes: u64, budget_bytes: u64, breakdown: Breakdown<'a> },
+    ConfigReloaded { applied: &'a [String], pending_reinit: &'a [String] },
+}
+
+impl<'a> From<&'a GpuEvent> for EventEntry<'a> {
+    fn from(event: &'a GpuEvent) -> Self {
+        match event {
+            GpuEvent::Allocated { handle_id, size } => EventEntry::Allocated { handle_id, size: *size },
+            GpuEvent::Freed { handle_id, size } => EventEntry::Freed { handle_id, size: *size },
+            GpuEvent::Resized { handle_id, old_size, new_size } => EventEntry::Resized {
+                handle_id,
+                old_size: *old_size,
+                new_size: *new_size,
+            },
+            GpuEvent::Dispatched { name, workgroups } => EventEntry::Dispatched { name, workgroups: *workgroups },
+            GpuEvent::Transfer { direction, bytes, duration } => EventEntry::Transfer {
+                direction: direction.name(),
+                bytes: *bytes,
+                duration_us: duration.as_micros(),
+            },
+            GpuEvent::Thermal { device_index, level } => EventEntry::Thermal {
+                device_index: *device_index,
+                level: *level,
+            },
+            GpuEvent::SlowPath { kind, detail } => EventEntry::SlowPath { slow_path: kind, detail },
+            GpuEvent::Error { source, message } => EventEntry::Error { source, message },
+            GpuEvent::DeviceStatusChanged { device_index, device_id, status } => EventEntry::DeviceStatusChanged {
+                device_index: *device_index,
+                device_id,
+                status: status.name(),
+            },
+            GpuEvent::DeviceLost { source } => EventEntry::DeviceLost { source },
+            GpuEvent::StageFailed { stage_id, node_id, reason } => EventEntry::StageFailed { stage_id, node_id, reason },
+            GpuEvent::StageReassigned { stage_id, from_node, to_node } => {
+                EventEntry::StageReassigned { stage_id, from_node, to_node }
+            }
+            GpuEvent::MemoryPressure { threshold_percent, used_bytes, budget_bytes, breakdown } => {
+                EventEntry::MemoryPressure {
+                    threshold_percent: *threshold_percent,
+                    used_bytes: *used_bytes,
+                    budget_bytes: *budget_bytes,
+                    breakdown: Breakdown(breakdown),
                 }
             }
+            GpuEvent::ConfigReloaded { applied, pending_reinit } => EventEntry::ConfigReloaded { applied, pending_reinit },
+        }
+    }
+}
+
+/// The `pollEvents` report
+#[derive(Serialize)]
+struct EventReport<'a> {
+    events: Vec<EventEntry<'a>>,
+    lagged: u64,
+}
+
+/// Drain buffered backend events
+/// @param max_events: maximum number of events to return, >= 0
+/// @return JSON object with the events and the number dropped due to lag, or null on error
+// SAFETY: JNI function - returns valid string or null
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_pollEvents(
+    mut env: JNIEnv,
+    _class: JClass,
+    max_events: jint,
+) -> jstring {
+    let _timer = jni_stats::time("pollEvents");
+    let max_events = match args::count("max_events", max_events) {
+        Ok(max_events) => max_events,
+        Err(e) => {
+            error!("Poll events failed: {}", e);
+            let _ = env.throw_new("java/lang/IllegalArgumentException", e.to_string());
+            return std::ptr::null_mut();
         }
+    };
+    let (polled, lagged) = events::drain(&mut EVENT_RECEIVER.lock(), max_events);
+    let report = EventReport {
+        events: polled.iter().map(EventEntry::from).collect(),
+        lagged,
+    };
+    let json = match serde_json::to_string(&report) {
+        Ok(json) => json,
         Err(e) => {
-            error!("Copy from device failed: {}", e);
-            let _ = env.throw_new("java/lang/RuntimeException", &e);
+            error!("Failed to serialize events: {}", e);
+            let _ = env.throw_new("java/lang/RuntimeException", e.to_string());
+            return std::ptr::null_mut();
+        }
+    };
+    match env.new_string(&json) {
+        Ok(jstr) => jstr.into_raw(),
+        Err(e) => {
+            error!("Failed to create JNI string: {}", e);
+            let _ = env.throw_new("java/lang/RuntimeException", &e.to_string());
             std::ptr::null_mut()
         }
     }
 }
 
-// ============ Utilities ============
+/// Get the native library version for wrapper compatibility checks
+/// @return version string in the form "<crate>+abi.<n>"
+// SAFETY: JNI function - returns valid string or null
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_getNativeVersion(
+    mut env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let _timer = jni_stats::time("getNativeVersion");
+    match env.new_string(version::native_version()) {
+        Ok(jstr) => jstr.into_raw(),
+        Err(e) => {
+            error!("Failed to create JNI string: {}", e);
+            let _ = env.throw_new("java/lang/RuntimeException", &e.to_string());
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// List the optional subsystems compiled into this native library
+/// @return JSON object with the enabled cargo features and embedded kernels
+// SAFETY: JNI function - returns valid string or null
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_getEnabledFeatures(
+    mut env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let _timer = jni_stats::time("getEnabledFeatures");
+    let quote = |name: &str| format!(r#""{}""#, name);
+    let features: Vec<String> = kernels::enabled_features().into_iter().map(quote).collect();
+    let kernel_names: Vec<String> = kernels::embedded().iter().map(|k| quote(k.name)).collect();
+    let json = format!(
+        r#"{{"features":[{}],"kernels":[{}]}}"#,
+        features.join(","),
+        kernel_names.join(",")
+    );
+    match env.new_string(&json) {
+        Ok(jstr) => jstr.into_raw(),
+        Err(e) => {
+            error!("Failed to create JNI string: {}", e);
+            let _ = env.throw_new("java/lang/RuntimeException", &e.to_string());
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Decompress all embedded kernels now rather than at first pipeline creation
+/// Meant for app warmup, e.g. behind a splash screen.
+/// @return JSON object with kernel counts, byte sizes and time taken
+// SAFETY: JNI function - returns valid string or null
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_extractKernels(
+    mut env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let _timer = jni_stats::time("extractKernels");
+    let json = match kernels::extract_all() {
+        Ok(stats) => format!(
+            r#"{{"extracted":{},"cached":{},"compressed_bytes":{},"spirv_bytes":{},"duration_us":{}}}"#,
+            stats.extracted,
+            stats.cached,
+            stats.compressed_bytes,
+            stats.spirv_bytes,
+            stats.duration.as_micros()
+        ),
+        Err(e) => {
+            error!("Kernel extraction failed: {}", e);
+            let _ = env.throw_new("java/lang/RuntimeException", &e.to_string());
+            return std::ptr::null_mut();
+        }
+    };
+    match env.new_string(&json) {
+        Ok(jstr) => jstr.into_raw(),
+        Err(e) => {
+            error!("Failed to create JNI string: {}", e);
+            let _ = env.throw_new("java/lang/RuntimeException", &e.to_string());
+            std::ptr::null_mut()
+        }
+    }
+}
+
+// ============ Remote shim ============
+
+/// Start the development server a workstation runs kernels through
+/// Listens on loopback only; reach it with `adb forward tcp:<port> tcp:<port>`.
+/// Replaces a server already running.
+/// @param port: TCP port, or 0 for any free one
+/// @return the port listened on, or -1 on error
+// SAFETY: JNI function - validates inputs and handles errors properly
+#[cfg(feature = "remote-debug")]
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_startRemoteShim(
+    mut env: JNIEnv,
+    _class: JClass,
+    port: jint,
+) -> jint {
+    let _timer = jni_stats::time("startRemoteShim");
+    match (|| -> Result<u16, String> {
+        let port = u16::try_from(args::in_range("port", port, 0..=u64::from(u16::MAX))?)
+            .map_err(|e| e.to_string())?;
+        let context = get_or_init_vulkan()?;
+        let mut shim = REMOTE_SHIM.lock();
+        // Free the port before binding it again
+        *shim = None;
+        let server = RemoteServer::bind(("127.0.0.1", port), context).map_err(|e| e.to_string())?;
+        let port = server.local_addr().port();
+        *shim = Some(server);
+        Ok(port)
+    })() {
+        Ok(port) => {
+            info!("Remote shim listening on 127.0.0.1:{}", port);
+            jint::from(port)
+        }
+        Err(e) => {
+            error!("Start remote shim failed: {}", e);
+            let _ = env.throw_new("java/lang/IllegalStateException", &e);
+            -1
+        }
+    }
+}
+
+/// `startRemoteShim` in builds without the `remote-debug` feature
+// SAFETY: JNI function - throws and returns -1
+#[cfg(not(feature = "remote-debug"))]
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_startRemoteShim(
+    mut env: JNIEnv,
+    _class: JClass,
+    _port: jint,
+) -> jint {
+    let _timer = jni_stats::time("startRemoteShim");
+    throw_unsupported(&mut env, "remote-debug");
+    -1
+}
+
+/// Stop the development server; connected clients keep their session until they disconnect
+/// @return true if a server was running
+// SAFETY: JNI function - takes no pointers
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_com_exo_gpu_VulkanGpu_stopRemoteShim(
+    _env: JNIEnv,
+    _class: JClass,
+) -> jboolean {
+    let _timer = jni_stats::time("stopRemoteShim");
+    #[cfg(feature = "remote-debug")]
+    let stopped = REMOTE_SHIM.lock().take().map(RemoteServer::shutdown).is_some();
+    #[cfg(not(feature = "remote-debug"))]
+    let stopped = false;
+    jboolean::from(stopped)
+}
 
 /// Shutdown Vulkan and clean up all resources
 // SAFETY: JNI function - clears all global state
@@ -430,23 +3680,40 @@ pub extern "C" fn Java_com_exo_gpu_VulkanGpu_shutdown(
     _env: JNIEnv,
     _class: JClass,
 ) -> jboolean {
+    let _timer = jni_stats::time("shutdown");
     // Clear all allocations
     {
         let mut allocs = MEMORY_ALLOCATIONS.lock();
         allocs.clear();
     }
+    // Destroys the devices and with them the memory of every allocation
+    device_context::close_all();
     
+    // Drop all loaded models
+    {
+        let mut models = MODEL_MANAGER.lock();
+        *models = None;
+    }
+    *MEMORY_WATERMARKS.lock() = None;
+    *SESSIONS.lock() = SessionKeeper::new();
+    #[cfg(feature = "remote-debug")]
+    if let Some(shim) = REMOTE_SHIM.lock().take() {
+        shim.shutdown();
+    }
+
     // Clear all device handles
     {
         let mut handles = DEVICE_HANDLES.lock();
         handles.clear();
     }
+    *DEVICE_SLOTS.lock() = DeviceSlots::new();
     
     // Clear Vulkan context
     {
         let mut ctx = VULKAN_CONTEXT.lock();
         *ctx = None;
     }
+    reset_vulkan();
     
     info!("Vulkan JNI shutdown complete");
     jboolean::from(true)
@@ -467,4 +3734,79 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_event_report_escapes_payloads() {
+        let message = "bad path \"C:\\models\"\n\tline two";
+        let events = [
+            GpuEvent::Error { source: "loadModel", message: message.to_string() },
+            GpuEvent::MemoryPressure {
+                threshold_percent: 80,
+                used_bytes: 900,
+                budget_bytes: 1000,
+                breakdown: vec![("b".to_string(), 600), ("a\"".to_string(), 300)],
+            },
+        ];
+        let report = EventReport {
+            events: events.iter().map(EventEntry::from).collect(),
+            lagged: 2,
+        };
+        let json: serde_json::Value = serde_json::from_str(&serde_json::to_string(&report).unwrap()).unwrap();
+        assert_eq!(json["lagged"], 2);
+        assert_eq!(json["events"][0]["kind"], "error");
+        assert_eq!(json["events"][0]["message"], message);
+        assert_eq!(json["events"][1]["kind"], "memory_pressure");
+        assert_eq!(json["events"][1]["breakdown"]["a\""], 300);
+    }
+
+    #[test]
+    fn test_device_report_escapes_names() {
+        let info = DeviceInfo {
+            device_id: "pci:5143:0000".to_string(),
+            name: r#"Adreno "Turbo" \ 750"#.to_string(),
+            vendor: "Qualcomm".to_string(),
+            vendor_id: 0x5143,
+            driver_version: "2149580800".to_string(),
+            api_version: "1.3.128".to_string(),
+            max_allocation_size: 1 << 32,
+            compute_units: 6,
+            compute_units_source: exo_vulkan_binding::compute_units::ComputeUnitSource::Estimated,
+            subgroup_size: 64,
+            max_clock_mhz: None,
+            total_memory_bytes: 8 << 30,
+            bandwidth_gbps: 51.2,
+        };
+        let report = DeviceReport {
+            devices: vec![DeviceEntry {
+                device_id: "vulkan:0".to_string(),
+                online: true,
+                info: &info,
+            }],
+            count: 1,
+            online: 1,
+        };
+        let json: serde_json::Value = serde_json::from_str(&serde_json::to_string(&report).unwrap()).unwrap();
+        let device = &json["devices"][0];
+        assert_eq!(device["name"], info.name.as_str());
+        assert_eq!(device["device_id"], "vulkan:0");
+        assert_eq!(device["stable_id"], "pci:5143:0000");
+        assert_eq!(device["memory_bytes"], 8u64 << 30);
+        assert_eq!(device["compute_units_source"], "estimated");
+        assert_eq!(device["vendor_id"], 0x5143);
+        assert!(device["max_clock_mhz"].is_null());
+    }
+
+    #[test]
+    fn test_model_list_escapes_names() {
+        let mut manager = ModelManager::new(1 << 20);
+        let id = "llama \"3\"\n";
+        manager.load(id, r"C:\models\llama.gguf", 1024).unwrap();
+        let models = manager.list();
+        let entries: Vec<ModelEntry> = models.iter().map(ModelEntry::from).collect();
+        let json: serde_json::Value = serde_json::from_str(&serde_json::to_string(&entries).unwrap()).unwrap();
+        assert_eq!(json[0]["model_id"], id);
+        assert_eq!(json[0]["path"], r"C:\models\llama.gguf");
+        assert_eq!(json[0]["quota_bytes"], 1024);
+        assert_eq!(json[0]["active_users"], 0);
+    }
 }
@@ -0,0 +1,221 @@
+//! Native method registration for relocated Java classes
+//!
+//! The exported `Java_com_exo_gpu_VulkanGpu_*` symbols only bind to a class
+//! named `com.exo.gpu.VulkanGpu`. Apps that repackage it (R8 obfuscation,
+//! shading into another package) set the `exo.gpu.jniClass` system property
+//! to the class's JNI name before `System.loadLibrary`, and `JNI_OnLoad`
+//! binds every native method to that class with `RegisterNatives`. The
+//! bundled `VulkanGpu.kt` sets the property to its own runtime name, so
+//! relocation needs no integrator code as long as method names are kept.
+
+use std::ffi::c_void;
+
+use jni::objects::{JString, JValue};
+use jni::sys::{JNI_VERSION_1_6, jint};
+use jni::{JNIEnv, JavaVM, NativeMethod};
+use log::{error, info};
+use thiserror::Error;
+
+use crate::*;
+
+/// Class the exported symbols bind to without registration
+pub const DEFAULT_CLASS: &str = "com/exo/gpu/VulkanGpu";
+
+/// System property naming the class to register natives on, e.g. `a/b/Gpu`
+pub const CLASS_PROPERTY: &str = "exo.gpu.jniClass";
+
+#[derive(Error, Debug)]
+pub enum NativesError {
+    #[error("JNI error: {0}")]
+    Jni(#[from] jni::errors::Error),
+    #[error("Invalid JNI class name: {0:?}")]
+    InvalidClass(String),
+}
+
+pub type NativesResult<T> = Result<T, NativesError>;
+
+/// Name, JNI signature and implementation of every `VulkanGpu` native method
+///
+/// Must match the `external fun` declarations in `VulkanGpu.kt`:
+/// `RegisterNatives` fails as a whole if any entry is missing from the class.
+fn methods() -> Vec<(&'static str, &'static str, *mut c_void)> {
+    vec![
+        ("initializeVulkan", "()Z", Java_com_exo_gpu_VulkanGpu_initializeVulkan as *mut c_void),
+        ("setVulkanLoaderPath", "(Ljava/lang/String;)Z", Java_com_exo_gpu_VulkanGpu_setVulkanLoaderPath as *mut c_void),
+        ("enumerateDevices", "()Ljava/lang/String;", Java_com_exo_gpu_VulkanGpu_enumerateDevices as *mut c_void),
+        ("rescanDevices", "()Ljava/lang/String;", Java_com_exo_gpu_VulkanGpu_rescanDevices as *mut c_void),
+        ("getDeviceName", "(I)Ljava/lang/String;", Java_com_exo_gpu_VulkanGpu_getDeviceName as *mut c_void),
+        ("getDeviceMemory", "(I)J", Java_com_exo_gpu_VulkanGpu_getDeviceMemory as *mut c_void),
+        ("getComputeUnits", "(I)I", Java_com_exo_gpu_VulkanGpu_getComputeUnits as *mut c_void),
+        ("getDriverVersion", "(I)Ljava/lang/String;", Java_com_exo_gpu_VulkanGpu_getDriverVersion as *mut c_void),
+        ("getVendorId", "(I)I", Java_com_exo_gpu_VulkanGpu_getVendorId as *mut c_void),
+        ("getApiVersion", "(I)Ljava/lang/String;", Java_com_exo_gpu_VulkanGpu_getApiVersion as *mut c_void),
+        ("getMaxAllocationSize", "(I)J", Java_com_exo_gpu_VulkanGpu_getMaxAllocationSize as *mut c_void),
+        ("getDeviceCapabilities", "(I)Ljava/lang/String;", Java_com_exo_gpu_VulkanGpu_getDeviceCapabilities as *mut c_void),
+        ("benchmarkBandwidth", "(I)F", Java_com_exo_gpu_VulkanGpu_benchmarkBandwidth as *mut c_void),
+        ("runBenchSuite", "(ILjava/lang/String;)Ljava/lang/String;", Java_com_exo_gpu_VulkanGpu_runBenchSuite as *mut c_void),
+        ("runRegressionTests", "(I)Ljava/lang/String;", Java_com_exo_gpu_VulkanGpu_runRegressionTests as *mut c_void),
+        ("openClient", "()Ljava/lang/String;", Java_com_exo_gpu_VulkanGpu_openClient as *mut c_void),
+        ("closeClient", "(Ljava/lang/String;)I", Java_com_exo_gpu_VulkanGpu_closeClient as *mut c_void),
+        ("pinSession", "(Ljava/lang/String;)Z", Java_com_exo_gpu_VulkanGpu_pinSession as *mut c_void),
+        ("resumeSession", "(Ljava/lang/String;)Z", Java_com_exo_gpu_VulkanGpu_resumeSession as *mut c_void),
+        ("allocateMemory", "(IJLjava/lang/String;)Ljava/lang/String;", Java_com_exo_gpu_VulkanGpu_allocateMemory as *mut c_void),
+        ("allocateBuffers", "(Ljava/lang/String;Ljava/lang/String;)Ljava/lang/String;", Java_com_exo_gpu_VulkanGpu_allocateBuffers as *mut c_void),
+        ("freeMemory", "(Ljava/lang/String;Ljava/lang/String;)Z", Java_com_exo_gpu_VulkanGpu_freeMemory as *mut c_void),
+        ("importHardwareBuffer", "(Landroid/hardware/HardwareBuffer;Ljava/lang/String;)Ljava/lang/String;", Java_com_exo_gpu_VulkanGpu_importHardwareBuffer as *mut c_void),
+        ("copyToDevice", "(Ljava/lang/String;[BLjava/lang/String;)Z", Java_com_exo_gpu_VulkanGpu_copyToDevice as *mut c_void),
+        ("copyFromDevice", "(Ljava/lang/String;JLjava/lang/String;)[B", Java_com_exo_gpu_VulkanGpu_copyFromDevice as *mut c_void),
+        ("loadModel", "(Ljava/lang/String;Ljava/lang/String;J)Z", Java_com_exo_gpu_VulkanGpu_loadModel as *mut c_void),
+        ("unloadModel", "(Ljava/lang/String;)Z", Java_com_exo_gpu_VulkanGpu_unloadModel as *mut c_void),
+        ("listModels", "()Ljava/lang/String;", Java_com_exo_gpu_VulkanGpu_listModels as *mut c_void),
+        ("exportState", "()[B", Java_com_exo_gpu_VulkanGpu_exportState as *mut c_void),
+        ("prepareHandover", "(Ljava/lang/String;)Ljava/lang/String;", Java_com_exo_gpu_VulkanGpu_prepareHandover as *mut c_void),
+        ("generateSpeculative", "(Ljava/lang/String;Ljava/lang/String;[IIII)[I", Java_com_exo_gpu_VulkanGpu_generateSpeculative as *mut c_void),
+        ("exportTokens", "(Ljava/lang/String;II)[I", Java_com_exo_gpu_VulkanGpu_exportTokens as *mut c_void),
+        ("createEvalDataset", "(Ljava/lang/String;[II)Ljava/lang/String;", Java_com_exo_gpu_VulkanGpu_createEvalDataset as *mut c_void),
+        ("evaluate", "(Ljava/lang/String;)Ljava/lang/String;", Java_com_exo_gpu_VulkanGpu_evaluate as *mut c_void),
+        ("releaseEvalDataset", "(Ljava/lang/String;)Z", Java_com_exo_gpu_VulkanGpu_releaseEvalDataset as *mut c_void),
+        ("setFrameBudgetMs", "(FI)Z", Java_com_exo_gpu_VulkanGpu_setFrameBudgetMs as *mut c_void),
+        ("setUiResponsive", "(Z)V", Java_com_exo_gpu_VulkanGpu_setUiResponsive as *mut c_void),
+        ("registerKernelFromAsset", "(Ljava/lang/String;[BLjava/lang/String;)Z", Java_com_exo_gpu_VulkanGpu_registerKernelFromAsset as *mut c_void),
+        ("dispatchKernel", "(Ljava/lang/String;[Ljava/lang/String;Ljava/lang/String;IIILjava/lang/String;)Ljava/lang/String;", Java_com_exo_gpu_VulkanGpu_dispatchKernel as *mut c_void),
+        ("waitJob", "(Ljava/lang/String;J)Z", Java_com_exo_gpu_VulkanGpu_waitJob as *mut c_void),
+        ("registerWeight", "(Ljava/lang/String;Ljava/lang/String;II[FLjava/lang/String;)Z", Java_com_exo_gpu_VulkanGpu_registerWeight as *mut c_void),
+        ("applyDelta", "(Ljava/lang/String;Ljava/lang/String;FLjava/lang/String;)Z", Java_com_exo_gpu_VulkanGpu_applyDelta as *mut c_void),
+        ("explainKernelSelection", "(Ljava/lang/String;)Ljava/lang/String;", Java_com_exo_gpu_VulkanGpu_explainKernelSelection as *mut c_void),
+        ("searchEmbeddings", "(Ljava/lang/String;I)Ljava/lang/String;", Java_com_exo_gpu_VulkanGpu_searchEmbeddings as *mut c_void),
+        ("createEmbeddingIndex", "(ILjava/lang/String;)Z", Java_com_exo_gpu_VulkanGpu_createEmbeddingIndex as *mut c_void),
+        ("appendEmbeddings", "([FI)[J", Java_com_exo_gpu_VulkanGpu_appendEmbeddings as *mut c_void),
+        ("deleteEmbeddings", "([J)I", Java_com_exo_gpu_VulkanGpu_deleteEmbeddings as *mut c_void),
+        ("computeLogMel", "(Ljava/nio/ByteBuffer;Ljava/nio/ByteBuffer;)I", Java_com_exo_gpu_VulkanGpu_computeLogMel as *mut c_void),
+        ("preprocessImage", "(Ljava/nio/ByteBuffer;IILjava/nio/ByteBuffer;III)Z", Java_com_exo_gpu_VulkanGpu_preprocessImage as *mut c_void),
+        ("applyConfig", "(Ljava/lang/String;)Ljava/lang/String;", Java_com_exo_gpu_VulkanGpu_applyConfig as *mut c_void),
+        ("getSlowPathStats", "()Ljava/lang/String;", Java_com_exo_gpu_VulkanGpu_getSlowPathStats as *mut c_void),
+        ("setBucketPolicy", "(Ljava/lang/String;)Z", Java_com_exo_gpu_VulkanGpu_setBucketPolicy as *mut c_void),
+        ("getPaddingStats", "()Ljava/lang/String;", Java_com_exo_gpu_VulkanGpu_getPaddingStats as *mut c_void),
+        ("getObjectUsage", "()Ljava/lang/String;", Java_com_exo_gpu_VulkanGpu_getObjectUsage as *mut c_void),
+        ("getPerformanceSummary", "()Ljava/lang/String;", Java_com_exo_gpu_VulkanGpu_getPerformanceSummary as *mut c_void),
+        ("getKernelStats", "()Ljava/lang/String;", Java_com_exo_gpu_VulkanGpu_getKernelStats as *mut c_void),
+        ("getMetricsHistory", "(Ljava/lang/String;)Ljava/lang/String;", Java_com_exo_gpu_VulkanGpu_getMetricsHistory as *mut c_void),
+        ("getMetricsHistoryBinary", "(Ljava/lang/String;)[B", Java_com_exo_gpu_VulkanGpu_getMetricsHistoryBinary as *mut c_void),
+        ("resetPerformanceStats", "()V", Java_com_exo_gpu_VulkanGpu_resetPerformanceStats as *mut c_void),
+        ("setTraceId", "(Ljava/lang/String;)Z", Java_com_exo_gpu_VulkanGpu_setTraceId as *mut c_void),
+        ("setClientTraceId", "(Ljava/lang/String;Ljava/lang/String;)Z", Java_com_exo_gpu_VulkanGpu_setClientTraceId as *mut c_void),
+        ("getTraceStats", "(Ljava/lang/String;)Ljava/lang/String;", Java_com_exo_gpu_VulkanGpu_getTraceStats as *mut c_void),
+        ("getJniStats", "()Ljava/lang/String;", Java_com_exo_gpu_VulkanGpu_getJniStats as *mut c_void),
+        ("resetJniStats", "()V", Java_com_exo_gpu_VulkanGpu_resetJniStats as *mut c_void),
+        ("inspectTensor", "(Ljava/lang/String;Ljava/lang/String;)Ljava/lang/String;", Java_com_exo_gpu_VulkanGpu_inspectTensor as *mut c_void),
+        ("setMemoryThresholds", "([I)Z", Java_com_exo_gpu_VulkanGpu_setMemoryThresholds as *mut c_void),
+        ("pollEvents", "(I)Ljava/lang/String;", Java_com_exo_gpu_VulkanGpu_pollEvents as *mut c_void),
+        ("getEnabledFeatures", "()Ljava/lang/String;", Java_com_exo_gpu_VulkanGpu_getEnabledFeatures as *mut c_void),
+        ("extractKernels", "()Ljava/lang/String;", Java_com_exo_gpu_VulkanGpu_extractKernels as *mut c_void),
+        ("startRemoteShim", "(I)I", Java_com_exo_gpu_VulkanGpu_startRemoteShim as *mut c_void),
+        ("stopRemoteShim", "()Z", Java_com_exo_gpu_VulkanGpu_stopRemoteShim as *mut c_void),
+        ("getNativeVersion", "()Ljava/lang/String;", Java_com_exo_gpu_VulkanGpu_getNativeVersion as *mut c_void),
+        ("isVulkanSupported", "()Z", Java_com_exo_gpu_VulkanGpu_isVulkanSupported as *mut c_void),
+        ("shutdown", "()Z", Java_com_exo_gpu_VulkanGpu_shutdown as *mut c_void),
+    ]
+}
+
+/// Whether `class` is a JNI internal name like `com/exo/gpu/VulkanGpu`
+fn is_class_name(class: &str) -> bool {
+    !class.is_empty()
+        && !class.contains('.')
+        && class
+            .split('/')
+            .all(|part| !part.is_empty() && !part.contains(|c: char| c.is_whitespace() || c == ';' || c == '['))
+}
+
+/// Bind every native method to `class`, given as a JNI name like `a/b/Gpu`
+///
+/// Needed only for classes not named `com/exo/gpu/VulkanGpu`; registering
+/// on the default class is harmless and simply rebinds the same symbols.
+pub fn register(env: &mut JNIEnv, class: &str) -> NativesResult<()> {
+    if !is_class_name(class) {
+        return Err(NativesError::InvalidClass(class.to_string()));
+    }
+    let methods: Vec<NativeMethod> = methods()
+        .into_iter()
+        .map(|(name, sig, fn_ptr)| NativeMethod {
+            name: name.into(),
+            sig: sig.into(),
+            fn_ptr,
+        })
+        .collect();
+    let class_ref = env.find_class(class)?;
+    env.register_native_methods(&class_ref, &methods)?;
+    info!("Registered {} native methods on {}", methods.len(), class);
+    Ok(())
+}
+
+/// `System.getProperty(CLASS_PROPERTY)`, if set
+fn configured_class(env: &mut JNIEnv) -> NativesResult<Option<String>> {
+    let key = env.new_string(CLASS_PROPERTY)?;
+    let value = env
+        .call_static_method(
+            "java/lang/System",
+            "getProperty",
+            "(Ljava/lang/String;)Ljava/lang/String;",
+            &[JValue::Object(&key)],
+        )?
+        .l()?;
+    if value.is_null() {
+        return Ok(None);
+    }
+    let value = JString::from(value);
+    let class: String = env.get_string(&value)?.into();
+    Ok(Some(class.trim().replace('.', "/")))
+}
+
+/// Register natives on the class named by `exo.gpu.jniClass`
+///
+/// Failures are logged and the pending exception cleared: the exported
+/// symbols still serve an unrelocated `VulkanGpu`, so loading never fails
+/// here.
+// SAFETY: called by the JVM once, on the thread running System.loadLibrary
+#[unsafe(no_mangle)]
+pub extern "system" fn JNI_OnLoad(vm: JavaVM, _reserved: *mut c_void) -> jint {
+    let Ok(mut env) = vm.get_env() else {
+        return JNI_VERSION_1_6;
+    };
+    let result = configured_class(&mut env).and_then(|class| match class {
+        Some(class) if class != DEFAULT_CLASS => register(&mut env, &class),
+        _ => Ok(()),
+    });
+    if let Err(e) = result {
+        error!("Native method registration failed: {}", e);
+        if env.exception_check().unwrap_or(false) {
+            let _ = env.exception_clear();
+        }
+    }
+    JNI_VERSION_1_6
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_method_table() {
+        let methods = methods();
+        let names: HashSet<_> = methods.iter().map(|(name, _, _)| *name).collect();
+        assert_eq!(names.len(), methods.len(), "duplicate method");
+        for (name, sig, fn_ptr) in &methods {
+            assert!(!fn_ptr.is_null(), "{}", name);
+            let (params, ret) = sig[1..].split_once(')').expect(name);
+            assert!(sig.starts_with('('), "{}", name);
+            assert!(!params.contains(')') && !ret.is_empty(), "{}", name);
+        }
+        assert!(names.contains("shutdown") && names.contains("initializeVulkan"));
+    }
+
+    #[test]
+    fn test_class_names() {
+        assert!(is_class_name(DEFAULT_CLASS));
+        assert!(is_class_name("a/b/C$Inner"));
+        for bad in ["", "com.exo.gpu.VulkanGpu", "a//b", "Lcom/exo/Gpu;", "a b/C"] {
+            assert!(!is_class_name(bad), "{}", bad);
+        }
+    }
+}
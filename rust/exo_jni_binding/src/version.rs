@@ -0,0 +1,35 @@
+//! Native library versioning
+//!
+//! Multi-ABI Android packages ship one `.so` per architecture, and it is easy
+//! for a stale library to end up next to a newer Kotlin wrapper. The wrapper
+//! compares its expected version against `getNativeVersion()` at load time.
+
+/// ABI revision of the JNI surface; bump whenever an exported signature changes
+pub const ABI_VERSION: u32 = 2;
+
+/// Crate version the native library was built from
+pub const CRATE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Full version string reported to Kotlin, e.g. `0.0.1+abi.1`
+pub fn native_version() -> String {
+    format!("{}+abi.{}", CRATE_VERSION, ABI_VERSION)
+}
+
+/// Versioned C symbol for packaging checks (`nm -D libexo_jni_binding.so`)
+#[cfg(feature = "abi-version")]
+#[unsafe(no_mangle)]
+pub extern "C" fn exo_gpu_abi_version() -> u32 {
+    ABI_VERSION
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_native_version_format() {
+        let version = native_version();
+        assert!(version.starts_with(CRATE_VERSION));
+        assert!(version.ends_with(&format!("+abi.{}", ABI_VERSION)));
+    }
+}
@@ -0,0 +1,142 @@
+//! `#[derive(KernelArgs)]` for exo_vulkan_binding kernels
+//!
+//! Maps a struct of buffers and scalars to a kernel's descriptor bindings and
+//! push constants:
+//!
+//! ```ignore
+//! #[derive(KernelArgs)]
+//! #[kernel(name = "softmax")]
+//! struct SoftmaxArgs {
+//!     #[binding(0)]
+//!     input: Tensor,
+//!     #[binding(1)]
+//!     output: Tensor,
+//!     rows: u32,
+//!     scale: f32,
+//! }
+//! ```
+//!
+//! Fields with `#[binding(N)]` become storage buffer descriptor writes; every
+//! other field is packed into push constants in declaration order. The
+//! generated layout is checked against SPIR-V reflection when the pipeline
+//! is created (see `exo_vulkan_binding::kernel_args`).
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{Data, DeriveInput, Fields, LitInt, LitStr, parse_macro_input};
+
+#[proc_macro_derive(KernelArgs, attributes(kernel, binding))]
+pub fn derive_kernel_args(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let kernel_name = kernel_name(input)?;
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(syn::Error::new_spanned(input, "KernelArgs requires named fields")),
+        },
+        _ => return Err(syn::Error::new_spanned(input, "KernelArgs can only be derived for structs")),
+    };
+
+    let krate = quote!(::exo_vulkan_binding::kernel_args);
+    let mut slots = Vec::new();
+    let mut buffers = Vec::new();
+    let mut pushes = Vec::new();
+    let mut seen_bindings = Vec::new();
+
+    for field in fields {
+        let ident = field.ident.as_ref().expect("named field");
+        let name = ident.to_string();
+        let ty = &field.ty;
+
+        match binding(field)? {
+            Some((binding, span)) => {
+                if seen_bindings.contains(&binding) {
+                    return Err(syn::Error::new(span, format!("binding {} used more than once", binding)));
+                }
+                seen_bindings.push(binding);
+                slots.push(quote! {
+                    slots.push(#krate::ArgSlot {
+                        name: #name,
+                        kind: #krate::ArgKind::Buffer { binding: #binding },
+                    });
+                });
+                buffers.push(quote! {
+                    (#binding, #krate::BufferArg::buffer_range(&self.#ident))
+                });
+            }
+            None => {
+                slots.push(quote! {
+                    slots.push(#krate::ArgSlot {
+                        name: #name,
+                        kind: #krate::ArgKind::PushConstant {
+                            offset,
+                            size: <#ty as #krate::PushConstant>::SIZE,
+                        },
+                    });
+                    offset += <#ty as #krate::PushConstant>::SIZE;
+                });
+                pushes.push(quote! {
+                    #krate::PushConstant::write(&self.#ident, &mut bytes);
+                });
+            }
+        }
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #krate::KernelArgs for #ident #ty_generics #where_clause {
+            const KERNEL: &'static str = #kernel_name;
+
+            #[allow(unused_mut, unused_variables, unused_assignments)]
+            fn slots() -> ::std::vec::Vec<#krate::ArgSlot> {
+                let mut slots = ::std::vec::Vec::new();
+                let mut offset: u32 = 0;
+                #(#slots)*
+                slots
+            }
+
+            fn buffers(&self) -> ::std::vec::Vec<(u32, ::exo_vulkan_binding::memory::BufferRange)> {
+                ::std::vec![#(#buffers),*]
+            }
+
+            #[allow(unused_mut)]
+            fn push_constants(&self) -> ::std::vec::Vec<u8> {
+                let mut bytes = ::std::vec::Vec::new();
+                #(#pushes)*
+                bytes
+            }
+        }
+    })
+}
+
+/// Value of `#[kernel(name = "...")]`
+fn kernel_name(input: &DeriveInput) -> syn::Result<LitStr> {
+    let mut name = None;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("kernel")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                name = Some(meta.value()?.parse::<LitStr>()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `name = \"...\"`"))
+            }
+        })?;
+    }
+    name.ok_or_else(|| syn::Error::new_spanned(&input.ident, "missing #[kernel(name = \"...\")]"))
+}
+
+/// Value of `#[binding(N)]`, if present
+fn binding(field: &syn::Field) -> syn::Result<Option<(u32, proc_macro2::Span)>> {
+    let Some(attr) = field.attrs.iter().find(|a| a.path().is_ident("binding")) else {
+        return Ok(None);
+    };
+    let lit: LitInt = attr.parse_args()?;
+    Ok(Some((lit.base10_parse()?, lit.span())))
+}
@@ -0,0 +1,126 @@
+//! Compiles the GLSL kernels in `shaders/` to SPIR-V and embeds the ones
+//! enabled by cargo features, zstd-compressed (see `src/kernels.rs`).
+//!
+//! `glslc` is taken from `$GLSLC` or `PATH`. Without it the crate still
+//! builds, but no kernels are embedded and `kernels::spirv` returns `None`.
+
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// zstd level for embedded blobs; decompression speed barely depends on it
+const ZSTD_LEVEL: i32 = 19;
+
+/// One SPIR-V variant of a shader source
+struct Shader {
+    name: &'static str,
+    source: &'static str,
+    defines: &'static [&'static str],
+    feature: &'static str,
+}
+
+const SHADERS: &[Shader] = &[
+    Shader { name: "reduce_pass1", source: "reduce.comp", defines: &["PASS=1"], feature: "kernels-core" },
+    Shader { name: "reduce_pass2", source: "reduce.comp", defines: &["PASS=2"], feature: "kernels-core" },
+    Shader { name: "permute_tiled", source: "permute.comp", defines: &["TILED=1"], feature: "kernels-core" },
+    Shader { name: "permute_general", source: "permute.comp", defines: &["TILED=0"], feature: "kernels-core" },
+    Shader { name: "penalty_count", source: "penalties.comp", defines: &["PASS=1"], feature: "kernels-core" },
+    Shader { name: "penalty_apply", source: "penalties.comp", defines: &["PASS=2"], feature: "kernels-core" },
+    Shader { name: "stop_sequences", source: "stop_sequences.comp", defines: &[], feature: "kernels-core" },
+    Shader { name: "dequantize_int8", source: "dequantize_int8.comp", defines: &[], feature: "kernels-core" },
+    Shader { name: "unpack_f16", source: "unpack_f16.comp", defines: &[], feature: "kernels-core" },
+    Shader { name: "embedding_score", source: "embedding_search.comp", defines: &["PASS=1"], feature: "kernels-core" },
+    Shader { name: "embedding_top_k", source: "embedding_search.comp", defines: &["PASS=2"], feature: "kernels-core" },
+    Shader { name: "embedding_score_int8", source: "embedding_quantized.comp", defines: &["BINARY=0"], feature: "kernels-core" },
+    Shader { name: "embedding_score_binary", source: "embedding_quantized.comp", defines: &["BINARY=1"], feature: "kernels-core" },
+    Shader { name: "elementwise_add", source: "elementwise.comp", defines: &["OP=0"], feature: "kernels-core" },
+    Shader { name: "elementwise_mul", source: "elementwise.comp", defines: &["OP=1"], feature: "kernels-core" },
+    Shader { name: "silu", source: "elementwise.comp", defines: &["OP=2"], feature: "kernels-core" },
+    Shader { name: "gelu", source: "elementwise.comp", defines: &["OP=3"], feature: "kernels-core" },
+    Shader { name: "axpy_f32", source: "axpy.comp", defines: &["INT8=0"], feature: "kernels-core" },
+    Shader { name: "axpy_int8", source: "axpy.comp", defines: &["INT8=1"], feature: "kernels-core" },
+    Shader { name: "softmax", source: "row_norm.comp", defines: &["RMS=0"], feature: "kernels-core" },
+    Shader { name: "rms_norm", source: "row_norm.comp", defines: &["RMS=1"], feature: "kernels-core" },
+    Shader { name: "cross_entropy", source: "cross_entropy.comp", defines: &["PASS=1"], feature: "kernels-core" },
+    Shader { name: "cross_entropy_sum", source: "cross_entropy.comp", defines: &["PASS=2"], feature: "kernels-core" },
+    Shader { name: "speculative_argmax", source: "speculative_accept.comp", defines: &["PASS=1"], feature: "kernels-core" },
+    Shader { name: "speculative_accept", source: "speculative_accept.comp", defines: &["PASS=2"], feature: "kernels-core" },
+    Shader { name: "gemm_f32", source: "gemm.comp", defines: &["F16=0"], feature: "kernels-core" },
+    Shader { name: "gemm_f16", source: "gemm.comp", defines: &["F16=1"], feature: "kernels-core" },
+    Shader { name: "gemm_block_sparse", source: "gemm_sparse.comp", defines: &["FORMAT=0"], feature: "kernels-core" },
+    Shader { name: "gemm_sparse_2_4", source: "gemm_sparse.comp", defines: &["FORMAT=1"], feature: "kernels-core" },
+    Shader { name: "mel_power", source: "mel_spectrogram.comp", defines: &["PASS=1"], feature: "kernels-media" },
+    Shader { name: "mel_filter", source: "mel_spectrogram.comp", defines: &["PASS=2"], feature: "kernels-media" },
+    Shader { name: "image_planar", source: "image_preprocess.comp", defines: &["PATCHIFY=0"], feature: "kernels-media" },
+    Shader { name: "image_patches", source: "image_preprocess.comp", defines: &["PATCHIFY=1"], feature: "kernels-media" },
+    Shader { name: "check_finite", source: "check_finite.comp", defines: &[], feature: "validation" },
+];
+
+fn feature_enabled(feature: &str) -> bool {
+    let var = format!("CARGO_FEATURE_{}", feature.to_uppercase().replace('-', "_"));
+    env::var_os(var).is_some()
+}
+
+/// Compile `shader` into `out_dir`; `Ok(None)` if glslc is not installed
+fn compile(glslc: &Path, shader: &Shader, out_dir: &Path) -> Result<Option<PathBuf>, String> {
+    let output = out_dir.join(format!("{}.spv", shader.name));
+    let mut cmd = Command::new(glslc);
+    cmd.arg("-fshader-stage=compute").arg("-O");
+    for define in shader.defines {
+        cmd.arg(format!("-D{}", define));
+    }
+    cmd.arg(Path::new("shaders").join(shader.source)).arg("-o").arg(&output);
+
+    let result = match cmd.output() {
+        Ok(result) => result,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.to_string()),
+    };
+    if !result.status.success() {
+        return Err(String::from_utf8_lossy(&result.stderr).into_owned());
+    }
+    Ok(Some(output))
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=shaders");
+    println!("cargo:rerun-if-env-changed=GLSLC");
+
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").expect("OUT_DIR is set by cargo"));
+    let glslc = env::var_os("GLSLC").map_or_else(|| PathBuf::from("glslc"), PathBuf::from);
+
+    let mut entries = Vec::new();
+    for shader in SHADERS.iter().filter(|s| feature_enabled(s.feature)) {
+        match compile(&glslc, shader, &out_dir) {
+            Ok(Some(path)) => {
+                let spirv = fs::read(&path).expect("read compiled SPIR-V");
+                let compressed = zstd::bulk::compress(&spirv, ZSTD_LEVEL).expect("compress SPIR-V");
+                let compressed_path = path.with_extension("spv.zst");
+                fs::write(&compressed_path, compressed).expect("write compressed SPIR-V");
+                entries.push(format!(
+                    "    EmbeddedKernel::new({:?}, {:?}, include_bytes!({:?}), {}),",
+                    shader.name,
+                    shader.feature,
+                    compressed_path,
+                    spirv.len()
+                ));
+            }
+            Ok(None) => {
+                println!("cargo:warning=glslc not found; building without embedded kernels");
+                break;
+            }
+            Err(e) => panic!("failed to compile {}: {}", shader.name, e),
+        }
+    }
+
+    // A static array rather than a slice: the entries' `OnceLock`s need a
+    // place of their own, not a promoted temporary
+    let table = format!(
+        "pub static EMBEDDED: [EmbeddedKernel; {}] = [\n{}\n];\n",
+        entries.len(),
+        entries.join("\n")
+    );
+    fs::write(out_dir.join("kernels.rs"), table).expect("write kernels.rs");
+}
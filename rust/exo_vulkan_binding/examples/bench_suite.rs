@@ -0,0 +1,30 @@
+//! Run the micro-op benchmark suite and print its JSON report
+//!
+//!     cargo run --release --example bench_suite -- [device-index] [op,op,...]
+//!
+//! Ops are those of `bench_suite::OPS`; all run by default.
+
+use std::error::Error;
+
+use exo_vulkan_binding::bench_suite::{SuiteConfig, bench_suite};
+use exo_vulkan_binding::initialize_vulkan;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = std::env::args().skip(1);
+    let index = match args.next() {
+        None => 0,
+        Some(arg) => arg.parse::<usize>().map_err(|e| format!("Invalid device index: {}", e))?,
+    };
+    let config = match args.next() {
+        None => SuiteConfig::default(),
+        Some(ops) => {
+            let ops: Vec<&str> = ops.split(',').map(str::trim).collect();
+            SuiteConfig::only(&ops)?
+        }
+    };
+
+    let context = initialize_vulkan()?;
+    let report = bench_suite(&context, index, &config)?;
+    println!("{}", report.to_json());
+    Ok(())
+}
@@ -0,0 +1,568 @@
+//! Compressed activation transfer between pipeline stages
+//!
+//! In pipeline-parallel inference over Wi-Fi, the activation crossing each
+//! stage boundary is sent once per token, and at phone-to-phone bandwidth
+//! the copy can cost more than the stage's compute. Encoding it as int8 cuts
+//! the payload to a quarter of f32. The sender encodes on the host (it
+//! already reads the activation back to send it); the receiver uploads the
+//! encoded bytes and expands them with one dispatch, so the f32 tensor is
+//! only ever materialized on the receiving device.
+//!
+//! Stages on different devices may compute at different precisions (a
+//! phone without 16-bit storage runs in f32). `negotiate` picks the wire
+//! format for a boundary from both stages' dtypes: the narrower of the two,
+//! which is exactly what the receiver can make use of, so the choice never
+//! costs precision. `BoundaryFormat` says which side converts.
+//!
+//! Two int8 schemes:
+//! - `Int8PerRow`: a symmetric scale per token row, computed from the data.
+//!   Needs no calibration and adapts to outlier tokens.
+//! - `Int8Calibrated`: per-channel scales learned offline by `calibration`,
+//!   shared by both ends, so no scales travel with the payload.
+//!
+//! Wire format (little endian): codec byte, rank byte, two reserved bytes,
+//! `rank` u32 dims, then the f32 or f16 elements, or for int8 codecs a u32
+//! count of `(scale, zero_point)` f32 pairs, the pairs, and the int8 bytes.
+//! f16 and int8 payloads are padded to 4 bytes. Calibrated params are not
+//! sent (count 0); the receiver supplies them.
+
+use ash::vk;
+use thiserror::Error;
+
+use crate::calibration::{QuantParams, QuantScheme};
+use crate::host_simd;
+use crate::memory::BufferRange;
+use crate::ops::{KernelBinding, MAX_GROUP_COUNT, OpsError};
+use crate::tensor::{ELEMENT_SIZE, Tensor};
+use crate::DeviceCapabilities;
+
+/// Threads per workgroup of the dequantize and f16 unpack kernels
+pub const DEQUANTIZE_WORKGROUP_SIZE: u32 = 256;
+
+const HEADER_BYTES: usize = 4;
+
+/// Codec-related errors
+#[derive(Error, Debug)]
+pub enum CodecError {
+    #[error("Activation of {actual} elements does not match shape {shape:?}")]
+    ShapeMismatch { shape: Vec<usize>, actual: usize },
+
+    #[error("Expected {expected} quantization params, got {actual}")]
+    ParamsMismatch { expected: usize, actual: usize },
+
+    #[error("Encoded activation truncated: needed {needed} bytes, got {actual}")]
+    Truncated { needed: usize, actual: usize },
+
+    #[error("Unknown activation codec {0}")]
+    UnknownCodec(u8),
+
+    #[error(transparent)]
+    Ops(#[from] OpsError),
+}
+
+pub type CodecResult<T> = Result<T, CodecError>;
+
+/// How a stage-boundary activation is sent
+#[derive(Clone, Debug, PartialEq)]
+pub enum ActivationCompression {
+    /// Raw f32
+    None,
+    /// IEEE half precision, rounded to nearest even
+    F16,
+    /// int8 with a symmetric scale per row
+    Int8PerRow,
+    /// int8 with calibrated per-channel (last axis) params
+    Int8Calibrated(Vec<QuantParams>),
+}
+
+/// Which elements share one set of quantization params
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Grouping {
+    PerRow,
+    PerColumn,
+}
+
+/// Payload of an encoded activation
+#[derive(Clone, Debug, PartialEq)]
+pub enum Payload {
+    F32(Vec<f32>),
+    /// f16 bit patterns
+    F16(Vec<u16>),
+    Int8 {
+        grouping: Grouping,
+        /// Empty for calibrated params, which are not sent
+        params: Vec<QuantParams>,
+        data: Vec<i8>,
+    },
+}
+
+/// An activation ready to send, or just received
+#[derive(Clone, Debug, PartialEq)]
+pub struct EncodedActivation {
+    pub shape: Vec<usize>,
+    pub payload: Payload,
+}
+
+/// Rows and columns of a channels-last activation
+fn rows_cols(shape: &[usize]) -> (usize, usize) {
+    let cols = shape.last().copied().unwrap_or(1);
+    let rows = shape[..shape.len().saturating_sub(1)].iter().product();
+    (rows, cols)
+}
+
+impl EncodedActivation {
+    /// Encode a contiguous channels-last activation
+    pub fn encode(data: &[f32], shape: &[usize], compression: &ActivationCompression) -> CodecResult<Self> {
+        let numel: usize = shape.iter().product();
+        if data.len() != numel {
+            return Err(CodecError::ShapeMismatch {
+                shape: shape.to_vec(),
+                actual: data.len(),
+            });
+        }
+        let (rows, cols) = rows_cols(shape);
+        let payload = match compression {
+            ActivationCompression::None => Payload::F32(data.to_vec()),
+            ActivationCompression::F16 => {
+                let mut halves = vec![0u16; data.len()];
+                host_simd::f32_to_f16_slice(data, &mut halves).expect("lengths match");
+                Payload::F16(halves)
+            }
+            ActivationCompression::Int8PerRow => {
+                let params: Vec<QuantParams> = (0..rows)
+                    .map(|r| {
+                        let row = &data[r * cols..(r + 1) * cols];
+                        let (min, max) = row
+                            .iter()
+                            .fold((0.0f32, 0.0f32), |(lo, hi), &x| (lo.min(x), hi.max(x)));
+                        QuantParams::from_range(min, max, QuantScheme::Symmetric)
+                    })
+                    .collect();
+                let data = data
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &x)| params[i / cols].quantize(x))
+                    .collect();
+                Payload::Int8 {
+                    grouping: Grouping::PerRow,
+                    params,
+                    data,
+                }
+            }
+            ActivationCompression::Int8Calibrated(params) => {
+                if params.len() != cols {
+                    return Err(CodecError::ParamsMismatch {
+                        expected: cols,
+                        actual: params.len(),
+                    });
+                }
+                Payload::Int8 {
+                    grouping: Grouping::PerColumn,
+                    params: Vec::new(),
+                    data: data
+                        .iter()
+                        .enumerate()
+                        .map(|(i, &x)| params[i % cols].quantize(x))
+                        .collect(),
+                }
+            }
+        };
+        Ok(Self {
+            shape: shape.to_vec(),
+            payload,
+        })
+    }
+
+    pub fn numel(&self) -> usize {
+        self.shape.iter().product()
+    }
+
+    /// Serialize for the wire
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let codec = match &self.payload {
+            Payload::F32(_) => 0,
+            Payload::F16(_) => 3,
+            Payload::Int8 {
+                grouping: Grouping::PerRow,
+                ..
+            } => 1,
+            Payload::Int8 {
+                grouping: Grouping::PerColumn,
+                ..
+            } => 2,
+        };
+        let mut bytes = vec![codec, self.shape.len() as u8, 0, 0];
+        for &dim in &self.shape {
+            bytes.extend_from_slice(&(dim as u32).to_le_bytes());
+        }
+        match &self.payload {
+            Payload::F32(data) => {
+                for x in data {
+                    bytes.extend_from_slice(&x.to_le_bytes());
+                }
+            }
+            Payload::F16(data) => {
+                for h in data {
+                    bytes.extend_from_slice(&h.to_le_bytes());
+                }
+                bytes.resize(bytes.len().next_multiple_of(4), 0);
+            }
+            Payload::Int8 { params, data, .. } => {
+                bytes.extend_from_slice(&(params.len() as u32).to_le_bytes());
+                for p in params {
+                    bytes.extend_from_slice(&p.scale.to_le_bytes());
+                    bytes.extend_from_slice(&(p.zero_point as f32).to_le_bytes());
+                }
+                bytes.extend(data.iter().map(|&q| q as u8));
+                bytes.resize(bytes.len().next_multiple_of(4), 0);
+            }
+        }
+        bytes
+    }
+
+    /// Parse bytes produced by `to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> CodecResult<Self> {
+        let mut reader = Reader { bytes, pos: 0 };
+        let header = reader.take(HEADER_BYTES)?;
+        let (codec, rank) = (header[0], header[1] as usize);
+        let shape = (0..rank)
+            .map(|_| reader.u32().map(|d| d as usize))
+            .collect::<CodecResult<Vec<_>>>()?;
+        let numel: usize = shape.iter().product();
+
+        let payload = match codec {
+            0 => Payload::F32(
+                (0..numel)
+                    .map(|_| reader.u32().map(f32::from_bits))
+                    .collect::<CodecResult<_>>()?,
+            ),
+            3 => Payload::F16(
+                reader
+                    .take(numel * 2)?
+                    .chunks_exact(2)
+                    .map(|b| u16::from_le_bytes([b[0], b[1]]))
+                    .collect(),
+            ),
+            1 | 2 => {
+                let count = reader.u32()? as usize;
+                let params = (0..count)
+                    .map(|_| {
+                        Ok(QuantParams {
+                            scale: f32::from_bits(reader.u32()?),
+                            zero_point: f32::from_bits(reader.u32()?) as i32,
+                        })
+                    })
+                    .collect::<CodecResult<Vec<_>>>()?;
+                let data = reader.take(numel)?.iter().map(|&b| b as i8).collect();
+                let grouping = if codec == 1 {
+                    Grouping::PerRow
+                } else {
+                    Grouping::PerColumn
+                };
+                Payload::Int8 {
+                    grouping,
+                    params,
+                    data,
+                }
+            }
+            other => return Err(CodecError::UnknownCodec(other)),
+        };
+        Ok(Self { shape, payload })
+    }
+
+    /// Quantization params the receiver uploads for `decompress`:
+    /// the sent ones, or `calibrated` when none were sent
+    pub fn params<'a>(&'a self, calibrated: &'a [QuantParams]) -> CodecResult<&'a [QuantParams]> {
+        let (rows, cols) = rows_cols(&self.shape);
+        let (grouping, sent) = match &self.payload {
+            Payload::F32(_) | Payload::F16(_) => return Ok(&[]),
+            Payload::Int8 { grouping, params, .. } => (grouping, params),
+        };
+        let (params, expected) = match grouping {
+            Grouping::PerRow => (sent.as_slice(), rows),
+            Grouping::PerColumn if sent.is_empty() => (calibrated, cols),
+            Grouping::PerColumn => (sent.as_slice(), cols),
+        };
+        if params.len() != expected {
+            return Err(CodecError::ParamsMismatch {
+                expected,
+                actual: params.len(),
+            });
+        }
+        Ok(params)
+    }
+
+    /// Host reference decode
+    pub fn decode_host(&self, calibrated: &[QuantParams]) -> CodecResult<Vec<f32>> {
+        let params = self.params(calibrated)?;
+        let cols = rows_cols(&self.shape).1;
+        Ok(match &self.payload {
+            Payload::F32(data) => data.clone(),
+            Payload::F16(data) => {
+                let mut values = vec![0.0f32; data.len()];
+                host_simd::f16_to_f32_slice(data, &mut values).expect("lengths match");
+                values
+            }
+            Payload::Int8 { grouping, data, .. } => data
+                .iter()
+                .enumerate()
+                .map(|(i, &q)| match grouping {
+                    Grouping::PerRow => params[i / cols].dequantize(q),
+                    Grouping::PerColumn => params[i % cols].dequantize(q),
+                })
+                .collect(),
+        })
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> CodecResult<&'a [u8]> {
+        let end = self.pos + len;
+        let slice = self.bytes.get(self.pos..end).ok_or(CodecError::Truncated {
+            needed: end,
+            actual: self.bytes.len(),
+        })?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u32(&mut self) -> CodecResult<u32> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+}
+
+/// `(scale, zero_point)` words to upload as the kernel's params buffer
+pub fn params_words(params: &[QuantParams]) -> Vec<f32> {
+    params
+        .iter()
+        .flat_map(|p| [p.scale, p.zero_point as f32])
+        .collect()
+}
+
+/// Scalar f16 conversions, kept here for existing callers
+pub use crate::host_simd::{f16_to_f32, f32_to_f16};
+
+/// Element type a stage computes and keeps activations in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum StageDtype {
+    F16,
+    F32,
+}
+
+impl StageDtype {
+    /// Widest dtype the device can keep activations in; f16 needs 16-bit
+    /// storage buffers
+    pub fn for_device(capabilities: &DeviceCapabilities) -> Self {
+        if capabilities.has_extension("VK_KHR_16bit_storage") {
+            Self::F16
+        } else {
+            Self::F32
+        }
+    }
+}
+
+/// How one stage boundary moves its activation
+#[derive(Clone, Debug, PartialEq)]
+pub struct BoundaryFormat {
+    pub compression: ActivationCompression,
+    /// The sender converts from its dtype before sending
+    pub convert_on_send: bool,
+    /// The receiver converts to its dtype after receiving
+    pub convert_on_receive: bool,
+}
+
+/// Agree on the wire format between a sender and receiver stage
+///
+/// Without an explicit int8 `requested`, the wire carries the narrower of
+/// the two dtypes: a wider stage's extra precision would be discarded by the
+/// other side anyway, so this is lossless relative to running both stages
+/// at the narrow dtype, and never sends more bytes than needed. An int8
+/// request is an explicit opt-in to lossy transfer and is kept.
+pub fn negotiate(sender: StageDtype, receiver: StageDtype, requested: &ActivationCompression) -> BoundaryFormat {
+    let wire = match requested {
+        ActivationCompression::Int8PerRow | ActivationCompression::Int8Calibrated(_) => None,
+        ActivationCompression::F16 => Some(StageDtype::F16),
+        ActivationCompression::None => Some(sender.min(receiver)),
+    };
+    let compression = match wire {
+        Some(StageDtype::F16) => ActivationCompression::F16,
+        Some(StageDtype::F32) => ActivationCompression::None,
+        None => requested.clone(),
+    };
+    BoundaryFormat {
+        compression,
+        convert_on_send: wire != Some(sender),
+        convert_on_receive: wire != Some(receiver),
+    }
+}
+
+/// Push constants of the dequantize and f16 unpack kernels (the unpack
+/// kernel only reads `count`)
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DequantizePushConstants {
+    pub count: u32,
+    pub cols: u32,
+    pub per_row: u32,
+    pub _pad: u32,
+}
+
+/// Receive-side conversion pipelines (`shaders/dequantize_int8.comp` and
+/// `shaders/unpack_f16.comp`)
+#[derive(Clone, Copy, Debug)]
+pub struct DecompressKernels {
+    pub int8: KernelBinding,
+    pub f16: KernelBinding,
+}
+
+/// Record expansion of an uploaded f16 or int8 payload into `output`
+///
+/// `packed` holds the payload bytes (padded to 4). For int8, `params` holds
+/// the words of `params_words(encoded.params(..))`; f16 needs none.
+/// `output` must be contiguous with the encoded shape.
+///
+/// # Safety Requirements
+/// - `cmd` must be in the recording state
+/// - The kernels' descriptor sets must not be in use by pending work
+/// - All buffers must stay alive until the command buffer completes
+pub unsafe fn decompress(
+    device: &ash::Device,
+    cmd: vk::CommandBuffer,
+    kernels: &DecompressKernels,
+    encoded: &EncodedActivation,
+    packed: &BufferRange,
+    params: Option<&BufferRange>,
+    output: &Tensor,
+) -> CodecResult<DequantizePushConstants> {
+    if output.layout.shape() != encoded.shape.as_slice() || !output.layout.is_contiguous() {
+        return Err(OpsError::ShapeMismatch(format!(
+            "output {:?} is not a contiguous {:?}",
+            output.layout.shape(),
+            encoded.shape
+        ))
+        .into());
+    }
+    let numel = encoded.numel();
+    let (rows, cols) = rows_cols(&encoded.shape);
+    let (payload_bytes, grouping) = match &encoded.payload {
+        Payload::F32(_) => {
+            return Err(OpsError::ShapeMismatch("f32 activations need no decompression".to_string()).into());
+        }
+        Payload::F16(_) => ((numel * 2).next_multiple_of(4) as u64, None),
+        Payload::Int8 { grouping, .. } => (numel.next_multiple_of(4) as u64, Some(*grouping)),
+    };
+    if packed.size < payload_bytes {
+        return Err(OpsError::ScratchTooSmall {
+            needed: payload_bytes,
+            available: packed.size,
+        }
+        .into());
+    }
+
+    let mut push = DequantizePushConstants {
+        count: numel as u32,
+        ..Default::default()
+    };
+    let kernel = match grouping {
+        None => &kernels.f16,
+        Some(grouping) => {
+            let groups = if grouping == Grouping::PerRow { rows } else { cols };
+            let params_bytes = groups as u64 * 2 * ELEMENT_SIZE;
+            let available = params.map_or(0, |p| p.size);
+            if available < params_bytes {
+                return Err(OpsError::ScratchTooSmall {
+                    needed: params_bytes,
+                    available,
+                }
+                .into());
+            }
+            push.cols = cols as u32;
+            push.per_row = u32::from(grouping == Grouping::PerRow);
+            &kernels.int8
+        }
+    };
+    let workgroups = push.count.div_ceil(DEQUANTIZE_WORKGROUP_SIZE).max(1);
+    // SAFETY: forwarded from the caller's guarantees
+    unsafe {
+        kernel.bind_buffers(device, packed, &output.range);
+        if let Some(params) = params.filter(|_| grouping.is_some()) {
+            kernel.bind_buffer(device, 2, params);
+        }
+        kernel.record(
+            device,
+            cmd,
+            &push,
+            [
+                workgroups.min(MAX_GROUP_COUNT),
+                workgroups.div_ceil(MAX_GROUP_COUNT),
+                1,
+            ],
+        );
+    }
+    Ok(push)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_per_row_round_trip() {
+        let data = [0.5, -1.0, 0.25, 100.0, -50.0, 25.0];
+        let encoded = EncodedActivation::encode(&data, &[2, 3], &ActivationCompression::Int8PerRow).unwrap();
+        let bytes = encoded.to_bytes();
+        // header, 2 dims, param count, 2 pairs, 6 bytes padded to 8
+        assert_eq!(bytes.len(), 4 + 8 + 4 + 16 + 8);
+
+        let decoded = EncodedActivation::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, encoded);
+        let values = decoded.decode_host(&[]).unwrap();
+        for (x, y) in data.iter().zip(&values) {
+            assert!((x - y).abs() <= x.abs() / 100.0 + 1e-6, "{} vs {}", x, y);
+        }
+        assert!(EncodedActivation::from_bytes(&bytes[..bytes.len() - 8]).is_err());
+    }
+
+    #[test]
+    fn test_calibrated_params_stay_local() {
+        let params = vec![QuantParams::from_range(-1.0, 1.0, QuantScheme::Symmetric); 2];
+        let compression = ActivationCompression::Int8Calibrated(params.clone());
+        let encoded = EncodedActivation::encode(&[1.0, -1.0, 0.0, 0.5], &[2, 2], &compression).unwrap();
+        let decoded = EncodedActivation::from_bytes(&encoded.to_bytes()).unwrap();
+
+        assert!(decoded.decode_host(&[]).is_err());
+        assert_eq!(decoded.decode_host(&params).unwrap()[..2], [1.0, -1.0]);
+        assert_eq!(params_words(&params).len(), 4);
+    }
+
+    #[test]
+    fn test_dtype_negotiation() {
+        let boundary = negotiate(StageDtype::F16, StageDtype::F32, &ActivationCompression::None);
+        assert_eq!(boundary.compression, ActivationCompression::F16);
+        assert!(!boundary.convert_on_send && boundary.convert_on_receive);
+
+        let boundary = negotiate(StageDtype::F32, StageDtype::F32, &ActivationCompression::None);
+        assert_eq!(boundary.compression, ActivationCompression::None);
+        assert!(!boundary.convert_on_send && !boundary.convert_on_receive);
+
+        let boundary = negotiate(StageDtype::F16, StageDtype::F16, &ActivationCompression::Int8PerRow);
+        assert!(boundary.convert_on_send && boundary.convert_on_receive);
+
+        for x in [0.0, -2.5, 65504.0, 6.0e-8, 1.0 / 3.0] {
+            let y = f16_to_f32(f32_to_f16(x));
+            // Relative bound for normals, one subnormal step (2^-24) below
+            assert!((x - y).abs() <= x.abs() / 1024.0 + 6.0e-8, "{} vs {}", x, y);
+        }
+        assert_eq!(f32_to_f16(1.0e6), 0x7c00);
+        assert_eq!(f32_to_f16(1.0 + 1.0 / 2048.0), 0x3c00);
+        let encoded = EncodedActivation::encode(&[1.5, -0.25, 3.0], &[3], &ActivationCompression::F16).unwrap();
+        let decoded = EncodedActivation::from_bytes(&encoded.to_bytes()).unwrap();
+        assert_eq!(decoded.decode_host(&[]).unwrap(), vec![1.5, -0.25, 3.0]);
+    }
+}
@@ -0,0 +1,220 @@
+//! Device memory bandwidth benchmark
+//!
+//! `DeviceInfo::bandwidth_gbps` feeds `NodeProfile::layer_time`, which the
+//! pipeline partitioner uses to split layers across nodes, but Vulkan has no
+//! query for it. `benchmark` measures it instead: it times copies between
+//! two device-local buffers and reports the bytes read plus written per
+//! second of the fastest run. Results are kept per device UUID for the
+//! process, and in the capability cache under `BENCHMARK_KEY` when one is
+//! given, so the benchmark runs once per device and driver. Until a device
+//! is benchmarked, enumeration reports `ESTIMATED_BANDWIDTH_GBPS`.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use ash::vk;
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::capability_cache::{CacheKey, CapabilityCache};
+use crate::device::{DeviceConfig, DeviceError, LogicalDevice};
+use crate::memory::{AllocationInfo, MemoryError, MemoryUsage};
+use crate::transfer::{DataTransfer, TransferError};
+use crate::{VulkanContext, VulkanError};
+
+/// Capability cache benchmark name of the result
+pub const BENCHMARK_KEY: &str = "copy_bandwidth_gbps";
+
+/// Bandwidth reported for devices not yet benchmarked
+pub const ESTIMATED_BANDWIDTH_GBPS: f32 = 32.0;
+
+/// Bandwidth benchmark errors
+#[derive(Error, Debug)]
+pub enum BandwidthError {
+    #[error(transparent)]
+    Context(#[from] VulkanError),
+
+    #[error(transparent)]
+    Device(#[from] DeviceError),
+
+    #[error(transparent)]
+    Memory(#[from] MemoryError),
+
+    #[error(transparent)]
+    Transfer(#[from] TransferError),
+
+    #[error("Copies finished too fast to time")]
+    TooFast,
+}
+
+pub type BandwidthResult<T> = Result<T, BandwidthError>;
+
+/// How much to copy, and how often
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BenchmarkConfig {
+    /// Bytes per copy, capped at 1/16 of the device's largest heap
+    pub bytes: u64,
+    /// Timed copies, after one untimed warm-up
+    pub iterations: u32,
+}
+
+impl Default for BenchmarkConfig {
+    fn default() -> Self {
+        Self {
+            bytes: 64 << 20,
+            iterations: 5,
+        }
+    }
+}
+
+lazy_static! {
+    static ref MEASURED: Mutex<HashMap<[u8; vk::UUID_SIZE], f32>> = Mutex::new(HashMap::new());
+}
+
+/// Bandwidth of the device with `device_uuid`, if benchmarked in this process
+pub fn measured(device_uuid: &[u8; vk::UUID_SIZE]) -> Option<f32> {
+    MEASURED.lock().get(device_uuid).copied()
+}
+
+/// Bandwidth of device `index` in GB/s, benchmarking it unless already known
+///
+/// Looks in the process-wide results, then `cache`, and only then runs
+/// `measure`, recording the result in both. Cache I/O failures are logged
+/// and otherwise ignored.
+pub fn benchmark(
+    context: &Arc<VulkanContext>,
+    index: usize,
+    cache: Option<&CapabilityCache>,
+) -> BandwidthResult<f32> {
+    let key = CacheKey {
+        device_uuid: context.get_device_uuid(index)?,
+        driver_version: context.get_device_properties(index)?.driver_version,
+    };
+    if let Some(gbps) = measured(&key.device_uuid) {
+        return Ok(gbps);
+    }
+
+    let mut record = cache
+        .and_then(|cache| {
+            cache.load(&key).unwrap_or_else(|e| {
+                log::warn!("Failed to read capability cache: {}", e);
+                None
+            })
+        })
+        .unwrap_or_default();
+    let gbps = match record.benchmarks.get(BENCHMARK_KEY) {
+        Some(&gbps) => gbps as f32,
+        None => {
+            let gbps = measure(context, index, BenchmarkConfig::default())?;
+            log::info!("Device {} copy bandwidth: {:.1} GB/s", index, gbps);
+            if let Some(cache) = cache {
+                record.benchmarks.insert(BENCHMARK_KEY.to_string(), f64::from(gbps));
+                if let Err(e) = cache.store(&key, &record) {
+                    log::warn!("Failed to write capability cache: {}", e);
+                }
+            }
+            gbps
+        }
+    };
+    MEASURED.lock().insert(key.device_uuid, gbps);
+    Ok(gbps)
+}
+
+/// Time device-to-device copies on device `index`
+///
+/// Creates its own logical device, so it can run before any other work
+/// on the device; takes a few hundred milliseconds on a phone.
+pub fn measure(context: &Arc<VulkanContext>, index: usize, config: BenchmarkConfig) -> BandwidthResult<f32> {
+    let largest_heap = context
+        .get_memory_properties(index)?
+        .memory_heaps_as_slice()
+        .iter()
+        .map(|heap| heap.size)
+        .max()
+        .unwrap_or(0);
+    let bytes = config.bytes.min(largest_heap / 16).max(1);
+
+    // Declared first so it is destroyed after everything created from it
+    let device = LogicalDevice::create(context, index, &DeviceConfig::default())?;
+    let pool = device.command_pool()?;
+    let transfer = device.data_transfer(&pool);
+    let mut allocator = device.memory_allocator();
+    let memory_type = allocator.memory_type_for(MemoryUsage::DeviceLocal)?;
+
+    let mut buffers = Vec::with_capacity(2);
+    let timed = (|| {
+        for _ in 0..2 {
+            buffers.push(allocator.allocate(bytes, memory_type, Uuid::new_v4().to_string())?);
+        }
+        let (src, dst) = (allocator.get_allocation(&buffers[0])?, allocator.get_allocation(&buffers[1])?);
+        // SAFETY: both allocations were just made on this device, are
+        // distinct, hold `bytes` and are used by nothing else
+        unsafe { time_copies(&transfer, src, dst, bytes, config.iterations) }
+    })();
+    // Freed on every path, so a failed run leaves nothing behind
+    for handle in &buffers {
+        if let Err(e) = allocator.deallocate(handle) {
+            log::warn!("Failed to free bandwidth buffer {}: {}", handle, e);
+        }
+    }
+    timed
+}
+
+/// GB/s of the fastest of `iterations` copies of `bytes`, after a warm-up
+///
+/// # Safety Requirements
+/// - transfer, src and dst must be of one device
+/// - src and dst must be distinct, hold `bytes` and be used by nothing else
+unsafe fn time_copies(
+    transfer: &DataTransfer,
+    src: &AllocationInfo,
+    dst: &AllocationInfo,
+    bytes: u64,
+    iterations: u32,
+) -> BandwidthResult<f32> {
+    let mut fastest = Duration::MAX;
+    for iteration in 0..=iterations {
+        let started = Instant::now();
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe { transfer.copy_device_to_device(src, dst, bytes) }?;
+        if iteration > 0 {
+            fastest = fastest.min(started.elapsed());
+        }
+    }
+    copy_gbps(bytes, fastest).ok_or(BandwidthError::TooFast)
+}
+
+/// GB/s of a copy of `bytes` taking `elapsed`, counting both the read and the write
+fn copy_gbps(bytes: u64, elapsed: Duration) -> Option<f32> {
+    let seconds = elapsed.as_secs_f64();
+    (seconds > 0.0 && elapsed != Duration::MAX).then(|| (2.0 * bytes as f64 / seconds / 1e9) as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copy_counts_read_and_write() {
+        assert_eq!(copy_gbps(500_000_000, Duration::from_secs(1)), Some(1.0));
+        assert_eq!(copy_gbps(1, Duration::ZERO), None);
+        assert_eq!(copy_gbps(1, Duration::MAX), None);
+    }
+
+    #[test]
+    fn test_measure_on_device() {
+        let Some(device) = crate::device::test_device() else {
+            return;
+        };
+        // Past MAX_SUBALLOCATION, so each buffer gets its own memory
+        let config = BenchmarkConfig {
+            bytes: 32 << 20,
+            iterations: 2,
+        };
+        let gbps = measure(device.context(), device.device_index(), config).unwrap();
+        assert!(gbps > 0.0);
+    }
+}
@@ -0,0 +1,780 @@
+//! Transformer micro-op benchmark suite
+//!
+//! The partition planner needs to know how long a layer takes on each node
+//! and the autotuner which ops are slow where, and neither can be derived
+//! from device properties. `bench_suite` times the ops a decoder layer is
+//! made of at the sizes 1B-13B models use:
+//!
+//! - GEMM: decode (one token) projections of 2048-5120 wide models with f16
+//!   weights, and prefill of 128 tokens with f32 weights
+//! - attention: one decode step of 32 heads over 512-4096 cached tokens
+//!   (scores GEMM, softmax, value GEMM)
+//! - dequant: int8 to f32 expansion
+//! - KV append: copying one token's keys and values into the cache
+//!
+//! Every case runs in a fresh command buffer on its own logical device and
+//! reports the fastest of `SuiteConfig::iterations` submissions after one
+//! warm-up, timed from submission to fence. Cases needing more than a
+//! quarter of the largest memory heap are skipped with the reason recorded,
+//! so the same suite runs on phones and desktops. `BenchReport::to_json` is
+//! the machine-readable form; `layer_time` feeds `NodeProfile::layer_time`
+//! and `store_in` keeps the timings in the capability cache.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use ash::vk;
+use serde_json::{Value, json};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::activation_codec::{DEQUANTIZE_WORKGROUP_SIZE, DequantizePushConstants};
+use crate::capability_cache::{CacheKey, CapabilityCache, CapabilityRecord};
+use crate::command::{CommandError, CommandPool, Fence, Queue};
+use crate::device::{DeviceConfig, DeviceError, LogicalDevice};
+use crate::kernel_library::{KernelRegistry, LibraryError, MatmulDims, wrapped_groups};
+use crate::memory::{AllocationSpec, BufferRange, MemoryAllocator, MemoryError, MemoryUsage};
+use crate::ops::compute_barrier;
+use crate::pipeline::{ComputePipeline, PipelineError};
+use crate::{VulkanContext, VulkanError};
+
+/// Capability cache benchmark names are this followed by the case name
+pub const CACHE_PREFIX: &str = "bench.";
+
+/// Longest one submission may take before the suite gives up on the device
+const CASE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Benchmark suite errors
+#[derive(Error, Debug)]
+pub enum BenchSuiteError {
+    #[error(transparent)]
+    Context(#[from] VulkanError),
+
+    #[error(transparent)]
+    Device(#[from] DeviceError),
+
+    #[error(transparent)]
+    Memory(#[from] MemoryError),
+
+    #[error(transparent)]
+    Command(#[from] CommandError),
+
+    #[error(transparent)]
+    Library(#[from] LibraryError),
+
+    #[error(transparent)]
+    Pipeline(#[from] PipelineError),
+
+    #[error("Unknown benchmark op {0:?}")]
+    UnknownOp(String),
+
+    #[error("Case {0} did not finish in time")]
+    Timeout(String),
+}
+
+pub type BenchSuiteResult<T> = Result<T, BenchSuiteError>;
+
+/// One timed workload
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BenchCase {
+    /// `[m, k] x [k, n]` with f32 activations
+    Gemm { m: u32, k: u32, n: u32, f16_weights: bool },
+    /// One decode step: each head scores a query against `context` keys,
+    /// softmaxes and mixes `context` values
+    Attention { context: u32, heads: u32, head_dim: u32 },
+    /// int8 to f32 expansion of `elements` values
+    Dequant { elements: u32 },
+    /// `appends` tokens' keys and values copied into a `context`-token cache
+    KvAppend {
+        kv_heads: u32,
+        head_dim: u32,
+        context: u32,
+        appends: u32,
+    },
+}
+
+/// Ops `SuiteConfig::only` accepts
+pub const OPS: [&str; 4] = ["gemm", "attention", "dequant", "kv_append"];
+
+impl BenchCase {
+    pub fn op(&self) -> &'static str {
+        match self {
+            BenchCase::Gemm { .. } => "gemm",
+            BenchCase::Attention { .. } => "attention",
+            BenchCase::Dequant { .. } => "dequant",
+            BenchCase::KvAppend { .. } => "kv_append",
+        }
+    }
+
+    /// Stable name, e.g. `gemm_f16_1x4096x11008`
+    pub fn name(&self) -> String {
+        match *self {
+            BenchCase::Gemm { m, k, n, f16_weights } => {
+                format!("gemm_{}_{}x{}x{}", if f16_weights { "f16" } else { "f32" }, m, k, n)
+            }
+            BenchCase::Attention {
+                context,
+                heads,
+                head_dim,
+            } => format!("attention_{}x{}_ctx{}", heads, head_dim, context),
+            BenchCase::Dequant { elements } => format!("dequant_int8_{}", elements),
+            BenchCase::KvAppend {
+                kv_heads,
+                head_dim,
+                context,
+                appends,
+            } => format!("kv_append_{}x{}_ctx{}_x{}", kv_heads, head_dim, context, appends),
+        }
+    }
+
+    /// Operations `time` covers: the appends of a KV case, otherwise 1
+    pub fn ops(&self) -> u32 {
+        match *self {
+            BenchCase::KvAppend { appends, .. } => appends,
+            _ => 1,
+        }
+    }
+
+    /// Floating point operations per run; 0 for pure data movement
+    pub fn flops(&self) -> u64 {
+        match *self {
+            BenchCase::Gemm { m, k, n, .. } => 2 * u64::from(m) * u64::from(k) * u64::from(n),
+            BenchCase::Attention {
+                context,
+                heads,
+                head_dim,
+            } => 4 * u64::from(heads) * u64::from(context) * u64::from(head_dim),
+            BenchCase::Dequant { .. } | BenchCase::KvAppend { .. } => 0,
+        }
+    }
+
+    /// Bytes read and written per run
+    pub fn bytes(&self) -> u64 {
+        match *self {
+            BenchCase::KvAppend {
+                kv_heads,
+                head_dim,
+                appends,
+                ..
+            } => 2 * u64::from(appends) * kv_token_bytes(kv_heads, head_dim),
+            _ => self.buffer_sizes().iter().sum(),
+        }
+    }
+
+    /// Bytes of the weights a GEMM streams, which bound decode time
+    pub fn weight_bytes(&self) -> u64 {
+        match *self {
+            BenchCase::Gemm { k, n, f16_weights, .. } => {
+                u64::from(k) * u64::from(n) * if f16_weights { 2 } else { 4 }
+            }
+            _ => 0,
+        }
+    }
+
+    /// Device memory the case allocates
+    pub fn device_bytes(&self) -> u64 {
+        self.buffer_sizes().iter().sum()
+    }
+
+    /// Sizes of the buffers `record` takes, in order, each a multiple of 4
+    fn buffer_sizes(&self) -> Vec<u64> {
+        let f32s = |count: u64| count * 4;
+        match *self {
+            BenchCase::Gemm { m, k, n, .. } => {
+                let (m, k, n) = (u64::from(m), u64::from(k), u64::from(n));
+                vec![f32s(m * k), self.weight_bytes().next_multiple_of(4), f32s(m * n)]
+            }
+            BenchCase::Attention {
+                context,
+                heads,
+                head_dim,
+            } => {
+                let (l, h, d) = (u64::from(context), u64::from(heads), u64::from(head_dim));
+                vec![f32s(h * d), f32s(h * d * l), f32s(h * l * d), f32s(h * l), f32s(h * l), f32s(h * d)]
+            }
+            BenchCase::Dequant { elements } => {
+                let e = u64::from(elements);
+                vec![e.next_multiple_of(4), f32s(e), f32s(2)]
+            }
+            BenchCase::KvAppend {
+                kv_heads,
+                head_dim,
+                context,
+                ..
+            } => {
+                let token = kv_token_bytes(kv_heads, head_dim);
+                vec![2 * token, 2 * token * u64::from(context)]
+            }
+        }
+    }
+
+    /// Record one run into `cmd`
+    ///
+    /// # Safety Requirements
+    /// - `cmd` must be in the recording state
+    /// - `buffers` must match `buffer_sizes` and stay alive until `cmd` completes
+    unsafe fn record(
+        &self,
+        device: &ash::Device,
+        cmd: vk::CommandBuffer,
+        kernels: &mut Kernels,
+        buffers: &[BufferRange],
+    ) -> BenchSuiteResult<()> {
+        match *self {
+            BenchCase::Gemm { m, k, n, f16_weights } => {
+                let dims = MatmulDims { m, n, k };
+                let (a, b, out) = (&buffers[0], &buffers[1], &buffers[2]);
+                // SAFETY: forwarded from the caller's guarantees
+                unsafe {
+                    if f16_weights {
+                        kernels.registry.matmul_f16(cmd, a, b, out, dims)?;
+                    } else {
+                        kernels.registry.matmul(cmd, a, b, out, dims)?;
+                    }
+                }
+            }
+            BenchCase::Attention {
+                context,
+                heads,
+                head_dim,
+            } => {
+                let [q, keys, values, scores, probs, out] = buffers else {
+                    unreachable!("attention takes six buffers");
+                };
+                let (l, d) = (u64::from(context), u64::from(head_dim));
+                // Keys are stored transposed per head, [head_dim, context], so
+                // the scores are a plain GEMM
+                for h in 0..u64::from(heads) {
+                    let dims = MatmulDims {
+                        m: 1,
+                        n: context,
+                        k: head_dim,
+                    };
+                    // SAFETY: forwarded from the caller's guarantees
+                    unsafe {
+                        kernels.registry.matmul(
+                            cmd,
+                            &q.slice(h * d * 4, d * 4)?,
+                            &keys.slice(h * d * l * 4, d * l * 4)?,
+                            &scores.slice(h * l * 4, l * 4)?,
+                            dims,
+                        )?;
+                    }
+                }
+                // SAFETY: forwarded from the caller's guarantees
+                unsafe {
+                    compute_barrier(device, cmd);
+                    kernels.registry.softmax(cmd, scores, probs, heads, context)?;
+                    compute_barrier(device, cmd);
+                }
+                for h in 0..u64::from(heads) {
+                    let dims = MatmulDims {
+                        m: 1,
+                        n: head_dim,
+                        k: context,
+                    };
+                    // SAFETY: forwarded from the caller's guarantees
+                    unsafe {
+                        kernels.registry.matmul(
+                            cmd,
+                            &probs.slice(h * l * 4, l * 4)?,
+                            &values.slice(h * l * d * 4, l * d * 4)?,
+                            &out.slice(h * d * 4, d * 4)?,
+                            dims,
+                        )?;
+                    }
+                }
+            }
+            BenchCase::Dequant { elements } => {
+                let groups = wrapped_groups(elements.div_ceil(DEQUANTIZE_WORKGROUP_SIZE));
+                // One params pair shared by every element
+                let push = DequantizePushConstants {
+                    count: elements,
+                    cols: elements,
+                    per_row: 1,
+                    _pad: 0,
+                };
+                let pipeline = kernels.dequant(device)?;
+                let bound = [(0, &buffers[0]), (1, &buffers[1]), (2, &buffers[2])];
+                // SAFETY: forwarded from the caller's guarantees; the default
+                // set is only used by this single-dispatch command buffer
+                unsafe { pipeline.dispatch(cmd, &bound, &push, groups) }?;
+            }
+            BenchCase::KvAppend {
+                kv_heads,
+                head_dim,
+                context,
+                appends,
+            } => {
+                let (token, cache) = (&buffers[0], &buffers[1]);
+                let half = kv_token_bytes(kv_heads, head_dim);
+                // Keys fill the first half of the cache and values the second
+                let plane = half * u64::from(context);
+                let regions: Vec<vk::BufferCopy> = (0..u64::from(appends.min(context)))
+                    .flat_map(|i| {
+                        [(0, i * half), (half, plane + i * half)].map(|(src, dst)| {
+                            vk::BufferCopy::default()
+                                .src_offset(token.offset + src)
+                                .dst_offset(cache.offset + dst)
+                                .size(half)
+                        })
+                    })
+                    .collect();
+                // SAFETY: cmd is recording and every region lies inside its buffer
+                unsafe {
+                    for region in regions.chunks(2) {
+                        device.cmd_copy_buffer(cmd, token.buffer, cache.buffer, region);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Bytes of one token's keys (or values) across `kv_heads`
+fn kv_token_bytes(kv_heads: u32, head_dim: u32) -> u64 {
+    u64::from(kv_heads) * u64::from(head_dim) * 4
+}
+
+/// The cases of `SuiteConfig::default`
+pub fn default_cases() -> Vec<BenchCase> {
+    // (hidden, ffn) of 1B, 7B and 13B class models
+    const WIDTHS: [(u32, u32); 3] = [(2048, 8192), (4096, 11008), (5120, 13824)];
+    let mut cases = Vec::new();
+    for (hidden, ffn) in WIDTHS {
+        for n in [hidden, ffn] {
+            cases.push(BenchCase::Gemm {
+                m: 1,
+                k: hidden,
+                n,
+                f16_weights: true,
+            });
+        }
+    }
+    for hidden in [2048, 4096] {
+        cases.push(BenchCase::Gemm {
+            m: 128,
+            k: hidden,
+            n: hidden,
+            f16_weights: false,
+        });
+    }
+    for context in [512, 2048, 4096] {
+        cases.push(BenchCase::Attention {
+            context,
+            heads: 32,
+            head_dim: 128,
+        });
+    }
+    for elements in [1 << 20, 16 << 20] {
+        cases.push(BenchCase::Dequant { elements });
+    }
+    cases.push(BenchCase::KvAppend {
+        kv_heads: 32,
+        head_dim: 128,
+        context: 4096,
+        appends: 64,
+    });
+    cases
+}
+
+/// What to run
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SuiteConfig {
+    pub cases: Vec<BenchCase>,
+    /// Timed runs per case, after one untimed warm-up
+    pub iterations: u32,
+}
+
+impl Default for SuiteConfig {
+    fn default() -> Self {
+        Self {
+            cases: default_cases(),
+            iterations: 3,
+        }
+    }
+}
+
+impl SuiteConfig {
+    /// The default cases of the listed ops (see `OPS`)
+    pub fn only(ops: &[&str]) -> BenchSuiteResult<Self> {
+        if let Some(unknown) = ops.iter().find(|op| !OPS.contains(op)) {
+            return Err(BenchSuiteError::UnknownOp(unknown.to_string()));
+        }
+        let mut config = Self::default();
+        config.cases.retain(|case| ops.contains(&case.op()));
+        Ok(config)
+    }
+}
+
+/// Outcome of one case
+#[derive(Clone, Debug, PartialEq)]
+pub struct CaseResult {
+    pub case: BenchCase,
+    /// Fastest run, or why the case was skipped or failed
+    pub time: Result<Duration, String>,
+}
+
+impl CaseResult {
+    pub fn gflops(&self) -> Option<f64> {
+        let seconds = self.time.as_ref().ok()?.as_secs_f64();
+        (self.case.flops() > 0 && seconds > 0.0).then(|| self.case.flops() as f64 / seconds / 1e9)
+    }
+
+    pub fn gbps(&self) -> Option<f64> {
+        let seconds = self.time.as_ref().ok()?.as_secs_f64();
+        (seconds > 0.0).then(|| self.case.bytes() as f64 / seconds / 1e9)
+    }
+}
+
+/// Results of one device
+#[derive(Clone, Debug, PartialEq)]
+pub struct BenchReport {
+    pub device: String,
+    pub driver_version: u32,
+    pub results: Vec<CaseResult>,
+}
+
+impl BenchReport {
+    pub fn get(&self, name: &str) -> Option<&CaseResult> {
+        self.results.iter().find(|r| r.case.name() == name)
+    }
+
+    /// Rate at which decode GEMMs stream weights, in bytes per second
+    ///
+    /// The total over all `m = 1` cases that ran, so larger shapes weigh more.
+    pub fn decode_weight_rate(&self) -> Option<f64> {
+        let (bytes, seconds) = self
+            .results
+            .iter()
+            .filter(|r| matches!(r.case, BenchCase::Gemm { m: 1, .. }))
+            .filter_map(|r| Some((r.case.weight_bytes() as f64, r.time.as_ref().ok()?.as_secs_f64())))
+            .fold((0.0, 0.0), |(b, s), (bytes, seconds)| (b + bytes, s + seconds));
+        (seconds > 0.0).then(|| bytes / seconds)
+    }
+
+    /// Decode time of a layer with `layer_bytes` of weights at the measured
+    /// GEMM rate, for `NodeProfile::layer_time`
+    pub fn layer_time(&self, layer_bytes: u64) -> Option<Duration> {
+        self.decode_weight_rate()
+            .map(|rate| Duration::from_secs_f64(layer_bytes as f64 / rate))
+    }
+
+    /// Record every timing in `record.benchmarks` as `bench.<name>` microseconds
+    pub fn store_in(&self, record: &mut CapabilityRecord) {
+        for result in &self.results {
+            if let Ok(time) = result.time {
+                record
+                    .benchmarks
+                    .insert(format!("{}{}", CACHE_PREFIX, result.case.name()), time.as_secs_f64() * 1e6);
+            }
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let results: Vec<Value> = self
+            .results
+            .iter()
+            .map(|r| {
+                let mut entry = json!({
+                    "name": r.case.name(),
+                    "op": r.case.op(),
+                    "ops": r.case.ops(),
+                    "flops": r.case.flops(),
+                    "bytes": r.case.bytes(),
+                });
+                match &r.time {
+                    Ok(time) => {
+                        entry["time_us"] = json!(time.as_secs_f64() * 1e6);
+                        entry["us_per_op"] = json!(time.as_secs_f64() * 1e6 / f64::from(r.case.ops().max(1)));
+                        entry["gflops"] = json!(r.gflops());
+                        entry["gbps"] = json!(r.gbps());
+                    }
+                    Err(reason) => entry["error"] = json!(reason),
+                }
+                entry
+            })
+            .collect();
+        json!({
+            "device": self.device,
+            "driver_version": self.driver_version,
+            "decode_weight_gbps": self.decode_weight_rate().map(|rate| rate / 1e9),
+            "results": results,
+        })
+        .to_string()
+    }
+}
+
+/// Pipelines the cases dispatch
+struct Kernels {
+    registry: KernelRegistry,
+    dequant: Option<ComputePipeline>,
+}
+
+impl Kernels {
+    fn dequant(&mut self, device: &ash::Device) -> BenchSuiteResult<&ComputePipeline> {
+        if self.dequant.is_none() {
+            self.dequant = Some(ComputePipeline::from_embedded(device, "dequantize_int8", vk::PipelineCache::null())?);
+        }
+        Ok(self.dequant.as_ref().expect("built above"))
+    }
+}
+
+/// A device set up for timing
+///
+/// Fields drop in declaration order, so everything created from the device
+/// is declared before it.
+struct Bench {
+    kernels: Kernels,
+    allocator: MemoryAllocator,
+    fence: Fence,
+    cmd: vk::CommandBuffer,
+    queue: Queue,
+    pool: CommandPool,
+    device: LogicalDevice,
+}
+
+impl Bench {
+    fn new(context: &Arc<VulkanContext>, index: usize) -> BenchSuiteResult<Self> {
+        let device = LogicalDevice::create(context, index, &DeviceConfig::default())?;
+        let pool = device.command_pool()?;
+        let cmd = pool.allocate_buffers(1)?[0];
+        Ok(Self {
+            kernels: Kernels {
+                registry: KernelRegistry::new(device.device(), vk::PipelineCache::null()),
+                dequant: None,
+            },
+            allocator: device.memory_allocator(),
+            fence: Fence::new(device.device().clone(), false)?,
+            cmd,
+            queue: device.compute_queue(),
+            pool,
+            device,
+        })
+    }
+
+    /// Record with `record`, submit and wait; the time from submission to fence
+    fn submit(&mut self, name: &str, record: impl FnOnce(&mut Self) -> BenchSuiteResult<()>) -> BenchSuiteResult<Duration> {
+        self.pool.reset_buffer(self.cmd)?;
+        self.pool
+            .begin_recording(self.cmd, vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
+        let recorded = record(self);
+        self.pool.end_recording(self.cmd)?;
+        recorded?;
+
+        self.fence.reset()?;
+        let started = Instant::now();
+        self.queue.submit(&[self.cmd], None, None, Some(self.fence.raw()))?;
+        let finished = self.fence.wait(CASE_TIMEOUT.as_nanos() as u64)?;
+        let elapsed = started.elapsed();
+        self.kernels.registry.release_sets()?;
+        if !finished {
+            return Err(BenchSuiteError::Timeout(name.to_string()));
+        }
+        Ok(elapsed)
+    }
+
+    /// Fastest of `iterations` runs of `case`, on zeroed buffers
+    fn run(&mut self, case: &BenchCase, iterations: u32) -> BenchSuiteResult<Duration> {
+        let memory_type = self.allocator.memory_type_for(MemoryUsage::DeviceLocal)?;
+        let specs: Vec<AllocationSpec> = case
+            .buffer_sizes()
+            .into_iter()
+            .map(|size| AllocationSpec {
+                handle_id: Uuid::new_v4().to_string(),
+                size,
+                memory_type_index: memory_type,
+            })
+            .collect();
+        let handles = self.allocator.allocate_many(&specs, false)?;
+        let timed = self.time(case, &handles, iterations);
+        for handle in &handles {
+            if let Err(e) = self.allocator.deallocate(handle) {
+                log::warn!("Failed to free benchmark buffer: {}", e);
+            }
+        }
+        timed
+    }
+
+    fn time(&mut self, case: &BenchCase, handles: &[String], iterations: u32) -> BenchSuiteResult<Duration> {
+        let buffers = handles
+            .iter()
+            .map(|handle| Ok(self.allocator.get_allocation(handle)?.range()))
+            .collect::<BenchSuiteResult<Vec<_>>>()?;
+        let name = case.name();
+
+        // Zeroed inputs keep NaNs and denormals from skewing the timings
+        self.submit(&name, |bench| {
+            let device = bench.device.device();
+            // SAFETY: cmd is recording; the ranges are live, 4-byte aligned
+            // allocations of this device
+            unsafe {
+                for range in &buffers {
+                    device.cmd_fill_buffer(bench.cmd, range.buffer, range.offset, range.size, 0);
+                }
+            }
+            Ok(())
+        })?;
+
+        let mut fastest = Duration::MAX;
+        for iteration in 0..=iterations {
+            let elapsed = self.submit(&name, |bench| {
+                let device = bench.device.device();
+                // SAFETY: cmd is recording; the buffers match the case and
+                // outlive the submission, which `submit` waits for
+                unsafe { case.record(device, bench.cmd, &mut bench.kernels, &buffers) }
+            })?;
+            if iteration > 0 {
+                fastest = fastest.min(elapsed);
+            }
+        }
+        Ok(fastest)
+    }
+}
+
+/// Run `config` on device `index`
+///
+/// Takes from a few seconds on a desktop GPU to a minute on a phone. Cases
+/// that do not fit or fail are reported with their error; the suite only
+/// fails if the device cannot be set up.
+pub fn bench_suite(context: &Arc<VulkanContext>, index: usize, config: &SuiteConfig) -> BenchSuiteResult<BenchReport> {
+    let properties = *context.get_device_properties(index)?;
+    let largest_heap = context
+        .get_memory_properties(index)?
+        .memory_heaps_as_slice()
+        .iter()
+        .map(|heap| heap.size)
+        .max()
+        .unwrap_or(0);
+    let budget = largest_heap / 4;
+
+    let mut bench = Bench::new(context, index)?;
+    let results = config
+        .cases
+        .iter()
+        .map(|case| {
+            let time = if case.device_bytes() > budget {
+                Err(format!(
+                    "skipped: needs {} MiB, budget {} MiB",
+                    case.device_bytes() >> 20,
+                    budget >> 20
+                ))
+            } else {
+                bench.run(case, config.iterations.max(1)).map_err(|e| e.to_string())
+            };
+            match &time {
+                Ok(time) => log::info!("{}: {} us", case.name(), time.as_micros()),
+                Err(e) => log::warn!("{}: {}", case.name(), e),
+            }
+            CaseResult { case: *case, time }
+        })
+        .collect();
+
+    Ok(BenchReport {
+        device: properties
+            .device_name_as_c_str()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        driver_version: properties.driver_version,
+        results,
+    })
+}
+
+/// Merge `report` into device `index`'s record in `cache`
+///
+/// Cache I/O failures are logged and otherwise ignored, as in `bandwidth`.
+pub fn store_report(
+    context: &VulkanContext,
+    index: usize,
+    report: &BenchReport,
+    cache: &CapabilityCache,
+) -> BenchSuiteResult<()> {
+    let key = CacheKey {
+        device_uuid: context.get_device_uuid(index)?,
+        driver_version: context.get_device_properties(index)?.driver_version,
+    };
+    let mut record = cache
+        .load(&key)
+        .unwrap_or_else(|e| {
+            log::warn!("Failed to read capability cache: {}", e);
+            None
+        })
+        .unwrap_or_default();
+    report.store_in(&mut record);
+    if let Err(e) = cache.store(&key, &record) {
+        log::warn!("Failed to write capability cache: {}", e);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cases_describe_their_work() {
+        let gemm = BenchCase::Gemm {
+            m: 1,
+            k: 4096,
+            n: 11008,
+            f16_weights: true,
+        };
+        assert_eq!(gemm.name(), "gemm_f16_1x4096x11008");
+        assert_eq!(gemm.flops(), 2 * 4096 * 11008);
+        assert_eq!(gemm.weight_bytes(), 4096 * 11008 * 2);
+        assert_eq!(gemm.buffer_sizes(), vec![4096 * 4, 4096 * 11008 * 2, 11008 * 4]);
+
+        let append = BenchCase::KvAppend {
+            kv_heads: 8,
+            head_dim: 128,
+            context: 1024,
+            appends: 16,
+        };
+        assert_eq!(append.ops(), 16);
+        assert_eq!(append.bytes(), 2 * 16 * 8 * 128 * 4);
+        assert_eq!(append.device_bytes(), 2 * 8 * 128 * 4 * 1025);
+
+        let config = SuiteConfig::only(&["dequant", "kv_append"]).unwrap();
+        assert!(config.cases.iter().all(|c| matches!(c.op(), "dequant" | "kv_append")));
+        assert!(!config.cases.is_empty());
+        assert!(matches!(SuiteConfig::only(&["conv"]), Err(BenchSuiteError::UnknownOp(_))));
+    }
+
+    #[test]
+    fn test_report_feeds_planner_and_cache() {
+        let gemm = |n, micros| CaseResult {
+            case: BenchCase::Gemm {
+                m: 1,
+                k: 1000,
+                n,
+                f16_weights: true,
+            },
+            time: Ok(Duration::from_micros(micros)),
+        };
+        let report = BenchReport {
+            device: "Test GPU".to_string(),
+            driver_version: 1,
+            results: vec![
+                gemm(1000, 100),
+                gemm(3000, 300),
+                CaseResult {
+                    case: BenchCase::Dequant { elements: 1 << 30 },
+                    time: Err("skipped".to_string()),
+                },
+            ],
+        };
+        // 8 MB of f16 weights in 400 us: 20 GB/s
+        assert_eq!(report.decode_weight_rate(), Some(2e10));
+        assert_eq!(report.layer_time(2_000_000), Some(Duration::from_micros(100)));
+
+        let mut record = CapabilityRecord::default();
+        report.store_in(&mut record);
+        assert_eq!(record.benchmarks.get("bench.gemm_f16_1x1000x1000"), Some(&100.0));
+        assert_eq!(record.benchmarks.len(), 2);
+
+        let json: Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["results"][0]["gflops"], json!(20.0));
+        assert_eq!(json["results"][2]["error"], "skipped");
+    }
+}
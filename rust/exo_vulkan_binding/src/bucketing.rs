@@ -0,0 +1,204 @@
+//! Sequence length bucketing
+//!
+//! Plans, pipelines and descriptor sets are reused only when shapes repeat,
+//! and prompt lengths rarely do. The graph compiler therefore pads each
+//! dynamic sequence length up to a bucket chosen by a `BucketPolicy`:
+//! coarser buckets mean more reuse but more wasted work on padding. Every
+//! padded length is recorded in `PaddingStats`, so the waste a policy costs
+//! on real traffic can be read back and the policy tuned.
+//!
+//! The process-wide policy starts as powers of two from `DEFAULT_MIN_BUCKET`.
+
+use std::collections::BTreeMap;
+
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use serde::Serialize;
+use thiserror::Error;
+
+/// Bucketing errors
+#[derive(Error, Debug)]
+pub enum BucketError {
+    #[error("Invalid bucket policy: {0}")]
+    InvalidPolicy(String),
+}
+
+pub type BucketResult<T> = Result<T, BucketError>;
+
+/// Smallest power-of-two bucket of the default policy
+pub const DEFAULT_MIN_BUCKET: u32 = 16;
+
+/// How sequence lengths are padded
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BucketPolicy {
+    /// No padding
+    Exact,
+    /// Next power of two, at least `min`
+    PowerOfTwo { min: u32 },
+    /// Next multiple of the step
+    Step(u32),
+}
+
+impl Default for BucketPolicy {
+    fn default() -> Self {
+        BucketPolicy::PowerOfTwo {
+            min: DEFAULT_MIN_BUCKET,
+        }
+    }
+}
+
+impl BucketPolicy {
+    /// Parse `exact`, `pow2`, `pow2:<min>` or `step:<n>`
+    pub fn parse(spec: &str) -> BucketResult<Self> {
+        let invalid = || BucketError::InvalidPolicy(spec.to_string());
+        let (kind, arg) = match spec.split_once(':') {
+            Some((kind, arg)) => (kind, Some(arg.parse::<u32>().map_err(|_| invalid())?)),
+            None => (spec, None),
+        };
+        match (kind, arg) {
+            ("exact", None) => Ok(BucketPolicy::Exact),
+            ("pow2", min) => Ok(BucketPolicy::PowerOfTwo {
+                min: min.unwrap_or(DEFAULT_MIN_BUCKET).max(1),
+            }),
+            ("step", Some(step)) if step > 0 => Ok(BucketPolicy::Step(step)),
+            _ => Err(invalid()),
+        }
+    }
+
+    /// The policy in `parse` syntax
+    pub fn name(self) -> String {
+        match self {
+            BucketPolicy::Exact => "exact".to_string(),
+            BucketPolicy::PowerOfTwo { min } => format!("pow2:{}", min),
+            BucketPolicy::Step(step) => format!("step:{}", step),
+        }
+    }
+
+    /// Bucket `len` is padded to; lengths past the largest bucket stay exact
+    pub fn pad(self, len: u32) -> u32 {
+        let padded = match self {
+            BucketPolicy::Exact => Some(len),
+            BucketPolicy::PowerOfTwo { min } => len.max(min).checked_next_power_of_two(),
+            BucketPolicy::Step(step) => len.div_ceil(step).checked_mul(step),
+        };
+        padded.unwrap_or(len).max(len)
+    }
+}
+
+/// Padding recorded by the graph compiler
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PaddingStats {
+    /// Plans compiled
+    pub plans: u64,
+    /// Real tokens, summed over plans
+    pub tokens: u64,
+    /// Padded tokens, summed over plans
+    pub padded_tokens: u64,
+    /// Plans per bucket
+    pub buckets: BTreeMap<u32, u64>,
+}
+
+impl PaddingStats {
+    /// Count one plan of `len` tokens padded to `padded`
+    pub fn record(&mut self, len: u32, padded: u32) {
+        self.plans += 1;
+        self.tokens += u64::from(len);
+        self.padded_tokens += u64::from(padded);
+        *self.buckets.entry(padded).or_insert(0) += 1;
+    }
+
+    /// Fraction of padded tokens that are padding
+    pub fn waste(&self) -> f64 {
+        if self.padded_tokens == 0 {
+            0.0
+        } else {
+            (self.padded_tokens - self.tokens) as f64 / self.padded_tokens as f64
+        }
+    }
+
+    /// JSON object for the JNI layer
+    pub fn to_json(&self) -> String {
+        let json = StatsJson {
+            policy: policy().name(),
+            plans: self.plans,
+            tokens: self.tokens,
+            padded_tokens: self.padded_tokens,
+            waste: self.waste(),
+            buckets: &self.buckets,
+        };
+        serde_json::to_string(&json).expect("padding stats always serialize")
+    }
+}
+
+/// Wire form of `PaddingStats`, with the policy they were gathered under
+#[derive(Serialize)]
+struct StatsJson<'a> {
+    policy: String,
+    plans: u64,
+    tokens: u64,
+    padded_tokens: u64,
+    waste: f64,
+    buckets: &'a BTreeMap<u32, u64>,
+}
+
+lazy_static! {
+    static ref POLICY: Mutex<BucketPolicy> = Mutex::new(BucketPolicy::default());
+    static ref STATS: Mutex<PaddingStats> = Mutex::new(PaddingStats::default());
+}
+
+/// The process-wide policy
+pub fn policy() -> BucketPolicy {
+    *POLICY.lock()
+}
+
+/// Replace the process-wide policy and clear the padding stats gathered under the old one
+pub fn set_policy(policy: BucketPolicy) {
+    *POLICY.lock() = policy;
+    *STATS.lock() = PaddingStats::default();
+    log::info!("Sequence length bucket policy: {}", policy.name());
+}
+
+/// Pad `len` with the process-wide policy and record it
+pub fn pad_and_record(len: u32) -> u32 {
+    let padded = policy().pad(len);
+    STATS.lock().record(len, padded);
+    padded
+}
+
+/// Padding recorded since the policy was last set
+pub fn stats() -> PaddingStats {
+    STATS.lock().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policies_pad() {
+        assert_eq!(BucketPolicy::Exact.pad(37), 37);
+        assert_eq!(BucketPolicy::PowerOfTwo { min: 16 }.pad(3), 16);
+        assert_eq!(BucketPolicy::PowerOfTwo { min: 16 }.pad(37), 64);
+        assert_eq!(BucketPolicy::PowerOfTwo { min: 16 }.pad(u32::MAX), u32::MAX);
+        assert_eq!(BucketPolicy::Step(32).pad(37), 64);
+        assert_eq!(BucketPolicy::Step(32).pad(64), 64);
+        assert_eq!(BucketPolicy::Step(32).pad(u32::MAX), u32::MAX);
+
+        assert_eq!(BucketPolicy::parse("step:32").unwrap(), BucketPolicy::Step(32));
+        assert_eq!(BucketPolicy::parse("pow2").unwrap(), BucketPolicy::default());
+        assert_eq!(BucketPolicy::parse("pow2:8").unwrap().name(), "pow2:8");
+        assert!(BucketPolicy::parse("step:0").is_err());
+        assert!(BucketPolicy::parse("exact:4").is_err());
+    }
+
+    #[test]
+    fn test_padding_waste() {
+        let mut stats = PaddingStats::default();
+        stats.record(48, 64);
+        stats.record(64, 64);
+        assert_eq!(stats.plans, 2);
+        assert_eq!(stats.buckets[&64], 2);
+        assert!((stats.waste() - 16.0 / 128.0).abs() < 1e-12);
+        assert!(stats.to_json().contains("\"buckets\":{\"64\":2}"));
+    }
+}
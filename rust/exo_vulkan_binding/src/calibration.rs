@@ -0,0 +1,287 @@
+//! Activation calibration for int8 quantization
+//!
+//! Calibration runs sample prompts through the float model and records the
+//! per-channel min/max of selected activations. The reduction happens on
+//! device (`ops::reduce` over every non-channel axis into a small stats
+//! buffer), so only `2 * channels` floats per activation come back per
+//! prompt. The accumulated ranges produce int8 scales for W8A8 paths without
+//! any desktop tooling.
+
+use std::collections::BTreeMap;
+
+use ash::vk;
+use thiserror::Error;
+
+use crate::memory::BufferRange;
+use crate::ops::{self, OpsError, ReduceKernels, ReduceOp};
+use crate::tensor::{ELEMENT_SIZE, Layout, Tensor};
+
+/// Calibration-related errors
+#[derive(Error, Debug)]
+pub enum CalibrationError {
+    #[error("Activation '{name}' has {actual} channels, previously {expected}")]
+    ChannelMismatch {
+        name: String,
+        expected: usize,
+        actual: usize,
+    },
+
+    #[error("Stats readback is {actual} bytes, expected {expected}")]
+    BadReadback { expected: usize, actual: usize },
+
+    #[error("Activation must be contiguous, channels last, with rank >= 2")]
+    BadLayout,
+
+    #[error(transparent)]
+    Ops(#[from] OpsError),
+}
+
+pub type CalibrationResult<T> = Result<T, CalibrationError>;
+
+/// How activation ranges map to int8
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuantScheme {
+    /// `q = round(x / scale)`, zero point 0, range [-127, 127]
+    Symmetric,
+    /// `q = round(x / scale) + zero_point`, range [-128, 127]
+    Asymmetric,
+}
+
+/// Quantization parameters for one channel
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QuantParams {
+    pub scale: f32,
+    pub zero_point: i32,
+}
+
+impl QuantParams {
+    /// Parameters covering `[min, max]` under `scheme`
+    pub fn from_range(min: f32, max: f32, scheme: QuantScheme) -> Self {
+        // Keep zero exactly representable and avoid a zero scale
+        let (min, max) = (min.min(0.0), max.max(0.0));
+        match scheme {
+            QuantScheme::Symmetric => Self {
+                scale: (min.abs().max(max) / 127.0).max(f32::MIN_POSITIVE),
+                zero_point: 0,
+            },
+            QuantScheme::Asymmetric => {
+                let scale = ((max - min) / 255.0).max(f32::MIN_POSITIVE);
+                Self {
+                    scale,
+                    zero_point: (-128.0 - min / scale).round().clamp(-128.0, 127.0) as i32,
+                }
+            }
+        }
+    }
+
+    pub fn quantize(&self, x: f32) -> i8 {
+        ((x / self.scale).round() as i32 + self.zero_point).clamp(-128, 127) as i8
+    }
+
+    pub fn dequantize(&self, q: i8) -> f32 {
+        (q as i32 - self.zero_point) as f32 * self.scale
+    }
+}
+
+/// Per-channel running range of one activation
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelStats {
+    pub min: Vec<f32>,
+    pub max: Vec<f32>,
+    /// Number of batches observed
+    pub batches: u64,
+}
+
+impl ChannelStats {
+    fn new(channels: usize) -> Self {
+        Self {
+            min: vec![f32::INFINITY; channels],
+            max: vec![f32::NEG_INFINITY; channels],
+            batches: 0,
+        }
+    }
+
+    pub fn channels(&self) -> usize {
+        self.min.len()
+    }
+
+    /// Quantization parameters per channel
+    pub fn params(&self, scheme: QuantScheme) -> Vec<QuantParams> {
+        self.min
+            .iter()
+            .zip(&self.max)
+            .map(|(&lo, &hi)| QuantParams::from_range(lo, hi, scheme))
+            .collect()
+    }
+}
+
+/// Accumulates activation ranges across calibration prompts
+#[derive(Clone, Debug, Default)]
+pub struct Calibrator {
+    stats: BTreeMap<String, ChannelStats>,
+}
+
+impl Calibrator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Merge one batch of per-channel ranges for activation `name`
+    pub fn observe(&mut self, name: &str, min: &[f32], max: &[f32]) -> CalibrationResult<()> {
+        let stats = self
+            .stats
+            .entry(name.to_string())
+            .or_insert_with(|| ChannelStats::new(min.len()));
+        for actual in [min.len(), max.len()] {
+            if actual != stats.channels() {
+                return Err(CalibrationError::ChannelMismatch {
+                    name: name.to_string(),
+                    expected: stats.channels(),
+                    actual,
+                });
+            }
+        }
+        for (acc, &v) in stats.min.iter_mut().zip(min) {
+            *acc = acc.min(v);
+        }
+        for (acc, &v) in stats.max.iter_mut().zip(max) {
+            *acc = acc.max(v);
+        }
+        stats.batches += 1;
+        Ok(())
+    }
+
+    /// Merge a stats buffer readback laid out as `[min; channels][max; channels]`
+    pub fn observe_readback(&mut self, name: &str, bytes: &[u8]) -> CalibrationResult<()> {
+        let expected = bytes.len() / 8 * 8;
+        if bytes.is_empty() || bytes.len() != expected {
+            return Err(CalibrationError::BadReadback {
+                expected,
+                actual: bytes.len(),
+            });
+        }
+        let values: Vec<f32> = bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        let (min, max) = values.split_at(values.len() / 2);
+        self.observe(name, min, max)
+    }
+
+    /// Reduce a host activation (channels last) and merge it
+    pub fn observe_host(&mut self, name: &str, data: &[f32], layout: &Layout) -> CalibrationResult<()> {
+        let layout = flatten_channels_last(layout)?;
+        let min = ops::reduce_host(data, &layout, 0, ReduceOp::Min)?;
+        let max = ops::reduce_host(data, &layout, 0, ReduceOp::Max)?;
+        self.observe(name, &min, &max)
+    }
+
+    pub fn stats(&self, name: &str) -> Option<&ChannelStats> {
+        self.stats.get(name)
+    }
+
+    /// Quantization parameters for every observed activation
+    pub fn params(&self, scheme: QuantScheme) -> BTreeMap<String, Vec<QuantParams>> {
+        self.stats
+            .iter()
+            .map(|(name, stats)| (name.clone(), stats.params(scheme)))
+            .collect()
+    }
+}
+
+/// View a channels-last activation as `[rows, channels]`
+fn flatten_channels_last(layout: &Layout) -> CalibrationResult<Layout> {
+    let rank = layout.rank();
+    if rank < 2 || !layout.is_contiguous() {
+        return Err(CalibrationError::BadLayout);
+    }
+    let channels = layout.shape()[rank - 1];
+    Ok(Layout::contiguous(&[layout.numel() / channels.max(1), channels]))
+}
+
+/// Bytes of the stats buffer for `channels` channels
+pub fn stats_buffer_bytes(channels: usize) -> u64 {
+    2 * channels as u64 * ELEMENT_SIZE
+}
+
+/// Reduction kernels for the min and max passes
+///
+/// Each needs its own descriptor sets: descriptor writes take effect
+/// immediately, so one set cannot serve two reductions in a command buffer.
+#[derive(Clone, Copy, Debug)]
+pub struct CalibrationKernels {
+    pub min: ReduceKernels,
+    pub max: ReduceKernels,
+}
+
+/// Record the per-channel min/max of a contiguous channels-last activation
+///
+/// Writes `[min; channels][max; channels]` into `stats`; read it back and
+/// pass it to `Calibrator::observe_readback`.
+///
+/// # Safety Requirements
+/// - Same as `ops::reduce`
+pub unsafe fn record_channel_stats(
+    device: &ash::Device,
+    cmd: vk::CommandBuffer,
+    kernels: &CalibrationKernels,
+    activation: &Tensor,
+    stats: &BufferRange,
+    scratch: Option<&BufferRange>,
+) -> CalibrationResult<()> {
+    let layout = flatten_channels_last(&activation.layout)?;
+    let channels = layout.shape()[1];
+    let input = Tensor {
+        range: activation.range,
+        layout,
+    };
+    let half = channels as u64 * ELEMENT_SIZE;
+    let outputs = [
+        (ReduceOp::Min, &kernels.min, stats.slice(0, half)),
+        (ReduceOp::Max, &kernels.max, stats.slice(half, half)),
+    ];
+    for (op, reduce_kernels, range) in outputs {
+        let range = range.map_err(|_| CalibrationError::BadReadback {
+            expected: stats_buffer_bytes(channels) as usize,
+            actual: stats.size as usize,
+        })?;
+        let output = Tensor::contiguous(range, &[channels]).map_err(OpsError::from)?;
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe {
+            ops::reduce(device, cmd, reduce_kernels, &input, 0, op, scratch, &output)?;
+            // The second reduction reuses the scratch buffer
+            ops::compute_barrier(device, cmd);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calibrator_accumulates_ranges() {
+        let mut calibrator = Calibrator::new();
+        let layout = Layout::contiguous(&[2, 2]);
+        calibrator.observe_host("mlp.in", &[1.0, -4.0, 3.0, 2.0], &layout).unwrap();
+        calibrator.observe_host("mlp.in", &[-2.0, 0.5, 0.0, 8.0], &layout).unwrap();
+
+        let stats = calibrator.stats("mlp.in").unwrap();
+        assert_eq!(stats.min, vec![-2.0, -4.0]);
+        assert_eq!(stats.max, vec![3.0, 8.0]);
+        assert_eq!(stats.batches, 2);
+        assert!(calibrator.observe("mlp.in", &[0.0], &[0.0]).is_err());
+    }
+
+    #[test]
+    fn test_quant_params_round_trip() {
+        let sym = QuantParams::from_range(-2.0, 1.0, QuantScheme::Symmetric);
+        assert_eq!(sym.quantize(-2.0), -127);
+        assert!((sym.dequantize(sym.quantize(0.5)) - 0.5).abs() <= sym.scale);
+
+        let asym = QuantParams::from_range(0.0, 6.0, QuantScheme::Asymmetric);
+        assert_eq!(asym.quantize(0.0), -128);
+        assert_eq!(asym.quantize(6.0), 127);
+    }
+}
@@ -0,0 +1,301 @@
+//! On-disk cache of per-device probe results
+//!
+//! Capability queries, kernel autotuning and benchmarks cost noticeable
+//! startup time, and their results only change when the hardware or the
+//! driver does. They are stored per device UUID together with the driver
+//! version that produced them; a record from a different driver version
+//! (Android OTA updates replace the GPU driver) is deleted on load, so stale
+//! autotune choices never outlive the driver they were measured on.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use ash::vk;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::kernel_select::{self, DeviceProfile};
+use crate::pipeline_cache::write_atomic;
+use crate::{VulkanContext, VulkanResult};
+
+/// Capability cache errors
+#[derive(Error, Debug)]
+pub enum CapabilityCacheError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Failed to encode record: {0}")]
+    Encode(#[from] serde_json::Error),
+}
+
+pub type CapabilityCacheResult<T> = Result<T, CapabilityCacheError>;
+
+/// Identifies the hardware and driver a record was measured on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CacheKey {
+    pub device_uuid: [u8; vk::UUID_SIZE],
+    pub driver_version: u32,
+}
+
+impl CacheKey {
+    fn file_name(&self) -> String {
+        let hex: String = self.device_uuid.iter().map(|b| format!("{:02x}", b)).collect();
+        format!("caps-{}.json", hex)
+    }
+}
+
+/// Everything cached for one device
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CapabilityRecord {
+    /// Driver version the record was measured on; set by `store`
+    pub driver_version: u32,
+    /// Rendered capability report
+    pub capabilities: Option<String>,
+    /// Best time per kernel, in microseconds
+    pub autotune_us: BTreeMap<String, u64>,
+    /// Named benchmark results, e.g. `copy_bandwidth_gbps`
+    pub benchmarks: BTreeMap<String, f64>,
+}
+
+impl CapabilityRecord {
+    /// Autotune results as durations
+    pub fn autotune(&self) -> impl Iterator<Item = (&str, Duration)> {
+        self.autotune_us
+            .iter()
+            .map(|(kernel, &us)| (kernel.as_str(), Duration::from_micros(us)))
+    }
+
+    pub fn set_autotune(&mut self, results: &[(&str, Duration)]) {
+        self.autotune_us = results
+            .iter()
+            .map(|&(kernel, time)| (kernel.to_string(), time.as_micros() as u64))
+            .collect();
+    }
+}
+
+/// Directory of per-device records
+#[derive(Clone, Debug)]
+pub struct CapabilityCache {
+    dir: PathBuf,
+}
+
+impl CapabilityCache {
+    pub fn new(dir: &Path) -> Self {
+        Self { dir: dir.to_path_buf() }
+    }
+
+    fn path(&self, key: &CacheKey) -> PathBuf {
+        self.dir.join(key.file_name())
+    }
+
+    /// The record for `key`, if one was stored under the same driver version
+    ///
+    /// Records from another driver version, and unreadable ones, are deleted.
+    pub fn load(&self, key: &CacheKey) -> CapabilityCacheResult<Option<CapabilityRecord>> {
+        let path = self.path(key);
+        let contents = match fs::read(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let discard = |reason: String| {
+            log::info!("Discarding capability cache {}: {}", path.display(), reason);
+            let _ = fs::remove_file(&path);
+            Ok(None)
+        };
+        match serde_json::from_slice::<CapabilityRecord>(&contents) {
+            Ok(record) if record.driver_version == key.driver_version => Ok(Some(record)),
+            Ok(record) => discard(format!(
+                "driver changed from {} to {}",
+                record.driver_version, key.driver_version
+            )),
+            Err(e) => discard(e.to_string()),
+        }
+    }
+
+    /// Replace the record for `key`
+    pub fn store(&self, key: &CacheKey, record: &CapabilityRecord) -> CapabilityCacheResult<()> {
+        let record = CapabilityRecord {
+            driver_version: key.driver_version,
+            ..record.clone()
+        };
+        fs::create_dir_all(&self.dir)?;
+        write_atomic(&self.path(key), &serde_json::to_vec(&record)?)?;
+        Ok(())
+    }
+
+    /// Delete the record for `key`, e.g. to force re-probing
+    pub fn invalidate(&self, key: &CacheKey) -> bool {
+        fs::remove_file(self.path(key)).is_ok()
+    }
+}
+
+/// Features a capability report lists, under their Vulkan names
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FeatureReport {
+    shader_int16: bool,
+    shader_int64: bool,
+    shader_float64: bool,
+    robust_buffer_access: bool,
+}
+
+/// Wire form of `capability_report`
+#[derive(Serialize)]
+struct CapabilityReport<'a> {
+    name: &'a str,
+    vendor_id: u32,
+    device_id: u32,
+    driver_version: u32,
+    api_version: String,
+    max_shared_memory: u32,
+    max_invocations: u32,
+    queue_families: usize,
+    features: FeatureReport,
+    extensions: &'a [String],
+}
+
+/// JSON capability report of device `index`
+pub fn capability_report(context: &VulkanContext, index: usize) -> VulkanResult<String> {
+    let properties = context.get_device_properties(index)?;
+    let profile = DeviceProfile::from_context(context, index)?;
+    let f = &profile.features;
+    let report = CapabilityReport {
+        name: &profile.name,
+        vendor_id: properties.vendor_id,
+        device_id: properties.device_id,
+        driver_version: properties.driver_version,
+        api_version: format!(
+            "{}.{}.{}",
+            vk::api_version_major(properties.api_version),
+            vk::api_version_minor(properties.api_version),
+            vk::api_version_patch(properties.api_version)
+        ),
+        max_shared_memory: profile.max_shared_memory,
+        max_invocations: profile.max_invocations,
+        queue_families: context.get_device_capabilities(index)?.queue_families.len(),
+        features: FeatureReport {
+            shader_int16: f.shader_int16 == vk::TRUE,
+            shader_int64: f.shader_int64 == vk::TRUE,
+            shader_float64: f.shader_float64 == vk::TRUE,
+            robust_buffer_access: f.robust_buffer_access == vk::TRUE,
+        },
+        extensions: &profile.extensions,
+    };
+    Ok(serde_json::to_string(&report).expect("capability reports always serialize"))
+}
+
+/// Capability report of device `index`, from `cache` when it holds a record
+/// for the current driver
+///
+/// Cached autotune results are restored into the kernel selection matrix,
+/// and the matrix's current results saved back, so timings survive restarts
+/// until the driver changes. Cache I/O failures are logged and otherwise
+/// ignored.
+///
+/// # Returns
+/// The report and whether it came from the cache
+pub fn device_capabilities(
+    context: &VulkanContext,
+    index: usize,
+    cache: Option<&CapabilityCache>,
+) -> VulkanResult<(String, bool)> {
+    let Some(cache) = cache else {
+        return Ok((capability_report(context, index)?, false));
+    };
+    let key = CacheKey {
+        device_uuid: context.get_device_uuid(index)?,
+        driver_version: context.get_device_properties(index)?.driver_version,
+    };
+    let device = DeviceProfile::from_context(context, index)?.name;
+
+    let cached = cache.load(&key).unwrap_or_else(|e| {
+        log::warn!("Failed to read capability cache: {}", e);
+        None
+    });
+    let (mut record, hit) = match cached {
+        Some(record) if record.capabilities.is_some() => (record, true),
+        _ => (
+            CapabilityRecord {
+                capabilities: Some(capability_report(context, index)?),
+                ..Default::default()
+            },
+            false,
+        ),
+    };
+
+    let mut matrix = kernel_select::global();
+    for (kernel, time) in record.autotune() {
+        matrix.restore_autotune(&device, kernel, time);
+    }
+    let before = record.clone();
+    record.set_autotune(&matrix.autotune_results(&device));
+    drop(matrix);
+    if (!hit || record != before)
+        && let Err(e) = cache.store(&key, &record)
+    {
+        log::warn!("Failed to write capability cache: {}", e);
+    }
+    Ok((record.capabilities.unwrap_or_default(), hit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(name: &str) -> CapabilityCache {
+        CapabilityCache::new(&std::env::temp_dir().join(format!("exo_{}_{}", name, std::process::id())))
+    }
+
+    #[test]
+    fn test_record_round_trips_for_same_driver() {
+        let cache = cache("caps_round_trip");
+        let key = CacheKey {
+            device_uuid: [3; 16],
+            driver_version: 7,
+        };
+        assert_eq!(cache.load(&key).unwrap(), None);
+
+        let mut record = CapabilityRecord {
+            capabilities: Some(r#"{"name":"Test GPU"}"#.to_string()),
+            ..Default::default()
+        };
+        record.set_autotune(&[("permute_tiled", Duration::from_micros(90))]);
+        record.benchmarks.insert("copy_bandwidth_gbps".to_string(), 12.5);
+        cache.store(&key, &record).unwrap();
+
+        let loaded = cache.load(&key).unwrap().unwrap();
+        assert_eq!(loaded.driver_version, 7);
+        assert_eq!(loaded.capabilities, record.capabilities);
+        assert_eq!(
+            loaded.autotune().collect::<Vec<_>>(),
+            vec![("permute_tiled", Duration::from_micros(90))]
+        );
+        assert!(cache.invalidate(&key));
+    }
+
+    #[test]
+    fn test_driver_update_invalidates_record() {
+        let cache = cache("caps_driver_update");
+        let key = CacheKey {
+            device_uuid: [4; 16],
+            driver_version: 1,
+        };
+        cache.store(&key, &CapabilityRecord::default()).unwrap();
+
+        let updated = CacheKey {
+            driver_version: 2,
+            ..key
+        };
+        assert_eq!(cache.load(&updated).unwrap(), None);
+        // Deleted, so the old driver's record is gone too
+        assert_eq!(cache.load(&key).unwrap(), None);
+
+        fs::write(cache.path(&key), b"{not json").unwrap();
+        assert_eq!(cache.load(&key).unwrap(), None);
+        assert!(!cache.path(&key).exists());
+    }
+}
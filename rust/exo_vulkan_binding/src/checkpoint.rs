@@ -0,0 +1,397 @@
+//! Backend state checkpoints
+//!
+//! Captures the logical state of the backend — loaded models, the tensor
+//! handles they own, KV cache shapes and session configuration — as a
+//! versioned binary blob. GPU memory contents are not included: after a
+//! backend restart the orchestrator replays the blob (reload models,
+//! re-create sessions, re-prefill caches) to reach an equivalent state.
+//!
+//! Encoding is deterministic: every list is sorted by its key before
+//! writing, so the same state always yields the same bytes and blobs can be
+//! compared or hashed directly.
+//!
+//! Layout (little-endian): magic `EXOS`, `u16` version, `u16` reserved, then
+//! the sections in order. Strings are a `u32` byte length followed by UTF-8.
+
+use std::collections::BTreeMap;
+
+use thiserror::Error;
+
+use crate::models::LoadedModel;
+
+/// Blob magic
+pub const STATE_MAGIC: [u8; 4] = *b"EXOS";
+
+/// Current blob version; readers reject newer versions
+pub const STATE_VERSION: u16 = 1;
+
+/// Checkpoint-related errors
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum CheckpointError {
+    #[error("Not a state blob")]
+    BadMagic,
+
+    #[error("Unsupported state version {0} (max {STATE_VERSION})")]
+    UnsupportedVersion(u16),
+
+    #[error("State blob truncated at byte {0}")]
+    Truncated(usize),
+
+    #[error("Invalid UTF-8 string at byte {0}")]
+    InvalidString(usize),
+
+    #[error("{0} trailing bytes after state")]
+    TrailingBytes(usize),
+}
+
+pub type CheckpointResult<T> = Result<T, CheckpointError>;
+
+/// A loaded model and its reservation
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ModelState {
+    pub model_id: String,
+    pub path: String,
+    pub quota_bytes: u64,
+    pub used_bytes: u64,
+}
+
+impl From<&LoadedModel> for ModelState {
+    fn from(model: &LoadedModel) -> Self {
+        Self {
+            model_id: model.model_id.clone(),
+            path: model.path.clone(),
+            quota_bytes: model.quota_bytes,
+            used_bytes: model.used_bytes,
+        }
+    }
+}
+
+/// A device allocation handle
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TensorState {
+    pub handle_id: String,
+    /// Namespace that owns the handle (see `registry`)
+    pub client: String,
+    pub device_id: String,
+    pub size_bytes: u64,
+    /// Model the allocation is charged to, if any
+    pub model_id: Option<String>,
+}
+
+/// Shape of a session's KV cache
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KvCacheState {
+    pub session_id: String,
+    pub model_id: String,
+    pub layers: u32,
+    /// Tokens currently cached; the orchestrator re-prefills this many
+    pub tokens: u32,
+    pub capacity_tokens: u32,
+}
+
+/// Per-session configuration, stored as opaque key/value pairs
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SessionState {
+    pub session_id: String,
+    pub model_id: String,
+    pub config: BTreeMap<String, String>,
+}
+
+/// Logical backend state
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BackendState {
+    pub capacity_bytes: u64,
+    pub models: Vec<ModelState>,
+    pub tensors: Vec<TensorState>,
+    pub kv_caches: Vec<KvCacheState>,
+    pub sessions: Vec<SessionState>,
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u32(&mut self, v: u32) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn u64(&mut self, v: u64) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn str(&mut self, s: &str) {
+        self.u32(s.len() as u32);
+        self.0.extend_from_slice(s.as_bytes());
+    }
+
+    fn len(&mut self, n: usize) {
+        self.u32(n as u32);
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> CheckpointResult<&[u8]> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or(CheckpointError::Truncated(self.pos))?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> CheckpointResult<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> CheckpointResult<u16> {
+        let b = self.take(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> CheckpointResult<u32> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn u64(&mut self) -> CheckpointResult<u64> {
+        let b = self.take(8)?;
+        let mut word = [0; 8];
+        word.copy_from_slice(b);
+        Ok(u64::from_le_bytes(word))
+    }
+
+    fn str(&mut self) -> CheckpointResult<String> {
+        let len = self.u32()? as usize;
+        let start = self.pos;
+        let b = self.take(len)?;
+        String::from_utf8(b.to_vec()).map_err(|_| CheckpointError::InvalidString(start))
+    }
+
+    /// Read a section length, rejecting counts the remaining bytes cannot hold
+    fn len(&mut self) -> CheckpointResult<usize> {
+        let at = self.pos;
+        let n = self.u32()? as usize;
+        if n > self.bytes.len() - self.pos {
+            return Err(CheckpointError::Truncated(at));
+        }
+        Ok(n)
+    }
+}
+
+impl BackendState {
+    /// Serialize into a versioned blob; equal states give equal bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut models: Vec<_> = self.models.iter().collect();
+        models.sort_by(|a, b| a.model_id.cmp(&b.model_id));
+        let mut tensors: Vec<_> = self.tensors.iter().collect();
+        tensors.sort_by(|a, b| a.handle_id.cmp(&b.handle_id));
+        let mut kv_caches: Vec<_> = self.kv_caches.iter().collect();
+        kv_caches.sort_by(|a, b| a.session_id.cmp(&b.session_id));
+        let mut sessions: Vec<_> = self.sessions.iter().collect();
+        sessions.sort_by(|a, b| a.session_id.cmp(&b.session_id));
+
+        let mut w = Writer(STATE_MAGIC.to_vec());
+        w.0.extend_from_slice(&STATE_VERSION.to_le_bytes());
+        w.0.extend_from_slice(&[0, 0]);
+        w.u64(self.capacity_bytes);
+
+        w.len(models.len());
+        for m in models {
+            w.str(&m.model_id);
+            w.str(&m.path);
+            w.u64(m.quota_bytes);
+            w.u64(m.used_bytes);
+        }
+
+        w.len(tensors.len());
+        for t in tensors {
+            w.str(&t.handle_id);
+            w.str(&t.client);
+            w.str(&t.device_id);
+            w.u64(t.size_bytes);
+            match &t.model_id {
+                Some(model_id) => {
+                    w.0.push(1);
+                    w.str(model_id);
+                }
+                None => w.0.push(0),
+            }
+        }
+
+        w.len(kv_caches.len());
+        for kv in kv_caches {
+            w.str(&kv.session_id);
+            w.str(&kv.model_id);
+            w.u32(kv.layers);
+            w.u32(kv.tokens);
+            w.u32(kv.capacity_tokens);
+        }
+
+        w.len(sessions.len());
+        for s in sessions {
+            w.str(&s.session_id);
+            w.str(&s.model_id);
+            w.len(s.config.len());
+            for (key, value) in &s.config {
+                w.str(key);
+                w.str(value);
+            }
+        }
+
+        w.0
+    }
+
+    /// Parse a blob written by `to_bytes` of this or an older version
+    pub fn from_bytes(bytes: &[u8]) -> CheckpointResult<Self> {
+        let mut r = Reader { bytes, pos: 0 };
+        if r.take(4).map_err(|_| CheckpointError::BadMagic)? != STATE_MAGIC {
+            return Err(CheckpointError::BadMagic);
+        }
+        let version = r.u16()?;
+        if version == 0 || version > STATE_VERSION {
+            return Err(CheckpointError::UnsupportedVersion(version));
+        }
+        r.u16()?;
+
+        let mut state = Self {
+            capacity_bytes: r.u64()?,
+            ..Self::default()
+        };
+
+        for _ in 0..r.len()? {
+            state.models.push(ModelState {
+                model_id: r.str()?,
+                path: r.str()?,
+                quota_bytes: r.u64()?,
+                used_bytes: r.u64()?,
+            });
+        }
+
+        for _ in 0..r.len()? {
+            state.tensors.push(TensorState {
+                handle_id: r.str()?,
+                client: r.str()?,
+                device_id: r.str()?,
+                size_bytes: r.u64()?,
+                model_id: match r.u8()? {
+                    0 => None,
+                    _ => Some(r.str()?),
+                },
+            });
+        }
+
+        for _ in 0..r.len()? {
+            state.kv_caches.push(KvCacheState {
+                session_id: r.str()?,
+                model_id: r.str()?,
+                layers: r.u32()?,
+                tokens: r.u32()?,
+                capacity_tokens: r.u32()?,
+            });
+        }
+
+        for _ in 0..r.len()? {
+            let mut session = SessionState {
+                session_id: r.str()?,
+                model_id: r.str()?,
+                config: BTreeMap::new(),
+            };
+            for _ in 0..r.len()? {
+                let key = r.str()?;
+                session.config.insert(key, r.str()?);
+            }
+            state.sessions.push(session);
+        }
+
+        match bytes.len() - r.pos {
+            0 => Ok(state),
+            extra => Err(CheckpointError::TrailingBytes(extra)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> BackendState {
+        let mut config = BTreeMap::new();
+        config.insert("temperature".to_string(), "0.7".to_string());
+        BackendState {
+            capacity_bytes: 1 << 30,
+            models: vec![
+                ModelState {
+                    model_id: "target".to_string(),
+                    path: "/m/target".to_string(),
+                    quota_bytes: 600,
+                    used_bytes: 128,
+                },
+                ModelState {
+                    model_id: "draft".to_string(),
+                    path: "/m/draft".to_string(),
+                    quota_bytes: 100,
+                    used_bytes: 0,
+                },
+            ],
+            tensors: vec![TensorState {
+                handle_id: "h1".to_string(),
+                client: "default".to_string(),
+                device_id: "vulkan:0".to_string(),
+                size_bytes: 128,
+                model_id: Some("target".to_string()),
+            }],
+            kv_caches: vec![KvCacheState {
+                session_id: "s".to_string(),
+                model_id: "target".to_string(),
+                layers: 32,
+                tokens: 17,
+                capacity_tokens: 4096,
+            }],
+            sessions: vec![SessionState {
+                session_id: "s".to_string(),
+                model_id: "target".to_string(),
+                config,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_round_trip_is_deterministic() {
+        let state = sample();
+        let bytes = state.to_bytes();
+        assert_eq!(&bytes[..4], b"EXOS");
+
+        let mut reordered = state.clone();
+        reordered.models.reverse();
+        assert_eq!(reordered.to_bytes(), bytes);
+
+        let decoded = BackendState::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.models[0].model_id, "draft");
+        assert_eq!(decoded.to_bytes(), bytes);
+        assert_eq!(decoded.sessions[0].config["temperature"], "0.7");
+    }
+
+    #[test]
+    fn test_rejects_bad_blobs() {
+        let bytes = sample().to_bytes();
+        assert_eq!(BackendState::from_bytes(b"nope"), Err(CheckpointError::BadMagic));
+        assert!(matches!(
+            BackendState::from_bytes(&bytes[..bytes.len() - 3]),
+            Err(CheckpointError::Truncated(_))
+        ));
+
+        let mut newer = bytes.clone();
+        newer[4] = 9;
+        assert_eq!(BackendState::from_bytes(&newer), Err(CheckpointError::UnsupportedVersion(9)));
+
+        let mut padded = bytes;
+        padded.push(0);
+        assert_eq!(BackendState::from_bytes(&padded), Err(CheckpointError::TrailingBytes(1)));
+    }
+}
@@ -0,0 +1,238 @@
+//! Chunk-level checksums for uploaded weights
+//!
+//! Some phones silently corrupt large DMA transfers under memory pressure,
+//! which only shows up later as gibberish output. The loader hashes each
+//! chunk (CRC32) as it uploads it; verification reads chunks back from the
+//! device — all of them, or a sample — and compares, naming the first bad
+//! chunk instead of letting corrupt weights reach inference.
+
+use thiserror::Error;
+
+use crate::events::{self, GpuEvent};
+use crate::memory::{AllocationInfo, MemoryError};
+use crate::transfer::{DataTransfer, TransferError};
+
+/// Checksum-related errors
+#[derive(Error, Debug)]
+pub enum ChecksumError {
+    #[error("Chunk {chunk} at offset {offset} corrupted: expected crc {expected:08x}, got {actual:08x}")]
+    Mismatch {
+        chunk: usize,
+        offset: u64,
+        expected: u32,
+        actual: u32,
+    },
+
+    #[error("Invalid chunk size: {0}")]
+    InvalidChunkSize(u64),
+
+    #[error("Data is {actual} bytes but checksums cover {expected}")]
+    LengthMismatch { expected: u64, actual: u64 },
+
+    #[error(transparent)]
+    Transfer(#[from] TransferError),
+
+    #[error(transparent)]
+    Memory(#[from] MemoryError),
+}
+
+pub type ChecksumResult<T> = Result<T, ChecksumError>;
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { 0xEDB8_8320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// CRC32 (IEEE) of `data`
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        CRC32_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Which chunks to read back when verifying
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VerifyMode {
+    /// Trust the upload
+    Off,
+    /// Every `every`-th chunk plus the last one
+    Sample { every: usize },
+    /// Every chunk
+    Full,
+}
+
+impl VerifyMode {
+    /// Chunk indices to check out of `count`
+    pub fn select(self, count: usize) -> Vec<usize> {
+        match self {
+            VerifyMode::Off => Vec::new(),
+            VerifyMode::Full => (0..count).collect(),
+            VerifyMode::Sample { every } => {
+                let mut chunks: Vec<usize> = (0..count).step_by(every.max(1)).collect();
+                if count > 0 && chunks.last() != Some(&(count - 1)) {
+                    chunks.push(count - 1);
+                }
+                chunks
+            }
+        }
+    }
+}
+
+/// Per-chunk CRC32s of a byte stream
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChunkChecksums {
+    pub chunk_size: u64,
+    pub total_len: u64,
+    pub crcs: Vec<u32>,
+}
+
+impl ChunkChecksums {
+    /// Hash `data` in `chunk_size` chunks
+    pub fn compute(data: &[u8], chunk_size: u64) -> ChecksumResult<Self> {
+        if chunk_size == 0 {
+            return Err(ChecksumError::InvalidChunkSize(chunk_size));
+        }
+        Ok(Self {
+            chunk_size,
+            total_len: data.len() as u64,
+            crcs: data.chunks(chunk_size as usize).map(crc32).collect(),
+        })
+    }
+
+    /// Number of chunks
+    pub fn len(&self) -> usize {
+        self.crcs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.crcs.is_empty()
+    }
+
+    /// `(offset, len)` of chunk `index`
+    pub fn chunk_range(&self, index: usize) -> (u64, u64) {
+        let offset = index as u64 * self.chunk_size;
+        (offset, self.chunk_size.min(self.total_len - offset))
+    }
+
+    /// Compare chunk `index` against `data`
+    pub fn check_chunk(&self, index: usize, data: &[u8]) -> ChecksumResult<()> {
+        let actual = crc32(data);
+        let expected = self.crcs[index];
+        if actual != expected {
+            return Err(ChecksumError::Mismatch {
+                chunk: index,
+                offset: self.chunk_range(index).0,
+                expected,
+                actual,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Upload `data` chunk by chunk, hashing each chunk as it goes
+///
+/// # Safety Requirements
+/// - Same as `DataTransfer::copy_to_device`
+pub unsafe fn upload_with_checksums(
+    transfer: &DataTransfer,
+    data: &[u8],
+    destination: &AllocationInfo,
+    chunk_size: u64,
+) -> ChecksumResult<ChunkChecksums> {
+    if chunk_size == 0 {
+        return Err(ChecksumError::InvalidChunkSize(chunk_size));
+    }
+    let mut crcs = Vec::with_capacity(data.len().div_ceil(chunk_size as usize));
+    for (index, chunk) in data.chunks(chunk_size as usize).enumerate() {
+        let view = destination.view(index as u64 * chunk_size, chunk.len() as u64)?;
+        // SAFETY: forwarded from the caller's guarantees; view lies within destination
+        unsafe { transfer.copy_to_device(chunk, &view)? };
+        crcs.push(crc32(chunk));
+    }
+    Ok(ChunkChecksums {
+        chunk_size,
+        total_len: data.len() as u64,
+        crcs,
+    })
+}
+
+/// Read back the chunks selected by `mode` and compare them to `checksums`
+///
+/// Returns the number of chunks verified. The first mismatch is reported on
+/// the event bus and returned as `ChecksumError::Mismatch`.
+///
+/// # Safety Requirements
+/// - Same as `DataTransfer::copy_from_device`
+pub unsafe fn verify_upload(
+    transfer: &DataTransfer,
+    source: &AllocationInfo,
+    checksums: &ChunkChecksums,
+    mode: VerifyMode,
+) -> ChecksumResult<usize> {
+    if checksums.total_len > source.size {
+        return Err(ChecksumError::LengthMismatch {
+            expected: checksums.total_len,
+            actual: source.size,
+        });
+    }
+
+    let chunks = mode.select(checksums.len());
+    for &index in &chunks {
+        let (offset, len) = checksums.chunk_range(index);
+        let view = source.view(offset, len)?;
+        // SAFETY: forwarded from the caller's guarantees; view lies within source
+        let bytes = unsafe { transfer.copy_from_device(&view, len)? };
+        if let Err(e) = checksums.check_chunk(index, &bytes) {
+            events::emit(GpuEvent::Error {
+                source: "checksum",
+                message: format!("{}: {}", source.handle_id, e),
+            });
+            return Err(e);
+        }
+    }
+    Ok(chunks.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32_known_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn test_chunk_checksums_detect_corruption() {
+        let mut data: Vec<u8> = (0..10u8).collect();
+        let checksums = ChunkChecksums::compute(&data, 4).unwrap();
+        assert_eq!(checksums.len(), 3);
+        assert_eq!(checksums.chunk_range(2), (8, 2));
+
+        data[5] ^= 0x10;
+        assert!(checksums.check_chunk(0, &data[0..4]).is_ok());
+        assert!(matches!(
+            checksums.check_chunk(1, &data[4..8]),
+            Err(ChecksumError::Mismatch { chunk: 1, offset: 4, .. })
+        ));
+    }
+
+    #[test]
+    fn test_sample_includes_last_chunk() {
+        assert_eq!(VerifyMode::Sample { every: 4 }.select(10), vec![0, 4, 8, 9]);
+        assert!(VerifyMode::Off.select(10).is_empty());
+    }
+}
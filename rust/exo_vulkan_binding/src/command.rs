@@ -3,9 +3,21 @@
 //! Provides abstractions for recording and submitting Vulkan commands,
 //! including synchronization primitives.
 
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread::ThreadId;
+
 use ash::vk;
+use parking_lot::Mutex;
 use thiserror::Error;
 
+use crate::events::{self, GpuEvent};
+use crate::object_budget::{self, BudgetError, ObjectKind, Recyclable};
+use crate::strict;
+use crate::throttle::SubmissionLimiter;
+use crate::trace;
+
 /// Command buffer related errors
 #[derive(Error, Debug)]
 pub enum CommandError {
@@ -24,17 +36,41 @@ pub enum CommandError {
     #[error("Synchronization failed: {0}")]
     SynchronizationFailed(String),
 
+    #[error("Submission would block: {0} submissions in flight")]
+    WouldBlock(u64),
+
+    /// The device was lost (driver reset, GPU hang, eGPU unplugged); every
+    /// object created from it must be recreated
+    #[error("Device lost during {0}")]
+    DeviceLost(&'static str),
+
+    #[error(transparent)]
+    Budget(#[from] BudgetError),
+
     #[error("Vulkan error: {0:?}")]
     VulkanError(vk::Result),
 }
 
 pub type CommandResult<T> = Result<T, CommandError>;
 
+/// Map a queue or fence error, reporting device loss on the event bus and
+/// counting the error against the current trace
+fn queue_error(source: &'static str, e: vk::Result, wrap: fn(String) -> CommandError) -> CommandError {
+    trace::record_error();
+    if e == vk::Result::ERROR_DEVICE_LOST {
+        events::emit(GpuEvent::DeviceLost { source });
+        return CommandError::DeviceLost(source);
+    }
+    wrap(e.to_string())
+}
+
 /// Represents a Vulkan command pool for allocating command buffers
 pub struct CommandPool {
     device: ash::Device,
     pool: vk::CommandPool,
     queue_family_index: u32,
+    /// Buffers allocated from the pool, released from the object budget on drop
+    allocated: AtomicU64,
 }
 
 impl CommandPool {
@@ -65,6 +101,7 @@ impl CommandPool {
                 device,
                 pool,
                 queue_family_index,
+                allocated: AtomicU64::new(0),
             })
         }
     }
@@ -77,7 +114,18 @@ impl CommandPool {
     /// # Returns
     /// Vector of allocated command buffers
     pub fn allocate_buffers(&self, count: u32) -> CommandResult<Vec<vk::CommandBuffer>> {
-        unsafe {
+        self.allocate(vk::CommandBufferLevel::PRIMARY, count)
+    }
+
+    /// Allocate secondary command buffers, executed from a primary with
+    /// `vkCmdExecuteCommands`
+    pub fn allocate_secondary_buffers(&self, count: u32) -> CommandResult<Vec<vk::CommandBuffer>> {
+        self.allocate(vk::CommandBufferLevel::SECONDARY, count)
+    }
+
+    fn allocate(&self, level: vk::CommandBufferLevel, count: u32) -> CommandResult<Vec<vk::CommandBuffer>> {
+        object_budget::acquire(ObjectKind::CommandBuffer, count as u64)?;
+        let buffers = unsafe {
             // Allocate command buffers
             // SAFETY:
             //   - pool is valid (created in new())
@@ -85,13 +133,20 @@ impl CommandPool {
             //   - count > 0 is caller's responsibility
             let alloc_info = vk::CommandBufferAllocateInfo::default()
                 .command_pool(self.pool)
-                .level(vk::CommandBufferLevel::PRIMARY)
+                .level(level)
                 .command_buffer_count(count);
 
             self.device
                 .allocate_command_buffers(&alloc_info)
                 .map_err(|e| CommandError::AllocationFailed(e.to_string()))
+        };
+        if let Ok(buffers) = &buffers {
+            strict::register_command_buffers(buffers, self.pool, self.queue_family_index);
+            self.allocated.fetch_add(count as u64, Ordering::Relaxed);
+        } else {
+            object_budget::release(ObjectKind::CommandBuffer, count as u64);
         }
+        buffers
     }
 
     /// Begin recording a command buffer
@@ -104,6 +159,7 @@ impl CommandPool {
         buffer: vk::CommandBuffer,
         flags: vk::CommandBufferUsageFlags,
     ) -> CommandResult<()> {
+        strict::buffer_from_pool(buffer, self.pool);
         unsafe {
             // Begin command buffer recording
             // SAFETY:
@@ -117,11 +173,33 @@ impl CommandPool {
         }
     }
 
+    /// Begin recording a secondary command buffer for compute work
+    ///
+    /// Compute secondaries inherit no render pass state.
+    pub fn begin_secondary(&self, buffer: vk::CommandBuffer) -> CommandResult<()> {
+        strict::buffer_from_pool(buffer, self.pool);
+        unsafe {
+            // Begin secondary command buffer recording
+            // SAFETY:
+            //   - buffer is a valid secondary buffer allocated from this pool
+            //   - device is valid
+            let inheritance = vk::CommandBufferInheritanceInfo::default();
+            let begin_info = vk::CommandBufferBeginInfo::default()
+                .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)
+                .inheritance_info(&inheritance);
+
+            self.device
+                .begin_command_buffer(buffer, &begin_info)
+                .map_err(|e| CommandError::RecordingFailed(e.to_string()))
+        }
+    }
+
     /// End recording a command buffer
     ///
     /// # Arguments
     /// * `buffer` - Command buffer to end
     pub fn end_recording(&self, buffer: vk::CommandBuffer) -> CommandResult<()> {
+        strict::buffer_from_pool(buffer, self.pool);
         unsafe {
             // End command buffer recording
             // SAFETY:
@@ -138,6 +216,7 @@ impl CommandPool {
     /// # Arguments
     /// * `buffer` - Command buffer to reset
     pub fn reset_buffer(&self, buffer: vk::CommandBuffer) -> CommandResult<()> {
+        strict::buffer_from_pool(buffer, self.pool);
         unsafe {
             // Reset command buffer
             // SAFETY:
@@ -182,6 +261,11 @@ impl CommandPool {
     pub fn queue_family_index(&self) -> u32 {
         self.queue_family_index
     }
+
+    /// Get the raw command pool handle
+    pub fn raw(&self) -> vk::CommandPool {
+        self.pool
+    }
 }
 
 impl Drop for CommandPool {
@@ -194,6 +278,89 @@ impl Drop for CommandPool {
             //   - no command buffers from this pool are in use
             self.device.destroy_command_pool(self.pool, None);
         }
+        strict::forget_pool(self.pool);
+        object_budget::release(ObjectKind::CommandBuffer, *self.allocated.get_mut());
+    }
+}
+
+/// Owner of a cached command pool
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PoolOwner {
+    /// A worker thread
+    Thread(ThreadId),
+    /// A logical stream of work that may hop between threads but is
+    /// recorded by one thread at a time
+    Stream(u64),
+}
+
+/// Per-thread / per-stream command pool cache
+///
+/// Vulkan command pools require external synchronization, so sharing one
+/// pool between recording threads serializes them. The cache hands each
+/// owner its own pool; the map lock is only held while looking it up.
+pub struct CommandPoolCache {
+    device: ash::Device,
+    pools: Mutex<HashMap<(PoolOwner, u32), Arc<CommandPool>>>,
+}
+
+impl CommandPoolCache {
+    /// Create an empty cache
+    ///
+    /// # Safety Requirements
+    /// - device must outlive the cache and every pool it hands out
+    pub fn new(device: ash::Device) -> Self {
+        Self {
+            device,
+            pools: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Pool for the calling thread, created on first use
+    ///
+    /// The pool must only be used to record on the calling thread.
+    pub fn for_current_thread(&self, queue_family_index: u32) -> CommandResult<Arc<CommandPool>> {
+        self.get_or_create(PoolOwner::Thread(std::thread::current().id()), queue_family_index)
+    }
+
+    /// Pool for a logical stream, created on first use
+    pub fn for_stream(&self, stream_id: u64, queue_family_index: u32) -> CommandResult<Arc<CommandPool>> {
+        self.get_or_create(PoolOwner::Stream(stream_id), queue_family_index)
+    }
+
+    fn get_or_create(&self, owner: PoolOwner, queue_family_index: u32) -> CommandResult<Arc<CommandPool>> {
+        let key = (owner, queue_family_index);
+        if let Some(pool) = self.pools.lock().get(&key) {
+            return Ok(Arc::clone(pool));
+        }
+
+        // Create outside the lock; pool creation can be slow on some drivers
+        let pool = Arc::new(CommandPool::new(self.device.clone(), queue_family_index)?);
+        Ok(Arc::clone(self.pools.lock().entry(key).or_insert(pool)))
+    }
+
+    /// Drop every pool owned by `owner` (e.g. when a worker thread exits)
+    ///
+    /// Pools are destroyed once their last `Arc` is dropped.
+    pub fn release(&self, owner: PoolOwner) -> usize {
+        let mut pools = self.pools.lock();
+        let before = pools.len();
+        pools.retain(|(pool_owner, _), _| *pool_owner != owner);
+        before - pools.len()
+    }
+
+    /// Drop the calling thread's pools
+    pub fn release_current_thread(&self) -> usize {
+        self.release(PoolOwner::Thread(std::thread::current().id()))
+    }
+
+    /// Number of cached pools
+    pub fn len(&self) -> usize {
+        self.pools.lock().len()
+    }
+
+    /// Whether no pools are cached
+    pub fn is_empty(&self) -> bool {
+        self.pools.lock().is_empty()
     }
 }
 
@@ -202,6 +369,7 @@ pub struct Queue {
     device: ash::Device,
     queue: vk::Queue,
     queue_family_index: u32,
+    limiter: Option<Arc<SubmissionLimiter>>,
 }
 
 impl Queue {
@@ -216,13 +384,50 @@ impl Queue {
         queue: vk::Queue,
         queue_family_index: u32,
     ) -> Self {
+        strict::non_null(queue, "queue");
         Queue {
             device,
             queue,
             queue_family_index,
+            limiter: None,
         }
     }
 
+    /// Cap in-flight submissions made through `submit_limited`
+    pub fn set_limiter(&mut self, limiter: Arc<SubmissionLimiter>) {
+        self.limiter = Some(limiter);
+    }
+
+    /// The submission limiter, if one is configured
+    pub fn limiter(&self) -> Option<&Arc<SubmissionLimiter>> {
+        self.limiter.as_ref()
+    }
+
+    /// Submit under the configured in-flight cap
+    ///
+    /// Returns the submission's timeline value; pass it to
+    /// `SubmissionLimiter::complete` once `fence` has signaled. Without a
+    /// limiter this behaves like `submit` and returns 0.
+    pub fn submit_limited(
+        &self,
+        buffers: &[vk::CommandBuffer],
+        wait_semaphore: Option<vk::Semaphore>,
+        signal_semaphore: Option<vk::Semaphore>,
+        fence: Option<vk::Fence>,
+    ) -> CommandResult<u64> {
+        let Some(limiter) = &self.limiter else {
+            self.submit(buffers, wait_semaphore, signal_semaphore, fence)?;
+            return Ok(0);
+        };
+
+        let value = limiter.acquire()?;
+        if let Err(e) = self.submit(buffers, wait_semaphore, signal_semaphore, fence) {
+            limiter.cancel(value);
+            return Err(e);
+        }
+        Ok(value)
+    }
+
     /// Submit command buffers to queue
     ///
     /// # Arguments
@@ -237,39 +442,62 @@ impl Queue {
         signal_semaphore: Option<vk::Semaphore>,
         fence: Option<vk::Fence>,
     ) -> CommandResult<()> {
+        let waits: Vec<_> = wait_semaphore.map(SubmitSemaphore::Binary).into_iter().collect();
+        let signals: Vec<_> = signal_semaphore.map(SubmitSemaphore::Binary).into_iter().collect();
+        self.submit_with(buffers, &waits, &signals, fence)
+    }
+
+    /// Submit command buffers waiting on and signaling any mix of binary
+    /// and timeline semaphores
+    ///
+    /// Chaining stages through timeline values lets a multi-stage pipeline
+    /// run without the CPU waiting between stages. Every wait blocks all
+    /// commands of the submission. Timeline entries need a device with
+    /// `VK_KHR_timeline_semaphore` enabled.
+    pub fn submit_with(
+        &self,
+        buffers: &[vk::CommandBuffer],
+        waits: &[SubmitSemaphore],
+        signals: &[SubmitSemaphore],
+        fence: Option<vk::Fence>,
+    ) -> CommandResult<()> {
+        strict::same_queue_family(buffers, self.queue_family_index);
+        let wait_semaphores: Vec<vk::Semaphore> = waits.iter().map(|s| s.raw()).collect();
+        let wait_stages = vec![vk::PipelineStageFlags::ALL_COMMANDS; waits.len()];
+        let signal_semaphores: Vec<vk::Semaphore> = signals.iter().map(|s| s.raw()).collect();
+        let wait_values = timeline_values(waits);
+        let signal_values = timeline_values(signals);
+        let mut timeline_info = vk::TimelineSemaphoreSubmitInfo::default()
+            .wait_semaphore_values(wait_values.as_deref().unwrap_or_default())
+            .signal_semaphore_values(signal_values.as_deref().unwrap_or_default());
+
+        let mut submit_info = vk::SubmitInfo::default()
+            .wait_semaphores(&wait_semaphores)
+            .wait_dst_stage_mask(&wait_stages)
+            .command_buffers(buffers)
+            .signal_semaphores(&signal_semaphores);
+        // Only chained when used, so binary-only submissions stay valid on
+        // devices without the extension
+        if wait_values.is_some() || signal_values.is_some() {
+            submit_info = submit_info.push_next(&mut timeline_info);
+        }
+
         unsafe {
-            // Build submit info
             // SAFETY:
             //   - device is valid
             //   - queue is valid
             //   - buffers, semaphores, fence are all valid
-            let wait_stages = [vk::PipelineStageFlags::ALL_COMMANDS];
-            let (wait_semaphores, wait_stages_ref) = if let Some(sem) = wait_semaphore {
-                (vec![sem], &wait_stages[..])
-            } else {
-                (vec![], &[][..])
-            };
-
-            let signal_semaphores = if let Some(sem) = signal_semaphore {
-                vec![sem]
-            } else {
-                vec![]
-            };
-
-            let submit_info = vk::SubmitInfo::default()
-                .wait_semaphores(&wait_semaphores)
-                .wait_dst_stage_mask(wait_stages_ref)
-                .command_buffers(buffers)
-                .signal_semaphores(&signal_semaphores);
-
+            //   - value arrays, when chained, match the semaphore arrays in length
             self.device
                 .queue_submit(self.queue, &[submit_info], fence.unwrap_or(vk::Fence::null()))
-                .map_err(|e| CommandError::SubmissionFailed(e.to_string()))
+                .map_err(|e| queue_error("queue_submit", e, CommandError::SubmissionFailed))
         }
     }
 
     /// Wait for queue to be idle
     pub fn wait_idle(&self) -> CommandResult<()> {
+        crate::diagnostics::note_queue_wait_idle();
+
         unsafe {
             // Wait for queue
             // SAFETY:
@@ -277,8 +505,14 @@ impl Queue {
             //   - device is valid
             self.device
                 .queue_wait_idle(self.queue)
-                .map_err(|e| CommandError::SynchronizationFailed(e.to_string()))
+                .map_err(|e| queue_error("queue_wait_idle", e, CommandError::SynchronizationFailed))?;
+        }
+
+        // Everything submitted so far has completed
+        if let Some(limiter) = &self.limiter {
+            limiter.complete_all();
         }
+        Ok(())
     }
 
     /// Get the queue family index
@@ -300,6 +534,7 @@ impl Fence {
     /// * `device` - Ash device
     /// * `signaled` - If true, fence starts in signaled state
     pub fn new(device: ash::Device, signaled: bool) -> CommandResult<Self> {
+        object_budget::acquire(ObjectKind::Fence, 1)?;
         unsafe {
             // Create fence
             // SAFETY:
@@ -312,9 +547,10 @@ impl Fence {
                 },
             );
 
-            let fence = device
-                .create_fence(&fence_info, None)
-                .map_err(|e| CommandError::VulkanError(e))?;
+            let fence = device.create_fence(&fence_info, None).map_err(|e| {
+                object_budget::release(ObjectKind::Fence, 1);
+                CommandError::VulkanError(e)
+            })?;
 
             Ok(Fence { device, fence })
         }
@@ -336,7 +572,7 @@ impl Fence {
             {
                 Ok(()) => Ok(true),
                 Err(vk::Result::TIMEOUT) => Ok(false),
-                Err(e) => Err(CommandError::SynchronizationFailed(e.to_string())),
+                Err(e) => Err(queue_error("wait_for_fences", e, CommandError::SynchronizationFailed)),
             }
         }
     }
@@ -369,6 +605,225 @@ impl Drop for Fence {
             //   - device is valid
             self.device.destroy_fence(self.fence, None);
         }
+        object_budget::release(ObjectKind::Fence, 1);
+    }
+}
+
+impl Recyclable for Fence {
+    /// Reset to unsignaled for reuse
+    fn recycle(&self) -> bool {
+        self.reset().is_ok()
+    }
+}
+
+/// Synchronization primitive: binary Semaphore
+pub struct Semaphore {
+    device: ash::Device,
+    semaphore: vk::Semaphore,
+}
+
+impl Semaphore {
+    /// Create a new binary semaphore
+    ///
+    /// # Arguments
+    /// * `device` - Ash device
+    pub fn new(device: ash::Device) -> CommandResult<Self> {
+        object_budget::acquire(ObjectKind::Semaphore, 1)?;
+        unsafe {
+            // Create semaphore
+            // SAFETY:
+            //   - device is valid
+            let semaphore = device
+                .create_semaphore(&vk::SemaphoreCreateInfo::default(), None)
+                .map_err(|e| {
+                    object_budget::release(ObjectKind::Semaphore, 1);
+                    CommandError::VulkanError(e)
+                })?;
+
+            Ok(Semaphore { device, semaphore })
+        }
+    }
+
+    /// Get the raw semaphore handle
+    pub fn raw(&self) -> vk::Semaphore {
+        self.semaphore
+    }
+}
+
+impl Drop for Semaphore {
+    fn drop(&mut self) {
+        unsafe {
+            // Destroy semaphore
+            // SAFETY:
+            //   - semaphore is valid
+            //   - device is valid
+            //   - no pending submission waits on or signals it
+            self.device.destroy_semaphore(self.semaphore, None);
+        }
+        object_budget::release(ObjectKind::Semaphore, 1);
+    }
+}
+
+impl Recyclable for Semaphore {
+    /// A binary semaphore whose last signal was waited on is unsignaled and
+    /// reusable as is
+    fn recycle(&self) -> bool {
+        true
+    }
+}
+
+/// A semaphore a submission waits on or signals
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SubmitSemaphore {
+    Binary(vk::Semaphore),
+    /// A timeline semaphore and the value to wait for or signal
+    Timeline(vk::Semaphore, u64),
+}
+
+impl SubmitSemaphore {
+    pub fn raw(&self) -> vk::Semaphore {
+        match *self {
+            SubmitSemaphore::Binary(semaphore) | SubmitSemaphore::Timeline(semaphore, _) => semaphore,
+        }
+    }
+}
+
+/// Per-semaphore values for `VkTimelineSemaphoreSubmitInfo`, or `None`
+/// when no timeline semaphore is involved
+///
+/// Binary semaphores take a placeholder value, which Vulkan ignores.
+fn timeline_values(semaphores: &[SubmitSemaphore]) -> Option<Vec<u64>> {
+    semaphores
+        .iter()
+        .any(|s| matches!(s, SubmitSemaphore::Timeline(..)))
+        .then(|| {
+            semaphores
+                .iter()
+                .map(|s| match *s {
+                    SubmitSemaphore::Binary(_) => 0,
+                    SubmitSemaphore::Timeline(_, value) => value,
+                })
+                .collect()
+        })
+}
+
+/// Synchronization primitive: timeline Semaphore (`VK_KHR_timeline_semaphore`)
+///
+/// Holds a monotonically increasing 64-bit counter. Submissions signal it to
+/// a value and later submissions, or the host, wait until it reaches one.
+pub struct TimelineSemaphore {
+    device: ash::Device,
+    loader: ash::khr::timeline_semaphore::Device,
+    semaphore: vk::Semaphore,
+}
+
+impl TimelineSemaphore {
+    /// Create a timeline semaphore starting at `initial_value`
+    ///
+    /// # Arguments
+    /// * `instance` - Instance the device was created from
+    /// * `device` - Ash device with `VK_KHR_timeline_semaphore` and its
+    ///   `timelineSemaphore` feature enabled
+    /// * `initial_value` - Initial counter value
+    pub fn new(instance: &ash::Instance, device: ash::Device, initial_value: u64) -> CommandResult<Self> {
+        object_budget::acquire(ObjectKind::Semaphore, 1)?;
+        let mut type_info = vk::SemaphoreTypeCreateInfo::default()
+            .semaphore_type(vk::SemaphoreType::TIMELINE)
+            .initial_value(initial_value);
+        let create_info = vk::SemaphoreCreateInfo::default().push_next(&mut type_info);
+        unsafe {
+            // Create semaphore
+            // SAFETY:
+            //   - device is valid and has timeline semaphores enabled
+            let semaphore = device.create_semaphore(&create_info, None).map_err(|e| {
+                object_budget::release(ObjectKind::Semaphore, 1);
+                CommandError::VulkanError(e)
+            })?;
+
+            let loader = ash::khr::timeline_semaphore::Device::new(instance, &device);
+            Ok(TimelineSemaphore {
+                device,
+                loader,
+                semaphore,
+            })
+        }
+    }
+
+    /// Current counter value
+    pub fn value(&self) -> CommandResult<u64> {
+        unsafe {
+            // SAFETY:
+            //   - semaphore is a valid timeline semaphore of device
+            self.loader
+                .get_semaphore_counter_value(self.semaphore)
+                .map_err(|e| queue_error("get_semaphore_counter_value", e, CommandError::SynchronizationFailed))
+        }
+    }
+
+    /// Set the counter to `value` from the host
+    ///
+    /// `value` must exceed the current value and every value a pending
+    /// submission will signal.
+    pub fn signal(&self, value: u64) -> CommandResult<()> {
+        let info = vk::SemaphoreSignalInfo::default().semaphore(self.semaphore).value(value);
+        unsafe {
+            // SAFETY:
+            //   - semaphore is a valid timeline semaphore of device
+            self.loader
+                .signal_semaphore(&info)
+                .map_err(|e| queue_error("signal_semaphore", e, CommandError::SynchronizationFailed))
+        }
+    }
+
+    /// Wait on the host until the counter reaches `value`
+    ///
+    /// # Arguments
+    /// * `value` - Counter value to wait for
+    /// * `timeout_ns` - Timeout in nanoseconds
+    ///
+    /// Returns false on timeout.
+    pub fn wait(&self, value: u64, timeout_ns: u64) -> CommandResult<bool> {
+        let semaphores = [self.semaphore];
+        let values = [value];
+        let info = vk::SemaphoreWaitInfo::default().semaphores(&semaphores).values(&values);
+        unsafe {
+            // SAFETY:
+            //   - semaphore is a valid timeline semaphore of device
+            match self.loader.wait_semaphores(&info, timeout_ns) {
+                Ok(()) => Ok(true),
+                Err(vk::Result::TIMEOUT) => Ok(false),
+                Err(e) => Err(queue_error("wait_semaphores", e, CommandError::SynchronizationFailed)),
+            }
+        }
+    }
+
+    /// Make a submission wait until the counter reaches `value`
+    pub fn wait_value(&self, value: u64) -> SubmitSemaphore {
+        SubmitSemaphore::Timeline(self.semaphore, value)
+    }
+
+    /// Make a submission set the counter to `value` when it completes
+    pub fn signal_value(&self, value: u64) -> SubmitSemaphore {
+        SubmitSemaphore::Timeline(self.semaphore, value)
+    }
+
+    /// Get the raw semaphore handle
+    pub fn raw(&self) -> vk::Semaphore {
+        self.semaphore
+    }
+}
+
+impl Drop for TimelineSemaphore {
+    fn drop(&mut self) {
+        unsafe {
+            // Destroy semaphore
+            // SAFETY:
+            //   - semaphore is valid
+            //   - device is valid
+            //   - no pending submission waits on or signals it
+            self.device.destroy_semaphore(self.semaphore, None);
+        }
+        object_budget::release(ObjectKind::Semaphore, 1);
     }
 }
 
@@ -381,4 +836,15 @@ mod tests {
         let err = CommandError::PoolCreationFailed("test".to_string());
         assert!(err.to_string().contains("test"));
     }
+
+    #[test]
+    fn test_timeline_values_only_with_timelines() {
+        let a = vk::Semaphore::null();
+        assert_eq!(timeline_values(&[]), None);
+        assert_eq!(timeline_values(&[SubmitSemaphore::Binary(a)]), None);
+        assert_eq!(
+            timeline_values(&[SubmitSemaphore::Binary(a), SubmitSemaphore::Timeline(a, 7)]),
+            Some(vec![0, 7])
+        );
+    }
 }
@@ -0,0 +1,252 @@
+//! Compute unit counts and clock rates
+//!
+//! Core Vulkan reports neither how many compute units a device has nor how
+//! fast they run, but schedulers weight devices by both. `query` asks the
+//! vendor extensions that do report a count, preferring the most exact:
+//!
+//! - `VK_AMD_shader_core_properties2`: active CUs, excluding harvested ones
+//! - `VK_AMD_shader_core_properties`: engines x arrays x CUs per array
+//! - `VK_ARM_shader_core_builtins`: Mali shader cores
+//! - `VK_NV_shader_sm_builtins`: streaming multiprocessors
+//!
+//! Devices exposing none of them (Adreno, Intel, Apple) get
+//! `estimate_compute_units`, and `ComputeUnitSource::Estimated` says so.
+//! Clock rates have no Vulkan query at all; `max_clock_mhz` reads the
+//! kernel's GPU frequency tables where the driver publishes them.
+
+use std::fs;
+use std::path::Path;
+
+use ash::vk;
+use serde::Serialize;
+
+use crate::{VulkanContext, VulkanResult};
+
+/// Where a compute unit count came from
+///
+/// Serializes as its `name`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ComputeUnitSource {
+    AmdShaderCore,
+    ArmShaderCoreBuiltins,
+    NvShaderSmBuiltins,
+    /// No extension reported a count; see `estimate_compute_units`
+    Estimated,
+}
+
+impl ComputeUnitSource {
+    pub fn name(self) -> &'static str {
+        match self {
+            ComputeUnitSource::AmdShaderCore => "amd_shader_core",
+            ComputeUnitSource::ArmShaderCoreBuiltins => "arm_shader_core_builtins",
+            ComputeUnitSource::NvShaderSmBuiltins => "nv_shader_sm_builtins",
+            ComputeUnitSource::Estimated => "estimated",
+        }
+    }
+}
+
+/// Compute resources of one device
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ComputeTopology {
+    pub compute_units: u32,
+    pub source: ComputeUnitSource,
+    /// Invocations per subgroup (warp, wavefront, fiber)
+    pub subgroup_size: u32,
+    /// Highest GPU clock the kernel allows, when it publishes one
+    pub max_clock_mhz: Option<u32>,
+}
+
+/// Query the compute topology of device `index`
+pub fn query(context: &VulkanContext, index: usize) -> VulkanResult<ComputeTopology> {
+    let physical_device = context.get_physical_device(index)?;
+    let properties = *context.get_device_properties(index)?;
+    let capabilities = context.get_device_capabilities(index)?;
+    let has = |name: &std::ffi::CStr| capabilities.has_extension(&name.to_string_lossy());
+
+    let mut subgroup = vk::PhysicalDeviceSubgroupProperties::default();
+    let mut amd = vk::PhysicalDeviceShaderCorePropertiesAMD::default();
+    let mut amd2 = vk::PhysicalDeviceShaderCoreProperties2AMD::default();
+    let mut arm = vk::PhysicalDeviceShaderCoreBuiltinsPropertiesARM::default();
+    let mut nv = vk::PhysicalDeviceShaderSMBuiltinsPropertiesNV::default();
+    {
+        // Only chain structs of supported extensions; drivers may reject others
+        let mut properties2 = vk::PhysicalDeviceProperties2::default().push_next(&mut subgroup);
+        if has(ash::amd::shader_core_properties::NAME) {
+            properties2 = properties2.push_next(&mut amd);
+        }
+        if has(ash::amd::shader_core_properties2::NAME) {
+            properties2 = properties2.push_next(&mut amd2);
+        }
+        if has(ash::arm::shader_core_builtins::NAME) {
+            properties2 = properties2.push_next(&mut arm);
+        }
+        if has(ash::nv::shader_sm_builtins::NAME) {
+            properties2 = properties2.push_next(&mut nv);
+        }
+        // SAFETY: physical_device was enumerated from the context's instance,
+        // which targets Vulkan 1.1, and every chained struct's extension is supported
+        unsafe {
+            context
+                .instance()
+                .get_physical_device_properties2(physical_device, &mut properties2)
+        };
+    }
+
+    let amd_total = amd
+        .shader_engine_count
+        .saturating_mul(amd.shader_arrays_per_engine_count)
+        .saturating_mul(amd.compute_units_per_shader_array);
+    let (compute_units, source) = [
+        (amd2.active_compute_unit_count, ComputeUnitSource::AmdShaderCore),
+        (amd_total, ComputeUnitSource::AmdShaderCore),
+        (arm.shader_core_count, ComputeUnitSource::ArmShaderCoreBuiltins),
+        (nv.shader_sm_count, ComputeUnitSource::NvShaderSmBuiltins),
+    ]
+    .into_iter()
+    .find(|&(count, _)| count > 0)
+    .unwrap_or((estimate_compute_units(properties.device_type), ComputeUnitSource::Estimated));
+
+    Ok(ComputeTopology {
+        compute_units,
+        source,
+        subgroup_size: subgroup.subgroup_size.max(1),
+        max_clock_mhz: max_clock_mhz(properties.vendor_id, properties.device_id),
+    })
+}
+
+/// Compute unit count when no extension reports one
+///
+/// A coarse guess by device type, good for weighting devices against each
+/// other but not for occupancy math: mobile and laptop GPUs have a handful
+/// of cores, desktop ones dozens. CPU implementations (lavapipe,
+/// SwiftShader) run one invocation stream per host thread.
+pub fn estimate_compute_units(device_type: vk::PhysicalDeviceType) -> u32 {
+    match device_type {
+        vk::PhysicalDeviceType::DISCRETE_GPU => 32,
+        vk::PhysicalDeviceType::INTEGRATED_GPU | vk::PhysicalDeviceType::VIRTUAL_GPU => 8,
+        vk::PhysicalDeviceType::CPU => std::thread::available_parallelism()
+            .map_or(1, |n| u32::try_from(n.get()).unwrap_or(u32::MAX)),
+        _ => 4,
+    }
+}
+
+/// Highest GPU clock in MHz, from sysfs
+///
+/// Tried in order: the Adreno (kgsl) driver, DRM cards matching the
+/// device's PCI ids (AMD `pp_dpm_sclk`, Intel `gt_max_freq_mhz`), then
+/// devfreq GPU nodes (Mali and other SoC GPUs). `None` where none exist or
+/// they are unreadable, as on most Android builds without root.
+pub fn max_clock_mhz(vendor_id: u32, device_id: u32) -> Option<u32> {
+    max_clock_mhz_in(Path::new("/sys/class"), vendor_id, device_id)
+}
+
+fn max_clock_mhz_in(class: &Path, vendor_id: u32, device_id: u32) -> Option<u32> {
+    let read = |path: &Path| fs::read_to_string(path).ok();
+
+    if let Some(mhz) = read(&class.join("kgsl/kgsl-3d0/max_gpuclk")).and_then(|s| hz_to_mhz(&s)) {
+        return Some(mhz);
+    }
+
+    let entries = |dir: &str| {
+        let mut paths: Vec<_> = fs::read_dir(class.join(dir))
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .collect();
+        paths.sort();
+        paths
+    };
+
+    for card in entries("drm") {
+        let device = card.join("device");
+        let id = |file: &str| read(&device.join(file)).and_then(|s| parse_hex_id(&s));
+        if id("vendor") != Some(vendor_id) || id("device") != Some(device_id) {
+            continue;
+        }
+        let mhz = read(&device.join("pp_dpm_sclk"))
+            .and_then(|s| max_dpm_level_mhz(&s))
+            .or_else(|| read(&card.join("gt_max_freq_mhz")).and_then(|s| s.trim().parse().ok()));
+        if mhz.is_some() {
+            return mhz;
+        }
+    }
+
+    entries("devfreq")
+        .into_iter()
+        .filter(|node| {
+            let name = node.file_name().map(|n| n.to_string_lossy().to_lowercase()).unwrap_or_default();
+            ["gpu", "mali", "kgsl"].iter().any(|hint| name.contains(hint))
+        })
+        .find_map(|node| read(&node.join("max_freq")).and_then(|s| hz_to_mhz(&s)))
+}
+
+/// A frequency in Hz, as sysfs writes it, in MHz
+fn hz_to_mhz(value: &str) -> Option<u32> {
+    let hz: u64 = value.trim().parse().ok()?;
+    u32::try_from(hz / 1_000_000).ok().filter(|&mhz| mhz > 0)
+}
+
+/// A PCI id such as `0x1002`
+fn parse_hex_id(value: &str) -> Option<u32> {
+    let value = value.trim();
+    u32::from_str_radix(value.strip_prefix("0x").unwrap_or(value), 16).ok()
+}
+
+/// Highest level of an AMD DPM table (`0: 500Mhz`, `1: 2500Mhz *`, ...)
+fn max_dpm_level_mhz(table: &str) -> Option<u32> {
+    table
+        .lines()
+        .filter_map(|line| {
+            let (_, level) = line.split_once(':')?;
+            let level = level.trim().trim_end_matches('*').trim();
+            level
+                .strip_suffix("Mhz")
+                .or_else(|| level.strip_suffix("MHz"))?
+                .trim()
+                .parse()
+                .ok()
+        })
+        .max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_sysfs_formats() {
+        assert_eq!(hz_to_mhz("585000000\n"), Some(585));
+        assert_eq!(hz_to_mhz("0"), None);
+        assert_eq!(parse_hex_id("0x1002\n"), Some(0x1002));
+        assert_eq!(max_dpm_level_mhz("0: 500Mhz\n1: 1800Mhz *\n2: 2500Mhz\n"), Some(2500));
+        assert_eq!(max_dpm_level_mhz(""), None);
+
+        assert_eq!(estimate_compute_units(vk::PhysicalDeviceType::DISCRETE_GPU), 32);
+        assert!(estimate_compute_units(vk::PhysicalDeviceType::CPU) >= 1);
+    }
+
+    #[test]
+    fn test_reads_clock_of_matching_card() {
+        let class = std::env::temp_dir().join(format!("exo_sysfs_{}", std::process::id()));
+        let write = |path: &str, contents: &str| {
+            let path = class.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        };
+        write("drm/card0/device/vendor", "0x8086\n");
+        write("drm/card0/device/device", "0x56a0\n");
+        write("drm/card0/gt_max_freq_mhz", "2400\n");
+        write("drm/card1/device/vendor", "0x1002\n");
+        write("drm/card1/device/device", "0x744c\n");
+        write("drm/card1/device/pp_dpm_sclk", "0: 500Mhz\n1: 2500Mhz *\n");
+        write("devfreq/13000000.mali/max_freq", "850000000\n");
+
+        assert_eq!(max_clock_mhz_in(&class, 0x1002, 0x744c), Some(2500));
+        assert_eq!(max_clock_mhz_in(&class, 0x8086, 0x56a0), Some(2400));
+        // No card matches, so the SoC GPU's devfreq node is used
+        assert_eq!(max_clock_mhz_in(&class, 0x13b5, 0), Some(850));
+        fs::remove_dir_all(&class).unwrap();
+    }
+}
@@ -0,0 +1,393 @@
+//! Runtime configuration and hot reload
+//!
+//! The backend's tunables as one JSON document, re-applicable while running.
+//! Fields are classified by when they take effect:
+//!
+//! - hot: log level, memory alert thresholds, the frame budget, object
+//!   count limits, the cache directory and JNI call statistics are read on
+//!   every use and apply immediately
+//! - cold: loader path and queue setup are consumed when the instance and
+//!   logical device are created; changing them is recorded and takes effect
+//!   after the backend is shut down and initialized again
+//!
+//! Applying an update merges it into the current configuration (absent fields
+//! are left unchanged) and returns a `ConfigDiff` describing what changed and
+//! whether it took effect.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::device::{DeviceConfig, GlobalPriority};
+use crate::events::{self, GpuEvent};
+use crate::frame_budget::FrameBudget;
+use crate::object_budget::ObjectKind;
+
+/// Configuration errors
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Malformed config: {0}")]
+    Malformed(#[from] serde_json::Error),
+
+    #[error("Invalid {field}: {reason}")]
+    Invalid { field: &'static str, reason: String },
+}
+
+pub type ConfigResult<T> = Result<T, ConfigError>;
+
+/// When a field change takes effect
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Reload {
+    /// Applied immediately
+    Hot,
+    /// Applied on the next initialization
+    Cold,
+}
+
+impl Reload {
+    pub fn name(self) -> &'static str {
+        match self {
+            Reload::Hot => "hot",
+            Reload::Cold => "cold",
+        }
+    }
+}
+
+/// Classify a field of `RuntimeConfig` by its JSON name
+pub fn reload_kind(field: &str) -> Reload {
+    match field {
+        "log_level" | "memory_thresholds" | "frame_budget_ms" | "refresh_rate_hz" | "object_limits"
+        | "cache_dir" | "jni_stats" => Reload::Hot,
+        _ => Reload::Cold,
+    }
+}
+
+/// Backend tunables; None means "unset" (built-in default) in the current
+/// configuration and "unchanged" in an update
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuntimeConfig {
+    /// `off`, `error`, `warn`, `info`, `debug` or `trace`
+    pub log_level: Option<String>,
+    /// Memory alert levels as percentages of total device memory
+    pub memory_thresholds: Option<Vec<u32>>,
+    /// GPU time per display frame for decode loops; 0 disables pacing
+    pub frame_budget_ms: Option<f32>,
+    pub refresh_rate_hz: Option<u32>,
+    /// Live object ceilings by kind (`pipeline`, `fence`, ...); kinds left
+    /// out are unlimited
+    pub object_limits: Option<BTreeMap<String, u64>>,
+    /// Directory for capability and autotune caches; empty disables caching
+    pub cache_dir: Option<String>,
+    /// Count and time JNI calls per entry point
+    pub jni_stats: Option<bool>,
+    /// Vulkan loader library; empty selects the system loader
+    pub loader_path: Option<String>,
+    pub queue_priority: Option<f32>,
+    /// `low`, `medium`, `high`, `realtime` or `none`
+    pub global_priority: Option<String>,
+    pub protected_submission: Option<bool>,
+    /// Validation layer and `log` messenger (see `debug_layers`)
+    pub debug_layers: Option<bool>,
+}
+
+/// One field that an update changed
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ConfigChange {
+    pub field: &'static str,
+    /// Previous value, None if it was unset
+    pub old: Option<String>,
+    pub new: String,
+    pub reload: Reload,
+}
+
+/// What an update changed
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConfigDiff {
+    pub changes: Vec<ConfigChange>,
+}
+
+impl ConfigDiff {
+    /// Names of changed fields that took effect immediately
+    pub fn applied(&self) -> Vec<&'static str> {
+        self.fields(Reload::Hot)
+    }
+
+    /// Names of changed fields waiting for the next initialization
+    pub fn pending_reinit(&self) -> Vec<&'static str> {
+        self.fields(Reload::Cold)
+    }
+
+    fn fields(&self, reload: Reload) -> Vec<&'static str> {
+        self.changes
+            .iter()
+            .filter(|c| c.reload == reload)
+            .map(|c| c.field)
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// JSON report: `{"changes":[{"field","old","new","reload"}],"requires_reinit":bool}`
+    pub fn to_json(&self) -> String {
+        let report = DiffJson {
+            changes: &self.changes,
+            requires_reinit: !self.pending_reinit().is_empty(),
+        };
+        serde_json::to_string(&report).expect("config changes always serialize")
+    }
+}
+
+/// Wire form of `ConfigDiff`
+#[derive(Serialize)]
+struct DiffJson<'a> {
+    changes: &'a [ConfigChange],
+    requires_reinit: bool,
+}
+
+fn invalid(field: &'static str, reason: impl Into<String>) -> ConfigError {
+    ConfigError::Invalid {
+        field,
+        reason: reason.into(),
+    }
+}
+
+fn parse_global_priority(name: &str) -> ConfigResult<Option<GlobalPriority>> {
+    Ok(match name.to_ascii_lowercase().as_str() {
+        "none" => None,
+        "low" => Some(GlobalPriority::Low),
+        "medium" => Some(GlobalPriority::Medium),
+        "high" => Some(GlobalPriority::High),
+        "realtime" => Some(GlobalPriority::Realtime),
+        other => return Err(invalid("global_priority", format!("unknown priority {:?}", other))),
+    })
+}
+
+impl RuntimeConfig {
+    /// Parse and validate a config document
+    pub fn from_json(json: &str) -> ConfigResult<Self> {
+        let config: Self = serde_json::from_str(json)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> ConfigResult<()> {
+        if let Some(level) = &self.log_level {
+            level
+                .parse::<log::LevelFilter>()
+                .map_err(|_| invalid("log_level", format!("unknown level {:?}", level)))?;
+        }
+        if let Some(bad) = self.memory_thresholds.iter().flatten().find(|&&p| p == 0 || p > 100) {
+            return Err(invalid("memory_thresholds", format!("{}% is outside (0, 100]", bad)));
+        }
+        if let Some(ms) = self.frame_budget_ms {
+            if !ms.is_finite() || ms < 0.0 {
+                return Err(invalid("frame_budget_ms", format!("{} is not a duration", ms)));
+            }
+            if ms > 0.0 && matches!(self.frame_budget(), Some(None)) {
+                return Err(invalid("frame_budget_ms", format!("{} ms does not fit in a frame", ms)));
+            }
+        }
+        if self.refresh_rate_hz == Some(0) {
+            return Err(invalid("refresh_rate_hz", "must be positive"));
+        }
+        if let Some(unknown) = self
+            .object_limits
+            .iter()
+            .flatten()
+            .find(|(kind, _)| ObjectKind::from_name(kind).is_none())
+        {
+            return Err(invalid("object_limits", format!("unknown object kind {:?}", unknown.0)));
+        }
+        if let Some(priority) = self.queue_priority
+            && !(0.0..=1.0).contains(&priority)
+        {
+            return Err(invalid("queue_priority", format!("{} is outside 0.0..=1.0", priority)));
+        }
+        if let Some(priority) = &self.global_priority {
+            parse_global_priority(priority)?;
+        }
+        Ok(())
+    }
+
+    /// Rendered value of every field, in declaration order
+    fn fields(&self) -> [(&'static str, Option<String>); 12] {
+        fn render<T: std::fmt::Debug>(value: &Option<T>) -> Option<String> {
+            value.as_ref().map(|v| format!("{:?}", v).trim_matches('"').to_string())
+        }
+        [
+            ("log_level", render(&self.log_level)),
+            ("memory_thresholds", render(&self.memory_thresholds)),
+            ("frame_budget_ms", render(&self.frame_budget_ms)),
+            ("refresh_rate_hz", render(&self.refresh_rate_hz)),
+            ("object_limits", render(&self.object_limits)),
+            ("cache_dir", render(&self.cache_dir)),
+            ("jni_stats", render(&self.jni_stats)),
+            ("loader_path", render(&self.loader_path)),
+            ("queue_priority", render(&self.queue_priority)),
+            ("global_priority", render(&self.global_priority)),
+            ("protected_submission", render(&self.protected_submission)),
+            ("debug_layers", render(&self.debug_layers)),
+        ]
+    }
+
+    /// Merge the fields `update` sets into this configuration
+    ///
+    /// # Returns
+    /// The fields whose value changed
+    pub fn apply(&mut self, update: &RuntimeConfig) -> ConfigDiff {
+        let before = self.fields();
+        macro_rules! merge {
+            ($($field:ident),*) => {
+                $(if update.$field.is_some() {
+                    self.$field = update.$field.clone();
+                })*
+            };
+        }
+        merge!(
+            log_level,
+            memory_thresholds,
+            frame_budget_ms,
+            refresh_rate_hz,
+            object_limits,
+            cache_dir,
+            jni_stats,
+            loader_path,
+            queue_priority,
+            global_priority,
+            protected_submission,
+            debug_layers
+        );
+
+        let changes: Vec<ConfigChange> = before
+            .into_iter()
+            .zip(self.fields())
+            .filter_map(|((field, old), (_, new))| {
+                let new = new?;
+                (old.as_ref() != Some(&new)).then(|| ConfigChange {
+                    field,
+                    old,
+                    new,
+                    reload: reload_kind(field),
+                })
+            })
+            .collect();
+        let diff = ConfigDiff { changes };
+        if !diff.is_empty() {
+            events::emit(GpuEvent::ConfigReloaded {
+                applied: diff.applied().iter().map(|f| f.to_string()).collect(),
+                pending_reinit: diff.pending_reinit().iter().map(|f| f.to_string()).collect(),
+            });
+        }
+        diff
+    }
+
+    pub fn log_level(&self) -> Option<log::LevelFilter> {
+        self.log_level.as_deref().and_then(|level| level.parse().ok())
+    }
+
+    /// Memory alert thresholds as fractions of the budget, if configured
+    pub fn memory_thresholds(&self) -> Option<Vec<f32>> {
+        self.memory_thresholds
+            .as_ref()
+            .map(|percents| percents.iter().map(|&p| p as f32 / 100.0).collect())
+    }
+
+    /// Frame budget to install: None if unset, Some(None) to disable pacing
+    pub fn frame_budget(&self) -> Option<Option<FrameBudget>> {
+        let ms = self.frame_budget_ms?;
+        let budget = std::time::Duration::from_secs_f32(ms / 1000.0);
+        Some(FrameBudget::new(budget, self.refresh_rate_hz.unwrap_or(60)))
+    }
+
+    /// Ceiling for every object kind, None for unlimited; None overall if unset
+    pub fn object_limits(&self) -> Option<Vec<(ObjectKind, Option<u64>)>> {
+        let limits = self.object_limits.as_ref()?;
+        Some(
+            ObjectKind::ALL
+                .iter()
+                .map(|&kind| (kind, limits.get(kind.name()).copied()))
+                .collect(),
+        )
+    }
+
+    /// Directory for on-disk caches, if caching is enabled
+    pub fn cache_dir(&self) -> Option<&std::path::Path> {
+        self.cache_dir.as_deref().filter(|dir| !dir.is_empty()).map(std::path::Path::new)
+    }
+
+    /// Logical device options, with defaults for unset fields
+    pub fn device_config(&self) -> DeviceConfig {
+        let defaults = DeviceConfig::default();
+        DeviceConfig {
+            queue_priority: self.queue_priority.unwrap_or(defaults.queue_priority),
+            global_priority: match &self.global_priority {
+                Some(name) => parse_global_priority(name).unwrap_or(defaults.global_priority),
+                None => defaults.global_priority,
+            },
+            protected_submission: self.protected_submission.unwrap_or(defaults.protected_submission),
+            ..defaults
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_reports_hot_and_cold_changes() {
+        let mut config = RuntimeConfig::from_json(r#"{"log_level":"info","queue_priority":0.5}"#).unwrap();
+        let update = RuntimeConfig::from_json(
+            r#"{"log_level":"debug","queue_priority":0.5,"global_priority":"none","memory_thresholds":[70]}"#,
+        )
+        .unwrap();
+        let diff = config.apply(&update);
+
+        assert_eq!(diff.applied(), vec!["log_level", "memory_thresholds"]);
+        assert_eq!(diff.pending_reinit(), vec!["global_priority"]);
+        assert_eq!(diff.changes[0].old.as_deref(), Some("info"));
+        assert_eq!(config.log_level(), Some(log::LevelFilter::Debug));
+        assert_eq!(config.memory_thresholds(), Some(vec![0.7]));
+        assert_eq!(config.device_config().global_priority, None);
+        assert_eq!(
+            diff.to_json(),
+            r#"{"changes":[{"field":"log_level","old":"info","new":"debug","reload":"hot"},{"field":"memory_thresholds","old":null,"new":"[70]","reload":"hot"},{"field":"global_priority","old":null,"new":"none","reload":"cold"}],"requires_reinit":true}"#
+        );
+
+        // Re-applying the same document changes nothing
+        assert!(config.apply(&update).is_empty());
+
+        let raw = "a\tb\n\"c\"\\";
+        let diff = ConfigDiff {
+            changes: vec![ConfigChange { field: "log_level", old: None, new: raw.to_string(), reload: Reload::Hot }],
+        };
+        let json: serde_json::Value = serde_json::from_str(&diff.to_json()).unwrap();
+        assert_eq!(json["changes"][0]["new"], raw);
+    }
+
+    #[test]
+    fn test_rejects_invalid_fields() {
+        assert!(matches!(RuntimeConfig::from_json(r#"{"queue_count":2}"#), Err(ConfigError::Malformed(_))));
+        for json in [
+            r#"{"log_level":"loud"}"#,
+            r#"{"memory_thresholds":[0]}"#,
+            r#"{"queue_priority":2.0}"#,
+            r#"{"global_priority":"urgent"}"#,
+            r#"{"frame_budget_ms":20,"refresh_rate_hz":60}"#,
+            r#"{"object_limits":{"buffers":10}}"#,
+        ] {
+            assert!(matches!(RuntimeConfig::from_json(json), Err(ConfigError::Invalid { .. })), "{}", json);
+        }
+
+        let config = RuntimeConfig::from_json(r#"{"frame_budget_ms":0,"object_limits":{"fence":64}}"#).unwrap();
+        assert_eq!(config.frame_budget(), Some(None));
+        let limits = config.object_limits().unwrap();
+        assert!(limits.contains(&(ObjectKind::Fence, Some(64))));
+        assert!(limits.contains(&(ObjectKind::Pipeline, None)));
+    }
+}
@@ -0,0 +1,206 @@
+//! Copy-on-write tensor clones
+//!
+//! `clone_cow` gives a tensor a second handle sharing its allocation, so
+//! forking a session (its KV cache, its sampler state) or sharing weights
+//! between sessions costs a table entry instead of a device copy. A real
+//! copy is made only when a sharer is about to be written: before running a
+//! plan, the backend passes the tensors bound to `graph::write_set` to
+//! `prepare_writes`, which moves every shared one to its own allocation
+//! first. Handles are resolved to the allocation actually holding the data
+//! with `resolve`; tensors never cloned resolve to themselves.
+
+use std::collections::HashMap;
+
+use ash::vk;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::memory::{MemoryAllocator, MemoryUsage};
+use crate::transfer::DataTransfer;
+
+/// Copy-on-write errors
+#[derive(Error, Debug)]
+pub enum CowError {
+    #[error("Tensor not found: {0}")]
+    NotFound(String),
+
+    #[error("Copy of {handle} failed: {reason}")]
+    CopyFailed { handle: String, reason: String },
+}
+
+pub type CowResult<T> = Result<T, CowError>;
+
+/// Logical tensor handles and the allocations backing them
+#[derive(Debug, Default)]
+pub struct CowTable {
+    /// Allocation behind each handle that is or has a clone
+    links: HashMap<String, String>,
+    /// Handles using each shared allocation
+    users: HashMap<String, usize>,
+}
+
+impl CowTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allocation holding the data of `handle`
+    pub fn resolve<'a>(&'a self, handle: &'a str) -> &'a str {
+        self.links.get(handle).map_or(handle, String::as_str)
+    }
+
+    /// Whether writing `handle` would need a copy first
+    pub fn is_shared(&self, handle: &str) -> bool {
+        self.users.get(self.resolve(handle)).is_some_and(|&n| n > 1)
+    }
+
+    /// A new handle sharing the allocation of `handle`
+    pub fn clone_cow(&mut self, handle: &str) -> String {
+        let allocation = self.resolve(handle).to_string();
+        if !self.links.contains_key(handle) {
+            self.links.insert(handle.to_string(), allocation.clone());
+            *self.users.entry(allocation.clone()).or_insert(0) += 1;
+        }
+        let clone = Uuid::new_v4().to_string();
+        self.links.insert(clone.clone(), allocation.clone());
+        *self.users.entry(allocation).or_insert(0) += 1;
+        clone
+    }
+
+    /// Give `handle` its own allocation if it shares one
+    ///
+    /// `copy` duplicates an allocation and returns the new allocation's
+    /// handle (see `device_copy`). Returns whether a copy was made.
+    pub fn prepare_write(
+        &mut self,
+        handle: &str,
+        copy: &mut impl FnMut(&str) -> Result<String, String>,
+    ) -> CowResult<bool> {
+        if !self.is_shared(handle) {
+            return Ok(false);
+        }
+        let allocation = self.resolve(handle).to_string();
+        let own = copy(&allocation).map_err(|reason| CowError::CopyFailed {
+            handle: handle.to_string(),
+            reason,
+        })?;
+        if let Some(users) = self.users.get_mut(&allocation) {
+            *users -= 1;
+        }
+        self.links.insert(handle.to_string(), own.clone());
+        self.users.insert(own, 1);
+        Ok(true)
+    }
+
+    /// `prepare_write` every tensor a plan is about to write
+    ///
+    /// # Returns
+    /// Number of copies made
+    pub fn prepare_writes<'a>(
+        &mut self,
+        handles: impl IntoIterator<Item = &'a str>,
+        mut copy: impl FnMut(&str) -> Result<String, String>,
+    ) -> CowResult<usize> {
+        let mut copies = 0;
+        for handle in handles {
+            copies += usize::from(self.prepare_write(handle, &mut copy)?);
+        }
+        Ok(copies)
+    }
+
+    /// Drop `handle`
+    ///
+    /// # Returns
+    /// The allocation to free, once no handle uses it
+    pub fn release(&mut self, handle: &str) -> Option<String> {
+        let Some(allocation) = self.links.remove(handle) else {
+            return Some(handle.to_string());
+        };
+        let users = self.users.get_mut(&allocation)?;
+        *users -= 1;
+        if *users > 0 {
+            return None;
+        }
+        self.users.remove(&allocation);
+        Some(allocation)
+    }
+}
+
+/// Copy an allocation into a new one of the same size and kind
+///
+/// The `copy` callback of `prepare_writes` for tensors living in `allocator`.
+///
+/// # Safety Requirements
+/// - `transfer` must belong to the allocator's device
+/// - No pending work may write the source allocation
+pub unsafe fn device_copy(
+    allocator: &mut MemoryAllocator,
+    transfer: &DataTransfer,
+    allocation: &str,
+) -> CowResult<String> {
+    let failed = |reason: String| CowError::CopyFailed {
+        handle: allocation.to_string(),
+        reason,
+    };
+    let src = allocator
+        .get_allocation(allocation)
+        .map_err(|_| CowError::NotFound(allocation.to_string()))?
+        .clone();
+    let usage = if src.memory_properties.contains(vk::MemoryPropertyFlags::DEVICE_LOCAL) {
+        MemoryUsage::DeviceLocal
+    } else {
+        MemoryUsage::Staging
+    };
+    let memory_type = allocator.memory_type_for(usage).map_err(|e| failed(e.to_string()))?;
+    let own = allocator
+        .allocate(src.size, memory_type, Uuid::new_v4().to_string())
+        .map_err(|e| failed(e.to_string()))?;
+    let copied = match allocator.get_allocation(&own) {
+        // SAFETY: both allocations are live on the transfer's device and
+        // distinct; the caller guarantees no pending writes to the source
+        Ok(dst) => unsafe { transfer.copy_device_to_device(&src, dst, src.size) }.map_err(|e| failed(e.to_string())),
+        Err(e) => Err(failed(e.to_string())),
+    };
+    if let Err(e) = copied {
+        let _ = allocator.deallocate(&own);
+        return Err(e);
+    }
+    Ok(own)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_splits_shared_tensor() {
+        let mut table = CowTable::new();
+        assert!(!table.is_shared("w"));
+        let fork = table.clone_cow("w");
+        assert!(table.is_shared("w") && table.is_shared(&fork));
+        assert_eq!(table.resolve(&fork), "w");
+
+        let mut copies = Vec::new();
+        let mut copy = |allocation: &str| -> Result<String, String> {
+            copies.push(allocation.to_string());
+            Ok(format!("{}-copy", allocation))
+        };
+        assert_eq!(table.prepare_writes([fork.as_str(), fork.as_str()], &mut copy).unwrap(), 1);
+        assert_eq!(copies, vec!["w".to_string()]);
+        assert_eq!(table.resolve(&fork), "w-copy");
+        assert_eq!(table.resolve("w"), "w");
+        assert!(!table.is_shared("w") && !table.is_shared(&fork));
+    }
+
+    #[test]
+    fn test_release_frees_last_user() {
+        let mut table = CowTable::new();
+        let a = table.clone_cow("w");
+        let b = table.clone_cow(&a);
+        assert_eq!(table.resolve(&b), "w");
+        assert_eq!(table.release("w"), None);
+        assert_eq!(table.release(&a), None);
+        assert_eq!(table.release(&b), Some("w".to_string()));
+        assert_eq!(table.release("plain"), Some("plain".to_string()));
+    }
+}
@@ -0,0 +1,238 @@
+//! Khronos validation layer and debug-utils messenger
+//!
+//! Opt-in debugging for backend bugs: with `EXO_VULKAN_DEBUG=1`,
+//! `set_enabled(true)` or `ContextBuilder::debug(true)`, the instance is
+//! created with `VK_LAYER_KHRONOS_validation` and a `VK_EXT_debug_utils`
+//! messenger that forwards every validation message to the `log` crate under
+//! the `vulkan` target: errors as errors, warnings as warnings, info as debug
+//! and verbose as trace. Validation errors are also counted, so CI can run
+//! a workload and assert `error_count()` stayed at zero.
+//!
+//! Android ships neither the layer nor the extension by default; the
+//! layer's `.so` must be packaged in the debug APK's `jniLibs`. Whatever is
+//! missing is logged and skipped, so enabling debug mode never stops Vulkan
+//! from initializing.
+
+use std::ffi::{CStr, c_void};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use ash::vk;
+
+use crate::{VulkanError, VulkanResult};
+
+/// Environment variable enabling debug mode: `1`, `true`, `on` or `yes`
+pub const DEBUG_ENV: &str = "EXO_VULKAN_DEBUG";
+
+pub const VALIDATION_LAYER: &CStr = c"VK_LAYER_KHRONOS_validation";
+
+/// `log` target of forwarded messages
+pub const LOG_TARGET: &str = "vulkan";
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static ERROR_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// Request debug mode for contexts created from now on, e.g. by `initialize_vulkan`
+///
+/// Takes effect at the next initialization, like `set_loader_path`.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether `set_enabled` or `EXO_VULKAN_DEBUG` asks for debug mode
+pub fn requested() -> bool {
+    ENABLED.load(Ordering::Relaxed) || std::env::var(DEBUG_ENV).is_ok_and(|v| parse_flag(&v))
+}
+
+fn parse_flag(value: &str) -> bool {
+    matches!(value.trim().to_ascii_lowercase().as_str(), "1" | "true" | "on" | "yes")
+}
+
+/// Validation errors reported since the process started
+pub fn error_count() -> u64 {
+    ERROR_COUNT.load(Ordering::Relaxed)
+}
+
+/// Log level of a message severity
+fn level(severity: vk::DebugUtilsMessageSeverityFlagsEXT) -> log::Level {
+    if severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR) {
+        log::Level::Error
+    } else if severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::WARNING) {
+        log::Level::Warn
+    } else if severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::INFO) {
+        log::Level::Debug
+    } else {
+        log::Level::Trace
+    }
+}
+
+unsafe extern "system" fn log_message(
+    severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    types: vk::DebugUtilsMessageTypeFlagsEXT,
+    data: *const vk::DebugUtilsMessengerCallbackDataEXT<'_>,
+    _user_data: *mut c_void,
+) -> vk::Bool32 {
+    let level = level(severity);
+    if level == log::Level::Error {
+        ERROR_COUNT.fetch_add(1, Ordering::Relaxed);
+    }
+    // SAFETY: the implementation passes valid callback data, whose strings
+    // are null or NUL-terminated, for the duration of the call
+    let (id, message) = unsafe {
+        match data.as_ref() {
+            Some(data) => (
+                data.message_id_name_as_c_str().map(CStr::to_string_lossy),
+                data.message_as_c_str().map(CStr::to_string_lossy),
+            ),
+            None => (None, None),
+        }
+    };
+    log::log!(
+        target: LOG_TARGET,
+        level,
+        "[{:?}] {}: {}",
+        types,
+        id.as_deref().unwrap_or("-"),
+        message.as_deref().unwrap_or("")
+    );
+    // Never abort the call that triggered the message
+    vk::FALSE
+}
+
+/// Messenger settings: every severity and type, routed to `log`
+///
+/// Also chained into `VkInstanceCreateInfo` so instance creation and
+/// destruction are covered.
+pub fn messenger_create_info() -> vk::DebugUtilsMessengerCreateInfoEXT<'static> {
+    vk::DebugUtilsMessengerCreateInfoEXT::default()
+        .message_severity(
+            vk::DebugUtilsMessageSeverityFlagsEXT::ERROR
+                | vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
+                | vk::DebugUtilsMessageSeverityFlagsEXT::INFO
+                | vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE,
+        )
+        .message_type(
+            vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
+                | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
+                | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
+        )
+        .pfn_user_callback(Some(log_message))
+}
+
+/// Layers and extensions debug mode can enable on this loader
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DebugSupport {
+    pub validation_layer: bool,
+    pub debug_utils: bool,
+}
+
+impl DebugSupport {
+    /// Query `entry` for debug mode, logging what is missing
+    ///
+    /// Failed queries count as unsupported.
+    pub fn query(entry: &ash::Entry) -> Self {
+        // SAFETY: entry is a loaded Vulkan entry point table
+        let layers = unsafe { entry.enumerate_instance_layer_properties() }.unwrap_or_default();
+        let validation_layer = layers
+            .iter()
+            .any(|layer| layer.layer_name_as_c_str() == Ok(VALIDATION_LAYER));
+        let has_debug_utils = |layer: Option<&CStr>| {
+            // SAFETY: as above; layer is null or a NUL-terminated name
+            unsafe { entry.enumerate_instance_extension_properties(layer) }
+                .unwrap_or_default()
+                .iter()
+                .any(|ext| ext.extension_name_as_c_str() == Ok(ash::ext::debug_utils::NAME))
+        };
+        // The validation layer provides the extension where the loader does not
+        let debug_utils = has_debug_utils(None) || (validation_layer && has_debug_utils(Some(VALIDATION_LAYER)));
+        if !validation_layer {
+            log::warn!("Debug mode requested but {:?} is not installed", VALIDATION_LAYER);
+        }
+        if !debug_utils {
+            log::warn!("Debug mode requested but {:?} is unavailable", ash::ext::debug_utils::NAME);
+        }
+        Self {
+            validation_layer,
+            debug_utils,
+        }
+    }
+
+    /// Layer names for `VkInstanceCreateInfo`
+    pub fn layers(&self) -> Vec<*const std::ffi::c_char> {
+        self.validation_layer
+            .then_some(VALIDATION_LAYER.as_ptr())
+            .into_iter()
+            .collect()
+    }
+
+    /// Extension names for `VkInstanceCreateInfo`
+    pub fn extensions(&self) -> Vec<*const std::ffi::c_char> {
+        self.debug_utils
+            .then_some(ash::ext::debug_utils::NAME.as_ptr())
+            .into_iter()
+            .collect()
+    }
+}
+
+/// An installed messenger; destroy it before its instance
+pub struct DebugMessenger {
+    loader: ash::ext::debug_utils::Instance,
+    messenger: vk::DebugUtilsMessengerEXT,
+}
+
+impl DebugMessenger {
+    /// Install the `log` messenger on `instance`, created with `VK_EXT_debug_utils`
+    pub fn new(entry: &ash::Entry, instance: &ash::Instance) -> VulkanResult<Self> {
+        let loader = ash::ext::debug_utils::Instance::new(entry, instance);
+        // SAFETY: the instance was created with the extension enabled and the
+        // callback is a 'static function
+        let messenger = unsafe { loader.create_debug_utils_messenger(&messenger_create_info(), None) }
+            .map_err(VulkanError::VulkanError)?;
+        Ok(Self { loader, messenger })
+    }
+
+    /// Remove the messenger
+    ///
+    /// # Safety Requirements
+    /// - Must be called once, before the instance it was created on is destroyed
+    pub unsafe fn destroy(&self) {
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe { self.loader.destroy_debug_utils_messenger(self.messenger, None) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flags_and_levels() {
+        for on in ["1", "true", " ON ", "yes"] {
+            assert!(parse_flag(on), "{}", on);
+        }
+        for off in ["", "0", "false", "debug"] {
+            assert!(!parse_flag(off), "{}", off);
+        }
+        assert_eq!(level(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR), log::Level::Error);
+        assert_eq!(level(vk::DebugUtilsMessageSeverityFlagsEXT::WARNING), log::Level::Warn);
+        assert_eq!(level(vk::DebugUtilsMessageSeverityFlagsEXT::INFO), log::Level::Debug);
+        assert_eq!(level(vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE), log::Level::Trace);
+    }
+
+    #[test]
+    fn test_callback_counts_errors() {
+        let before = error_count();
+        let data = vk::DebugUtilsMessengerCallbackDataEXT::default().message(c"vkCmdDispatch: no pipeline bound");
+        // SAFETY: data is valid for the call
+        let result = unsafe {
+            log_message(
+                vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
+                vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION,
+                &data,
+                std::ptr::null_mut(),
+            )
+        };
+        assert_eq!(result, vk::FALSE);
+        assert!(error_count() > before);
+        assert_eq!(DebugSupport::default().layers().len(), 0);
+    }
+}
@@ -0,0 +1,335 @@
+//! Descriptor pools and sets
+//!
+//! `DescriptorManager` hands out descriptor sets for storage and uniform
+//! buffer bindings without callers sizing pools up front. Sets come from a
+//! chain of pools; when the newest one is exhausted
+//! (`ERROR_OUT_OF_POOL_MEMORY` or `ERROR_FRAGMENTED_POOL`) another pool,
+//! twice as large up to `MAX_SETS_PER_POOL`, is created and the allocation
+//! retried. Freed sets are kept per layout and handed back on the next
+//! allocation with that layout rather than returned to the driver, so a
+//! steady decode loop stops allocating after its first few tokens.
+//!
+//! Pools count against the `DescriptorPool` object budget.
+
+use std::collections::HashMap;
+
+use ash::vk;
+use thiserror::Error;
+
+use crate::memory::BufferRange;
+use crate::object_budget::{self, BudgetError, ObjectKind};
+
+/// Descriptor errors
+#[derive(Error, Debug)]
+pub enum DescriptorError {
+    #[error(transparent)]
+    Budget(#[from] BudgetError),
+
+    #[error("Descriptor set {0:?} was not allocated by this manager")]
+    UnknownSet(vk::DescriptorSet),
+
+    #[error("Vulkan error: {0:?}")]
+    VulkanError(vk::Result),
+}
+
+pub type DescriptorResult<T> = Result<T, DescriptorError>;
+
+/// Sets the first pool holds
+pub const INITIAL_SETS_PER_POOL: u32 = 32;
+
+/// Largest pool the manager grows to
+pub const MAX_SETS_PER_POOL: u32 = 1024;
+
+/// Descriptors of each type reserved per set in a pool
+pub const STORAGE_BUFFERS_PER_SET: u32 = 8;
+pub const UNIFORM_BUFFERS_PER_SET: u32 = 2;
+
+/// Sets the `index`th pool of a chain holds
+pub fn pool_capacity(index: usize) -> u32 {
+    let shift = index.min(31) as u32;
+    INITIAL_SETS_PER_POOL.saturating_mul(1 << shift).min(MAX_SETS_PER_POOL)
+}
+
+/// Whether an allocation failure means "try another pool"
+fn is_pool_exhausted(result: vk::Result) -> bool {
+    matches!(result, vk::Result::ERROR_OUT_OF_POOL_MEMORY | vk::Result::ERROR_FRAGMENTED_POOL)
+}
+
+/// Buffer descriptor kinds the manager writes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DescriptorKind {
+    StorageBuffer,
+    UniformBuffer,
+}
+
+impl DescriptorKind {
+    pub fn vk_type(self) -> vk::DescriptorType {
+        match self {
+            DescriptorKind::StorageBuffer => vk::DescriptorType::STORAGE_BUFFER,
+            DescriptorKind::UniformBuffer => vk::DescriptorType::UNIFORM_BUFFER,
+        }
+    }
+}
+
+/// One buffer binding to write into a set
+#[derive(Clone, Copy, Debug)]
+pub struct BufferWrite<'a> {
+    pub binding: u32,
+    pub kind: DescriptorKind,
+    pub range: &'a BufferRange,
+}
+
+impl<'a> BufferWrite<'a> {
+    pub fn storage(binding: u32, range: &'a BufferRange) -> Self {
+        Self {
+            binding,
+            kind: DescriptorKind::StorageBuffer,
+            range,
+        }
+    }
+
+    pub fn uniform(binding: u32, range: &'a BufferRange) -> Self {
+        Self {
+            binding,
+            kind: DescriptorKind::UniformBuffer,
+            range,
+        }
+    }
+}
+
+/// Pool and set counters
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DescriptorStats {
+    pub pools: usize,
+    /// Sets currently handed out
+    pub live_sets: u64,
+    /// Freed sets waiting for reuse
+    pub recycled_sets: u64,
+    /// Allocations served from the recycled sets
+    pub reuses: u64,
+}
+
+/// Freed sets per layout, and which layout each live set has
+#[derive(Default)]
+struct SetRecycler {
+    free: HashMap<vk::DescriptorSetLayout, Vec<vk::DescriptorSet>>,
+    live: HashMap<vk::DescriptorSet, vk::DescriptorSetLayout>,
+    reuses: u64,
+}
+
+impl SetRecycler {
+    fn take(&mut self, layout: vk::DescriptorSetLayout) -> Option<vk::DescriptorSet> {
+        let set = self.free.get_mut(&layout).and_then(Vec::pop)?;
+        self.live.insert(set, layout);
+        self.reuses += 1;
+        Some(set)
+    }
+
+    fn track(&mut self, set: vk::DescriptorSet, layout: vk::DescriptorSetLayout) {
+        self.live.insert(set, layout);
+    }
+
+    fn give_back(&mut self, set: vk::DescriptorSet) -> DescriptorResult<()> {
+        let layout = self.live.remove(&set).ok_or(DescriptorError::UnknownSet(set))?;
+        self.free.entry(layout).or_default().push(set);
+        Ok(())
+    }
+
+    fn recycled(&self) -> u64 {
+        self.free.values().map(|sets| sets.len() as u64).sum()
+    }
+
+    fn clear(&mut self) {
+        self.free.clear();
+        self.live.clear();
+    }
+}
+
+/// Growing chain of descriptor pools with per-layout set recycling
+pub struct DescriptorManager {
+    device: ash::Device,
+    pools: Vec<vk::DescriptorPool>,
+    recycler: SetRecycler,
+}
+
+impl DescriptorManager {
+    /// Create a manager; the first pool is created on first allocation
+    ///
+    /// `device` must outlive the manager.
+    pub fn new(device: &ash::Device) -> Self {
+        Self {
+            device: device.clone(),
+            pools: Vec::new(),
+            recycler: SetRecycler::default(),
+        }
+    }
+
+    fn create_pool(&mut self) -> DescriptorResult<vk::DescriptorPool> {
+        object_budget::acquire(ObjectKind::DescriptorPool, 1)?;
+        let max_sets = pool_capacity(self.pools.len());
+        let pool_sizes = [
+            vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(max_sets * STORAGE_BUFFERS_PER_SET),
+            vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::UNIFORM_BUFFER)
+                .descriptor_count(max_sets * UNIFORM_BUFFERS_PER_SET),
+        ];
+        let info = vk::DescriptorPoolCreateInfo::default()
+            .max_sets(max_sets)
+            .pool_sizes(&pool_sizes);
+        // SAFETY: device is valid per `new`; info only points at locals
+        let pool = match unsafe { self.device.create_descriptor_pool(&info, None) } {
+            Ok(pool) => pool,
+            Err(e) => {
+                object_budget::release(ObjectKind::DescriptorPool, 1);
+                return Err(DescriptorError::VulkanError(e));
+            }
+        };
+        log::debug!("Created descriptor pool {} ({} sets)", self.pools.len(), max_sets);
+        self.pools.push(pool);
+        Ok(pool)
+    }
+
+    fn allocate_from(&self, pool: vk::DescriptorPool, layout: vk::DescriptorSetLayout) -> Result<vk::DescriptorSet, vk::Result> {
+        let set_layouts = [layout];
+        let info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(pool)
+            .set_layouts(&set_layouts);
+        // SAFETY: pool was created from self.device; the caller's layout is too
+        unsafe { self.device.allocate_descriptor_sets(&info) }.map(|sets| sets[0])
+    }
+
+    /// A set with `layout`, recycled if one was freed, else newly allocated
+    ///
+    /// The layout may use at most `STORAGE_BUFFERS_PER_SET` storage and
+    /// `UNIFORM_BUFFERS_PER_SET` uniform buffer descriptors.
+    pub fn allocate(&mut self, layout: vk::DescriptorSetLayout) -> DescriptorResult<vk::DescriptorSet> {
+        if let Some(set) = self.recycler.take(layout) {
+            return Ok(set);
+        }
+        let set = match self.pools.last() {
+            Some(&pool) => match self.allocate_from(pool, layout) {
+                Ok(set) => set,
+                Err(e) if is_pool_exhausted(e) => {
+                    let pool = self.create_pool()?;
+                    self.allocate_from(pool, layout).map_err(DescriptorError::VulkanError)?
+                }
+                Err(e) => return Err(DescriptorError::VulkanError(e)),
+            },
+            None => {
+                let pool = self.create_pool()?;
+                self.allocate_from(pool, layout).map_err(DescriptorError::VulkanError)?
+            }
+        };
+        self.recycler.track(set, layout);
+        Ok(set)
+    }
+
+    /// Point `set`'s bindings at buffers
+    ///
+    /// # Safety Requirements
+    /// - `set` must not be in use by pending work
+    /// - Every range must lie inside a live buffer of this device
+    pub unsafe fn write(&self, set: vk::DescriptorSet, writes: &[BufferWrite]) {
+        let infos: Vec<[vk::DescriptorBufferInfo; 1]> = writes.iter().map(|w| [w.range.descriptor_info()]).collect();
+        let descriptor_writes: Vec<vk::WriteDescriptorSet> = writes
+            .iter()
+            .zip(&infos)
+            .map(|(w, info)| {
+                vk::WriteDescriptorSet::default()
+                    .dst_set(set)
+                    .dst_binding(w.binding)
+                    .descriptor_type(w.kind.vk_type())
+                    .buffer_info(info)
+            })
+            .collect();
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe { self.device.update_descriptor_sets(&descriptor_writes, &[]) };
+    }
+
+    /// `allocate` followed by `write`
+    ///
+    /// # Safety Requirements
+    /// - As for `write`
+    pub unsafe fn allocate_written(
+        &mut self,
+        layout: vk::DescriptorSetLayout,
+        writes: &[BufferWrite],
+    ) -> DescriptorResult<vk::DescriptorSet> {
+        let set = self.allocate(layout)?;
+        // SAFETY: forwarded from the caller's guarantees; a fresh or recycled set is idle
+        unsafe { self.write(set, writes) };
+        Ok(set)
+    }
+
+    /// Return `set` for reuse by the next allocation with its layout
+    ///
+    /// The caller must ensure no pending work still uses it.
+    pub fn free(&mut self, set: vk::DescriptorSet) -> DescriptorResult<()> {
+        self.recycler.give_back(set)
+    }
+
+    /// Return every set to the pools at once, e.g. between sessions
+    ///
+    /// # Safety Requirements
+    /// - No pending work may use any set from this manager
+    pub unsafe fn reset(&mut self) -> DescriptorResult<()> {
+        for &pool in &self.pools {
+            // SAFETY: pool was created from self.device; caller guarantees it is idle
+            unsafe { self.device.reset_descriptor_pool(pool, vk::DescriptorPoolResetFlags::empty()) }
+                .map_err(DescriptorError::VulkanError)?;
+        }
+        self.recycler.clear();
+        Ok(())
+    }
+
+    pub fn stats(&self) -> DescriptorStats {
+        DescriptorStats {
+            pools: self.pools.len(),
+            live_sets: self.recycler.live.len() as u64,
+            recycled_sets: self.recycler.recycled(),
+            reuses: self.recycler.reuses,
+        }
+    }
+}
+
+impl Drop for DescriptorManager {
+    fn drop(&mut self) {
+        for pool in self.pools.drain(..) {
+            // SAFETY: pool was created from self.device; the owner guarantees
+            // no pending work uses its sets
+            unsafe { self.device.destroy_descriptor_pool(pool, None) };
+            object_budget::release(ObjectKind::DescriptorPool, 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ash::vk::Handle;
+
+    #[test]
+    fn test_pools_double_up_to_cap() {
+        assert_eq!(pool_capacity(0), INITIAL_SETS_PER_POOL);
+        assert_eq!(pool_capacity(1), INITIAL_SETS_PER_POOL * 2);
+        assert_eq!(pool_capacity(40), MAX_SETS_PER_POOL);
+        assert!(is_pool_exhausted(vk::Result::ERROR_FRAGMENTED_POOL));
+        assert!(!is_pool_exhausted(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY));
+    }
+
+    #[test]
+    fn test_freed_sets_reused_per_layout() {
+        let (layout_a, layout_b) = (vk::DescriptorSetLayout::from_raw(1), vk::DescriptorSetLayout::from_raw(2));
+        let set = vk::DescriptorSet::from_raw(10);
+        let mut recycler = SetRecycler::default();
+        recycler.track(set, layout_a);
+        recycler.give_back(set).unwrap();
+        assert!(matches!(recycler.give_back(set), Err(DescriptorError::UnknownSet(_))));
+
+        assert_eq!(recycler.take(layout_b), None);
+        assert_eq!(recycler.take(layout_a), Some(set));
+        assert_eq!((recycler.recycled(), recycler.reuses), (0, 1));
+    }
+}
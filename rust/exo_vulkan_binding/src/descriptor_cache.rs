@@ -0,0 +1,268 @@
+//! Persistent descriptor sets
+//!
+//! A decode step dispatches the same kernels over the same weight and
+//! activation buffers token after token, yet rewriting every descriptor set
+//! before each dispatch is a measurable share of recording time. The cache
+//! keeps one written set per (kernel, tensor set), where the tensor set is
+//! the allocation handles in binding order, and hands it back on the next
+//! dispatch with the same key.
+//!
+//! Entries go stale when an allocation they reference is freed or resized
+//! (a set binds the whole allocation at its size when written, and growing
+//! also replaces the buffer), so the cache subscribes to the event bus and
+//! drops every entry naming a freed or resized handle. Dropped sets are kept per kernel and reused on the next miss of
+//! that kernel instead of allocating a new one. This is safe because a
+//! buffer may only be freed once no pending work uses it, and so no pending
+//! work uses a set that referenced it either.
+
+use std::collections::{HashMap, HashSet};
+
+use ash::vk;
+use tokio::sync::broadcast;
+
+use crate::events::{self, GpuEvent};
+
+/// Cache key: kernel name and allocation handles in binding order
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DescriptorKey {
+    pub kernel: String,
+    pub handles: Vec<String>,
+}
+
+impl DescriptorKey {
+    pub fn new(kernel: &str, handles: &[&str]) -> Self {
+        Self {
+            kernel: kernel.to_string(),
+            handles: handles.iter().map(|h| h.to_string()).collect(),
+        }
+    }
+}
+
+/// Cache effectiveness counters
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DescriptorCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Entries dropped because an allocation they referenced went away
+    pub invalidations: u64,
+}
+
+/// Written descriptor sets keyed by (kernel, tensor set)
+pub struct DescriptorCache {
+    entries: HashMap<DescriptorKey, vk::DescriptorSet>,
+    /// Keys referencing each handle
+    by_handle: HashMap<String, HashSet<DescriptorKey>>,
+    /// Invalidated sets per kernel, ready to be rewritten
+    recycled: HashMap<String, Vec<vk::DescriptorSet>>,
+    events: broadcast::Receiver<GpuEvent>,
+    stats: DescriptorCacheStats,
+}
+
+impl Default for DescriptorCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DescriptorCache {
+    /// Create an empty cache listening for frees from now on
+    pub fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+            by_handle: HashMap::new(),
+            recycled: HashMap::new(),
+            events: events::subscribe(),
+            stats: DescriptorCacheStats::default(),
+        }
+    }
+
+    /// The set for `key`, writing one on a miss
+    ///
+    /// On a miss `write` receives a recycled set of the same kernel if one is
+    /// available (or None, in which case it allocates one), points its
+    /// bindings at the key's buffers and returns it.
+    pub fn get_or_write<E>(
+        &mut self,
+        key: &DescriptorKey,
+        write: impl FnOnce(Option<vk::DescriptorSet>) -> Result<vk::DescriptorSet, E>,
+    ) -> Result<vk::DescriptorSet, E> {
+        self.process_events();
+        if let Some(&set) = self.entries.get(key) {
+            self.stats.hits += 1;
+            return Ok(set);
+        }
+
+        self.stats.misses += 1;
+        let recycled = self.recycled.get_mut(&key.kernel).and_then(Vec::pop);
+        let set = match write(recycled) {
+            Ok(set) => set,
+            Err(e) => {
+                // The set was not written; keep it for the next attempt
+                if let Some(set) = recycled {
+                    self.recycled.entry(key.kernel.clone()).or_default().push(set);
+                }
+                return Err(e);
+            }
+        };
+        for handle in &key.handles {
+            self.by_handle.entry(handle.clone()).or_default().insert(key.clone());
+        }
+        self.entries.insert(key.clone(), set);
+        Ok(set)
+    }
+
+    /// Apply frees and resizes reported on the event bus since the last call
+    ///
+    /// If the subscription lagged, events may have been missed and every
+    /// entry is dropped.
+    pub fn process_events(&mut self) {
+        loop {
+            match self.events.try_recv() {
+                Ok(GpuEvent::Freed { handle_id, .. } | GpuEvent::Resized { handle_id, .. }) => {
+                    self.invalidate_handle(&handle_id);
+                }
+                Ok(_) => {}
+                Err(broadcast::error::TryRecvError::Lagged(_)) => self.invalidate_all(),
+                Err(_) => break,
+            }
+        }
+    }
+
+    /// Drop every entry referencing `handle_id`
+    ///
+    /// # Returns
+    /// Number of entries dropped
+    pub fn invalidate_handle(&mut self, handle_id: &str) -> usize {
+        let Some(keys) = self.by_handle.remove(handle_id) else {
+            return 0;
+        };
+        let count = keys.len();
+        for key in keys {
+            self.remove(&key);
+        }
+        count
+    }
+
+    /// Drop every entry of `kernel`, e.g. when its pipeline is destroyed
+    ///
+    /// Its recycled sets are returned too, for the caller to free.
+    pub fn invalidate_kernel(&mut self, kernel: &str) -> Vec<vk::DescriptorSet> {
+        let keys: Vec<DescriptorKey> = self.entries.keys().filter(|k| k.kernel == kernel).cloned().collect();
+        for key in &keys {
+            self.remove(key);
+        }
+        self.recycled.remove(kernel).unwrap_or_default()
+    }
+
+    /// Drop every entry; sets stay available for reuse
+    pub fn invalidate_all(&mut self) {
+        let keys: Vec<DescriptorKey> = self.entries.keys().cloned().collect();
+        for key in &keys {
+            self.remove(key);
+        }
+    }
+
+    fn remove(&mut self, key: &DescriptorKey) {
+        let Some(set) = self.entries.remove(key) else {
+            return;
+        };
+        for handle in &key.handles {
+            if let Some(keys) = self.by_handle.get_mut(handle) {
+                keys.remove(key);
+                if keys.is_empty() {
+                    self.by_handle.remove(handle);
+                }
+            }
+        }
+        self.recycled.entry(key.kernel.clone()).or_default().push(set);
+        self.stats.invalidations += 1;
+    }
+
+    /// Number of live entries
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn stats(&self) -> DescriptorCacheStats {
+        self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ash::vk::Handle;
+
+    fn set(raw: u64) -> vk::DescriptorSet {
+        vk::DescriptorSet::from_raw(raw)
+    }
+
+    #[test]
+    fn test_repeated_dispatch_reuses_set() {
+        let mut cache = DescriptorCache::new();
+        let key = DescriptorKey::new("matmul", &["dc_reuse_w", "dc_reuse_x"]);
+        let written = cache.get_or_write(&key, |recycled| {
+            assert!(recycled.is_none());
+            Ok::<_, ()>(set(1))
+        });
+        assert_eq!(written, Ok(set(1)));
+        let again = cache.get_or_write(&key, |_| -> Result<_, ()> { panic!("should hit") });
+        assert_eq!(again, Ok(set(1)));
+        assert_eq!((cache.stats().hits, cache.stats().misses), (1, 1));
+
+        // A failed write leaves nothing cached
+        let other = DescriptorKey::new("matmul", &["dc_reuse_w", "dc_reuse_y"]);
+        assert_eq!(cache.get_or_write(&other, |_| Err("no pool")), Err("no pool"));
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_freed_allocation_invalidates_and_recycles() {
+        let mut cache = DescriptorCache::new();
+        let a = DescriptorKey::new("softmax", &["dc_free_a", "dc_free_out"]);
+        let b = DescriptorKey::new("gelu", &["dc_free_b", "dc_free_out"]);
+        cache.get_or_write(&a, |_| Ok::<_, ()>(set(1))).unwrap();
+        cache.get_or_write(&b, |_| Ok::<_, ()>(set(2))).unwrap();
+
+        events::emit(GpuEvent::Freed {
+            handle_id: "dc_free_a".to_string(),
+            size: 64,
+        });
+        cache.process_events();
+        assert_eq!(cache.len(), 1);
+
+        // The next softmax miss rewrites the invalidated set
+        let c = DescriptorKey::new("softmax", &["dc_free_c", "dc_free_out"]);
+        let written = cache.get_or_write(&c, |recycled| Ok::<_, ()>(recycled.unwrap_or(set(3))));
+        assert_eq!(written, Ok(set(1)));
+
+        assert_eq!(cache.invalidate_handle("dc_free_out"), 2);
+        assert!(cache.is_empty());
+        assert_eq!(cache.invalidate_kernel("gelu"), vec![set(2)]);
+        assert_eq!(cache.stats().invalidations, 3);
+    }
+
+    #[test]
+    fn test_shrunk_allocation_invalidates() {
+        let mut cache = DescriptorCache::new();
+        let key = DescriptorKey::new("rmsnorm", &["dc_shrink_x", "dc_shrink_out"]);
+        cache.get_or_write(&key, |_| Ok::<_, ()>(set(1))).unwrap();
+
+        // What `MemoryAllocator::resize` reports when shrinking in place,
+        // where the buffer and its handle stay the same
+        events::emit(GpuEvent::Resized {
+            handle_id: "dc_shrink_x".to_string(),
+            old_size: 4096,
+            new_size: 1024,
+        });
+        cache.process_events();
+        assert!(cache.is_empty());
+        let rewritten = cache.get_or_write(&key, |recycled| Ok::<_, ()>(recycled.unwrap_or(set(2))));
+        assert_eq!(rewritten, Ok(set(1)));
+        assert_eq!(cache.stats().misses, 2);
+    }
+}
@@ -0,0 +1,589 @@
+//! Logical device creation
+//!
+//! Describes how exo's logical device and queues should be created. On phones
+//! the GPU is shared with UI compositing and games, so the defaults ask for a
+//! below-normal queue priority and, where `VK_EXT_global_priority` /
+//! `VK_KHR_global_priority` is available, a LOW system-wide priority.
+//!
+//! `LogicalDevice` creates the device itself: it picks a compute queue family
+//! (a dedicated one when the GPU has it) and, where present, a transfer-only
+//! family for DMA uploads, enables the configured extensions and features, and
+//! hands out the `MemoryAllocator`, `CommandPool` and `DataTransfer` built on it.
+//! `VK_KHR_timeline_semaphore` is enabled wherever the device supports it.
+
+use std::ffi::{CStr, CString};
+use std::sync::Arc;
+
+use ash::vk;
+use thiserror::Error;
+
+use crate::command::{CommandError, CommandPool, Queue, TimelineSemaphore};
+use crate::memory::MemoryAllocator;
+use crate::transfer::DataTransfer;
+use crate::{DeviceCapabilities, VulkanContext, VulkanError};
+
+/// Logical device errors
+#[derive(Error, Debug)]
+pub enum DeviceError {
+    #[error("Device has no compute queue family")]
+    NoComputeQueue,
+
+    #[error("Device has no protected compute queue family")]
+    NoProtectedQueue,
+
+    #[error("Required extension not supported: {0}")]
+    MissingExtension(String),
+
+    #[error("Required feature not supported: {0}")]
+    MissingFeature(&'static str),
+
+    #[error(transparent)]
+    Context(#[from] VulkanError),
+
+    #[error(transparent)]
+    Command(#[from] CommandError),
+
+    #[error("Vulkan error: {0:?}")]
+    VulkanError(vk::Result),
+}
+
+pub type DeviceResult<T> = Result<T, DeviceError>;
+
+/// System-wide queue priority (`VK_EXT_global_priority`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GlobalPriority {
+    Low,
+    Medium,
+    High,
+    Realtime,
+}
+
+impl GlobalPriority {
+    /// Vulkan enum value
+    pub fn to_vk(self) -> vk::QueueGlobalPriorityKHR {
+        match self {
+            GlobalPriority::Low => vk::QueueGlobalPriorityKHR::LOW,
+            GlobalPriority::Medium => vk::QueueGlobalPriorityKHR::MEDIUM,
+            GlobalPriority::High => vk::QueueGlobalPriorityKHR::HIGH,
+            GlobalPriority::Realtime => vk::QueueGlobalPriorityKHR::REALTIME,
+        }
+    }
+}
+
+/// Optional core features a kernel may depend on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeviceFeature {
+    ShaderInt16,
+    ShaderInt64,
+    ShaderFloat64,
+    RobustBufferAccess,
+}
+
+impl DeviceFeature {
+    /// Vulkan name of the feature
+    pub fn name(self) -> &'static str {
+        match self {
+            DeviceFeature::ShaderInt16 => "shaderInt16",
+            DeviceFeature::ShaderInt64 => "shaderInt64",
+            DeviceFeature::ShaderFloat64 => "shaderFloat64",
+            DeviceFeature::RobustBufferAccess => "robustBufferAccess",
+        }
+    }
+
+    pub fn is_supported(self, features: &vk::PhysicalDeviceFeatures) -> bool {
+        let flag = match self {
+            DeviceFeature::ShaderInt16 => features.shader_int16,
+            DeviceFeature::ShaderInt64 => features.shader_int64,
+            DeviceFeature::ShaderFloat64 => features.shader_float64,
+            DeviceFeature::RobustBufferAccess => features.robust_buffer_access,
+        };
+        flag == vk::TRUE
+    }
+
+    fn enable(self, features: vk::PhysicalDeviceFeatures) -> vk::PhysicalDeviceFeatures {
+        match self {
+            DeviceFeature::ShaderInt16 => features.shader_int16(true),
+            DeviceFeature::ShaderInt64 => features.shader_int64(true),
+            DeviceFeature::ShaderFloat64 => features.shader_float64(true),
+            DeviceFeature::RobustBufferAccess => features.robust_buffer_access(true),
+        }
+    }
+}
+
+/// Options for creating exo's logical device
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeviceConfig {
+    /// Relative priority of exo's queues within the process, 0.0..=1.0
+    pub queue_priority: f32,
+    /// System-wide priority, applied only when the device supports it
+    pub global_priority: Option<GlobalPriority>,
+    /// Request a protected-capable queue (requires the protectedMemory feature)
+    pub protected_submission: bool,
+    /// Extensions device creation fails without
+    pub extensions: Vec<String>,
+    /// Extensions enabled when the device supports them, e.g. `VK_EXT_external_memory_host`
+    pub optional_extensions: Vec<String>,
+    /// Features device creation fails without
+    pub features: Vec<DeviceFeature>,
+}
+
+impl Default for DeviceConfig {
+    fn default() -> Self {
+        Self {
+            queue_priority: 0.5,
+            global_priority: Some(GlobalPriority::Low),
+            protected_submission: false,
+            extensions: Vec::new(),
+            optional_extensions: Vec::new(),
+            features: Vec::new(),
+        }
+    }
+}
+
+impl DeviceConfig {
+    /// Queue priority clamped to the range Vulkan accepts
+    pub fn clamped_queue_priority(&self) -> f32 {
+        if self.queue_priority.is_nan() {
+            0.5
+        } else {
+            self.queue_priority.clamp(0.0, 1.0)
+        }
+    }
+
+    /// Name of the global priority extension to enable, if requested and supported
+    pub fn global_priority_extension(&self, capabilities: &DeviceCapabilities) -> Option<&'static CStr> {
+        self.global_priority?;
+        [ash::khr::global_priority::NAME, ash::ext::global_priority::NAME]
+            .into_iter()
+            .find(|name| capabilities.has_extension(&name.to_string_lossy()))
+    }
+
+    /// Global priority to chain into queue creation, if it can be honored
+    pub fn effective_global_priority(&self, capabilities: &DeviceCapabilities) -> Option<GlobalPriority> {
+        let priority = self.global_priority?;
+        if self.global_priority_extension(capabilities).is_none() {
+            log::info!("Global queue priority {:?} unsupported; using default", priority);
+            return None;
+        }
+        Some(priority)
+    }
+
+    /// Queue creation flags for this configuration
+    pub fn queue_create_flags(&self) -> vk::DeviceQueueCreateFlags {
+        if self.protected_submission {
+            vk::DeviceQueueCreateFlags::PROTECTED
+        } else {
+            vk::DeviceQueueCreateFlags::empty()
+        }
+    }
+
+    /// Extensions to enable on a device with `capabilities`, global priority excluded
+    ///
+    /// Fails on the first required extension the device lacks; optional ones
+    /// it lacks are skipped.
+    pub fn resolve_extensions(&self, capabilities: &DeviceCapabilities) -> DeviceResult<Vec<String>> {
+        if let Some(missing) = self.extensions.iter().find(|name| !capabilities.has_extension(name)) {
+            return Err(DeviceError::MissingExtension(missing.clone()));
+        }
+        let mut enabled = self.extensions.clone();
+        for name in &self.optional_extensions {
+            if !capabilities.has_extension(name) {
+                log::info!("Optional extension {} unsupported", name);
+            } else if !enabled.contains(name) {
+                enabled.push(name.clone());
+            }
+        }
+        Ok(enabled)
+    }
+
+    /// Core features to enable on a device with `capabilities`
+    pub fn resolve_features(&self, capabilities: &DeviceCapabilities) -> DeviceResult<vk::PhysicalDeviceFeatures> {
+        self.features
+            .iter()
+            .try_fold(vk::PhysicalDeviceFeatures::default(), |enabled, &feature| {
+                if feature.is_supported(&capabilities.features) {
+                    Ok(feature.enable(enabled))
+                } else {
+                    Err(DeviceError::MissingFeature(feature.name()))
+                }
+            })
+    }
+
+    /// Build the create info for one queue family
+    ///
+    /// `priorities` holds one entry per queue and `global` receives the global
+    /// priority struct chained into the result, so both must outlive it.
+    pub fn queue_create_info<'a>(
+        &self,
+        queue_family_index: u32,
+        priorities: &'a [f32],
+        global: Option<&'a mut vk::DeviceQueueGlobalPriorityCreateInfoKHR<'a>>,
+    ) -> vk::DeviceQueueCreateInfo<'a> {
+        let info = vk::DeviceQueueCreateInfo::default()
+            .flags(self.queue_create_flags())
+            .queue_family_index(queue_family_index)
+            .queue_priorities(priorities);
+        match global {
+            Some(global) => info.push_next(global),
+            None => info,
+        }
+    }
+}
+
+/// Queue families exo uses on one device
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QueueSelection {
+    /// Family for dispatches
+    pub compute: u32,
+    /// Transfer-only family for uploads, when the device has one
+    pub transfer: Option<u32>,
+}
+
+/// Choose queue families from a device's `queue_families`
+///
+/// Prefers a compute family without graphics, whose queues do not compete
+/// with rendering; otherwise takes the first compute family. With
+/// `protected`, only families supporting protected submission qualify for
+/// compute. The transfer family must support neither graphics nor compute.
+pub fn select_queue_families(queue_families: &[vk::QueueFamilyProperties], protected: bool) -> Option<QueueSelection> {
+    let usable = |flags: vk::QueueFlags| {
+        flags.contains(vk::QueueFlags::COMPUTE) && (!protected || flags.contains(vk::QueueFlags::PROTECTED))
+    };
+    let position = |pred: &dyn Fn(vk::QueueFlags) -> bool| {
+        queue_families
+            .iter()
+            .position(|family| family.queue_count > 0 && pred(family.queue_flags))
+            .map(|index| index as u32)
+    };
+    let compute = position(&|flags| usable(flags) && !flags.contains(vk::QueueFlags::GRAPHICS))
+        .or_else(|| position(&usable))?;
+    let transfer = position(&|flags| {
+        flags.contains(vk::QueueFlags::TRANSFER)
+            && !flags.intersects(vk::QueueFlags::COMPUTE | vk::QueueFlags::GRAPHICS)
+    });
+    Some(QueueSelection { compute, transfer })
+}
+
+/// exo's logical device on one physical device
+///
+/// Objects created from the device (allocators, pools, pipelines) must be
+/// dropped before it; it waits for the device to go idle before destroying it.
+pub struct LogicalDevice {
+    device: ash::Device,
+    physical_device: vk::PhysicalDevice,
+    device_index: usize,
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    queues: QueueSelection,
+    compute_queue: vk::Queue,
+    transfer_queue: Option<vk::Queue>,
+    extensions: Vec<String>,
+    /// Keeps the instance alive while the device exists
+    context: Arc<VulkanContext>,
+}
+
+impl LogicalDevice {
+    /// Create a logical device on physical device `index`
+    ///
+    /// Requests one compute queue and, when the device has a transfer-only
+    /// family, one transfer queue, both at the configured priority.
+    pub fn create(context: &Arc<VulkanContext>, index: usize, config: &DeviceConfig) -> DeviceResult<Self> {
+        let physical_device = context.get_physical_device(index)?;
+        let capabilities = context.get_device_capabilities(index)?;
+        let queues = select_queue_families(&capabilities.queue_families, config.protected_submission).ok_or(
+            if config.protected_submission {
+                DeviceError::NoProtectedQueue
+            } else {
+                DeviceError::NoComputeQueue
+            },
+        )?;
+
+        let mut extensions = config.resolve_extensions(capabilities)?;
+        let features = config.resolve_features(capabilities)?;
+        let mut global = config
+            .effective_global_priority(capabilities)
+            .map(|priority| vk::DeviceQueueGlobalPriorityCreateInfoKHR::default().global_priority(priority.to_vk()));
+        if global.is_some()
+            && let Some(name) = config.global_priority_extension(capabilities)
+        {
+            let name = name.to_string_lossy().into_owned();
+            if !extensions.contains(&name) {
+                extensions.push(name);
+            }
+        }
+        // Timeline semaphores cost nothing to enable, so take them wherever offered
+        let timeline_name = ash::khr::timeline_semaphore::NAME.to_string_lossy().into_owned();
+        let timeline = capabilities.has_extension(&timeline_name) && timeline_semaphore_supported(context, physical_device);
+        if timeline && !extensions.contains(&timeline_name) {
+            extensions.push(timeline_name);
+        }
+        let extension_names: Vec<CString> = extensions
+            .iter()
+            .map(|name| CString::new(name.as_str()).map_err(|_| DeviceError::MissingExtension(name.clone())))
+            .collect::<DeviceResult<_>>()?;
+        let extension_ptrs: Vec<*const std::ffi::c_char> = extension_names.iter().map(|name| name.as_ptr()).collect();
+
+        let priorities = [config.clamped_queue_priority()];
+        let mut queue_infos = vec![config.queue_create_info(queues.compute, &priorities, global.as_mut())];
+        if let Some(transfer) = queues.transfer {
+            queue_infos.push(
+                vk::DeviceQueueCreateInfo::default()
+                    .queue_family_index(transfer)
+                    .queue_priorities(&priorities),
+            );
+        }
+
+        let mut protected = vk::PhysicalDeviceProtectedMemoryFeatures::default().protected_memory(true);
+        let mut timeline_features = vk::PhysicalDeviceTimelineSemaphoreFeatures::default().timeline_semaphore(true);
+        let mut create_info = vk::DeviceCreateInfo::default()
+            .queue_create_infos(&queue_infos)
+            .enabled_extension_names(&extension_ptrs)
+            .enabled_features(&features);
+        if config.protected_submission {
+            if !protected_memory_supported(context, physical_device) {
+                return Err(DeviceError::MissingFeature("protectedMemory"));
+            }
+            create_info = create_info.push_next(&mut protected);
+        }
+        if timeline {
+            create_info = create_info.push_next(&mut timeline_features);
+        }
+
+        // SAFETY: physical_device was enumerated from context's instance, and
+        // every extension and feature enabled was checked against it above
+        let device = unsafe { context.instance().create_device(physical_device, &create_info, None) }
+            .map_err(DeviceError::VulkanError)?;
+        // SAFETY: queue 0 of each family was requested above; protected
+        // queues must be fetched with the matching flags
+        let compute_queue = unsafe {
+            if config.protected_submission {
+                let info = vk::DeviceQueueInfo2::default()
+                    .flags(vk::DeviceQueueCreateFlags::PROTECTED)
+                    .queue_family_index(queues.compute)
+                    .queue_index(0);
+                device.get_device_queue2(&info)
+            } else {
+                device.get_device_queue(queues.compute, 0)
+            }
+        };
+        // SAFETY: as above
+        let transfer_queue = queues.transfer.map(|family| unsafe { device.get_device_queue(family, 0) });
+
+        log::info!(
+            "Created logical device on device {} (compute family {}, transfer family {:?}, extensions {:?})",
+            index,
+            queues.compute,
+            queues.transfer,
+            extensions
+        );
+        Ok(Self {
+            device,
+            physical_device,
+            device_index: index,
+            memory_properties: *context.get_memory_properties(index)?,
+            queues,
+            compute_queue,
+            transfer_queue,
+            extensions,
+            context: Arc::clone(context),
+        })
+    }
+
+    /// The logical device handle
+    pub fn device(&self) -> &ash::Device {
+        &self.device
+    }
+
+    pub fn physical_device(&self) -> vk::PhysicalDevice {
+        self.physical_device
+    }
+
+    /// Index of the physical device in the context
+    pub fn device_index(&self) -> usize {
+        self.device_index
+    }
+
+    pub fn context(&self) -> &Arc<VulkanContext> {
+        &self.context
+    }
+
+    pub fn memory_properties(&self) -> &vk::PhysicalDeviceMemoryProperties {
+        &self.memory_properties
+    }
+
+    pub fn queue_families(&self) -> QueueSelection {
+        self.queues
+    }
+
+    /// Whether the named extension was enabled
+    pub fn has_extension(&self, name: &str) -> bool {
+        self.extensions.iter().any(|e| e == name)
+    }
+
+    /// Whether `timeline_semaphore` can create semaphores on this device
+    pub fn supports_timeline_semaphores(&self) -> bool {
+        self.has_extension(&ash::khr::timeline_semaphore::NAME.to_string_lossy())
+    }
+
+    /// A timeline semaphore starting at `initial_value`
+    pub fn timeline_semaphore(&self, initial_value: u64) -> DeviceResult<TimelineSemaphore> {
+        if !self.supports_timeline_semaphores() {
+            return Err(DeviceError::MissingExtension(
+                ash::khr::timeline_semaphore::NAME.to_string_lossy().into_owned(),
+            ));
+        }
+        Ok(TimelineSemaphore::new(&self.context.instance(), self.device.clone(), initial_value)?)
+    }
+
+    /// The compute queue
+    pub fn compute_queue(&self) -> Queue {
+        Queue::new(self.device.clone(), self.compute_queue, self.queues.compute)
+    }
+
+    /// The transfer-only queue, if the device has one
+    pub fn transfer_queue(&self) -> Option<Queue> {
+        Some(Queue::new(self.device.clone(), self.transfer_queue?, self.queues.transfer?))
+    }
+
+    /// A memory allocator for this device
+    pub fn memory_allocator(&self) -> MemoryAllocator {
+        MemoryAllocator::new(self.device.clone(), self.memory_properties)
+    }
+
+    /// A command pool on the compute family
+    pub fn command_pool(&self) -> DeviceResult<CommandPool> {
+        Ok(CommandPool::new(self.device.clone(), self.queues.compute)?)
+    }
+
+    /// A transfer manager submitting to the compute queue
+    ///
+    /// `pool` must come from `command_pool` and outlive the result.
+    pub fn data_transfer(&self, pool: &CommandPool) -> DataTransfer {
+        DataTransfer::new(self.device.clone(), self.compute_queue, pool.raw(), &self.memory_properties)
+    }
+}
+
+impl Drop for LogicalDevice {
+    fn drop(&mut self) {
+        // SAFETY: the device is valid and, per the type's contract, nothing
+        // created from it outlives it
+        unsafe {
+            let _ = self.device.device_wait_idle();
+            self.device.destroy_device(None);
+        }
+    }
+}
+
+fn protected_memory_supported(context: &VulkanContext, physical_device: vk::PhysicalDevice) -> bool {
+    let mut protected = vk::PhysicalDeviceProtectedMemoryFeatures::default();
+    {
+        let mut features = vk::PhysicalDeviceFeatures2::default().push_next(&mut protected);
+        // SAFETY: physical_device was enumerated from context's instance, which targets Vulkan 1.1
+        unsafe {
+            context
+                .instance()
+                .get_physical_device_features2(physical_device, &mut features)
+        };
+    }
+    protected.protected_memory == vk::TRUE
+}
+
+fn timeline_semaphore_supported(context: &VulkanContext, physical_device: vk::PhysicalDevice) -> bool {
+    let mut timeline = vk::PhysicalDeviceTimelineSemaphoreFeatures::default();
+    {
+        let mut features = vk::PhysicalDeviceFeatures2::default().push_next(&mut timeline);
+        // SAFETY: as in protected_memory_supported
+        unsafe {
+            context
+                .instance()
+                .get_physical_device_features2(physical_device, &mut features)
+        };
+    }
+    timeline.timeline_semaphore == vk::TRUE
+}
+
+/// A device on the host's first GPU, for tests that need real Vulkan
+///
+/// `None` where the host has no Vulkan device; such tests then pass
+/// without checking anything.
+#[cfg(test)]
+pub(crate) fn test_device() -> Option<LogicalDevice> {
+    let context = crate::initialize_vulkan().ok()?;
+    LogicalDevice::create(&context, 0, &DeviceConfig::default()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capabilities(extensions: &[&str]) -> DeviceCapabilities {
+        DeviceCapabilities {
+            features: vk::PhysicalDeviceFeatures::default(),
+            queue_families: Vec::new(),
+            extensions: extensions.iter().map(|e| e.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_global_priority_requires_extension() {
+        let config = DeviceConfig::default();
+        assert_eq!(config.effective_global_priority(&capabilities(&[])), None);
+        assert_eq!(
+            config.effective_global_priority(&capabilities(&["VK_EXT_global_priority"])),
+            Some(GlobalPriority::Low)
+        );
+    }
+
+    #[test]
+    fn test_queue_priority_clamped() {
+        let config = DeviceConfig {
+            queue_priority: 3.0,
+            ..DeviceConfig::default()
+        };
+        assert_eq!(config.clamped_queue_priority(), 1.0);
+    }
+
+    fn family(flags: vk::QueueFlags) -> vk::QueueFamilyProperties {
+        vk::QueueFamilyProperties::default().queue_flags(flags).queue_count(1)
+    }
+
+    #[test]
+    fn test_select_prefers_dedicated_families() {
+        let universal = family(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER);
+        assert_eq!(
+            select_queue_families(&[universal], false),
+            Some(QueueSelection { compute: 0, transfer: None })
+        );
+        let families = [
+            universal,
+            family(vk::QueueFlags::TRANSFER),
+            family(vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER),
+        ];
+        assert_eq!(
+            select_queue_families(&families, false),
+            Some(QueueSelection { compute: 2, transfer: Some(1) })
+        );
+        assert_eq!(select_queue_families(&families, true), None);
+    }
+
+    #[test]
+    fn test_required_extensions_and_features_checked() {
+        let config = DeviceConfig {
+            extensions: vec!["VK_KHR_external_memory_fd".to_string()],
+            optional_extensions: vec!["VK_EXT_external_memory_host".to_string()],
+            features: vec![DeviceFeature::ShaderInt16],
+            ..DeviceConfig::default()
+        };
+        assert!(matches!(
+            config.resolve_extensions(&capabilities(&[])),
+            Err(DeviceError::MissingExtension(name)) if name == "VK_KHR_external_memory_fd"
+        ));
+        assert_eq!(
+            config.resolve_extensions(&capabilities(&["VK_KHR_external_memory_fd"])).unwrap(),
+            vec!["VK_KHR_external_memory_fd".to_string()]
+        );
+        assert!(matches!(
+            config.resolve_features(&capabilities(&[])),
+            Err(DeviceError::MissingFeature("shaderInt16"))
+        ));
+    }
+}
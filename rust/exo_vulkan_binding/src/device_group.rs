@@ -0,0 +1,403 @@
+//! Device groups for linked GPUs (`VK_KHR_device_group`, core in Vulkan 1.1)
+//!
+//! Desktop nodes with linked adapters can expose several physical devices as
+//! one group, and a single logical device can span it. Memory allocated on a
+//! group device has one instance per physical device; a copy recorded on one
+//! device can write straight into another device's instance over the peer
+//! link, so moving activations between them never touches host memory.
+
+use std::time::Instant;
+
+use ash::vk;
+use thiserror::Error;
+
+use crate::command::{CommandError, CommandPool, Fence};
+use crate::device::DeviceConfig;
+use crate::events::{self, GpuEvent, TransferDirection};
+use crate::memory::BufferRange;
+use crate::{VulkanContext, VulkanError};
+
+/// Device group errors
+#[derive(Error, Debug)]
+pub enum DeviceGroupError {
+    #[error("Device index {index} out of range for a group of {count}")]
+    DeviceOutOfRange { index: usize, count: usize },
+
+    #[error("Device {src} cannot copy into device {dst}'s memory on heap {heap}")]
+    PeerCopyUnsupported { src: usize, dst: usize, heap: u32 },
+
+    #[error("Device group has no compute queue family")]
+    NoComputeQueue,
+
+    #[error("Invalid peer copy: {0}")]
+    InvalidCopy(String),
+
+    #[error("Peer copy timed out")]
+    Timeout,
+
+    #[error(transparent)]
+    Context(#[from] VulkanError),
+
+    #[error(transparent)]
+    Command(#[from] CommandError),
+
+    #[error("Vulkan error: {0:?}")]
+    VulkanError(vk::Result),
+}
+
+pub type DeviceGroupResult<T> = Result<T, DeviceGroupError>;
+
+/// How long `copy_peer` waits for the copy to finish
+const PEER_COPY_TIMEOUT_NS: u64 = 10_000_000_000;
+
+/// Physical devices that can share one logical device
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeviceGroupInfo {
+    /// Indices into the context's physical device list, in group order
+    pub device_indices: Vec<usize>,
+    /// Whether memory may be allocated on a subset of the group's devices
+    pub subset_allocation: bool,
+}
+
+impl DeviceGroupInfo {
+    /// Number of physical devices in the group
+    pub fn len(&self) -> usize {
+        self.device_indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.device_indices.is_empty()
+    }
+
+    /// Whether the group links more than one GPU
+    pub fn is_linked(&self) -> bool {
+        self.len() > 1
+    }
+
+    /// Device mask covering every device in the group
+    pub fn all_devices_mask(&self) -> u32 {
+        (1u32 << self.len()) - 1
+    }
+
+    fn check_device(&self, index: usize) -> DeviceGroupResult<()> {
+        if index >= self.len() {
+            return Err(DeviceGroupError::DeviceOutOfRange {
+                index,
+                count: self.len(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Device mask selecting group device `index` alone
+pub fn device_mask(index: usize) -> u32 {
+    1u32 << index
+}
+
+/// Per-device memory instance indices for binding a peer view
+///
+/// Device `src` binds to `dst`'s instance of the memory; every other device
+/// binds its own, as the spec requires when a group device binds at all.
+pub fn peer_device_indices(device_count: usize, src: usize, dst: usize) -> Vec<u32> {
+    (0..device_count as u32)
+        .map(|i| if i as usize == src { dst as u32 } else { i })
+        .collect()
+}
+
+/// A logical device spanning a device group
+pub struct GroupDevice {
+    device: ash::Device,
+    group: DeviceGroupInfo,
+    queue: vk::Queue,
+    command_pool: Option<CommandPool>,
+}
+
+impl GroupDevice {
+    /// Create a logical device over every physical device in `group`
+    ///
+    /// Uses the first compute-capable queue family of the group's first
+    /// device; all devices in a group share queue family layouts.
+    pub fn create(context: &VulkanContext, group: &DeviceGroupInfo, config: &DeviceConfig) -> DeviceGroupResult<Self> {
+        group.check_device(0)?;
+        let first = group.device_indices[0];
+        let capabilities = context.get_device_capabilities(first)?;
+        let queue_family_index = capabilities
+            .queue_families
+            .iter()
+            .position(|family| family.queue_flags.contains(vk::QueueFlags::COMPUTE))
+            .ok_or(DeviceGroupError::NoComputeQueue)? as u32;
+
+        let physical_devices = group
+            .device_indices
+            .iter()
+            .map(|&index| context.get_physical_device(index))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let priorities = [config.clamped_queue_priority()];
+        let mut global = config
+            .effective_global_priority(capabilities)
+            .map(|priority| vk::DeviceQueueGlobalPriorityCreateInfoKHR::default().global_priority(priority.to_vk()));
+        let extensions: Vec<*const std::ffi::c_char> = config
+            .global_priority_extension(capabilities)
+            .filter(|_| global.is_some())
+            .map(|name| name.as_ptr())
+            .into_iter()
+            .collect();
+        let queue_infos = [config.queue_create_info(queue_family_index, &priorities, global.as_mut())];
+
+        let mut group_info = vk::DeviceGroupDeviceCreateInfo::default().physical_devices(&physical_devices);
+        let create_info = vk::DeviceCreateInfo::default()
+            .queue_create_infos(&queue_infos)
+            .enabled_extension_names(&extensions)
+            .push_next(&mut group_info);
+
+        // SAFETY: the physical devices were enumerated from context's instance
+        // and form one group, as DeviceGroupDeviceCreateInfo requires
+        let device = unsafe { context.instance().create_device(physical_devices[0], &create_info, None) }
+            .map_err(DeviceGroupError::VulkanError)?;
+        // SAFETY: queue 0 of queue_family_index was requested above
+        let queue = unsafe { device.get_device_queue(queue_family_index, 0) };
+        let command_pool = match CommandPool::new(device.clone(), queue_family_index) {
+            Ok(pool) => pool,
+            Err(e) => {
+                // SAFETY: nothing has been created from the device yet
+                unsafe { device.destroy_device(None) };
+                return Err(e.into());
+            }
+        };
+
+        log::info!("Created group device over physical devices {:?}", group.device_indices);
+        Ok(Self {
+            device,
+            group: group.clone(),
+            queue,
+            command_pool: Some(command_pool),
+        })
+    }
+
+    /// The logical device handle
+    pub fn device(&self) -> &ash::Device {
+        &self.device
+    }
+
+    pub fn group(&self) -> &DeviceGroupInfo {
+        &self.group
+    }
+
+    pub fn queue(&self) -> vk::Queue {
+        self.queue
+    }
+
+    /// How device `local` may access `remote`'s instance of memory on `heap`
+    pub fn peer_memory_features(&self, heap: u32, local: usize, remote: usize) -> DeviceGroupResult<vk::PeerMemoryFeatureFlags> {
+        self.group.check_device(local)?;
+        self.group.check_device(remote)?;
+        if local == remote {
+            return Ok(vk::PeerMemoryFeatureFlags::COPY_SRC
+                | vk::PeerMemoryFeatureFlags::COPY_DST
+                | vk::PeerMemoryFeatureFlags::GENERIC_SRC
+                | vk::PeerMemoryFeatureFlags::GENERIC_DST);
+        }
+        // SAFETY: both indices are within the group the device was created over
+        Ok(unsafe { self.device.get_device_group_peer_memory_features(heap, local as u32, remote as u32) })
+    }
+
+    /// Allocate memory with one instance on each device in `device_mask`
+    ///
+    /// # Safety Requirements
+    /// - memory_type_index must be a valid memory type of the group's devices
+    /// - the returned memory must be freed with this device
+    pub unsafe fn allocate_instances(
+        &self,
+        size: vk::DeviceSize,
+        memory_type_index: u32,
+        device_mask: u32,
+    ) -> DeviceGroupResult<vk::DeviceMemory> {
+        if device_mask == 0 || device_mask & !self.group.all_devices_mask() != 0 {
+            return Err(DeviceGroupError::InvalidCopy(format!("device mask {:#b} outside group", device_mask)));
+        }
+        let mut flags = vk::MemoryAllocateFlagsInfo::default()
+            .flags(vk::MemoryAllocateFlags::DEVICE_MASK)
+            .device_mask(device_mask);
+        let info = vk::MemoryAllocateInfo::default()
+            .allocation_size(size)
+            .memory_type_index(memory_type_index)
+            .push_next(&mut flags);
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe { self.device.allocate_memory(&info, None) }.map_err(DeviceGroupError::VulkanError)
+    }
+
+    /// Copy `src` on device `src_device` into `dst_device`'s instance of
+    /// `dst_memory` at `dst_offset`, over the peer link
+    ///
+    /// Blocks until the copy completes.
+    ///
+    /// # Safety Requirements
+    /// - src.buffer must be a TRANSFER_SRC buffer of this device, bound to
+    ///   memory with an instance on src_device
+    /// - dst_memory must have an instance on dst_device, come from heap
+    ///   `dst_heap`, and hold `src.size` bytes at dst_offset
+    /// - neither range may be in use by other submissions
+    pub unsafe fn copy_peer(
+        &self,
+        src_device: usize,
+        dst_device: usize,
+        src: &BufferRange,
+        dst_memory: vk::DeviceMemory,
+        dst_offset: vk::DeviceSize,
+        dst_heap: u32,
+    ) -> DeviceGroupResult<()> {
+        if !self
+            .peer_memory_features(dst_heap, src_device, dst_device)?
+            .contains(vk::PeerMemoryFeatureFlags::COPY_DST)
+        {
+            return Err(DeviceGroupError::PeerCopyUnsupported {
+                src: src_device,
+                dst: dst_device,
+                heap: dst_heap,
+            });
+        }
+        if src.size == 0 {
+            return Ok(());
+        }
+        let started = Instant::now();
+
+        // A buffer whose src_device binding aliases dst_device's memory instance
+        let buffer_info = vk::BufferCreateInfo::default()
+            .size(src.size)
+            .usage(vk::BufferUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        // SAFETY: device is valid; the buffer is destroyed before returning
+        let peer_buffer = unsafe { self.device.create_buffer(&buffer_info, None) }.map_err(DeviceGroupError::VulkanError)?;
+
+        // SAFETY: peer_buffer was just created and is destroyed exactly once
+        let result = unsafe {
+            self.bind_peer_view(peer_buffer, dst_memory, dst_offset, src_device, dst_device)
+                .and_then(|()| self.submit_peer_copy(src_device, src, peer_buffer))
+        };
+        // SAFETY: the copy has completed (or never started)
+        unsafe { self.device.destroy_buffer(peer_buffer, None) };
+        result?;
+
+        events::emit(GpuEvent::Transfer {
+            direction: TransferDirection::DeviceToDevice,
+            bytes: src.size,
+            duration: started.elapsed(),
+        });
+        Ok(())
+    }
+
+    /// Bind `buffer` so that src_device sees dst_device's memory instance
+    unsafe fn bind_peer_view(
+        &self,
+        buffer: vk::Buffer,
+        memory: vk::DeviceMemory,
+        offset: vk::DeviceSize,
+        src_device: usize,
+        dst_device: usize,
+    ) -> DeviceGroupResult<()> {
+        // SAFETY: buffer is a valid, unbound buffer of this device
+        let requirements = unsafe { self.device.get_buffer_memory_requirements(buffer) };
+        if offset % requirements.alignment.max(1) != 0 {
+            return Err(DeviceGroupError::InvalidCopy(format!(
+                "destination offset {} not aligned to {}",
+                offset, requirements.alignment
+            )));
+        }
+
+        let device_indices = peer_device_indices(self.group.len(), src_device, dst_device);
+        let mut group_bind = vk::BindBufferMemoryDeviceGroupInfo::default().device_indices(&device_indices);
+        let bind = vk::BindBufferMemoryInfo::default()
+            .buffer(buffer)
+            .memory(memory)
+            .memory_offset(offset)
+            .push_next(&mut group_bind);
+        // SAFETY: forwarded from copy_peer's guarantees on memory
+        unsafe { self.device.bind_buffer_memory2(&[bind]) }.map_err(DeviceGroupError::VulkanError)
+    }
+
+    /// Record and submit the copy on src_device alone, then wait for it
+    unsafe fn submit_peer_copy(&self, src_device: usize, src: &BufferRange, peer_buffer: vk::Buffer) -> DeviceGroupResult<()> {
+        let pool = self.command_pool.as_ref().ok_or(DeviceGroupError::NoComputeQueue)?;
+        let cmd = pool.allocate_buffers(1)?[0];
+        let mask = device_mask(src_device);
+
+        let mut group_begin = vk::DeviceGroupCommandBufferBeginInfo::default().device_mask(mask);
+        let begin_info = vk::CommandBufferBeginInfo::default()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)
+            .push_next(&mut group_begin);
+        let region = vk::BufferCopy::default()
+            .src_offset(src.offset)
+            .dst_offset(0)
+            .size(src.size);
+
+        // SAFETY: cmd was just allocated from this device's pool; both buffers
+        // are valid and bound (guaranteed by copy_peer's caller and bind_peer_view)
+        unsafe {
+            self.device
+                .begin_command_buffer(cmd, &begin_info)
+                .map_err(DeviceGroupError::VulkanError)?;
+            self.device.cmd_set_device_mask(cmd, mask);
+            self.device.cmd_copy_buffer(cmd, src.buffer, peer_buffer, &[region]);
+            self.device.end_command_buffer(cmd).map_err(DeviceGroupError::VulkanError)?;
+        }
+
+        let fence = Fence::new(self.device.clone(), false)?;
+        let cmds = [cmd];
+        let masks = [mask];
+        let mut group_submit = vk::DeviceGroupSubmitInfo::default().command_buffer_device_masks(&masks);
+        let submit = vk::SubmitInfo::default()
+            .command_buffers(&cmds)
+            .push_next(&mut group_submit);
+        // SAFETY: cmd is fully recorded and the queue belongs to this device
+        unsafe { self.device.queue_submit(self.queue, &[submit], fence.raw()) }.map_err(DeviceGroupError::VulkanError)?;
+
+        let completed = fence.wait(PEER_COPY_TIMEOUT_NS)?;
+        if !completed {
+            // The command buffer may still be pending; leak it rather than free it
+            return Err(DeviceGroupError::Timeout);
+        }
+        // SAFETY: the submission has completed
+        unsafe { self.device.free_command_buffers(pool.raw(), &cmds) };
+        Ok(())
+    }
+}
+
+impl Drop for GroupDevice {
+    fn drop(&mut self) {
+        // SAFETY: the device is idle before its pool and itself are destroyed,
+        // and nothing created from it outlives this struct
+        unsafe {
+            let _ = self.device.device_wait_idle();
+            self.command_pool = None;
+            self.device.destroy_device(None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peer_device_indices() {
+        assert_eq!(peer_device_indices(3, 0, 2), vec![2, 1, 2]);
+        assert_eq!(peer_device_indices(2, 1, 1), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_group_masks() {
+        let group = DeviceGroupInfo {
+            device_indices: vec![1, 2],
+            subset_allocation: true,
+        };
+        assert!(group.is_linked());
+        assert_eq!(group.all_devices_mask(), 0b11);
+        assert_eq!(device_mask(1), 0b10);
+        assert!(matches!(
+            group.check_device(2),
+            Err(DeviceGroupError::DeviceOutOfRange { index: 2, count: 2 })
+        ));
+    }
+}
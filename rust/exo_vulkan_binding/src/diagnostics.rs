@@ -0,0 +1,164 @@
+//! Slow-path detection for integrators
+//!
+//! Heuristics that spot usage patterns known to be slow on mobile GPUs and
+//! log a structured warning the first time (and every power-of-two repeat)
+//! they are seen. Counts are kept in process-wide counters so apps can
+//! surface them in their own metrics.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+use parking_lot::Mutex;
+
+/// Uploads smaller than this are considered "tiny"
+pub const SMALL_UPLOAD_BYTES: usize = 4096;
+
+/// Consecutive tiny uploads before the pattern is reported
+pub const SMALL_UPLOAD_STREAK: u64 = 16;
+
+/// Queue idle waits closer together than this are considered per-token syncs
+pub const FREQUENT_WAIT_INTERVAL_MS: u128 = 5;
+
+/// Known-slow usage patterns
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlowPath {
+    /// `queue_wait_idle` called back to back, typically once per token
+    FrequentQueueWaitIdle,
+    /// Many uploads below `SMALL_UPLOAD_BYTES` in a row instead of one batched copy
+    SmallUploadLoop,
+    /// Staged readback of HOST_VISIBLE memory that could simply be mapped
+    UnmappedHostVisibleRead,
+}
+
+impl SlowPath {
+    /// Stable name used in log lines and metrics
+    pub fn name(self) -> &'static str {
+        match self {
+            SlowPath::FrequentQueueWaitIdle => "frequent_queue_wait_idle",
+            SlowPath::SmallUploadLoop => "small_upload_loop",
+            SlowPath::UnmappedHostVisibleRead => "unmapped_host_visible_read",
+        }
+    }
+
+    fn hint(self) -> &'static str {
+        match self {
+            SlowPath::FrequentQueueWaitIdle => "batch work per submit and wait on a fence instead",
+            SlowPath::SmallUploadLoop => "coalesce uploads into one larger copy",
+            SlowPath::UnmappedHostVisibleRead => "map the allocation and read it directly",
+        }
+    }
+
+    fn counter(self) -> &'static AtomicU64 {
+        match self {
+            SlowPath::FrequentQueueWaitIdle => &FREQUENT_WAIT_COUNT,
+            SlowPath::SmallUploadLoop => &SMALL_UPLOAD_COUNT,
+            SlowPath::UnmappedHostVisibleRead => &UNMAPPED_READ_COUNT,
+        }
+    }
+}
+
+static FREQUENT_WAIT_COUNT: AtomicU64 = AtomicU64::new(0);
+static SMALL_UPLOAD_COUNT: AtomicU64 = AtomicU64::new(0);
+static UNMAPPED_READ_COUNT: AtomicU64 = AtomicU64::new(0);
+static SMALL_UPLOAD_RUN: AtomicU64 = AtomicU64::new(0);
+
+lazy_static::lazy_static! {
+    static ref LAST_WAIT_IDLE: Mutex<Option<Instant>> = Mutex::new(None);
+}
+
+/// Snapshot of slow-path counters
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SlowPathStats {
+    pub frequent_queue_wait_idle: u64,
+    pub small_upload_loop: u64,
+    pub unmapped_host_visible_read: u64,
+}
+
+/// Record one occurrence of a slow path, logging on the 1st, 2nd, 4th, 8th... hit
+pub fn record(kind: SlowPath, detail: &str) {
+    let count = kind.counter().fetch_add(1, Ordering::Relaxed) + 1;
+    crate::events::emit(crate::events::GpuEvent::SlowPath {
+        kind: kind.name(),
+        detail: detail.to_string(),
+    });
+    if count.is_power_of_two() {
+        log::warn!(
+            "slow_path={} count={} detail=\"{}\" hint=\"{}\"",
+            kind.name(),
+            count,
+            detail,
+            kind.hint()
+        );
+    }
+}
+
+/// Note a queue idle wait; flags it if the previous one was very recent
+pub fn note_queue_wait_idle() {
+    let now = Instant::now();
+    let previous = LAST_WAIT_IDLE.lock().replace(now);
+    if let Some(previous) = previous {
+        let gap_ms = now.duration_since(previous).as_millis();
+        if gap_ms < FREQUENT_WAIT_INTERVAL_MS {
+            record(
+                SlowPath::FrequentQueueWaitIdle,
+                &format!("{}ms since previous wait", gap_ms),
+            );
+        }
+    }
+}
+
+/// Note a host-to-device upload of `len` bytes
+pub fn note_upload(len: usize) {
+    if len >= SMALL_UPLOAD_BYTES {
+        SMALL_UPLOAD_RUN.store(0, Ordering::Relaxed);
+        return;
+    }
+    let run = SMALL_UPLOAD_RUN.fetch_add(1, Ordering::Relaxed) + 1;
+    if run >= SMALL_UPLOAD_STREAK && run % SMALL_UPLOAD_STREAK == 0 {
+        record(
+            SlowPath::SmallUploadLoop,
+            &format!("{} consecutive uploads < {} bytes", run, SMALL_UPLOAD_BYTES),
+        );
+    }
+}
+
+/// Current counter values
+pub fn stats() -> SlowPathStats {
+    SlowPathStats {
+        frequent_queue_wait_idle: FREQUENT_WAIT_COUNT.load(Ordering::Relaxed),
+        small_upload_loop: SMALL_UPLOAD_COUNT.load(Ordering::Relaxed),
+        unmapped_host_visible_read: UNMAPPED_READ_COUNT.load(Ordering::Relaxed),
+    }
+}
+
+/// Reset all counters and streak trackers
+pub fn reset() {
+    FREQUENT_WAIT_COUNT.store(0, Ordering::Relaxed);
+    SMALL_UPLOAD_COUNT.store(0, Ordering::Relaxed);
+    UNMAPPED_READ_COUNT.store(0, Ordering::Relaxed);
+    SMALL_UPLOAD_RUN.store(0, Ordering::Relaxed);
+    *LAST_WAIT_IDLE.lock() = None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_small_upload_streak_counted() {
+        let before = stats().small_upload_loop;
+        SMALL_UPLOAD_RUN.store(0, Ordering::Relaxed);
+        for _ in 0..SMALL_UPLOAD_STREAK {
+            note_upload(64);
+        }
+        assert!(stats().small_upload_loop > before);
+    }
+
+    #[test]
+    fn test_slow_path_names_are_distinct() {
+        assert_ne!(
+            SlowPath::SmallUploadLoop.name(),
+            SlowPath::UnmappedHostVisibleRead.name()
+        );
+    }
+}
@@ -0,0 +1,376 @@
+//! DLPack tensor exchange
+//!
+//! DLPack is the tensor interchange ABI that ndarray, candle, burn and most
+//! Python frameworks can produce or consume. The `#[repr(C)]` types below
+//! mirror `dlpack.h` (v0.8, unversioned `DLManagedTensor`), so tensors cross
+//! crate and language boundaries as a pointer plus a deleter instead of
+//! through bespoke conversion code.
+//!
+//! Only f32 tensors are exchanged, matching `Tensor`. Host tensors use
+//! `kDLCPU`; device tensors use `kDLVulkan`, whose `data` field carries the
+//! raw `VkBuffer` handle and whose `byte_offset` is the offset into it.
+
+use std::ffi::c_void;
+use std::ptr::NonNull;
+
+use ash::vk;
+use ash::vk::Handle;
+use thiserror::Error;
+
+use crate::memory::BufferRange;
+use crate::tensor::{ELEMENT_SIZE, Layout, Tensor, TensorError};
+
+/// `DLDeviceType` value; kept as a plain integer since foreign producers may
+/// pass device types this crate has no variant for
+pub type DLDeviceType = i32;
+
+/// `kDLCPU`
+pub const DEVICE_CPU: DLDeviceType = 1;
+
+/// `kDLVulkan`
+pub const DEVICE_VULKAN: DLDeviceType = 7;
+
+/// `kDLFloat`
+pub const DTYPE_FLOAT: u8 = 2;
+
+/// DLPack-related errors
+#[derive(Error, Debug)]
+pub enum DlpackError {
+    #[error("Null DLPack tensor")]
+    NullTensor,
+
+    #[error("Unsupported dtype (code {code}, {bits} bits, {lanes} lanes); only f32 is supported")]
+    UnsupportedDtype { code: u8, bits: u8, lanes: u16 },
+
+    #[error("Unsupported device type {0}")]
+    UnsupportedDevice(DLDeviceType),
+
+    #[error("Invalid shape or strides: {0}")]
+    InvalidLayout(String),
+
+    #[error("Host data holds {available} elements but the layout spans {needed}")]
+    DataTooShort { needed: usize, available: usize },
+
+    #[error(transparent)]
+    Tensor(#[from] TensorError),
+}
+
+pub type DlpackResult<T> = Result<T, DlpackError>;
+
+/// `DLDevice`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DLDevice {
+    pub device_type: DLDeviceType,
+    pub device_id: i32,
+}
+
+/// `DLDataType`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DLDataType {
+    pub code: u8,
+    pub bits: u8,
+    pub lanes: u16,
+}
+
+impl DLDataType {
+    pub const F32: Self = Self {
+        code: DTYPE_FLOAT,
+        bits: 32,
+        lanes: 1,
+    };
+}
+
+/// `DLTensor`
+#[repr(C)]
+#[derive(Debug)]
+pub struct DLTensor {
+    pub data: *mut c_void,
+    pub device: DLDevice,
+    pub ndim: i32,
+    pub dtype: DLDataType,
+    pub shape: *mut i64,
+    /// Element strides; null means compact row-major
+    pub strides: *mut i64,
+    pub byte_offset: u64,
+}
+
+/// `DLManagedTensor`
+#[repr(C)]
+#[derive(Debug)]
+pub struct DLManagedTensor {
+    pub dl_tensor: DLTensor,
+    pub manager_ctx: *mut c_void,
+    pub deleter: Option<unsafe extern "C" fn(*mut DLManagedTensor)>,
+}
+
+/// Storage kept alive by an exported tensor until its deleter runs
+struct ExportContext {
+    shape: Vec<i64>,
+    strides: Vec<i64>,
+    /// Host elements; empty for device exports, which borrow the buffer
+    host: Vec<f32>,
+}
+
+unsafe extern "C" fn delete_exported(managed: *mut DLManagedTensor) {
+    if managed.is_null() {
+        return;
+    }
+    // SAFETY: `managed` and its context were created by `export` via
+    // Box::into_raw and the deleter runs exactly once
+    unsafe {
+        let managed = Box::from_raw(managed);
+        drop(Box::from_raw(managed.manager_ctx.cast::<ExportContext>()));
+    }
+}
+
+fn to_i64(values: &[usize]) -> DlpackResult<Vec<i64>> {
+    values
+        .iter()
+        .map(|&v| {
+            i64::try_from(v).map_err(|_| DlpackError::InvalidLayout(format!("{} overflows i64", v)))
+        })
+        .collect()
+}
+
+fn export(
+    mut ctx: Box<ExportContext>,
+    data: *mut c_void,
+    device: DLDevice,
+    byte_offset: u64,
+) -> *mut DLManagedTensor {
+    let dl_tensor = DLTensor {
+        data,
+        device,
+        ndim: ctx.shape.len() as i32,
+        dtype: DLDataType::F32,
+        shape: ctx.shape.as_mut_ptr(),
+        strides: ctx.strides.as_mut_ptr(),
+        byte_offset,
+    };
+    Box::into_raw(Box::new(DLManagedTensor {
+        dl_tensor,
+        manager_ctx: Box::into_raw(ctx).cast(),
+        deleter: Some(delete_exported),
+    }))
+}
+
+/// Export host data as a `kDLCPU` tensor that owns it
+///
+/// The consumer releases it by calling its `deleter`.
+pub fn export_host(data: Vec<f32>, layout: &Layout) -> DlpackResult<*mut DLManagedTensor> {
+    if data.len() < layout.span() {
+        return Err(DlpackError::DataTooShort {
+            needed: layout.span(),
+            available: data.len(),
+        });
+    }
+    let mut ctx = Box::new(ExportContext {
+        shape: to_i64(layout.shape())?,
+        strides: to_i64(layout.strides())?,
+        host: data,
+    });
+    let data = ctx.host.as_mut_ptr().cast();
+    let device = DLDevice {
+        device_type: DEVICE_CPU,
+        device_id: 0,
+    };
+    Ok(export(ctx, data, device, 0))
+}
+
+/// Export a device tensor as a `kDLVulkan` tensor
+///
+/// The export borrows the buffer: the allocation behind `tensor` must outlive
+/// the consumer's use of it, and the deleter only frees the descriptor.
+pub fn export_device(tensor: &Tensor, device_id: i32) -> DlpackResult<*mut DLManagedTensor> {
+    let ctx = Box::new(ExportContext {
+        shape: to_i64(tensor.layout.shape())?,
+        strides: to_i64(tensor.layout.strides())?,
+        host: Vec::new(),
+    });
+    let data = tensor.range.buffer.as_raw() as usize as *mut c_void;
+    let device = DLDevice {
+        device_type: DEVICE_VULKAN,
+        device_id,
+    };
+    Ok(export(ctx, data, device, tensor.range.offset))
+}
+
+/// A foreign tensor taken over from a `DLManagedTensor`
+///
+/// Calls the producer's deleter when dropped.
+#[derive(Debug)]
+pub struct ImportedTensor {
+    managed: NonNull<DLManagedTensor>,
+    layout: Layout,
+}
+
+impl ImportedTensor {
+    pub fn device(&self) -> DLDevice {
+        // SAFETY: `managed` stays valid until drop
+        unsafe { self.managed.as_ref().dl_tensor.device }
+    }
+
+    pub fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    /// Elements of a `kDLCPU` tensor, from the first addressed element up
+    /// to the end of the layout's span
+    pub fn host_data(&self) -> DlpackResult<&[f32]> {
+        // SAFETY: `managed` stays valid until drop
+        let tensor = unsafe { &self.managed.as_ref().dl_tensor };
+        if tensor.device.device_type != DEVICE_CPU {
+            return Err(DlpackError::UnsupportedDevice(tensor.device.device_type));
+        }
+        let span = self.layout.span();
+        if span == 0 {
+            return Ok(&[]);
+        }
+        // SAFETY: the producer guarantees `span` f32s are readable from
+        // data + byte_offset for as long as the tensor is alive
+        unsafe {
+            let first = tensor
+                .data
+                .cast::<u8>()
+                .add(tensor.byte_offset as usize)
+                .cast::<f32>();
+            Ok(std::slice::from_raw_parts(first, span))
+        }
+    }
+
+    /// Gather a `kDLCPU` tensor into a row-major `Vec`
+    pub fn to_contiguous(&self) -> DlpackResult<Vec<f32>> {
+        let data = self.host_data()?;
+        if self.layout.is_contiguous() {
+            return Ok(data.to_vec());
+        }
+        Ok((0..self.layout.numel())
+            .map(|i| data[self.layout.offset_of(i)])
+            .collect())
+    }
+
+    /// View a `kDLVulkan` tensor as a `Tensor` over its `VkBuffer`
+    pub fn device_tensor(&self) -> DlpackResult<Tensor> {
+        // SAFETY: `managed` stays valid until drop
+        let tensor = unsafe { &self.managed.as_ref().dl_tensor };
+        if tensor.device.device_type != DEVICE_VULKAN {
+            return Err(DlpackError::UnsupportedDevice(tensor.device.device_type));
+        }
+        let range = BufferRange {
+            buffer: vk::Buffer::from_raw(tensor.data as usize as u64),
+            offset: tensor.byte_offset,
+            size: self.layout.span() as u64 * ELEMENT_SIZE,
+        };
+        Ok(Tensor::new(range, self.layout.clone())?)
+    }
+
+    /// Give ownership back without running the deleter
+    pub fn into_raw(self) -> *mut DLManagedTensor {
+        std::mem::ManuallyDrop::new(self).managed.as_ptr()
+    }
+}
+
+impl Drop for ImportedTensor {
+    fn drop(&mut self) {
+        // SAFETY: ownership was transferred in `import`; the deleter runs once
+        unsafe {
+            if let Some(deleter) = self.managed.as_ref().deleter {
+                deleter(self.managed.as_ptr());
+            }
+        }
+    }
+}
+
+/// Take ownership of a DLPack tensor
+///
+/// On error the tensor is not consumed and the caller still owns it.
+///
+/// # Safety Requirements
+/// - managed must be null or point to a valid `DLManagedTensor` whose shape
+///   (and strides, if non-null) arrays hold `ndim` elements
+/// - on success, the caller must not use or delete `managed` again
+pub unsafe fn import(managed: *mut DLManagedTensor) -> DlpackResult<ImportedTensor> {
+    let managed = NonNull::new(managed).ok_or(DlpackError::NullTensor)?;
+    // SAFETY: validity guaranteed by the caller
+    let tensor = unsafe { &managed.as_ref().dl_tensor };
+
+    if tensor.dtype != DLDataType::F32 {
+        return Err(DlpackError::UnsupportedDtype {
+            code: tensor.dtype.code,
+            bits: tensor.dtype.bits,
+            lanes: tensor.dtype.lanes,
+        });
+    }
+    if !matches!(tensor.device.device_type, DEVICE_CPU | DEVICE_VULKAN) {
+        return Err(DlpackError::UnsupportedDevice(tensor.device.device_type));
+    }
+    if tensor.byte_offset % ELEMENT_SIZE != 0 {
+        return Err(DlpackError::InvalidLayout(format!(
+            "byte offset {} is not f32-aligned",
+            tensor.byte_offset
+        )));
+    }
+
+    let ndim = usize::try_from(tensor.ndim)
+        .map_err(|_| DlpackError::InvalidLayout(format!("ndim {}", tensor.ndim)))?;
+    let dims = |ptr: *const i64, what: &str| -> DlpackResult<Vec<usize>> {
+        if ndim == 0 {
+            return Ok(Vec::new());
+        }
+        // SAFETY: the caller guarantees `ndim` readable elements
+        let values = unsafe { std::slice::from_raw_parts(ptr, ndim) };
+        values
+            .iter()
+            .map(|&v| {
+                usize::try_from(v)
+                    .map_err(|_| DlpackError::InvalidLayout(format!("negative {} {}", what, v)))
+            })
+            .collect()
+    };
+
+    let shape = dims(tensor.shape, "dimension")?;
+    let layout = if tensor.strides.is_null() {
+        Layout::contiguous(&shape)
+    } else {
+        Layout::with_strides(&shape, &dims(tensor.strides, "stride")?)?
+    };
+    Ok(ImportedTensor { managed, layout })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host_round_trip() {
+        let layout = Layout::contiguous(&[2, 3]).permute(&[1, 0]).unwrap();
+        let managed = export_host(vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0], &layout).unwrap();
+
+        // SAFETY: freshly exported and consumed once
+        let imported = unsafe { import(managed) }.unwrap();
+        assert_eq!(imported.layout(), &layout);
+        assert_eq!(
+            imported.to_contiguous().unwrap(),
+            vec![0.0, 3.0, 1.0, 4.0, 2.0, 5.0]
+        );
+        assert!(imported.device_tensor().is_err());
+    }
+
+    #[test]
+    fn test_import_rejects_other_dtypes() {
+        let managed = export_host(vec![1.0; 4], &Layout::contiguous(&[4])).unwrap();
+        // SAFETY: `managed` is valid until the deleter below runs
+        unsafe {
+            (*managed).dl_tensor.dtype.bits = 16;
+            assert!(matches!(
+                import(managed),
+                Err(DlpackError::UnsupportedDtype { bits: 16, .. })
+            ));
+            // Not consumed on error
+            delete_exported(managed);
+        }
+        assert!(export_host(vec![1.0; 3], &Layout::contiguous(&[4])).is_err());
+    }
+}
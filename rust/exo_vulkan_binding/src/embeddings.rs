@@ -0,0 +1,815 @@
+//! Embedding similarity search on device
+//!
+//! On-device RAG retrieval scores a query embedding against every row of an
+//! embedding matrix and keeps the k best, sharing the GPU and its memory with
+//! the LLM instead of running a separate vector store on the CPU. Pass 1
+//! computes the cosine similarity of each row; pass 2 runs k argmax rounds in
+//! one workgroup, which beats a full sort for the small k retrieval uses.
+//!
+//! The index grows incrementally: rows are appended under stable ids, and
+//! deletion only sets a tombstone bit that pass 1 honours, so neither moves
+//! other rows on device. Once a quarter of the rows are tombstones the index
+//! compacts itself. `DeviceIndex` mirrors it into GPU memory, growing its
+//! buffers `CHUNK_ROWS` at a time through `MemoryAllocator::resize` and
+//! uploading only the rows appended since the last sync (everything after a
+//! compaction).
+//!
+//! Rows can be quantized on insert to cut index memory: int8 (4x, cosine of
+//! the integer vectors, which a per-row scale would not change) or binary
+//! (32x, sign bits compared by Hamming distance). Rows are packed into 32-bit
+//! words, zero padded per row, and the query is quantized the same way.
+//!
+//! Integrations register the query embeddings their embedding model
+//! produces (`register_query`), and `searchEmbeddings` looks queries up by
+//! tensor handle.
+
+use std::collections::HashMap;
+
+use ash::vk;
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::memory::{BufferRange, MemoryAllocator, MemoryError};
+use crate::ops::{KernelBinding, MAX_GROUP_COUNT, OpsError, OpsResult, compute_barrier};
+use crate::transfer::{DataTransfer, TransferError};
+
+/// Threads per workgroup of both search passes
+pub const EMBEDDING_WORKGROUP_SIZE: u32 = 256;
+
+/// Bytes per result written by the top-k pass: row (u32) and score (f32)
+pub const MATCH_BYTES: u64 = 8;
+
+/// Row written for top-k rounds past the row count
+const NO_ROW: u32 = u32::MAX;
+
+const WORD_SIZE: u64 = 4;
+
+/// Rows the device buffers grow by
+pub const CHUNK_ROWS: usize = 1024;
+
+/// Fraction of tombstoned rows that triggers compaction
+pub const COMPACT_FRACTION: f32 = 0.25;
+
+/// Embedding search errors
+#[derive(Error, Debug)]
+pub enum EmbeddingError {
+    #[error("No embedding index registered")]
+    NoIndex,
+
+    #[error("Query not registered: {0}")]
+    UnknownQuery(String),
+
+    #[error("Expected {expected} dimensions, got {actual}")]
+    DimensionMismatch { expected: usize, actual: usize },
+
+    #[error("{0} values is not a whole number of rows")]
+    PartialRow(usize),
+
+    #[error(transparent)]
+    Memory(#[from] MemoryError),
+
+    #[error(transparent)]
+    Transfer(#[from] TransferError),
+}
+
+pub type EmbeddingResult<T> = Result<T, EmbeddingError>;
+
+/// Push constants shared by both search passes
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EmbeddingPushConstants {
+    pub rows: u32,
+    pub dim: u32,
+    pub k: u32,
+    pub _pad: u32,
+}
+
+/// The two search pipelines
+#[derive(Clone, Copy, Debug)]
+pub struct EmbeddingKernels {
+    /// Scoring kernel of the index's quantization (`Quantization::score_kernel`)
+    pub score: KernelBinding,
+    /// `embedding_top_k`
+    pub top_k: KernelBinding,
+}
+
+/// How index rows are stored
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Quantization {
+    /// f32 values, one per word
+    #[default]
+    Float32,
+    /// Symmetric int8, four per word, scaled so the largest magnitude is 127
+    Int8,
+    /// Sign bits, 32 per word; bit set for positive values
+    Binary,
+}
+
+impl Quantization {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "f32" => Some(Self::Float32),
+            "int8" => Some(Self::Int8),
+            "binary" => Some(Self::Binary),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Float32 => "f32",
+            Self::Int8 => "int8",
+            Self::Binary => "binary",
+        }
+    }
+
+    /// Kernel computing scores for rows stored this way
+    pub fn score_kernel(self) -> &'static str {
+        match self {
+            Self::Float32 => "embedding_score",
+            Self::Int8 => "embedding_score_int8",
+            Self::Binary => "embedding_score_binary",
+        }
+    }
+
+    /// 32-bit words per row of `dim` values
+    pub fn row_words(self, dim: usize) -> usize {
+        match self {
+            Self::Float32 => dim,
+            Self::Int8 => dim.div_ceil(4),
+            Self::Binary => dim.div_ceil(32),
+        }
+    }
+
+    /// Pack one row into `row_words` words; padding is zero, which changes no score
+    pub fn quantize(self, row: &[f32]) -> Vec<u32> {
+        let mut words = vec![0; self.row_words(row.len())];
+        match self {
+            Self::Float32 => {
+                for (word, v) in words.iter_mut().zip(row) {
+                    *word = v.to_bits();
+                }
+            }
+            Self::Int8 => {
+                let max = row.iter().fold(0.0f32, |m, v| m.max(v.abs()));
+                let scale = if max > 0.0 { 127.0 / max } else { 0.0 };
+                for (i, v) in row.iter().enumerate() {
+                    let q = (v * scale).round().clamp(-127.0, 127.0) as i8;
+                    words[i / 4] |= u32::from(q as u8) << (8 * (i % 4));
+                }
+            }
+            Self::Binary => {
+                for (i, v) in row.iter().enumerate() {
+                    if *v > 0.0 {
+                        words[i / 32] |= 1 << (i % 32);
+                    }
+                }
+            }
+        }
+        words
+    }
+
+    /// Similarity of two packed rows of `dim` values, as the score kernels compute it
+    ///
+    /// Cosine similarity for f32 and int8; for binary `1 - 2 * hamming / dim`,
+    /// which is 1 for equal signs and -1 for opposite ones.
+    pub fn similarity(self, dim: usize, a: &[u32], b: &[u32]) -> f32 {
+        match self {
+            Self::Float32 => {
+                let a: Vec<f32> = a.iter().map(|&w| f32::from_bits(w)).collect();
+                let b: Vec<f32> = b.iter().map(|&w| f32::from_bits(w)).collect();
+                cosine_similarity(&a, &b)
+            }
+            Self::Int8 => {
+                let bytes = |words: &[u32]| -> Vec<i64> {
+                    words
+                        .iter()
+                        .flat_map(|w| w.to_le_bytes())
+                        .map(|b| i64::from(b as i8))
+                        .collect()
+                };
+                let (a, b) = (bytes(a), bytes(b));
+                let (dot, norm_a, norm_b) = a
+                    .iter()
+                    .zip(&b)
+                    .fold((0, 0, 0), |(d, na, nb), (&x, &y)| (d + x * y, na + x * x, nb + y * y));
+                let denom = ((norm_a * norm_b) as f32).sqrt();
+                if denom > 0.0 { dot as f32 / denom } else { 0.0 }
+            }
+            Self::Binary => {
+                let hamming: u32 = a.iter().zip(b).map(|(x, y)| (x ^ y).count_ones()).sum();
+                if dim > 0 { 1.0 - 2.0 * hamming as f32 / dim as f32 } else { 0.0 }
+            }
+        }
+    }
+}
+
+/// One search result, by device row
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Match {
+    pub row: u32,
+    /// Cosine similarity in [-1, 1]
+    pub score: f32,
+}
+
+/// One search result, by the stable id of the row
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Hit {
+    pub id: u64,
+    /// Cosine similarity in [-1, 1]
+    pub score: f32,
+}
+
+fn groups(threads: u32) -> [u32; 3] {
+    let groups = threads.div_ceil(EMBEDDING_WORKGROUP_SIZE).max(1);
+    [
+        groups.min(MAX_GROUP_COUNT),
+        groups.div_ceil(MAX_GROUP_COUNT),
+        1,
+    ]
+}
+
+/// Record a top-k similarity search
+///
+/// `matrix` holds rows of `dim` values packed by `quantization`, and `query`
+/// one such row; `kernels.score` must be `quantization.score_kernel()`. `tombstones` holds one bit per row (`EmbeddingIndex::tombstone_words`);
+/// set rows never match. `scores` is scratch of one f32 per row. `results`
+/// receives `k` matches of `MATCH_BYTES` each, best first; decode them with
+/// `parse_matches`.
+///
+/// # Safety Requirements
+/// - `cmd` must be in the recording state
+/// - The kernels' descriptor sets must not be in use by pending work
+/// - All buffers must stay alive until the command buffer completes
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn record_search(
+    device: &ash::Device,
+    cmd: vk::CommandBuffer,
+    kernels: &EmbeddingKernels,
+    matrix: &BufferRange,
+    dim: u32,
+    quantization: Quantization,
+    query: &BufferRange,
+    tombstones: &BufferRange,
+    scores: &BufferRange,
+    results: &BufferRange,
+    k: u32,
+) -> OpsResult<EmbeddingPushConstants> {
+    let row_bytes = quantization.row_words(dim as usize) as u64 * WORD_SIZE;
+    if dim == 0 || query.size < row_bytes {
+        return Err(OpsError::ShapeMismatch(format!(
+            "query of {} bytes holds fewer than {} {} values",
+            query.size,
+            dim,
+            quantization.name()
+        )));
+    }
+    let rows = (matrix.size / row_bytes) as u32;
+    if tombstones.size < u64::from(rows.div_ceil(32)) * WORD_SIZE {
+        return Err(OpsError::ShapeMismatch(format!(
+            "tombstones of {} bytes cover fewer than {} rows",
+            tombstones.size, rows
+        )));
+    }
+    let needed = u64::from(rows) * WORD_SIZE;
+    if scores.size < needed {
+        return Err(OpsError::ScratchTooSmall {
+            needed,
+            available: scores.size,
+        });
+    }
+    if results.size < u64::from(k) * MATCH_BYTES {
+        return Err(OpsError::ShapeMismatch(format!(
+            "results of {} bytes hold fewer than {} matches",
+            results.size, k
+        )));
+    }
+
+    let push = EmbeddingPushConstants { rows, dim, k, _pad: 0 };
+    // SAFETY: forwarded from the caller's guarantees
+    unsafe {
+        kernels.score.bind_buffers(device, matrix, scores);
+        kernels.score.bind_buffer(device, 2, query);
+        kernels.score.bind_buffer(device, 3, tombstones);
+        kernels.score.record(device, cmd, &push, groups(rows));
+        compute_barrier(device, cmd);
+        kernels.top_k.bind_buffers(device, scores, results);
+        kernels.top_k.record(device, cmd, &push, [1, 1, 1]);
+    }
+    Ok(push)
+}
+
+/// Decode the results of `record_search`, dropping rounds past the row count
+pub fn parse_matches(bytes: &[u8]) -> Vec<Match> {
+    bytes
+        .chunks_exact(MATCH_BYTES as usize)
+        .map(|b| Match {
+            row: u32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            score: f32::from_le_bytes([b[4], b[5], b[6], b[7]]),
+        })
+        .filter(|m| m.row != NO_ROW)
+        .collect()
+}
+
+/// Cosine similarity; 0 when either vector is zero
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let (dot, norm_a, norm_b) = a
+        .iter()
+        .zip(b)
+        .fold((0.0, 0.0, 0.0), |(d, na, nb), (&x, &y)| (d + x * y, na + x * x, nb + y * y));
+    let denom = (norm_a * norm_b).sqrt();
+    if denom > 0.0 { dot / denom } else { 0.0 }
+}
+
+fn is_tombstoned(tombstones: &[u32], row: usize) -> bool {
+    tombstones.get(row / 32).is_some_and(|word| word >> (row % 32) & 1 != 0)
+}
+
+/// Host reference for `record_search`; `matrix` and `query` are packed by `quantization`
+pub fn search_host(
+    matrix: &[u32],
+    dim: usize,
+    quantization: Quantization,
+    query: &[u32],
+    tombstones: &[u32],
+    k: usize,
+) -> Vec<Match> {
+    let mut matches: Vec<Match> = matrix
+        .chunks_exact(quantization.row_words(dim).max(1))
+        .enumerate()
+        .filter(|&(row, _)| !is_tombstoned(tombstones, row))
+        .map(|(row, words)| Match {
+            row: row as u32,
+            score: quantization.similarity(dim, query, words),
+        })
+        .collect();
+    // Best first; ties to the lower row, as on device
+    matches.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.row.cmp(&b.row)));
+    matches.truncate(k);
+    matches
+}
+
+/// JSON array: `[{"id":n,"score":x},...]`
+pub fn hits_json(hits: &[Hit]) -> String {
+    serde_json::to_string(hits).expect("hits always serialize")
+}
+
+/// Embedding rows under stable ids, with tombstones for deleted rows
+///
+/// Row positions change only on compaction, which bumps `generation`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EmbeddingIndex {
+    dim: usize,
+    quantization: Quantization,
+    /// Rows packed by `quantization`
+    words: Vec<u32>,
+    /// Id of each row
+    ids: Vec<u64>,
+    /// Row of each live id
+    rows: HashMap<u64, usize>,
+    /// One bit per row, set once deleted
+    tombstones: Vec<u32>,
+    next_id: u64,
+    generation: u64,
+}
+
+impl EmbeddingIndex {
+    pub fn new(dim: usize) -> Self {
+        Self::with_quantization(dim, Quantization::Float32)
+    }
+
+    /// Index quantizing rows as they are inserted
+    pub fn with_quantization(dim: usize, quantization: Quantization) -> Self {
+        Self {
+            dim,
+            quantization,
+            ..Self::default()
+        }
+    }
+
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    pub fn quantization(&self) -> Quantization {
+        self.quantization
+    }
+
+    /// Words per stored row
+    pub fn row_words(&self) -> usize {
+        self.quantization.row_words(self.dim)
+    }
+
+    /// Number of rows, tombstoned ones included
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Rows not deleted
+    pub fn live(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Packed rows, as uploaded for `record_search`
+    pub fn words(&self) -> &[u32] {
+        &self.words
+    }
+
+    /// Tombstone bits, as uploaded for `record_search`
+    pub fn tombstone_words(&self) -> &[u32] {
+        &self.tombstones
+    }
+
+    /// Bumped by every compaction, which moves rows
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Append rows given as consecutive values of `dim` each, quantizing them
+    ///
+    /// # Returns
+    /// Ids of the new rows, in order
+    pub fn append(&mut self, values: &[f32]) -> EmbeddingResult<Vec<u64>> {
+        if values.len().checked_rem(self.dim) != Some(0) {
+            return Err(EmbeddingError::PartialRow(values.len()));
+        }
+        let count = values.len() / self.dim;
+        let ids: Vec<u64> = (self.next_id..self.next_id + count as u64).collect();
+        self.next_id += count as u64;
+        for &id in &ids {
+            self.rows.insert(id, self.ids.len());
+            self.ids.push(id);
+        }
+        for row in values.chunks_exact(self.dim) {
+            self.words.extend(self.quantization.quantize(row));
+        }
+        self.tombstones.resize(self.ids.len().div_ceil(32), 0);
+        Ok(ids)
+    }
+
+    /// Tombstone the row of `id`, compacting once enough rows are dead
+    ///
+    /// # Returns
+    /// Whether `id` was live
+    pub fn delete(&mut self, id: u64) -> bool {
+        let Some(row) = self.rows.remove(&id) else {
+            return false;
+        };
+        self.tombstones[row / 32] |= 1 << (row % 32);
+        if self.needs_compaction() {
+            self.compact();
+        }
+        true
+    }
+
+    /// Whether at least `COMPACT_FRACTION` of the rows are tombstones
+    pub fn needs_compaction(&self) -> bool {
+        let dead = self.len() - self.live();
+        dead > 0 && dead as f32 >= self.len() as f32 * COMPACT_FRACTION
+    }
+
+    /// Drop tombstoned rows, moving the live ones together
+    pub fn compact(&mut self) {
+        let stride = self.row_words();
+        let mut words = Vec::with_capacity(self.live() * stride);
+        let mut ids = Vec::with_capacity(self.live());
+        for (row, &id) in self.ids.iter().enumerate() {
+            if !is_tombstoned(&self.tombstones, row) {
+                words.extend_from_slice(&self.words[row * stride..(row + 1) * stride]);
+                ids.push(id);
+            }
+        }
+        self.rows = ids.iter().enumerate().map(|(row, &id)| (id, row)).collect();
+        self.tombstones = vec![0; ids.len().div_ceil(32)];
+        self.words = words;
+        self.ids = ids;
+        self.generation += 1;
+    }
+
+    /// The `k` live rows most similar to `query`
+    pub fn search(&self, query: &[f32], k: usize) -> EmbeddingResult<Vec<Hit>> {
+        self.check_dim(query)?;
+        let query = self.quantization.quantize(query);
+        let matches = search_host(&self.words, self.dim, self.quantization, &query, &self.tombstones, k);
+        Ok(self.resolve(&matches))
+    }
+
+    /// Ids of device results; only valid while the device copy is synced
+    pub fn resolve(&self, matches: &[Match]) -> Vec<Hit> {
+        matches
+            .iter()
+            .filter_map(|m| {
+                let id = *self.ids.get(m.row as usize)?;
+                Some(Hit { id, score: m.score })
+            })
+            .collect()
+    }
+
+    fn check_dim(&self, values: &[f32]) -> EmbeddingResult<()> {
+        if values.len() != self.dim {
+            return Err(EmbeddingError::DimensionMismatch {
+                expected: self.dim,
+                actual: values.len(),
+            });
+        }
+        Ok(())
+    }
+}
+
+fn word_bytes(words: &[u32]) -> Vec<u8> {
+    words.iter().flat_map(|w| w.to_le_bytes()).collect()
+}
+
+/// GPU copy of an `EmbeddingIndex`
+///
+/// Owns two allocations, `<name>.matrix` and `<name>.tombstones`, of any
+/// memory type: they grow with a copy on the sync transfer's queue.
+#[derive(Debug)]
+pub struct DeviceIndex {
+    matrix: String,
+    tombstones: String,
+    dim: usize,
+    quantization: Quantization,
+    capacity_rows: usize,
+    synced_rows: usize,
+    synced_generation: u64,
+}
+
+impl DeviceIndex {
+    /// Allocate room for `CHUNK_ROWS` rows of `dim` values packed by `quantization`
+    pub fn new(
+        allocator: &mut MemoryAllocator,
+        name: &str,
+        dim: usize,
+        quantization: Quantization,
+        memory_type_index: u32,
+    ) -> EmbeddingResult<Self> {
+        let matrix = allocator.allocate(
+            (CHUNK_ROWS * quantization.row_words(dim)) as u64 * WORD_SIZE,
+            memory_type_index,
+            format!("{}.matrix", name),
+        )?;
+        let tombstones = match allocator.allocate(
+            CHUNK_ROWS.div_ceil(32) as u64 * WORD_SIZE,
+            memory_type_index,
+            format!("{}.tombstones", name),
+        ) {
+            Ok(handle) => handle,
+            Err(e) => {
+                let _ = allocator.deallocate(&matrix);
+                return Err(e.into());
+            }
+        };
+        Ok(Self {
+            matrix,
+            tombstones,
+            dim,
+            quantization,
+            capacity_rows: CHUNK_ROWS,
+            synced_rows: 0,
+            synced_generation: 0,
+        })
+    }
+
+    /// Rows the buffers hold without growing
+    pub fn capacity_rows(&self) -> usize {
+        self.capacity_rows
+    }
+
+    fn row_words(&self) -> usize {
+        self.quantization.row_words(self.dim)
+    }
+
+    /// Bring the device copy up to date with `index`
+    ///
+    /// Grows the buffers to the next multiple of `CHUNK_ROWS` rows when
+    /// needed, uploads the rows appended since the last sync (all rows if
+    /// the index was compacted) and the tombstone bits.
+    ///
+    /// # Safety Requirements
+    /// - transfer must use the device of allocator
+    /// - no pending work may use the buffers
+    ///
+    /// # Returns
+    /// Rows uploaded
+    pub unsafe fn sync(
+        &mut self,
+        allocator: &mut MemoryAllocator,
+        transfer: &DataTransfer,
+        index: &EmbeddingIndex,
+    ) -> EmbeddingResult<usize> {
+        if index.dim() != self.dim || index.quantization() != self.quantization {
+            return Err(EmbeddingError::DimensionMismatch {
+                expected: self.row_words(),
+                actual: index.row_words(),
+            });
+        }
+        let stride = self.row_words();
+        let rows = index.len();
+        if rows > self.capacity_rows {
+            let capacity = rows.next_multiple_of(CHUNK_ROWS);
+            // SAFETY: forwarded from the caller's guarantees
+            unsafe {
+                allocator.resize_on_device(&self.matrix, (capacity * stride) as u64 * WORD_SIZE, transfer)?;
+                allocator.resize_on_device(&self.tombstones, capacity.div_ceil(32) as u64 * WORD_SIZE, transfer)?;
+            }
+            self.capacity_rows = capacity;
+        }
+
+        let from = if index.generation() == self.synced_generation {
+            self.synced_rows.min(rows)
+        } else {
+            0
+        };
+        if from < rows {
+            let bytes = word_bytes(&index.words()[from * stride..rows * stride]);
+            let offset = (from * stride) as u64 * WORD_SIZE;
+            let view = allocator.get_allocation(&self.matrix)?.view(offset, bytes.len() as u64)?;
+            // SAFETY: transfer and view share a device and nothing uses the buffer
+            unsafe { transfer.copy_to_device(&bytes, &view) }?;
+        }
+        let words = word_bytes(index.tombstone_words());
+        if !words.is_empty() {
+            let view = allocator.get_allocation(&self.tombstones)?.view(0, words.len() as u64)?;
+            // SAFETY: as above
+            unsafe { transfer.copy_to_device(&words, &view) }?;
+        }
+
+        self.synced_rows = rows;
+        self.synced_generation = index.generation();
+        Ok(rows - from)
+    }
+
+    /// Matrix and tombstone ranges covering the synced rows, for `record_search`
+    ///
+    /// The index must not be empty; descriptors cannot bind empty ranges.
+    pub fn ranges(&self, allocator: &MemoryAllocator) -> EmbeddingResult<(BufferRange, BufferRange)> {
+        let matrix = allocator
+            .get_allocation(&self.matrix)?
+            .range()
+            .slice(0, (self.synced_rows * self.row_words()) as u64 * WORD_SIZE)?;
+        let tombstones = allocator
+            .get_allocation(&self.tombstones)?
+            .range()
+            .slice(0, self.synced_rows.div_ceil(32) as u64 * WORD_SIZE)?;
+        Ok((matrix, tombstones))
+    }
+
+    /// Free both allocations
+    pub fn free(self, allocator: &mut MemoryAllocator) -> EmbeddingResult<()> {
+        allocator.deallocate(&self.matrix)?;
+        allocator.deallocate(&self.tombstones)?;
+        Ok(())
+    }
+}
+
+lazy_static! {
+    static ref INDEX: Mutex<Option<EmbeddingIndex>> = Mutex::new(None);
+    static ref QUERIES: Mutex<HashMap<String, Vec<f32>>> = Mutex::new(HashMap::new());
+}
+
+/// Replace the process-wide index
+pub fn set_index(index: EmbeddingIndex) {
+    *INDEX.lock() = Some(index);
+}
+
+/// Drop the process-wide index
+pub fn clear_index() -> Option<EmbeddingIndex> {
+    INDEX.lock().take()
+}
+
+/// Append rows of `dim` values to the process-wide index, creating it if needed
+pub fn append(values: &[f32], dim: usize) -> EmbeddingResult<Vec<u64>> {
+    let mut index = INDEX.lock();
+    let index = index.get_or_insert_with(|| EmbeddingIndex::new(dim));
+    if index.dim() != dim {
+        return Err(EmbeddingError::DimensionMismatch {
+            expected: index.dim(),
+            actual: dim,
+        });
+    }
+    index.append(values)
+}
+
+/// Delete rows of the process-wide index
+///
+/// # Returns
+/// How many of `ids` were live
+pub fn delete(ids: &[u64]) -> usize {
+    INDEX
+        .lock()
+        .as_mut()
+        .map_or(0, |index| ids.iter().filter(|&&id| index.delete(id)).count())
+}
+
+/// Make a query embedding searchable under the handle of its tensor
+pub fn register_query(handle: &str, embedding: Vec<f32>) {
+    QUERIES.lock().insert(handle.to_string(), embedding);
+}
+
+pub fn unregister_query(handle: &str) -> bool {
+    QUERIES.lock().remove(handle).is_some()
+}
+
+/// Search the process-wide index with a registered query
+pub fn search(query_handle: &str, k: usize) -> EmbeddingResult<Vec<Hit>> {
+    let query = QUERIES
+        .lock()
+        .get(query_handle)
+        .cloned()
+        .ok_or_else(|| EmbeddingError::UnknownQuery(query_handle.to_string()))?;
+    INDEX
+        .lock()
+        .as_ref()
+        .ok_or(EmbeddingError::NoIndex)?
+        .search(&query, k)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_host_ranks_by_cosine() {
+        let mut index = EmbeddingIndex::new(2);
+        let ids = index
+            .append(&[1.0, 0.0, 0.0, 1.0, 2.0, 2.0, 0.0, 0.0, 3.0, 0.0])
+            .unwrap();
+        assert_eq!(ids, vec![0, 1, 2, 3, 4]);
+        let hits = index.search(&[1.0, 0.0], 3).unwrap();
+        // Rows 0 and 4 tie at 1.0; the lower row wins
+        let ids: Vec<u64> = hits.iter().map(|h| h.id).collect();
+        assert_eq!(ids, vec![0, 4, 2]);
+        assert!((hits[2].score - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+        assert!(matches!(
+            index.search(&[1.0], 1),
+            Err(EmbeddingError::DimensionMismatch { expected: 2, actual: 1 })
+        ));
+        assert!(matches!(index.append(&[1.0]), Err(EmbeddingError::PartialRow(1))));
+        assert_eq!(hits_json(&hits[..1]), r#"[{"id":0,"score":1.0}]"#);
+    }
+
+    #[test]
+    fn test_parse_matches_drops_missing_rows() {
+        let mut bytes = Vec::new();
+        for (row, score) in [(7u32, 0.5f32), (NO_ROW, -2.0)] {
+            bytes.extend_from_slice(&row.to_le_bytes());
+            bytes.extend_from_slice(&score.to_le_bytes());
+        }
+        assert_eq!(parse_matches(&bytes), vec![Match { row: 7, score: 0.5 }]);
+        assert_eq!(groups(1), [1, 1, 1]);
+    }
+
+    #[test]
+    fn test_tombstones_and_compaction_keep_ids() {
+        let mut index = EmbeddingIndex::new(1);
+        index.append(&[1.0; 8]).unwrap();
+        assert!(index.delete(0));
+        assert!(!index.delete(0));
+        assert_eq!(index.tombstone_words(), &[1]);
+        let ids: Vec<u64> = index.search(&[1.0], 2).unwrap().iter().map(|h| h.id).collect();
+        assert_eq!(ids, vec![1, 2]);
+
+        // The second delete of eight rows reaches COMPACT_FRACTION
+        assert!(index.delete(5));
+        assert_eq!((index.len(), index.live(), index.generation()), (6, 6, 1));
+        assert_eq!(index.tombstone_words(), &[0]);
+        assert_eq!(index.resolve(&[Match { row: 4, score: 1.0 }]), vec![Hit { id: 6, score: 1.0 }]);
+        assert_eq!(index.append(&[1.0]).unwrap(), vec![8]);
+    }
+
+    #[test]
+    fn test_quantized_rows_rank_like_f32() {
+        assert_eq!(Quantization::Int8.quantize(&[1.0, -0.5, 0.0, 0.25, 2.0]), vec![0x10_00_E0_40, 0x7F]);
+        assert_eq!(Quantization::Binary.quantize(&[1.0, -1.0, 0.0, 3.0]), vec![0b1001]);
+        assert_eq!(Quantization::from_name("binary"), Some(Quantization::Binary));
+
+        let rows = [1.0, 0.1, 0.0, 1.0, 0.7, 0.7, -1.0, -1.0];
+        for quantization in [Quantization::Int8, Quantization::Binary] {
+            let mut index = EmbeddingIndex::with_quantization(2, quantization);
+            index.append(&rows).unwrap();
+            assert_eq!(index.words().len(), 4);
+            let hits = index.search(&[1.0, 0.2], 4).unwrap();
+            assert_eq!(hits[0].id, 0, "{}", quantization.name());
+            assert_eq!(hits[3].id, 3, "{}", quantization.name());
+        }
+    }
+
+    #[test]
+    fn test_registered_query_searches_index() {
+        assert!(matches!(search("missing-query", 1), Err(EmbeddingError::UnknownQuery(_))));
+        set_index(EmbeddingIndex::new(2));
+        assert_eq!(append(&[0.0, 1.0], 2).unwrap(), vec![0]);
+        assert!(matches!(append(&[0.0], 1), Err(EmbeddingError::DimensionMismatch { .. })));
+        register_query("query-handle", vec![0.0, 2.0]);
+        assert_eq!(search("query-handle", 5).unwrap(), vec![Hit { id: 0, score: 1.0 }]);
+        assert_eq!(delete(&[0, 9]), 1);
+        assert!(unregister_query("query-handle"));
+    }
+}
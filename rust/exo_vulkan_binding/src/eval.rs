@@ -0,0 +1,280 @@
+//! Teacher-forced evaluation
+//!
+//! Measures how well a registered model predicts a token dataset, so users
+//! can quantify what a quantized or sparsified checkpoint costs on their own
+//! phone. The dataset is cut into windows; each window is fed in one forward
+//! pass from an empty KV cache and every position is scored against the
+//! token that actually follows it. The summed cross-entropy gives mean NLL
+//! and perplexity.
+//!
+//! Scoring goes through `LanguageModel::forward_nll`: runtimes keeping
+//! logits on device reduce them there with `KernelRegistry::cross_entropy`
+//! and read back one value per window; others are scored from their host
+//! logits by `sum_nll_host`.
+//!
+//! Datasets are registered per model under a handle, which `evaluate` takes.
+
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use serde::Serialize;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::speculative::{self, LanguageModel, SpeculativeError};
+
+/// Evaluation errors
+#[derive(Error, Debug)]
+pub enum EvalError {
+    #[error("Invalid dataset: {0}")]
+    InvalidDataset(String),
+
+    #[error("Dataset not found: {0}")]
+    NotFound(String),
+
+    #[error("{logits} logit rows for {targets} targets")]
+    LengthMismatch { logits: usize, targets: usize },
+
+    #[error("Target token {token} outside vocabulary of {vocab}")]
+    TargetOutOfRange { token: u32, vocab: usize },
+
+    #[error(transparent)]
+    Model(#[from] SpeculativeError),
+}
+
+pub type EvalResult<T> = Result<T, EvalError>;
+
+/// Tokens scored per forward pass unless the dataset says otherwise
+pub const DEFAULT_WINDOW: usize = 512;
+
+/// Token sequence to evaluate a model on
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EvalDataset {
+    model_id: String,
+    tokens: Vec<u32>,
+    window: usize,
+}
+
+impl EvalDataset {
+    /// `tokens` scored by `model_id`, `window` predictions per forward pass
+    pub fn new(model_id: &str, tokens: Vec<u32>, window: usize) -> EvalResult<Self> {
+        if tokens.len() < 2 {
+            return Err(EvalError::InvalidDataset("at least 2 tokens are needed".to_string()));
+        }
+        if window == 0 {
+            return Err(EvalError::InvalidDataset("window must be > 0".to_string()));
+        }
+        Ok(Self {
+            model_id: model_id.to_string(),
+            tokens,
+            window,
+        })
+    }
+
+    pub fn model_id(&self) -> &str {
+        &self.model_id
+    }
+
+    /// `(inputs, targets)` of each forward pass
+    ///
+    /// Consecutive windows share one token, so every token but the first is
+    /// predicted exactly once.
+    pub fn windows(&self) -> impl Iterator<Item = (&[u32], &[u32])> + '_ {
+        (0..self.tokens.len() - 1).step_by(self.window).map(|start| {
+            let end = (start + self.window + 1).min(self.tokens.len());
+            (&self.tokens[start..end - 1], &self.tokens[start + 1..end])
+        })
+    }
+}
+
+/// Outcome of an evaluation
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct EvalReport {
+    /// Predictions scored
+    pub tokens: u64,
+    pub windows: u64,
+    /// Summed negative log-likelihood, in nats
+    pub total_nll: f64,
+}
+
+impl EvalReport {
+    pub fn mean_nll(&self) -> f64 {
+        if self.tokens == 0 { 0.0 } else { self.total_nll / self.tokens as f64 }
+    }
+
+    pub fn perplexity(&self) -> f64 {
+        self.mean_nll().exp()
+    }
+
+    /// JSON object for the JNI layer
+    ///
+    /// Non-finite figures (a zero-probability target makes the NLL infinite)
+    /// are `null`, since JSON has no infinity or NaN.
+    pub fn to_json(&self) -> String {
+        let finite = |v: f64| v.is_finite().then_some(v);
+        let json = ReportJson {
+            tokens: self.tokens,
+            windows: self.windows,
+            mean_nll: finite(self.mean_nll()),
+            perplexity: finite(self.perplexity()),
+        };
+        serde_json::to_string(&json).expect("report fields always serialize")
+    }
+}
+
+/// Wire form of `EvalReport`
+#[derive(Serialize)]
+struct ReportJson {
+    tokens: u64,
+    windows: u64,
+    mean_nll: Option<f64>,
+    perplexity: Option<f64>,
+}
+
+/// Summed cross-entropy of `logits[i]` against `targets[i]`, in nats
+pub fn sum_nll_host(logits: &[Vec<f32>], targets: &[u32]) -> EvalResult<f64> {
+    if logits.len() != targets.len() {
+        return Err(EvalError::LengthMismatch {
+            logits: logits.len(),
+            targets: targets.len(),
+        });
+    }
+    logits.iter().zip(targets).try_fold(0.0, |total, (row, &token)| {
+        let logit = *row.get(token as usize).ok_or(EvalError::TargetOutOfRange {
+            token,
+            vocab: row.len(),
+        })?;
+        let max = row.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let sum: f64 = row.iter().map(|&v| f64::from(v - max).exp()).sum();
+        Ok(total + sum.ln() + f64::from(max) - f64::from(logit))
+    })
+}
+
+/// Score `dataset` with `model`, clearing its KV cache
+pub fn evaluate_model(model: &mut dyn LanguageModel, dataset: &EvalDataset) -> EvalResult<EvalReport> {
+    let mut report = EvalReport::default();
+    for (inputs, targets) in dataset.windows() {
+        model.truncate_kv(0);
+        report.total_nll += model.forward_nll(inputs, targets)?;
+        report.tokens += targets.len() as u64;
+        report.windows += 1;
+    }
+    model.truncate_kv(0);
+    Ok(report)
+}
+
+lazy_static! {
+    static ref DATASETS: Mutex<HashMap<String, EvalDataset>> = Mutex::new(HashMap::new());
+}
+
+/// Register a dataset for `evaluate`
+///
+/// # Returns
+/// Dataset handle
+pub fn register_dataset(dataset: EvalDataset) -> String {
+    let handle = Uuid::new_v4().to_string();
+    DATASETS.lock().insert(handle.clone(), dataset);
+    handle
+}
+
+/// Forget a dataset
+pub fn release_dataset(handle: &str) -> bool {
+    DATASETS.lock().remove(handle).is_some()
+}
+
+/// The dataset registered under `handle`
+pub fn dataset(handle: &str) -> EvalResult<EvalDataset> {
+    DATASETS
+        .lock()
+        .get(handle)
+        .cloned()
+        .ok_or_else(|| EvalError::NotFound(handle.to_string()))
+}
+
+/// Score a registered dataset with the runtime of its model
+pub fn evaluate(handle: &str) -> EvalResult<EvalReport> {
+    let dataset = dataset(handle)?;
+    let model = speculative::runtime(dataset.model_id())?;
+    let mut model = model.lock();
+    evaluate_model(&mut **model, &dataset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::speculative::SpeculativeResult;
+
+    /// Predicts `last + 1` with logit 2 over an 8-token vocabulary
+    struct Counter {
+        kv: usize,
+    }
+
+    impl LanguageModel for Counter {
+        fn forward(&mut self, tokens: &[u32]) -> SpeculativeResult<Vec<Vec<f32>>> {
+            self.kv += tokens.len();
+            Ok(tokens
+                .iter()
+                .map(|&t| (0..8).map(|v| if v == (t + 1) % 8 { 2.0 } else { 0.0 }).collect())
+                .collect())
+        }
+
+        fn kv_len(&self) -> usize {
+            self.kv
+        }
+
+        fn truncate_kv(&mut self, len: usize) {
+            self.kv = self.kv.min(len);
+        }
+    }
+
+    #[test]
+    fn test_windows_cover_every_prediction() {
+        let dataset = EvalDataset::new("m", (0..6).collect(), 2).unwrap();
+        let windows: Vec<_> = dataset.windows().collect();
+        assert_eq!(
+            windows,
+            vec![(&[0, 1][..], &[1, 2][..]), (&[2, 3][..], &[3, 4][..]), (&[4][..], &[5][..])]
+        );
+        assert!(EvalDataset::new("m", vec![1], 2).is_err());
+        assert!(EvalDataset::new("m", vec![1, 2], 0).is_err());
+    }
+
+    #[test]
+    fn test_report_json_nulls_non_finite() {
+        let report = EvalReport {
+            tokens: 2,
+            windows: 1,
+            total_nll: f64::INFINITY,
+        };
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["tokens"], 2);
+        assert!(json["mean_nll"].is_null() && json["perplexity"].is_null());
+
+        let nan = EvalReport { total_nll: f64::NAN, ..report };
+        assert!(serde_json::from_str::<serde_json::Value>(&nan.to_json()).unwrap()["mean_nll"].is_null());
+        let finite = EvalReport { total_nll: 2.0, ..report };
+        let json: serde_json::Value = serde_json::from_str(&finite.to_json()).unwrap();
+        assert_eq!(json["mean_nll"], 1.0);
+    }
+
+    #[test]
+    fn test_evaluate_model() {
+        let mut model = Counter { kv: 0 };
+        let dataset = EvalDataset::new("m", vec![0, 1, 2, 3, 0], 3).unwrap();
+        let report = evaluate_model(&mut model, &dataset).unwrap();
+        assert_eq!((report.tokens, report.windows), (4, 2));
+        assert_eq!(model.kv_len(), 0);
+
+        // Three right guesses and one wrong one, from the same distribution
+        let z = (2.0f64.exp() + 7.0).ln();
+        assert!((report.total_nll - (3.0 * (z - 2.0) + z)).abs() < 1e-6);
+        assert!((report.perplexity() - report.mean_nll().exp()).abs() < 1e-12);
+        assert!(report.to_json().contains("\"tokens\":4"));
+
+        assert!(matches!(
+            sum_nll_host(&[vec![0.0; 8]], &[8]),
+            Err(EvalError::TargetOutOfRange { token: 8, vocab: 8 })
+        ));
+    }
+}
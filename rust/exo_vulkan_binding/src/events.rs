@@ -0,0 +1,189 @@
+//! Telemetry event bus
+//!
+//! A single process-wide broadcast channel of `GpuEvent`s. Subsystems emit
+//! events here instead of each growing its own notification path; metrics,
+//! tracing, the JNI layer and tests subscribe to whatever they need. Slow
+//! subscribers lose the oldest events rather than blocking emitters.
+
+use std::time::Duration;
+
+use tokio::sync::broadcast;
+
+/// Events buffered per subscriber before the oldest are dropped
+pub const EVENT_BUS_CAPACITY: usize = 1024;
+
+/// Direction of a data transfer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransferDirection {
+    HostToDevice,
+    DeviceToHost,
+    DeviceToDevice,
+}
+
+impl TransferDirection {
+    /// Stable name for logs and metrics
+    pub fn name(self) -> &'static str {
+        match self {
+            TransferDirection::HostToDevice => "host_to_device",
+            TransferDirection::DeviceToHost => "device_to_host",
+            TransferDirection::DeviceToDevice => "device_to_device",
+        }
+    }
+}
+
+/// Availability change of an enumerated device
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeviceStatus {
+    /// Seen for the first time and given the next free index
+    Added,
+    /// Missing from a re-enumeration; its index stays reserved
+    Offline,
+    /// Seen again after going offline, at its old index
+    Online,
+}
+
+impl DeviceStatus {
+    /// Stable name for logs and metrics
+    pub fn name(self) -> &'static str {
+        match self {
+            DeviceStatus::Added => "added",
+            DeviceStatus::Offline => "offline",
+            DeviceStatus::Online => "online",
+        }
+    }
+}
+
+/// Something observable that happened in the GPU backend
+#[derive(Clone, Debug, PartialEq)]
+pub enum GpuEvent {
+    /// Device memory allocated
+    Allocated { handle_id: String, size: u64 },
+    /// Device memory freed
+    Freed { handle_id: String, size: u64 },
+    /// An allocation changed size, possibly moving to a new buffer
+    Resized {
+        handle_id: String,
+        old_size: u64,
+        new_size: u64,
+    },
+    /// Compute work submitted to a queue
+    Dispatched { name: String, workgroups: [u32; 3] },
+    /// Data copied between host and device
+    Transfer {
+        direction: TransferDirection,
+        bytes: u64,
+        duration: Duration,
+    },
+    /// Device thermal state changed (0 = nominal, higher is hotter)
+    Thermal { device_index: usize, level: u32 },
+    /// A known-slow usage pattern was detected
+    SlowPath { kind: &'static str, detail: String },
+    /// An operation failed
+    Error { source: &'static str, message: String },
+    /// A device appeared, vanished or came back (see `hotplug`)
+    DeviceStatusChanged {
+        device_index: usize,
+        device_id: String,
+        status: DeviceStatus,
+    },
+    /// The Vulkan device was lost; `source` is the call that reported it
+    DeviceLost { source: &'static str },
+    /// A pipeline stage stopped responding or lost its device
+    StageFailed {
+        stage_id: String,
+        node_id: String,
+        reason: String,
+    },
+    /// A failed stage's layers were loaded onto another node
+    StageReassigned {
+        stage_id: String,
+        from_node: String,
+        to_node: String,
+    },
+    /// Memory usage crossed a high-water mark; `breakdown` is bytes per tag
+    MemoryPressure {
+        threshold_percent: u32,
+        used_bytes: u64,
+        budget_bytes: u64,
+        breakdown: Vec<(String, u64)>,
+    },
+    /// Runtime configuration changed; cold fields wait for re-initialization
+    ConfigReloaded {
+        applied: Vec<String>,
+        pending_reinit: Vec<String>,
+    },
+}
+
+impl GpuEvent {
+    /// Stable event kind name
+    pub fn kind(&self) -> &'static str {
+        match self {
+            GpuEvent::Allocated { .. } => "allocated",
+            GpuEvent::Freed { .. } => "freed",
+            GpuEvent::Resized { .. } => "resized",
+            GpuEvent::Dispatched { .. } => "dispatched",
+            GpuEvent::Transfer { .. } => "transfer",
+            GpuEvent::Thermal { .. } => "thermal",
+            GpuEvent::SlowPath { .. } => "slow_path",
+            GpuEvent::Error { .. } => "error",
+            GpuEvent::DeviceStatusChanged { .. } => "device_status",
+            GpuEvent::DeviceLost { .. } => "device_lost",
+            GpuEvent::StageFailed { .. } => "stage_failed",
+            GpuEvent::StageReassigned { .. } => "stage_reassigned",
+            GpuEvent::MemoryPressure { .. } => "memory_pressure",
+            GpuEvent::ConfigReloaded { .. } => "config_reloaded",
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref EVENT_BUS: broadcast::Sender<GpuEvent> = broadcast::channel(EVENT_BUS_CAPACITY).0;
+}
+
+/// Publish an event to all current subscribers
+pub fn emit(event: GpuEvent) {
+    // No subscribers is fine; the event is simply dropped
+    let _ = EVENT_BUS.send(event);
+}
+
+/// Subscribe to events emitted from now on
+pub fn subscribe() -> broadcast::Receiver<GpuEvent> {
+    EVENT_BUS.subscribe()
+}
+
+/// Number of live subscribers
+pub fn subscriber_count() -> usize {
+    EVENT_BUS.receiver_count()
+}
+
+/// Drain up to `max` buffered events without blocking
+///
+/// Lagged events are skipped; the number skipped is returned alongside.
+pub fn drain(receiver: &mut broadcast::Receiver<GpuEvent>, max: usize) -> (Vec<GpuEvent>, u64) {
+    let mut events = Vec::new();
+    let mut lagged = 0;
+    while events.len() < max {
+        match receiver.try_recv() {
+            Ok(event) => events.push(event),
+            Err(broadcast::error::TryRecvError::Lagged(n)) => lagged += n,
+            Err(_) => break,
+        }
+    }
+    (events, lagged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subscriber_receives_events() {
+        let mut receiver = subscribe();
+        emit(GpuEvent::Thermal {
+            device_index: 0,
+            level: 2,
+        });
+        let (events, _) = drain(&mut receiver, usize::MAX);
+        assert!(events.iter().any(|e| matches!(e, GpuEvent::Thermal { level: 2, .. })));
+    }
+}
@@ -0,0 +1,357 @@
+//! Pipeline stage failure detection and re-assignment
+//!
+//! When a phone drops off Wi-Fi or its GPU is lost, the pipeline stalls on
+//! the stage it hosted. `StageMonitor` tracks which node runs which layer
+//! range, expects a heartbeat from every stage (typically one per decode
+//! step), and reports a stage as failed when its heartbeats stop or its
+//! device is lost. Failures go to registered hooks and to the event bus as
+//! `GpuEvent::StageFailed`, so exo's orchestrator can react without polling.
+//!
+//! Healing is `reassign`: the stage's layer range is loaded onto a
+//! surviving node through a `StageLoader`, which reads the weights from that
+//! node's local model cache, and the monitor switches the stage over.
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+
+use crate::events::{self, GpuEvent};
+
+/// Failover-related errors
+#[derive(Error, Debug)]
+pub enum FailoverError {
+    #[error("Unknown stage: {0}")]
+    UnknownStage(String),
+
+    #[error("Stage {0} is already assigned")]
+    DuplicateStage(String),
+
+    #[error("Loading stage {stage_id} onto {node_id} failed: {message}")]
+    LoadFailed {
+        stage_id: String,
+        node_id: String,
+        message: String,
+    },
+}
+
+pub type FailoverResult<T> = Result<T, FailoverError>;
+
+/// Where one pipeline stage runs
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StageAssignment {
+    pub stage_id: String,
+    pub model_id: String,
+    pub layers: Range<usize>,
+    pub node_id: String,
+    pub device_index: usize,
+    /// Allocation handles holding the stage's weights
+    pub allocations: Vec<String>,
+}
+
+/// Why a stage is considered failed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FailureKind {
+    /// No heartbeat for longer than the timeout
+    Timeout(Duration),
+    /// The hosting device was lost
+    DeviceLost,
+}
+
+/// A stage that needs re-assignment
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StageFailure {
+    pub stage_id: String,
+    pub node_id: String,
+    pub layers: Range<usize>,
+    pub kind: FailureKind,
+}
+
+impl StageFailure {
+    fn reason(&self) -> String {
+        match &self.kind {
+            FailureKind::Timeout(elapsed) => format!("no heartbeat for {} ms", elapsed.as_millis()),
+            FailureKind::DeviceLost => "device lost".to_string(),
+        }
+    }
+}
+
+/// Loads a stage's layers onto a node; implemented by the orchestrator
+/// side that knows where each node's model cache lives
+pub trait StageLoader {
+    /// Load `layers` of `model_id` onto `device_index` of `node_id`
+    ///
+    /// # Returns
+    /// Allocation handles holding the loaded weights
+    fn load_stage(
+        &mut self,
+        model_id: &str,
+        layers: Range<usize>,
+        node_id: &str,
+        device_index: usize,
+    ) -> Result<Vec<String>, String>;
+}
+
+/// Callback invoked for every newly detected failure
+pub type FailureHook = Box<dyn Fn(&StageFailure) + Send + Sync>;
+
+struct MonitoredStage {
+    assignment: StageAssignment,
+    last_heartbeat: Instant,
+    /// Set once reported, so each failure is delivered once
+    failed: bool,
+}
+
+/// Watches pipeline stages for timeouts and device loss
+pub struct StageMonitor {
+    timeout: Duration,
+    stages: HashMap<String, MonitoredStage>,
+    hooks: Vec<FailureHook>,
+}
+
+impl StageMonitor {
+    /// Stages without a heartbeat for `timeout` are reported failed
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            stages: HashMap::new(),
+            hooks: Vec::new(),
+        }
+    }
+
+    /// Register a callback for detected failures
+    pub fn on_failure(&mut self, hook: FailureHook) {
+        self.hooks.push(hook);
+    }
+
+    /// Start monitoring a stage
+    pub fn assign(&mut self, assignment: StageAssignment, now: Instant) -> FailoverResult<()> {
+        if self.stages.contains_key(&assignment.stage_id) {
+            return Err(FailoverError::DuplicateStage(assignment.stage_id));
+        }
+        self.stages.insert(
+            assignment.stage_id.clone(),
+            MonitoredStage {
+                assignment,
+                last_heartbeat: now,
+                failed: false,
+            },
+        );
+        Ok(())
+    }
+
+    /// Stop monitoring a stage, returning its assignment
+    pub fn remove(&mut self, stage_id: &str) -> FailoverResult<StageAssignment> {
+        self.stages
+            .remove(stage_id)
+            .map(|s| s.assignment)
+            .ok_or_else(|| FailoverError::UnknownStage(stage_id.to_string()))
+    }
+
+    pub fn assignment(&self, stage_id: &str) -> Option<&StageAssignment> {
+        self.stages.get(stage_id).map(|s| &s.assignment)
+    }
+
+    /// Record that a stage completed work
+    ///
+    /// Heartbeats from a stage already reported failed are ignored; it stays
+    /// failed until reassigned.
+    pub fn heartbeat(&mut self, stage_id: &str, now: Instant) -> FailoverResult<()> {
+        let stage = self
+            .stages
+            .get_mut(stage_id)
+            .ok_or_else(|| FailoverError::UnknownStage(stage_id.to_string()))?;
+        if !stage.failed {
+            stage.last_heartbeat = now;
+        }
+        Ok(())
+    }
+
+    fn report(&self, failure: &StageFailure) {
+        log::warn!("Stage {} on {} failed: {}", failure.stage_id, failure.node_id, failure.reason());
+        events::emit(GpuEvent::StageFailed {
+            stage_id: failure.stage_id.clone(),
+            node_id: failure.node_id.clone(),
+            reason: failure.reason(),
+        });
+        for hook in &self.hooks {
+            hook(failure);
+        }
+    }
+
+    fn fail_where(&mut self, kind: impl Fn(&MonitoredStage) -> Option<FailureKind>) -> Vec<StageFailure> {
+        let mut failures = Vec::new();
+        for stage in self.stages.values_mut().filter(|s| !s.failed) {
+            if let Some(kind) = kind(stage) {
+                stage.failed = true;
+                failures.push(StageFailure {
+                    stage_id: stage.assignment.stage_id.clone(),
+                    node_id: stage.assignment.node_id.clone(),
+                    layers: stage.assignment.layers.clone(),
+                    kind,
+                });
+            }
+        }
+        failures.sort_by_key(|f| f.layers.start);
+        for failure in &failures {
+            self.report(failure);
+        }
+        failures
+    }
+
+    /// Report stages whose heartbeat is overdue
+    pub fn check(&mut self, now: Instant) -> Vec<StageFailure> {
+        let timeout = self.timeout;
+        self.fail_where(|stage| {
+            let elapsed = now.saturating_duration_since(stage.last_heartbeat);
+            (elapsed > timeout).then_some(FailureKind::Timeout(elapsed))
+        })
+    }
+
+    /// Report every stage on a lost device (see `CommandError::DeviceLost`)
+    /// or on a node that left the cluster (`device_index` None)
+    pub fn device_lost(&mut self, node_id: &str, device_index: Option<usize>) -> Vec<StageFailure> {
+        self.fail_where(|stage| {
+            let on_device = stage.assignment.node_id == node_id
+                && device_index.is_none_or(|d| d == stage.assignment.device_index);
+            on_device.then_some(FailureKind::DeviceLost)
+        })
+    }
+
+    /// Stages currently failed and awaiting reassignment
+    pub fn failed(&self) -> Vec<&StageAssignment> {
+        self.stages
+            .values()
+            .filter(|s| s.failed)
+            .map(|s| &s.assignment)
+            .collect()
+    }
+
+    /// Load a stage's layer range onto another node and switch it over
+    ///
+    /// On failure the stage keeps its old assignment and can be retried on
+    /// a different node.
+    pub fn reassign(
+        &mut self,
+        stage_id: &str,
+        node_id: &str,
+        device_index: usize,
+        loader: &mut dyn StageLoader,
+        now: Instant,
+    ) -> FailoverResult<StageAssignment> {
+        let stage = self
+            .stages
+            .get_mut(stage_id)
+            .ok_or_else(|| FailoverError::UnknownStage(stage_id.to_string()))?;
+        let allocations = loader
+            .load_stage(
+                &stage.assignment.model_id,
+                stage.assignment.layers.clone(),
+                node_id,
+                device_index,
+            )
+            .map_err(|message| FailoverError::LoadFailed {
+                stage_id: stage_id.to_string(),
+                node_id: node_id.to_string(),
+                message,
+            })?;
+
+        let replacement = StageAssignment {
+            node_id: node_id.to_string(),
+            device_index,
+            allocations,
+            ..stage.assignment.clone()
+        };
+        let previous = std::mem::replace(&mut stage.assignment, replacement);
+        stage.last_heartbeat = now;
+        stage.failed = false;
+
+        events::emit(GpuEvent::StageReassigned {
+            stage_id: stage_id.to_string(),
+            from_node: previous.node_id.clone(),
+            to_node: node_id.to_string(),
+        });
+        log::info!(
+            "Stage {} (layers {:?}) moved from {} to {}",
+            stage_id,
+            previous.layers,
+            previous.node_id,
+            node_id
+        );
+        Ok(previous)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    fn stage(id: &str, node: &str, layers: Range<usize>) -> StageAssignment {
+        StageAssignment {
+            stage_id: id.to_string(),
+            model_id: "m".to_string(),
+            layers,
+            node_id: node.to_string(),
+            device_index: 0,
+            allocations: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_timeout_and_device_loss_reported_once() {
+        let start = Instant::now();
+        let mut monitor = StageMonitor::new(Duration::from_secs(1));
+        let seen = Arc::new(AtomicUsize::new(0));
+        let counter = seen.clone();
+        monitor.on_failure(Box::new(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        }));
+        monitor.assign(stage("s0", "a", 0..8), start).unwrap();
+        monitor.assign(stage("s1", "b", 8..16), start).unwrap();
+
+        monitor.heartbeat("s0", start + Duration::from_millis(900)).unwrap();
+        let failures = monitor.check(start + Duration::from_millis(1500));
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].stage_id, "s1");
+        assert!(monitor.check(start + Duration::from_secs(5)).iter().all(|f| f.stage_id == "s0"));
+
+        let mut monitor = StageMonitor::new(Duration::from_secs(1));
+        monitor.assign(stage("s0", "a", 0..8), start).unwrap();
+        assert_eq!(monitor.device_lost("a", Some(0))[0].kind, FailureKind::DeviceLost);
+        assert!(monitor.device_lost("a", None).is_empty());
+        assert_eq!(seen.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_reassign_loads_layers() {
+        struct Loader(Vec<(Range<usize>, String)>);
+        impl StageLoader for Loader {
+            fn load_stage(&mut self, _: &str, layers: Range<usize>, node: &str, _: usize) -> Result<Vec<String>, String> {
+                if node == "full" {
+                    return Err("out of memory".to_string());
+                }
+                self.0.push((layers, node.to_string()));
+                Ok(vec!["h".to_string()])
+            }
+        }
+
+        let now = Instant::now();
+        let mut monitor = StageMonitor::new(Duration::from_secs(1));
+        monitor.assign(stage("s1", "b", 8..16), now).unwrap();
+        monitor.device_lost("b", None);
+        let mut loader = Loader(Vec::new());
+
+        assert!(monitor.reassign("s1", "full", 0, &mut loader, now).is_err());
+        assert_eq!(monitor.failed().len(), 1);
+        let previous = monitor.reassign("s1", "c", 1, &mut loader, now).unwrap();
+        assert_eq!(previous.node_id, "b");
+        assert_eq!(loader.0, vec![(8..16, "c".to_string())]);
+        let current = monitor.assignment("s1").unwrap();
+        assert_eq!((current.node_id.as_str(), current.device_index), ("c", 1));
+        assert!(monitor.failed().is_empty());
+    }
+}
@@ -0,0 +1,367 @@
+//! Per-tenant quotas and weighted fair queuing
+//!
+//! A shared node serves several tenants (gRPC sessions, Activities; the
+//! client ids of `registry`). Requests are queued per tenant and dispatched
+//! by weighted fair queuing: each tenant has a virtual time that advances by
+//! `tokens / weight` whenever it is charged, and the backlogged tenant with
+//! the lowest virtual time goes next. A tenant that goes idle and comes back
+//! resumes at the current minimum rather than with banked credit.
+//!
+//! On top of the fair order, each tenant has a token bucket limiting its
+//! token rate and a cap on the device memory it may hold. A tenant over its
+//! rate is skipped until its bucket refills; the batcher learns when from
+//! `next_eligible`.
+//!
+//! Time is passed in by the caller, so the scheduler does no clock reads.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+
+/// Fair-share-related errors
+#[derive(Error, Debug, PartialEq)]
+pub enum FairShareError {
+    #[error("Unknown tenant: {0}")]
+    UnknownTenant(String),
+
+    #[error("Tenant already registered: {0}")]
+    DuplicateTenant(String),
+
+    #[error("Invalid quota: {0}")]
+    InvalidQuota(String),
+
+    #[error("Tenant {tenant} memory quota exceeded: requested {requested} bytes, {available} available")]
+    MemoryQuotaExceeded {
+        tenant: String,
+        requested: u64,
+        available: u64,
+    },
+}
+
+pub type FairShareResult<T> = Result<T, FairShareError>;
+
+/// Limits of one tenant
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TenantQuota {
+    /// Relative share of dispatch order; a weight-2 tenant gets twice the
+    /// tokens of a weight-1 tenant when both are backlogged
+    pub weight: f64,
+    /// Sustained token rate; `None` for unlimited
+    pub tokens_per_second: Option<f64>,
+    /// Tokens that may be spent at once after idling
+    pub burst_tokens: u64,
+    /// Device memory the tenant may hold; `None` for unlimited
+    pub memory_bytes: Option<u64>,
+}
+
+impl Default for TenantQuota {
+    fn default() -> Self {
+        Self {
+            weight: 1.0,
+            tokens_per_second: None,
+            burst_tokens: 0,
+            memory_bytes: None,
+        }
+    }
+}
+
+impl TenantQuota {
+    fn validate(&self) -> FairShareResult<()> {
+        if !self.weight.is_finite() || self.weight <= 0.0 {
+            return Err(FairShareError::InvalidQuota(format!("weight {}", self.weight)));
+        }
+        if let Some(rate) = self.tokens_per_second {
+            if !rate.is_finite() || rate <= 0.0 {
+                return Err(FairShareError::InvalidQuota(format!("token rate {}", rate)));
+            }
+            if self.burst_tokens == 0 {
+                return Err(FairShareError::InvalidQuota(
+                    "rate-limited tenant needs a burst of at least 1 token".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A queued request and its estimated token cost
+struct Queued<R> {
+    request: R,
+    tokens: u64,
+}
+
+struct Tenant<R> {
+    quota: TenantQuota,
+    queue: VecDeque<Queued<R>>,
+    virtual_time: f64,
+    /// Token bucket level; negative after a request larger than the bucket
+    bucket: f64,
+    refilled_at: Instant,
+    memory_used: u64,
+}
+
+impl<R> Tenant<R> {
+    /// Bucket level at `now`
+    fn bucket_at(&self, now: Instant) -> f64 {
+        match self.quota.tokens_per_second {
+            Some(rate) => {
+                let elapsed = now.saturating_duration_since(self.refilled_at).as_secs_f64();
+                (self.bucket + elapsed * rate).min(self.quota.burst_tokens as f64)
+            }
+            None => self.bucket,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        self.bucket = self.bucket_at(now);
+        self.refilled_at = now;
+    }
+
+    /// Time from `now` until a request of `tokens` fits in the bucket;
+    /// requests larger than the burst wait for a full bucket
+    fn wait_for(&self, tokens: u64, now: Instant) -> Duration {
+        let Some(rate) = self.quota.tokens_per_second else {
+            return Duration::ZERO;
+        };
+        let needed = (tokens as f64).min(self.quota.burst_tokens as f64) - self.bucket_at(now);
+        if needed <= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(needed / rate)
+        }
+    }
+}
+
+/// Usage snapshot of one tenant
+#[derive(Clone, Debug, PartialEq)]
+pub struct TenantUsage {
+    pub queued: usize,
+    pub virtual_time: f64,
+    pub memory_used: u64,
+}
+
+/// Weighted fair queue with per-tenant rate and memory quotas
+pub struct FairShareScheduler<R> {
+    tenants: HashMap<String, Tenant<R>>,
+}
+
+impl<R> Default for FairShareScheduler<R> {
+    fn default() -> Self {
+        Self {
+            tenants: HashMap::new(),
+        }
+    }
+}
+
+impl<R> FairShareScheduler<R> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lowest virtual time among backlogged tenants
+    fn min_virtual_time(&self) -> Option<f64> {
+        self.tenants
+            .values()
+            .filter(|t| !t.queue.is_empty())
+            .map(|t| t.virtual_time)
+            .min_by(f64::total_cmp)
+    }
+
+    fn tenant_mut(&mut self, tenant: &str) -> FairShareResult<&mut Tenant<R>> {
+        self.tenants
+            .get_mut(tenant)
+            .ok_or_else(|| FairShareError::UnknownTenant(tenant.to_string()))
+    }
+
+    pub fn add_tenant(&mut self, tenant: &str, quota: TenantQuota, now: Instant) -> FairShareResult<()> {
+        quota.validate()?;
+        if self.tenants.contains_key(tenant) {
+            return Err(FairShareError::DuplicateTenant(tenant.to_string()));
+        }
+        let virtual_time = self.min_virtual_time().unwrap_or(0.0);
+        self.tenants.insert(
+            tenant.to_string(),
+            Tenant {
+                quota,
+                queue: VecDeque::new(),
+                virtual_time,
+                bucket: quota.burst_tokens as f64,
+                refilled_at: now,
+                memory_used: 0,
+            },
+        );
+        Ok(())
+    }
+
+    /// Change a tenant's limits; applies to the next dispatch
+    pub fn set_quota(&mut self, tenant: &str, quota: TenantQuota) -> FairShareResult<()> {
+        quota.validate()?;
+        let entry = self.tenant_mut(tenant)?;
+        entry.quota = quota;
+        entry.bucket = entry.bucket.min(quota.burst_tokens as f64);
+        Ok(())
+    }
+
+    /// Remove a tenant, returning its still-queued requests
+    pub fn remove_tenant(&mut self, tenant: &str) -> FairShareResult<Vec<R>> {
+        let removed = self
+            .tenants
+            .remove(tenant)
+            .ok_or_else(|| FairShareError::UnknownTenant(tenant.to_string()))?;
+        Ok(removed.queue.into_iter().map(|q| q.request).collect())
+    }
+
+    /// Queue a request estimated to cost `tokens` (prompt plus expected output)
+    pub fn enqueue(&mut self, tenant: &str, request: R, tokens: u64) -> FairShareResult<()> {
+        let floor = self.min_virtual_time();
+        let entry = self.tenant_mut(tenant)?;
+        if entry.queue.is_empty()
+            && let Some(floor) = floor
+        {
+            // Returning from idle: no credit for the time spent away
+            entry.virtual_time = entry.virtual_time.max(floor);
+        }
+        entry.queue.push_back(Queued { request, tokens });
+        Ok(())
+    }
+
+    /// Dispatch the next request in fair order among tenants within their
+    /// rate, charging its estimated tokens
+    pub fn next(&mut self, now: Instant) -> Option<(String, R)> {
+        let mut best: Option<(&String, f64)> = None;
+        for (id, tenant) in &self.tenants {
+            let Some(head) = tenant.queue.front() else {
+                continue;
+            };
+            if tenant.wait_for(head.tokens, now) > Duration::ZERO {
+                continue;
+            }
+            if best.is_none_or(|(_, vt)| tenant.virtual_time < vt) {
+                best = Some((id, tenant.virtual_time));
+            }
+        }
+        let id = best?.0.clone();
+        let tenant = self.tenants.get_mut(&id)?;
+        let queued = tenant.queue.pop_front()?;
+        tenant.refill(now);
+        charge(tenant, queued.tokens);
+        Some((id, queued.request))
+    }
+
+    /// Earliest time at which `next` can dispatch, if anything is queued
+    pub fn next_eligible(&self, now: Instant) -> Option<Instant> {
+        self.tenants
+            .values()
+            .filter_map(|t| Some(now + t.wait_for(t.queue.front()?.tokens, now)))
+            .min()
+    }
+
+    /// Charge tokens generated beyond a request's estimate, e.g. per decode
+    /// step in continuous batching
+    pub fn charge_tokens(&mut self, tenant: &str, tokens: u64, now: Instant) -> FairShareResult<()> {
+        let entry = self.tenant_mut(tenant)?;
+        entry.refill(now);
+        charge(entry, tokens);
+        Ok(())
+    }
+
+    /// Reserve device memory against the tenant's quota
+    pub fn reserve_memory(&mut self, tenant: &str, bytes: u64) -> FairShareResult<()> {
+        let entry = self.tenant_mut(tenant)?;
+        if let Some(limit) = entry.quota.memory_bytes {
+            let available = limit.saturating_sub(entry.memory_used);
+            if bytes > available {
+                return Err(FairShareError::MemoryQuotaExceeded {
+                    tenant: tenant.to_string(),
+                    requested: bytes,
+                    available,
+                });
+            }
+        }
+        entry.memory_used += bytes;
+        Ok(())
+    }
+
+    /// Return memory reserved with `reserve_memory`
+    pub fn release_memory(&mut self, tenant: &str, bytes: u64) -> FairShareResult<()> {
+        let entry = self.tenant_mut(tenant)?;
+        entry.memory_used = entry.memory_used.saturating_sub(bytes);
+        Ok(())
+    }
+
+    pub fn usage(&self, tenant: &str) -> Option<TenantUsage> {
+        self.tenants.get(tenant).map(|t| TenantUsage {
+            queued: t.queue.len(),
+            virtual_time: t.virtual_time,
+            memory_used: t.memory_used,
+        })
+    }
+
+    /// Requests queued across all tenants
+    pub fn queued(&self) -> usize {
+        self.tenants.values().map(|t| t.queue.len()).sum()
+    }
+}
+
+fn charge<R>(tenant: &mut Tenant<R>, tokens: u64) {
+    tenant.virtual_time += tokens as f64 / tenant.quota.weight;
+    if tenant.quota.tokens_per_second.is_some() {
+        tenant.bucket -= tokens as f64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weighted_order() {
+        let now = Instant::now();
+        let mut scheduler = FairShareScheduler::new();
+        scheduler.add_tenant("heavy", TenantQuota::default(), now).unwrap();
+        let quota = TenantQuota {
+            weight: 2.0,
+            ..TenantQuota::default()
+        };
+        scheduler.add_tenant("light", quota, now).unwrap();
+        for i in 0..6 {
+            scheduler.enqueue("heavy", i, 100).unwrap();
+            scheduler.enqueue("light", i, 100).unwrap();
+        }
+
+        let order: Vec<String> = (0..6).map(|_| scheduler.next(now).unwrap().0).collect();
+        let light = order.iter().filter(|t| *t == "light").count();
+        assert_eq!(light, 4);
+        assert_eq!(scheduler.queued(), 6);
+        assert_eq!(scheduler.remove_tenant("heavy").unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_rate_and_memory_quota() {
+        let now = Instant::now();
+        let mut scheduler = FairShareScheduler::new();
+        let quota = TenantQuota {
+            tokens_per_second: Some(100.0),
+            burst_tokens: 100,
+            memory_bytes: Some(1024),
+            ..TenantQuota::default()
+        };
+        scheduler.add_tenant("a", quota, now).unwrap();
+        scheduler.enqueue("a", 1, 100).unwrap();
+        scheduler.enqueue("a", 2, 50).unwrap();
+
+        assert_eq!(scheduler.next(now), Some(("a".to_string(), 1)));
+        assert_eq!(scheduler.next(now), None);
+        assert_eq!(scheduler.next_eligible(now), Some(now + Duration::from_millis(500)));
+        assert_eq!(scheduler.next(now + Duration::from_millis(500)), Some(("a".to_string(), 2)));
+
+        scheduler.reserve_memory("a", 1000).unwrap();
+        assert!(matches!(
+            scheduler.reserve_memory("a", 100),
+            Err(FairShareError::MemoryQuotaExceeded { available: 24, .. })
+        ));
+        scheduler.release_memory("a", 1000).unwrap();
+        assert!(scheduler.reserve_memory("a", 100).is_ok());
+    }
+}
@@ -0,0 +1,167 @@
+//! Cooperative decoding under a UI frame budget
+//!
+//! On a phone the GPU is shared with the app's compositor. A decode loop
+//! that submits back-to-back forward passes starves the UI and drops frames
+//! at 60/120 Hz. With a frame budget set, the loop gets at most `budget` of
+//! GPU time per display frame: before each step `FramePacer` predicts the
+//! step's cost from recent GPU timestamps, and if it would overrun the
+//! budget it sleeps until the next frame boundary so the UI's work can run.
+//!
+//! The budget is process-wide (set from JNI `setFrameBudgetMs`); decode
+//! loops pick it up when they start.
+
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+/// GPU time allowed per display frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameBudget {
+    /// GPU time the decode loop may use each frame
+    pub budget: Duration,
+    /// Display frame period, e.g. 16.6 ms at 60 Hz
+    pub frame_interval: Duration,
+}
+
+impl FrameBudget {
+    /// Budget for a display refreshing at `refresh_hz`
+    ///
+    /// # Returns
+    /// None if the budget does not fit in a frame
+    pub fn new(budget: Duration, refresh_hz: u32) -> Option<Self> {
+        let frame_interval = Duration::from_secs(1) / refresh_hz.max(1);
+        (!budget.is_zero() && budget < frame_interval).then_some(Self { budget, frame_interval })
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref FRAME_BUDGET: Mutex<Option<FrameBudget>> = Mutex::new(None);
+}
+
+/// Set or clear (None) the process-wide frame budget
+pub fn set_frame_budget(budget: Option<FrameBudget>) {
+    *FRAME_BUDGET.lock() = budget;
+}
+
+/// Current process-wide frame budget
+pub fn frame_budget() -> Option<FrameBudget> {
+    *FRAME_BUDGET.lock()
+}
+
+/// Weight of the newest sample in the step cost estimate
+const ESTIMATE_WEIGHT: f64 = 0.25;
+
+/// Paces one decode loop against a `FrameBudget`
+#[derive(Debug)]
+pub struct FramePacer {
+    config: FrameBudget,
+    frame_start: Instant,
+    /// GPU time spent in the current frame
+    used: Duration,
+    /// Smoothed GPU time of one step
+    step_estimate: Option<Duration>,
+}
+
+impl FramePacer {
+    pub fn new(config: FrameBudget, now: Instant) -> Self {
+        Self {
+            config,
+            frame_start: now,
+            used: Duration::ZERO,
+            step_estimate: None,
+        }
+    }
+
+    /// Pacer for the process-wide budget, if one is set
+    pub fn from_global(now: Instant) -> Option<Self> {
+        frame_budget().map(|config| Self::new(config, now))
+    }
+
+    /// Advance `frame_start` to the frame containing `now`
+    fn roll_frame(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.frame_start);
+        if elapsed >= self.config.frame_interval {
+            let frames = elapsed.as_nanos() / self.config.frame_interval.as_nanos();
+            self.frame_start += self.config.frame_interval * frames as u32;
+            self.used = Duration::ZERO;
+        }
+    }
+
+    /// Decide whether the next step may run now
+    ///
+    /// # Returns
+    /// How long to yield before the step, or None to run it immediately.
+    /// At least one step runs per frame so a step longer than the budget
+    /// still makes progress.
+    pub fn before_step(&mut self, now: Instant) -> Option<Duration> {
+        self.roll_frame(now);
+        let estimate = self.step_estimate.unwrap_or(Duration::ZERO);
+        if self.used.is_zero() || self.used + estimate <= self.config.budget {
+            return None;
+        }
+        let next_frame = self.frame_start + self.config.frame_interval;
+        self.frame_start = next_frame;
+        self.used = Duration::ZERO;
+        Some(next_frame.saturating_duration_since(now))
+    }
+
+    /// Record the GPU time a step took, from timestamp queries when available
+    pub fn after_step(&mut self, gpu_time: Duration) {
+        self.used += gpu_time;
+        self.step_estimate = Some(match self.step_estimate {
+            Some(estimate) => estimate.mul_f64(1.0 - ESTIMATE_WEIGHT) + gpu_time.mul_f64(ESTIMATE_WEIGHT),
+            None => gpu_time,
+        });
+    }
+
+    /// `before_step` against the wall clock, sleeping if told to yield
+    pub fn pace(&mut self) {
+        if let Some(wait) = self.before_step(Instant::now()) {
+            std::thread::sleep(wait);
+        }
+    }
+
+    /// GPU time used in the current frame
+    pub fn used(&self) -> Duration {
+        self.used
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn test_budget_must_fit_frame() {
+        assert!(FrameBudget::new(ms(4), 60).is_some());
+        assert!(FrameBudget::new(ms(10), 120).is_none());
+        assert!(FrameBudget::new(Duration::ZERO, 60).is_none());
+    }
+
+    #[test]
+    fn test_yields_to_next_frame_when_over_budget() {
+        let start = Instant::now();
+        let config = FrameBudget::new(ms(6), 100).unwrap();
+        let mut pacer = FramePacer::new(config, start);
+
+        assert_eq!(pacer.before_step(start), None);
+        pacer.after_step(ms(3));
+        assert_eq!(pacer.before_step(start + ms(3)), None);
+        pacer.after_step(ms(3));
+
+        // 6 ms used and the next step is predicted at 3 ms: wait out the frame
+        assert_eq!(pacer.before_step(start + ms(6)), Some(ms(4)));
+        assert_eq!(pacer.used(), Duration::ZERO);
+        pacer.after_step(ms(3));
+        assert_eq!(pacer.before_step(start + ms(13)), None);
+
+        // A late step lands in a later frame with a fresh budget
+        pacer.after_step(ms(5));
+        assert_eq!(pacer.before_step(start + ms(45)), None);
+        assert_eq!(pacer.used(), Duration::ZERO);
+    }
+}
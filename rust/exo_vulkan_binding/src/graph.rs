@@ -0,0 +1,764 @@
+//! Op graph and dispatch planning
+//!
+//! A minimal graph of tensor ops and the compiler passes that turn it into a
+//! list of kernel dispatches. The fusion pass merges chains of elementwise
+//! ops (e.g. bias + GELU + residual) into one dispatch of the elementwise
+//! megakernel, which interprets a short instruction list per element instead
+//! of round-tripping every intermediate through device memory.
+//!
+//! `execute` walks a plan on a `DispatchBackend`; in debug mode it scans each
+//! dispatch's output for NaN/Inf and aborts naming the op that produced them.
+//! Ops marked optional (stats collection, extra checks) carry a GPU-time
+//! budget: the executor skips them once they overrun it `OVERRUN_STRIKES`
+//! times in a row, or as soon as they fail, so debug ops can stay in
+//! production graphs without risking latency.
+//!
+//! `compile` also pads the sequence length with the `bucketing` policy, so
+//! graphs for nearby prompt lengths share a plan and its pipelines.
+
+use std::collections::{BTreeSet, HashMap};
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::bucketing::{self, BucketPolicy, PaddingStats};
+use crate::ops::NonFinite;
+
+/// Graph-related errors
+#[derive(Error, Debug)]
+pub enum GraphError {
+    #[error("Invalid node reference: {0}")]
+    InvalidNode(usize),
+
+    #[error("Graph is not topologically ordered at node {0}")]
+    NotTopological(usize),
+
+    #[error("Fused kernel exceeds {max} instructions")]
+    ProgramTooLong { max: usize },
+
+    #[error("Non-finite output from {op} (nan: {has_nan}, inf: {has_inf}, first index: {first_index:?})")]
+    NonFinite {
+        op: String,
+        has_nan: bool,
+        has_inf: bool,
+        first_index: Option<u32>,
+    },
+
+    #[error("Optional op {0} has consumers; only sink ops can be skipped")]
+    OptionalConsumed(String),
+
+    #[error("Backend error: {0}")]
+    Backend(String),
+}
+
+pub type GraphResult<T> = Result<T, GraphError>;
+
+/// Index of a node in its graph
+pub type NodeId = usize;
+
+/// Tensor operations known to the compiler
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OpKind {
+    /// Graph input (weights, activations)
+    Input,
+    /// `a + b`
+    Add,
+    /// `a * b`
+    Mul,
+    /// `a * scalar`
+    Scale(f32),
+    /// `a + bias`, bias broadcast along the last axis
+    BiasAdd,
+    Gelu,
+    Relu,
+    Silu,
+    MatMul,
+    Softmax,
+    RmsNorm,
+}
+
+impl OpKind {
+    /// Stable op name for logs and errors
+    pub fn name(self) -> &'static str {
+        match self {
+            OpKind::Input => "input",
+            OpKind::Add => "add",
+            OpKind::Mul => "mul",
+            OpKind::Scale(_) => "scale",
+            OpKind::BiasAdd => "bias_add",
+            OpKind::Gelu => "gelu",
+            OpKind::Relu => "relu",
+            OpKind::Silu => "silu",
+            OpKind::MatMul => "matmul",
+            OpKind::Softmax => "softmax",
+            OpKind::RmsNorm => "rms_norm",
+        }
+    }
+
+    /// Whether the op maps each output element from the same-index inputs
+    pub fn is_elementwise(self) -> bool {
+        matches!(
+            self,
+            OpKind::Add
+                | OpKind::Mul
+                | OpKind::Scale(_)
+                | OpKind::BiasAdd
+                | OpKind::Gelu
+                | OpKind::Relu
+                | OpKind::Silu
+        )
+    }
+}
+
+/// One op and the nodes it reads
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+    pub op: OpKind,
+    pub inputs: Vec<NodeId>,
+    /// Model-level name (e.g. "layers.3.mlp.act"), used in errors
+    pub name: Option<String>,
+    /// GPU-time budget of an optional op; `None` for ops the model needs
+    pub budget: Option<Duration>,
+}
+
+/// Ops in topological order
+#[derive(Clone, Debug, Default)]
+pub struct Graph {
+    nodes: Vec<Node>,
+}
+
+impl Graph {
+    /// Create an empty graph
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a node; inputs must already exist
+    pub fn add(&mut self, op: OpKind, inputs: &[NodeId]) -> GraphResult<NodeId> {
+        if let Some(&bad) = inputs.iter().find(|&&i| i >= self.nodes.len()) {
+            return Err(GraphError::InvalidNode(bad));
+        }
+        self.nodes.push(Node {
+            op,
+            inputs: inputs.to_vec(),
+            name: None,
+            budget: None,
+        });
+        Ok(self.nodes.len() - 1)
+    }
+
+    /// Add a graph input
+    pub fn input(&mut self) -> NodeId {
+        self.nodes.push(Node {
+            op: OpKind::Input,
+            inputs: Vec::new(),
+            name: None,
+            budget: None,
+        });
+        self.nodes.len() - 1
+    }
+
+    /// Name a node for error messages
+    pub fn set_name(&mut self, node: NodeId, name: &str) -> GraphResult<()> {
+        self.nodes.get_mut(node).ok_or(GraphError::InvalidNode(node))?.name = Some(name.to_string());
+        Ok(())
+    }
+
+    /// Mark a node optional with a per-run GPU-time budget
+    ///
+    /// Nothing may consume an optional node, since the executor may skip it.
+    pub fn set_optional(&mut self, node: NodeId, budget: Duration) -> GraphResult<()> {
+        self.nodes.get_mut(node).ok_or(GraphError::InvalidNode(node))?.budget = Some(budget);
+        Ok(())
+    }
+
+    /// Human-readable label: the node's name, or op name and index
+    pub fn label(&self, node: NodeId) -> String {
+        match self.nodes.get(node) {
+            Some(Node { name: Some(name), op, .. }) => format!("{} ({})", name, op.name()),
+            Some(Node { op, .. }) => format!("{}#{}", op.name(), node),
+            None => format!("#{}", node),
+        }
+    }
+
+    /// All nodes
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// Number of consumers of each node
+    pub fn consumer_counts(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.nodes.len()];
+        for node in &self.nodes {
+            for &input in &node.inputs {
+                counts[input] += 1;
+            }
+        }
+        counts
+    }
+}
+
+/// Maximum instructions in one fused elementwise program
+pub const MAX_FUSED_INSTRUCTIONS: usize = 16;
+
+/// Operand of a fused instruction
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operand {
+    /// Running value of the chain
+    Acc,
+    /// The n-th external input buffer of the fused kernel
+    Input(usize),
+}
+
+/// One step of the elementwise megakernel
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FusedInstr {
+    Load(usize),
+    Add(Operand),
+    Mul(Operand),
+    Scale(f32),
+    BiasAdd(usize),
+    Gelu,
+    Relu,
+    Silu,
+}
+
+impl FusedInstr {
+    /// Encode as `(opcode, argument)` words for the megakernel push constants
+    pub fn encode(self) -> [u32; 2] {
+        let operand = |o: Operand| match o {
+            Operand::Acc => u32::MAX,
+            Operand::Input(i) => i as u32,
+        };
+        match self {
+            FusedInstr::Load(i) => [0, i as u32],
+            FusedInstr::Add(o) => [1, operand(o)],
+            FusedInstr::Mul(o) => [2, operand(o)],
+            FusedInstr::Scale(s) => [3, s.to_bits()],
+            FusedInstr::BiasAdd(i) => [4, i as u32],
+            FusedInstr::Gelu => [5, 0],
+            FusedInstr::Relu => [6, 0],
+            FusedInstr::Silu => [7, 0],
+        }
+    }
+}
+
+/// A chain of elementwise nodes compiled into one dispatch
+#[derive(Clone, Debug, PartialEq)]
+pub struct FusedKernel {
+    /// Graph nodes covered by this kernel, in execution order
+    pub nodes: Vec<NodeId>,
+    /// External graph nodes read by the kernel, indexed by `Operand::Input`
+    pub inputs: Vec<NodeId>,
+    pub program: Vec<FusedInstr>,
+}
+
+impl FusedKernel {
+    /// Reference implementation of the megakernel for one element
+    ///
+    /// Inputs shorter than the output (biases) are indexed modulo their length.
+    pub fn eval(&self, inputs: &[&[f32]], index: usize) -> f32 {
+        let read = |i: usize| {
+            let data = inputs[i];
+            data[index % data.len()]
+        };
+        let mut acc = 0.0;
+        for instr in &self.program {
+            let operand = |o: &Operand, acc: f32| match *o {
+                Operand::Acc => acc,
+                Operand::Input(i) => read(i),
+            };
+            acc = match instr {
+                FusedInstr::Load(i) => read(*i),
+                FusedInstr::Add(o) => acc + operand(o, acc),
+                FusedInstr::Mul(o) => acc * operand(o, acc),
+                FusedInstr::Scale(s) => acc * s,
+                FusedInstr::BiasAdd(i) => acc + read(*i),
+                FusedInstr::Gelu => gelu(acc),
+                FusedInstr::Relu => acc.max(0.0),
+                FusedInstr::Silu => acc / (1.0 + (-acc).exp()),
+            };
+        }
+        acc
+    }
+
+    /// Encoded program words for push constants
+    pub fn encode(&self) -> Vec<u32> {
+        self.program.iter().flat_map(|i| i.encode()).collect()
+    }
+}
+
+/// tanh approximation of GELU, matching the shader
+pub fn gelu(x: f32) -> f32 {
+    0.5 * x * (1.0 + (0.797_884_6 * (x + 0.044_715 * x * x * x)).tanh())
+}
+
+/// One kernel launch in the compiled plan
+#[derive(Clone, Debug, PartialEq)]
+pub enum Dispatch {
+    /// A node executed by its own kernel
+    Single(NodeId),
+    /// Several elementwise nodes executed by the megakernel
+    Fused(FusedKernel),
+}
+
+impl Dispatch {
+    /// Node whose value the dispatch produces
+    pub fn output(&self) -> NodeId {
+        match self {
+            Dispatch::Single(node) => *node,
+            Dispatch::Fused(kernel) => *kernel.nodes.last().expect("fused kernels are never empty"),
+        }
+    }
+}
+
+/// Runs planned dispatches on some device
+pub trait DispatchBackend {
+    /// Record or execute one dispatch
+    fn run(&mut self, graph: &Graph, dispatch: &Dispatch) -> GraphResult<()>;
+
+    /// Scan the value of `node` for NaN/Inf (e.g. with `ops::check_finite`)
+    fn check_finite(&mut self, graph: &Graph, node: NodeId) -> GraphResult<NonFinite>;
+
+    /// GPU time of the last `run`, for backends that measure it (e.g. with
+    /// a `profiler::TimestampPool`)
+    ///
+    /// Budgets of optional ops are only enforced on backends that do.
+    fn last_gpu_time(&mut self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Consecutive budget overruns after which an optional op is disabled
+pub const OVERRUN_STRIKES: u32 = 3;
+
+/// Which optional ops the executor has disabled, kept across runs
+#[derive(Clone, Debug, Default)]
+pub struct OptionalOps {
+    /// Consecutive overruns per op
+    overruns: HashMap<NodeId, u32>,
+    disabled: BTreeSet<NodeId>,
+}
+
+impl OptionalOps {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_disabled(&self, node: NodeId) -> bool {
+        self.disabled.contains(&node)
+    }
+
+    /// Disabled ops, in node order
+    pub fn disabled(&self) -> Vec<NodeId> {
+        self.disabled.iter().copied().collect()
+    }
+
+    /// Re-enable every op, e.g. after moving to a faster device
+    pub fn reset(&mut self) {
+        self.overruns.clear();
+        self.disabled.clear();
+    }
+
+    /// Count a run of `node` taking `elapsed`; true if that disabled it
+    fn record(&mut self, node: NodeId, elapsed: Duration, budget: Duration) -> bool {
+        if elapsed <= budget {
+            self.overruns.remove(&node);
+            return false;
+        }
+        let strikes = self.overruns.entry(node).or_default();
+        *strikes += 1;
+        *strikes >= OVERRUN_STRIKES && self.disabled.insert(node)
+    }
+}
+
+/// Options for `execute`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ExecuteOptions {
+    /// Scan every dispatch's output and abort on the first NaN/Inf
+    pub check_numerics: bool,
+}
+
+impl ExecuteOptions {
+    /// Options for debugging numeric blowups
+    pub fn debug() -> Self {
+        Self { check_numerics: true }
+    }
+}
+
+/// Run `plan` in order on `backend`
+pub fn execute(
+    graph: &Graph,
+    plan: &[Dispatch],
+    backend: &mut impl DispatchBackend,
+    options: ExecuteOptions,
+) -> GraphResult<()> {
+    execute_with(graph, plan, backend, options, &mut OptionalOps::new())
+}
+
+/// `execute`, skipping the optional ops `optional` has disabled and
+/// disabling those that fail or keep overrunning their budget
+pub fn execute_with(
+    graph: &Graph,
+    plan: &[Dispatch],
+    backend: &mut impl DispatchBackend,
+    options: ExecuteOptions,
+    optional: &mut OptionalOps,
+) -> GraphResult<()> {
+    for dispatch in plan {
+        // Optional ops are never fused, so their dispatch is their own
+        if let Dispatch::Single(node) = *dispatch
+            && let Some(budget) = graph.nodes()[node].budget
+        {
+            run_optional(graph, dispatch, node, budget, backend, optional);
+            continue;
+        }
+        backend.run(graph, dispatch)?;
+        if !options.check_numerics {
+            continue;
+        }
+        let node = dispatch.output();
+        let result = backend.check_finite(graph, node)?;
+        if !result.is_finite() {
+            return Err(GraphError::NonFinite {
+                op: graph.label(node),
+                has_nan: result.has_nan,
+                has_inf: result.has_inf,
+                first_index: result.first_index,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Run one optional op; errors and overruns disable it instead of failing the plan
+fn run_optional(
+    graph: &Graph,
+    dispatch: &Dispatch,
+    node: NodeId,
+    budget: Duration,
+    backend: &mut impl DispatchBackend,
+    optional: &mut OptionalOps,
+) {
+    if optional.is_disabled(node) {
+        return;
+    }
+    if let Err(e) = backend.run(graph, dispatch) {
+        log::warn!("Disabling optional op {}: {}", graph.label(node), e);
+        optional.disabled.insert(node);
+        return;
+    }
+    if let Some(elapsed) = backend.last_gpu_time()
+        && optional.record(node, elapsed, budget)
+    {
+        log::warn!(
+            "Disabling optional op {}: {:?} exceeds its {:?} budget",
+            graph.label(node),
+            elapsed,
+            budget
+        );
+    }
+}
+
+/// Plan dispatches, fusing elementwise chains
+///
+/// A node joins the chain of its first input when that input is elementwise,
+/// has no other consumers, and the chain stays within `MAX_FUSED_INSTRUCTIONS`.
+/// Optional nodes always get their own dispatch so they can be skipped.
+pub fn fuse_elementwise(graph: &Graph) -> GraphResult<Vec<Dispatch>> {
+    let consumers = graph.consumer_counts();
+    let mut plan: Vec<Dispatch> = Vec::new();
+    // For each node, index in `plan` of the open chain it ends
+    let mut open_chain: Vec<Option<usize>> = vec![None; graph.nodes().len()];
+
+    for (id, node) in graph.nodes().iter().enumerate() {
+        if node.inputs.iter().any(|&i| i >= id) {
+            return Err(GraphError::NotTopological(id));
+        }
+
+        if node.op == OpKind::Input {
+            continue;
+        }
+        if node.budget.is_some() {
+            if consumers[id] > 0 {
+                return Err(GraphError::OptionalConsumed(graph.label(id)));
+            }
+            plan.push(Dispatch::Single(id));
+            continue;
+        }
+        if !node.op.is_elementwise() {
+            plan.push(Dispatch::Single(id));
+            continue;
+        }
+
+        let chain_head = node.inputs.first().copied();
+        let extendable = chain_head
+            .filter(|&head| consumers[head] == 1)
+            .and_then(|head| open_chain[head]);
+
+        let slot = match extendable {
+            Some(slot) => slot,
+            None => {
+                let mut kernel = FusedKernel {
+                    nodes: Vec::new(),
+                    inputs: Vec::new(),
+                    program: Vec::new(),
+                };
+                if let Some(head) = chain_head {
+                    kernel.inputs.push(head);
+                    kernel.program.push(FusedInstr::Load(0));
+                }
+                plan.push(Dispatch::Fused(kernel));
+                plan.len() - 1
+            }
+        };
+
+        let Dispatch::Fused(kernel) = &mut plan[slot] else {
+            unreachable!("open chains always point at fused dispatches");
+        };
+
+        let mut operand_for = |input: NodeId| {
+            kernel.inputs.iter().position(|&i| i == input).unwrap_or_else(|| {
+                kernel.inputs.push(input);
+                kernel.inputs.len() - 1
+            })
+        };
+        let second = node.inputs.get(1).copied();
+        let instr = match node.op {
+            OpKind::Add => FusedInstr::Add(second.map_or(Operand::Acc, |i| Operand::Input(operand_for(i)))),
+            OpKind::Mul => FusedInstr::Mul(second.map_or(Operand::Acc, |i| Operand::Input(operand_for(i)))),
+            OpKind::BiasAdd => FusedInstr::BiasAdd(second.map_or(0, &mut operand_for)),
+            OpKind::Scale(s) => FusedInstr::Scale(s),
+            OpKind::Gelu => FusedInstr::Gelu,
+            OpKind::Relu => FusedInstr::Relu,
+            OpKind::Silu => FusedInstr::Silu,
+            _ => unreachable!("non-elementwise ops handled above"),
+        };
+        kernel.program.push(instr);
+        kernel.nodes.push(id);
+        if kernel.program.len() > MAX_FUSED_INSTRUCTIONS {
+            return Err(GraphError::ProgramTooLong {
+                max: MAX_FUSED_INSTRUCTIONS,
+            });
+        }
+
+        if let Some(head) = chain_head {
+            open_chain[head] = None;
+        }
+        open_chain[id] = Some(slot);
+    }
+
+    // Single-node "fused" kernels are just plain dispatches
+    Ok(plan
+        .into_iter()
+        .map(|d| match d {
+            Dispatch::Fused(k) if k.nodes.len() == 1 => Dispatch::Single(k.nodes[0]),
+            other => other,
+        })
+        .collect())
+}
+
+/// Nodes whose values `plan` writes to memory
+///
+/// Fused intermediates live in registers and are not included. Backends
+/// pass the tensors bound to these nodes to `CowTable::prepare_writes`
+/// before executing the plan.
+pub fn write_set(plan: &[Dispatch]) -> Vec<NodeId> {
+    plan.iter().map(Dispatch::output).collect()
+}
+
+/// A plan for one sequence length bucket
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompiledPlan {
+    pub dispatches: Vec<Dispatch>,
+    /// Tokens the caller asked for
+    pub seq_len: u32,
+    /// Tokens the dispatches cover; rows past `seq_len` are padding the
+    /// backend fills with pad tokens and masks out
+    pub padded_len: u32,
+}
+
+impl CompiledPlan {
+    /// `write_set` of the plan's dispatches
+    pub fn write_set(&self) -> Vec<NodeId> {
+        write_set(&self.dispatches)
+    }
+}
+
+/// Plan `graph` for `seq_len` tokens, padded with the process-wide bucket policy
+///
+/// The padding is recorded in `bucketing::stats`.
+pub fn compile(graph: &Graph, seq_len: u32) -> GraphResult<CompiledPlan> {
+    let dispatches = fuse_elementwise(graph)?;
+    Ok(CompiledPlan {
+        dispatches,
+        seq_len,
+        padded_len: bucketing::pad_and_record(seq_len),
+    })
+}
+
+/// `compile` with an explicit policy, recording into `stats`
+pub fn compile_with(
+    graph: &Graph,
+    seq_len: u32,
+    policy: BucketPolicy,
+    stats: &mut PaddingStats,
+) -> GraphResult<CompiledPlan> {
+    let dispatches = fuse_elementwise(graph)?;
+    let padded_len = policy.pad(seq_len);
+    stats.record(seq_len, padded_len);
+    Ok(CompiledPlan {
+        dispatches,
+        seq_len,
+        padded_len,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bias_gelu_residual_fuses_into_one_dispatch() {
+        let mut g = Graph::new();
+        let x = g.input();
+        let w = g.input();
+        let b = g.input();
+        let mm = g.add(OpKind::MatMul, &[x, w]).unwrap();
+        let biased = g.add(OpKind::BiasAdd, &[mm, b]).unwrap();
+        let act = g.add(OpKind::Gelu, &[biased]).unwrap();
+        let out = g.add(OpKind::Add, &[act, x]).unwrap();
+
+        let plan = fuse_elementwise(&g).unwrap();
+        assert_eq!(plan.len(), 2);
+        assert_eq!(write_set(&plan), vec![mm, out]);
+        let Dispatch::Fused(kernel) = &plan[1] else {
+            panic!("expected fused dispatch");
+        };
+        assert_eq!(kernel.nodes.len(), 3);
+
+        let mm_out = [1.0, -2.0];
+        let bias = [0.5];
+        let residual = [10.0, 20.0];
+        let value = kernel.eval(&[&mm_out, &bias, &residual], 1);
+        assert!((value - (gelu(-1.5) + 20.0)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_compile_pads_to_bucket() {
+        let mut g = Graph::new();
+        let x = g.input();
+        let _act = g.add(OpKind::Gelu, &[x]).unwrap();
+        let mut stats = PaddingStats::default();
+        let plan = compile_with(&g, 100, BucketPolicy::Step(64), &mut stats).unwrap();
+        assert_eq!((plan.seq_len, plan.padded_len), (100, 128));
+        assert_eq!(plan.dispatches, fuse_elementwise(&g).unwrap());
+        assert_eq!(stats.padded_tokens, 128);
+    }
+
+    #[test]
+    fn test_shared_intermediate_breaks_chain() {
+        let mut g = Graph::new();
+        let x = g.input();
+        let a = g.add(OpKind::Relu, &[x]).unwrap();
+        let _b = g.add(OpKind::Gelu, &[a]).unwrap();
+        let _c = g.add(OpKind::Silu, &[a]).unwrap();
+        assert_eq!(fuse_elementwise(&g).unwrap().len(), 3);
+    }
+
+    /// Pretends every node after `bad` produced a NaN
+    struct PoisonAfter {
+        bad: NodeId,
+        ran: Vec<NodeId>,
+    }
+
+    impl DispatchBackend for PoisonAfter {
+        fn run(&mut self, _graph: &Graph, dispatch: &Dispatch) -> GraphResult<()> {
+            self.ran.push(dispatch.output());
+            Ok(())
+        }
+
+        fn check_finite(&mut self, _graph: &Graph, node: NodeId) -> GraphResult<NonFinite> {
+            Ok(NonFinite {
+                has_nan: node >= self.bad,
+                has_inf: false,
+                first_index: (node >= self.bad).then_some(0),
+            })
+        }
+    }
+
+    #[test]
+    fn test_debug_execution_aborts_at_first_non_finite_op() {
+        let mut g = Graph::new();
+        let x = g.input();
+        let w = g.input();
+        let mm = g.add(OpKind::MatMul, &[x, w]).unwrap();
+        let sm = g.add(OpKind::Softmax, &[mm]).unwrap();
+        let _mm2 = g.add(OpKind::MatMul, &[sm, w]).unwrap();
+        g.set_name(sm, "layers.0.attn.softmax").unwrap();
+        let plan = fuse_elementwise(&g).unwrap();
+
+        let mut backend = PoisonAfter { bad: sm, ran: Vec::new() };
+        execute(&g, &plan, &mut backend, ExecuteOptions::default()).unwrap();
+        assert_eq!(backend.ran.len(), 3);
+
+        let mut backend = PoisonAfter { bad: sm, ran: Vec::new() };
+        let err = execute(&g, &plan, &mut backend, ExecuteOptions::debug()).unwrap_err();
+        assert!(matches!(err, GraphError::NonFinite { ref op, has_nan: true, .. } if op.contains("attn.softmax")));
+        assert_eq!(backend.ran, vec![mm, sm]);
+    }
+
+    /// Reports a fixed GPU time per run and fails the nodes in `failing`
+    struct Timed {
+        gpu_time: Duration,
+        failing: Vec<NodeId>,
+        ran: Vec<NodeId>,
+    }
+
+    impl DispatchBackend for Timed {
+        fn run(&mut self, _graph: &Graph, dispatch: &Dispatch) -> GraphResult<()> {
+            if self.failing.contains(&dispatch.output()) {
+                return Err(GraphError::Backend("stats buffer lost".to_string()));
+            }
+            self.ran.push(dispatch.output());
+            Ok(())
+        }
+
+        fn check_finite(&mut self, _graph: &Graph, _node: NodeId) -> GraphResult<NonFinite> {
+            Ok(NonFinite::default())
+        }
+
+        fn last_gpu_time(&mut self) -> Option<Duration> {
+            Some(self.gpu_time)
+        }
+    }
+
+    #[test]
+    fn test_optional_ops_disabled_on_overrun_or_failure() {
+        let mut g = Graph::new();
+        let x = g.input();
+        let act = g.add(OpKind::Relu, &[x]).unwrap();
+        let stats = g.add(OpKind::Scale(1.0), &[act]).unwrap();
+        let check = g.add(OpKind::Softmax, &[act]).unwrap();
+        g.set_optional(stats, Duration::from_micros(100)).unwrap();
+        g.set_optional(check, Duration::from_millis(10)).unwrap();
+        let plan = fuse_elementwise(&g).unwrap();
+        assert_eq!(plan, vec![Dispatch::Single(act), Dispatch::Single(stats), Dispatch::Single(check)]);
+
+        let mut backend = Timed {
+            gpu_time: Duration::from_micros(500),
+            failing: vec![check],
+            ran: Vec::new(),
+        };
+        let mut optional = OptionalOps::new();
+        for _ in 0..OVERRUN_STRIKES + 1 {
+            execute_with(&g, &plan, &mut backend, ExecuteOptions::debug(), &mut optional).unwrap();
+        }
+        assert_eq!(optional.disabled(), vec![stats, check]);
+        let stats_runs = backend.ran.iter().filter(|&&n| n == stats).count();
+        assert_eq!(stats_runs, OVERRUN_STRIKES as usize);
+
+        let _consumer = g.add(OpKind::Gelu, &[stats]).unwrap();
+        assert!(matches!(fuse_elementwise(&g), Err(GraphError::OptionalConsumed(_))));
+    }
+}
@@ -0,0 +1,245 @@
+//! Soft shutdown with resource handover
+//!
+//! Restarting the service or installing an app update would otherwise mean
+//! reading every model back from flash and uploading it again. Before it
+//! exits, the old process quiesces the device and exports the memory of the
+//! tensors worth keeping as opaque FDs. The manifest lists them with the
+//! metadata needed to register them again. The FDs reach the replacement
+//! process out of band (Binder `ParcelFileDescriptor`s or `SCM_RIGHTS`) and
+//! are numbered differently there, so `import` takes them keyed by handle
+//! rather than trusting the numbers in the manifest.
+//!
+//! Opaque FDs only import into the same device and driver. The manifest
+//! therefore records both UUIDs, and `import` refuses a manifest from another
+//! driver; after an OTA update the models are reloaded from disk as before.
+
+use std::collections::BTreeMap;
+use std::os::fd::{FromRawFd, OwnedFd};
+
+use ash::vk;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::memory::{ExternalMemory, MemoryAllocator, MemoryError};
+
+/// Handover errors
+#[derive(Error, Debug)]
+pub enum HandoverError {
+    #[error("Memory error: {0}")]
+    Memory(#[from] MemoryError),
+
+    #[error("Invalid manifest: {0}")]
+    InvalidManifest(String),
+
+    #[error("Manifest was written for another device or driver")]
+    Incompatible,
+
+    #[error("No file descriptor for tensor {0}")]
+    MissingFd(String),
+
+    #[error("Vulkan error: {0:?}")]
+    VulkanError(vk::Result),
+}
+
+pub type HandoverResult<T> = Result<T, HandoverError>;
+
+/// Manifest format version
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// One handed-over tensor
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HandoverTensor {
+    pub handle_id: String,
+    /// Bytes in use
+    pub size: u64,
+    /// Size of the exported device memory
+    pub allocation_size: u64,
+    pub memory_type_index: u32,
+    /// FD in the exporting process; None when the memory could not be
+    /// exported and the replacement must reload the tensor
+    pub fd: Option<i32>,
+    /// Caller-defined metadata, e.g. owning model and client
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// Everything a replacement process needs to take over tensors
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HandoverManifest {
+    pub version: u32,
+    /// `deviceUUID` of the exporting device, hex
+    pub device_uuid: String,
+    /// `driverUUID` of the exporting driver, hex
+    pub driver_uuid: String,
+    pub tensors: Vec<HandoverTensor>,
+}
+
+fn hex(uuid: &[u8; vk::UUID_SIZE]) -> String {
+    uuid.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+impl HandoverManifest {
+    pub fn new(device_uuid: &[u8; vk::UUID_SIZE], driver_uuid: &[u8; vk::UUID_SIZE]) -> Self {
+        Self {
+            version: MANIFEST_VERSION,
+            device_uuid: hex(device_uuid),
+            driver_uuid: hex(driver_uuid),
+            tensors: Vec::new(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("manifest fields always serialize")
+    }
+
+    pub fn from_json(json: &str) -> HandoverResult<Self> {
+        let manifest: Self = serde_json::from_str(json).map_err(|e| HandoverError::InvalidManifest(e.to_string()))?;
+        if manifest.version != MANIFEST_VERSION {
+            return Err(HandoverError::InvalidManifest(format!(
+                "unknown version {}",
+                manifest.version
+            )));
+        }
+        Ok(manifest)
+    }
+
+    /// Whether FDs from this manifest can be imported on the given device and driver
+    pub fn is_compatible(&self, device_uuid: &[u8; vk::UUID_SIZE], driver_uuid: &[u8; vk::UUID_SIZE]) -> bool {
+        self.device_uuid == hex(device_uuid) && self.driver_uuid == hex(driver_uuid)
+    }
+}
+
+/// Wait for the device to go idle and export `tensors`
+///
+/// Each entry names an allocation and the metadata to record with it. On
+/// failure every FD exported so far is closed. The caller owns the FDs in the
+/// returned manifest and must keep them open until they are sent.
+///
+/// # Safety Requirements
+/// - device must be the device of allocator and external_memory_fd
+/// - no other thread may submit work to device during the call
+pub unsafe fn prepare(
+    device: &ash::Device,
+    allocator: &MemoryAllocator,
+    external_memory_fd: &ash::khr::external_memory_fd::Device,
+    device_uuid: &[u8; vk::UUID_SIZE],
+    driver_uuid: &[u8; vk::UUID_SIZE],
+    tensors: &[(String, BTreeMap<String, String>)],
+) -> HandoverResult<HandoverManifest> {
+    // SAFETY: device is valid and nothing else submits (caller's responsibility)
+    unsafe { device.device_wait_idle() }.map_err(HandoverError::VulkanError)?;
+
+    let mut manifest = HandoverManifest::new(device_uuid, driver_uuid);
+    for (handle_id, metadata) in tensors {
+        let exported = allocator
+            .get_allocation(handle_id)
+            .and_then(|allocation| Ok((allocation.size, allocator.export_fd(handle_id, external_memory_fd)?)));
+        let (size, memory) = match exported {
+            Ok(exported) => exported,
+            Err(e) => {
+                close_fds(&manifest);
+                return Err(e.into());
+            }
+        };
+        manifest.tensors.push(HandoverTensor {
+            handle_id: handle_id.clone(),
+            size,
+            allocation_size: memory.allocation_size,
+            memory_type_index: memory.memory_type_index,
+            fd: Some(memory.fd),
+            metadata: metadata.clone(),
+        });
+    }
+    log::info!("Prepared handover of {} tensors", manifest.tensors.len());
+    Ok(manifest)
+}
+
+/// Close the FDs a manifest holds in this process
+pub fn close_fds(manifest: &HandoverManifest) {
+    for fd in manifest.tensors.iter().filter_map(|t| t.fd) {
+        // SAFETY: the FD came from export_fd in this process and is owned by the manifest
+        drop(unsafe { OwnedFd::from_raw_fd(fd) });
+    }
+}
+
+/// Register the exported tensors of `manifest` in `allocator`
+///
+/// `fds` maps handle IDs to the FDs as received in this process. Tensors the
+/// exporter could not export are skipped; the caller reloads them. Either
+/// every exported tensor is imported or none is. Each FD imported belongs to
+/// the allocator from then on, even when a later failure frees it again; the
+/// FDs not reached stay with the caller.
+///
+/// # Returns
+/// Handle IDs of the imported tensors
+pub fn import(
+    allocator: &mut MemoryAllocator,
+    manifest: &HandoverManifest,
+    device_uuid: &[u8; vk::UUID_SIZE],
+    driver_uuid: &[u8; vk::UUID_SIZE],
+    fds: &BTreeMap<String, i32>,
+) -> HandoverResult<Vec<String>> {
+    if !manifest.is_compatible(device_uuid, driver_uuid) {
+        return Err(HandoverError::Incompatible);
+    }
+    let exported: Vec<&HandoverTensor> = manifest.tensors.iter().filter(|t| t.fd.is_some()).collect();
+    if let Some(missing) = exported.iter().find(|t| !fds.contains_key(&t.handle_id)) {
+        return Err(HandoverError::MissingFd(missing.handle_id.clone()));
+    }
+
+    let mut imported: Vec<String> = Vec::with_capacity(exported.len());
+    for tensor in exported {
+        let memory = ExternalMemory {
+            fd: fds[&tensor.handle_id],
+            allocation_size: tensor.allocation_size,
+            memory_type_index: tensor.memory_type_index,
+        };
+        if let Err(e) = allocator.import_fd(tensor.handle_id.clone(), tensor.size, memory) {
+            for handle_id in &imported {
+                let _ = allocator.deallocate(handle_id);
+            }
+            return Err(e.into());
+        }
+        imported.push(tensor.handle_id.clone());
+    }
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest() -> HandoverManifest {
+        let mut manifest = HandoverManifest::new(&[1; 16], &[2; 16]);
+        manifest.tensors.push(HandoverTensor {
+            handle_id: "weights".to_string(),
+            size: 1000,
+            allocation_size: 1024,
+            memory_type_index: 3,
+            fd: Some(17),
+            metadata: BTreeMap::from([("model_id".to_string(), "llama".to_string())]),
+        });
+        manifest
+    }
+
+    #[test]
+    fn test_manifest_round_trips() {
+        let json = manifest().to_json();
+        assert!(json.starts_with(r#"{"version":1,"device_uuid":"01010101"#));
+        assert_eq!(HandoverManifest::from_json(&json).unwrap(), manifest());
+
+        let future = json.replacen(r#""version":1"#, r#""version":2"#, 1);
+        assert!(matches!(
+            HandoverManifest::from_json(&future),
+            Err(HandoverError::InvalidManifest(_))
+        ));
+    }
+
+    #[test]
+    fn test_other_driver_is_incompatible() {
+        let manifest = manifest();
+        assert!(manifest.is_compatible(&[1; 16], &[2; 16]));
+        assert!(!manifest.is_compatible(&[1; 16], &[9; 16]));
+        assert!(!manifest.is_compatible(&[9; 16], &[2; 16]));
+    }
+}
@@ -0,0 +1,250 @@
+//! Pinned host buffers for repeated uploads
+//!
+//! Multimodal front-ends upload the same host regions over and over: an
+//! audio ring buffer every 20 ms, an image tile buffer per frame. Going
+//! through `DataTransfer::copy_to_device` borrows a pooled staging buffer
+//! and waits for the queue on every call. Registering the region instead
+//! pairs it with its own pre-mapped staging allocation, so each upload is
+//! one memcpy plus one buffer copy recorded wherever the caller likes.
+//!
+//! On unified-memory devices the staging allocation is picked from a
+//! `DEVICE_LOCAL | HOST_VISIBLE` memory type; kernels can bind it directly
+//! and skip the buffer copy entirely (see `is_device_visible`).
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use ash::vk;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::memory::{AllocationInfo, BufferRange};
+use crate::transfer::{DataTransfer, TransferError};
+
+/// Host buffer errors
+#[derive(Error, Debug)]
+pub enum HostBufferError {
+    #[error("Host buffer not found: {0}")]
+    NotFound(String),
+
+    #[error("Staging allocation must be mapped and host visible")]
+    NotMapped,
+
+    #[error("Staging allocation of {staging} bytes cannot hold {len} host bytes")]
+    TooSmall { staging: u64, len: usize },
+
+    #[error("Range {start}..{end} outside host buffer of {len} bytes")]
+    OutOfRange { start: usize, end: usize, len: usize },
+
+    #[error("Transfer failed: {0}")]
+    Transfer(#[from] TransferError),
+}
+
+pub type HostBufferResult<T> = Result<T, HostBufferError>;
+
+/// Pick the memory type for persistent staging
+///
+/// Prefers `DEVICE_LOCAL | HOST_VISIBLE | HOST_COHERENT` (unified memory,
+/// directly usable by kernels), then any `HOST_VISIBLE | HOST_COHERENT` type.
+pub fn staging_memory_type(properties: &vk::PhysicalDeviceMemoryProperties) -> Option<u32> {
+    let types = &properties.memory_types[..properties.memory_type_count as usize];
+    let host = vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+    let find = |flags: vk::MemoryPropertyFlags| {
+        types
+            .iter()
+            .position(|t| t.property_flags.contains(flags))
+            .map(|i| i as u32)
+    };
+    find(host | vk::MemoryPropertyFlags::DEVICE_LOCAL).or_else(|| find(host))
+}
+
+struct PinnedHostBuffer {
+    ptr: *const u8,
+    len: usize,
+    staging: AllocationInfo,
+}
+
+/// Host regions registered for repeated upload
+#[derive(Default)]
+pub struct HostBufferRegistry {
+    buffers: HashMap<String, PinnedHostBuffer>,
+}
+
+// SAFETY: registered host pointers are only read, and `register_host_buffer` requires
+// them to stay valid until unregistered; staging mappings are owned by the
+// registered allocations
+unsafe impl Send for HostBufferRegistry {}
+
+impl HostBufferRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register `len` bytes at `ptr` for repeated upload through `staging`
+    ///
+    /// # Safety Requirements
+    /// - `ptr` must be valid for reads of `len` bytes until `unregister`
+    /// - `staging` must stay allocated and mapped until `unregister`
+    ///
+    /// # Returns
+    /// Handle for `upload` and `stage`
+    pub unsafe fn register_host_buffer(&mut self, ptr: *const u8, len: usize, staging: AllocationInfo) -> HostBufferResult<String> {
+        if staging.mapped_ptr.is_none()
+            || !staging
+                .memory_properties
+                .contains(vk::MemoryPropertyFlags::HOST_VISIBLE)
+        {
+            return Err(HostBufferError::NotMapped);
+        }
+        if (len as u64) > staging.size {
+            return Err(HostBufferError::TooSmall {
+                staging: staging.size,
+                len,
+            });
+        }
+        let handle = Uuid::new_v4().to_string();
+        self.buffers.insert(handle.clone(), PinnedHostBuffer { ptr, len, staging });
+        Ok(handle)
+    }
+
+    /// Forget a host buffer
+    ///
+    /// # Returns
+    /// The staging allocation, for the caller to free
+    pub fn unregister(&mut self, handle: &str) -> HostBufferResult<AllocationInfo> {
+        self.buffers
+            .remove(handle)
+            .map(|b| b.staging)
+            .ok_or_else(|| HostBufferError::NotFound(handle.to_string()))
+    }
+
+    fn buffer(&self, handle: &str) -> HostBufferResult<&PinnedHostBuffer> {
+        self.buffers
+            .get(handle)
+            .ok_or_else(|| HostBufferError::NotFound(handle.to_string()))
+    }
+
+    /// Whether kernels can bind the staging memory directly (unified memory)
+    pub fn is_device_visible(&self, handle: &str) -> HostBufferResult<bool> {
+        Ok(self
+            .buffer(handle)?
+            .staging
+            .memory_properties
+            .contains(vk::MemoryPropertyFlags::DEVICE_LOCAL))
+    }
+
+    /// Copy the current contents of `range` (whole region if None) into staging
+    ///
+    /// # Returns
+    /// The staged bytes, at the same offsets as in the host region
+    pub fn stage(&self, handle: &str, range: Option<Range<usize>>) -> HostBufferResult<BufferRange> {
+        let buffer = self.buffer(handle)?;
+        let range = range.unwrap_or(0..buffer.len);
+        if range.start > range.end || range.end > buffer.len {
+            return Err(HostBufferError::OutOfRange {
+                start: range.start,
+                end: range.end,
+                len: buffer.len,
+            });
+        }
+        let dst = buffer.staging.mapped_ptr.ok_or(HostBufferError::NotMapped)?;
+        unsafe {
+            // SAFETY: `register_host_buffer` guarantees the host region is readable and the
+            // staging mapping holds at least `len` bytes; `range` is within both
+            std::ptr::copy_nonoverlapping(buffer.ptr.add(range.start), dst.add(range.start), range.len());
+        }
+        buffer
+            .staging
+            .range()
+            .slice(range.start as u64, range.len() as u64)
+            .map_err(|_| HostBufferError::TooSmall {
+                staging: buffer.staging.size,
+                len: buffer.len,
+            })
+    }
+
+    /// Upload the whole region to the start of `destination`
+    ///
+    /// # Safety Requirements
+    /// - `destination` must be valid, allocated and not in use by the GPU
+    pub unsafe fn upload(
+        &self,
+        handle: &str,
+        transfer: &DataTransfer,
+        destination: &AllocationInfo,
+    ) -> HostBufferResult<()> {
+        self.stage(handle, None)?;
+        let buffer = self.buffer(handle)?;
+        // SAFETY: staging and destination are distinct valid allocations
+        unsafe { transfer.copy_device_to_device(&buffer.staging, destination, buffer.len as u64)? };
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffers.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::memory::Liveness;
+
+    fn staging(backing: &mut [u8], flags: vk::MemoryPropertyFlags) -> AllocationInfo {
+        AllocationInfo {
+            handle_id: "staging".to_string(),
+            size: backing.len() as u64,
+            capacity: backing.len() as u64,
+            device_memory: vk::DeviceMemory::null(),
+            buffer: vk::Buffer::null(),
+            offset: 0,
+            mapped_ptr: Some(backing.as_mut_ptr()),
+            memory_properties: flags,
+            generation: 1,
+            liveness: Arc::new(Liveness::new(1)),
+        }
+    }
+
+    #[test]
+    fn test_stage_copies_current_host_contents() {
+        let mut host = vec![1u8, 2, 3, 4];
+        let mut backing = vec![0u8; 8];
+        let mut registry = HostBufferRegistry::new();
+        let handle = unsafe {
+            registry
+                .register_host_buffer(host.as_ptr(), host.len(), staging(&mut backing, vk::MemoryPropertyFlags::HOST_VISIBLE))
+                .unwrap()
+        };
+        assert!(!registry.is_device_visible(&handle).unwrap());
+
+        host[2] = 9;
+        let range = registry.stage(&handle, Some(2..4)).unwrap();
+        assert_eq!((range.offset, range.size), (2, 2));
+        assert!(registry.stage(&handle, Some(2..5)).is_err());
+        registry.stage(&handle, None).unwrap();
+        assert_eq!(&backing[..4], &[1, 2, 9, 4]);
+
+        registry.unregister(&handle).unwrap();
+        assert!(registry.is_empty());
+    }
+
+    #[test]
+    fn test_prefers_unified_memory() {
+        let mut props = vk::PhysicalDeviceMemoryProperties {
+            memory_type_count: 3,
+            ..Default::default()
+        };
+        let host = vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+        props.memory_types[0].property_flags = vk::MemoryPropertyFlags::DEVICE_LOCAL;
+        props.memory_types[1].property_flags = host;
+        assert_eq!(staging_memory_type(&props), Some(1));
+        props.memory_types[2].property_flags = host | vk::MemoryPropertyFlags::DEVICE_LOCAL;
+        assert_eq!(staging_memory_type(&props), Some(2));
+    }
+}
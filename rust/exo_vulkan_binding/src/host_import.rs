@@ -0,0 +1,333 @@
+//! Zero-copy weight uploads from mapped files
+//!
+//! Loading a multi-GB checkpoint through `DataTransfer::copy_to_device`
+//! reads every byte twice on the CPU: once when the page cache fills the
+//! mapping, and once more when it is copied into a staging buffer. With
+//! `VK_EXT_external_memory_host` the mapped file pages themselves can be
+//! imported as device-visible memory, and the GPU copies each tensor
+//! straight out of them.
+//!
+//! Imports must start and end on `minImportedHostPointerAlignment`, so the
+//! mapping is imported from its aligned-down start to its aligned-up end.
+//! That stays within the mapping as long as the alignment is no larger than
+//! a page, which holds on the drivers we ship on (4 KiB). Large files are
+//! imported in windows of at most `MAX_WINDOW` bytes to stay under
+//! `maxMemoryAllocationSize`.
+//!
+//! Some drivers refuse to import read-only mappings; `upload_from_mapping`
+//! then falls back to the staging path.
+
+use std::ffi::c_void;
+use std::marker::PhantomData;
+use std::ops::Range;
+
+use ash::vk;
+use thiserror::Error;
+
+use crate::memory::AllocationInfo;
+use crate::transfer::{DataTransfer, TransferError};
+use crate::{VulkanContext, VulkanError};
+
+/// Host import errors
+#[derive(Error, Debug)]
+pub enum HostImportError {
+    #[error("VK_EXT_external_memory_host is not supported by this device")]
+    Unsupported,
+
+    #[error("Range {start}..{end} outside mapping of {len} bytes")]
+    OutOfRange { start: usize, end: usize, len: usize },
+
+    #[error("Device error: {0}")]
+    Device(#[from] VulkanError),
+
+    #[error("Transfer failed: {0}")]
+    Transfer(#[from] TransferError),
+
+    #[error("Vulkan error: {0:?}")]
+    VulkanError(vk::Result),
+}
+
+pub type HostImportResult<T> = Result<T, HostImportError>;
+
+/// Extension that allows importing host pointers
+pub const EXTENSION_NAME: &str = "VK_EXT_external_memory_host";
+
+/// Largest single import
+pub const MAX_WINDOW: u64 = 256 * 1024 * 1024;
+
+const HANDLE_TYPE: vk::ExternalMemoryHandleTypeFlags = vk::ExternalMemoryHandleTypeFlags::HOST_ALLOCATION_EXT;
+
+/// Imports host memory on one device
+pub struct HostImporter {
+    device: ash::Device,
+    external_memory_host: ash::ext::external_memory_host::Device,
+    alignment: u64,
+}
+
+impl HostImporter {
+    /// Importer for device `index` of `context`
+    ///
+    /// `device` must have been created from that physical device with
+    /// `VK_EXT_external_memory_host` enabled.
+    pub fn new(context: &VulkanContext, index: usize, device: &ash::Device) -> HostImportResult<Self> {
+        if !context.get_device_capabilities(index)?.has_extension(EXTENSION_NAME) {
+            return Err(HostImportError::Unsupported);
+        }
+        let physical_device = context.get_physical_device(index)?;
+        let instance = context.instance();
+        let mut host_properties = vk::PhysicalDeviceExternalMemoryHostPropertiesEXT::default();
+        {
+            let mut properties = vk::PhysicalDeviceProperties2::default().push_next(&mut host_properties);
+            // SAFETY: physical_device was enumerated from the context's instance
+            unsafe { instance.get_physical_device_properties2(physical_device, &mut properties) };
+        }
+        Ok(Self {
+            device: device.clone(),
+            external_memory_host: ash::ext::external_memory_host::Device::new(&instance, device),
+            alignment: host_properties.min_imported_host_pointer_alignment.max(1),
+        })
+    }
+
+    /// `minImportedHostPointerAlignment` of the device
+    pub fn alignment(&self) -> u64 {
+        self.alignment
+    }
+
+    /// Import `mapping` as memory the GPU can copy from
+    ///
+    /// # Safety Requirements
+    /// - mapping must be part of a page-granular mapping (e.g. `mmap`) so
+    ///   the alignment padding around it is mapped too
+    /// - the mapped pages must not be unmapped or remapped while the import
+    ///   is alive, which the borrow only partly enforces for raw mappings
+    pub unsafe fn import<'a>(&self, mapping: &'a [u8]) -> HostImportResult<ImportedHostMemory<'a>> {
+        let address = mapping.as_ptr() as u64;
+        let base = address - address % self.alignment;
+        let lead = address - base;
+        let mut imported = ImportedHostMemory {
+            device: self.device.clone(),
+            windows: Vec::new(),
+            lead,
+            len: mapping.len() as u64,
+            mapping: PhantomData,
+        };
+        for (start, len) in windows(lead, mapping.len() as u64, self.alignment, MAX_WINDOW) {
+            // SAFETY: the window lies within the mapping plus its alignment
+            // padding (caller's responsibility) and is aligned as required
+            let window = unsafe { self.import_window((base + start) as *mut c_void, len) }?;
+            imported.windows.push(ImportWindow { start, len, ..window });
+        }
+        Ok(imported)
+    }
+
+    /// Import `len` aligned bytes at `ptr` into a transfer source buffer
+    unsafe fn import_window(&self, ptr: *mut c_void, len: u64) -> HostImportResult<ImportWindow> {
+        let mut pointer_properties = vk::MemoryHostPointerPropertiesEXT::default();
+        // SAFETY: ptr is an aligned host pointer (caller's responsibility)
+        unsafe {
+            (self.external_memory_host.fp().get_memory_host_pointer_properties_ext)(
+                self.device.handle(),
+                HANDLE_TYPE,
+                ptr,
+                &mut pointer_properties,
+            )
+        }
+        .result()
+        .map_err(HostImportError::VulkanError)?;
+
+        let mut external_info = vk::ExternalMemoryBufferCreateInfo::default().handle_types(HANDLE_TYPE);
+        let buffer_info = vk::BufferCreateInfo::default()
+            .size(len)
+            .usage(vk::BufferUsageFlags::TRANSFER_SRC)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .push_next(&mut external_info);
+        // SAFETY: device is valid; the create info is fully initialised
+        let buffer = unsafe { self.device.create_buffer(&buffer_info, None) }.map_err(HostImportError::VulkanError)?;
+
+        // SAFETY: buffer was just created on self.device
+        let requirements = unsafe { self.device.get_buffer_memory_requirements(buffer) };
+        let type_bits = requirements.memory_type_bits & pointer_properties.memory_type_bits;
+        if type_bits == 0 {
+            // SAFETY: buffer is unused
+            unsafe { self.device.destroy_buffer(buffer, None) };
+            return Err(HostImportError::VulkanError(vk::Result::ERROR_INVALID_EXTERNAL_HANDLE));
+        }
+        let mut import_info = vk::ImportMemoryHostPointerInfoEXT::default()
+            .handle_type(HANDLE_TYPE)
+            .host_pointer(ptr);
+        let alloc_info = vk::MemoryAllocateInfo::default()
+            .allocation_size(len)
+            .memory_type_index(type_bits.trailing_zeros())
+            .push_next(&mut import_info);
+        // SAFETY: the memory type accepts both the buffer and the pointer
+        let memory = match unsafe { self.device.allocate_memory(&alloc_info, None) } {
+            Ok(memory) => memory,
+            Err(e) => {
+                // SAFETY: buffer is unused
+                unsafe { self.device.destroy_buffer(buffer, None) };
+                return Err(HostImportError::VulkanError(e));
+            }
+        };
+        // SAFETY: memory covers the buffer's whole size
+        if let Err(e) = unsafe { self.device.bind_buffer_memory(buffer, memory, 0) } {
+            // SAFETY: neither object is in use
+            unsafe {
+                self.device.destroy_buffer(buffer, None);
+                self.device.free_memory(memory, None);
+            }
+            return Err(HostImportError::VulkanError(e));
+        }
+        Ok(ImportWindow {
+            buffer,
+            memory,
+            start: 0,
+            len,
+        })
+    }
+}
+
+/// One imported window; `start` is relative to the aligned-down mapping start
+struct ImportWindow {
+    buffer: vk::Buffer,
+    memory: vk::DeviceMemory,
+    start: u64,
+    len: u64,
+}
+
+/// A mapping imported as device-visible memory; borrows the mapping
+pub struct ImportedHostMemory<'a> {
+    device: ash::Device,
+    windows: Vec<ImportWindow>,
+    /// Bytes between the aligned-down start and the mapping start
+    lead: u64,
+    len: u64,
+    mapping: PhantomData<&'a [u8]>,
+}
+
+impl ImportedHostMemory<'_> {
+    /// Bytes of the imported mapping
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Copies moving `size` bytes at `offset` in the mapping to `dst_offset`
+    pub fn copy_regions(&self, offset: u64, size: u64, dst_offset: u64) -> Vec<(vk::Buffer, vk::BufferCopy)> {
+        let spans: Vec<(u64, u64)> = self.windows.iter().map(|w| (w.start, w.len)).collect();
+        regions(&spans, self.lead + offset, size, dst_offset)
+            .into_iter()
+            .map(|(i, region)| (self.windows[i].buffer, region))
+            .collect()
+    }
+}
+
+impl Drop for ImportedHostMemory<'_> {
+    fn drop(&mut self) {
+        for window in &self.windows {
+            // SAFETY: transfers from the windows wait for completion, so no
+            // pending work reads them
+            unsafe {
+                self.device.destroy_buffer(window.buffer, None);
+                self.device.free_memory(window.memory, None);
+            }
+        }
+    }
+}
+
+/// Windows `(start, len)` covering `lead + len` bytes from an aligned base
+fn windows(lead: u64, len: u64, alignment: u64, max_window: u64) -> Vec<(u64, u64)> {
+    let total = (lead + len).next_multiple_of(alignment);
+    let step = (max_window / alignment).max(1) * alignment;
+    let mut windows = Vec::new();
+    let mut start = 0;
+    while start < total {
+        windows.push((start, step.min(total - start)));
+        start += step;
+    }
+    windows
+}
+
+/// Per-window copies of `size` bytes at `offset` from the aligned base
+fn regions(windows: &[(u64, u64)], offset: u64, size: u64, dst_offset: u64) -> Vec<(usize, vk::BufferCopy)> {
+    windows
+        .iter()
+        .enumerate()
+        .filter_map(|(i, &(start, len))| {
+            let lo = offset.max(start);
+            let hi = (offset + size).min(start + len);
+            (lo < hi).then(|| {
+                (
+                    i,
+                    vk::BufferCopy::default()
+                        .src_offset(lo - start)
+                        .dst_offset(dst_offset + lo - offset)
+                        .size(hi - lo),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Upload `range` of a mapped weight file into `dst`
+///
+/// Copies straight from `imported` when the mapping was imported, and
+/// through a staging buffer otherwise.
+///
+/// # Safety Requirements
+/// - imported, if given, must be the import of `mapping` on the device of `transfer`
+/// - dst must be valid and not in use by other work
+///
+/// # Returns
+/// Whether the zero-copy path was used
+pub unsafe fn upload_from_mapping(
+    transfer: &DataTransfer,
+    imported: Option<&ImportedHostMemory>,
+    mapping: &[u8],
+    range: Range<usize>,
+    dst: &AllocationInfo,
+) -> HostImportResult<bool> {
+    if range.start > range.end || range.end > mapping.len() {
+        return Err(HostImportError::OutOfRange {
+            start: range.start,
+            end: range.end,
+            len: mapping.len(),
+        });
+    }
+    let size = range.len() as u64;
+    // SAFETY: forwarded from the caller
+    unsafe {
+        match imported {
+            Some(imported) => transfer.copy_from_imported(imported, range.start as u64, dst, size)?,
+            None => transfer.copy_to_device(&mapping[range], dst)?,
+        }
+    }
+    Ok(imported.is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_windows_cover_aligned_span() {
+        // 100 bytes starting 10 bytes past an aligned base, 64-byte alignment
+        assert_eq!(windows(10, 100, 64, 256), vec![(0, 128)]);
+        assert_eq!(windows(10, 600, 64, 256), vec![(0, 256), (256, 256), (512, 128)]);
+        // Windows never shrink below one alignment unit
+        assert_eq!(windows(0, 100, 64, 16), vec![(0, 64), (64, 64)]);
+    }
+
+    #[test]
+    fn test_regions_split_across_windows() {
+        let spans = [(0, 256), (256, 256)];
+        let regions = regions(&spans, 200, 100, 1000);
+        let summary: Vec<_> = regions
+            .iter()
+            .map(|(i, r)| (*i, r.src_offset, r.dst_offset, r.size))
+            .collect();
+        assert_eq!(summary, vec![(0, 200, 1000, 56), (1, 0, 1056, 44)]);
+    }
+}
@@ -0,0 +1,584 @@
+//! SIMD host conversions for weight and activation formats
+//!
+//! Loading a quantized model unpacks or converts every weight once on the
+//! CPU, and the CPU fallback does the same per call; one element at a time
+//! that dominates load time. These routines work a vector at a time (8
+//! elements on AVX2 + F16C, 4 to 16 on NEON). x86_64 picks the AVX2 path
+//! at runtime; NEON is part of the aarch64 baseline, so Android always
+//! takes it. Every path gives the same bits as the scalar reference in
+//! `scalar`, except for NaN payloads.
+//!
+//! Block formats are GGML's, 32 elements per block:
+//! - Q8_0: f16 scale `d`, then 32 int8 `q`; `x = q * d`
+//! - Q4_0: f16 scale `d`, then 16 bytes holding element `j` in the low
+//!   nibble of byte `j` and element `j + 16` in its high nibble; `x = (q - 8) * d`
+
+use thiserror::Error;
+
+/// Conversion errors
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum SimdError {
+    #[error("{what}: {actual} elements, expected {expected}")]
+    LengthMismatch {
+        what: &'static str,
+        expected: usize,
+        actual: usize,
+    },
+
+    #[error("{0} elements is not a whole number of {QK}-element blocks")]
+    PartialBlock(usize),
+}
+
+pub type SimdResult<T> = Result<T, SimdError>;
+
+/// Elements per Q8_0 / Q4_0 block
+pub const QK: usize = 32;
+
+/// Bytes per Q8_0 block: f16 scale and 32 int8
+pub const Q8_0_BLOCK_BYTES: usize = 2 + QK;
+
+/// Bytes per Q4_0 block: f16 scale and 16 bytes of nibbles
+pub const Q4_0_BLOCK_BYTES: usize = 2 + QK / 2;
+
+/// Instruction set the conversions run on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SimdPath {
+    Scalar,
+    /// AVX2 with F16C
+    Avx2,
+    Neon,
+}
+
+impl SimdPath {
+    pub fn name(self) -> &'static str {
+        match self {
+            SimdPath::Scalar => "scalar",
+            SimdPath::Avx2 => "avx2",
+            SimdPath::Neon => "neon",
+        }
+    }
+}
+
+/// Fastest path this CPU supports
+pub fn detected() -> SimdPath {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("f16c") {
+            SimdPath::Avx2
+        } else {
+            SimdPath::Scalar
+        }
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        SimdPath::Neon
+    }
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    {
+        SimdPath::Scalar
+    }
+}
+
+fn check_len(what: &'static str, expected: usize, actual: usize) -> SimdResult<()> {
+    if expected != actual {
+        return Err(SimdError::LengthMismatch { what, expected, actual });
+    }
+    Ok(())
+}
+
+/// Check `elements` fill whole blocks and `bytes` holds exactly those blocks
+fn check_blocks(elements: usize, bytes: usize, block_bytes: usize) -> SimdResult<()> {
+    if !elements.is_multiple_of(QK) {
+        return Err(SimdError::PartialBlock(elements));
+    }
+    check_len("blocks", elements / QK * block_bytes, bytes)
+}
+
+/// Round an f32 to the nearest f16 bit pattern, ties to even
+pub fn f32_to_f16(x: f32) -> u16 {
+    let bits = x.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xff) as i32;
+    let man = bits & 0x7f_ffff;
+    if exp == 0xff {
+        // Inf stays inf; NaN stays a quiet NaN
+        return sign | 0x7c00 | if man != 0 { 0x200 } else { 0 };
+    }
+    let round = |value: u32, rem: u32, halfway: u32| {
+        if rem > halfway || (rem == halfway && value & 1 == 1) {
+            value + 1
+        } else {
+            value
+        }
+    };
+    let e = exp - 127 + 15;
+    if e >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if e <= 0 {
+        // Subnormal or zero
+        if e < -10 {
+            return sign;
+        }
+        let m = man | 0x80_0000;
+        let shift = (14 - e) as u32;
+        return sign | round(m >> shift, m & ((1 << shift) - 1), 1 << (shift - 1)) as u16;
+    }
+    // A mantissa carry rolls into the exponent, up to inf
+    sign | round(((e as u32) << 10) | (man >> 13), man & 0x1fff, 0x1000) as u16
+}
+
+/// Widen an f16 bit pattern to f32
+pub fn f16_to_f32(h: u16) -> f32 {
+    let sign = u32::from(h & 0x8000) << 16;
+    let exp = u32::from((h >> 10) & 0x1f);
+    let man = u32::from(h & 0x3ff);
+    match exp {
+        0 => {
+            let value = man as f32 * f32::from_bits(0x3380_0000); // 2^-24
+            if sign != 0 { -value } else { value }
+        }
+        0x1f => f32::from_bits(sign | 0x7f80_0000 | (man << 13)),
+        _ => f32::from_bits(sign | ((exp + 112) << 23) | (man << 13)),
+    }
+}
+
+/// Scale of a block, and the factor that maps its values onto the int grid
+fn block_scale(d: f32) -> (u16, f32) {
+    (f32_to_f16(d), if d != 0.0 { 1.0 / d } else { 0.0 })
+}
+
+fn block_d(block: &[u8]) -> f32 {
+    f16_to_f32(u16::from_le_bytes([block[0], block[1]]))
+}
+
+/// Reference implementations; the SIMD paths match them bit for bit
+pub mod scalar {
+    use super::*;
+
+    pub fn f32_to_f16_slice(src: &[f32], dst: &mut [u16]) {
+        for (h, &x) in dst.iter_mut().zip(src) {
+            *h = f32_to_f16(x);
+        }
+    }
+
+    pub fn f16_to_f32_slice(src: &[u16], dst: &mut [f32]) {
+        for (x, &h) in dst.iter_mut().zip(src) {
+            *x = f16_to_f32(h);
+        }
+    }
+
+    /// Quantize one 32-element block
+    pub fn quantize_q8_0_block(src: &[f32], block: &mut [u8]) {
+        let amax = src.iter().fold(0.0f32, |m, x| m.max(x.abs()));
+        let (d, id) = block_scale(amax / 127.0);
+        block[..2].copy_from_slice(&d.to_le_bytes());
+        for (q, &x) in block[2..].iter_mut().zip(src) {
+            *q = ((x * id).round_ties_even() as i32).clamp(-128, 127) as i8 as u8;
+        }
+    }
+
+    pub fn dequantize_q8_0_block(block: &[u8], dst: &mut [f32]) {
+        let d = block_d(block);
+        for (x, &q) in dst.iter_mut().zip(&block[2..]) {
+            *x = f32::from(q as i8) * d;
+        }
+    }
+
+    pub fn dequantize_q4_0_block(block: &[u8], dst: &mut [f32]) {
+        let d = block_d(block);
+        let (low, high) = dst.split_at_mut(QK / 2);
+        for (j, &byte) in block[2..].iter().enumerate() {
+            low[j] = f32::from((byte & 0x0f) as i8 - 8) * d;
+            high[j] = f32::from((byte >> 4) as i8 - 8) * d;
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+    use std::arch::x86_64::*;
+
+    use super::{QK, block_d, block_scale};
+
+    /// # Safety Requirements
+    /// - The CPU must support AVX2 and F16C
+    #[target_feature(enable = "avx2,f16c")]
+    pub unsafe fn f32_to_f16_slice(src: &[f32], dst: &mut [u16]) -> usize {
+        let n = src.len().min(dst.len()) / 8 * 8;
+        for i in (0..n).step_by(8) {
+            // SAFETY: i + 8 <= n, within both slices
+            unsafe {
+                let v = _mm256_loadu_ps(src.as_ptr().add(i));
+                let h = _mm256_cvtps_ph::<_MM_FROUND_TO_NEAREST_INT>(v);
+                _mm_storeu_si128(dst.as_mut_ptr().add(i) as *mut __m128i, h);
+            }
+        }
+        n
+    }
+
+    /// # Safety Requirements
+    /// - The CPU must support AVX2 and F16C
+    #[target_feature(enable = "avx2,f16c")]
+    pub unsafe fn f16_to_f32_slice(src: &[u16], dst: &mut [f32]) -> usize {
+        let n = src.len().min(dst.len()) / 8 * 8;
+        for i in (0..n).step_by(8) {
+            // SAFETY: i + 8 <= n, within both slices
+            unsafe {
+                let h = _mm_loadu_si128(src.as_ptr().add(i) as *const __m128i);
+                _mm256_storeu_ps(dst.as_mut_ptr().add(i), _mm256_cvtph_ps(h));
+            }
+        }
+        n
+    }
+
+    /// Scale 8 int32 lanes by `d` and store them at `dst`
+    ///
+    /// # Safety Requirements
+    /// - `dst` must have 8 writable f32s
+    #[target_feature(enable = "avx2,f16c")]
+    unsafe fn store_scaled(dst: *mut f32, q: __m256i, d: __m256) {
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe { _mm256_storeu_ps(dst, _mm256_mul_ps(_mm256_cvtepi32_ps(q), d)) };
+    }
+
+    /// # Safety Requirements
+    /// - The CPU must support AVX2 and F16C
+    /// - `src` and `block` must hold one block
+    #[target_feature(enable = "avx2,f16c")]
+    pub unsafe fn quantize_q8_0_block(src: &[f32], block: &mut [u8]) {
+        // SAFETY: src holds 32 floats
+        let v = unsafe { [0, 8, 16, 24].map(|i| _mm256_loadu_ps(src.as_ptr().add(i))) };
+        let sign = _mm256_set1_ps(-0.0);
+        let abs = v.map(|x| _mm256_andnot_ps(sign, x));
+        let m = _mm256_max_ps(_mm256_max_ps(abs[0], abs[1]), _mm256_max_ps(abs[2], abs[3]));
+        let m4 = _mm_max_ps(_mm256_castps256_ps128(m), _mm256_extractf128_ps::<1>(m));
+        let m2 = _mm_max_ps(m4, _mm_movehl_ps(m4, m4));
+        let amax = _mm_cvtss_f32(_mm_max_ss(m2, _mm_movehdup_ps(m2)));
+
+        let (d, id) = block_scale(amax / 127.0);
+        block[..2].copy_from_slice(&d.to_le_bytes());
+        let id = _mm256_set1_ps(id);
+        // Conversion rounds to nearest even under the default MXCSR
+        let q = v.map(|x| _mm256_cvtps_epi32(_mm256_mul_ps(x, id)));
+        // Saturating packs interleave 128-bit lanes; the permute restores order
+        let packed = _mm256_packs_epi16(_mm256_packs_epi32(q[0], q[1]), _mm256_packs_epi32(q[2], q[3]));
+        let ordered = _mm256_permutevar8x32_epi32(packed, _mm256_setr_epi32(0, 4, 1, 5, 2, 6, 3, 7));
+        // SAFETY: block holds 2 + 32 bytes
+        unsafe { _mm256_storeu_si256(block.as_mut_ptr().add(2) as *mut __m256i, ordered) };
+    }
+
+    /// # Safety Requirements
+    /// - The CPU must support AVX2 and F16C
+    /// - `block` and `dst` must hold one block
+    #[target_feature(enable = "avx2,f16c")]
+    pub unsafe fn dequantize_q8_0_block(block: &[u8], dst: &mut [f32]) {
+        let d = _mm256_set1_ps(block_d(block));
+        for i in (0..QK).step_by(8) {
+            // SAFETY: block holds 2 + 32 bytes and dst 32 floats
+            unsafe {
+                let q = _mm_loadl_epi64(block.as_ptr().add(2 + i) as *const __m128i);
+                store_scaled(dst.as_mut_ptr().add(i), _mm256_cvtepi8_epi32(q), d);
+            }
+        }
+    }
+
+    /// # Safety Requirements
+    /// - The CPU must support AVX2 and F16C
+    /// - `block` and `dst` must hold one block
+    #[target_feature(enable = "avx2,f16c")]
+    pub unsafe fn dequantize_q4_0_block(block: &[u8], dst: &mut [f32]) {
+        let d = _mm256_set1_ps(block_d(block));
+        // SAFETY: block holds 2 + 16 bytes
+        let qs = unsafe { _mm_loadu_si128(block.as_ptr().add(2) as *const __m128i) };
+        let mask = _mm_set1_epi8(0x0f);
+        let eight = _mm_set1_epi8(8);
+        let low = _mm_sub_epi8(_mm_and_si128(qs, mask), eight);
+        let high = _mm_sub_epi8(_mm_and_si128(_mm_srli_epi16::<4>(qs), mask), eight);
+        for (offset, nibbles) in [(0, low), (QK / 2, high)] {
+            // SAFETY: offset + 16 <= 32, within dst
+            unsafe {
+                store_scaled(dst.as_mut_ptr().add(offset), _mm256_cvtepi8_epi32(nibbles), d);
+                store_scaled(
+                    dst.as_mut_ptr().add(offset + 8),
+                    _mm256_cvtepi8_epi32(_mm_srli_si128::<8>(nibbles)),
+                    d,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod neon {
+    use std::arch::aarch64::*;
+    use std::arch::asm;
+
+    use super::{QK, block_d, block_scale};
+
+    // Stable Rust has no f16 NEON intrinsics, so the conversions use
+    // FCVTN/FCVTL directly; both round to nearest even under the default FPCR
+
+    pub fn f32_to_f16_slice(src: &[f32], dst: &mut [u16]) -> usize {
+        let n = src.len().min(dst.len()) / 4 * 4;
+        for i in (0..n).step_by(4) {
+            // SAFETY: i + 4 <= n, within both slices
+            unsafe {
+                let v = vld1q_f32(src.as_ptr().add(i));
+                let h: uint16x4_t;
+                asm!("fcvtn {h:v}.4h, {v:v}.4s", v = in(vreg) v, h = out(vreg) h, options(pure, nomem, nostack));
+                vst1_u16(dst.as_mut_ptr().add(i), h);
+            }
+        }
+        n
+    }
+
+    pub fn f16_to_f32_slice(src: &[u16], dst: &mut [f32]) -> usize {
+        let n = src.len().min(dst.len()) / 4 * 4;
+        for i in (0..n).step_by(4) {
+            // SAFETY: i + 4 <= n, within both slices
+            unsafe {
+                let h = vld1_u16(src.as_ptr().add(i));
+                let v: float32x4_t;
+                asm!("fcvtl {v:v}.4s, {h:v}.4h", h = in(vreg) h, v = out(vreg) v, options(pure, nomem, nostack));
+                vst1q_f32(dst.as_mut_ptr().add(i), v);
+            }
+        }
+        n
+    }
+
+    /// Scale 16 int8 lanes by `d` and store them at `dst`
+    ///
+    /// # Safety Requirements
+    /// - `dst` must have 16 writable f32s
+    unsafe fn store_scaled(dst: *mut f32, q: int8x16_t, d: f32) {
+        let halves = [vmovl_s8(vget_low_s8(q)), vmovl_high_s8(q)];
+        for (i, h) in halves.into_iter().enumerate() {
+            let low = vcvtq_f32_s32(vmovl_s16(vget_low_s16(h)));
+            let high = vcvtq_f32_s32(vmovl_high_s16(h));
+            // SAFETY: forwarded from the caller's guarantees
+            unsafe {
+                vst1q_f32(dst.add(8 * i), vmulq_n_f32(low, d));
+                vst1q_f32(dst.add(8 * i + 4), vmulq_n_f32(high, d));
+            }
+        }
+    }
+
+    /// # Safety Requirements
+    /// - `src` and `block` must hold one block
+    pub unsafe fn quantize_q8_0_block(src: &[f32], block: &mut [u8]) {
+        // SAFETY: src holds 32 floats
+        let v: [float32x4_t; 8] = unsafe { std::array::from_fn(|i| vld1q_f32(src.as_ptr().add(4 * i))) };
+        let amax = vmaxvq_f32(v.iter().fold(vdupq_n_f32(0.0), |m, &x| vmaxq_f32(m, vabsq_f32(x))));
+
+        let (d, id) = block_scale(amax / 127.0);
+        block[..2].copy_from_slice(&d.to_le_bytes());
+        let q = v.map(|x| vqmovn_s32(vcvtnq_s32_f32(vmulq_n_f32(x, id))));
+        for (i, pair) in q.chunks_exact(4).enumerate() {
+            let bytes = vcombine_s8(
+                vqmovn_s16(vcombine_s16(pair[0], pair[1])),
+                vqmovn_s16(vcombine_s16(pair[2], pair[3])),
+            );
+            // SAFETY: block holds 2 + 32 bytes
+            unsafe { vst1q_s8(block.as_mut_ptr().add(2 + 16 * i) as *mut i8, bytes) };
+        }
+    }
+
+    /// # Safety Requirements
+    /// - `block` and `dst` must hold one block
+    pub unsafe fn dequantize_q8_0_block(block: &[u8], dst: &mut [f32]) {
+        let d = block_d(block);
+        for i in (0..QK).step_by(16) {
+            // SAFETY: block holds 2 + 32 bytes and dst 32 floats
+            unsafe { store_scaled(dst.as_mut_ptr().add(i), vld1q_s8(block.as_ptr().add(2 + i) as *const i8), d) };
+        }
+    }
+
+    /// # Safety Requirements
+    /// - `block` and `dst` must hold one block
+    pub unsafe fn dequantize_q4_0_block(block: &[u8], dst: &mut [f32]) {
+        let d = block_d(block);
+        // SAFETY: block holds 2 + 16 bytes
+        let qs = unsafe { vld1q_u8(block.as_ptr().add(2)) };
+        let eight = vdupq_n_s8(8);
+        let low = vsubq_s8(vreinterpretq_s8_u8(vandq_u8(qs, vdupq_n_u8(0x0f))), eight);
+        let high = vsubq_s8(vreinterpretq_s8_u8(vshrq_n_u8::<4>(qs)), eight);
+        // SAFETY: dst holds 32 floats
+        unsafe {
+            store_scaled(dst.as_mut_ptr(), low, d);
+            store_scaled(dst.as_mut_ptr().add(QK / 2), high, d);
+        }
+    }
+}
+
+/// Convert f32s to f16 bit patterns, rounding to nearest even
+pub fn f32_to_f16_slice(src: &[f32], dst: &mut [u16]) -> SimdResult<()> {
+    check_len("f16 output", src.len(), dst.len())?;
+    let done = match detected() {
+        // SAFETY: the path was detected on this CPU
+        #[cfg(target_arch = "x86_64")]
+        SimdPath::Avx2 => unsafe { avx2::f32_to_f16_slice(src, dst) },
+        #[cfg(target_arch = "aarch64")]
+        SimdPath::Neon => neon::f32_to_f16_slice(src, dst),
+        _ => 0,
+    };
+    scalar::f32_to_f16_slice(&src[done..], &mut dst[done..]);
+    Ok(())
+}
+
+/// Widen f16 bit patterns to f32s
+pub fn f16_to_f32_slice(src: &[u16], dst: &mut [f32]) -> SimdResult<()> {
+    check_len("f32 output", src.len(), dst.len())?;
+    let done = match detected() {
+        // SAFETY: the path was detected on this CPU
+        #[cfg(target_arch = "x86_64")]
+        SimdPath::Avx2 => unsafe { avx2::f16_to_f32_slice(src, dst) },
+        #[cfg(target_arch = "aarch64")]
+        SimdPath::Neon => neon::f16_to_f32_slice(src, dst),
+        _ => 0,
+    };
+    scalar::f16_to_f32_slice(&src[done..], &mut dst[done..]);
+    Ok(())
+}
+
+/// Pack f32s into Q8_0 blocks; `src` must be a whole number of blocks
+pub fn quantize_q8_0(src: &[f32], blocks: &mut [u8]) -> SimdResult<()> {
+    check_blocks(src.len(), blocks.len(), Q8_0_BLOCK_BYTES)?;
+    let path = detected();
+    for (values, block) in src.chunks_exact(QK).zip(blocks.chunks_exact_mut(Q8_0_BLOCK_BYTES)) {
+        match path {
+            // SAFETY: the path was detected on this CPU; chunks are one block
+            #[cfg(target_arch = "x86_64")]
+            SimdPath::Avx2 => unsafe { avx2::quantize_q8_0_block(values, block) },
+            // SAFETY: chunks are one block
+            #[cfg(target_arch = "aarch64")]
+            SimdPath::Neon => unsafe { neon::quantize_q8_0_block(values, block) },
+            _ => scalar::quantize_q8_0_block(values, block),
+        }
+    }
+    Ok(())
+}
+
+/// Unpack Q8_0 blocks into f32s
+pub fn dequantize_q8_0(blocks: &[u8], dst: &mut [f32]) -> SimdResult<()> {
+    check_blocks(dst.len(), blocks.len(), Q8_0_BLOCK_BYTES)?;
+    let path = detected();
+    for (block, values) in blocks.chunks_exact(Q8_0_BLOCK_BYTES).zip(dst.chunks_exact_mut(QK)) {
+        match path {
+            // SAFETY: the path was detected on this CPU; chunks are one block
+            #[cfg(target_arch = "x86_64")]
+            SimdPath::Avx2 => unsafe { avx2::dequantize_q8_0_block(block, values) },
+            // SAFETY: chunks are one block
+            #[cfg(target_arch = "aarch64")]
+            SimdPath::Neon => unsafe { neon::dequantize_q8_0_block(block, values) },
+            _ => scalar::dequantize_q8_0_block(block, values),
+        }
+    }
+    Ok(())
+}
+
+/// Pack f32s into Q4_0 blocks; `src` must be a whole number of blocks
+///
+/// Follows GGML's reference: the element of largest magnitude maps to -8.
+/// Packing happens once per checkpoint, so this stays scalar.
+pub fn quantize_q4_0(src: &[f32], blocks: &mut [u8]) -> SimdResult<()> {
+    check_blocks(src.len(), blocks.len(), Q4_0_BLOCK_BYTES)?;
+    for (values, block) in src.chunks_exact(QK).zip(blocks.chunks_exact_mut(Q4_0_BLOCK_BYTES)) {
+        let max = values.iter().fold(0.0f32, |m, &x| if x.abs() > m.abs() { x } else { m });
+        let (d, id) = block_scale(max / -8.0);
+        block[..2].copy_from_slice(&d.to_le_bytes());
+        let nibble = |x: f32| ((x * id + 8.5) as u8).min(15);
+        for j in 0..QK / 2 {
+            block[2 + j] = nibble(values[j]) | (nibble(values[j + QK / 2]) << 4);
+        }
+    }
+    Ok(())
+}
+
+/// Unpack Q4_0 blocks into f32s
+pub fn dequantize_q4_0(blocks: &[u8], dst: &mut [f32]) -> SimdResult<()> {
+    check_blocks(dst.len(), blocks.len(), Q4_0_BLOCK_BYTES)?;
+    let path = detected();
+    for (block, values) in blocks.chunks_exact(Q4_0_BLOCK_BYTES).zip(dst.chunks_exact_mut(QK)) {
+        match path {
+            // SAFETY: the path was detected on this CPU; chunks are one block
+            #[cfg(target_arch = "x86_64")]
+            SimdPath::Avx2 => unsafe { avx2::dequantize_q4_0_block(block, values) },
+            // SAFETY: chunks are one block
+            #[cfg(target_arch = "aarch64")]
+            SimdPath::Neon => unsafe { neon::dequantize_q4_0_block(block, values) },
+            _ => scalar::dequantize_q4_0_block(block, values),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Values covering signs, ties, subnormals and out-of-range magnitudes
+    fn samples(n: usize) -> Vec<f32> {
+        (0..n)
+            .map(|i| match i % 7 {
+                0 => i as f32 * 0.37 - 20.0,
+                1 => 1.0 + 1.0 / 2048.0,
+                2 => -6.0e-8 * i as f32,
+                3 => 7.0e4,
+                4 => -(i as f32).sqrt(),
+                5 => 0.0,
+                _ => 1.0e-3 / (i as f32 + 1.0),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_f16_paths_match_scalar() {
+        // 37 exercises the vector body and a scalar tail
+        let src = samples(37);
+        let (mut fast, mut reference) = (vec![0u16; 37], vec![0u16; 37]);
+        f32_to_f16_slice(&src, &mut fast).unwrap();
+        scalar::f32_to_f16_slice(&src, &mut reference);
+        assert_eq!(fast, reference);
+
+        let (mut widened, mut widened_ref) = (vec![0.0f32; 37], vec![0.0f32; 37]);
+        f16_to_f32_slice(&fast, &mut widened).unwrap();
+        scalar::f16_to_f32_slice(&fast, &mut widened_ref);
+        assert_eq!(widened, widened_ref);
+        assert!(matches!(f16_to_f32_slice(&fast, &mut widened[1..]), Err(SimdError::LengthMismatch { .. })));
+    }
+
+    #[test]
+    fn test_q8_0_round_trip() {
+        let src = samples(2 * QK);
+        let mut blocks = vec![0u8; 2 * Q8_0_BLOCK_BYTES];
+        quantize_q8_0(&src, &mut blocks).unwrap();
+        let mut reference = vec![0u8; Q8_0_BLOCK_BYTES];
+        scalar::quantize_q8_0_block(&src[QK..], &mut reference);
+        assert_eq!(&blocks[Q8_0_BLOCK_BYTES..], &reference[..]);
+
+        let mut values = vec![0.0f32; 2 * QK];
+        dequantize_q8_0(&blocks, &mut values).unwrap();
+        for (block, (x, y)) in src.chunks(QK).zip(values.chunks(QK)).enumerate() {
+            let d = block_d(&blocks[block * Q8_0_BLOCK_BYTES..]);
+            assert!(x.iter().zip(y).all(|(a, b)| (a - b).abs() <= d * 0.51 + a.abs() * 1e-3));
+        }
+        assert_eq!(quantize_q8_0(&src[1..], &mut blocks), Err(SimdError::PartialBlock(2 * QK - 1)));
+    }
+
+    #[test]
+    fn test_q4_0_round_trip() {
+        let src: Vec<f32> = (0..QK).map(|i| i as f32 - 16.0).collect();
+        let mut block = vec![0u8; Q4_0_BLOCK_BYTES];
+        quantize_q4_0(&src, &mut block).unwrap();
+        // The largest magnitude, -16, sets d = 2 and maps to nibble 0
+        assert_eq!(block_d(&block), 2.0);
+        assert_eq!(block[2] & 0x0f, 0);
+
+        let (mut values, mut reference) = (vec![0.0f32; QK], vec![0.0f32; QK]);
+        dequantize_q4_0(&block, &mut values).unwrap();
+        scalar::dequantize_q4_0_block(&block, &mut reference);
+        assert_eq!(values, reference);
+        assert!(src.iter().zip(&values).all(|(a, b)| (a - b).abs() <= 1.0));
+    }
+}
@@ -0,0 +1,217 @@
+//! Stable device indices across re-enumeration
+//!
+//! Vulkan numbers physical devices in enumeration order, so when an eGPU is
+//! unplugged or a driver restarts, every later device shifts down and a
+//! caller holding index 2 silently gets what used to be index 3.
+//! `DeviceSlots` gives each device (keyed by `DeviceInfo::device_id`, which
+//! is derived from its `deviceUUID`) a fixed index the first time it is
+//! seen. `reconcile` with a fresh enumeration maps those indices onto the
+//! new context's ones: vanished devices keep their index but go offline,
+//! returning devices reclaim it, and new devices take the next free one.
+//! Every change is emitted as `GpuEvent::DeviceStatusChanged`.
+//!
+//! Vulkan loaders report hot-plugged devices only to instances created
+//! after the change, so a rescan resets the global context first; see
+//! `reenumerate`.
+
+use thiserror::Error;
+
+use crate::events::{self, DeviceStatus, GpuEvent};
+use crate::{DeviceInfo, VulkanResult, initialize_vulkan, reset_vulkan};
+
+/// Device slot errors
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum HotplugError {
+    #[error("Device index {0} was never enumerated")]
+    NotFound(usize),
+
+    #[error("Device {index} ({device_id}) is offline")]
+    Offline { index: usize, device_id: String },
+}
+
+pub type HotplugResult<T> = Result<T, HotplugError>;
+
+/// One stable device index
+#[derive(Clone, Debug)]
+pub struct DeviceSlot {
+    /// Info from the latest enumeration that saw the device
+    pub info: DeviceInfo,
+    /// Index in the current context, `None` while offline
+    pub context_index: Option<usize>,
+}
+
+impl DeviceSlot {
+    pub fn is_online(&self) -> bool {
+        self.context_index.is_some()
+    }
+}
+
+/// A device whose availability changed in a `reconcile`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeviceChange {
+    pub index: usize,
+    pub device_id: String,
+    pub status: DeviceStatus,
+}
+
+/// Every device seen so far, by stable index
+#[derive(Clone, Debug, Default)]
+pub struct DeviceSlots {
+    slots: Vec<DeviceSlot>,
+}
+
+impl DeviceSlots {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Map a fresh enumeration onto the stable indices
+    ///
+    /// `devices` must be in the current context's order. Emits and returns
+    /// the changes, ordered by index.
+    pub fn reconcile(&mut self, devices: Vec<DeviceInfo>) -> Vec<DeviceChange> {
+        let was_online: Vec<bool> = self.slots.iter().map(DeviceSlot::is_online).collect();
+        for slot in &mut self.slots {
+            slot.context_index = None;
+        }
+
+        let mut changes = Vec::new();
+        for (context_index, info) in devices.into_iter().enumerate() {
+            let existing = self.slots.iter().position(|s| s.info.device_id == info.device_id);
+            let (index, status) = match existing {
+                Some(index) => (index, (!was_online[index]).then_some(DeviceStatus::Online)),
+                None => {
+                    self.slots.push(DeviceSlot {
+                        info: info.clone(),
+                        context_index: None,
+                    });
+                    (self.slots.len() - 1, Some(DeviceStatus::Added))
+                }
+            };
+            let slot = &mut self.slots[index];
+            slot.info = info;
+            slot.context_index = Some(context_index);
+            if let Some(status) = status {
+                changes.push(DeviceChange {
+                    index,
+                    device_id: slot.info.device_id.clone(),
+                    status,
+                });
+            }
+        }
+
+        for (index, slot) in self.slots.iter().enumerate() {
+            if was_online.get(index).copied().unwrap_or(false) && !slot.is_online() {
+                changes.push(DeviceChange {
+                    index,
+                    device_id: slot.info.device_id.clone(),
+                    status: DeviceStatus::Offline,
+                });
+            }
+        }
+        changes.sort_by_key(|change| change.index);
+
+        for change in &changes {
+            log::info!("Device {} ({}) {}", change.index, change.device_id, change.status.name());
+            events::emit(GpuEvent::DeviceStatusChanged {
+                device_index: change.index,
+                device_id: change.device_id.clone(),
+                status: change.status,
+            });
+        }
+        changes
+    }
+
+    pub fn get(&self, index: usize) -> HotplugResult<&DeviceSlot> {
+        self.slots.get(index).ok_or(HotplugError::NotFound(index))
+    }
+
+    /// Index of stable device `index` in the current context
+    pub fn context_index(&self, index: usize) -> HotplugResult<usize> {
+        let slot = self.get(index)?;
+        slot.context_index.ok_or_else(|| HotplugError::Offline {
+            index,
+            device_id: slot.info.device_id.clone(),
+        })
+    }
+
+    /// Slots by stable index, online or not
+    pub fn iter(&self) -> impl Iterator<Item = &DeviceSlot> {
+        self.slots.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+}
+
+/// Enumerate devices on a fresh instance so hot-plugged ones are seen
+///
+/// Replaces the global context. Contexts already handed out stay valid
+/// (they keep their own instance alive) but see the old device list.
+pub fn reenumerate() -> VulkanResult<Vec<DeviceInfo>> {
+    reset_vulkan();
+    initialize_vulkan()?.enumerate_devices()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute_units::ComputeUnitSource;
+
+    fn device(id: &str) -> DeviceInfo {
+        DeviceInfo {
+            device_id: id.to_string(),
+            name: id.to_string(),
+            vendor: "test".to_string(),
+            vendor_id: 0,
+            driver_version: "0".to_string(),
+            api_version: "1.1.0".to_string(),
+            max_allocation_size: 1 << 30,
+            compute_units: 1,
+            compute_units_source: ComputeUnitSource::Estimated,
+            subgroup_size: 32,
+            max_clock_mhz: None,
+            total_memory_bytes: 0,
+            bandwidth_gbps: 0.0,
+        }
+    }
+
+    fn statuses(changes: &[DeviceChange]) -> Vec<(usize, DeviceStatus)> {
+        changes.iter().map(|c| (c.index, c.status)).collect()
+    }
+
+    #[test]
+    fn test_indices_survive_unplug_and_replug() {
+        let mut slots = DeviceSlots::new();
+        let added = slots.reconcile(vec![device("igpu"), device("egpu"), device("dgpu")]);
+        assert_eq!(
+            statuses(&added),
+            vec![(0, DeviceStatus::Added), (1, DeviceStatus::Added), (2, DeviceStatus::Added)]
+        );
+
+        // Unplugging the eGPU shifts the dGPU to context index 1, not stable index 1
+        let removed = slots.reconcile(vec![device("igpu"), device("dgpu")]);
+        assert_eq!(statuses(&removed), vec![(1, DeviceStatus::Offline)]);
+        assert_eq!(slots.context_index(2), Ok(1));
+        assert!(matches!(slots.context_index(1), Err(HotplugError::Offline { index: 1, .. })));
+
+        let back = slots.reconcile(vec![device("egpu"), device("igpu"), device("dgpu"), device("new")]);
+        assert_eq!(statuses(&back), vec![(1, DeviceStatus::Online), (3, DeviceStatus::Added)]);
+        assert_eq!(slots.context_index(1), Ok(0));
+        assert_eq!(slots.get(3).unwrap().info.device_id, "new");
+        assert_eq!(slots.context_index(4), Err(HotplugError::NotFound(4)));
+    }
+
+    #[test]
+    fn test_unchanged_enumeration_emits_nothing() {
+        let mut slots = DeviceSlots::new();
+        slots.reconcile(vec![device("gpu")]);
+        assert!(slots.reconcile(vec![device("gpu")]).is_empty());
+        assert_eq!(slots.len(), 1);
+    }
+}
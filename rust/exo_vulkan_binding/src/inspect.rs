@@ -0,0 +1,408 @@
+//! Debug readback of tensor slices
+//!
+//! `debug_read` copies back only the bytes covering a requested slice of a
+//! device tensor and formats the result shape-aware, so NaNs and blowups can
+//! be spotted on-device without downloading gigabyte tensors. Tensors can be
+//! registered by name for inspection from Kotlin (`inspectTensor`).
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use serde::{Serialize, Serializer};
+use thiserror::Error;
+
+use crate::memory::{AllocationInfo, MemoryError};
+use crate::tensor::{ELEMENT_SIZE, Layout};
+use crate::transfer::{DataTransfer, TransferError};
+
+/// Largest span `debug_read` will copy back
+pub const MAX_DEBUG_READ_BYTES: u64 = 1 << 20;
+
+/// Inspection-related errors
+#[derive(Error, Debug)]
+pub enum InspectError {
+    #[error("Invalid slice: {0}")]
+    InvalidSlice(String),
+
+    #[error("Slice spans {0} bytes, more than the {MAX_DEBUG_READ_BYTES} byte limit")]
+    TooLarge(u64),
+
+    #[error("Tensor not registered: {0}")]
+    NotFound(String),
+
+    #[error(transparent)]
+    Transfer(#[from] TransferError),
+
+    #[error(transparent)]
+    Memory(#[from] MemoryError),
+}
+
+pub type InspectResult<T> = Result<T, InspectError>;
+
+/// Selection along one dimension
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DimSlice {
+    /// Single index; the dimension is dropped from the result
+    Index(usize),
+    /// `start..end` stepping by `step`; `end` is clamped to the dim
+    Range { start: usize, end: usize, step: usize },
+}
+
+impl DimSlice {
+    /// Whole dimension
+    pub const FULL: DimSlice = DimSlice::Range {
+        start: 0,
+        end: usize::MAX,
+        step: 1,
+    };
+
+    /// Selected indices of a dimension of length `len`
+    fn indices(self, len: usize) -> InspectResult<Vec<usize>> {
+        match self {
+            DimSlice::Index(i) if i < len => Ok(vec![i]),
+            DimSlice::Index(i) => Err(InspectError::InvalidSlice(format!("index {} out of range {}", i, len))),
+            DimSlice::Range { start, end, step } => {
+                if step == 0 {
+                    return Err(InspectError::InvalidSlice("step must be > 0".to_string()));
+                }
+                Ok((start..end.min(len)).step_by(step).collect())
+            }
+        }
+    }
+}
+
+/// Parse a slice like `[0, [2, 10], null, [0, 64, 8]]`
+///
+/// Each entry is an index, `[start, end]`, `[start, end, step]` or `null` for
+/// the whole dimension. Missing trailing dims select everything.
+pub fn parse_slice(spec: &str) -> InspectResult<Vec<DimSlice>> {
+    let spec = spec.trim();
+    if spec.is_empty() {
+        return Ok(Vec::new());
+    }
+    let invalid = || InspectError::InvalidSlice(spec.to_string());
+    let inner = spec.strip_prefix('[').and_then(|s| s.strip_suffix(']')).ok_or_else(invalid)?;
+
+    let mut dims = Vec::new();
+    let mut rest = inner.trim();
+    while !rest.is_empty() {
+        let (item, tail) = if rest.starts_with('[') {
+            let close = rest.find(']').ok_or_else(invalid)?;
+            (&rest[..=close], &rest[close + 1..])
+        } else {
+            rest.split_at(rest.find(',').unwrap_or(rest.len()))
+        };
+        let item = item.trim();
+
+        dims.push(if item == "null" {
+            DimSlice::FULL
+        } else if let Some(range) = item.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            let parts = range
+                .split(',')
+                .map(|p| p.trim().parse::<usize>().map_err(|_| invalid()))
+                .collect::<InspectResult<Vec<_>>>()?;
+            match parts[..] {
+                [start, end] => DimSlice::Range { start, end, step: 1 },
+                [start, end, step] => DimSlice::Range { start, end, step },
+                _ => return Err(invalid()),
+            }
+        } else {
+            DimSlice::Index(item.parse().map_err(|_| invalid())?)
+        });
+
+        rest = tail.trim_start();
+        rest = rest.strip_prefix(',').unwrap_or(rest).trim_start();
+    }
+    Ok(dims)
+}
+
+/// A slice read back from the device
+#[derive(Clone, Debug, PartialEq)]
+pub struct TensorSample {
+    /// Shape of the slice (indexed dims dropped)
+    pub shape: Vec<usize>,
+    pub values: Vec<f32>,
+}
+
+impl TensorSample {
+    pub fn nan_count(&self) -> usize {
+        self.values.iter().filter(|v| v.is_nan()).count()
+    }
+
+    pub fn inf_count(&self) -> usize {
+        self.values.iter().filter(|v| v.is_infinite()).count()
+    }
+
+    /// Min and max over finite values
+    pub fn finite_range(&self) -> Option<(f32, f32)> {
+        self.values
+            .iter()
+            .filter(|v| v.is_finite())
+            .fold(None, |acc, &v| match acc {
+                None => Some((v, v)),
+                Some((lo, hi)) => Some((lo.min(v), hi.max(v))),
+            })
+    }
+
+    /// Values as nested JSON arrays; NaN and infinities become strings
+    pub fn values_json(&self) -> String {
+        serde_json::to_string(&Nested {
+            shape: &self.shape,
+            values: &self.values,
+        })
+        .expect("sample values always serialize")
+    }
+
+    /// Summary and values as a JSON object
+    pub fn to_json(&self, name: &str) -> String {
+        let range = self.finite_range();
+        serde_json::to_string(&SampleReport {
+            name,
+            shape: &self.shape,
+            nan_count: self.nan_count(),
+            inf_count: self.inf_count(),
+            min: range.map(|(lo, _)| lo),
+            max: range.map(|(_, hi)| hi),
+            values: Nested {
+                shape: &self.shape,
+                values: &self.values,
+            },
+        })
+        .expect("sample reports always serialize")
+    }
+}
+
+/// `TensorSample::to_json`
+#[derive(Serialize)]
+struct SampleReport<'a> {
+    name: &'a str,
+    shape: &'a [usize],
+    nan_count: usize,
+    inf_count: usize,
+    min: Option<f32>,
+    max: Option<f32>,
+    values: Nested<'a>,
+}
+
+/// Values of `shape` as nested arrays, NaN and infinities as strings
+struct Nested<'a> {
+    shape: &'a [usize],
+    values: &'a [f32],
+}
+
+impl Serialize for Nested<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.shape.split_first() {
+            None => match self.values.first() {
+                None => serializer.serialize_none(),
+                Some(v) if v.is_nan() => serializer.serialize_str("NaN"),
+                Some(v) if v.is_infinite() => serializer.serialize_str(if *v > 0.0 { "Inf" } else { "-Inf" }),
+                Some(&v) => serializer.serialize_f32(v),
+            },
+            Some((&dim, rest)) => {
+                let stride = rest.iter().product::<usize>();
+                serializer.collect_seq((0..dim).map(|i| Nested {
+                    shape: rest,
+                    values: &self.values[i * stride..(i + 1) * stride],
+                }))
+            }
+        }
+    }
+}
+
+/// Element offsets selected by `slice`, and the resulting shape
+fn selected_offsets(layout: &Layout, slice: &[DimSlice]) -> InspectResult<(Vec<usize>, Vec<usize>)> {
+    if slice.len() > layout.rank() {
+        return Err(InspectError::InvalidSlice(format!(
+            "{} dims given for rank {}",
+            slice.len(),
+            layout.rank()
+        )));
+    }
+
+    let mut shape = Vec::new();
+    let mut selections = Vec::with_capacity(layout.rank());
+    for (dim, &len) in layout.shape().iter().enumerate() {
+        let selection = slice.get(dim).copied().unwrap_or(DimSlice::FULL);
+        let indices = selection.indices(len)?;
+        if matches!(selection, DimSlice::Range { .. }) {
+            shape.push(indices.len());
+        }
+        selections.push(indices);
+    }
+
+    // Refuse before materializing offsets for a huge selection
+    let bytes = selections.iter().map(|s| s.len() as u64).product::<u64>() * ELEMENT_SIZE;
+    if bytes > MAX_DEBUG_READ_BYTES {
+        return Err(InspectError::TooLarge(bytes));
+    }
+
+    let mut offsets = vec![0usize];
+    for (indices, &stride) in selections.iter().zip(layout.strides()) {
+        offsets = offsets
+            .iter()
+            .flat_map(|&base| indices.iter().map(move |&i| base + i * stride))
+            .collect();
+    }
+    Ok((offsets, shape))
+}
+
+/// Gather the slice from host bytes holding elements `first..` of the tensor
+pub fn gather_slice(
+    bytes: &[u8],
+    first_element: usize,
+    layout: &Layout,
+    slice: &[DimSlice],
+) -> InspectResult<TensorSample> {
+    let (offsets, shape) = selected_offsets(layout, slice)?;
+    let size = ELEMENT_SIZE as usize;
+    let values = offsets
+        .iter()
+        .map(|&offset| {
+            let at = (offset - first_element) * size;
+            bytes
+                .get(at..at + size)
+                .map(|b| f32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+                .ok_or_else(|| InspectError::InvalidSlice(format!("element {} outside readback", offset)))
+        })
+        .collect::<InspectResult<_>>()?;
+    Ok(TensorSample { shape, values })
+}
+
+/// Copy back just the bytes covering `slice` of an f32 tensor
+///
+/// # Safety Requirements
+/// - Same as `DataTransfer::copy_from_device`
+pub unsafe fn debug_read(
+    transfer: &DataTransfer,
+    allocation: &AllocationInfo,
+    layout: &Layout,
+    slice: &[DimSlice],
+) -> InspectResult<TensorSample> {
+    let (offsets, _) = selected_offsets(layout, slice)?;
+    let (Some(&first), Some(&last)) = (offsets.iter().min(), offsets.iter().max()) else {
+        return gather_slice(&[], 0, layout, slice);
+    };
+
+    let span = (last - first + 1) as u64 * ELEMENT_SIZE;
+    if span > MAX_DEBUG_READ_BYTES {
+        return Err(InspectError::TooLarge(span));
+    }
+    let view = allocation.view(first as u64 * ELEMENT_SIZE, span)?;
+    // SAFETY: forwarded from the caller's guarantees; view lies within allocation
+    let bytes = unsafe { transfer.copy_from_device(&view, span)? };
+    gather_slice(&bytes, first, layout, slice)
+}
+
+/// A tensor registered for inspection by name
+#[derive(Clone)]
+pub struct NamedTensor {
+    allocation: AllocationInfo,
+    layout: Layout,
+    transfer: Arc<DataTransfer>,
+}
+
+impl NamedTensor {
+    pub fn new(allocation: &AllocationInfo, layout: Layout, transfer: Arc<DataTransfer>) -> Self {
+        Self {
+            // Inspection always reads through the transfer queue
+            allocation: AllocationInfo {
+                mapped_ptr: None,
+                ..allocation.clone()
+            },
+            layout,
+            transfer,
+        }
+    }
+}
+
+// SAFETY: the only non-Send field is AllocationInfo::mapped_ptr, which `new` clears
+unsafe impl Send for NamedTensor {}
+
+lazy_static::lazy_static! {
+    static ref NAMED_TENSORS: Mutex<HashMap<String, NamedTensor>> = Mutex::new(HashMap::new());
+}
+
+/// Make a tensor inspectable as `name`, replacing any previous registration
+pub fn register_tensor(name: &str, tensor: NamedTensor) {
+    NAMED_TENSORS.lock().insert(name.to_string(), tensor);
+}
+
+/// Stop inspecting `name`; must be called before its allocation is freed
+pub fn unregister_tensor(name: &str) -> bool {
+    NAMED_TENSORS.lock().remove(name).is_some()
+}
+
+/// Names of registered tensors, sorted
+pub fn registered_tensors() -> Vec<String> {
+    let mut names: Vec<String> = NAMED_TENSORS.lock().keys().cloned().collect();
+    names.sort();
+    names
+}
+
+/// Read back a slice of a registered tensor as JSON
+pub fn inspect_tensor(name: &str, slice_spec: &str) -> InspectResult<String> {
+    let slice = parse_slice(slice_spec)?;
+    let tensor = NAMED_TENSORS
+        .lock()
+        .get(name)
+        .cloned()
+        .ok_or_else(|| InspectError::NotFound(name.to_string()))?;
+    // SAFETY: registered tensors are unregistered before their allocation is freed
+    let sample = unsafe { debug_read(&tensor.transfer, &tensor.allocation, &tensor.layout, &slice)? };
+    Ok(sample.to_json(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_slice() {
+        let slice = parse_slice("[1, [0, 4], null, [2, 10, 4]]").unwrap();
+        assert_eq!(
+            slice,
+            vec![
+                DimSlice::Index(1),
+                DimSlice::Range { start: 0, end: 4, step: 1 },
+                DimSlice::FULL,
+                DimSlice::Range { start: 2, end: 10, step: 4 },
+            ]
+        );
+        assert!(parse_slice("[[1, 2, 3, 4]]").is_err());
+        assert!(parse_slice("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_gather_slice_reads_only_needed_span() {
+        // 3x4 tensor, read row 1 columns 1..3 from a readback starting at element 5
+        let layout = Layout::contiguous(&[3, 4]);
+        let slice = parse_slice("[1, [1, 3]]").unwrap();
+        let bytes: Vec<u8> = [5.0f32, f32::NAN].iter().flat_map(|v| v.to_ne_bytes()).collect();
+
+        let sample = gather_slice(&bytes, 5, &layout, &slice).unwrap();
+        assert_eq!(sample.shape, vec![2]);
+        assert_eq!(sample.nan_count(), 1);
+        assert_eq!(sample.values_json(), r#"[5.0,"NaN"]"#);
+    }
+
+    #[test]
+    fn test_report_escapes_name() {
+        let sample = TensorSample {
+            shape: vec![2, 2],
+            values: vec![1.5, f32::NEG_INFINITY, f32::NAN, -2.0],
+        };
+        let name = "attn \"q\"\\0\n";
+        let json: serde_json::Value = serde_json::from_str(&sample.to_json(name)).unwrap();
+        assert_eq!(json["name"], name);
+        assert_eq!(json["shape"], serde_json::json!([2, 2]));
+        assert_eq!((json["nan_count"].clone(), json["inf_count"].clone()), (1.into(), 1.into()));
+        assert_eq!((json["min"].as_f64(), json["max"].as_f64()), (Some(-2.0), Some(1.5)));
+        assert_eq!(json["values"], serde_json::json!([[1.5, "-Inf"], ["NaN", -2.0]]));
+
+        let empty = TensorSample { shape: vec![0], values: Vec::new() };
+        let json: serde_json::Value = serde_json::from_str(&empty.to_json("e")).unwrap();
+        assert!(json["min"].is_null() && json["max"].is_null());
+    }
+}
@@ -0,0 +1,226 @@
+//! Jobs callers wait on
+//!
+//! Work started through the JNI layer (e.g. `dispatchKernel`) returns at
+//! once with a job id; the app later waits on it with a timeout. A job is
+//! pending until whoever runs it calls `finish`. Waiting on a finished job
+//! returns its outcome and forgets it, so each job is collected once.
+//! Jobs nobody collects are dropped `FINISHED_TTL` after they finish, so
+//! abandoned jobs do not pile up.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use parking_lot::{Condvar, Mutex};
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Job errors
+#[derive(Error, Debug)]
+pub enum JobError {
+    #[error("Job not found: {0}")]
+    NotFound(String),
+}
+
+pub type JobResult<T> = Result<T, JobError>;
+
+/// How long a finished job is kept for a `wait` to collect it
+pub const FINISHED_TTL: Duration = Duration::from_secs(600);
+
+/// Where a job is
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum JobState {
+    Pending,
+    Completed,
+    Failed(String),
+}
+
+impl JobState {
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, JobState::Pending)
+    }
+}
+
+/// A job's state, and when it finished
+#[derive(Debug)]
+struct Entry {
+    state: JobState,
+    finished_at: Option<Instant>,
+}
+
+/// Jobs by id, with a condition variable signalled when one finishes
+#[derive(Default)]
+pub struct JobTable {
+    jobs: Mutex<HashMap<String, Entry>>,
+    finished: Condvar,
+}
+
+impl JobTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a pending job and return its id
+    ///
+    /// Also drops finished jobs that have waited past `FINISHED_TTL`.
+    pub fn start(&self) -> String {
+        let id = Uuid::new_v4().to_string();
+        let mut jobs = self.jobs.lock();
+        sweep(&mut jobs, Instant::now());
+        jobs.insert(
+            id.clone(),
+            Entry {
+                state: JobState::Pending,
+                finished_at: None,
+            },
+        );
+        id
+    }
+
+    /// Record the outcome of a job and wake its waiters
+    pub fn finish(&self, id: &str, outcome: Result<(), String>) -> JobResult<()> {
+        let mut jobs = self.jobs.lock();
+        let entry = jobs.get_mut(id).ok_or_else(|| JobError::NotFound(id.to_string()))?;
+        entry.state = match outcome {
+            Ok(()) => JobState::Completed,
+            Err(reason) => JobState::Failed(reason),
+        };
+        entry.finished_at = Some(Instant::now());
+        self.finished.notify_all();
+        Ok(())
+    }
+
+    /// Current state of a job, without collecting it
+    pub fn state(&self, id: &str) -> JobResult<JobState> {
+        self.jobs
+            .lock()
+            .get(id)
+            .map(|entry| entry.state.clone())
+            .ok_or_else(|| JobError::NotFound(id.to_string()))
+    }
+
+    /// Jobs in the table, finished or not
+    pub fn len(&self) -> usize {
+        self.jobs.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Wait up to `timeout` for every pending job to finish, e.g. before a
+    /// handover
+    ///
+    /// Returns whether none is left pending. Finished jobs stay in the
+    /// table for their waiters.
+    pub fn wait_idle(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now().checked_add(timeout);
+        let mut jobs = self.jobs.lock();
+        while jobs.values().any(|entry| !entry.state.is_finished()) {
+            match deadline {
+                Some(deadline) => {
+                    if self.finished.wait_until(&mut jobs, deadline).timed_out() {
+                        return jobs.values().all(|entry| entry.state.is_finished());
+                    }
+                }
+                None => self.finished.wait(&mut jobs),
+            }
+        }
+        true
+    }
+
+    /// Wait up to `timeout` for a job to finish
+    ///
+    /// Returns `Pending` on timeout; a finished job is removed.
+    pub fn wait(&self, id: &str, timeout: Duration) -> JobResult<JobState> {
+        let deadline = Instant::now().checked_add(timeout);
+        let mut jobs = self.jobs.lock();
+        loop {
+            let entry = jobs.get(id).ok_or_else(|| JobError::NotFound(id.to_string()))?;
+            if entry.state.is_finished() {
+                return Ok(jobs.remove(id).map_or(JobState::Completed, |entry| entry.state));
+            }
+            match deadline {
+                Some(deadline) => {
+                    if self.finished.wait_until(&mut jobs, deadline).timed_out() {
+                        return Ok(jobs.get(id).map_or(JobState::Pending, |entry| entry.state.clone()));
+                    }
+                }
+                None => self.finished.wait(&mut jobs),
+            }
+        }
+    }
+}
+
+/// Drop jobs that finished `FINISHED_TTL` or longer before `now`
+fn sweep(jobs: &mut HashMap<String, Entry>, now: Instant) {
+    jobs.retain(|_, entry| {
+        entry
+            .finished_at
+            .is_none_or(|finished| now.saturating_duration_since(finished) < FINISHED_TTL)
+    });
+}
+
+lazy_static! {
+    static ref JOBS: JobTable = JobTable::new();
+}
+
+/// The process-wide job table
+pub fn global() -> &'static JobTable {
+    &JOBS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wait_collects_finished_job() {
+        let table = JobTable::new();
+        let id = table.start();
+        assert_eq!(table.wait(&id, Duration::ZERO).unwrap(), JobState::Pending);
+        table.finish(&id, Err("lost".to_string())).unwrap();
+        assert_eq!(table.wait(&id, Duration::ZERO).unwrap(), JobState::Failed("lost".to_string()));
+        assert!(matches!(table.state(&id), Err(JobError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_uncollected_jobs_expire() {
+        let table = JobTable::new();
+        let abandoned = table.start();
+        let running = table.start();
+        table.finish(&abandoned, Ok(())).unwrap();
+
+        sweep(&mut table.jobs.lock(), Instant::now());
+        assert_eq!(table.len(), 2);
+        sweep(&mut table.jobs.lock(), Instant::now() + FINISHED_TTL);
+        assert!(matches!(table.state(&abandoned), Err(JobError::NotFound(_))));
+        assert_eq!(table.state(&running).unwrap(), JobState::Pending);
+    }
+
+    #[test]
+    fn test_wait_wakes_on_finish() {
+        let table = std::sync::Arc::new(JobTable::new());
+        let id = table.start();
+        let finisher = {
+            let (table, id) = (table.clone(), id.clone());
+            std::thread::spawn(move || table.finish(&id, Ok(())).unwrap())
+        };
+        assert_eq!(table.wait(&id, Duration::from_secs(10)).unwrap(), JobState::Completed);
+        finisher.join().unwrap();
+    }
+
+    #[test]
+    fn test_wait_idle_waits_for_pending_jobs() {
+        let table = std::sync::Arc::new(JobTable::new());
+        let id = table.start();
+        assert!(!table.wait_idle(Duration::ZERO));
+        let finisher = {
+            let (table, id) = (table.clone(), id.clone());
+            std::thread::spawn(move || table.finish(&id, Ok(())).unwrap())
+        };
+        assert!(table.wait_idle(Duration::from_secs(10)));
+        finisher.join().unwrap();
+        assert_eq!(table.state(&id).unwrap(), JobState::Completed);
+    }
+}
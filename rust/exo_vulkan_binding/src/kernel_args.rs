@@ -0,0 +1,470 @@
+//! Type-safe kernel arguments
+//!
+//! `#[derive(KernelArgs)]` (from `exo_kernel_derive`) turns a struct of
+//! buffers and scalars into descriptor writes and push constant bytes for a
+//! named kernel. `KernelInterface::reflect` reads the bindings and push
+//! constant layout a SPIR-V module actually declares, and `validate` rejects
+//! argument structs that disagree with it when the pipeline is created rather
+//! than producing garbage at dispatch time.
+
+use std::collections::{HashMap, HashSet};
+
+use ash::vk;
+use thiserror::Error;
+
+pub use exo_kernel_derive::KernelArgs;
+
+use crate::memory::BufferRange;
+use crate::tensor::Tensor;
+
+/// Kernel argument errors
+#[derive(Error, Debug)]
+pub enum KernelArgsError {
+    #[error("Invalid SPIR-V: {0}")]
+    InvalidSpirv(String),
+
+    #[error("Kernel '{kernel}': shader binding {binding} has no argument")]
+    MissingBinding { kernel: &'static str, binding: u32 },
+
+    #[error("Kernel '{kernel}': argument '{name}' binds {binding}, which the shader does not declare")]
+    UnknownBinding {
+        kernel: &'static str,
+        name: &'static str,
+        binding: u32,
+    },
+
+    #[error("Kernel '{kernel}': push constant layout mismatch: {detail}")]
+    PushConstantMismatch { kernel: &'static str, detail: String },
+}
+
+pub type KernelArgsResult<T> = Result<T, KernelArgsError>;
+
+/// How one argument reaches the kernel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArgKind {
+    /// Storage buffer at `binding` of descriptor set 0
+    Buffer { binding: u32 },
+    /// `size` bytes of push constants at `offset`
+    PushConstant { offset: u32, size: u32 },
+}
+
+/// One field of an argument struct
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ArgSlot {
+    pub name: &'static str,
+    pub kind: ArgKind,
+}
+
+/// Arguments of a named kernel; implement with `#[derive(KernelArgs)]`
+pub trait KernelArgs {
+    /// Kernel the arguments belong to
+    const KERNEL: &'static str;
+
+    /// Layout of every field, in declaration order
+    fn slots() -> Vec<ArgSlot>;
+
+    /// `(binding, range)` of every buffer argument
+    fn buffers(&self) -> Vec<(u32, BufferRange)>;
+
+    /// Packed push constant bytes
+    fn push_constants(&self) -> Vec<u8>;
+}
+
+/// Types usable as buffer arguments
+pub trait BufferArg {
+    fn buffer_range(&self) -> BufferRange;
+}
+
+impl BufferArg for BufferRange {
+    fn buffer_range(&self) -> BufferRange {
+        *self
+    }
+}
+
+impl BufferArg for Tensor {
+    fn buffer_range(&self) -> BufferRange {
+        self.range
+    }
+}
+
+/// Types usable as push constant arguments (4-byte aligned, std430 packing)
+pub trait PushConstant {
+    const SIZE: u32;
+
+    fn write(&self, out: &mut Vec<u8>);
+}
+
+macro_rules! scalar_push_constant {
+    ($($ty:ty),*) => {
+        $(impl PushConstant for $ty {
+            const SIZE: u32 = 4;
+
+            fn write(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_ne_bytes());
+            }
+        })*
+    };
+}
+
+scalar_push_constant!(u32, i32, f32);
+
+impl<T: PushConstant, const N: usize> PushConstant for [T; N] {
+    const SIZE: u32 = T::SIZE * N as u32;
+
+    fn write(&self, out: &mut Vec<u8>) {
+        for value in self {
+            value.write(out);
+        }
+    }
+}
+
+/// Bindings and push constant layout declared by a SPIR-V module
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KernelInterface {
+    /// Storage/uniform buffer bindings of descriptor set 0
+    pub buffer_bindings: Vec<u32>,
+    /// `(offset, size)` of each push constant block member
+    pub push_constant_members: Vec<(u32, u32)>,
+    /// Names of the GLCompute entry points
+    pub entry_points: Vec<String>,
+    /// `LocalSize` of the first compute entry point, if declared as literals
+    pub local_size: Option<[u32; 3]>,
+}
+
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+const SPIRV_HEADER_WORDS: usize = 5;
+
+const OP_ENTRY_POINT: u32 = 15;
+const OP_EXECUTION_MODE: u32 = 16;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+
+const EXECUTION_MODEL_GL_COMPUTE: u32 = 5;
+const EXECUTION_MODE_LOCAL_SIZE: u32 = 17;
+
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+const STORAGE_CLASS_UNIFORM: u32 = 2;
+const STORAGE_CLASS_PUSH_CONSTANT: u32 = 9;
+const STORAGE_CLASS_STORAGE_BUFFER: u32 = 12;
+
+#[derive(Clone, Debug)]
+enum SpirvType {
+    Scalar(u32),
+    Vector(u32, u32),
+    Array(u32, u32),
+    Struct(Vec<u32>),
+    /// Pointee type
+    Pointer(u32),
+}
+
+impl KernelInterface {
+    /// Reflect the interface of a compute shader module
+    pub fn reflect(spirv: &[u32]) -> KernelArgsResult<Self> {
+        if spirv.len() < SPIRV_HEADER_WORDS || spirv[0] != SPIRV_MAGIC {
+            return Err(KernelArgsError::InvalidSpirv("bad header".to_string()));
+        }
+
+        let mut types: HashMap<u32, SpirvType> = HashMap::new();
+        let mut constants: HashMap<u32, u32> = HashMap::new();
+        let mut array_strides: HashMap<u32, u32> = HashMap::new();
+        let mut bindings: HashMap<u32, u32> = HashMap::new();
+        let mut sets: HashMap<u32, u32> = HashMap::new();
+        let mut member_offsets: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut variables: Vec<(u32, u32, u32)> = Vec::new();
+        let mut entry_points: Vec<(u32, String)> = Vec::new();
+        let mut local_sizes: HashMap<u32, [u32; 3]> = HashMap::new();
+
+        let mut words = &spirv[SPIRV_HEADER_WORDS..];
+        while let Some(&first) = words.first() {
+            let (count, opcode) = ((first >> 16) as usize, first & 0xffff);
+            if count == 0 || count > words.len() {
+                return Err(KernelArgsError::InvalidSpirv(format!("truncated instruction {}", opcode)));
+            }
+            let ops = &words[1..count];
+            let op = |i: usize| ops.get(i).copied().unwrap_or(0);
+            match opcode {
+                OP_ENTRY_POINT if op(0) == EXECUTION_MODEL_GL_COMPUTE => {
+                    entry_points.push((op(1), literal_string(ops.get(2..).unwrap_or_default())));
+                }
+                OP_EXECUTION_MODE if op(1) == EXECUTION_MODE_LOCAL_SIZE => {
+                    local_sizes.insert(op(0), [op(2), op(3), op(4)]);
+                }
+                OP_TYPE_INT | OP_TYPE_FLOAT => {
+                    types.insert(op(0), SpirvType::Scalar(op(1) / 8));
+                }
+                OP_TYPE_VECTOR | OP_TYPE_MATRIX => {
+                    types.insert(op(0), SpirvType::Vector(op(1), op(2)));
+                }
+                OP_TYPE_ARRAY => {
+                    types.insert(op(0), SpirvType::Array(op(1), op(2)));
+                }
+                OP_TYPE_STRUCT => {
+                    types.insert(op(0), SpirvType::Struct(ops.get(1..).unwrap_or_default().to_vec()));
+                }
+                OP_TYPE_POINTER => {
+                    types.insert(op(0), SpirvType::Pointer(op(2)));
+                }
+                OP_CONSTANT => {
+                    constants.insert(op(1), op(2));
+                }
+                OP_VARIABLE => variables.push((op(0), op(1), op(2))),
+                OP_DECORATE => match op(1) {
+                    DECORATION_BINDING => {
+                        bindings.insert(op(0), op(2));
+                    }
+                    DECORATION_DESCRIPTOR_SET => {
+                        sets.insert(op(0), op(2));
+                    }
+                    DECORATION_ARRAY_STRIDE => {
+                        array_strides.insert(op(0), op(2));
+                    }
+                    _ => {}
+                },
+                OP_MEMBER_DECORATE if op(2) == DECORATION_OFFSET => {
+                    member_offsets.insert((op(0), op(1)), op(3));
+                }
+                _ => {}
+            }
+            words = &words[count..];
+        }
+
+        let size_of = |id: u32| type_size(id, &types, &constants, &array_strides);
+        let mut interface = KernelInterface::default();
+        for (pointer_type, id, storage_class) in variables {
+            match storage_class {
+                STORAGE_CLASS_UNIFORM | STORAGE_CLASS_STORAGE_BUFFER => {
+                    if sets.get(&id).copied().unwrap_or(0) == 0 {
+                        if let Some(&binding) = bindings.get(&id) {
+                            interface.buffer_bindings.push(binding);
+                        }
+                    }
+                }
+                STORAGE_CLASS_PUSH_CONSTANT => {
+                    let Some(SpirvType::Pointer(block)) = types.get(&pointer_type) else {
+                        return Err(KernelArgsError::InvalidSpirv("push constant is not a pointer".to_string()));
+                    };
+                    let Some(SpirvType::Struct(members)) = types.get(block) else {
+                        return Err(KernelArgsError::InvalidSpirv("push constant is not a block".to_string()));
+                    };
+                    for (index, &member) in members.iter().enumerate() {
+                        let offset = member_offsets.get(&(*block, index as u32)).copied().unwrap_or(0);
+                        interface.push_constant_members.push((offset, size_of(member)?));
+                    }
+                }
+                _ => {}
+            }
+        }
+        interface.buffer_bindings.sort_unstable();
+        interface.push_constant_members.sort_unstable();
+        interface.local_size = entry_points.first().and_then(|(id, _)| local_sizes.get(id).copied());
+        interface.entry_points = entry_points.into_iter().map(|(_, name)| name).collect();
+        Ok(interface)
+    }
+
+    /// Total push constant bytes
+    pub fn push_constant_size(&self) -> u32 {
+        self.push_constant_members.iter().map(|(offset, size)| offset + size).max().unwrap_or(0)
+    }
+
+    /// Check `A` matches this interface
+    pub fn validate<A: KernelArgs>(&self) -> KernelArgsResult<()> {
+        let slots = A::slots();
+        let mut provided = HashSet::new();
+        let mut push = Vec::new();
+        for slot in &slots {
+            match slot.kind {
+                ArgKind::Buffer { binding } => {
+                    if !self.buffer_bindings.contains(&binding) {
+                        return Err(KernelArgsError::UnknownBinding {
+                            kernel: A::KERNEL,
+                            name: slot.name,
+                            binding,
+                        });
+                    }
+                    provided.insert(binding);
+                }
+                ArgKind::PushConstant { offset, size } => push.push((offset, size)),
+            }
+        }
+
+        if let Some(&binding) = self.buffer_bindings.iter().find(|b| !provided.contains(b)) {
+            return Err(KernelArgsError::MissingBinding {
+                kernel: A::KERNEL,
+                binding,
+            });
+        }
+
+        if push != self.push_constant_members {
+            return Err(KernelArgsError::PushConstantMismatch {
+                kernel: A::KERNEL,
+                detail: format!("arguments {:?}, shader {:?}", push, self.push_constant_members),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Decode a nul-terminated SPIR-V literal string
+fn literal_string(words: &[u32]) -> String {
+    let bytes: Vec<u8> = words
+        .iter()
+        .flat_map(|w| w.to_le_bytes())
+        .take_while(|&b| b != 0)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+fn type_size(
+    id: u32,
+    types: &HashMap<u32, SpirvType>,
+    constants: &HashMap<u32, u32>,
+    array_strides: &HashMap<u32, u32>,
+) -> KernelArgsResult<u32> {
+    let size = |id| type_size(id, types, constants, array_strides);
+    match types.get(&id) {
+        Some(SpirvType::Scalar(bytes)) => Ok(*bytes),
+        Some(SpirvType::Vector(component, count)) => Ok(size(*component)? * count),
+        Some(SpirvType::Array(element, length)) => {
+            let length = constants
+                .get(length)
+                .ok_or_else(|| KernelArgsError::InvalidSpirv(format!("array length %{} is not a constant", length)))?;
+            let stride = match array_strides.get(&id) {
+                Some(&stride) => stride,
+                None => size(*element)?,
+            };
+            Ok(stride * length)
+        }
+        Some(SpirvType::Struct(members)) => members.iter().map(|&m| size(m)).sum(),
+        Some(SpirvType::Pointer(_)) | None => {
+            Err(KernelArgsError::InvalidSpirv(format!("cannot size type %{}", id)))
+        }
+    }
+}
+
+/// Write the buffer arguments of `args` into `descriptor_set`
+///
+/// # Safety Requirements
+/// - `descriptor_set` must have been allocated for the kernel `A` targets
+/// - The set must not be in use by pending command buffers
+pub unsafe fn write_descriptors<A: KernelArgs>(device: &ash::Device, descriptor_set: vk::DescriptorSet, args: &A) {
+    let infos: Vec<(u32, [vk::DescriptorBufferInfo; 1])> = args
+        .buffers()
+        .into_iter()
+        .map(|(binding, range)| (binding, [range.descriptor_info()]))
+        .collect();
+    let writes: Vec<vk::WriteDescriptorSet> = infos
+        .iter()
+        .map(|(binding, info)| {
+            vk::WriteDescriptorSet::default()
+                .dst_set(descriptor_set)
+                .dst_binding(*binding)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(info)
+        })
+        .collect();
+    // SAFETY: caller guarantees the set matches the kernel and is not in use
+    unsafe { device.update_descriptor_sets(&writes, &[]) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(KernelArgs)]
+    #[kernel(name = "scale")]
+    struct ScaleArgs {
+        #[binding(0)]
+        input: BufferRange,
+        #[binding(1)]
+        output: BufferRange,
+        len: u32,
+        factors: [f32; 2],
+    }
+
+    fn range(size: u64) -> BufferRange {
+        BufferRange {
+            buffer: vk::Buffer::null(),
+            offset: 0,
+            size,
+        }
+    }
+
+    /// Hand-assembled module: two storage buffers and a `{ uint; float[2]; }` push block
+    fn scale_spirv() -> Vec<u32> {
+        let inst = |opcode: u32, ops: &[u32]| {
+            let mut words = vec![((ops.len() as u32 + 1) << 16) | opcode];
+            words.extend_from_slice(ops);
+            words
+        };
+        [
+            vec![SPIRV_MAGIC, 0x0001_0000, 0, 100, 0],
+            inst(OP_ENTRY_POINT, &[EXECUTION_MODEL_GL_COMPUTE, 4, u32::from_le_bytes(*b"main"), 0]),
+            inst(OP_EXECUTION_MODE, &[4, EXECUTION_MODE_LOCAL_SIZE, 64, 1, 1]),
+            inst(OP_DECORATE, &[10, DECORATION_BINDING, 0]),
+            inst(OP_DECORATE, &[11, DECORATION_BINDING, 1]),
+            inst(OP_DECORATE, &[5, DECORATION_ARRAY_STRIDE, 4]),
+            inst(OP_MEMBER_DECORATE, &[6, 0, DECORATION_OFFSET, 0]),
+            inst(OP_MEMBER_DECORATE, &[6, 1, DECORATION_OFFSET, 4]),
+            inst(OP_TYPE_INT, &[1, 32, 0]),
+            inst(OP_TYPE_FLOAT, &[2, 32]),
+            inst(OP_CONSTANT, &[1, 3, 2]),
+            inst(OP_TYPE_ARRAY, &[5, 2, 3]),
+            inst(OP_TYPE_STRUCT, &[6, 1, 5]),
+            inst(OP_TYPE_POINTER, &[7, STORAGE_CLASS_PUSH_CONSTANT, 6]),
+            inst(OP_TYPE_POINTER, &[8, STORAGE_CLASS_STORAGE_BUFFER, 6]),
+            inst(OP_VARIABLE, &[7, 9, STORAGE_CLASS_PUSH_CONSTANT]),
+            inst(OP_VARIABLE, &[8, 10, STORAGE_CLASS_STORAGE_BUFFER]),
+            inst(OP_VARIABLE, &[8, 11, STORAGE_CLASS_STORAGE_BUFFER]),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn test_derive_packs_arguments() {
+        let args = ScaleArgs {
+            input: range(16),
+            output: range(32),
+            len: 4,
+            factors: [0.5, 2.0],
+        };
+        assert_eq!(ScaleArgs::KERNEL, "scale");
+        assert_eq!(args.buffers().iter().map(|(b, r)| (*b, r.size)).collect::<Vec<_>>(), vec![(0, 16), (1, 32)]);
+        assert_eq!(args.push_constants().len(), 12);
+        assert_eq!(
+            ScaleArgs::slots()[3].kind,
+            ArgKind::PushConstant { offset: 4, size: 8 }
+        );
+    }
+
+    #[test]
+    fn test_validate_against_reflection() {
+        let interface = KernelInterface::reflect(&scale_spirv()).unwrap();
+        assert_eq!(interface.buffer_bindings, vec![0, 1]);
+        assert_eq!(interface.push_constant_size(), 12);
+        assert_eq!(interface.entry_points, vec!["main".to_string()]);
+        assert_eq!(interface.local_size, Some([64, 1, 1]));
+        interface.validate::<ScaleArgs>().unwrap();
+
+        let missing_output = KernelInterface {
+            buffer_bindings: vec![0, 1, 2],
+            ..interface
+        };
+        assert!(matches!(
+            missing_output.validate::<ScaleArgs>(),
+            Err(KernelArgsError::MissingBinding { binding: 2, .. })
+        ));
+    }
+}
@@ -0,0 +1,797 @@
+//! Built-in ML kernels
+//!
+//! `KernelRegistry` records the ops most transformer layers are made of
+//! (elementwise add and multiply, SiLU and GELU, row softmax, RMSNorm,
+//! cross-entropy, speculative acceptance, in-place axpy over f32 or int8
+//! weights and GEMM with f32, f16 or `sparse` weights) from the kernels embedded by
+//! `build.rs`, so inference code dispatches them without writing shaders or
+//! layouts.
+//!
+//! Pipelines are built on first use. Every dispatch takes its own descriptor
+//! set from the registry's `DescriptorManager`, so one command buffer can
+//! hold any number of ops; `release_sets` returns them once it completes.
+//! Ops do not synchronize with each other: place `ops::compute_barrier`
+//! between dependent ones. The `*_host` functions compute the same results
+//! on the CPU for tests and as a fallback.
+
+use std::collections::HashMap;
+
+use ash::vk;
+use thiserror::Error;
+
+use crate::calibration::QuantParams;
+use crate::descriptor::{DescriptorError, DescriptorManager};
+use crate::host_simd::f16_to_f32;
+use crate::memory::BufferRange;
+use crate::ops::{MAX_GROUP_COUNT, compute_barrier};
+use crate::pipeline::{ComputePipeline, PipelineError};
+use crate::sparse::{SparseLayout, SparseMatrix};
+use crate::tensor::ELEMENT_SIZE;
+
+/// Kernel library errors
+#[derive(Error, Debug)]
+pub enum LibraryError {
+    #[error("Shape mismatch: {0}")]
+    ShapeMismatch(String),
+
+    #[error(transparent)]
+    Pipeline(#[from] PipelineError),
+
+    #[error(transparent)]
+    Descriptor(#[from] DescriptorError),
+}
+
+pub type LibraryResult<T> = Result<T, LibraryError>;
+
+/// Workgroup size of the elementwise and row kernels
+pub const WORKGROUP_SIZE: u32 = 256;
+
+/// Output tile edge of the GEMM kernels
+pub const GEMM_TILE: u32 = 16;
+
+/// Epsilon most RMSNorm layers use
+pub const DEFAULT_RMS_EPS: f32 = 1e-6;
+
+/// `result[1]` of `KernelRegistry::speculative_accept` when no target row follows the accepted draft
+pub const NO_TOKEN: u32 = u32::MAX;
+
+/// Two-input elementwise ops
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Elementwise {
+    Add,
+    Mul,
+}
+
+impl Elementwise {
+    fn kernel(self) -> &'static str {
+        match self {
+            Elementwise::Add => "elementwise_add",
+            Elementwise::Mul => "elementwise_mul",
+        }
+    }
+
+    pub fn apply(self, a: f32, b: f32) -> f32 {
+        match self {
+            Elementwise::Add => a + b,
+            Elementwise::Mul => a * b,
+        }
+    }
+}
+
+/// Activation functions
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Activation {
+    Silu,
+    /// tanh approximation, as in GPT-2 and most GELU checkpoints
+    Gelu,
+}
+
+impl Activation {
+    fn kernel(self) -> &'static str {
+        match self {
+            Activation::Silu => "silu",
+            Activation::Gelu => "gelu",
+        }
+    }
+
+    pub fn apply(self, x: f32) -> f32 {
+        match self {
+            Activation::Silu => x / (1.0 + (-x).exp()),
+            Activation::Gelu => {
+                let inner = (0.797_884_6 * (x + 0.044_715 * x * x * x)).clamp(-15.0, 15.0);
+                0.5 * x * (1.0 + inner.tanh())
+            }
+        }
+    }
+}
+
+/// `[m, k] x [k, n] -> [m, n]`, all row-major
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MatmulDims {
+    pub m: u32,
+    pub n: u32,
+    pub k: u32,
+}
+
+/// Push constants of the elementwise kernels
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LinearPushConstants {
+    pub len: u32,
+    /// Groups per row of the dispatch, for indices past `MAX_GROUP_COUNT`
+    pub groups_x: u32,
+}
+
+/// Push constants of the axpy kernels
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AxpyPushConstants {
+    pub len: u32,
+    pub groups_x: u32,
+    /// Elements per row sharing quantization params; unused for f32
+    pub cols: u32,
+    pub scale: f32,
+}
+
+/// Push constants of the softmax and RMSNorm kernels
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RowPushConstants {
+    pub rows: u32,
+    pub cols: u32,
+    pub groups_x: u32,
+    /// RMSNorm epsilon; unused by softmax
+    pub eps: f32,
+}
+
+/// Push constants of the speculative acceptance kernels
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AcceptPushConstants {
+    pub rows: u32,
+    pub cols: u32,
+    pub groups_x: u32,
+    pub draft_len: u32,
+}
+
+/// Push constants of the GEMM kernels
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GemmPushConstants {
+    pub m: u32,
+    pub n: u32,
+    pub k: u32,
+    pub _pad: u32,
+}
+
+/// Dispatch of `groups` workgroups, wrapped into y past `MAX_GROUP_COUNT`
+pub fn wrapped_groups(groups: u32) -> [u32; 3] {
+    let groups = groups.max(1);
+    [groups.min(MAX_GROUP_COUNT), groups.div_ceil(MAX_GROUP_COUNT), 1]
+}
+
+/// Workgroups of a GEMM, one per output tile
+pub fn gemm_groups(dims: MatmulDims) -> LibraryResult<[u32; 3]> {
+    let groups = [dims.n.div_ceil(GEMM_TILE).max(1), dims.m.div_ceil(GEMM_TILE).max(1), 1];
+    if groups[0] > MAX_GROUP_COUNT || groups[1] > MAX_GROUP_COUNT {
+        return Err(LibraryError::ShapeMismatch(format!(
+            "{}x{} output exceeds {} tiles per axis",
+            dims.m, dims.n, MAX_GROUP_COUNT
+        )));
+    }
+    Ok(groups)
+}
+
+/// GEMM shape of `a[m, layout.k] x b` with sparse `b`
+fn sparse_dims(layout: SparseLayout, m: u32) -> LibraryResult<MatmulDims> {
+    let dim = |v: usize| {
+        u32::try_from(v).map_err(|_| LibraryError::ShapeMismatch(format!("sparse weight dimension {} exceeds u32", v)))
+    };
+    Ok(MatmulDims {
+        m,
+        n: dim(layout.n)?,
+        k: dim(layout.k)?,
+    })
+}
+
+/// Check `range` holds `bytes`
+fn check_size(what: &str, range: &BufferRange, bytes: u64) -> LibraryResult<()> {
+    if range.size < bytes {
+        return Err(LibraryError::ShapeMismatch(format!(
+            "{} of {} bytes is smaller than the {} needed",
+            what, range.size, bytes
+        )));
+    }
+    Ok(())
+}
+
+fn f32_bytes(count: u64) -> u64 {
+    count * ELEMENT_SIZE
+}
+
+/// Pipelines of the built-in kernels and the descriptor sets their dispatches use
+pub struct KernelRegistry {
+    device: ash::Device,
+    cache: vk::PipelineCache,
+    pipelines: HashMap<&'static str, ComputePipeline>,
+    descriptors: DescriptorManager,
+    /// Sets written since the last `release_sets`
+    in_use: Vec<vk::DescriptorSet>,
+}
+
+impl KernelRegistry {
+    /// Create a registry; `device` must outlive it, `cache` may be null
+    pub fn new(device: &ash::Device, cache: vk::PipelineCache) -> Self {
+        Self {
+            device: device.clone(),
+            cache,
+            pipelines: HashMap::new(),
+            descriptors: DescriptorManager::new(device),
+            in_use: Vec::new(),
+        }
+    }
+
+    /// Build the pipeline of `kernel` now rather than at its first dispatch
+    pub fn warm(&mut self, kernel: &'static str) -> LibraryResult<()> {
+        if !self.pipelines.contains_key(kernel) {
+            let pipeline = ComputePipeline::from_embedded(&self.device, kernel, self.cache)?;
+            self.pipelines.insert(kernel, pipeline);
+        }
+        Ok(())
+    }
+
+    /// Record `kernel` into `cmd` with a fresh descriptor set
+    ///
+    /// # Safety Requirements
+    /// - `cmd` must be in the recording state
+    /// - All buffers must stay alive until the command buffer completes
+    unsafe fn run<P: Copy>(
+        &mut self,
+        cmd: vk::CommandBuffer,
+        kernel: &'static str,
+        buffers: &[(u32, &BufferRange)],
+        push: &P,
+        groups: [u32; 3],
+    ) -> LibraryResult<()> {
+        self.warm(kernel)?;
+        let pipeline = &self.pipelines[kernel];
+        let set = self.descriptors.allocate(pipeline.set_layout())?;
+        // SAFETY: forwarded from the caller's guarantees; the set was just
+        // allocated or recycled, so no pending work uses it
+        let dispatched = unsafe { pipeline.dispatch_with_set(cmd, set, buffers, push, groups) };
+        if let Err(e) = dispatched {
+            self.descriptors.free(set)?;
+            return Err(e.into());
+        }
+        self.in_use.push(set);
+        Ok(())
+    }
+
+    /// `out = a op b` over `len` f32s
+    ///
+    /// # Safety Requirements
+    /// - `cmd` must be in the recording state
+    /// - All buffers must stay alive until the command buffer completes
+    pub unsafe fn elementwise(
+        &mut self,
+        cmd: vk::CommandBuffer,
+        op: Elementwise,
+        a: &BufferRange,
+        b: &BufferRange,
+        out: &BufferRange,
+        len: u32,
+    ) -> LibraryResult<()> {
+        let bytes = f32_bytes(u64::from(len));
+        check_size("a", a, bytes)?;
+        check_size("b", b, bytes)?;
+        check_size("out", out, bytes)?;
+        let groups = wrapped_groups(len.div_ceil(WORKGROUP_SIZE));
+        let push = LinearPushConstants {
+            len,
+            groups_x: groups[0],
+        };
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe { self.run(cmd, op.kernel(), &[(0, a), (1, out), (2, b)], &push, groups) }
+    }
+
+    /// `out = activation(x)` over `len` f32s
+    ///
+    /// # Safety Requirements
+    /// - As for `elementwise`
+    pub unsafe fn activation(
+        &mut self,
+        cmd: vk::CommandBuffer,
+        activation: Activation,
+        x: &BufferRange,
+        out: &BufferRange,
+        len: u32,
+    ) -> LibraryResult<()> {
+        let bytes = f32_bytes(u64::from(len));
+        check_size("x", x, bytes)?;
+        check_size("out", out, bytes)?;
+        let groups = wrapped_groups(len.div_ceil(WORKGROUP_SIZE));
+        let push = LinearPushConstants {
+            len,
+            groups_x: groups[0],
+        };
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe { self.run(cmd, activation.kernel(), &[(0, x), (1, out)], &push, groups) }
+    }
+
+    /// `w += scale * delta` over `len` f32s, in place
+    ///
+    /// # Safety Requirements
+    /// - As for `elementwise`
+    pub unsafe fn axpy(
+        &mut self,
+        cmd: vk::CommandBuffer,
+        w: &BufferRange,
+        delta: &BufferRange,
+        len: u32,
+        scale: f32,
+    ) -> LibraryResult<()> {
+        let bytes = f32_bytes(u64::from(len));
+        check_size("w", w, bytes)?;
+        check_size("delta", delta, bytes)?;
+        let groups = wrapped_groups(len.div_ceil(WORKGROUP_SIZE));
+        let push = AxpyPushConstants {
+            len,
+            groups_x: groups[0],
+            cols: 0,
+            scale,
+        };
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe { self.run(cmd, "axpy_f32", &[(0, w), (1, delta)], &push, groups) }
+    }
+
+    /// `w += scale * delta` over a `[rows, cols]` int8 matrix, in place
+    ///
+    /// `w` packs four values to a word, little end first; `params` holds a
+    /// `(scale, zero_point)` f32 pair per row (`activation_codec::params_words`).
+    /// Each value is dequantized, updated and requantized with its row's
+    /// params, which stay fixed: updates finer than half a step are lost and
+    /// values past the row's range saturate.
+    ///
+    /// # Safety Requirements
+    /// - As for `elementwise`
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn axpy_int8(
+        &mut self,
+        cmd: vk::CommandBuffer,
+        w: &BufferRange,
+        params: &BufferRange,
+        delta: &BufferRange,
+        rows: u32,
+        cols: u32,
+        scale: f32,
+    ) -> LibraryResult<()> {
+        let len = rows.checked_mul(cols).ok_or_else(|| {
+            LibraryError::ShapeMismatch(format!("{}x{} int8 weight exceeds u32 elements", rows, cols))
+        })?;
+        check_size("w", w, u64::from(len).div_ceil(4) * 4)?;
+        check_size("params", params, f32_bytes(2 * u64::from(rows)))?;
+        check_size("delta", delta, f32_bytes(u64::from(len)))?;
+        let groups = wrapped_groups(len.div_ceil(4).div_ceil(WORKGROUP_SIZE));
+        let push = AxpyPushConstants {
+            len,
+            groups_x: groups[0],
+            cols: cols.max(1),
+            scale,
+        };
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe { self.run(cmd, "axpy_int8", &[(0, w), (1, delta), (2, params)], &push, groups) }
+    }
+
+    /// Softmax of each row of a `[rows, cols]` matrix
+    ///
+    /// # Safety Requirements
+    /// - As for `elementwise`
+    pub unsafe fn softmax(
+        &mut self,
+        cmd: vk::CommandBuffer,
+        x: &BufferRange,
+        out: &BufferRange,
+        rows: u32,
+        cols: u32,
+    ) -> LibraryResult<()> {
+        let bytes = f32_bytes(u64::from(rows) * u64::from(cols));
+        check_size("x", x, bytes)?;
+        check_size("out", out, bytes)?;
+        let groups = wrapped_groups(rows);
+        let push = RowPushConstants {
+            rows,
+            cols,
+            groups_x: groups[0],
+            eps: 0.0,
+        };
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe { self.run(cmd, "softmax", &[(0, x), (1, out)], &push, groups) }
+    }
+
+    /// RMSNorm of each row of a `[rows, cols]` matrix, scaled by `weight[cols]`
+    ///
+    /// # Safety Requirements
+    /// - As for `elementwise`
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn rms_norm(
+        &mut self,
+        cmd: vk::CommandBuffer,
+        x: &BufferRange,
+        weight: &BufferRange,
+        out: &BufferRange,
+        rows: u32,
+        cols: u32,
+        eps: f32,
+    ) -> LibraryResult<()> {
+        let bytes = f32_bytes(u64::from(rows) * u64::from(cols));
+        check_size("x", x, bytes)?;
+        check_size("weight", weight, f32_bytes(u64::from(cols)))?;
+        check_size("out", out, bytes)?;
+        let groups = wrapped_groups(rows);
+        let push = RowPushConstants {
+            rows,
+            cols,
+            groups_x: groups[0],
+            eps,
+        };
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe { self.run(cmd, "rms_norm", &[(0, x), (1, out), (2, weight)], &push, groups) }
+    }
+
+    /// Cross-entropy of `[rows, vocab]` logits against `targets[rows]` (u32)
+    ///
+    /// Writes each row's negative log-likelihood to `nll[rows]`, then their
+    /// sum to `total[0]` behind a barrier of its own. Targets must be below
+    /// `vocab`; others score 0.
+    ///
+    /// # Safety Requirements
+    /// - As for `elementwise`
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn cross_entropy(
+        &mut self,
+        cmd: vk::CommandBuffer,
+        logits: &BufferRange,
+        targets: &BufferRange,
+        nll: &BufferRange,
+        total: &BufferRange,
+        rows: u32,
+        vocab: u32,
+    ) -> LibraryResult<()> {
+        check_size("logits", logits, f32_bytes(u64::from(rows) * u64::from(vocab)))?;
+        // u32 targets are as wide as f32s
+        check_size("targets", targets, f32_bytes(u64::from(rows)))?;
+        check_size("nll", nll, f32_bytes(u64::from(rows)))?;
+        check_size("total", total, f32_bytes(1))?;
+        let groups = wrapped_groups(rows);
+        let push = RowPushConstants {
+            rows,
+            cols: vocab,
+            groups_x: groups[0],
+            eps: 0.0,
+        };
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe {
+            self.run(cmd, "cross_entropy", &[(0, logits), (1, nll), (2, targets)], &push, groups)?;
+            compute_barrier(&self.device, cmd);
+            self.run(cmd, "cross_entropy_sum", &[(0, nll), (1, total)], &push, [1, 1, 1])
+        }
+    }
+
+    /// Greedy acceptance of `draft[draft_len]` (u32) against `[rows, vocab]` target logits
+    ///
+    /// Writes each row's argmax to `best[rows]`, then behind a barrier of its
+    /// own the accepted draft length to `result[0]` and the target's token at
+    /// the first rejected position to `result[1]`, or `NO_TOKEN` when every
+    /// row was accepted. `accept_result` decodes the two words; the result
+    /// matches `speculative::accept_greedy`.
+    ///
+    /// # Safety Requirements
+    /// - As for `elementwise`
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn speculative_accept(
+        &mut self,
+        cmd: vk::CommandBuffer,
+        logits: &BufferRange,
+        draft: &BufferRange,
+        best: &BufferRange,
+        result: &BufferRange,
+        rows: u32,
+        vocab: u32,
+        draft_len: u32,
+    ) -> LibraryResult<()> {
+        check_size("logits", logits, f32_bytes(u64::from(rows) * u64::from(vocab)))?;
+        // u32 tokens are as wide as f32s
+        check_size("draft", draft, f32_bytes(u64::from(draft_len)))?;
+        check_size("best", best, f32_bytes(u64::from(rows)))?;
+        check_size("result", result, f32_bytes(2))?;
+        let groups = wrapped_groups(rows);
+        let push = AcceptPushConstants {
+            rows,
+            cols: vocab,
+            groups_x: groups[0],
+            draft_len,
+        };
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe {
+            self.run(cmd, "speculative_argmax", &[(0, logits), (1, best)], &push, groups)?;
+            compute_barrier(&self.device, cmd);
+            self.run(cmd, "speculative_accept", &[(0, best), (1, result), (2, draft)], &push, [1, 1, 1])
+        }
+    }
+
+    /// `out = a x b` with f32 `b`
+    ///
+    /// # Safety Requirements
+    /// - As for `elementwise`
+    pub unsafe fn matmul(
+        &mut self,
+        cmd: vk::CommandBuffer,
+        a: &BufferRange,
+        b: &BufferRange,
+        out: &BufferRange,
+        dims: MatmulDims,
+    ) -> LibraryResult<()> {
+        check_size("b", b, f32_bytes(u64::from(dims.k) * u64::from(dims.n)))?;
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe { self.gemm(cmd, "gemm_f32", a, &[b], out, dims) }
+    }
+
+    /// `out = a x b` with f16 `b`, two values per 32-bit word, low half first
+    ///
+    /// Halves the weight memory of `matmul`; accumulation stays f32.
+    ///
+    /// # Safety Requirements
+    /// - As for `elementwise`
+    pub unsafe fn matmul_f16(
+        &mut self,
+        cmd: vk::CommandBuffer,
+        a: &BufferRange,
+        b: &BufferRange,
+        out: &BufferRange,
+        dims: MatmulDims,
+    ) -> LibraryResult<()> {
+        let words = (u64::from(dims.k) * u64::from(dims.n)).div_ceil(2);
+        check_size("b", b, f32_bytes(words))?;
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe { self.gemm(cmd, "gemm_f16", a, &[b], out, dims) }
+    }
+
+    /// `out = a x b` with sparse `b` of shape `[layout.k, layout.n]`
+    ///
+    /// `values` and `index` hold `SparseMatrix::values` and `index` as laid
+    /// out by the `sparse` module. Block-sparse weights skip their missing
+    /// blocks; 2:4 weights halve the multiply-adds of `matmul`.
+    ///
+    /// # Safety Requirements
+    /// - As for `elementwise`
+    /// - `index` must be valid for `layout`, as `SparseMatrix::from_parts` checks
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn matmul_sparse(
+        &mut self,
+        cmd: vk::CommandBuffer,
+        a: &BufferRange,
+        layout: SparseLayout,
+        values: &BufferRange,
+        index: &BufferRange,
+        out: &BufferRange,
+        m: u32,
+    ) -> LibraryResult<()> {
+        let dims = sparse_dims(layout, m)?;
+        check_size("values", values, f32_bytes(layout.value_count(0) as u64))?;
+        check_size("index", index, f32_bytes(layout.index_words(0) as u64))?;
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe { self.gemm(cmd, layout.format.kernel(), a, &[values, index], out, dims) }
+    }
+
+    /// # Safety Requirements
+    /// - As for `elementwise`
+    unsafe fn gemm(
+        &mut self,
+        cmd: vk::CommandBuffer,
+        kernel: &'static str,
+        a: &BufferRange,
+        weights: &[&BufferRange],
+        out: &BufferRange,
+        dims: MatmulDims,
+    ) -> LibraryResult<()> {
+        check_size("a", a, f32_bytes(u64::from(dims.m) * u64::from(dims.k)))?;
+        check_size("out", out, f32_bytes(u64::from(dims.m) * u64::from(dims.n)))?;
+        let groups = gemm_groups(dims)?;
+        let push = GemmPushConstants {
+            m: dims.m,
+            n: dims.n,
+            k: dims.k,
+            _pad: 0,
+        };
+        let mut buffers = vec![(0, a), (1, out)];
+        buffers.extend((2..).zip(weights.iter().copied()));
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe { self.run(cmd, kernel, &buffers, &push, groups) }
+    }
+
+    /// Return the descriptor sets of every op recorded so far
+    ///
+    /// Call once the command buffers holding them have completed.
+    pub fn release_sets(&mut self) -> LibraryResult<()> {
+        for set in self.in_use.drain(..) {
+            self.descriptors.free(set)?;
+        }
+        Ok(())
+    }
+
+    /// Pipelines built so far
+    pub fn built(&self) -> Vec<&'static str> {
+        let mut names: Vec<&'static str> = self.pipelines.keys().copied().collect();
+        names.sort_unstable();
+        names
+    }
+}
+
+/// Host reference for `KernelRegistry::elementwise`
+pub fn elementwise_host(op: Elementwise, a: &[f32], b: &[f32]) -> Vec<f32> {
+    a.iter().zip(b).map(|(&x, &y)| op.apply(x, y)).collect()
+}
+
+/// Host reference for `KernelRegistry::activation`
+pub fn activation_host(activation: Activation, x: &[f32]) -> Vec<f32> {
+    x.iter().map(|&v| activation.apply(v)).collect()
+}
+
+/// Host reference for `KernelRegistry::axpy`
+pub fn axpy_host(w: &mut [f32], delta: &[f32], scale: f32) {
+    for (w, &d) in w.iter_mut().zip(delta) {
+        *w += scale * d;
+    }
+}
+
+/// Host reference for `KernelRegistry::axpy_int8`; `params` has one entry per row
+pub fn axpy_int8_host(w: &mut [i8], params: &[QuantParams], delta: &[f32], cols: usize, scale: f32) {
+    for ((row, delta), params) in w.chunks_mut(cols.max(1)).zip(delta.chunks(cols.max(1))).zip(params) {
+        for (q, &d) in row.iter_mut().zip(delta) {
+            *q = params.quantize(params.dequantize(*q) + scale * d);
+        }
+    }
+}
+
+/// Host reference for `KernelRegistry::softmax`
+pub fn softmax_host(x: &[f32], cols: usize) -> Vec<f32> {
+    x.chunks(cols.max(1))
+        .flat_map(|row| {
+            let max = row.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+            let exps: Vec<f32> = row.iter().map(|&v| (v - max).exp()).collect();
+            let inv_sum = 1.0 / exps.iter().sum::<f32>();
+            exps.into_iter().map(move |e| e * inv_sum)
+        })
+        .collect()
+}
+
+/// Host reference for `KernelRegistry::rms_norm`
+pub fn rms_norm_host(x: &[f32], weight: &[f32], eps: f32) -> Vec<f32> {
+    let cols = weight.len().max(1);
+    x.chunks(cols)
+        .flat_map(|row| {
+            let mean_square = row.iter().map(|v| v * v).sum::<f32>() / cols as f32;
+            let scale = 1.0 / (mean_square + eps).sqrt();
+            row.iter().zip(weight).map(move |(&v, &w)| v * scale * w)
+        })
+        .collect()
+}
+
+/// Host reference for the `nll` output of `KernelRegistry::cross_entropy`
+pub fn cross_entropy_host(logits: &[f32], targets: &[u32], vocab: usize) -> Vec<f32> {
+    logits
+        .chunks(vocab.max(1))
+        .zip(targets)
+        .map(|(row, &target)| match row.get(target as usize) {
+            Some(&logit) => {
+                let max = row.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+                row.iter().map(|&v| (v - max).exp()).sum::<f32>().ln() + max - logit
+            }
+            None => 0.0,
+        })
+        .collect()
+}
+
+/// Decode the `result` words of `KernelRegistry::speculative_accept`
+///
+/// Returns the accepted draft length and the target's correction (or bonus)
+/// token, as `speculative::accept_greedy` does.
+pub fn accept_result(result: [u32; 2]) -> (usize, Option<u32>) {
+    (result[0] as usize, (result[1] != NO_TOKEN).then_some(result[1]))
+}
+
+/// Host reference for `KernelRegistry::matmul`
+pub fn matmul_host(a: &[f32], b: &[f32], dims: MatmulDims) -> Vec<f32> {
+    let (m, n, k) = (dims.m as usize, dims.n as usize, dims.k as usize);
+    let mut out = vec![0.0; m * n];
+    for row in 0..m {
+        for kk in 0..k {
+            let scale = a[row * k + kk];
+            for col in 0..n {
+                out[row * n + col] += scale * b[kk * n + col];
+            }
+        }
+    }
+    out
+}
+
+/// Host reference for `KernelRegistry::matmul_f16`; `b` packed as on device
+pub fn matmul_f16_host(a: &[f32], b: &[u32], dims: MatmulDims) -> Vec<f32> {
+    let values: Vec<f32> = b
+        .iter()
+        .flat_map(|&word| [f16_to_f32(word as u16), f16_to_f32((word >> 16) as u16)])
+        .take(dims.k as usize * dims.n as usize)
+        .collect();
+    matmul_host(a, &values, dims)
+}
+
+/// Host reference for `KernelRegistry::matmul_sparse`
+pub fn matmul_sparse_host(a: &[f32], b: &SparseMatrix, m: u32) -> LibraryResult<Vec<f32>> {
+    Ok(matmul_host(a, &b.to_dense(), sparse_dims(b.layout(), m)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host_simd::f32_to_f16;
+
+    #[test]
+    fn test_host_references() {
+        assert_eq!(elementwise_host(Elementwise::Mul, &[2.0, 3.0], &[4.0, 0.5]), vec![8.0, 1.5]);
+        let silu = activation_host(Activation::Silu, &[0.0, 10.0]);
+        assert_eq!(silu[0], 0.0);
+        assert!((silu[1] - 9.999_546).abs() < 1e-4);
+        assert!((Activation::Gelu.apply(1.0) - 0.841_192).abs() < 1e-4);
+
+        let softmax = softmax_host(&[1.0, 1.0, 0.0, f32::NEG_INFINITY], 2);
+        assert_eq!(softmax, vec![0.5, 0.5, 1.0, 0.0]);
+        let norm = rms_norm_host(&[3.0, 4.0], &[1.0, 2.0], 0.0);
+        let scale = 1.0 / 12.5f32.sqrt();
+        assert_eq!(norm, vec![3.0 * scale, 8.0 * scale]);
+
+        let nll = cross_entropy_host(&[0.0, 0.0, 1.0, 0.0], &[1, 1], 2);
+        assert!((nll[0] - 2.0f32.ln()).abs() < 1e-6);
+        assert!((nll[1] - (1.0 + 1.0f32.exp()).ln()).abs() < 1e-6);
+        assert_eq!(cross_entropy_host(&[1.0, 2.0], &[2], 2), vec![0.0]);
+
+        assert_eq!(accept_result([2, 7]), (2, Some(7)));
+        assert_eq!(accept_result([3, NO_TOKEN]), (3, None));
+    }
+
+    #[test]
+    fn test_matmul_f16_matches_f32() {
+        let dims = MatmulDims { m: 2, n: 3, k: 3 };
+        let a = [1.0, 2.0, 3.0, -1.0, 0.5, 0.0];
+        let b = [1.0, 0.0, 2.0, 0.0, 1.0, -1.0, 0.25, 0.5, 1.0];
+        let expected = vec![1.75, 3.5, 3.0, -1.0, 0.5, -2.5];
+        assert_eq!(matmul_host(&a, &b, dims), expected);
+
+        // Nine halves round up to five words
+        let packed: Vec<u32> = b
+            .chunks(2)
+            .map(|pair| u32::from(f32_to_f16(pair[0])) | u32::from(f32_to_f16(*pair.get(1).unwrap_or(&0.0))) << 16)
+            .collect();
+        assert_eq!(packed.len(), 5);
+        assert_eq!(matmul_f16_host(&a, &packed, dims), expected);
+    }
+
+    #[test]
+    fn test_matmul_sparse_matches_dense() {
+        use crate::sparse::TwoFourMatrix;
+
+        let dims = MatmulDims { m: 2, n: 2, k: 4 };
+        let a = [1.0, 2.0, 3.0, 4.0, -1.0, 0.0, 1.0, 0.5];
+        let b = [1.0, 0.0, 0.0, 2.0, 0.0, -1.0, 3.0, 0.0];
+        let sparse = SparseMatrix::TwoFour(TwoFourMatrix::from_dense(&b, 4, 2).unwrap());
+        assert_eq!(matmul_sparse_host(&a, &sparse, dims.m).unwrap(), matmul_host(&a, &b, dims));
+        assert_eq!(sparse_dims(sparse.layout(), 2).unwrap(), dims);
+    }
+
+    #[test]
+    fn test_dispatch_shapes() {
+        assert_eq!(wrapped_groups(0), [1, 1, 1]);
+        assert_eq!(wrapped_groups(MAX_GROUP_COUNT + 1), [MAX_GROUP_COUNT, 2, 1]);
+        assert_eq!(gemm_groups(MatmulDims { m: 33, n: 16, k: 7 }).unwrap(), [1, 3, 1]);
+        assert!(gemm_groups(MatmulDims { m: 1, n: u32::MAX, k: 1 }).is_err());
+    }
+}
@@ -0,0 +1,268 @@
+//! Custom kernels registered at runtime
+//!
+//! Apps can ship their own compute kernels (a bespoke postprocessing pass,
+//! an experimental op) as SPIR-V in their assets and register them without
+//! rebuilding the native library. Each kernel comes with a small metadata
+//! document describing the interface the app will dispatch it with; the
+//! module is reflected (`KernelInterface::reflect`) and registration fails
+//! unless the two agree, so a stale or mismatched blob is caught at load time
+//! rather than as garbage output.
+//!
+//! Metadata JSON:
+//!
+//! ```json
+//! {"entry_point": "main", "bindings": [0, 1], "push_constant_bytes": 8, "workgroup_size": [64, 1, 1]}
+//! ```
+//!
+//! `entry_point` defaults to `main`, `push_constant_bytes` to 0 and
+//! `workgroup_size` is only checked when given.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::kernel_args::{KernelArgsError, KernelInterface};
+use crate::kernels::{self, SPIRV_MAGIC};
+
+/// Plugin kernel errors
+#[derive(Error, Debug)]
+pub enum PluginError {
+    #[error("Invalid kernel name {0:?}")]
+    InvalidName(String),
+
+    #[error("Kernel name already in use: {0}")]
+    NameTaken(String),
+
+    #[error("Kernel {name}: {reason}")]
+    InvalidSpirv { name: String, reason: String },
+
+    #[error("Malformed kernel metadata: {0}")]
+    Metadata(#[from] serde_json::Error),
+
+    #[error("Reflection failed: {0}")]
+    Reflection(#[from] KernelArgsError),
+
+    #[error("Kernel {name}: metadata disagrees with SPIR-V: {detail}")]
+    Mismatch { name: String, detail: String },
+}
+
+pub type PluginResult<T> = Result<T, PluginError>;
+
+fn default_entry_point() -> String {
+    "main".to_string()
+}
+
+/// Interface an app declares for its kernel
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KernelMetadata {
+    #[serde(default = "default_entry_point")]
+    pub entry_point: String,
+    /// Storage buffer bindings of descriptor set 0
+    pub bindings: Vec<u32>,
+    #[serde(default)]
+    pub push_constant_bytes: u32,
+    #[serde(default)]
+    pub workgroup_size: Option<[u32; 3]>,
+}
+
+impl KernelMetadata {
+    pub fn from_json(json: &str) -> PluginResult<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+}
+
+/// A validated runtime kernel
+#[derive(Debug)]
+pub struct PluginKernel {
+    pub name: String,
+    pub spirv: Box<[u32]>,
+    pub metadata: KernelMetadata,
+    /// What the module actually declares
+    pub interface: KernelInterface,
+}
+
+/// Decode SPIR-V bytes (as stored in an asset) into words
+pub fn spirv_words(name: &str, bytes: &[u8]) -> PluginResult<Box<[u32]>> {
+    let invalid = |reason: &str| PluginError::InvalidSpirv {
+        name: name.to_string(),
+        reason: reason.to_string(),
+    };
+    if bytes.is_empty() || !bytes.len().is_multiple_of(4) {
+        return Err(invalid("length is not a multiple of 4"));
+    }
+    let words: Box<[u32]> = bytes
+        .chunks_exact(4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+    if words[0] != SPIRV_MAGIC {
+        return Err(invalid("missing SPIR-V magic"));
+    }
+    Ok(words)
+}
+
+/// Reflect `spirv` and check it implements `metadata`
+pub fn validate(name: &str, spirv: &[u32], metadata: &KernelMetadata) -> PluginResult<KernelInterface> {
+    let interface = KernelInterface::reflect(spirv)?;
+    let mismatch = |detail: String| PluginError::Mismatch {
+        name: name.to_string(),
+        detail,
+    };
+
+    if !interface.entry_points.contains(&metadata.entry_point) {
+        return Err(mismatch(format!(
+            "no compute entry point {:?} (module has {:?})",
+            metadata.entry_point, interface.entry_points
+        )));
+    }
+    let mut bindings = metadata.bindings.clone();
+    bindings.sort_unstable();
+    if bindings != interface.buffer_bindings {
+        return Err(mismatch(format!(
+            "bindings {:?}, shader declares {:?}",
+            bindings, interface.buffer_bindings
+        )));
+    }
+    if metadata.push_constant_bytes != interface.push_constant_size() {
+        return Err(mismatch(format!(
+            "{} push constant bytes, shader declares {}",
+            metadata.push_constant_bytes,
+            interface.push_constant_size()
+        )));
+    }
+    if let Some(size) = metadata.workgroup_size
+        && interface.local_size != Some(size)
+    {
+        return Err(mismatch(format!(
+            "workgroup size {:?}, shader declares {:?}",
+            size, interface.local_size
+        )));
+    }
+    Ok(interface)
+}
+
+lazy_static::lazy_static! {
+    static ref PLUGINS: Mutex<HashMap<String, Arc<PluginKernel>>> = Mutex::new(HashMap::new());
+}
+
+/// Validate and register kernel `name` from SPIR-V bytes
+///
+/// Names must not shadow an embedded kernel or another plugin; unregister
+/// first to replace one.
+pub fn register_kernel(name: &str, spirv: &[u8], metadata: KernelMetadata) -> PluginResult<Arc<PluginKernel>> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.') {
+        return Err(PluginError::InvalidName(name.to_string()));
+    }
+    if kernels::get(name).is_ok() {
+        return Err(PluginError::NameTaken(name.to_string()));
+    }
+
+    let spirv = spirv_words(name, spirv)?;
+    let interface = validate(name, &spirv, &metadata)?;
+    let kernel = Arc::new(PluginKernel {
+        name: name.to_string(),
+        spirv,
+        metadata,
+        interface,
+    });
+
+    let mut plugins = PLUGINS.lock();
+    if plugins.contains_key(name) {
+        return Err(PluginError::NameTaken(name.to_string()));
+    }
+    plugins.insert(name.to_string(), Arc::clone(&kernel));
+    log::info!("Registered kernel plugin {}", name);
+    Ok(kernel)
+}
+
+/// Remove plugin `name`; pipelines already created from it stay valid
+pub fn unregister_kernel(name: &str) -> bool {
+    PLUGINS.lock().remove(name).is_some()
+}
+
+/// Look up plugin `name`
+pub fn get(name: &str) -> Option<Arc<PluginKernel>> {
+    PLUGINS.lock().get(name).cloned()
+}
+
+/// Names of every registered plugin, sorted
+pub fn names() -> Vec<String> {
+    let mut names: Vec<String> = PLUGINS.lock().keys().cloned().collect();
+    names.sort();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Compute module named `main`, local size 64, storage buffers 0 and 1, 4 push constant bytes
+    fn module() -> Vec<u8> {
+        let inst = |opcode: u32, ops: &[u32]| {
+            let mut words = vec![((ops.len() as u32 + 1) << 16) | opcode];
+            words.extend_from_slice(ops);
+            words
+        };
+        let words = [
+            vec![SPIRV_MAGIC, 0x0001_0000, 0, 20, 0],
+            inst(15, &[5, 4, u32::from_le_bytes(*b"main"), 0]),
+            inst(16, &[4, 17, 64, 1, 1]),
+            inst(71, &[10, 33, 0]),
+            inst(71, &[11, 33, 1]),
+            inst(72, &[6, 0, 35, 0]),
+            inst(21, &[1, 32, 0]),
+            inst(30, &[6, 1]),
+            inst(32, &[7, 9, 6]),
+            inst(32, &[8, 12, 6]),
+            inst(59, &[7, 9, 9]),
+            inst(59, &[8, 10, 12]),
+            inst(59, &[8, 11, 12]),
+        ]
+        .concat();
+        words.iter().flat_map(|w| w.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn test_register_validates_against_reflection() {
+        let metadata =
+            KernelMetadata::from_json(r#"{"bindings":[1,0],"push_constant_bytes":4,"workgroup_size":[64,1,1]}"#)
+                .unwrap();
+        let kernel = register_kernel("test_plugin_ok", &module(), metadata.clone()).unwrap();
+        assert_eq!(kernel.interface.buffer_bindings, vec![0, 1]);
+        assert!(get("test_plugin_ok").is_some());
+        assert!(names().contains(&"test_plugin_ok".to_string()));
+        assert!(matches!(
+            register_kernel("test_plugin_ok", &module(), metadata),
+            Err(PluginError::NameTaken(_))
+        ));
+        assert!(unregister_kernel("test_plugin_ok"));
+        assert!(get("test_plugin_ok").is_none());
+    }
+
+    #[test]
+    fn test_rejects_mismatched_modules() {
+        let metadata = |json: &str| KernelMetadata::from_json(json).unwrap();
+        for json in [
+            r#"{"bindings":[0],"push_constant_bytes":4}"#,
+            r#"{"bindings":[0,1]}"#,
+            r#"{"entry_point":"run","bindings":[0,1],"push_constant_bytes":4}"#,
+            r#"{"bindings":[0,1],"push_constant_bytes":4,"workgroup_size":[32,1,1]}"#,
+        ] {
+            assert!(
+                matches!(register_kernel("test_plugin_bad", &module(), metadata(json)), Err(PluginError::Mismatch { .. })),
+                "{}",
+                json
+            );
+        }
+        let ok = metadata(r#"{"bindings":[0,1],"push_constant_bytes":4}"#);
+        assert!(matches!(
+            register_kernel("test_plugin_bad", &module()[..18], ok.clone()),
+            Err(PluginError::InvalidSpirv { .. })
+        ));
+        assert!(matches!(register_kernel("bad name", &module(), ok), Err(PluginError::InvalidName(_))));
+        assert!(KernelMetadata::from_json(r#"{"bindings":[0],"queue":1}"#).is_err());
+    }
+}
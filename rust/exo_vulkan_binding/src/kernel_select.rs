@@ -0,0 +1,399 @@
+//! Capability-gated kernel variant selection
+//!
+//! Several ops ship more than one kernel variant, and which one a device runs
+//! depends on its features and limits, known driver quirks and, when
+//! available, autotune timings measured on the device itself. When the same
+//! model is fast on one phone and slow on another, the first question is
+//! which variant each ran and why; `SelectionMatrix::explain` answers it by
+//! evaluating every candidate against a `DeviceProfile` and reporting each
+//! requirement that passed or failed.
+//!
+//! Selection among eligible variants uses the fastest autotune result when
+//! every eligible variant has one, and the static preference order otherwise.
+//! Shape-dependent choices (e.g. `PermutePlan` falling back to the general
+//! kernel for shapes the tiled one cannot cover) happen per call on top of
+//! this.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use ash::vk;
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use crate::kernels;
+use crate::{VulkanContext, VulkanResult};
+
+/// A condition a device must meet to run a variant
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Requirement {
+    /// Core feature by its Vulkan name, e.g. `shaderInt16`
+    Feature(&'static str),
+    /// Device extension
+    Extension(&'static str),
+    /// `maxComputeSharedMemorySize` in bytes
+    MinSharedMemory(u32),
+    /// `maxComputeWorkGroupInvocations`
+    MinInvocations(u32),
+}
+
+/// One kernel implementing an op
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KernelVariant {
+    pub op: &'static str,
+    /// Embedded kernel name
+    pub kernel: &'static str,
+    pub requirements: &'static [Requirement],
+}
+
+/// A driver or device known to misbehave with a variant
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Quirk {
+    pub kernel: String,
+    /// Matches devices with this PCI vendor id, any vendor if None
+    pub vendor_id: Option<u32>,
+    /// Matches devices whose name contains this, any device if None
+    pub device_name_contains: Option<String>,
+    pub reason: String,
+}
+
+impl Quirk {
+    fn applies(&self, device: &DeviceProfile) -> bool {
+        self.vendor_id.is_none_or(|id| id == device.vendor_id)
+            && self
+                .device_name_contains
+                .as_deref()
+                .is_none_or(|name| device.name.contains(name))
+    }
+}
+
+/// Shared memory the tiled permute kernel stages a tile in (`float[32][33]`)
+const PERMUTE_TILE_BYTES: u32 = 32 * 33 * 4;
+
+/// Built-in variants, preferred first within each op
+pub const VARIANTS: &[KernelVariant] = &[
+    KernelVariant {
+        op: "permute",
+        kernel: "permute_tiled",
+        requirements: &[Requirement::MinSharedMemory(PERMUTE_TILE_BYTES), Requirement::MinInvocations(256)],
+    },
+    KernelVariant {
+        op: "permute",
+        kernel: "permute_general",
+        requirements: &[Requirement::MinInvocations(256)],
+    },
+    KernelVariant {
+        op: "reduce",
+        kernel: "reduce_pass1",
+        requirements: &[Requirement::MinSharedMemory(256 * 4), Requirement::MinInvocations(256)],
+    },
+    KernelVariant {
+        op: "dequantize",
+        kernel: "dequantize_int8",
+        requirements: &[Requirement::MinInvocations(256)],
+    },
+    KernelVariant {
+        op: "stop_sequences",
+        kernel: "stop_sequences",
+        requirements: &[Requirement::MinInvocations(64)],
+    },
+    KernelVariant {
+        op: "embedding_search",
+        kernel: "embedding_top_k",
+        requirements: &[Requirement::MinSharedMemory(256 * 8), Requirement::MinInvocations(256)],
+    },
+];
+
+/// What selection needs to know about a device
+#[derive(Clone, Debug)]
+pub struct DeviceProfile {
+    pub name: String,
+    pub vendor_id: u32,
+    pub max_shared_memory: u32,
+    pub max_invocations: u32,
+    pub features: vk::PhysicalDeviceFeatures,
+    pub extensions: Vec<String>,
+}
+
+impl DeviceProfile {
+    /// Profile of enumerated device `index`
+    pub fn from_context(context: &VulkanContext, index: usize) -> VulkanResult<Self> {
+        let properties = context.get_device_properties(index)?;
+        let capabilities = context.get_device_capabilities(index)?;
+        Ok(Self {
+            name: properties
+                .device_name_as_c_str()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            vendor_id: properties.vendor_id,
+            max_shared_memory: properties.limits.max_compute_shared_memory_size,
+            max_invocations: properties.limits.max_compute_work_group_invocations,
+            features: capabilities.features,
+            extensions: capabilities.extensions.clone(),
+        })
+    }
+
+    fn has_feature(&self, name: &str) -> bool {
+        let f = &self.features;
+        let enabled = match name {
+            "shaderInt16" => f.shader_int16,
+            "shaderInt64" => f.shader_int64,
+            "shaderFloat64" => f.shader_float64,
+            "robustBufferAccess" => f.robust_buffer_access,
+            _ => vk::FALSE,
+        };
+        enabled == vk::TRUE
+    }
+
+    /// Whether `requirement` holds, with the evidence
+    fn check(&self, requirement: &Requirement) -> (bool, String) {
+        match *requirement {
+            Requirement::Feature(name) => {
+                let ok = self.has_feature(name);
+                (ok, format!("feature {}: {}", name, if ok { "supported" } else { "missing" }))
+            }
+            Requirement::Extension(name) => {
+                let ok = self.extensions.iter().any(|e| e == name);
+                (ok, format!("extension {}: {}", name, if ok { "supported" } else { "missing" }))
+            }
+            Requirement::MinSharedMemory(bytes) => (
+                self.max_shared_memory >= bytes,
+                format!("shared memory {} >= {} bytes", self.max_shared_memory, bytes),
+            ),
+            Requirement::MinInvocations(count) => (
+                self.max_invocations >= count,
+                format!("workgroup invocations {} >= {}", self.max_invocations, count),
+            ),
+        }
+    }
+}
+
+/// How one candidate fared
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct CandidateReport {
+    pub kernel: &'static str,
+    pub eligible: bool,
+    /// One line per requirement, quirk or build check
+    pub reasons: Vec<String>,
+    #[serde(rename = "autotune_us", serialize_with = "micros")]
+    pub autotune: Option<Duration>,
+}
+
+/// `duration` in whole microseconds, or null
+fn micros<S: serde::Serializer>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error> {
+    duration.map(|d| d.as_micros() as u64).serialize(serializer)
+}
+
+/// Why a variant was chosen for an op
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SelectionReport {
+    pub op: String,
+    pub device: String,
+    /// None if no variant is eligible (or the op is unknown)
+    pub chosen: Option<&'static str>,
+    /// `autotune`, `preference` or `none`
+    pub basis: &'static str,
+    pub candidates: Vec<CandidateReport>,
+}
+
+impl SelectionReport {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("selection reports always serialize")
+    }
+}
+
+/// Variants, quirks and autotune results for selection
+#[derive(Debug)]
+pub struct SelectionMatrix {
+    variants: Vec<KernelVariant>,
+    quirks: Vec<Quirk>,
+    /// Best time per `(device name, kernel)`
+    autotune: HashMap<(String, &'static str), Duration>,
+    /// Treat every variant as embedded (tests, builds without `glslc`)
+    assume_embedded: bool,
+}
+
+impl Default for SelectionMatrix {
+    fn default() -> Self {
+        Self::new(VARIANTS.to_vec())
+    }
+}
+
+impl SelectionMatrix {
+    pub fn new(variants: Vec<KernelVariant>) -> Self {
+        Self {
+            variants,
+            quirks: Vec::new(),
+            autotune: HashMap::new(),
+            assume_embedded: false,
+        }
+    }
+
+    /// Exclude a variant on matching devices
+    pub fn add_quirk(&mut self, quirk: Quirk) {
+        self.quirks.push(quirk);
+    }
+
+    /// Record a measured time of `kernel` on `device`; the best time is kept
+    pub fn record_autotune(&mut self, device: &str, kernel: &'static str, time: Duration) {
+        self.autotune
+            .entry((device.to_string(), kernel))
+            .and_modify(|best| *best = (*best).min(time))
+            .or_insert(time);
+    }
+
+    /// Best recorded time of every kernel on `device`
+    pub fn autotune_results(&self, device: &str) -> Vec<(&'static str, Duration)> {
+        let mut results: Vec<(&'static str, Duration)> = self
+            .autotune
+            .iter()
+            .filter(|((name, _), _)| name == device)
+            .map(|((_, kernel), &time)| (*kernel, time))
+            .collect();
+        results.sort();
+        results
+    }
+
+    /// Re-record a time saved from `autotune_results`
+    ///
+    /// # Returns
+    /// false if `kernel` is not a known variant (e.g. the cache predates a
+    /// kernel rename) and the time was dropped
+    pub fn restore_autotune(&mut self, device: &str, kernel: &str, time: Duration) -> bool {
+        let Some(kernel) = self.variants.iter().map(|v| v.kernel).find(|&k| k == kernel) else {
+            return false;
+        };
+        self.record_autotune(device, kernel, time);
+        true
+    }
+
+    /// Evaluate every variant of `op` on `device`
+    pub fn explain(&self, op: &str, device: &DeviceProfile) -> SelectionReport {
+        let candidates: Vec<CandidateReport> = self
+            .variants
+            .iter()
+            .filter(|v| v.op == op)
+            .map(|variant| {
+                let mut eligible = true;
+                let mut reasons = Vec::new();
+                if !self.assume_embedded && kernels::get(variant.kernel).is_err() {
+                    eligible = false;
+                    reasons.push("not embedded in this build".to_string());
+                }
+                for requirement in variant.requirements {
+                    let (ok, detail) = device.check(requirement);
+                    eligible &= ok;
+                    reasons.push(format!("{} {}", if ok { "ok:" } else { "fail:" }, detail));
+                }
+                for quirk in self.quirks.iter().filter(|q| q.kernel == variant.kernel && q.applies(device)) {
+                    eligible = false;
+                    reasons.push(format!("quirk: {}", quirk.reason));
+                }
+                let autotune = self.autotune.get(&(device.name.clone(), variant.kernel)).copied();
+                if let Some(time) = autotune {
+                    reasons.push(format!("autotune: {} us", time.as_micros()));
+                }
+                CandidateReport {
+                    kernel: variant.kernel,
+                    eligible,
+                    reasons,
+                    autotune,
+                }
+            })
+            .collect();
+
+        let eligible: Vec<&CandidateReport> = candidates.iter().filter(|c| c.eligible).collect();
+        let (chosen, basis) = if eligible.len() > 1 && eligible.iter().all(|c| c.autotune.is_some()) {
+            (eligible.iter().min_by_key(|c| c.autotune).map(|c| c.kernel), "autotune")
+        } else if let Some(first) = eligible.first() {
+            (Some(first.kernel), "preference")
+        } else {
+            (None, "none")
+        };
+
+        SelectionReport {
+            op: op.to_string(),
+            device: device.name.clone(),
+            chosen,
+            basis,
+            candidates,
+        }
+    }
+
+    /// Variant `explain` picks for `op` on `device`
+    pub fn select(&self, op: &str, device: &DeviceProfile) -> Option<&'static str> {
+        self.explain(op, device).chosen
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref SELECTION: Mutex<SelectionMatrix> = Mutex::new(SelectionMatrix::default());
+}
+
+/// Process-wide selection matrix
+pub fn global() -> parking_lot::MutexGuard<'static, SelectionMatrix> {
+    SELECTION.lock()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(shared: u32, invocations: u32) -> DeviceProfile {
+        DeviceProfile {
+            name: "Test GPU".to_string(),
+            vendor_id: 0x13B5,
+            max_shared_memory: shared,
+            max_invocations: invocations,
+            features: vk::PhysicalDeviceFeatures::default(),
+            extensions: Vec::new(),
+        }
+    }
+
+    fn matrix() -> SelectionMatrix {
+        SelectionMatrix {
+            assume_embedded: true,
+            ..SelectionMatrix::default()
+        }
+    }
+
+    #[test]
+    fn test_limits_and_quirks_gate_variants() {
+        let mut matrix = matrix();
+        assert_eq!(matrix.select("permute", &device(32768, 1024)), Some("permute_tiled"));
+
+        let report = matrix.explain("permute", &device(4096, 1024));
+        assert_eq!(report.chosen, Some("permute_general"));
+        assert_eq!(report.basis, "preference");
+        assert_eq!(report.candidates[0].reasons[0], "fail: shared memory 4096 >= 4224 bytes");
+
+        matrix.add_quirk(Quirk {
+            kernel: "permute_general".to_string(),
+            vendor_id: Some(0x13B5),
+            device_name_contains: None,
+            reason: "test quirk\n\"driver bug\"".to_string(),
+        });
+        let report = matrix.explain("permute", &device(4096, 128));
+        assert_eq!((report.chosen, report.basis), (None, "none"));
+        assert!(report.candidates[1].reasons.contains(&"quirk: test quirk\n\"driver bug\"".to_string()));
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert!(json["chosen"].is_null());
+        assert_eq!(json["candidates"][1]["reasons"], serde_json::json!(report.candidates[1].reasons));
+        assert!(matrix.explain("no_such_op", &device(0, 0)).candidates.is_empty());
+    }
+
+    #[test]
+    fn test_autotune_overrides_preference() {
+        let mut matrix = matrix();
+        let gpu = device(32768, 1024);
+        matrix.record_autotune("Test GPU", "permute_tiled", Duration::from_micros(90));
+        assert_eq!(matrix.explain("permute", &gpu).basis, "preference");
+
+        matrix.record_autotune("Test GPU", "permute_general", Duration::from_micros(120));
+        matrix.record_autotune("Test GPU", "permute_general", Duration::from_micros(60));
+        let report = matrix.explain("permute", &gpu);
+        assert_eq!((report.chosen, report.basis), (Some("permute_general"), "autotune"));
+        assert_eq!(report.candidates[1].autotune, Some(Duration::from_micros(60)));
+        assert!(report.to_json().contains(r#""autotune_us":60"#));
+    }
+}
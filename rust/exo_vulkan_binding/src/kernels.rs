@@ -0,0 +1,200 @@
+//! Embedded compute kernels
+//!
+//! `build.rs` compiles `shaders/*.comp` and embeds the SPIR-V of every kernel
+//! whose cargo feature is enabled, so a build without `kernels-core` or
+//! `kernels-media` carries none of those blobs in the `.so`.
+//!
+//! Blobs are stored zstd-compressed and decompressed on first use, typically
+//! at pipeline creation. Apps that would rather pay that cost up front call
+//! `extract_all` during warmup.
+
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use ruzstd::decoding::FrameDecoder;
+use thiserror::Error;
+
+/// First word of every SPIR-V module
+pub const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+/// Kernel-related errors
+#[derive(Error, Debug)]
+pub enum KernelsError {
+    #[error("Kernel not embedded in this build: {0}")]
+    NotEmbedded(String),
+
+    #[error("Failed to decompress kernel {name}: {reason}")]
+    Decompress { name: &'static str, reason: String },
+
+    #[error("Kernel {0} is not valid SPIR-V")]
+    InvalidSpirv(&'static str),
+}
+
+pub type KernelsResult<T> = Result<T, KernelsError>;
+
+/// Compressed SPIR-V of one kernel variant compiled into the binary
+#[derive(Debug)]
+pub struct EmbeddedKernel {
+    pub name: &'static str,
+    /// Cargo feature that embedded it
+    pub feature: &'static str,
+    /// zstd frame holding the SPIR-V
+    pub compressed: &'static [u8],
+    /// Size of the SPIR-V once decompressed
+    pub spirv_len: usize,
+    words: OnceLock<Box<[u32]>>,
+}
+
+impl EmbeddedKernel {
+    // Only called from the generated table, which is empty without glslc
+    #[allow(dead_code)]
+    const fn new(name: &'static str, feature: &'static str, compressed: &'static [u8], spirv_len: usize) -> Self {
+        Self {
+            name,
+            feature,
+            compressed,
+            spirv_len,
+            words: OnceLock::new(),
+        }
+    }
+
+    /// SPIR-V words, decompressing on first call
+    pub fn spirv(&self) -> KernelsResult<&[u32]> {
+        if let Some(words) = self.words.get() {
+            return Ok(words);
+        }
+        // Racing callers may both decompress; the first result is kept
+        let words = self.decompress()?;
+        Ok(self.words.get_or_init(|| words))
+    }
+
+    /// Whether the SPIR-V has already been decompressed
+    pub fn is_extracted(&self) -> bool {
+        self.words.get().is_some()
+    }
+
+    fn decompress(&self) -> KernelsResult<Box<[u32]>> {
+        let mut bytes = vec![0u8; self.spirv_len];
+        let written = FrameDecoder::new()
+            .decode_all(self.compressed, &mut bytes)
+            .map_err(|e| KernelsError::Decompress {
+                name: self.name,
+                reason: e.to_string(),
+            })?;
+        if written != self.spirv_len || written % 4 != 0 {
+            return Err(KernelsError::InvalidSpirv(self.name));
+        }
+
+        let words: Box<[u32]> = bytes
+            .chunks_exact(4)
+            .map(|b| u32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        if words.first() != Some(&SPIRV_MAGIC) {
+            return Err(KernelsError::InvalidSpirv(self.name));
+        }
+        Ok(words)
+    }
+}
+
+include!(concat!(env!("OUT_DIR"), "/kernels.rs"));
+
+/// Every kernel compiled into this build
+pub fn embedded() -> &'static [EmbeddedKernel] {
+    &EMBEDDED
+}
+
+/// Look up kernel `name`
+pub fn get(name: &str) -> KernelsResult<&'static EmbeddedKernel> {
+    EMBEDDED
+        .iter()
+        .find(|k| k.name == name)
+        .ok_or_else(|| KernelsError::NotEmbedded(name.to_string()))
+}
+
+/// SPIR-V of kernel `name`; `NotEmbedded` if its feature was compiled out
+/// (or the build had no `glslc`)
+pub fn spirv(name: &str) -> KernelsResult<&'static [u32]> {
+    get(name)?.spirv()
+}
+
+/// Result of `extract_all`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ExtractStats {
+    /// Kernels decompressed by this call
+    pub extracted: usize,
+    /// Kernels that were already decompressed
+    pub cached: usize,
+    pub compressed_bytes: usize,
+    pub spirv_bytes: usize,
+    pub duration: Duration,
+}
+
+/// Decompress every embedded kernel now instead of at first use
+pub fn extract_all() -> KernelsResult<ExtractStats> {
+    let start = Instant::now();
+    let mut stats = ExtractStats::default();
+    for kernel in &EMBEDDED {
+        if kernel.is_extracted() {
+            stats.cached += 1;
+        } else {
+            kernel.spirv()?;
+            stats.extracted += 1;
+        }
+        stats.compressed_bytes += kernel.compressed.len();
+        stats.spirv_bytes += kernel.spirv_len;
+    }
+    stats.duration = start.elapsed();
+    Ok(stats)
+}
+
+/// Cargo features this build was compiled with
+pub fn enabled_features() -> Vec<&'static str> {
+    [
+        ("kernels-core", cfg!(feature = "kernels-core")),
+        ("kernels-media", cfg!(feature = "kernels-media")),
+        ("loader-gguf", cfg!(feature = "loader-gguf")),
+        ("loader-safetensors", cfg!(feature = "loader-safetensors")),
+        ("profiling", cfg!(feature = "profiling")),
+        ("validation", cfg!(feature = "validation")),
+        ("strict-checks", cfg!(feature = "strict-checks")),
+        ("remote-debug", cfg!(feature = "remote-debug")),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ruzstd::encoding::{CompressionLevel, compress_to_vec};
+
+    fn kernel_from(spirv: &[u8]) -> EmbeddedKernel {
+        let compressed = compress_to_vec(spirv, CompressionLevel::Fastest);
+        EmbeddedKernel::new("test", "kernels-core", Box::leak(compressed.into_boxed_slice()), spirv.len())
+    }
+
+    #[test]
+    fn test_embedded_kernels_match_features() {
+        let features = enabled_features();
+        for kernel in embedded() {
+            assert!(features.contains(&kernel.feature));
+        }
+        assert!(matches!(spirv("no_such_kernel"), Err(KernelsError::NotEmbedded(_))));
+        assert!(extract_all().unwrap().extracted + extract_all().unwrap().cached >= embedded().len());
+    }
+
+    #[test]
+    fn test_lazy_decompression() {
+        let words = [SPIRV_MAGIC, 0x0001_0000, 0, 7, 0];
+        let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_ne_bytes()).collect();
+        let kernel = kernel_from(&bytes);
+
+        assert!(!kernel.is_extracted());
+        assert_eq!(kernel.spirv().unwrap(), &words);
+        assert!(kernel.is_extracted());
+
+        let garbage = kernel_from(&[1, 2, 3, 4]);
+        assert!(matches!(garbage.spirv(), Err(KernelsError::InvalidSpirv("test"))));
+    }
+}
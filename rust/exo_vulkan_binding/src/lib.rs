@@ -3,13 +3,107 @@
 //! This module provides raw Vulkan FFI bindings for device detection and compute operations.
 //! It handles device enumeration, memory management, and command buffer submission.
 
+#[cfg(feature = "kernels-core")]
+pub mod activation_codec;
+pub mod bandwidth;
+#[cfg(feature = "kernels-core")]
+pub mod bench_suite;
+pub mod bucketing;
+#[cfg(feature = "kernels-core")]
+pub mod calibration;
+pub mod capability_cache;
+#[cfg(feature = "validation")]
+pub mod checkpoint;
+pub mod checksum;
 pub mod command;
+pub mod compute_units;
+pub mod config;
+pub mod cow;
+pub mod debug_layers;
+pub mod descriptor;
+pub mod descriptor_cache;
+pub mod device;
+pub mod device_group;
+pub mod diagnostics;
+pub mod dlpack;
+#[cfg(feature = "kernels-core")]
+pub mod embeddings;
+pub mod eval;
+pub mod events;
+pub mod fair_share;
+pub mod failover;
+pub mod frame_budget;
+#[cfg(feature = "kernels-core")]
+pub mod graph;
+#[cfg(unix)]
+pub mod handover;
+pub mod host_buffers;
+pub mod host_import;
+pub mod host_simd;
+pub mod hotplug;
+#[cfg(feature = "validation")]
+pub mod inspect;
+pub mod jobs;
+pub mod kernel_args;
+#[cfg(feature = "kernels-core")]
+pub mod kernel_library;
+pub mod kernel_plugins;
+pub mod kernel_select;
+pub mod kernels;
+pub mod loader;
+#[cfg(feature = "kernels-media")]
+pub mod media;
 pub mod memory;
+pub mod memory_report;
+pub mod memory_watermark;
+pub mod metrics_history;
+pub mod models;
+pub mod node_profile;
+pub mod object_budget;
+#[cfg(feature = "kernels-core")]
+pub mod ops;
+#[cfg(feature = "kernels-core")]
+pub mod parallel_record;
+#[cfg(feature = "kernels-core")]
+pub mod penalties;
+pub mod performance;
+pub mod pipeline;
+pub mod pipeline_cache;
+pub mod pipeline_pool;
+#[cfg(feature = "profiling")]
+pub mod profiler;
+pub mod readback;
+#[cfg(feature = "kernels-core")]
+pub mod regression;
+pub mod registry;
+#[cfg(feature = "remote-debug")]
+pub mod remote;
+pub mod sampler;
+pub mod session;
+pub mod sparse;
+pub mod speculative;
+pub mod staging;
+#[cfg(feature = "kernels-core")]
+pub mod stop;
+pub mod strict;
+pub mod tensor;
+pub mod throttle;
+pub mod token_history;
+pub mod trace;
 pub mod transfer;
+pub mod transfer_scheduler;
+#[cfg(feature = "kernels-core")]
+pub mod weight_delta;
+
+// Lets `#[derive(KernelArgs)]` refer to this crate by name from inside it
+extern crate self as exo_vulkan_binding;
 
 use ash::vk;
 use parking_lot::Mutex;
-use std::sync::Arc;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
 use thiserror::Error;
 use uuid::Uuid;
 
@@ -18,6 +112,12 @@ use uuid::Uuid;
 pub enum VulkanError {
     #[error("Vulkan initialization failed: {0}")]
     InitializationFailed(String),
+
+    #[error("Vulkan loader not available: {0}")]
+    LoaderUnavailable(String),
+
+    #[error("No Vulkan driver (ICD) available: {0:?}")]
+    NoDriver(vk::Result),
     
     #[error("Device not found: {0}")]
     DeviceNotFound(String),
@@ -39,17 +139,51 @@ pub enum VulkanError {
 pub type VulkanResult<T> = Result<T, VulkanError>;
 
 /// Device information returned by Vulkan enumeration
-#[derive(Clone, Debug)]
+///
+/// Serializes to the device entries of the JNI `enumerateDevices` report,
+/// which calls `device_id` `stable_id` and `total_memory_bytes` `memory_bytes`.
+#[derive(Clone, Debug, Serialize)]
 pub struct DeviceInfo {
+    /// Stable across enumerations and instances: the `deviceUUID`, or the
+    /// PCI vendor and device ids for drivers that leave it zeroed
+    #[serde(rename = "stable_id")]
     pub device_id: String,
     pub name: String,
     pub vendor: String,
+    /// PCI vendor id, e.g. `0x5143` for Qualcomm
+    pub vendor_id: u32,
+    /// Driver-specific encoding of the driver version
     pub driver_version: String,
+    /// Highest Vulkan version the device supports, as `major.minor.patch`
+    pub api_version: String,
+    /// Largest single allocation the driver accepts (`maxMemoryAllocationSize`)
+    pub max_allocation_size: u64,
     pub compute_units: u32,
+    /// Whether `compute_units` was reported by the driver or estimated
+    pub compute_units_source: compute_units::ComputeUnitSource,
+    pub subgroup_size: u32,
+    pub max_clock_mhz: Option<u32>,
+    #[serde(rename = "memory_bytes")]
     pub total_memory_bytes: u64,
+    /// Measured by `VulkanContext::benchmark_bandwidth`, estimated until then
     pub bandwidth_gbps: f32,
 }
 
+/// Per-device capabilities, queried lazily once a device is selected
+#[derive(Clone, Debug)]
+pub struct DeviceCapabilities {
+    pub features: vk::PhysicalDeviceFeatures,
+    pub queue_families: Vec<vk::QueueFamilyProperties>,
+    pub extensions: Vec<String>,
+}
+
+impl DeviceCapabilities {
+    /// Whether the device exposes the named extension
+    pub fn has_extension(&self, name: &str) -> bool {
+        self.extensions.iter().any(|e| e == name)
+    }
+}
+
 /// Global Vulkan context - initialized once per process
 pub struct VulkanContext {
     #[allow(dead_code)]
@@ -58,40 +192,132 @@ pub struct VulkanContext {
     physical_devices: Vec<vk::PhysicalDevice>,
     device_properties: Vec<vk::PhysicalDeviceProperties>,
     device_memory_properties: Vec<vk::PhysicalDeviceMemoryProperties>,
+    device_capabilities: Vec<OnceLock<DeviceCapabilities>>,
+    /// Loader library this context was created from, `None` for the system loader
+    loader_path: Option<PathBuf>,
+    /// Installed in debug mode; destroyed before the instance
+    debug_messenger: Option<debug_layers::DebugMessenger>,
+    validation_enabled: bool,
 }
 
-impl VulkanContext {
+/// Options of a `VulkanContext`, defaulting to the process-wide settings
+#[derive(Clone, Debug)]
+pub struct ContextBuilder {
+    loader_path: Option<PathBuf>,
+    debug: bool,
+}
+
+impl Default for ContextBuilder {
+    fn default() -> Self {
+        Self {
+            loader_path: loader_path(),
+            debug: debug_layers::requested(),
+        }
+    }
+}
+
+impl ContextBuilder {
+    /// Loader library to use, `None` for the system loader
+    pub fn loader_path(mut self, path: Option<PathBuf>) -> Self {
+        self.loader_path = path;
+        self
+    }
+
+    /// Enable the validation layer and route its messages to `log` (see `debug_layers`)
+    pub fn debug(mut self, enabled: bool) -> Self {
+        self.debug = enabled;
+        self
+    }
+
     /// Initialize Vulkan and enumerate devices
-    pub fn new() -> VulkanResult<Self> {
+    pub fn build(self) -> VulkanResult<VulkanContext> {
         unsafe {
-            let entry = ash::Entry::load()
-                .map_err(|e| VulkanError::InitializationFailed(e.to_string()))?;
+            let ContextBuilder { loader_path, debug } = self;
+            let entry = match &loader_path {
+                Some(path) => {
+                    log::info!("Loading Vulkan from {}", path.display());
+                    ash::Entry::load_from(path)
+                }
+                None => ash::Entry::load(),
+            }
+            .map_err(|e| VulkanError::LoaderUnavailable(e.to_string()))?;
 
             let app_info = vk::ApplicationInfo::default()
                 .application_name(c"exo")
                 .engine_name(c"exo")
                 .api_version(vk::make_api_version(0, 1, 1, 0));
 
-            let create_info = vk::InstanceCreateInfo::default()
-                .application_info(&app_info);
+            let support = if debug {
+                debug_layers::DebugSupport::query(&entry)
+            } else {
+                debug_layers::DebugSupport::default()
+            };
+            let (layers, extensions) = (support.layers(), support.extensions());
+            let mut instance_messenger = debug_layers::messenger_create_info();
+            let mut create_info = vk::InstanceCreateInfo::default()
+                .application_info(&app_info)
+                .enabled_layer_names(&layers)
+                .enabled_extension_names(&extensions);
+            if support.debug_utils {
+                create_info = create_info.push_next(&mut instance_messenger);
+            }
 
             let instance = entry
                 .create_instance(&create_info, None)
-                .map_err(|e| VulkanError::VulkanError(e))?;
+                .map_err(|e| match e {
+                    // The loader is present but found no usable ICD
+                    vk::Result::ERROR_INCOMPATIBLE_DRIVER
+                    | vk::Result::ERROR_INITIALIZATION_FAILED => VulkanError::NoDriver(e),
+                    _ => VulkanError::VulkanError(e),
+                })?;
+
+            let debug_messenger = if support.debug_utils {
+                match debug_layers::DebugMessenger::new(&entry, &instance) {
+                    Ok(messenger) => Some(messenger),
+                    Err(e) => {
+                        log::warn!("Failed to install debug messenger: {}", e);
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+            if debug {
+                log::info!(
+                    "Vulkan debug mode: validation layer {}, messenger {}",
+                    if support.validation_layer { "on" } else { "off" },
+                    if debug_messenger.is_some() { "on" } else { "off" }
+                );
+            }
 
             let physical_devices = instance
                 .enumerate_physical_devices()
                 .map_err(|e| VulkanError::VulkanError(e))?;
 
-            let device_properties: Vec<_> = physical_devices
-                .iter()
-                .map(|&pd| instance.get_physical_device_properties(pd))
-                .collect();
-
-            let device_memory_properties: Vec<_> = physical_devices
-                .iter()
-                .map(|&pd| instance.get_physical_device_memory_properties(pd))
-                .collect();
+            // Query each device on its own thread; with several ICDs (or
+            // MoltenVK) the serial queries dominate cold-start time
+            let (device_properties, device_memory_properties): (Vec<_>, Vec<_>) =
+                std::thread::scope(|scope| {
+                    let instance = &instance;
+                    let handles: Vec<_> = physical_devices
+                        .iter()
+                        .map(|&pd| {
+                            // pd was enumerated from instance, which outlives the scope
+                            scope.spawn(move || {
+                                (
+                                    instance.get_physical_device_properties(pd),
+                                    instance.get_physical_device_memory_properties(pd),
+                                )
+                            })
+                        })
+                        .collect();
+                    handles
+                        .into_iter()
+                        .map(|h| h.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+                        .unzip()
+                });
+
+            let device_capabilities = physical_devices.iter().map(|_| OnceLock::new()).collect();
 
             Ok(VulkanContext {
                 entry: Arc::new(entry),
@@ -99,15 +325,32 @@ impl VulkanContext {
                 physical_devices,
                 device_properties,
                 device_memory_properties,
+                device_capabilities,
+                loader_path,
+                debug_messenger,
+                validation_enabled: support.validation_layer,
             })
         }
     }
+}
+
+impl VulkanContext {
+    /// Initialize Vulkan and enumerate devices with the process-wide settings
+    pub fn new() -> VulkanResult<Self> {
+        Self::builder().build()
+    }
+
+    /// Options for a context other than the process-wide ones
+    pub fn builder() -> ContextBuilder {
+        ContextBuilder::default()
+    }
 
     /// Get list of all available GPU devices
     pub fn enumerate_devices(&self) -> VulkanResult<Vec<DeviceInfo>> {
         let mut devices = Vec::new();
+        let mut seen_ids: HashMap<String, usize> = HashMap::new();
 
-        for (idx, &_physical_device) in self.physical_devices.iter().enumerate() {
+        for idx in 0..self.physical_devices.len() {
             let props = &self.device_properties[idx];
 
             // SAFETY: device_properties is managed and valid
@@ -123,32 +366,93 @@ impl VulkanContext {
                 0x106B => "Apple",
                 0x8086 => "Intel",
                 0x13B5 => "ARM",
+                0x5143 => "Qualcomm",
                 _ => "Unknown",
             };
 
-            // TODO: Get actual compute units from device properties
-            let compute_units = 16; // Default estimate
+            let topology = compute_units::query(self, idx)?;
 
             // Get total memory
             let mem_props = &self.device_memory_properties[idx];
             let total_memory_bytes = mem_props.memory_heaps[0].size;
 
-            let device_id = Uuid::new_v4().to_string();
+            let uuid = self.get_device_uuid(idx)?;
+            let mut device_id = if uuid.iter().any(|&b| b != 0) {
+                Uuid::from_bytes(uuid).to_string()
+            } else {
+                format!("pci:{:04x}:{:04x}", props.vendor_id, props.device_id)
+            };
+            // Identical boards without UUIDs are told apart by enumeration order
+            let duplicates = seen_ids.entry(device_id.clone()).or_insert(0);
+            if *duplicates > 0 {
+                device_id = format!("{}#{}", device_id, duplicates);
+            }
+            *duplicates += 1;
 
             devices.push(DeviceInfo {
                 device_id,
                 name: device_name,
                 vendor: vendor_name.to_string(),
+                vendor_id: props.vendor_id,
                 driver_version: format!("{}", props.driver_version),
-                compute_units,
+                api_version: format!(
+                    "{}.{}.{}",
+                    vk::api_version_major(props.api_version),
+                    vk::api_version_minor(props.api_version),
+                    vk::api_version_patch(props.api_version)
+                ),
+                max_allocation_size: self.get_max_allocation_size(idx)?,
+                compute_units: topology.compute_units,
+                compute_units_source: topology.source,
+                subgroup_size: topology.subgroup_size,
+                max_clock_mhz: topology.max_clock_mhz,
                 total_memory_bytes,
-                bandwidth_gbps: 32.0, // TODO: Query actual bandwidth
+                bandwidth_gbps: bandwidth::measured(&uuid).unwrap_or(bandwidth::ESTIMATED_BANDWIDTH_GBPS),
             });
         }
 
         Ok(devices)
     }
 
+    /// Measure the copy bandwidth of device `index` in GB/s
+    ///
+    /// Runs once per device per process; later enumerations report the
+    /// result in `DeviceInfo::bandwidth_gbps`. See `bandwidth::benchmark`
+    /// to also cache it on disk.
+    pub fn benchmark_bandwidth(self: &Arc<Self>, index: usize) -> bandwidth::BandwidthResult<f32> {
+        bandwidth::benchmark(self, index, None)
+    }
+
+    /// Enumerate device groups (linked GPUs that can share one logical device)
+    ///
+    /// Every physical device belongs to exactly one group; most systems
+    /// report one single-device group per GPU.
+    pub fn enumerate_device_groups(&self) -> VulkanResult<Vec<device_group::DeviceGroupInfo>> {
+        // SAFETY: instance is valid for the lifetime of self
+        let groups = unsafe {
+            let count = self
+                .instance
+                .enumerate_physical_device_groups_len()
+                .map_err(VulkanError::VulkanError)?;
+            let mut groups = vec![vk::PhysicalDeviceGroupProperties::default(); count];
+            self.instance
+                .enumerate_physical_device_groups(&mut groups)
+                .map_err(VulkanError::VulkanError)?;
+            groups
+        };
+
+        Ok(groups
+            .iter()
+            .map(|group| device_group::DeviceGroupInfo {
+                device_indices: group.physical_devices[..group.physical_device_count as usize]
+                    .iter()
+                    .filter_map(|pd| self.physical_devices.iter().position(|p| p == pd))
+                    .collect(),
+                subset_allocation: group.subset_allocation == vk::TRUE,
+            })
+            .collect())
+    }
+
     /// Get a specific device by index
     pub fn get_physical_device(&self, index: usize) -> VulkanResult<vk::PhysicalDevice> {
         self.physical_devices
@@ -171,23 +475,153 @@ impl VulkanContext {
             .ok_or_else(|| VulkanError::DeviceNotFound(format!("Device {} not found", index)))
     }
 
+    /// `deviceUUID` of a device, stable across reboots and driver updates
+    pub fn get_device_uuid(&self, index: usize) -> VulkanResult<[u8; vk::UUID_SIZE]> {
+        Ok(self.get_id_properties(index)?.device_uuid)
+    }
+
+    /// `driverUUID` of a device; external memory is only shared between
+    /// processes whose device and driver UUIDs both match
+    pub fn get_driver_uuid(&self, index: usize) -> VulkanResult<[u8; vk::UUID_SIZE]> {
+        Ok(self.get_id_properties(index)?.driver_uuid)
+    }
+
+    /// `maxMemoryAllocationSize` of a device (Vulkan 1.1 maintenance3)
+    pub fn get_max_allocation_size(&self, index: usize) -> VulkanResult<u64> {
+        let physical_device = self.get_physical_device(index)?;
+        let mut maintenance3 = vk::PhysicalDeviceMaintenance3Properties::default();
+        {
+            let mut properties = vk::PhysicalDeviceProperties2::default().push_next(&mut maintenance3);
+            // SAFETY: physical_device was enumerated from self.instance, which targets Vulkan 1.1
+            unsafe {
+                self.instance
+                    .get_physical_device_properties2(physical_device, &mut properties)
+            };
+        }
+        Ok(maintenance3.max_memory_allocation_size)
+    }
+
+    fn get_id_properties(&self, index: usize) -> VulkanResult<vk::PhysicalDeviceIDProperties<'static>> {
+        let physical_device = self.get_physical_device(index)?;
+        let mut id_properties = vk::PhysicalDeviceIDProperties::default();
+        {
+            let mut properties = vk::PhysicalDeviceProperties2::default().push_next(&mut id_properties);
+            // SAFETY: physical_device was enumerated from self.instance, which targets Vulkan 1.1
+            unsafe {
+                self.instance
+                    .get_physical_device_properties2(physical_device, &mut properties)
+            };
+        }
+        id_properties.p_next = std::ptr::null_mut();
+        Ok(id_properties)
+    }
+
+    /// Get features, queue families and extensions of a device
+    ///
+    /// Queried on first use and cached, so enumeration stays cheap.
+    pub fn get_device_capabilities(&self, index: usize) -> VulkanResult<&DeviceCapabilities> {
+        let physical_device = self.get_physical_device(index)?;
+        let cell = self
+            .device_capabilities
+            .get(index)
+            .ok_or_else(|| VulkanError::DeviceNotFound(format!("Device {} not found", index)))?;
+
+        Ok(cell.get_or_init(|| {
+            // SAFETY: physical_device was enumerated from self.instance
+            unsafe {
+                let extensions = self
+                    .instance
+                    .enumerate_device_extension_properties(physical_device)
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|ext| ext.extension_name_as_c_str().ok())
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .collect();
+
+                DeviceCapabilities {
+                    features: self.instance.get_physical_device_features(physical_device),
+                    queue_families: self
+                        .instance
+                        .get_physical_device_queue_family_properties(physical_device),
+                    extensions,
+                }
+            }
+        }))
+    }
+
     /// Get the instance handle (for advanced operations)
     pub fn instance(&self) -> Arc<ash::Instance> {
         Arc::clone(&self.instance)
     }
+
+    /// Loader library in use, `None` for the system loader
+    pub fn loader_path(&self) -> Option<&Path> {
+        self.loader_path.as_deref()
+    }
+
+    /// Whether `VK_LAYER_KHRONOS_validation` is active on this instance
+    pub fn validation_enabled(&self) -> bool {
+        self.validation_enabled
+    }
 }
 
 impl Drop for VulkanContext {
     fn drop(&mut self) {
         unsafe {
+            if let Some(messenger) = &self.debug_messenger {
+                messenger.destroy();
+            }
             self.instance.destroy_instance(None);
         }
     }
 }
 
+/// Environment variable naming a Vulkan loader library to use instead of the system one
+pub const LOADER_PATH_ENV: &str = "EXO_VULKAN_LOADER";
+
 lazy_static::lazy_static! {
     /// Global Vulkan context singleton
     static ref VULKAN_CONTEXT: Mutex<Option<Arc<VulkanContext>>> = Mutex::new(None);
+    /// Loader library override set at runtime
+    static ref LOADER_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+}
+
+/// Load Vulkan from `path` instead of the system `libvulkan`
+///
+/// Lets apps ship an updated driver (e.g. a `vulkan.adreno.so` from a driver
+/// package) and choose between it and the system driver. The library must
+/// export the loader entry points. `None` restores the system loader. Must be
+/// called before Vulkan is initialized, or after `reset_vulkan`.
+pub fn set_loader_path(path: Option<PathBuf>) -> VulkanResult<()> {
+    if VULKAN_CONTEXT.lock().is_some() {
+        return Err(VulkanError::InitializationFailed(
+            "Vulkan already initialized; reset before changing the loader".to_string(),
+        ));
+    }
+    if let Some(path) = &path {
+        if !path.is_file() {
+            return Err(VulkanError::LoaderUnavailable(format!("{} is not a file", path.display())));
+        }
+    }
+    *LOADER_PATH.lock() = path;
+    Ok(())
+}
+
+/// Loader library the next initialization will use
+///
+/// A path set with `set_loader_path` wins over `EXO_VULKAN_LOADER`.
+pub fn loader_path() -> Option<PathBuf> {
+    LOADER_PATH
+        .lock()
+        .clone()
+        .or_else(|| std::env::var_os(LOADER_PATH_ENV).filter(|p| !p.is_empty()).map(PathBuf::from))
+}
+
+/// Drop the global context so the next `initialize_vulkan` starts fresh
+///
+/// The instance is destroyed once every outstanding `Arc<VulkanContext>` is dropped.
+pub fn reset_vulkan() {
+    *VULKAN_CONTEXT.lock() = None;
 }
 
 /// Initialize Vulkan globally (call once)
@@ -203,6 +637,24 @@ pub fn initialize_vulkan() -> VulkanResult<Arc<VulkanContext>> {
     Ok(context)
 }
 
+/// Probe whether Vulkan is usable on this system
+///
+/// Never panics or returns an error: a missing loader, a missing ICD, or a
+/// system with zero physical devices all report `false`.
+pub fn is_vulkan_supported() -> bool {
+    match std::panic::catch_unwind(initialize_vulkan) {
+        Ok(Ok(context)) => !context.physical_devices.is_empty(),
+        Ok(Err(e)) => {
+            log::info!("Vulkan not supported: {}", e);
+            false
+        }
+        Err(_) => {
+            log::warn!("Vulkan support probe panicked");
+            false
+        }
+    }
+}
+
 /// Get the global Vulkan context if initialized
 pub fn get_vulkan_context() -> VulkanResult<Arc<VulkanContext>> {
     let ctx = VULKAN_CONTEXT.lock();
@@ -221,6 +673,14 @@ pub fn enumerate_vulkan_devices() -> VulkanResult<Vec<DeviceInfo>> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_loader_path_must_exist() {
+        assert!(matches!(
+            set_loader_path(Some(PathBuf::from("/nonexistent/libvulkan.so"))),
+            Err(VulkanError::LoaderUnavailable(_)) | Err(VulkanError::InitializationFailed(_))
+        ));
+    }
+
     #[test]
     fn test_initialize_vulkan() {
         // This may fail if Vulkan is not available
@@ -229,6 +689,18 @@ mod tests {
         let _ = result;
     }
 
+    #[test]
+    fn test_is_vulkan_supported_never_panics() {
+        // Result depends on the host; the probe itself must not panic
+        let _ = is_vulkan_supported();
+    }
+
+    #[test]
+    fn test_loader_error_display() {
+        let err = VulkanError::LoaderUnavailable("libvulkan.so".to_string());
+        assert!(err.to_string().contains("libvulkan.so"));
+    }
+
     #[test]
     fn test_get_context_before_init() {
         // Context should fail if not initialized
@@ -0,0 +1,307 @@
+//! Weight file format detection
+//!
+//! Model weights arrive as GGUF or safetensors files. Each header parser sits
+//! behind its own cargo feature (`loader-gguf`, `loader-safetensors`) so an
+//! app that ships a single format can compile the other out; detection
+//! itself is always available, so a file in a compiled-out format is reported
+//! as unsupported rather than unrecognised.
+//!
+//! Sparsified safetensors checkpoints list their sparse weights in
+//! `__metadata__` (see the `sparse` module); the header reports them so the
+//! caller can assemble each with `SparseMatrix::from_parts`.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use thiserror::Error;
+
+use crate::sparse::SparseLayout;
+
+/// Loader-related errors
+#[derive(Error, Debug)]
+pub enum LoaderError {
+    #[error("Unrecognised weight file format")]
+    UnknownFormat,
+
+    #[error("{} support is not compiled into this build (enable feature `{}`)", .0.name(), .0.feature())]
+    Unsupported(WeightFormat),
+
+    #[error("Malformed {format} header: {reason}")]
+    Malformed { format: &'static str, reason: String },
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+pub type LoaderResult<T> = Result<T, LoaderError>;
+
+/// Largest safetensors JSON header accepted (the format's own limit)
+pub const MAX_SAFETENSORS_HEADER: u64 = 100 * 1024 * 1024;
+
+/// Bytes needed to detect the format and read the fixed part of a header
+const PREFIX_LEN: usize = 24;
+
+/// On-disk weight format
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WeightFormat {
+    Gguf,
+    Safetensors,
+}
+
+impl WeightFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            WeightFormat::Gguf => "GGUF",
+            WeightFormat::Safetensors => "safetensors",
+        }
+    }
+
+    /// Cargo feature that enables this format
+    pub fn feature(self) -> &'static str {
+        match self {
+            WeightFormat::Gguf => "loader-gguf",
+            WeightFormat::Safetensors => "loader-safetensors",
+        }
+    }
+
+    /// Whether this build can parse the format
+    pub fn is_enabled(self) -> bool {
+        match self {
+            WeightFormat::Gguf => cfg!(feature = "loader-gguf"),
+            WeightFormat::Safetensors => cfg!(feature = "loader-safetensors"),
+        }
+    }
+
+    /// Identify a file from its first bytes
+    pub fn detect(prefix: &[u8]) -> Option<Self> {
+        if prefix.starts_with(b"GGUF") {
+            return Some(WeightFormat::Gguf);
+        }
+        // safetensors: u64 LE header length followed by a JSON object
+        match (prefix.get(..8), prefix.get(8)) {
+            (Some(len), Some(b'{')) if u64::from_le_bytes(len.try_into().ok()?) > 1 => {
+                Some(WeightFormat::Safetensors)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Summary of a weight file header
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WeightHeader {
+    pub format: WeightFormat,
+    /// GGUF version; 0 for safetensors
+    pub version: u32,
+    pub tensor_count: u64,
+    /// Bytes of metadata before the tensor data (safetensors) or metadata
+    /// key/value count (GGUF)
+    pub metadata: u64,
+    /// Sparse weights by name, sorted; always empty for GGUF
+    pub sparse: Vec<(String, SparseLayout)>,
+}
+
+/// Detect the format of `path` and parse its header
+pub fn read_header(path: &Path) -> LoaderResult<WeightHeader> {
+    let mut file = File::open(path)?;
+    let mut prefix = Vec::with_capacity(PREFIX_LEN);
+    (&mut file).take(PREFIX_LEN as u64).read_to_end(&mut prefix)?;
+
+    let format = WeightFormat::detect(&prefix).ok_or(LoaderError::UnknownFormat)?;
+    if !format.is_enabled() {
+        return Err(LoaderError::Unsupported(format));
+    }
+    match format {
+        #[cfg(feature = "loader-gguf")]
+        WeightFormat::Gguf => gguf::parse_header(&prefix),
+        #[cfg(feature = "loader-safetensors")]
+        WeightFormat::Safetensors => safetensors::parse_header(&prefix, &mut file),
+        #[allow(unreachable_patterns)]
+        _ => Err(LoaderError::Unsupported(format)),
+    }
+}
+
+#[cfg(feature = "loader-gguf")]
+mod gguf {
+    use super::{LoaderError, LoaderResult, WeightFormat, WeightHeader};
+
+    fn malformed(reason: &str) -> LoaderError {
+        LoaderError::Malformed {
+            format: "GGUF",
+            reason: reason.to_string(),
+        }
+    }
+
+    /// Parse `magic, version: u32, tensor_count: u64, kv_count: u64`
+    pub(super) fn parse_header(prefix: &[u8]) -> LoaderResult<WeightHeader> {
+        let field = |range: std::ops::Range<usize>| {
+            prefix.get(range).ok_or_else(|| malformed("truncated header"))
+        };
+        let version = u32::from_le_bytes(field(4..8)?.try_into().map_err(|_| malformed("version"))?);
+        if !(2..=3).contains(&version) {
+            return Err(malformed(&format!("unsupported version {}", version)));
+        }
+        Ok(WeightHeader {
+            format: WeightFormat::Gguf,
+            version,
+            tensor_count: u64::from_le_bytes(field(8..16)?.try_into().map_err(|_| malformed("tensor count"))?),
+            metadata: u64::from_le_bytes(field(16..24)?.try_into().map_err(|_| malformed("kv count"))?),
+            sparse: Vec::new(),
+        })
+    }
+}
+
+#[cfg(feature = "loader-safetensors")]
+mod safetensors {
+    use std::collections::HashMap;
+    use std::io::Read;
+
+    use serde::Deserialize;
+
+    use super::{LoaderError, LoaderResult, MAX_SAFETENSORS_HEADER, WeightFormat, WeightHeader};
+    use crate::sparse::{METADATA_PREFIX, SparseLayout};
+
+    /// The part of the JSON header `sparse_tensors` reads
+    #[derive(Deserialize)]
+    struct Metadata {
+        #[serde(rename = "__metadata__", default)]
+        entries: HashMap<String, String>,
+    }
+
+    fn malformed(reason: &str) -> LoaderError {
+        LoaderError::Malformed {
+            format: "safetensors",
+            reason: reason.to_string(),
+        }
+    }
+
+    /// Read the JSON header that follows the 8-byte length and count tensors
+    pub(super) fn parse_header(prefix: &[u8], rest: &mut impl Read) -> LoaderResult<WeightHeader> {
+        let len_bytes = prefix.get(..8).ok_or_else(|| malformed("truncated header"))?;
+        let header_len = u64::from_le_bytes(len_bytes.try_into().map_err(|_| malformed("length"))?);
+        if header_len > MAX_SAFETENSORS_HEADER {
+            return Err(malformed(&format!("header of {} bytes exceeds limit", header_len)));
+        }
+
+        let mut json = prefix.get(8..).unwrap_or_default().to_vec();
+        json.truncate(header_len as usize);
+        let remaining = header_len - json.len() as u64;
+        rest.by_ref().take(remaining).read_to_end(&mut json)?;
+        if json.len() as u64 != header_len {
+            return Err(malformed("truncated header"));
+        }
+
+        Ok(WeightHeader {
+            format: WeightFormat::Safetensors,
+            version: 0,
+            tensor_count: count_tensors(&json)?,
+            metadata: 8 + header_len,
+            sparse: sparse_tensors(&json)?,
+        })
+    }
+
+    /// Sparse weights declared by `exo.sparse.<name>` metadata entries
+    pub(super) fn sparse_tensors(json: &[u8]) -> LoaderResult<Vec<(String, SparseLayout)>> {
+        let metadata: Metadata = serde_json::from_slice(json).map_err(|e| malformed(&e.to_string()))?;
+        let mut sparse = metadata
+            .entries
+            .iter()
+            .filter_map(|(key, value)| Some((key.strip_prefix(METADATA_PREFIX)?, value)))
+            .map(|(name, value)| {
+                let layout = SparseLayout::parse(value).map_err(|e| malformed(&format!("{}: {}", name, e)))?;
+                Ok((name.to_string(), layout))
+            })
+            .collect::<LoaderResult<Vec<_>>>()?;
+        sparse.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        Ok(sparse)
+    }
+
+    /// Count tensor entries: objects at depth 2 carrying a `"dtype"` key
+    ///
+    /// A full JSON parser would be overkill for a header whose tensor entries
+    /// all look like `"name": {"dtype": ..., "shape": ..., ...}`.
+    pub(super) fn count_tensors(json: &[u8]) -> LoaderResult<u64> {
+        let mut depth = 0u32;
+        let mut count = 0;
+        let mut in_string = false;
+        let mut escaped = false;
+        let mut string_start = 0;
+        for (i, &byte) in json.iter().enumerate() {
+            if in_string {
+                match byte {
+                    _ if escaped => escaped = false,
+                    b'\\' => escaped = true,
+                    b'"' => {
+                        in_string = false;
+                        let is_key = json[i + 1..].iter().find(|b| !b.is_ascii_whitespace()) == Some(&b':');
+                        if depth == 2 && is_key && &json[string_start..i] == b"dtype" {
+                            count += 1;
+                        }
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            match byte {
+                b'"' => {
+                    in_string = true;
+                    string_start = i + 1;
+                }
+                b'{' | b'[' => depth += 1,
+                b'}' | b']' => depth = depth.checked_sub(1).ok_or_else(|| malformed("unbalanced JSON"))?,
+                _ => {}
+            }
+        }
+        if depth != 0 || in_string {
+            return Err(malformed("unbalanced JSON"));
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_formats() {
+        let mut gguf = b"GGUF".to_vec();
+        gguf.extend_from_slice(&3u32.to_le_bytes());
+        assert_eq!(WeightFormat::detect(&gguf), Some(WeightFormat::Gguf));
+
+        let mut st = 2u64.to_le_bytes().to_vec();
+        st.extend_from_slice(b"{}");
+        assert_eq!(WeightFormat::detect(&st), Some(WeightFormat::Safetensors));
+        assert_eq!(WeightFormat::detect(b"PK\x03\x04 not weights"), None);
+    }
+
+    #[cfg(feature = "loader-safetensors")]
+    #[test]
+    fn test_safetensors_header() {
+        let json = br#"{"__metadata__":{"format":"pt"},"a":{"dtype":"F16","shape":[2,2],"data_offsets":[0,8]},"b":{"dtype":"F32","shape":[1],"data_offsets":[8,12]}}"#;
+        let mut bytes = (json.len() as u64).to_le_bytes().to_vec();
+        bytes.extend_from_slice(json);
+        let (prefix, rest) = bytes.split_at(PREFIX_LEN);
+
+        let header = safetensors::parse_header(prefix, &mut &rest[..]).unwrap();
+        assert_eq!(header.tensor_count, 2);
+        assert_eq!(header.metadata, 8 + json.len() as u64);
+        assert!(header.sparse.is_empty());
+    }
+
+    #[cfg(feature = "loader-safetensors")]
+    #[test]
+    fn test_safetensors_sparse_metadata() {
+        use crate::sparse::SparseFormat;
+
+        let json = br#"{"__metadata__":{"format":"pt","exo.sparse.w":"2:4;8,4"},"w.values":{"dtype":"F32","shape":[4,4],"data_offsets":[0,64]},"w.index":{"dtype":"U32","shape":[1,4],"data_offsets":[64,80]}}"#;
+        let sparse = safetensors::sparse_tensors(json).unwrap();
+        assert_eq!(sparse.len(), 1);
+        assert_eq!(sparse[0].0, "w");
+        assert_eq!(sparse[0].1.format, SparseFormat::TwoFour);
+        assert_eq!((sparse[0].1.k, sparse[0].1.n), (8, 4));
+
+        assert!(safetensors::sparse_tensors(br#"{"__metadata__":{"exo.sparse.w":"csr"}}"#).is_err());
+    }
+}
@@ -0,0 +1,466 @@
+//! Audio and image preprocessing kernels
+//!
+//! Whisper-class speech models and VLM vision towers need their input in a
+//! model-specific form: a log-mel spectrogram, or a resized, normalized and
+//! often patchified image. Doing that on the host means a round trip of the
+//! raw input through CPU memory before the upload. These kernels take the
+//! raw samples or RGBA8 pixels already on the device and write the encoder
+//! input next to the model's other buffers.
+//!
+//! Each kernel has a host reference (`log_mel_host`, `preprocess_image_host`)
+//! computing the same thing in the same order, for tests and for devices
+//! without the kernels.
+
+use std::f32::consts::PI;
+
+use ash::vk;
+use thiserror::Error;
+
+use crate::memory::BufferRange;
+use crate::ops::{KernelBinding, MAX_GROUP_COUNT, OpsError, compute_barrier};
+use crate::tensor::ELEMENT_SIZE;
+
+/// Threads per workgroup of the media kernels
+pub const MEDIA_WORKGROUP_SIZE: u32 = 256;
+
+/// Media preprocessing errors
+#[derive(Error, Debug)]
+pub enum MediaError {
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(String),
+
+    #[error(transparent)]
+    Ops(#[from] OpsError),
+}
+
+pub type MediaResult<T> = Result<T, MediaError>;
+
+fn groups(count: u32) -> [u32; 3] {
+    let workgroups = count.div_ceil(MEDIA_WORKGROUP_SIZE).max(1);
+    [workgroups.min(MAX_GROUP_COUNT), workgroups.div_ceil(MAX_GROUP_COUNT), 1]
+}
+
+fn check_size(range: &BufferRange, values: u64) -> MediaResult<()> {
+    let needed = values * ELEMENT_SIZE;
+    if range.size < needed {
+        return Err(OpsError::ScratchTooSmall {
+            needed,
+            available: range.size,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+// ============ Log-mel spectrogram ============
+
+/// Short-time Fourier transform and mel filterbank parameters
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MelConfig {
+    pub sample_rate: u32,
+    pub n_fft: u32,
+    pub hop_length: u32,
+    pub n_mels: u32,
+}
+
+/// Whisper's front-end: 25 ms windows every 10 ms at 16 kHz, 80 mel bands
+pub const WHISPER_MEL: MelConfig = MelConfig {
+    sample_rate: 16_000,
+    n_fft: 400,
+    hop_length: 160,
+    n_mels: 80,
+};
+
+impl MelConfig {
+    /// Frequency bins of the one-sided spectrum
+    pub fn n_freq(&self) -> u32 {
+        self.n_fft / 2 + 1
+    }
+
+    /// Frames for `n_samples` of audio with centred, reflect-padded windows
+    pub fn n_frames(&self, n_samples: u32) -> u32 {
+        1 + n_samples / self.hop_length.max(1)
+    }
+
+    fn validate(&self, n_samples: u32) -> MediaResult<()> {
+        if self.n_fft < 2 || self.hop_length == 0 || self.n_mels == 0 || self.sample_rate == 0 {
+            return Err(MediaError::InvalidConfig(format!("{:?}", self)));
+        }
+        // Reflect padding mirrors at most n_fft / 2 samples from each end
+        if n_samples <= self.n_fft / 2 {
+            return Err(MediaError::InvalidConfig(format!(
+                "{} samples is shorter than half a {}-sample window",
+                n_samples, self.n_fft
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn hz_to_mel(hz: f32) -> f32 {
+    // Slaney scale: linear below 1 kHz, logarithmic above
+    let log_step = 6.4f32.ln() / 27.0;
+    if hz < 1000.0 { hz * 3.0 / 200.0 } else { 15.0 + (hz / 1000.0).ln() / log_step }
+}
+
+fn mel_to_hz(mel: f32) -> f32 {
+    let log_step = 6.4f32.ln() / 27.0;
+    if mel < 15.0 { mel * 200.0 / 3.0 } else { 1000.0 * ((mel - 15.0) * log_step).exp() }
+}
+
+/// Slaney-normalized triangular mel filters, `[n_mels, n_freq]` row-major
+///
+/// Matches librosa's default filterbank, which Whisper was trained with.
+pub fn mel_filterbank(config: &MelConfig) -> Vec<f32> {
+    let n_freq = config.n_freq() as usize;
+    let n_mels = config.n_mels as usize;
+    let nyquist = config.sample_rate as f32 / 2.0;
+    let max_mel = hz_to_mel(nyquist);
+    let points: Vec<f32> = (0..n_mels + 2)
+        .map(|i| mel_to_hz(max_mel * i as f32 / (n_mels + 1) as f32))
+        .collect();
+
+    let mut filters = vec![0.0; n_mels * n_freq];
+    for mel in 0..n_mels {
+        let (lower, centre, upper) = (points[mel], points[mel + 1], points[mel + 2]);
+        let norm = 2.0 / (upper - lower);
+        for bin in 0..n_freq {
+            let hz = bin as f32 * config.sample_rate as f32 / config.n_fft as f32;
+            let rising = (hz - lower) / (centre - lower);
+            let falling = (upper - hz) / (upper - centre);
+            filters[mel * n_freq + bin] = rising.min(falling).max(0.0) * norm;
+        }
+    }
+    filters
+}
+
+/// Host reference for `log_mel`: `[n_mels, n_frames]` of log10 mel power
+pub fn log_mel_host(samples: &[f32], config: &MelConfig, filters: &[f32]) -> MediaResult<Vec<f32>> {
+    config.validate(samples.len() as u32)?;
+    let n = samples.len() as i64;
+    let n_fft = config.n_fft as usize;
+    let n_freq = config.n_freq() as usize;
+    let n_frames = config.n_frames(samples.len() as u32) as usize;
+    if filters.len() < config.n_mels as usize * n_freq {
+        return Err(MediaError::InvalidConfig("filterbank too small".to_string()));
+    }
+
+    let mut power = vec![0.0f32; n_frames * n_freq];
+    for frame in 0..n_frames {
+        let start = (frame * config.hop_length as usize) as i64 - (n_fft / 2) as i64;
+        let windowed: Vec<f32> = (0..n_fft)
+            .map(|k| {
+                let mut j = start + k as i64;
+                j = if j < 0 { -j } else { j };
+                j = if j >= n { 2 * (n - 1) - j } else { j };
+                let window = 0.5 - 0.5 * (2.0 * PI * k as f32 / n_fft as f32).cos();
+                samples[j.clamp(0, n - 1) as usize] * window
+            })
+            .collect();
+        for bin in 0..n_freq {
+            let (mut re, mut im) = (0.0f32, 0.0f32);
+            for (k, x) in windowed.iter().enumerate() {
+                let angle = -2.0 * PI * ((bin * k) % n_fft) as f32 / n_fft as f32;
+                re += x * angle.cos();
+                im += x * angle.sin();
+            }
+            power[frame * n_freq + bin] = re * re + im * im;
+        }
+    }
+
+    let mut mel = vec![0.0; config.n_mels as usize * n_frames];
+    for (m, row) in mel.chunks_mut(n_frames).enumerate() {
+        let filter = &filters[m * n_freq..(m + 1) * n_freq];
+        for (frame, out) in row.iter_mut().enumerate() {
+            let spectrum = &power[frame * n_freq..(frame + 1) * n_freq];
+            let acc: f32 = filter.iter().zip(spectrum).map(|(w, p)| w * p).sum();
+            *out = acc.max(1e-10).log10();
+        }
+    }
+    Ok(mel)
+}
+
+/// Push constants shared by both mel passes
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MelPushConstants {
+    pub n_samples: u32,
+    pub n_fft: u32,
+    pub hop: u32,
+    pub n_freq: u32,
+    pub n_frames: u32,
+    pub n_mels: u32,
+    pub _pad: [u32; 2],
+}
+
+/// The two mel pipelines (`shaders/mel_spectrogram.comp` with `PASS` = 1 and 2)
+#[derive(Clone, Copy, Debug)]
+pub struct MelKernels {
+    pub power: KernelBinding,
+    pub filter: KernelBinding,
+}
+
+/// Record a log-mel spectrogram of `n_samples` f32 samples into `output`
+///
+/// `filters` holds `mel_filterbank(config)`; `power` is scratch for
+/// `n_frames * n_freq` floats. `output` receives `[n_mels, n_frames]`.
+///
+/// # Safety Requirements
+/// - `cmd` must be in the recording state
+/// - The kernels' descriptor sets must not be in use by pending work
+/// - All buffers must stay alive until the command buffer completes
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn log_mel(
+    device: &ash::Device,
+    cmd: vk::CommandBuffer,
+    kernels: &MelKernels,
+    config: &MelConfig,
+    samples: &BufferRange,
+    n_samples: u32,
+    filters: &BufferRange,
+    power: &BufferRange,
+    output: &BufferRange,
+) -> MediaResult<MelPushConstants> {
+    config.validate(n_samples)?;
+    let push = MelPushConstants {
+        n_samples,
+        n_fft: config.n_fft,
+        hop: config.hop_length,
+        n_freq: config.n_freq(),
+        n_frames: config.n_frames(n_samples),
+        n_mels: config.n_mels,
+        _pad: [0; 2],
+    };
+    check_size(samples, n_samples as u64)?;
+    check_size(filters, push.n_mels as u64 * push.n_freq as u64)?;
+    check_size(power, push.n_frames as u64 * push.n_freq as u64)?;
+    check_size(output, push.n_mels as u64 * push.n_frames as u64)?;
+
+    // SAFETY: forwarded from the caller's guarantees
+    unsafe {
+        kernels.power.bind_buffers(device, samples, power);
+        kernels.power.record(device, cmd, &push, groups(push.n_frames * push.n_freq));
+        compute_barrier(device, cmd);
+        kernels.filter.bind_buffers(device, power, output);
+        kernels.filter.bind_buffer(device, 2, filters);
+        kernels.filter.record(device, cmd, &push, groups(push.n_mels * push.n_frames));
+    }
+    Ok(push)
+}
+
+// ============ Image resize / normalize / patchify ============
+
+/// CLIP / SigLIP-style per-channel normalization constants
+pub const CLIP_MEAN: [f32; 3] = [0.481_454_66, 0.457_827_5, 0.408_210_73];
+pub const CLIP_STD: [f32; 3] = [0.268_629_55, 0.261_302_6, 0.275_777_1];
+
+/// Target geometry and normalization of a vision encoder's input
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImageConfig {
+    pub out_width: u32,
+    pub out_height: u32,
+    pub mean: [f32; 3],
+    pub std: [f32; 3],
+    /// Emit `[patches, 3, p, p]` instead of planar `[3, h, w]`
+    pub patch_size: Option<u32>,
+}
+
+impl ImageConfig {
+    /// Output floats per image
+    pub fn output_len(&self) -> u64 {
+        3 * self.out_width as u64 * self.out_height as u64
+    }
+
+    fn validate(&self, in_width: u32, in_height: u32) -> MediaResult<()> {
+        if in_width == 0 || in_height == 0 || self.out_width == 0 || self.out_height == 0 {
+            return Err(MediaError::InvalidConfig("image dimensions must be > 0".to_string()));
+        }
+        if self.std.iter().any(|&s| s == 0.0) {
+            return Err(MediaError::InvalidConfig("std must be non-zero".to_string()));
+        }
+        if let Some(patch) = self.patch_size
+            && (patch == 0 || self.out_width % patch != 0 || self.out_height % patch != 0)
+        {
+            return Err(MediaError::InvalidConfig(format!(
+                "{}x{} output is not a whole number of {}-pixel patches",
+                self.out_width, self.out_height, patch
+            )));
+        }
+        Ok(())
+    }
+
+    fn push_constants(&self, in_width: u32, in_height: u32) -> ImagePushConstants {
+        ImagePushConstants {
+            in_width,
+            in_height,
+            out_width: self.out_width,
+            out_height: self.out_height,
+            patch_size: self.patch_size.unwrap_or(1),
+            _pad: [0; 3],
+            mean: [self.mean[0], self.mean[1], self.mean[2], 0.0],
+            inv_std: [1.0 / self.std[0], 1.0 / self.std[1], 1.0 / self.std[2], 0.0],
+        }
+    }
+}
+
+/// Push constants of the image kernels
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ImagePushConstants {
+    pub in_width: u32,
+    pub in_height: u32,
+    pub out_width: u32,
+    pub out_height: u32,
+    pub patch_size: u32,
+    pub _pad: [u32; 3],
+    pub mean: [f32; 4],
+    pub inv_std: [f32; 4],
+}
+
+/// The image pipelines (`shaders/image_preprocess.comp` with `PATCHIFY` = 0 and 1)
+#[derive(Clone, Copy, Debug)]
+pub struct ImageKernels {
+    pub planar: KernelBinding,
+    pub patches: KernelBinding,
+}
+
+/// Output index of channel `c` at `(x, y)`, matching the kernel's layout
+fn output_index(config: &ImageConfig, c: usize, x: usize, y: usize) -> usize {
+    let (w, h) = (config.out_width as usize, config.out_height as usize);
+    match config.patch_size {
+        None => (c * h + y) * w + x,
+        Some(p) => {
+            let p = p as usize;
+            let patch = (y / p) * (w / p) + x / p;
+            ((patch * 3 + c) * p + y % p) * p + x % p
+        }
+    }
+}
+
+/// Host reference for `preprocess_image` over packed RGBA8 pixels
+pub fn preprocess_image_host(pixels: &[u32], in_width: u32, in_height: u32, config: &ImageConfig) -> MediaResult<Vec<f32>> {
+    config.validate(in_width, in_height)?;
+    let (iw, ih) = (in_width as usize, in_height as usize);
+    if pixels.len() < iw * ih {
+        return Err(MediaError::InvalidConfig(format!("{} pixels for a {}x{} image", pixels.len(), iw, ih)));
+    }
+    let push = config.push_constants(in_width, in_height);
+    let channel = |x: usize, y: usize, c: usize| ((pixels[y * iw + x] >> (c * 8)) & 0xff) as f32;
+
+    let mut out = vec![0.0; config.output_len() as usize];
+    for y in 0..config.out_height as usize {
+        let sy = ((y as f32 + 0.5) * ih as f32 / config.out_height as f32 - 0.5).clamp(0.0, (ih - 1) as f32);
+        let (y0, fy) = (sy as usize, sy.fract());
+        let y1 = (y0 + 1).min(ih - 1);
+        for x in 0..config.out_width as usize {
+            let sx = ((x as f32 + 0.5) * iw as f32 / config.out_width as f32 - 0.5).clamp(0.0, (iw - 1) as f32);
+            let (x0, fx) = (sx as usize, sx.fract());
+            let x1 = (x0 + 1).min(iw - 1);
+            for c in 0..3 {
+                let top = channel(x0, y0, c) * (1.0 - fx) + channel(x1, y0, c) * fx;
+                let bottom = channel(x0, y1, c) * (1.0 - fx) + channel(x1, y1, c) * fx;
+                let value = (top * (1.0 - fy) + bottom * fy) / 255.0;
+                out[output_index(config, c, x, y)] = (value - push.mean[c]) * push.inv_std[c];
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Record resize + normalize (+ patchify) of packed RGBA8 `pixels` into `output`
+///
+/// # Safety Requirements
+/// - `cmd` must be in the recording state
+/// - The kernel's descriptor set must not be in use by pending work
+/// - All buffers must stay alive until the command buffer completes
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn preprocess_image(
+    device: &ash::Device,
+    cmd: vk::CommandBuffer,
+    kernels: &ImageKernels,
+    config: &ImageConfig,
+    pixels: &BufferRange,
+    in_width: u32,
+    in_height: u32,
+    output: &BufferRange,
+) -> MediaResult<ImagePushConstants> {
+    config.validate(in_width, in_height)?;
+    check_size(pixels, in_width as u64 * in_height as u64)?;
+    check_size(output, config.output_len())?;
+    let push = config.push_constants(in_width, in_height);
+    let kernel = if config.patch_size.is_some() { &kernels.patches } else { &kernels.planar };
+
+    // SAFETY: forwarded from the caller's guarantees
+    unsafe {
+        kernel.bind_buffers(device, pixels, output);
+        kernel.record(device, cmd, &push, groups(config.output_len() as u32));
+    }
+    Ok(push)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_mel_peaks_at_tone() {
+        let config = MelConfig {
+            sample_rate: 8000,
+            n_fft: 64,
+            hop_length: 32,
+            n_mels: 16,
+        };
+        let filters = mel_filterbank(&config);
+        assert!(filters.iter().all(|&w| w >= 0.0));
+        assert!(filters.chunks(config.n_freq() as usize).all(|f| f.iter().any(|&w| w > 0.0)));
+
+        let tone = 1000.0;
+        let samples: Vec<f32> = (0..256)
+            .map(|i| (2.0 * PI * tone * i as f32 / config.sample_rate as f32).sin())
+            .collect();
+        let mel = log_mel_host(&samples, &config, &filters).unwrap();
+        let frames = config.n_frames(256) as usize;
+        assert_eq!(mel.len(), 16 * frames);
+
+        let frame = frames / 2;
+        let loudest = (0..16)
+            .max_by(|&a, &b| mel[a * frames + frame].total_cmp(&mel[b * frames + frame]))
+            .unwrap();
+        let (lower, upper) = (
+            mel_to_hz(hz_to_mel(4000.0) * loudest as f32 / 17.0),
+            mel_to_hz(hz_to_mel(4000.0) * (loudest + 2) as f32 / 17.0),
+        );
+        assert!(lower < tone && tone < upper, "band {} covers {}..{}", loudest, lower, upper);
+        assert!(log_mel_host(&samples[..16], &config, &filters).is_err());
+    }
+
+    #[test]
+    fn test_image_patch_layout_matches_planar() {
+        let pixels: Vec<u32> = (0..16u32).map(|i| i * 16 | (255 - i * 16) << 8 | 128 << 16).collect();
+        let planar = ImageConfig {
+            out_width: 4,
+            out_height: 4,
+            mean: [0.5; 3],
+            std: [0.5; 3],
+            patch_size: None,
+        };
+        let patched = ImageConfig {
+            patch_size: Some(2),
+            ..planar
+        };
+        let a = preprocess_image_host(&pixels, 4, 4, &planar).unwrap();
+        let b = preprocess_image_host(&pixels, 4, 4, &patched).unwrap();
+        assert_eq!(a[0], 2.0 * (0.0 / 255.0 - 0.5));
+        assert_eq!(a[16 + 5], 2.0 * ((255.0 - 80.0) / 255.0 - 0.5));
+        for c in 0..3 {
+            for y in 0..4 {
+                for x in 0..4 {
+                    assert_eq!(a[output_index(&planar, c, x, y)], b[output_index(&patched, c, x, y)]);
+                }
+            }
+        }
+        // Second patch (top right) starts with pixel (2, 0)
+        assert_eq!(b[12], a[2]);
+        assert!(preprocess_image_host(&pixels, 4, 4, &ImageConfig { patch_size: Some(3), ..planar }).is_err());
+    }
+}
@@ -3,9 +3,15 @@
 //! Provides safe abstractions over Vulkan device memory allocation, mapping, and deallocation.
 //! All unsafe operations are documented with SAFETY comments explaining invariants.
 
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
 use ash::vk;
 use thiserror::Error;
 
+use crate::events::{self, GpuEvent};
+use crate::transfer::DataTransfer;
+
 /// Memory-related errors
 #[derive(Error, Debug)]
 pub enum MemoryError {
@@ -21,20 +27,364 @@ pub enum MemoryError {
     #[error("Invalid memory type: {0}")]
     InvalidMemoryType(String),
 
+    #[error("Allocation {0} still has {1} outstanding mapping(s)")]
+    MappingOutstanding(String, usize),
+
+    #[error("Stale mapping: allocation {0} was freed (generation {1})")]
+    StaleMapping(String, u64),
+
+    #[error("External memory error: {0}")]
+    ExternalMemory(String),
+
     #[error("Vulkan error: {0:?}")]
     VulkanError(vk::Result),
 }
 
 pub type MemoryResult<T> = Result<T, MemoryError>;
 
+/// A `(buffer, offset, size)` range that kernels and copies operate on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BufferRange {
+    pub buffer: vk::Buffer,
+    pub offset: vk::DeviceSize,
+    pub size: vk::DeviceSize,
+}
+
+impl BufferRange {
+    /// Descriptor info for binding this range as a storage buffer
+    pub fn descriptor_info(&self) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo::default()
+            .buffer(self.buffer)
+            .offset(self.offset)
+            .range(self.size)
+    }
+
+    /// Sub-range starting `offset` bytes into this range
+    pub fn slice(&self, offset: vk::DeviceSize, size: vk::DeviceSize) -> MemoryResult<Self> {
+        let end = offset
+            .checked_add(size)
+            .ok_or_else(|| MemoryError::AllocationFailed("range overflow".to_string()))?;
+        if end > self.size {
+            return Err(MemoryError::AllocationFailed(format!(
+                "slice {}..{} exceeds range size {}",
+                offset, end, self.size
+            )));
+        }
+        Ok(Self {
+            buffer: self.buffer,
+            offset: self.offset + offset,
+            size,
+        })
+    }
+}
+
+/// Liveness shared between an allocation and the `MappedSlice`s into it
+#[derive(Debug)]
+pub struct Liveness {
+    /// Generation of the live allocation, or 0 once freed
+    generation: AtomicU64,
+    /// Number of `MappedSlice`s currently alive
+    outstanding_maps: AtomicUsize,
+}
+
+impl Liveness {
+    pub(crate) fn new(generation: u64) -> Self {
+        Self {
+            generation: AtomicU64::new(generation),
+            outstanding_maps: AtomicUsize::new(0),
+        }
+    }
+
+    fn is_live(&self, generation: u64) -> bool {
+        self.generation.load(Ordering::Acquire) == generation
+    }
+
+    fn invalidate(&self) {
+        self.generation.store(0, Ordering::Release);
+    }
+
+    fn outstanding(&self) -> usize {
+        self.outstanding_maps.load(Ordering::Acquire)
+    }
+}
+
+/// Host view of a mapped allocation
+///
+/// Keeps the allocation from being unmapped or freed while alive. Debug builds
+/// additionally check the allocation's generation on every access, turning a
+/// use-after-free into a `StaleMapping` error.
+#[derive(Debug)]
+pub struct MappedSlice {
+    ptr: *mut u8,
+    len: usize,
+    handle_id: String,
+    generation: u64,
+    liveness: Arc<Liveness>,
+}
+
+impl MappedSlice {
+    fn new(ptr: *mut u8, len: usize, handle_id: String, generation: u64, liveness: Arc<Liveness>) -> Self {
+        liveness.outstanding_maps.fetch_add(1, Ordering::AcqRel);
+        Self {
+            ptr,
+            len,
+            handle_id,
+            generation,
+            liveness,
+        }
+    }
+
+    fn check_live(&self) -> MemoryResult<()> {
+        if cfg!(debug_assertions) && !self.liveness.is_live(self.generation) {
+            return Err(MemoryError::StaleMapping(self.handle_id.clone(), self.generation));
+        }
+        Ok(())
+    }
+
+    /// Mapped bytes as a shared slice
+    pub fn as_slice(&self) -> MemoryResult<&[u8]> {
+        self.check_live()?;
+        // SAFETY:
+        //   - ptr/len describe the mapped range of a live allocation
+        //   - the allocation cannot be unmapped or freed while self exists
+        Ok(unsafe { std::slice::from_raw_parts(self.ptr, self.len) })
+    }
+
+    /// Mapped bytes as a mutable slice
+    pub fn as_mut_slice(&mut self) -> MemoryResult<&mut [u8]> {
+        self.check_live()?;
+        // SAFETY: as in `as_slice`; &mut self guarantees exclusive access via this view
+        Ok(unsafe { std::slice::from_raw_parts_mut(self.ptr, self.len) })
+    }
+
+    /// Raw mapped pointer, checked for liveness first
+    pub fn as_ptr(&self) -> MemoryResult<*mut u8> {
+        self.check_live()?;
+        Ok(self.ptr)
+    }
+
+    /// Length of the mapped range in bytes
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the mapped range is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Generation of the allocation this view was created from
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+}
+
+impl Drop for MappedSlice {
+    fn drop(&mut self) {
+        self.liveness.outstanding_maps.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
 /// Information about a single memory allocation
 #[derive(Clone, Debug)]
 pub struct AllocationInfo {
     pub handle_id: String,
     pub size: u64,
+    /// Bytes of backing buffer; exceeds `size` after a shrinking `resize`
+    pub capacity: u64,
     pub device_memory: vk::DeviceMemory,
     pub buffer: vk::Buffer,
+    /// Byte offset of this allocation within `buffer` and `device_memory`
+    pub offset: vk::DeviceSize,
     pub mapped_ptr: Option<*mut u8>,
+    /// Property flags of the memory type backing this allocation
+    pub memory_properties: vk::MemoryPropertyFlags,
+    /// Generation tag; unique per allocator, never reused for a handle
+    pub generation: u64,
+    /// Liveness shared with outstanding `MappedSlice`s
+    pub liveness: Arc<Liveness>,
+}
+
+impl AllocationInfo {
+    /// Whether the allocation this info describes still exists
+    pub fn is_live(&self) -> bool {
+        self.liveness.is_live(self.generation)
+    }
+
+    /// The buffer range backing this allocation
+    pub fn range(&self) -> BufferRange {
+        BufferRange {
+            buffer: self.buffer,
+            offset: self.offset,
+            size: self.size,
+        }
+    }
+
+    /// View of `size` bytes starting `offset` bytes into this allocation
+    ///
+    /// The view shares the allocation's handle and liveness; it must not be
+    /// freed on its own.
+    pub fn view(&self, offset: vk::DeviceSize, size: vk::DeviceSize) -> MemoryResult<Self> {
+        let range = self.range().slice(offset, size)?;
+        Ok(Self {
+            size,
+            offset: range.offset,
+            mapped_ptr: self.mapped_ptr.map(|ptr| ptr.wrapping_add(offset as usize)),
+            ..self.clone()
+        })
+    }
+}
+
+/// One allocation of an `allocate_many` batch
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AllocationSpec {
+    pub handle_id: String,
+    pub size: u64,
+    pub memory_type_index: u32,
+}
+
+/// Offset alignment of allocations packed into a slab
+///
+/// 256 is the largest `minStorageBufferOffsetAlignment` Vulkan allows, so
+/// every suballocation can be bound as a storage buffer on any device.
+pub const SLAB_ALIGNMENT: u64 = 256;
+
+/// Device memory `allocate` sub-allocates small allocations from
+pub const BLOCK_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Largest allocation `allocate` places in a block; larger ones get their own memory
+pub const MAX_SUBALLOCATION: u64 = BLOCK_SIZE / 4;
+
+/// Free ranges of a sub-allocation block, coalesced as they are freed
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct FreeList {
+    /// Offset → length
+    ranges: std::collections::BTreeMap<u64, u64>,
+}
+
+impl FreeList {
+    fn new(size: u64) -> Self {
+        Self {
+            ranges: std::collections::BTreeMap::from([(0, size)]),
+        }
+    }
+
+    /// First-fit offset of `size` bytes
+    fn allocate(&mut self, size: u64) -> Option<u64> {
+        let (&offset, &len) = self.ranges.iter().find(|(_, len)| **len >= size)?;
+        self.ranges.remove(&offset);
+        if len > size {
+            self.ranges.insert(offset + size, len - size);
+        }
+        Some(offset)
+    }
+
+    /// Return `size` bytes at `offset`, merging them with free neighbours
+    fn release(&mut self, offset: u64, size: u64) {
+        let (mut start, mut end) = (offset, offset + size);
+        if let Some((&prev, &len)) = self.ranges.range(..offset).next_back()
+            && prev + len == offset
+        {
+            self.ranges.remove(&prev);
+            start = prev;
+        }
+        if let Some(len) = self.ranges.remove(&end) {
+            end += len;
+        }
+        self.ranges.insert(start, end - start);
+    }
+
+    /// Return the part of a `capacity`-byte member at `offset` past its
+    /// first `size` bytes, keeping the member's span aligned
+    fn release_tail(&mut self, offset: u64, capacity: u64, size: u64) {
+        let held = capacity.next_multiple_of(SLAB_ALIGNMENT);
+        let kept = size.next_multiple_of(SLAB_ALIGNMENT);
+        if kept < held {
+            self.release(offset + kept, held - kept);
+        }
+    }
+}
+
+/// Device memory shared by several allocations: an `allocate_many` slab or
+/// a block `allocate` sub-allocates from
+struct Slab {
+    buffer: vk::Buffer,
+    /// Base of the persistent mapping, if the memory is host visible
+    mapped_ptr: Option<*mut u8>,
+    property_flags: vk::MemoryPropertyFlags,
+    /// Memory type the members were requested with
+    memory_type_index: u32,
+    /// Allocations still using the slab
+    live: usize,
+    /// Free space of a block; slabs never reuse space
+    free: Option<FreeList>,
+}
+
+/// Handle type used to share device memory between processes
+pub const EXTERNAL_HANDLE_TYPE: vk::ExternalMemoryHandleTypeFlags = vk::ExternalMemoryHandleTypeFlags::OPAQUE_FD;
+
+/// Device memory exported as a file descriptor
+///
+/// Importing an opaque FD requires the exporter's allocation size and memory
+/// type, so both travel with it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExternalMemory {
+    pub fd: i32,
+    pub allocation_size: vk::DeviceSize,
+    pub memory_type_index: u32,
+}
+
+/// What memory is used for, which decides its memory type
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryUsage {
+    /// Mapped by the host to fill or read copies; must be host coherent
+    Staging,
+    /// Read and written by kernels
+    DeviceLocal,
+}
+
+impl MemoryUsage {
+    /// `(required, avoided)` property sets to try, best first
+    fn preferences(self) -> &'static [(vk::MemoryPropertyFlags, vk::MemoryPropertyFlags)] {
+        const HOST: vk::MemoryPropertyFlags =
+            vk::MemoryPropertyFlags::from_raw(vk::MemoryPropertyFlags::HOST_VISIBLE.as_raw() | vk::MemoryPropertyFlags::HOST_COHERENT.as_raw());
+        const LOCAL: vk::MemoryPropertyFlags = vk::MemoryPropertyFlags::DEVICE_LOCAL;
+        const NONE: vk::MemoryPropertyFlags = vk::MemoryPropertyFlags::empty();
+        match self {
+            // System memory first, sparing the small host-visible VRAM window
+            // of discrete GPUs; on UMA devices every type is device local
+            MemoryUsage::Staging => &[(HOST, LOCAL), (HOST, NONE)],
+            // VRAM first; UMA devices make every local type host visible, and
+            // devices without a local type still need some memory
+            MemoryUsage::DeviceLocal => &[
+                (LOCAL, vk::MemoryPropertyFlags::HOST_VISIBLE),
+                (LOCAL, NONE),
+                (NONE, NONE),
+            ],
+        }
+    }
+}
+
+/// Memory type for `usage` among the types allowed by `type_bits`
+///
+/// `type_bits` is `vk::MemoryRequirements::memory_type_bits`, or `u32::MAX`
+/// before a resource exists. Vulkan lists types best first, so the first
+/// match of each preference wins.
+pub fn find_memory_type(
+    properties: &vk::PhysicalDeviceMemoryProperties,
+    type_bits: u32,
+    usage: MemoryUsage,
+) -> Option<u32> {
+    let types = &properties.memory_types[..properties.memory_type_count as usize];
+    usage.preferences().iter().find_map(|&(required, avoided)| {
+        types
+            .iter()
+            .enumerate()
+            .position(|(i, t)| {
+                type_bits & (1 << i) != 0 && t.property_flags.contains(required) && !t.property_flags.intersects(avoided)
+            })
+            .map(|i| i as u32)
+    })
 }
 
 /// Manages Vulkan device memory allocations
@@ -42,6 +392,13 @@ pub struct MemoryAllocator {
     device: ash::Device,
     physical_device_memory_properties: vk::PhysicalDeviceMemoryProperties,
     allocations: std::collections::HashMap<String, AllocationInfo>,
+    /// Slabs and blocks by their device memory; the memory is freed with the last member
+    slabs: std::collections::HashMap<vk::DeviceMemory, Slab>,
+    /// Allocation size and memory type of memory that can be exported
+    external: std::collections::HashMap<vk::DeviceMemory, (vk::DeviceSize, u32)>,
+    /// Whether new allocations are created exportable
+    exportable: bool,
+    next_generation: u64,
 }
 
 impl MemoryAllocator {
@@ -58,12 +415,224 @@ impl MemoryAllocator {
             device,
             physical_device_memory_properties: memory_properties,
             allocations: std::collections::HashMap::new(),
+            slabs: std::collections::HashMap::new(),
+            external: std::collections::HashMap::new(),
+            exportable: false,
+            next_generation: 1,
+        }
+    }
+
+    /// Memory type to pass to `allocate` for `usage`
+    pub fn memory_type_for(&self, usage: MemoryUsage) -> MemoryResult<u32> {
+        find_memory_type(&self.physical_device_memory_properties, u32::MAX, usage)
+            .ok_or_else(|| MemoryError::InvalidMemoryType(format!("No memory type for {:?}", usage)))
+    }
+
+    /// Create later allocations so their memory can be exported with `export_fd`
+    ///
+    /// The device must have been created with `VK_KHR_external_memory_fd`.
+    /// Slab members from `allocate_many` are never exportable.
+    pub fn set_exportable(&mut self, exportable: bool) {
+        self.exportable = exportable;
+    }
+
+    /// Export the memory of an allocation as an opaque file descriptor
+    ///
+    /// The caller owns the returned FD. It stays valid after this process
+    /// frees the allocation or exits, which is what lets another process
+    /// import it.
+    ///
+    /// # Arguments
+    /// * `handle_id` - Allocation made while `set_exportable(true)`
+    /// * `external_memory_fd` - Extension loader for this allocator's device
+    pub fn export_fd(
+        &self,
+        handle_id: &str,
+        external_memory_fd: &ash::khr::external_memory_fd::Device,
+    ) -> MemoryResult<ExternalMemory> {
+        let allocation = self.get_allocation(handle_id)?;
+        let &(allocation_size, memory_type_index) = self
+            .external
+            .get(&allocation.device_memory)
+            .ok_or_else(|| MemoryError::ExternalMemory(format!("{} was not allocated exportable", handle_id)))?;
+        let info = vk::MemoryGetFdInfoKHR::default()
+            .memory(allocation.device_memory)
+            .handle_type(EXTERNAL_HANDLE_TYPE);
+        // SAFETY: the memory was allocated on this device with EXTERNAL_HANDLE_TYPE exportable
+        let fd = unsafe { external_memory_fd.get_memory_fd(&info) }.map_err(MemoryError::VulkanError)?;
+        Ok(ExternalMemory {
+            fd,
+            allocation_size,
+            memory_type_index,
+        })
+    }
+
+    /// Register memory exported by another process under `handle_id`
+    ///
+    /// On success the allocator owns `memory.fd`; on failure the caller still
+    /// does. The exporter must have used the same device and driver (equal
+    /// `deviceUUID` and `driverUUID`).
+    ///
+    /// # Arguments
+    /// * `handle_id` - Unique identifier for the imported allocation
+    /// * `size` - Bytes of the allocation as the exporter used it
+    /// * `memory` - FD, allocation size and memory type from `export_fd`
+    pub fn import_fd(&mut self, handle_id: String, size: u64, memory: ExternalMemory) -> MemoryResult<String> {
+        if self.allocations.contains_key(&handle_id) {
+            return Err(MemoryError::AllocationFailed(format!("duplicate handle {}", handle_id)));
+        }
+        if size > memory.allocation_size {
+            return Err(MemoryError::ExternalMemory(format!(
+                "{}: size {} exceeds imported allocation of {}",
+                handle_id, size, memory.allocation_size
+            )));
         }
+        let allocation = self.create_external(size, memory.memory_type_index, handle_id.clone(), Some(memory))?;
+        self.allocations.insert(handle_id.clone(), allocation);
+        events::emit(GpuEvent::Allocated {
+            handle_id: handle_id.clone(),
+            size,
+        });
+        Ok(handle_id)
+    }
+
+    /// Register an Android `AHardwareBuffer` as an allocation, without copying
+    ///
+    /// Camera, MediaCodec and NNAPI buffers are then read and written by
+    /// kernels in place. Only `AHARDWAREBUFFER_FORMAT_BLOB` buffers can back
+    /// a Vulkan buffer. Vulkan takes its own reference to the hardware
+    /// buffer, so the caller may release theirs once this returns; the
+    /// reference is dropped when the allocation is freed. To hand GPU
+    /// results to another API, create the hardware buffer on that side and
+    /// import it here: the memory is shared both ways.
+    ///
+    /// The device must have been created with
+    /// `VK_ANDROID_external_memory_android_hardware_buffer`.
+    ///
+    /// # Safety Requirements
+    /// - `hardware_buffer` must point to a live `AHardwareBuffer`
+    /// - `loader` must belong to this allocator's device
+    ///
+    /// # Arguments
+    /// * `handle_id` - Unique identifier for the imported allocation
+    /// * `size` - Bytes of the hardware buffer to expose (its width, for BLOB buffers)
+    /// * `hardware_buffer` - Buffer to import
+    /// * `loader` - Extension loader for this allocator's device
+    pub unsafe fn import_ahardware_buffer(
+        &mut self,
+        handle_id: String,
+        size: u64,
+        hardware_buffer: *mut vk::AHardwareBuffer,
+        loader: &ash::android::external_memory_android_hardware_buffer::Device,
+    ) -> MemoryResult<String> {
+        if self.allocations.contains_key(&handle_id) {
+            return Err(MemoryError::AllocationFailed(format!("duplicate handle {}", handle_id)));
+        }
+        if size == 0 || hardware_buffer.is_null() {
+            return Err(MemoryError::ExternalMemory(format!(
+                "{}: hardware buffer must be non-null with size > 0",
+                handle_id
+            )));
+        }
+
+        let mut properties = vk::AndroidHardwareBufferPropertiesANDROID::default();
+        // SAFETY: the caller guarantees the hardware buffer is live and the loader is ours
+        unsafe { loader.get_android_hardware_buffer_properties(hardware_buffer, &mut properties) }
+            .map_err(MemoryError::VulkanError)?;
+        if size > properties.allocation_size {
+            return Err(MemoryError::ExternalMemory(format!(
+                "{}: size {} exceeds hardware buffer of {}",
+                handle_id, size, properties.allocation_size
+            )));
+        }
+
+        let mut external_info = vk::ExternalMemoryBufferCreateInfo::default()
+            .handle_types(vk::ExternalMemoryHandleTypeFlags::ANDROID_HARDWARE_BUFFER_ANDROID);
+        let buffer_info = vk::BufferCreateInfo::default()
+            .size(size)
+            .usage(
+                vk::BufferUsageFlags::TRANSFER_DST
+                    | vk::BufferUsageFlags::TRANSFER_SRC
+                    | vk::BufferUsageFlags::STORAGE_BUFFER,
+            )
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .push_next(&mut external_info);
+        // SAFETY: device is valid for the allocator's lifetime and size is checked above
+        let buffer = unsafe { self.device.create_buffer(&buffer_info, None) }.map_err(MemoryError::VulkanError)?;
+        // SAFETY: buffer was just created on this device
+        let requirements = unsafe { self.device.get_buffer_memory_requirements(buffer) };
+        let type_bits = properties.memory_type_bits & requirements.memory_type_bits;
+        let memory_type_index = find_memory_type(&self.physical_device_memory_properties, type_bits, MemoryUsage::DeviceLocal)
+            .or_else(|| (type_bits != 0).then(|| type_bits.trailing_zeros()));
+        let Some(memory_type_index) = memory_type_index else {
+            // SAFETY: buffer is unused and owned here
+            unsafe { self.device.destroy_buffer(buffer, None) };
+            return Err(MemoryError::ExternalMemory(format!(
+                "{}: no memory type can hold both the buffer and the hardware buffer",
+                handle_id
+            )));
+        };
+
+        // Imports must use the hardware buffer's own allocation size
+        let mut import_info = vk::ImportAndroidHardwareBufferInfoANDROID::default().buffer(hardware_buffer);
+        let alloc_info = vk::MemoryAllocateInfo::default()
+            .allocation_size(properties.allocation_size)
+            .memory_type_index(memory_type_index)
+            .push_next(&mut import_info);
+        // SAFETY: the memory type was taken from the hardware buffer's own properties
+        let device_memory = match unsafe { self.device.allocate_memory(&alloc_info, None) } {
+            Ok(memory) => memory,
+            Err(e) => {
+                // SAFETY: buffer is unused and owned here
+                unsafe { self.device.destroy_buffer(buffer, None) };
+                return Err(MemoryError::VulkanError(e));
+            }
+        };
+        // SAFETY: buffer and memory are fresh, compatible and the memory covers `size`
+        if let Err(e) = unsafe { self.device.bind_buffer_memory(buffer, device_memory, 0) } {
+            // SAFETY: neither object is used by anything else yet
+            unsafe {
+                self.device.free_memory(device_memory, None);
+                self.device.destroy_buffer(buffer, None);
+            }
+            return Err(MemoryError::VulkanError(e));
+        }
+
+        let generation = self.next_generation;
+        self.next_generation += 1;
+        let memory_properties =
+            self.physical_device_memory_properties.memory_types[memory_type_index as usize].property_flags;
+        self.allocations.insert(
+            handle_id.clone(),
+            AllocationInfo {
+                handle_id: handle_id.clone(),
+                size,
+                capacity: size,
+                device_memory,
+                buffer,
+                offset: 0,
+                mapped_ptr: None,
+                memory_properties,
+                generation,
+                liveness: Arc::new(Liveness::new(generation)),
+            },
+        );
+        events::emit(GpuEvent::Allocated {
+            handle_id: handle_id.clone(),
+            size,
+        });
+        Ok(handle_id)
     }
 
     /// Allocate device memory with a backing buffer
     ///
-    /// Creates a buffer and allocates device memory for it.
+    /// Allocations up to `MAX_SUBALLOCATION` are placed at `SLAB_ALIGNMENT`
+    /// offsets in shared `BLOCK_SIZE` blocks, one buffer and one
+    /// `vkAllocateMemory` per block, so loading thousands of tensors stays
+    /// far below `maxMemoryAllocationCount`. Freed ranges merge with their
+    /// free neighbours and are reused; a block is returned to the driver
+    /// when its last allocation is freed. Larger and exportable allocations
+    /// get their own buffer and memory.
     ///
     /// # Safety Requirements
     /// - device handle must be valid and not destroyed during lifetime of returned allocation
@@ -83,6 +652,253 @@ impl MemoryAllocator {
         memory_type_index: u32,
         handle_id: String,
     ) -> MemoryResult<String> {
+        let allocation = if self.exportable || size == 0 || size > MAX_SUBALLOCATION {
+            self.create(size, memory_type_index, handle_id.clone())?
+        } else {
+            self.suballocate(size, memory_type_index, handle_id.clone())?
+        };
+        self.allocations.insert(handle_id.clone(), allocation);
+        events::emit(GpuEvent::Allocated {
+            handle_id: handle_id.clone(),
+            size,
+        });
+        Ok(handle_id)
+    }
+
+    /// Allocate a batch of buffers in one pass
+    ///
+    /// Every spec is validated before anything is allocated, and a failure
+    /// part-way frees what the batch already created, so the batch either
+    /// fully succeeds or leaves the allocator unchanged.
+    ///
+    /// With `slab`, specs sharing a memory type are packed at
+    /// `SLAB_ALIGNMENT` offsets into one buffer backed by a single
+    /// `vkAllocateMemory`, which matters when building graphs of thousands
+    /// of small tensors. Host-visible slabs are mapped once for all members.
+    /// Slab members are freed individually but the memory is returned only
+    /// when the last one goes; resizing a member past its size moves it out
+    /// into its own allocation.
+    ///
+    /// # Returns
+    /// Handle IDs in spec order
+    pub fn allocate_many(&mut self, specs: &[AllocationSpec], slab: bool) -> MemoryResult<Vec<String>> {
+        let mut seen = std::collections::HashSet::new();
+        for spec in specs {
+            if spec.size == 0 {
+                return Err(MemoryError::AllocationFailed(format!("{}: size must be > 0", spec.handle_id)));
+            }
+            if spec.memory_type_index >= self.physical_device_memory_properties.memory_type_count {
+                return Err(MemoryError::InvalidMemoryType(format!(
+                    "memory_type_index {} >= memory type count {}",
+                    spec.memory_type_index, self.physical_device_memory_properties.memory_type_count
+                )));
+            }
+            if self.allocations.contains_key(&spec.handle_id) || !seen.insert(spec.handle_id.as_str()) {
+                return Err(MemoryError::AllocationFailed(format!("duplicate handle {}", spec.handle_id)));
+            }
+        }
+
+        let mut created: Vec<AllocationInfo> = Vec::with_capacity(specs.len());
+        let result = if slab {
+            let mut groups: std::collections::BTreeMap<u32, Vec<&AllocationSpec>> = std::collections::BTreeMap::new();
+            for spec in specs {
+                groups.entry(spec.memory_type_index).or_default().push(spec);
+            }
+            groups
+                .into_iter()
+                .try_for_each(|(memory_type_index, group)| {
+                    created.extend(self.create_slab(&group, memory_type_index)?);
+                    Ok(())
+                })
+        } else {
+            specs.iter().try_for_each(|spec| {
+                created.push(self.create(spec.size, spec.memory_type_index, spec.handle_id.clone())?);
+                Ok(())
+            })
+        };
+        if let Err(e) = result {
+            for allocation in &created {
+                self.destroy(allocation);
+            }
+            return Err(e);
+        }
+
+        for allocation in created {
+            events::emit(GpuEvent::Allocated {
+                handle_id: allocation.handle_id.clone(),
+                size: allocation.size,
+            });
+            self.allocations.insert(allocation.handle_id.clone(), allocation);
+        }
+        Ok(specs.iter().map(|spec| spec.handle_id.clone()).collect())
+    }
+
+    /// Create one buffer and memory holding every spec of `group`
+    fn create_slab(&mut self, group: &[&AllocationSpec], memory_type_index: u32) -> MemoryResult<Vec<AllocationInfo>> {
+        let offsets = slab_offsets(group.iter().map(|spec| spec.size));
+        let total = offsets.last().zip(group.last()).map(|(o, s)| o + s.size).unwrap_or(0);
+        let device_memory = self.create_shared(total, memory_type_index, group.len(), None)?;
+        Ok(group
+            .iter()
+            .zip(offsets)
+            .map(|(spec, offset)| self.member(device_memory, spec.handle_id.clone(), spec.size, offset))
+            .collect())
+    }
+
+    /// Place an allocation in a block of `memory_type_index`, creating a
+    /// block if none has room
+    fn suballocate(&mut self, size: u64, memory_type_index: u32, handle_id: String) -> MemoryResult<AllocationInfo> {
+        if memory_type_index >= self.physical_device_memory_properties.memory_type_count {
+            return Err(MemoryError::InvalidMemoryType(format!(
+                "memory_type_index {} >= memory type count {}",
+                memory_type_index, self.physical_device_memory_properties.memory_type_count
+            )));
+        }
+        let span = size.next_multiple_of(SLAB_ALIGNMENT);
+        let placed = self.slabs.iter_mut().find_map(|(&memory, slab)| {
+            let free = slab.free.as_mut().filter(|_| slab.memory_type_index == memory_type_index)?;
+            let offset = free.allocate(span)?;
+            slab.live += 1;
+            Some((memory, offset))
+        });
+        let (device_memory, offset) = match placed {
+            Some(placed) => placed,
+            None => {
+                let mut free = FreeList::new(BLOCK_SIZE);
+                let offset = free.allocate(span).unwrap_or(0);
+                (self.create_shared(BLOCK_SIZE, memory_type_index, 1, Some(free))?, offset)
+            }
+        };
+        Ok(self.member(device_memory, handle_id, size, offset))
+    }
+
+    /// Allocation info of a member of the slab or block at `device_memory`
+    fn member(&mut self, device_memory: vk::DeviceMemory, handle_id: String, size: u64, offset: u64) -> AllocationInfo {
+        let slab = &self.slabs[&device_memory];
+        let generation = self.next_generation;
+        self.next_generation += 1;
+        AllocationInfo {
+            handle_id,
+            size,
+            capacity: size,
+            device_memory,
+            buffer: slab.buffer,
+            offset,
+            mapped_ptr: slab.mapped_ptr.map(|ptr| ptr.wrapping_add(offset as usize)),
+            memory_properties: slab.property_flags,
+            generation,
+            liveness: Arc::new(Liveness::new(generation)),
+        }
+    }
+
+    /// Create a buffer and memory of `total` bytes shared by `live` members
+    /// and register it as a slab (or a block, with `free`)
+    fn create_shared(
+        &mut self,
+        total: u64,
+        memory_type_index: u32,
+        live: usize,
+        free: Option<FreeList>,
+    ) -> MemoryResult<vk::DeviceMemory> {
+        let requested_type = memory_type_index;
+        unsafe {
+            // SAFETY:
+            //   - device is valid (guaranteed by contract)
+            //   - total > 0 since every spec size was validated
+            let buffer_info = vk::BufferCreateInfo::default()
+                .size(total)
+                .usage(
+                    vk::BufferUsageFlags::TRANSFER_DST
+                        | vk::BufferUsageFlags::TRANSFER_SRC
+                        | vk::BufferUsageFlags::STORAGE_BUFFER,
+                )
+                .sharing_mode(vk::SharingMode::EXCLUSIVE);
+            let buffer = self
+                .device
+                .create_buffer(&buffer_info, None)
+                .map_err(MemoryError::VulkanError)?;
+
+            let mem_requirements = self.device.get_buffer_memory_requirements(buffer);
+            let memory_type_index = if mem_requirements.memory_type_bits & (1 << memory_type_index) != 0 {
+                memory_type_index
+            } else {
+                match self.find_compatible_memory_type(mem_requirements.memory_type_bits, memory_type_index) {
+                    Ok(index) => index,
+                    Err(e) => {
+                        self.device.destroy_buffer(buffer, None);
+                        return Err(e);
+                    }
+                }
+            };
+            let property_flags = self.physical_device_memory_properties.memory_types[memory_type_index as usize].property_flags;
+
+            // SAFETY: memory type is compatible with the buffer; buffer is unbound
+            let alloc_info = vk::MemoryAllocateInfo::default()
+                .allocation_size(mem_requirements.size)
+                .memory_type_index(memory_type_index);
+            let device_memory = match self.device.allocate_memory(&alloc_info, None) {
+                Ok(memory) => memory,
+                Err(e) => {
+                    self.device.destroy_buffer(buffer, None);
+                    return Err(MemoryError::VulkanError(e));
+                }
+            };
+            let release = |device: &ash::Device, e: vk::Result| {
+                device.free_memory(device_memory, None);
+                device.destroy_buffer(buffer, None);
+                MemoryError::VulkanError(e)
+            };
+            self.device
+                .bind_buffer_memory(buffer, device_memory, 0)
+                .map_err(|e| release(&self.device, e))?;
+
+            // SAFETY: the memory is host visible and not mapped elsewhere
+            let mapped_ptr = if property_flags.contains(vk::MemoryPropertyFlags::HOST_VISIBLE) {
+                let ptr = self
+                    .device
+                    .map_memory(device_memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty())
+                    .map_err(|e| release(&self.device, e))?;
+                Some(ptr as *mut u8)
+            } else {
+                None
+            };
+
+            self.slabs.insert(
+                device_memory,
+                Slab {
+                    buffer,
+                    mapped_ptr,
+                    property_flags,
+                    memory_type_index: requested_type,
+                    live,
+                    free,
+                },
+            );
+            Ok(device_memory)
+        }
+    }
+
+    /// Create the buffer and memory for an allocation without registering it
+    fn create(
+        &mut self,
+        size: u64,
+        memory_type_index: u32,
+        handle_id: String,
+    ) -> MemoryResult<AllocationInfo> {
+        self.create_external(size, memory_type_index, handle_id, None)
+    }
+
+    /// `create`, importing `import` instead of allocating new memory if given
+    ///
+    /// New memory is made exportable when `set_exportable` is on.
+    fn create_external(
+        &mut self,
+        size: u64,
+        memory_type_index: u32,
+        handle_id: String,
+        import: Option<ExternalMemory>,
+    ) -> MemoryResult<AllocationInfo> {
+        let external = self.exportable || import.is_some();
         // Validate inputs
         if size == 0 {
             return Err(MemoryError::AllocationFailed(
@@ -114,6 +930,12 @@ impl MemoryAllocator {
                         | vk::BufferUsageFlags::STORAGE_BUFFER,
                 )
                 .sharing_mode(vk::SharingMode::EXCLUSIVE);
+            let mut external_info = vk::ExternalMemoryBufferCreateInfo::default().handle_types(EXTERNAL_HANDLE_TYPE);
+            let buffer_info = if external {
+                buffer_info.push_next(&mut external_info)
+            } else {
+                buffer_info
+            };
 
             let buffer = self
                 .device
@@ -132,15 +954,29 @@ impl MemoryAllocator {
             let memory_type = &self.physical_device_memory_properties
                 .memory_types[memory_type_index as usize];
 
-            if !matches_memory_requirements(
-                mem_requirements,
-                memory_type.property_flags,
-                memory_type_index,
-            ) {
+            if let Some(import) = import {
+                // Imported memory keeps the exporter's type and size
+                if mem_requirements.memory_type_bits & (1 << memory_type_index) == 0
+                    || mem_requirements.size > import.allocation_size
+                {
+                    self.device.destroy_buffer(buffer, None);
+                    return Err(MemoryError::ExternalMemory(format!(
+                        "{}: imported memory does not fit the buffer",
+                        handle_id
+                    )));
+                }
+            } else if !matches_memory_requirements(mem_requirements, memory_type_index) {
                 // Memory type doesn't match requirements, find compatible type
+                self.device.destroy_buffer(buffer, None);
                 let compatible_index = self
-                    .find_compatible_memory_type(mem_requirements.memory_type_bits)?;
-                return self.allocate(size, compatible_index, handle_id);
+                    .find_compatible_memory_type(mem_requirements.memory_type_bits, memory_type_index)?;
+                if compatible_index == memory_type_index {
+                    return Err(MemoryError::InvalidMemoryType(format!(
+                        "{}: memory type {} cannot back the buffer",
+                        handle_id, memory_type_index
+                    )));
+                }
+                return self.create_external(size, compatible_index, handle_id, None);
             }
 
             // Allocate device memory
@@ -148,18 +984,28 @@ impl MemoryAllocator {
             //   - device is valid
             //   - memory_type_index is validated
             //   - size is validated
+            //   - an imported FD comes with the exporter's allocation size and type
+            let allocation_size = import.map_or(mem_requirements.size, |import| import.allocation_size);
+            let mut export_info = vk::ExportMemoryAllocateInfo::default().handle_types(EXTERNAL_HANDLE_TYPE);
+            let mut import_info = vk::ImportMemoryFdInfoKHR::default().handle_type(EXTERNAL_HANDLE_TYPE);
             let alloc_info = vk::MemoryAllocateInfo::default()
-                .allocation_size(mem_requirements.size)
+                .allocation_size(allocation_size)
                 .memory_type_index(memory_type_index);
+            let alloc_info = match import {
+                Some(import) => {
+                    import_info = import_info.fd(import.fd);
+                    alloc_info.push_next(&mut import_info)
+                }
+                None if external => alloc_info.push_next(&mut export_info),
+                None => alloc_info,
+            };
 
             let device_memory = self
                 .device
                 .allocate_memory(&alloc_info, None)
                 .map_err(|e| {
                     // Clean up buffer on allocation failure
-                    unsafe {
-                        self.device.destroy_buffer(buffer, None);
-                    }
+                    self.device.destroy_buffer(buffer, None);
                     MemoryError::VulkanError(e)
                 })?;
 
@@ -173,73 +1019,260 @@ impl MemoryAllocator {
                 .bind_buffer_memory(buffer, device_memory, 0)
                 .map_err(|e| {
                     // Clean up on bind failure
-                    unsafe {
-                        self.device.free_memory(device_memory, None);
-                        self.device.destroy_buffer(buffer, None);
-                    }
+                    self.device.free_memory(device_memory, None);
+                    self.device.destroy_buffer(buffer, None);
                     MemoryError::VulkanError(e)
                 })?;
 
-            let allocation = AllocationInfo {
-                handle_id: handle_id.clone(),
+            if external {
+                self.external.insert(device_memory, (allocation_size, memory_type_index));
+            }
+            let generation = self.next_generation;
+            self.next_generation += 1;
+
+            Ok(AllocationInfo {
+                handle_id,
                 size,
+                capacity: size,
                 device_memory,
                 buffer,
+                offset: 0,
                 mapped_ptr: None,
-            };
+                memory_properties: memory_type.property_flags,
+                generation,
+                liveness: Arc::new(Liveness::new(generation)),
+            })
+        }
+    }
+
+    /// Change an allocation's size, keeping its handle and leading contents
+    ///
+    /// Shrinking trims the allocation in place. A block member returns the
+    /// freed tail to its block; dedicated and `allocate_many` allocations
+    /// keep their buffer, so growing back up to `capacity` later is free.
+    /// Growing past the capacity creates a larger buffer of the same memory
+    /// type, copies the contents over through a mapping and frees the old
+    /// one; the allocation gets a new generation, so stale `MappedSlice`s of
+    /// the old buffer are detected. Memory that is not host visible can only
+    /// grow through `resize_on_device`. Either way `GpuEvent::Resized` is
+    /// emitted, since ranges and descriptors built from the old size are stale.
+    ///
+    /// Fails with `MappingOutstanding` while any `MappedSlice` is alive.
+    ///
+    /// # Arguments
+    /// * `handle_id` - Allocation handle
+    /// * `new_size` - New size in bytes (must be > 0)
+    pub fn resize(&mut self, handle_id: &str, new_size: u64) -> MemoryResult<()> {
+        self.resize_with(handle_id, new_size, None)
+    }
+
+    /// `resize` that grows with a buffer-to-buffer copy on `transfer`'s
+    /// queue, so allocations of any memory type can grow
+    ///
+    /// # Safety Requirements
+    /// - transfer must use the device of this allocator
+    /// - no pending work may use the allocation if it has to grow
+    pub unsafe fn resize_on_device(
+        &mut self,
+        handle_id: &str,
+        new_size: u64,
+        transfer: &DataTransfer,
+    ) -> MemoryResult<()> {
+        self.resize_with(handle_id, new_size, Some(transfer))
+    }
+
+    /// `resize`, copying through `transfer` instead of a mapping if given
+    fn resize_with(&mut self, handle_id: &str, new_size: u64, transfer: Option<&DataTransfer>) -> MemoryResult<()> {
+        if new_size == 0 {
+            return Err(MemoryError::AllocationFailed("size must be > 0".to_string()));
+        }
+
+        let allocation = self.get_allocation(handle_id)?;
+        let outstanding = allocation.liveness.outstanding();
+        if outstanding > 0 {
+            return Err(MemoryError::MappingOutstanding(handle_id.to_string(), outstanding));
+        }
+
+        let resized = GpuEvent::Resized {
+            handle_id: handle_id.to_string(),
+            old_size: allocation.size,
+            new_size,
+        };
+        if new_size <= allocation.capacity {
+            self.shrink(handle_id, new_size)?;
+            events::emit(resized);
+            return Ok(());
+        }
+
+        let memory_properties = allocation.memory_properties;
+        if transfer.is_none() && !memory_properties.contains(vk::MemoryPropertyFlags::HOST_VISIBLE) {
+            return Err(MemoryError::MapFailed(format!(
+                "cannot grow {} through a mapping: memory is not host visible",
+                handle_id
+            )));
+        }
+        let memory_type_index = self.memory_type_with_properties(memory_properties)?;
+        let mut grown = self.create(new_size, memory_type_index, handle_id.to_string())?;
+
+        let old = self.get_allocation(handle_id)?;
+        let copied = match transfer {
+            Some(transfer) => {
+                // SAFETY: the caller guarantees transfer shares the device and
+                // nothing uses the old allocation; `old.size` <= `new_size`
+                let copied = unsafe { transfer.copy_device_to_device(old, &grown, old.size) }.map_err(|e| {
+                    MemoryError::AllocationFailed(format!("cannot copy {} to its grown buffer: {}", handle_id, e))
+                });
+                // Leave the grown allocation mapped if the old one was
+                match (copied, old.mapped_ptr) {
+                    (Ok(()), Some(_)) => unsafe {
+                        // SAFETY: grown is a fresh, unmapped, host-visible
+                        // allocation of `new_size` bytes
+                        self.device
+                            .map_memory(grown.device_memory, 0, new_size, vk::MemoryMapFlags::empty())
+                            .map(|ptr| grown.mapped_ptr = Some(ptr as *mut u8))
+                            .map_err(MemoryError::VulkanError)
+                    },
+                    (copied, _) => copied,
+                }
+            }
+            None => unsafe {
+                // SAFETY:
+                //   - both allocations are valid, host visible and not mapped by any MappedSlice
+                //   - the mapped ranges lie within each allocation
+                //   - `old.size` <= `new_size`, so the copy stays in bounds of both
+                let src = match old.mapped_ptr {
+                    Some(ptr) => Ok(ptr),
+                    None => self
+                        .device
+                        .map_memory(old.device_memory, old.offset, old.size, vk::MemoryMapFlags::empty())
+                        .map(|ptr| ptr as *mut u8),
+                };
+                let dst = self
+                    .device
+                    .map_memory(grown.device_memory, 0, new_size, vk::MemoryMapFlags::empty())
+                    .map(|ptr| ptr as *mut u8);
+                if let (Ok(src), Ok(dst)) = (src, dst) {
+                    std::ptr::copy_nonoverlapping(src, dst, old.size as usize);
+                }
+
+                // Leave both allocations mapped exactly as the old one was
+                if old.mapped_ptr.is_none() && src.is_ok() {
+                    self.device.unmap_memory(old.device_memory);
+                }
+                match (old.mapped_ptr, dst) {
+                    (Some(_), Ok(dst)) => grown.mapped_ptr = Some(dst),
+                    (None, Ok(_)) => self.device.unmap_memory(grown.device_memory),
+                    _ => {}
+                }
+                src.and(dst).map(|_| ()).map_err(MemoryError::VulkanError)
+            },
+        };
+        if let Err(e) = copied {
+            self.destroy(&grown);
+            return Err(e);
+        }
 
-            self.allocations.insert(handle_id.clone(), allocation);
+        let old = self
+            .allocations
+            .insert(handle_id.to_string(), grown)
+            .ok_or_else(|| MemoryError::NotFound(handle_id.to_string()))?;
+        self.release(&old);
+        events::emit(GpuEvent::Allocated {
+            handle_id: handle_id.to_string(),
+            size: new_size,
+        });
+        events::emit(resized);
+        Ok(())
+    }
 
-            Ok(handle_id)
+    /// Set a size within the capacity, returning a block member's unused
+    /// tail to its block
+    fn shrink(&mut self, handle_id: &str, new_size: u64) -> MemoryResult<()> {
+        let allocation = self
+            .allocations
+            .get_mut(handle_id)
+            .ok_or_else(|| MemoryError::NotFound(handle_id.to_string()))?;
+        allocation.size = new_size;
+        let free = self
+            .slabs
+            .get_mut(&allocation.device_memory)
+            .and_then(|slab| slab.free.as_mut());
+        if let Some(free) = free {
+            free.release_tail(allocation.offset, allocation.capacity, new_size);
+            allocation.capacity = new_size;
         }
+        Ok(())
+    }
+
+    /// First memory type whose property flags are exactly `properties`
+    fn memory_type_with_properties(&self, properties: vk::MemoryPropertyFlags) -> MemoryResult<u32> {
+        self.physical_device_memory_properties.memory_types
+            [..self.physical_device_memory_properties.memory_type_count as usize]
+            .iter()
+            .position(|t| t.property_flags == properties)
+            .map(|i| i as u32)
+            .ok_or_else(|| MemoryError::InvalidMemoryType(format!("No memory type with {:?}", properties)))
     }
 
     /// Map device memory to host address space
     ///
+    /// The allocation stays mapped (and cannot be unmapped or freed) while any
+    /// returned `MappedSlice` is alive.
+    ///
     /// # Safety Requirements
-    /// - handle_id must refer to valid, previously allocated memory
-    /// - returned pointer is valid only until unmapped
     /// - memory must be HOST_VISIBLE
     ///
     /// # Arguments
     /// * `handle_id` - Allocation handle
     ///
     /// # Returns
-    /// Mapped pointer to device memory in host space
-    pub fn map(&mut self, handle_id: &str) -> MemoryResult<*mut u8> {
+    /// Liveness-checked view of the mapped range
+    pub fn map(&mut self, handle_id: &str) -> MemoryResult<MappedSlice> {
         let allocation = self
             .allocations
             .get_mut(handle_id)
             .ok_or_else(|| MemoryError::NotFound(handle_id.to_string()))?;
+        crate::strict::host_visible(allocation.memory_properties, handle_id);
 
-        if let Some(ptr) = allocation.mapped_ptr {
+        let ptr = match allocation.mapped_ptr {
             // Already mapped
-            return Ok(ptr);
-        }
+            Some(ptr) => ptr,
+            None => unsafe {
+                // Map memory to host address space
+                // SAFETY:
+                //   - device_memory is valid (from allocation)
+                //   - offset and capacity lie within this allocation
+                //   - device is valid
+                // The whole capacity is mapped so a later in-place `resize`
+                // stays within the mapping.
+                let ptr = self
+                    .device
+                    .map_memory(
+                        allocation.device_memory,
+                        allocation.offset,
+                        allocation.capacity,
+                        vk::MemoryMapFlags::empty(),
+                    )
+                    .map_err(MemoryError::VulkanError)? as *mut u8;
 
-        unsafe {
-            // Map memory to host address space
-            // SAFETY:
-            //   - device_memory is valid (from allocation)
-            //   - offset 0 and size are valid for this allocation
-            //   - device is valid
-            let ptr = self
-                .device
-                .map_memory(
-                    allocation.device_memory,
-                    0,
-                    vk::WHOLE_SIZE,
-                    vk::MemoryMapFlags::empty(),
-                )
-                .map_err(MemoryError::VulkanError)? as *mut u8;
+                allocation.mapped_ptr = Some(ptr);
+                ptr
+            },
+        };
 
-            allocation.mapped_ptr = Some(ptr);
-            Ok(ptr)
-        }
+        Ok(MappedSlice::new(
+            ptr,
+            allocation.size as usize,
+            allocation.handle_id.clone(),
+            allocation.generation,
+            Arc::clone(&allocation.liveness),
+        ))
     }
 
     /// Unmap device memory from host address space
     ///
+    /// Fails with `MappingOutstanding` while any `MappedSlice` is alive.
+    ///
     /// # Arguments
     /// * `handle_id` - Allocation handle
     pub fn unmap(&mut self, handle_id: &str) -> MemoryResult<()> {
@@ -248,7 +1281,13 @@ impl MemoryAllocator {
             .get_mut(handle_id)
             .ok_or_else(|| MemoryError::NotFound(handle_id.to_string()))?;
 
-        if allocation.mapped_ptr.is_some() {
+        let outstanding = allocation.liveness.outstanding();
+        if outstanding > 0 {
+            return Err(MemoryError::MappingOutstanding(handle_id.to_string(), outstanding));
+        }
+
+        // Slab and block members share one persistent mapping
+        if allocation.mapped_ptr.is_some() && !self.slabs.contains_key(&allocation.device_memory) {
             unsafe {
                 // Unmap memory
                 // SAFETY:
@@ -265,14 +1304,60 @@ impl MemoryAllocator {
 
     /// Deallocate device memory
     ///
+    /// Fails with `MappingOutstanding` while any `MappedSlice` is alive.
+    ///
     /// # Arguments
     /// * `handle_id` - Allocation handle
     pub fn deallocate(&mut self, handle_id: &str) -> MemoryResult<()> {
+        let outstanding = self.get_allocation(handle_id)?.liveness.outstanding();
+        if outstanding > 0 {
+            return Err(MemoryError::MappingOutstanding(handle_id.to_string(), outstanding));
+        }
+
         let allocation = self
             .allocations
             .remove(handle_id)
             .ok_or_else(|| MemoryError::NotFound(handle_id.to_string()))?;
 
+        self.release(&allocation);
+        Ok(())
+    }
+
+    /// Unmap and destroy an allocation's Vulkan objects, invalidating its mappings
+    fn release(&mut self, allocation: &AllocationInfo) {
+        self.destroy(allocation);
+        events::emit(GpuEvent::Freed {
+            handle_id: allocation.handle_id.clone(),
+            size: allocation.size,
+        });
+    }
+
+    /// Destroy an allocation's Vulkan objects without reporting a free
+    fn destroy(&mut self, allocation: &AllocationInfo) {
+        allocation.liveness.invalidate();
+
+        if let Some(slab) = self.slabs.get_mut(&allocation.device_memory) {
+            if let Some(free) = slab.free.as_mut() {
+                free.release(allocation.offset, allocation.capacity.next_multiple_of(SLAB_ALIGNMENT));
+            }
+            slab.live -= 1;
+            if slab.live > 0 {
+                return;
+            }
+        }
+        if let Some(slab) = self.slabs.remove(&allocation.device_memory) {
+            unsafe {
+                // SAFETY: the last member is gone, so nothing uses the slab's mapping,
+                // buffer or memory any more
+                if slab.mapped_ptr.is_some() {
+                    self.device.unmap_memory(allocation.device_memory);
+                }
+                self.device.destroy_buffer(slab.buffer, None);
+                self.device.free_memory(allocation.device_memory, None);
+            }
+            return;
+        }
+
         // Unmap if still mapped
         if allocation.mapped_ptr.is_some() {
             unsafe {
@@ -289,8 +1374,7 @@ impl MemoryAllocator {
             self.device.destroy_buffer(allocation.buffer, None);
             self.device.free_memory(allocation.device_memory, None);
         }
-
-        Ok(())
+        self.external.remove(&allocation.device_memory);
     }
 
     /// Get allocation info
@@ -311,39 +1395,65 @@ impl MemoryAllocator {
     }
 
     /// Find a compatible memory type for given requirements
-    fn find_compatible_memory_type(&self, type_bits: u32) -> MemoryResult<u32> {
-        for (i, memory_type) in self.physical_device_memory_properties.memory_types
-            [..self.physical_device_memory_properties.memory_type_count as usize]
-            .iter()
-            .enumerate()
-        {
-            if (type_bits & (1 << i)) != 0 {
-                return Ok(i as u32);
-            }
-        }
-        Err(MemoryError::InvalidMemoryType(
-            "No compatible memory type found".to_string(),
-        ))
+    fn find_compatible_memory_type(&self, type_bits: u32, requested: u32) -> MemoryResult<u32> {
+        compatible_memory_type(&self.physical_device_memory_properties, type_bits, requested)
+            .ok_or_else(|| MemoryError::InvalidMemoryType("No compatible memory type found".to_string()))
     }
 }
 
+/// Memory type allowed by `type_bits` to use instead of type `requested`
+///
+/// Prefers a type with every property of the `requested` one, so a
+/// mappable request stays mappable, then the best type for the usage the
+/// requested type implies.
+fn compatible_memory_type(properties: &vk::PhysicalDeviceMemoryProperties, type_bits: u32, requested: u32) -> Option<u32> {
+    let types = &properties.memory_types[..properties.memory_type_count as usize];
+    let wanted = types.get(requested as usize).map_or(vk::MemoryPropertyFlags::empty(), |t| t.property_flags);
+    let usage = if wanted.contains(vk::MemoryPropertyFlags::HOST_VISIBLE) {
+        MemoryUsage::Staging
+    } else {
+        MemoryUsage::DeviceLocal
+    };
+    types
+        .iter()
+        .enumerate()
+        .position(|(i, t)| type_bits & (1 << i) != 0 && t.property_flags.contains(wanted))
+        .map(|i| i as u32)
+        .or_else(|| find_memory_type(properties, type_bits, usage))
+}
+
+/// Offsets of consecutive allocations of `sizes` packed at `SLAB_ALIGNMENT`
+fn slab_offsets(sizes: impl Iterator<Item = u64>) -> Vec<u64> {
+    let mut next = 0;
+    sizes
+        .map(|size| {
+            let offset = next;
+            next = (offset + size).next_multiple_of(SLAB_ALIGNMENT);
+            offset
+        })
+        .collect()
+}
+
 /// Check if memory type matches buffer requirements
-fn matches_memory_requirements(
-    requirements: vk::MemoryRequirements,
-    property_flags: vk::MemoryPropertyFlags,
-    type_index: u32,
-) -> bool {
-    // Check if this memory type is in the compatible types for the buffer
+///
+/// Any type the buffer allows will do: whether it must be mappable is up to
+/// the caller's choice of type.
+fn matches_memory_requirements(requirements: vk::MemoryRequirements, type_index: u32) -> bool {
     (requirements.memory_type_bits & (1 << type_index)) != 0
-        && property_flags.contains(vk::MemoryPropertyFlags::HOST_VISIBLE)
 }
 
 impl Drop for MemoryAllocator {
     fn drop(&mut self) {
-        // Clean up all remaining allocations
-        let handles: Vec<_> = self.allocations.keys().cloned().collect();
-        for handle in handles {
-            let _ = self.deallocate(&handle);
+        // Clean up all remaining allocations; any outstanding mappings become stale
+        let allocations: Vec<_> = self.allocations.drain().map(|(_, a)| a).collect();
+        for allocation in &allocations {
+            if allocation.liveness.outstanding() > 0 {
+                log::warn!(
+                    "Freeing allocation {} with outstanding mappings",
+                    allocation.handle_id
+                );
+            }
+            self.release(allocation);
         }
     }
 }
@@ -352,6 +1462,161 @@ impl Drop for MemoryAllocator {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_buffer_range_slice() {
+        let range = BufferRange {
+            buffer: vk::Buffer::null(),
+            offset: 256,
+            size: 1024,
+        };
+        let sub = range.slice(128, 512).unwrap();
+        assert_eq!(sub.offset, 384);
+        assert_eq!(sub.descriptor_info().offset, 384);
+        assert!(range.slice(1000, 100).is_err());
+    }
+
+    #[test]
+    fn test_mapped_slice_detects_free() {
+        let mut backing = vec![0u8; 16];
+        let liveness = Arc::new(Liveness::new(7));
+        let mut slice = MappedSlice::new(backing.as_mut_ptr(), backing.len(), "h".to_string(), 7, Arc::clone(&liveness));
+        assert_eq!(liveness.outstanding(), 1);
+        slice.as_mut_slice().unwrap()[0] = 42;
+        assert_eq!(slice.as_slice().unwrap()[0], 42);
+
+        liveness.invalidate();
+        if cfg!(debug_assertions) {
+            assert!(matches!(slice.as_slice(), Err(MemoryError::StaleMapping(_, 7))));
+        }
+        drop(slice);
+        assert_eq!(liveness.outstanding(), 0);
+    }
+
+    #[test]
+    fn test_slab_offsets_are_aligned() {
+        assert_eq!(slab_offsets([100, 256, 1, 4].into_iter()), vec![0, 256, 512, 768]);
+        assert!(slab_offsets(std::iter::empty()).is_empty());
+    }
+
+    #[test]
+    fn test_free_list_reuses_and_coalesces() {
+        let mut free = FreeList::new(1024);
+        assert_eq!(free.allocate(256), Some(0));
+        assert_eq!(free.allocate(512), Some(256));
+        assert_eq!(free.allocate(512), None);
+        free.release(0, 256);
+        assert_eq!(free.allocate(256), Some(0));
+        free.release(0, 256);
+        free.release(256, 512);
+        assert_eq!(free, FreeList::new(1024));
+        assert_eq!(free.allocate(1024), Some(0));
+    }
+
+    #[test]
+    fn test_free_list_shrink_returns_tail() {
+        let mut free = FreeList::new(1024);
+        assert_eq!(free.allocate(768), Some(0));
+        // Shrinking within the aligned span frees nothing
+        free.release_tail(0, 700, 600);
+        assert_eq!(free.allocate(512), None);
+        free.release_tail(0, 700, 200);
+        assert_eq!(free.allocate(512), Some(256));
+        free.release(256, 512);
+        free.release(0, 256);
+        assert_eq!(free, FreeList::new(1024));
+    }
+
+    #[test]
+    fn test_find_memory_type() {
+        let host = vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+        let local = vk::MemoryPropertyFlags::DEVICE_LOCAL;
+        let mut discrete = vk::PhysicalDeviceMemoryProperties {
+            memory_type_count: 3,
+            ..Default::default()
+        };
+        discrete.memory_types[0].property_flags = local;
+        discrete.memory_types[1].property_flags = local | host;
+        discrete.memory_types[2].property_flags = host | vk::MemoryPropertyFlags::HOST_CACHED;
+        assert_eq!(find_memory_type(&discrete, u32::MAX, MemoryUsage::DeviceLocal), Some(0));
+        assert_eq!(find_memory_type(&discrete, u32::MAX, MemoryUsage::Staging), Some(2));
+        assert_eq!(find_memory_type(&discrete, 0b011, MemoryUsage::Staging), Some(1));
+        assert_eq!(find_memory_type(&discrete, 0b001, MemoryUsage::Staging), None);
+
+        // UMA: one type that is everything
+        let mut uma = vk::PhysicalDeviceMemoryProperties {
+            memory_type_count: 1,
+            ..Default::default()
+        };
+        uma.memory_types[0].property_flags = local | host;
+        assert_eq!(find_memory_type(&uma, u32::MAX, MemoryUsage::Staging), Some(0));
+        assert_eq!(find_memory_type(&uma, u32::MAX, MemoryUsage::DeviceLocal), Some(0));
+        uma.memory_types[0].property_flags = host;
+        assert_eq!(find_memory_type(&uma, u32::MAX, MemoryUsage::DeviceLocal), Some(0));
+    }
+
+    #[test]
+    fn test_device_local_types_need_no_mapping() {
+        let requirements = vk::MemoryRequirements {
+            memory_type_bits: 0b011,
+            ..Default::default()
+        };
+        assert!(matches_memory_requirements(requirements, 0));
+        assert!(!matches_memory_requirements(requirements, 2));
+
+        let host = vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+        let local = vk::MemoryPropertyFlags::DEVICE_LOCAL;
+        let mut discrete = vk::PhysicalDeviceMemoryProperties {
+            memory_type_count: 3,
+            ..Default::default()
+        };
+        discrete.memory_types[0].property_flags = local;
+        discrete.memory_types[1].property_flags = host;
+        discrete.memory_types[2].property_flags = local | host;
+        assert_eq!(compatible_memory_type(&discrete, 0b100, 0), Some(2));
+        assert_eq!(compatible_memory_type(&discrete, 0b101, 1), Some(2));
+        assert_eq!(compatible_memory_type(&discrete, 0b011, 2), Some(1));
+        assert_eq!(compatible_memory_type(&discrete, 0, 0), None);
+    }
+
+    #[test]
+    fn test_large_device_local_allocation() {
+        let Some(device) = crate::device::test_device() else {
+            return;
+        };
+        let mut allocator = device.memory_allocator();
+        let memory_type = find_memory_type(device.memory_properties(), u32::MAX, MemoryUsage::DeviceLocal).unwrap();
+        let size = 32 << 20;
+        assert!(size > MAX_SUBALLOCATION);
+        let handle = allocator.allocate(size, memory_type, "large".to_string()).unwrap();
+        assert_eq!(allocator.get_allocation(&handle).unwrap().size, size);
+        allocator.deallocate(&handle).unwrap();
+    }
+
+    #[test]
+    fn test_grow_device_local_past_suballocation() {
+        let Some(device) = crate::device::test_device() else {
+            return;
+        };
+        let pool = device.command_pool().unwrap();
+        let transfer = device.data_transfer(&pool);
+        let mut allocator = device.memory_allocator();
+        let memory_type = find_memory_type(device.memory_properties(), u32::MAX, MemoryUsage::DeviceLocal).unwrap();
+        let handle = allocator.allocate(4096, memory_type, "grown".to_string()).unwrap();
+        let view = allocator.get_allocation(&handle).unwrap().view(0, 4).unwrap();
+        // SAFETY: transfer and allocation share the device and nothing else uses it
+        unsafe { transfer.copy_to_device(&[1, 2, 3, 4], &view) }.unwrap();
+
+        let size = 2 * MAX_SUBALLOCATION;
+        // SAFETY: as above
+        unsafe { allocator.resize_on_device(&handle, size, &transfer) }.unwrap();
+        let grown = allocator.get_allocation(&handle).unwrap();
+        assert_eq!(grown.size, size);
+        let view = grown.view(0, 4).unwrap();
+        // SAFETY: as above
+        assert_eq!(unsafe { transfer.copy_from_device(&view, 4) }.unwrap(), [1, 2, 3, 4]);
+        allocator.deallocate(&handle).unwrap();
+    }
+
     #[test]
     fn test_memory_error_display() {
         let err = MemoryError::AllocationFailed("test".to_string());
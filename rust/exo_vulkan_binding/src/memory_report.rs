@@ -0,0 +1,197 @@
+//! Driver-level memory accounting via `VK_EXT_device_memory_report`
+//!
+//! When the extension is available the device is created with a report
+//! callback that sees every allocation, free, import and failed allocation,
+//! including driver-internal memory (pipelines, descriptor pools, command
+//! pools) that never goes through `MemoryAllocator`. The tracker keeps
+//! running totals for metrics and a live-object table for leak detection.
+
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::sync::Arc;
+
+use ash::vk;
+use parking_lot::Mutex;
+
+use crate::events::{self, GpuEvent};
+
+/// A memory object the driver reported as live
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReportedObject {
+    pub memory_object_id: u64,
+    pub size: u64,
+    pub object_type: vk::ObjectType,
+    pub heap_index: u32,
+    pub imported: bool,
+}
+
+impl ReportedObject {
+    /// Memory backing anything other than an application `VkDeviceMemory`
+    pub fn is_driver_internal(&self) -> bool {
+        self.object_type != vk::ObjectType::DEVICE_MEMORY
+    }
+}
+
+/// Running totals from the driver's memory reports
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryReportStats {
+    pub allocated_bytes: u64,
+    pub freed_bytes: u64,
+    pub imported_bytes: u64,
+    pub driver_internal_bytes: u64,
+    pub live_objects: usize,
+    pub failed_allocations: u64,
+}
+
+#[derive(Default)]
+struct TrackerState {
+    stats: MemoryReportStats,
+    live: HashMap<u64, ReportedObject>,
+}
+
+/// Collects `VK_EXT_device_memory_report` callbacks for one device
+#[derive(Default)]
+pub struct MemoryReportTracker {
+    state: Mutex<TrackerState>,
+}
+
+impl MemoryReportTracker {
+    /// Create an empty tracker
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Create info to chain into `vk::DeviceCreateInfo::push_next`
+    ///
+    /// # Safety Requirements
+    /// - `self` must outlive the device created with the returned info
+    pub unsafe fn create_info(self: &Arc<Self>) -> vk::DeviceDeviceMemoryReportCreateInfoEXT<'static> {
+        vk::DeviceDeviceMemoryReportCreateInfoEXT::default()
+            .pfn_user_callback(Some(memory_report_callback))
+            .user_data(Arc::as_ptr(self) as *mut c_void)
+    }
+
+    /// Apply one report
+    pub fn record(
+        &self,
+        event_type: vk::DeviceMemoryReportEventTypeEXT,
+        memory_object_id: u64,
+        size: u64,
+        object_type: vk::ObjectType,
+        heap_index: u32,
+    ) {
+        let mut state = self.state.lock();
+        let object = ReportedObject {
+            memory_object_id,
+            size,
+            object_type,
+            heap_index,
+            imported: event_type == vk::DeviceMemoryReportEventTypeEXT::IMPORT,
+        };
+
+        match event_type {
+            vk::DeviceMemoryReportEventTypeEXT::ALLOCATE | vk::DeviceMemoryReportEventTypeEXT::IMPORT => {
+                if object.imported {
+                    state.stats.imported_bytes += size;
+                } else {
+                    state.stats.allocated_bytes += size;
+                }
+                if object.is_driver_internal() {
+                    state.stats.driver_internal_bytes += size;
+                }
+                state.live.insert(memory_object_id, object);
+            }
+            vk::DeviceMemoryReportEventTypeEXT::FREE | vk::DeviceMemoryReportEventTypeEXT::UNIMPORT => {
+                // Free reports may carry size 0; use the size recorded at allocation
+                if let Some(live) = state.live.remove(&memory_object_id) {
+                    if !live.imported {
+                        state.stats.freed_bytes += live.size;
+                    }
+                    if live.is_driver_internal() {
+                        state.stats.driver_internal_bytes =
+                            state.stats.driver_internal_bytes.saturating_sub(live.size);
+                    }
+                }
+            }
+            vk::DeviceMemoryReportEventTypeEXT::ALLOCATION_FAILED => {
+                state.stats.failed_allocations += 1;
+                drop(state);
+                events::emit(GpuEvent::Error {
+                    source: "device_memory_report",
+                    message: format!("driver allocation of {} bytes on heap {} failed", size, heap_index),
+                });
+                return;
+            }
+            _ => {}
+        }
+        state.stats.live_objects = state.live.len();
+    }
+
+    /// Current totals
+    pub fn stats(&self) -> MemoryReportStats {
+        self.state.lock().stats
+    }
+
+    /// Bytes currently held by the driver on behalf of this device
+    pub fn resident_bytes(&self) -> u64 {
+        self.state.lock().live.values().map(|o| o.size).sum()
+    }
+
+    /// Objects still live, largest first — anything left at teardown is a leak
+    pub fn live_objects(&self) -> Vec<ReportedObject> {
+        let mut objects: Vec<_> = self.state.lock().live.values().copied().collect();
+        objects.sort_by(|a, b| b.size.cmp(&a.size));
+        objects
+    }
+}
+
+/// Callback registered with the driver
+///
+/// # Safety Requirements
+/// - `user_data` must point to a live `MemoryReportTracker` (see `create_info`)
+unsafe extern "system" fn memory_report_callback(
+    callback_data: *const vk::DeviceMemoryReportCallbackDataEXT<'_>,
+    user_data: *mut c_void,
+) {
+    if callback_data.is_null() || user_data.is_null() {
+        return;
+    }
+    // SAFETY: the driver passes a valid report for the duration of the call
+    let data = unsafe { &*callback_data };
+    // SAFETY: user_data is the tracker pointer installed by `create_info`
+    let tracker = unsafe { &*(user_data as *const MemoryReportTracker) };
+    tracker.record(data.ty, data.memory_object_id, data.size, data.object_type, data.heap_index);
+}
+
+/// Whether the physical device exposes `VK_EXT_device_memory_report`
+pub fn supports_device_memory_report(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> bool {
+    // SAFETY: physical_device was enumerated from this instance
+    let extensions = match unsafe { instance.enumerate_device_extension_properties(physical_device) } {
+        Ok(extensions) => extensions,
+        Err(_) => return false,
+    };
+    extensions.iter().any(|ext| {
+        ext.extension_name_as_c_str()
+            .is_ok_and(|name| name == ash::ext::device_memory_report::NAME)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tracks_driver_internal_and_leaks() {
+        let tracker = MemoryReportTracker::new();
+        tracker.record(vk::DeviceMemoryReportEventTypeEXT::ALLOCATE, 1, 4096, vk::ObjectType::DEVICE_MEMORY, 0);
+        tracker.record(vk::DeviceMemoryReportEventTypeEXT::ALLOCATE, 2, 512, vk::ObjectType::PIPELINE, 0);
+        tracker.record(vk::DeviceMemoryReportEventTypeEXT::FREE, 1, 0, vk::ObjectType::DEVICE_MEMORY, 0);
+
+        let stats = tracker.stats();
+        assert_eq!(stats.allocated_bytes, 4608);
+        assert_eq!(stats.freed_bytes, 4096);
+        assert_eq!(stats.driver_internal_bytes, 512);
+        assert_eq!(tracker.live_objects().len(), 1);
+        assert!(tracker.live_objects()[0].is_driver_internal());
+    }
+}
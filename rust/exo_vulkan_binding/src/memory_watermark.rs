@@ -0,0 +1,159 @@
+//! Memory high-water-mark alerts
+//!
+//! Running out of device memory on a phone is abrupt: the next allocation
+//! fails mid-generation. `MemoryWatermarks` watches usage against a budget
+//! and raises an alert when it crosses a threshold (80% and 95% by default),
+//! with a per-tag breakdown (client, model, KV cache, ...) so the app can shed
+//! context or switch to a smaller model while there is still room to do so.
+//!
+//! Each threshold fires once on the way up and re-arms only after usage falls
+//! `REARM_MARGIN` below it, so usage hovering at a threshold does not flood
+//! subscribers.
+
+use crate::events::{self, GpuEvent};
+
+/// Thresholds used when none are configured, as fractions of the budget
+pub const DEFAULT_THRESHOLDS: [f32; 2] = [0.80, 0.95];
+
+/// Fraction of the budget usage must drop below a threshold to re-arm it
+pub const REARM_MARGIN: f32 = 0.05;
+
+/// A threshold crossing
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemoryAlert {
+    /// Highest threshold crossed, as a fraction of the budget
+    pub threshold: f32,
+    pub used_bytes: u64,
+    pub budget_bytes: u64,
+    /// Usage per tag, largest first
+    pub breakdown: Vec<(String, u64)>,
+}
+
+/// Callback invoked for every alert
+pub type AlertHook = Box<dyn Fn(&MemoryAlert) + Send + Sync>;
+
+/// Tracks usage against a budget and raises threshold alerts
+pub struct MemoryWatermarks {
+    budget_bytes: u64,
+    /// Ascending fractions of the budget
+    thresholds: Vec<f32>,
+    /// Number of thresholds currently crossed (and not re-armed)
+    crossed: usize,
+    hooks: Vec<AlertHook>,
+}
+
+impl MemoryWatermarks {
+    /// Watch `budget_bytes` with thresholds given as fractions in (0, 1]
+    ///
+    /// Out-of-range thresholds are dropped.
+    pub fn new(budget_bytes: u64, thresholds: &[f32]) -> Self {
+        let mut thresholds: Vec<f32> = thresholds
+            .iter()
+            .copied()
+            .filter(|t| *t > 0.0 && *t <= 1.0)
+            .collect();
+        thresholds.sort_by(f32::total_cmp);
+        thresholds.dedup();
+        Self {
+            budget_bytes,
+            thresholds,
+            crossed: 0,
+            hooks: Vec::new(),
+        }
+    }
+
+    /// Register a callback for alerts
+    pub fn on_alert(&mut self, hook: AlertHook) {
+        self.hooks.push(hook);
+    }
+
+    pub fn budget_bytes(&self) -> u64 {
+        self.budget_bytes
+    }
+
+    pub fn thresholds(&self) -> &[f32] {
+        &self.thresholds
+    }
+
+    /// Feed the current per-tag usage
+    ///
+    /// # Returns
+    /// The alert raised, if usage crossed a threshold that was armed
+    pub fn observe(&mut self, usage: &[(String, u64)]) -> Option<MemoryAlert> {
+        let used_bytes: u64 = usage.iter().map(|(_, bytes)| bytes).sum();
+        let fraction = used_bytes as f32 / self.budget_bytes.max(1) as f32;
+
+        // Re-arm thresholds usage has clearly dropped below
+        while self.crossed > 0 && fraction < self.thresholds[self.crossed - 1] - REARM_MARGIN {
+            self.crossed -= 1;
+        }
+
+        let reached = self.thresholds.iter().filter(|&&t| fraction >= t).count();
+        if reached <= self.crossed {
+            return None;
+        }
+        self.crossed = reached;
+
+        let mut breakdown: Vec<(String, u64)> = usage.iter().filter(|(_, bytes)| *bytes > 0).cloned().collect();
+        breakdown.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let alert = MemoryAlert {
+            threshold: self.thresholds[reached - 1],
+            used_bytes,
+            budget_bytes: self.budget_bytes,
+            breakdown,
+        };
+
+        log::warn!(
+            "Memory usage {} / {} bytes crossed {:.0}% of budget",
+            used_bytes,
+            self.budget_bytes,
+            alert.threshold * 100.0
+        );
+        events::emit(GpuEvent::MemoryPressure {
+            threshold_percent: (alert.threshold * 100.0).round() as u32,
+            used_bytes,
+            budget_bytes: self.budget_bytes,
+            breakdown: alert.breakdown.clone(),
+        });
+        for hook in &self.hooks {
+            hook(&alert);
+        }
+        Some(alert)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(parts: &[(&str, u64)]) -> Vec<(String, u64)> {
+        parts.iter().map(|(tag, bytes)| (tag.to_string(), *bytes)).collect()
+    }
+
+    #[test]
+    fn test_alerts_once_per_threshold() {
+        let mut marks = MemoryWatermarks::new(1000, &DEFAULT_THRESHOLDS);
+        assert!(marks.observe(&usage(&[("kv", 500)])).is_none());
+
+        let alert = marks.observe(&usage(&[("kv", 500), ("model", 320)])).unwrap();
+        assert_eq!(alert.threshold, 0.80);
+        assert_eq!(alert.used_bytes, 820);
+        assert_eq!(alert.breakdown, usage(&[("kv", 500), ("model", 320)]));
+        assert!(marks.observe(&usage(&[("kv", 830)])).is_none());
+
+        // Jumping past both thresholds reports the highest one
+        let mut marks = MemoryWatermarks::new(1000, &DEFAULT_THRESHOLDS);
+        assert_eq!(marks.observe(&usage(&[("kv", 990)])).unwrap().threshold, 0.95);
+    }
+
+    #[test]
+    fn test_rearms_below_margin() {
+        let mut marks = MemoryWatermarks::new(1000, &[0.8, 1.5]);
+        assert_eq!(marks.thresholds(), &[0.8]);
+        assert!(marks.observe(&usage(&[("a", 800)])).is_some());
+        assert!(marks.observe(&usage(&[("a", 770)])).is_none());
+        assert!(marks.observe(&usage(&[("a", 800)])).is_none());
+        assert!(marks.observe(&usage(&[("a", 700)])).is_none());
+        assert!(marks.observe(&usage(&[("a", 810)])).is_some());
+    }
+}
@@ -0,0 +1,314 @@
+//! Multi-resolution history of memory use and throughput
+//!
+//! Diagnostics screens chart how the backend behaved over a whole session,
+//! which `PerformanceTracker`'s one-minute window cannot show. `MetricsHistory`
+//! folds the same token and GPU-time samples, plus total allocated memory,
+//! into fixed-size ring buffers at three resolutions: one point per second
+//! for five minutes, per ten seconds for an hour and per five minutes for a
+//! day. Memory is a gauge: each point holds the peak of its interval and
+//! intervals without samples repeat the last value, so charts have no gaps.
+//!
+//! Points are exported as JSON rows or as a compact little-endian binary
+//! (`EXMH` header, then 24 bytes per point) for screens polling often.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+
+const MAGIC: [u8; 4] = *b"EXMH";
+const FORMAT_VERSION: u32 = 1;
+const HEADER_LEN: usize = 4 + 4 + 4 + 8 + 4;
+
+/// Bytes per point in the binary export
+pub const POINT_LEN: usize = 8 + 8 + 4 + 4;
+
+/// Interval covered by one point
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resolution {
+    /// One second, kept for five minutes
+    Second,
+    /// Ten seconds, kept for an hour
+    TenSeconds,
+    /// Five minutes, kept for a day
+    FiveMinutes,
+}
+
+impl Resolution {
+    pub const ALL: [Resolution; 3] = [Resolution::Second, Resolution::TenSeconds, Resolution::FiveMinutes];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Resolution::Second => "1s",
+            Resolution::TenSeconds => "10s",
+            Resolution::FiveMinutes => "5m",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|r| r.name() == name)
+    }
+
+    pub fn interval(self) -> Duration {
+        match self {
+            Resolution::Second => Duration::from_secs(1),
+            Resolution::TenSeconds => Duration::from_secs(10),
+            Resolution::FiveMinutes => Duration::from_secs(300),
+        }
+    }
+
+    /// Points kept, oldest first to go
+    pub fn capacity(self) -> usize {
+        match self {
+            Resolution::Second => 300,
+            Resolution::TenSeconds => 360,
+            Resolution::FiveMinutes => 288,
+        }
+    }
+}
+
+/// Metrics of one interval
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Point {
+    /// Start of the interval, from the first recorded sample
+    pub start_ms: u64,
+    /// Peak total allocated memory
+    pub memory_bytes: u64,
+    pub tokens: u32,
+    /// GPU time spent, in microseconds
+    pub gpu_busy_us: u32,
+}
+
+/// Ring buffer of one resolution; the last point is still filling
+#[derive(Debug)]
+struct Series {
+    resolution: Resolution,
+    points: VecDeque<Point>,
+    /// Index of the filling point since the history started
+    current: u64,
+}
+
+impl Series {
+    fn new(resolution: Resolution) -> Self {
+        Self {
+            resolution,
+            points: VecDeque::from([Point::default()]),
+            current: 0,
+        }
+    }
+
+    fn interval_ms(&self) -> u64 {
+        self.resolution.interval().as_millis() as u64
+    }
+
+    /// Close points until the one covering `elapsed` is filling
+    ///
+    /// New points start at `memory_bytes`; a gap longer than the buffer
+    /// only creates the points that will be kept.
+    fn advance(&mut self, elapsed: Duration, memory_bytes: u64) -> &mut Point {
+        let index = elapsed.as_millis() as u64 / self.interval_ms();
+        if index > self.current {
+            let capacity = self.resolution.capacity() as u64;
+            let first = (self.current + 1).max(index.saturating_sub(capacity - 1));
+            for i in first..=index {
+                if self.points.len() == self.resolution.capacity() {
+                    self.points.pop_front();
+                }
+                self.points.push_back(Point {
+                    start_ms: i * self.interval_ms(),
+                    memory_bytes,
+                    ..Point::default()
+                });
+            }
+            self.current = index;
+        }
+        self.points.back_mut().expect("series always has a filling point")
+    }
+}
+
+/// Memory and throughput points at every `Resolution`
+#[derive(Debug)]
+pub struct MetricsHistory {
+    started: Option<Instant>,
+    /// Latest memory sample, carried into new points
+    memory_bytes: u64,
+    series: [Series; 3],
+}
+
+impl Default for MetricsHistory {
+    fn default() -> Self {
+        Self {
+            started: None,
+            memory_bytes: 0,
+            series: Resolution::ALL.map(Series::new),
+        }
+    }
+}
+
+impl MetricsHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply `update` to the filling point of every resolution at `at`
+    fn update(&mut self, at: Instant, update: impl Fn(&mut Point)) {
+        let elapsed = at.saturating_duration_since(*self.started.get_or_insert(at));
+        for series in &mut self.series {
+            update(series.advance(elapsed, self.memory_bytes));
+        }
+    }
+
+    /// Record total allocated memory at `at`
+    pub fn record_memory(&mut self, bytes: u64, at: Instant) {
+        self.update(at, |p| p.memory_bytes = p.memory_bytes.max(bytes));
+        self.memory_bytes = bytes;
+    }
+
+    /// Count `tokens` generated at `at`
+    pub fn record_tokens(&mut self, tokens: u64, at: Instant) {
+        let tokens = u32::try_from(tokens).unwrap_or(u32::MAX);
+        self.update(at, |p| p.tokens = p.tokens.saturating_add(tokens));
+    }
+
+    /// Count `gpu_time` of work that finished at `at`
+    pub fn record_gpu_time(&mut self, gpu_time: Duration, at: Instant) {
+        let micros = u32::try_from(gpu_time.as_micros()).unwrap_or(u32::MAX);
+        self.update(at, |p| p.gpu_busy_us = p.gpu_busy_us.saturating_add(micros));
+    }
+
+    /// Milliseconds from the first sample to `now`
+    fn elapsed_ms(&self, now: Instant) -> u64 {
+        self.started
+            .map_or(0, |started| now.saturating_duration_since(started).as_millis() as u64)
+    }
+
+    /// Points of `resolution` up to `now`, oldest first, the last one partial
+    ///
+    /// Empty until something was recorded.
+    pub fn points(&mut self, resolution: Resolution, now: Instant) -> Vec<Point> {
+        let Some(started) = self.started else {
+            return Vec::new();
+        };
+        let series = &mut self.series[resolution as usize];
+        series.advance(now.saturating_duration_since(started), self.memory_bytes);
+        series.points.iter().copied().collect()
+    }
+
+    /// `{"resolution":"1s","interval_ms":1000,"now_ms":..,"columns":[..],"points":[[..],..]}`
+    pub fn to_json(&mut self, resolution: Resolution, now: Instant) -> String {
+        let points: Vec<String> = self
+            .points(resolution, now)
+            .iter()
+            .map(|p| format!("[{},{},{},{}]", p.start_ms, p.memory_bytes, p.tokens, p.gpu_busy_us))
+            .collect();
+        format!(
+            r#"{{"resolution":"{}","interval_ms":{},"now_ms":{},"columns":["start_ms","memory_bytes","tokens","gpu_busy_us"],"points":[{}]}}"#,
+            resolution.name(),
+            resolution.interval().as_millis(),
+            self.elapsed_ms(now),
+            points.join(",")
+        )
+    }
+
+    /// `EXMH`, format version, interval ms, now ms (u64) and point count,
+    /// then per point start ms and memory bytes (u64), tokens and GPU us
+    /// (u32), all little-endian
+    pub fn to_bytes(&mut self, resolution: Resolution, now: Instant) -> Vec<u8> {
+        let points = self.points(resolution, now);
+        let mut out = Vec::with_capacity(HEADER_LEN + points.len() * POINT_LEN);
+        out.extend_from_slice(&MAGIC);
+        out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        out.extend_from_slice(&(resolution.interval().as_millis() as u32).to_le_bytes());
+        out.extend_from_slice(&self.elapsed_ms(now).to_le_bytes());
+        out.extend_from_slice(&(points.len() as u32).to_le_bytes());
+        for p in &points {
+            out.extend_from_slice(&p.start_ms.to_le_bytes());
+            out.extend_from_slice(&p.memory_bytes.to_le_bytes());
+            out.extend_from_slice(&p.tokens.to_le_bytes());
+            out.extend_from_slice(&p.gpu_busy_us.to_le_bytes());
+        }
+        out
+    }
+
+    /// Forget all history, e.g. when a new session starts
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+lazy_static! {
+    static ref HISTORY: Mutex<MetricsHistory> = Mutex::new(MetricsHistory::new());
+}
+
+/// The process-wide history generation and allocation record into
+pub fn global() -> parking_lot::MutexGuard<'static, MetricsHistory> {
+    HISTORY.lock()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolutions_fold_samples() {
+        let mut history = MetricsHistory::new();
+        let start = Instant::now();
+        assert!(history.points(Resolution::Second, start).is_empty());
+
+        history.record_memory(1000, start);
+        for s in 0..25 {
+            let at = start + Duration::from_millis(s * 1000 + 500);
+            history.record_tokens(2, at);
+            history.record_gpu_time(Duration::from_millis(100), at);
+            if s == 12 {
+                history.record_memory(400, at);
+            }
+        }
+
+        let now = start + Duration::from_secs(25);
+        let seconds = history.points(Resolution::Second, now);
+        assert_eq!(seconds.len(), 26);
+        assert_eq!(seconds[3], Point { start_ms: 3000, memory_bytes: 1000, tokens: 2, gpu_busy_us: 100_000 });
+        assert_eq!(seconds[12].memory_bytes, 1000);
+        assert_eq!(seconds[13].memory_bytes, 400);
+        assert_eq!(seconds[25].tokens, 0);
+
+        let tens = history.points(Resolution::TenSeconds, now);
+        assert_eq!(tens.iter().map(|p| p.tokens).collect::<Vec<_>>(), vec![20, 20, 10]);
+        assert_eq!(history.points(Resolution::FiveMinutes, now)[0].tokens, 50);
+
+        // An idle hour keeps only the newest points, memory carried forward
+        let later = now + Duration::from_secs(3600);
+        let seconds = history.points(Resolution::Second, later);
+        assert_eq!(seconds.len(), Resolution::Second.capacity());
+        assert!(seconds.iter().all(|p| p.memory_bytes == 400 && p.tokens == 0));
+        assert_eq!(seconds.last().unwrap().start_ms, 3625 * 1000);
+    }
+
+    #[test]
+    fn test_exports() {
+        let mut history = MetricsHistory::new();
+        let start = Instant::now();
+        history.record_memory(4096, start);
+        history.record_tokens(3, start + Duration::from_millis(1500));
+
+        let now = start + Duration::from_millis(1800);
+        let json = history.to_json(Resolution::Second, now);
+        assert!(json.starts_with(r#"{"resolution":"1s","interval_ms":1000,"now_ms":1800,"#));
+        assert!(json.ends_with(r#""points":[[0,4096,0,0],[1000,4096,3,0]]}"#));
+
+        let bytes = history.to_bytes(Resolution::Second, now);
+        assert_eq!(bytes.len(), HEADER_LEN + 2 * POINT_LEN);
+        assert_eq!(bytes[..4], MAGIC);
+        assert_eq!(u32::from_le_bytes(bytes[20..24].try_into().unwrap()), 2);
+        let second = &bytes[HEADER_LEN + POINT_LEN..];
+        assert_eq!(u64::from_le_bytes(second[..8].try_into().unwrap()), 1000);
+        assert_eq!(u32::from_le_bytes(second[16..20].try_into().unwrap()), 3);
+
+        assert_eq!(Resolution::from_name("10s"), Some(Resolution::TenSeconds));
+        assert_eq!(Resolution::from_name("1h"), None);
+        history.reset();
+        assert!(history.points(Resolution::Second, now).is_empty());
+    }
+}
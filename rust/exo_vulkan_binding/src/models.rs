@@ -0,0 +1,271 @@
+//! Concurrent model hosting
+//!
+//! Tracks several loaded models at once, each with its own memory quota, and
+//! unloads the least recently used idle models when a new model needs room.
+//! The manager only does the bookkeeping; callers free the device memory of
+//! the models it reports as evicted.
+
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+/// Model management errors
+#[derive(Error, Debug)]
+pub enum ModelError {
+    #[error("Model not found: {0}")]
+    NotFound(String),
+
+    #[error("Model already loaded: {0}")]
+    AlreadyLoaded(String),
+
+    #[error("Model {0} is in use")]
+    InUse(String),
+
+    #[error("Quota exceeded for model {model_id}: {requested} bytes requested, {available} available")]
+    QuotaExceeded {
+        model_id: String,
+        requested: u64,
+        available: u64,
+    },
+
+    #[error("Insufficient memory: {requested} bytes requested, {available} reclaimable")]
+    InsufficientMemory { requested: u64, available: u64 },
+}
+
+pub type ModelResult<T> = Result<T, ModelError>;
+
+/// A model resident in device memory
+#[derive(Clone, Debug)]
+pub struct LoadedModel {
+    pub model_id: String,
+    pub path: String,
+    /// Maximum bytes this model may allocate
+    pub quota_bytes: u64,
+    /// Bytes currently charged against the quota
+    pub used_bytes: u64,
+    /// Allocation handles owned by this model
+    pub allocations: Vec<String>,
+    /// Active users; models with users are never evicted
+    pub active_users: u32,
+    last_used: u64,
+}
+
+impl LoadedModel {
+    /// Whether the model can be unloaded to make room
+    pub fn is_idle(&self) -> bool {
+        self.active_users == 0
+    }
+}
+
+/// Hosts multiple models under a shared memory budget
+pub struct ModelManager {
+    capacity_bytes: u64,
+    models: HashMap<String, LoadedModel>,
+    clock: u64,
+}
+
+impl ModelManager {
+    /// Create a manager with a total budget across all model quotas
+    pub fn new(capacity_bytes: u64) -> Self {
+        Self {
+            capacity_bytes,
+            models: HashMap::new(),
+            clock: 0,
+        }
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    /// Sum of quotas reserved by loaded models
+    pub fn reserved_bytes(&self) -> u64 {
+        self.models.values().map(|m| m.quota_bytes).sum()
+    }
+
+    /// Total budget across all model quotas
+    pub fn capacity_bytes(&self) -> u64 {
+        self.capacity_bytes
+    }
+
+    /// Register a model and reserve its quota, evicting idle models as needed
+    ///
+    /// # Returns
+    /// The models that were evicted to make room; their allocations must be freed
+    pub fn load(&mut self, model_id: &str, path: &str, quota_bytes: u64) -> ModelResult<Vec<LoadedModel>> {
+        if self.models.contains_key(model_id) {
+            return Err(ModelError::AlreadyLoaded(model_id.to_string()));
+        }
+
+        let free = self.capacity_bytes.saturating_sub(self.reserved_bytes());
+        let evicted = if quota_bytes > free {
+            self.evict_lru(quota_bytes - free)?
+        } else {
+            Vec::new()
+        };
+
+        let last_used = self.tick();
+        self.models.insert(
+            model_id.to_string(),
+            LoadedModel {
+                model_id: model_id.to_string(),
+                path: path.to_string(),
+                quota_bytes,
+                used_bytes: 0,
+                allocations: Vec::new(),
+                active_users: 0,
+                last_used,
+            },
+        );
+
+        Ok(evicted)
+    }
+
+    /// Unload idle models, least recently used first, until `needed` bytes are free
+    fn evict_lru(&mut self, needed: u64) -> ModelResult<Vec<LoadedModel>> {
+        let mut idle: Vec<_> = self
+            .models
+            .values()
+            .filter(|m| m.is_idle())
+            .map(|m| (m.last_used, m.model_id.clone(), m.quota_bytes))
+            .collect();
+        idle.sort();
+
+        let reclaimable: u64 = idle.iter().map(|(_, _, quota)| quota).sum();
+        if reclaimable < needed {
+            return Err(ModelError::InsufficientMemory {
+                requested: needed,
+                available: reclaimable,
+            });
+        }
+
+        let mut freed = 0;
+        let mut evicted = Vec::new();
+        for (_, model_id, quota) in idle {
+            if freed >= needed {
+                break;
+            }
+            if let Some(model) = self.models.remove(&model_id) {
+                log::info!("Evicting idle model {} ({} bytes)", model_id, quota);
+                freed += quota;
+                evicted.push(model);
+            }
+        }
+        Ok(evicted)
+    }
+
+    /// Remove a model, returning it so its allocations can be freed
+    pub fn unload(&mut self, model_id: &str) -> ModelResult<LoadedModel> {
+        let model = self
+            .models
+            .get(model_id)
+            .ok_or_else(|| ModelError::NotFound(model_id.to_string()))?;
+        if !model.is_idle() {
+            return Err(ModelError::InUse(model_id.to_string()));
+        }
+        self.models
+            .remove(model_id)
+            .ok_or_else(|| ModelError::NotFound(model_id.to_string()))
+    }
+
+    /// Charge an allocation against a model's quota
+    pub fn charge(&mut self, model_id: &str, handle_id: &str, size_bytes: u64) -> ModelResult<()> {
+        let last_used = self.tick();
+        let model = self
+            .models
+            .get_mut(model_id)
+            .ok_or_else(|| ModelError::NotFound(model_id.to_string()))?;
+
+        let available = model.quota_bytes.saturating_sub(model.used_bytes);
+        if size_bytes > available {
+            return Err(ModelError::QuotaExceeded {
+                model_id: model_id.to_string(),
+                requested: size_bytes,
+                available,
+            });
+        }
+
+        model.used_bytes += size_bytes;
+        model.allocations.push(handle_id.to_string());
+        model.last_used = last_used;
+        Ok(())
+    }
+
+    /// Release an allocation previously charged to a model
+    pub fn uncharge(&mut self, model_id: &str, handle_id: &str, size_bytes: u64) -> ModelResult<()> {
+        let model = self
+            .models
+            .get_mut(model_id)
+            .ok_or_else(|| ModelError::NotFound(model_id.to_string()))?;
+        model.allocations.retain(|h| h != handle_id);
+        model.used_bytes = model.used_bytes.saturating_sub(size_bytes);
+        Ok(())
+    }
+
+    /// Mark a model as in use, protecting it from eviction
+    pub fn acquire(&mut self, model_id: &str) -> ModelResult<()> {
+        let last_used = self.tick();
+        let model = self
+            .models
+            .get_mut(model_id)
+            .ok_or_else(|| ModelError::NotFound(model_id.to_string()))?;
+        model.active_users += 1;
+        model.last_used = last_used;
+        Ok(())
+    }
+
+    /// Release a use taken with `acquire`
+    pub fn release(&mut self, model_id: &str) -> ModelResult<()> {
+        let model = self
+            .models
+            .get_mut(model_id)
+            .ok_or_else(|| ModelError::NotFound(model_id.to_string()))?;
+        model.active_users = model.active_users.saturating_sub(1);
+        Ok(())
+    }
+
+    /// Get a loaded model
+    pub fn get(&self, model_id: &str) -> ModelResult<&LoadedModel> {
+        self.models
+            .get(model_id)
+            .ok_or_else(|| ModelError::NotFound(model_id.to_string()))
+    }
+
+    /// Loaded models, most recently used first
+    pub fn list(&self) -> Vec<LoadedModel> {
+        let mut models: Vec<_> = self.models.values().cloned().collect();
+        models.sort_by(|a, b| b.last_used.cmp(&a.last_used));
+        models
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru_eviction_skips_active_models() {
+        let mut manager = ModelManager::new(300);
+        manager.load("draft", "/m/draft", 100).unwrap();
+        manager.load("target", "/m/target", 100).unwrap();
+        manager.load("embed", "/m/embed", 100).unwrap();
+        manager.acquire("draft").unwrap();
+
+        let evicted = manager.load("vision", "/m/vision", 100).unwrap();
+        assert_eq!(evicted.len(), 1);
+        assert_eq!(evicted[0].model_id, "target");
+        assert!(manager.get("draft").is_ok());
+    }
+
+    #[test]
+    fn test_quota_enforced() {
+        let mut manager = ModelManager::new(1000);
+        manager.load("m", "/m", 100).unwrap();
+        manager.charge("m", "a", 80).unwrap();
+        assert!(matches!(
+            manager.charge("m", "b", 40),
+            Err(ModelError::QuotaExceeded { available: 20, .. })
+        ));
+    }
+}
@@ -0,0 +1,476 @@
+//! Node profiles for pipeline partitioning
+//!
+//! A good layer split between heterogeneous phones depends on both how fast
+//! each node runs a layer and how slow the links between consecutive stages
+//! are: a fast GPU behind a poor Wi-Fi link may be worth fewer layers than a
+//! slower one on a good link. `probe_link` measures round-trip latency and
+//! throughput against a peer running `ProbeServer`; `NodeProfile` merges
+//! those link facts with the node's `DeviceInfo`, and `plan_pipeline` splits
+//! layers across an ordered chain of nodes to minimize the slowest stage.
+//!
+//! The probe speaks a tiny protocol over one TCP connection: `P` + 8 bytes
+//! is echoed back, `T` + u64 length + payload is answered with one byte
+//! once the payload has been read.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::ops::Range;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+
+use crate::DeviceInfo;
+
+const PING: u8 = b'P';
+const THROUGHPUT: u8 = b'T';
+const PING_BYTES: usize = 8;
+
+/// Profiling-related errors
+#[derive(Error, Debug)]
+pub enum ProfileError {
+    #[error("Probe I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Probe protocol error: {0}")]
+    Protocol(String),
+
+    #[error("No route from {from} to {to} in profiles")]
+    MissingLink { from: String, to: String },
+
+    #[error("Cannot place {layers} layers: {reason}")]
+    Infeasible { layers: usize, reason: String },
+}
+
+pub type ProfileResult<T> = Result<T, ProfileError>;
+
+/// Probe settings
+#[derive(Clone, Copy, Debug)]
+pub struct ProbeConfig {
+    pub ping_samples: usize,
+    /// Bytes sent by the throughput microbenchmark
+    pub payload_bytes: usize,
+    pub timeout: Duration,
+}
+
+impl Default for ProbeConfig {
+    fn default() -> Self {
+        Self {
+            ping_samples: 8,
+            payload_bytes: 4 << 20,
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Measured quality of the link to one peer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LinkStats {
+    /// Median round-trip time
+    pub rtt: Duration,
+    pub throughput_bytes_per_sec: f64,
+}
+
+impl LinkStats {
+    /// One-way time to send `bytes` over the link
+    pub fn transfer_time(&self, bytes: u64) -> Duration {
+        let streaming = if self.throughput_bytes_per_sec > 0.0 {
+            bytes as f64 / self.throughput_bytes_per_sec
+        } else {
+            0.0
+        };
+        self.rtt / 2 + Duration::from_secs_f64(streaming)
+    }
+}
+
+/// Answers probes from peers until shut down or dropped
+pub struct ProbeServer {
+    addr: SocketAddr,
+    stopping: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl ProbeServer {
+    /// Listen on `addr` (port 0 picks a free one)
+    pub fn bind(addr: impl ToSocketAddrs) -> ProfileResult<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let stopping = Arc::new(AtomicBool::new(false));
+        let stop_flag = stopping.clone();
+        let handle = thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stop_flag.load(Ordering::Acquire) {
+                    break;
+                }
+                let Ok(stream) = stream else {
+                    continue;
+                };
+                thread::spawn(move || {
+                    if let Err(e) = serve(stream) {
+                        log::debug!("Probe connection ended: {}", e);
+                    }
+                });
+            }
+        });
+        Ok(Self {
+            addr,
+            stopping,
+            handle: Some(handle),
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Stop accepting probes
+    pub fn shutdown(mut self) {
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            self.stopping.store(true, Ordering::Release);
+            // Wake the accept loop so it sees the flag
+            if TcpStream::connect_timeout(&self.addr, Duration::from_secs(1)).is_ok() {
+                let _ = handle.join();
+            }
+        }
+    }
+}
+
+impl Drop for ProbeServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn serve(mut stream: TcpStream) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+    let mut op = [0u8; 1];
+    let mut chunk = vec![0u8; 64 << 10];
+    loop {
+        if stream.read(&mut op)? == 0 {
+            return Ok(());
+        }
+        match op[0] {
+            PING => {
+                let mut ping = [0u8; PING_BYTES];
+                stream.read_exact(&mut ping)?;
+                stream.write_all(&ping)?;
+            }
+            THROUGHPUT => {
+                let mut len = [0u8; 8];
+                stream.read_exact(&mut len)?;
+                let mut remaining = u64::from_le_bytes(len);
+                while remaining > 0 {
+                    let want = remaining.min(chunk.len() as u64) as usize;
+                    stream.read_exact(&mut chunk[..want])?;
+                    remaining -= want as u64;
+                }
+                stream.write_all(&[1])?;
+            }
+            other => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unknown probe op {}", other),
+                ));
+            }
+        }
+    }
+}
+
+/// Measure latency and throughput to the `ProbeServer` at `addr`
+pub fn probe_link(addr: SocketAddr, config: &ProbeConfig) -> ProfileResult<LinkStats> {
+    let mut stream = TcpStream::connect_timeout(&addr, config.timeout)?;
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(config.timeout))?;
+    stream.set_write_timeout(Some(config.timeout))?;
+
+    let mut rtts = Vec::with_capacity(config.ping_samples.max(1));
+    for sample in 0..config.ping_samples.max(1) {
+        let ping = (sample as u64).to_le_bytes();
+        let start = Instant::now();
+        stream.write_all(&[PING])?;
+        stream.write_all(&ping)?;
+        let mut echo = [0u8; PING_BYTES];
+        stream.read_exact(&mut echo)?;
+        rtts.push(start.elapsed());
+        if echo != ping {
+            return Err(ProfileError::Protocol("ping echo mismatch".to_string()));
+        }
+    }
+    rtts.sort();
+    let rtt = rtts[rtts.len() / 2];
+
+    let payload = vec![0u8; config.payload_bytes];
+    let start = Instant::now();
+    stream.write_all(&[THROUGHPUT])?;
+    stream.write_all(&(payload.len() as u64).to_le_bytes())?;
+    stream.write_all(&payload)?;
+    let mut ack = [0u8; 1];
+    stream.read_exact(&mut ack)?;
+    // The ack costs one round trip on top of the streaming time
+    let streaming = start.elapsed().saturating_sub(rtt).as_secs_f64();
+    let throughput_bytes_per_sec = if streaming > 0.0 {
+        payload.len() as f64 / streaming
+    } else {
+        f64::INFINITY
+    };
+
+    Ok(LinkStats {
+        rtt,
+        throughput_bytes_per_sec,
+    })
+}
+
+/// Everything the planner knows about one node
+#[derive(Clone, Debug)]
+pub struct NodeProfile {
+    pub node_id: String,
+    pub devices: Vec<DeviceInfo>,
+    /// Measured time for one layer, when benchmarked; otherwise estimated
+    /// from device memory bandwidth
+    pub layer_time: Option<Duration>,
+    /// Links to peers by node id
+    pub links: HashMap<String, LinkStats>,
+}
+
+impl NodeProfile {
+    pub fn new(node_id: impl Into<String>, devices: Vec<DeviceInfo>) -> Self {
+        Self {
+            node_id: node_id.into(),
+            devices,
+            layer_time: None,
+            links: HashMap::new(),
+        }
+    }
+
+    /// Probe `peer` at `addr` and record the link
+    pub fn probe(&mut self, peer: &str, addr: SocketAddr, config: &ProbeConfig) -> ProfileResult<LinkStats> {
+        let stats = probe_link(addr, config)?;
+        self.links.insert(peer.to_string(), stats);
+        Ok(stats)
+    }
+
+    pub fn memory_bytes(&self) -> u64 {
+        self.devices.iter().map(|d| d.total_memory_bytes).sum()
+    }
+
+    /// Time for one decode step through one layer of `layer_bytes`
+    ///
+    /// Decode is bound by weight reads, so without a measurement this is
+    /// the time to stream the layer at the devices' combined bandwidth.
+    pub fn layer_time(&self, layer_bytes: u64) -> Option<Duration> {
+        if let Some(measured) = self.layer_time {
+            return Some(measured);
+        }
+        let bandwidth: f64 = self.devices.iter().map(|d| f64::from(d.bandwidth_gbps) * 1e9).sum();
+        (bandwidth > 0.0).then(|| Duration::from_secs_f64(layer_bytes as f64 / bandwidth))
+    }
+}
+
+/// Model facts the planner needs
+#[derive(Clone, Copy, Debug)]
+pub struct PipelineModel {
+    pub layers: usize,
+    pub layer_bytes: u64,
+    /// Bytes of the activation crossing each stage boundary per step
+    pub activation_bytes: u64,
+}
+
+/// One stage of a pipeline plan
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stage {
+    pub node_id: String,
+    pub layers: Range<usize>,
+    /// Compute plus the send to the next stage
+    pub step_time: Duration,
+}
+
+/// Per-step costs of one node in a chain
+#[derive(Clone, Copy, Debug)]
+struct StageCost {
+    layer: f64,
+    send: f64,
+    max_layers: usize,
+}
+
+impl StageCost {
+    /// Layers that fit in a per-stage time budget
+    fn fits(&self, budget: f64) -> usize {
+        if budget <= self.send {
+            0
+        } else if self.layer <= 0.0 {
+            self.max_layers
+        } else {
+            (((budget - self.send) / self.layer).floor() as usize).min(self.max_layers)
+        }
+    }
+}
+
+/// Split `model` across `chain` (in pipeline order) so the slowest stage,
+/// counting compute and the send to the next stage, is as fast as possible
+///
+/// Every node takes at least one layer, since each stage only has links
+/// to its neighbours; drop slow nodes and re-plan to try shorter chains.
+pub fn plan_pipeline(chain: &[NodeProfile], model: &PipelineModel) -> ProfileResult<Vec<Stage>> {
+    let infeasible = |reason: String| ProfileError::Infeasible {
+        layers: model.layers,
+        reason,
+    };
+    if chain.is_empty() || model.layers < chain.len() {
+        return Err(infeasible(format!("{} nodes need at least one layer each", chain.len())));
+    }
+
+    let mut costs = Vec::with_capacity(chain.len());
+    for (i, node) in chain.iter().enumerate() {
+        let layer = node
+            .layer_time(model.layer_bytes)
+            .ok_or_else(|| infeasible(format!("no speed estimate for {}", node.node_id)))?;
+        let send = match chain.get(i + 1) {
+            Some(next) => node
+                .links
+                .get(&next.node_id)
+                .ok_or_else(|| ProfileError::MissingLink {
+                    from: node.node_id.clone(),
+                    to: next.node_id.clone(),
+                })?
+                .transfer_time(model.activation_bytes),
+            None => Duration::ZERO,
+        };
+        let max_layers = node
+            .memory_bytes()
+            .checked_div(model.layer_bytes)
+            .map_or(model.layers, |n| n.min(model.layers as u64) as usize);
+        if max_layers == 0 {
+            return Err(infeasible(format!("{} cannot hold one layer", node.node_id)));
+        }
+        costs.push(StageCost {
+            layer: layer.as_secs_f64(),
+            send: send.as_secs_f64(),
+            max_layers,
+        });
+    }
+    if costs.iter().map(|c| c.max_layers).sum::<usize>() < model.layers {
+        return Err(infeasible("not enough memory across nodes".to_string()));
+    }
+
+    let capacity = |budget: f64| -> usize {
+        let fits: Vec<usize> = costs.iter().map(|c| c.fits(budget)).collect();
+        if fits.contains(&0) { 0 } else { fits.iter().sum() }
+    };
+
+    // Binary search the smallest per-stage budget that fits every layer
+    let mut hi = costs
+        .iter()
+        .map(|c| c.layer * model.layers as f64 + c.send)
+        .fold(f64::MIN_POSITIVE, f64::max);
+    while capacity(hi) < model.layers {
+        hi *= 2.0;
+    }
+    let mut lo = 0.0;
+    for _ in 0..64 {
+        let mid = (lo + hi) / 2.0;
+        if capacity(mid) >= model.layers {
+            hi = mid;
+        } else {
+            lo = mid;
+        }
+    }
+
+    let mut stages = Vec::with_capacity(chain.len());
+    let mut next_layer = 0;
+    for (i, (node, cost)) in chain.iter().zip(&costs).enumerate() {
+        // Leave at least one layer for every later stage
+        let remaining = model.layers - next_layer;
+        let take = cost.fits(hi).min(remaining - (chain.len() - i - 1));
+        stages.push(Stage {
+            node_id: node.node_id.clone(),
+            layers: next_layer..next_layer + take,
+            step_time: Duration::from_secs_f64(cost.layer * take as f64 + cost.send),
+        });
+        next_layer += take;
+    }
+    Ok(stages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: &str, layer_ms: u64, memory_bytes: u64) -> NodeProfile {
+        let device = DeviceInfo {
+            device_id: format!("{}:0", id),
+            name: id.to_string(),
+            vendor: "test".to_string(),
+            vendor_id: 0,
+            driver_version: "0".to_string(),
+            api_version: "1.1.0".to_string(),
+            max_allocation_size: 1 << 30,
+            compute_units: 1,
+            compute_units_source: crate::compute_units::ComputeUnitSource::Estimated,
+            subgroup_size: 32,
+            max_clock_mhz: None,
+            total_memory_bytes: memory_bytes,
+            bandwidth_gbps: 0.0,
+        };
+        let mut profile = NodeProfile::new(id, vec![device]);
+        profile.layer_time = Some(Duration::from_millis(layer_ms));
+        profile
+    }
+
+    #[test]
+    fn test_plan_accounts_for_speed_link_and_memory() {
+        let model = PipelineModel {
+            layers: 12,
+            layer_bytes: 100,
+            activation_bytes: 1000,
+        };
+        let mut fast = node("fast", 1, u64::MAX);
+        let slow = node("slow", 2, u64::MAX);
+        let link = |ms: u64| LinkStats {
+            rtt: Duration::from_millis(2 * ms),
+            throughput_bytes_per_sec: f64::INFINITY,
+        };
+
+        fast.links.insert("slow".to_string(), link(0));
+        let plan = plan_pipeline(&[fast.clone(), slow.clone()], &model).unwrap();
+        assert_eq!(plan[0].layers, 0..8);
+        assert_eq!(plan[1].layers, 8..12);
+
+        // A 4 ms link costs the fast node a layer: 7 + 4 ms vs 5 * 2 ms
+        fast.links.insert("slow".to_string(), link(4));
+        let plan = plan_pipeline(&[fast.clone(), slow.clone()], &model).unwrap();
+        assert_eq!(plan[0].layers, 0..7);
+        assert_eq!(plan[0].step_time, Duration::from_millis(11));
+
+        // Memory for only 3 layers caps the fast node
+        let mut small = node("fast", 1, 300);
+        small.links.insert("slow".to_string(), link(0));
+        let plan = plan_pipeline(&[small, slow.clone()], &model).unwrap();
+        assert_eq!(plan[0].layers, 0..3);
+        assert!(matches!(
+            plan_pipeline(&[slow.clone(), fast], &model),
+            Err(ProfileError::MissingLink { .. })
+        ));
+    }
+
+    #[test]
+    fn test_probe_loopback() {
+        let server = ProbeServer::bind("127.0.0.1:0").unwrap();
+        let config = ProbeConfig {
+            ping_samples: 3,
+            payload_bytes: 1 << 16,
+            timeout: Duration::from_secs(5),
+        };
+        let mut profile = NodeProfile::new("a", Vec::new());
+        let stats = profile.probe("b", server.local_addr(), &config).unwrap();
+        assert!(stats.throughput_bytes_per_sec > 0.0);
+        assert_eq!(profile.links["b"], stats);
+        server.shutdown();
+    }
+}
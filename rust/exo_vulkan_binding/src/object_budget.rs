@@ -0,0 +1,281 @@
+//! Vulkan object count budgets and recycling
+//!
+//! Some mobile drivers cap live objects far below what the spec suggests
+//! (a few thousand fences, or a handful of descriptor pools) and fail
+//! creation with `ERROR_OUT_OF_HOST_MEMORY` or worse once a long
+//! multi-model session has leaked its way there. This module counts live
+//! objects per kind against configurable ceilings, so exhaustion surfaces as
+//! a clear `BudgetExceeded` error naming the kind, and provides `Recycler`
+//! pools so short-lived objects are reused rather than created per
+//! submission.
+//!
+//! Creation sites call `acquire` before creating an object and `release`
+//! when destroying it; `command::Fence`, `command::Semaphore` and command
+//! buffers allocated through `command::CommandPool` are counted this way.
+
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use thiserror::Error;
+
+/// Budget errors
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum BudgetError {
+    #[error("{kind} budget exhausted: {live} live, limit {limit}")]
+    BudgetExceeded {
+        kind: &'static str,
+        live: u64,
+        limit: u64,
+    },
+}
+
+pub type BudgetResult<T> = Result<T, BudgetError>;
+
+/// Kinds of counted Vulkan objects
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ObjectKind {
+    Pipeline,
+    DescriptorPool,
+    CommandBuffer,
+    Fence,
+    Semaphore,
+}
+
+impl ObjectKind {
+    pub const ALL: [ObjectKind; 5] = [
+        ObjectKind::Pipeline,
+        ObjectKind::DescriptorPool,
+        ObjectKind::CommandBuffer,
+        ObjectKind::Fence,
+        ObjectKind::Semaphore,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ObjectKind::Pipeline => "pipeline",
+            ObjectKind::DescriptorPool => "descriptor_pool",
+            ObjectKind::CommandBuffer => "command_buffer",
+            ObjectKind::Fence => "fence",
+            ObjectKind::Semaphore => "semaphore",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Live count and ceiling of one kind
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct KindUsage {
+    pub live: u64,
+    /// Most live at once
+    pub peak: u64,
+    pub limit: Option<u64>,
+}
+
+/// Live object counts with per-kind ceilings
+#[derive(Debug, Default)]
+pub struct ObjectBudget {
+    usage: Mutex<[KindUsage; 5]>,
+}
+
+impl ObjectBudget {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cap live objects of `kind`; None removes the cap
+    ///
+    /// Lowering a limit below the live count only blocks new objects.
+    pub fn set_limit(&self, kind: ObjectKind, limit: Option<u64>) {
+        self.usage.lock()[kind.index()].limit = limit;
+    }
+
+    /// Count `count` new objects of `kind`, failing if that would pass its limit
+    pub fn acquire(&self, kind: ObjectKind, count: u64) -> BudgetResult<()> {
+        let mut usage = self.usage.lock();
+        let entry = &mut usage[kind.index()];
+        if let Some(limit) = entry.limit
+            && entry.live + count > limit
+        {
+            log::warn!("{} budget exhausted ({} live, limit {})", kind.name(), entry.live, limit);
+            return Err(BudgetError::BudgetExceeded {
+                kind: kind.name(),
+                live: entry.live,
+                limit,
+            });
+        }
+        entry.live += count;
+        entry.peak = entry.peak.max(entry.live);
+        Ok(())
+    }
+
+    /// Uncount `count` destroyed objects of `kind`
+    pub fn release(&self, kind: ObjectKind, count: u64) {
+        let mut usage = self.usage.lock();
+        let entry = &mut usage[kind.index()];
+        entry.live = entry.live.saturating_sub(count);
+    }
+
+    pub fn usage(&self, kind: ObjectKind) -> KindUsage {
+        self.usage.lock()[kind.index()]
+    }
+
+    /// JSON: `{"pipeline":{"live":n,"peak":n,"limit":n|null},...}`
+    pub fn to_json(&self) -> String {
+        let usage = *self.usage.lock();
+        let kinds: Vec<String> = ObjectKind::ALL
+            .iter()
+            .map(|&kind| {
+                let u = usage[kind.index()];
+                format!(
+                    r#""{}":{{"live":{},"peak":{},"limit":{}}}"#,
+                    kind.name(),
+                    u.live,
+                    u.peak,
+                    u.limit.map_or_else(|| "null".to_string(), |l| l.to_string())
+                )
+            })
+            .collect();
+        format!("{{{}}}", kinds.join(","))
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref GLOBAL: ObjectBudget = ObjectBudget::new();
+}
+
+/// The process-wide budget used by the creation sites in this crate
+pub fn global() -> &'static ObjectBudget {
+    &GLOBAL
+}
+
+/// `global().acquire`
+pub fn acquire(kind: ObjectKind, count: u64) -> BudgetResult<()> {
+    GLOBAL.acquire(kind, count)
+}
+
+/// `global().release`
+pub fn release(kind: ObjectKind, count: u64) {
+    GLOBAL.release(kind, count)
+}
+
+/// Objects that can go back to a `Recycler`
+pub trait Recyclable {
+    /// Return the object to its initial state; false drops it instead
+    fn recycle(&self) -> bool;
+}
+
+/// Pool of idle objects for reuse
+///
+/// Holds at most `max_idle` objects; extras are dropped (and so destroyed)
+/// when returned.
+pub struct Recycler<T> {
+    idle: Mutex<Vec<T>>,
+    max_idle: usize,
+}
+
+impl<T: Recyclable> Recycler<T> {
+    pub fn new(max_idle: usize) -> Arc<Self> {
+        Arc::new(Self {
+            idle: Mutex::new(Vec::new()),
+            max_idle,
+        })
+    }
+
+    /// An idle object, or a new one from `create`
+    pub fn take<E>(&self, create: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+        match self.idle.lock().pop() {
+            Some(object) => Ok(object),
+            None => create(),
+        }
+    }
+
+    /// Hand back an object no pending work uses
+    pub fn put(&self, object: T) {
+        if !object.recycle() {
+            return;
+        }
+        let mut idle = self.idle.lock();
+        if idle.len() < self.max_idle {
+            idle.push(object);
+        }
+    }
+
+    pub fn idle(&self) -> usize {
+        self.idle.lock().len()
+    }
+
+    /// Destroy every idle object
+    pub fn clear(&self) {
+        self.idle.lock().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit_blocks_new_objects() {
+        let budget = ObjectBudget::new();
+        budget.set_limit(ObjectKind::Fence, Some(2));
+        budget.acquire(ObjectKind::Fence, 2).unwrap();
+        assert_eq!(
+            budget.acquire(ObjectKind::Fence, 1),
+            Err(BudgetError::BudgetExceeded {
+                kind: "fence",
+                live: 2,
+                limit: 2
+            })
+        );
+        budget.acquire(ObjectKind::Semaphore, 10).unwrap();
+
+        budget.release(ObjectKind::Fence, 1);
+        budget.acquire(ObjectKind::Fence, 1).unwrap();
+        assert_eq!(
+            budget.usage(ObjectKind::Fence),
+            KindUsage {
+                live: 2,
+                peak: 2,
+                limit: Some(2)
+            }
+        );
+        assert!(budget.to_json().contains(r#""semaphore":{"live":10,"peak":10,"limit":null}"#));
+    }
+
+    struct Token {
+        reusable: bool,
+    }
+
+    impl Recyclable for Token {
+        fn recycle(&self) -> bool {
+            self.reusable
+        }
+    }
+
+    #[test]
+    fn test_recycler_reuses_up_to_max_idle() {
+        let recycler = Recycler::new(1);
+        let created = std::cell::Cell::new(0);
+        let make = || {
+            created.set(created.get() + 1);
+            Ok::<_, ()>(Token { reusable: true })
+        };
+        let a = recycler.take(make).unwrap();
+        let b = recycler.take(make).unwrap();
+        recycler.put(a);
+        recycler.put(b);
+        assert_eq!(recycler.idle(), 1);
+        recycler.take(make).unwrap();
+        assert_eq!(created.get(), 2);
+
+        recycler.put(Token { reusable: false });
+        assert_eq!(recycler.idle(), 0);
+    }
+}
@@ -0,0 +1,751 @@
+//! Host APIs for device-side tensor ops
+//!
+//! Each op validates shapes on the host, derives a dispatch plan and push
+//! constants, and records its kernels into a caller-provided command buffer.
+//! A host reference implementation following the same plan backs the tests
+//! and serves as the CPU fallback. GLSL sources live in `shaders/`.
+
+use std::mem::size_of;
+
+use ash::vk;
+use thiserror::Error;
+
+use crate::memory::BufferRange;
+use crate::tensor::{ELEMENT_SIZE, Layout, MAX_RANK, Tensor, TensorError};
+
+/// Op-related errors
+#[derive(Error, Debug)]
+pub enum OpsError {
+    #[error(transparent)]
+    Tensor(#[from] TensorError),
+
+    #[error("Shape mismatch: {0}")]
+    ShapeMismatch(String),
+
+    #[error("Scratch buffer too small: need {needed} bytes, have {available}")]
+    ScratchTooSmall { needed: u64, available: u64 },
+}
+
+pub type OpsResult<T> = Result<T, OpsError>;
+
+/// A compute pipeline ready to dispatch
+///
+/// Every op kernel reads binding 0 and writes binding 1 of its descriptor set.
+#[derive(Clone, Copy, Debug)]
+pub struct KernelBinding {
+    pub pipeline: vk::Pipeline,
+    pub layout: vk::PipelineLayout,
+    pub descriptor_set: vk::DescriptorSet,
+}
+
+impl KernelBinding {
+    /// Point bindings 0 and 1 at `src` and `dst`
+    ///
+    /// # Safety Requirements
+    /// - The descriptor set must not be in use by pending command buffers
+    pub unsafe fn bind_buffers(&self, device: &ash::Device, src: &BufferRange, dst: &BufferRange) {
+        let src_info = [src.descriptor_info()];
+        let dst_info = [dst.descriptor_info()];
+        let writes = [
+            vk::WriteDescriptorSet::default()
+                .dst_set(self.descriptor_set)
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(&src_info),
+            vk::WriteDescriptorSet::default()
+                .dst_set(self.descriptor_set)
+                .dst_binding(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(&dst_info),
+        ];
+        // SAFETY: caller guarantees the set is not in use
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+    }
+
+    /// Point one extra `binding` (beyond 0 and 1) at `range`
+    ///
+    /// # Safety Requirements
+    /// - The descriptor set must not be in use by pending command buffers
+    pub unsafe fn bind_buffer(&self, device: &ash::Device, binding: u32, range: &BufferRange) {
+        let info = [range.descriptor_info()];
+        let write = vk::WriteDescriptorSet::default()
+            .dst_set(self.descriptor_set)
+            .dst_binding(binding)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .buffer_info(&info);
+        // SAFETY: caller guarantees the set is not in use
+        unsafe { device.update_descriptor_sets(&[write], &[]) };
+    }
+
+    /// Record a dispatch with `push` as push constants
+    ///
+    /// # Safety Requirements
+    /// - `cmd` must be in the recording state
+    pub unsafe fn record<P: Copy>(&self, device: &ash::Device, cmd: vk::CommandBuffer, push: &P, groups: [u32; 3]) {
+        // SAFETY: P is a repr(C) plain-data push constant struct
+        let bytes = unsafe { std::slice::from_raw_parts(push as *const P as *const u8, size_of::<P>()) };
+        // SAFETY: caller guarantees cmd is recording
+        unsafe {
+            device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::COMPUTE, self.pipeline);
+            device.cmd_bind_descriptor_sets(
+                cmd,
+                vk::PipelineBindPoint::COMPUTE,
+                self.layout,
+                0,
+                &[self.descriptor_set],
+                &[],
+            );
+            device.cmd_push_constants(cmd, self.layout, vk::ShaderStageFlags::COMPUTE, 0, bytes);
+            device.cmd_dispatch(cmd, groups[0], groups[1], groups[2]);
+        }
+    }
+}
+
+/// Make compute writes visible to the next compute dispatch
+///
+/// # Safety Requirements
+/// - `cmd` must be in the recording state
+pub unsafe fn compute_barrier(device: &ash::Device, cmd: vk::CommandBuffer) {
+    let barrier = vk::MemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ);
+    // SAFETY: caller guarantees cmd is recording
+    unsafe {
+        device.cmd_pipeline_barrier(
+            cmd,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::DependencyFlags::empty(),
+            &[barrier],
+            &[],
+            &[],
+        );
+    }
+}
+
+/// Workgroup size of the reduction kernels
+pub const REDUCE_WORKGROUP_SIZE: u32 = 256;
+
+/// Elements each thread folds before the shared-memory tree
+pub const REDUCE_ELEMENTS_PER_THREAD: u32 = 4;
+
+/// Largest dispatch dimension guaranteed by the spec
+pub(crate) const MAX_GROUP_COUNT: u32 = 65535;
+
+/// Reduction operator
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReduceOp {
+    Sum,
+    Mean,
+    Max,
+    Min,
+}
+
+impl ReduceOp {
+    /// Value passed to the kernel
+    pub fn code(self) -> u32 {
+        match self {
+            ReduceOp::Sum => 0,
+            ReduceOp::Mean => 1,
+            ReduceOp::Max => 2,
+            ReduceOp::Min => 3,
+        }
+    }
+
+    pub fn identity(self) -> f32 {
+        match self {
+            ReduceOp::Sum | ReduceOp::Mean => 0.0,
+            ReduceOp::Max => f32::NEG_INFINITY,
+            ReduceOp::Min => f32::INFINITY,
+        }
+    }
+
+    pub fn combine(self, a: f32, b: f32) -> f32 {
+        match self {
+            ReduceOp::Sum | ReduceOp::Mean => a + b,
+            ReduceOp::Max => a.max(b),
+            ReduceOp::Min => a.min(b),
+        }
+    }
+
+    /// Final value from the combined accumulator over `count` elements
+    pub fn finalize(self, acc: f32, count: usize) -> f32 {
+        match self {
+            ReduceOp::Mean if count > 0 => acc / count as f32,
+            _ => acc,
+        }
+    }
+}
+
+/// Push constants shared by both reduction passes
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReducePushConstants {
+    /// Rank of the output index space
+    pub rank: u32,
+    pub axis_len: u32,
+    pub axis_stride: u32,
+    /// Partials per output; 1 means pass 1 writes the final value
+    pub chunks: u32,
+    pub op: u32,
+    pub outputs: u32,
+    pub _pad: [u32; 2],
+    pub out_shape: [u32; MAX_RANK],
+    /// Input strides of the non-reduced dims, matching `out_shape`
+    pub in_strides: [u32; MAX_RANK],
+}
+
+/// Dispatch plan for reducing one axis of a strided tensor
+///
+/// Pass 1 runs `chunks` workgroups per output, each folding
+/// `REDUCE_WORKGROUP_SIZE * REDUCE_ELEMENTS_PER_THREAD` elements of the axis
+/// into a partial. Pass 2 folds the partials of each output and applies the
+/// finalizer. Short axes need only pass 1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReducePlan {
+    pub push: ReducePushConstants,
+    pub output_layout: Layout,
+    /// Input layout with the reduced axis removed
+    pub outer_layout: Layout,
+}
+
+impl ReducePlan {
+    /// Plan a reduction of `axis`
+    pub fn new(input: &Layout, axis: usize, op: ReduceOp) -> OpsResult<Self> {
+        input.check_axis(axis)?;
+        input.check_kernel_rank()?;
+
+        let outer_layout = input.remove_axis(axis)?;
+        let output_layout = Layout::contiguous(outer_layout.shape());
+        let axis_len = input.shape()[axis];
+        let per_group = (REDUCE_WORKGROUP_SIZE * REDUCE_ELEMENTS_PER_THREAD) as usize;
+
+        let mut push = ReducePushConstants {
+            rank: outer_layout.rank() as u32,
+            axis_len: axis_len as u32,
+            axis_stride: input.strides()[axis] as u32,
+            chunks: axis_len.div_ceil(per_group).max(1) as u32,
+            op: op.code(),
+            outputs: outer_layout.numel() as u32,
+            ..Default::default()
+        };
+        for (i, (&dim, &stride)) in outer_layout.shape().iter().zip(outer_layout.strides()).enumerate() {
+            push.out_shape[i] = dim as u32;
+            push.in_strides[i] = stride as u32;
+        }
+
+        Ok(Self {
+            push,
+            output_layout,
+            outer_layout,
+        })
+    }
+
+    /// Whether a second pass over partials is needed
+    pub fn is_two_pass(&self) -> bool {
+        self.push.chunks > 1
+    }
+
+    /// Scratch bytes needed for partials
+    pub fn scratch_bytes(&self) -> u64 {
+        if self.is_two_pass() {
+            self.push.outputs as u64 * self.push.chunks as u64 * ELEMENT_SIZE
+        } else {
+            0
+        }
+    }
+
+    /// Workgroup counts for pass 1
+    pub fn pass1_groups(&self) -> [u32; 3] {
+        let outputs = self.push.outputs.max(1);
+        [
+            self.push.chunks,
+            outputs.min(MAX_GROUP_COUNT),
+            outputs.div_ceil(MAX_GROUP_COUNT),
+        ]
+    }
+
+    /// Workgroup counts for pass 2
+    pub fn pass2_groups(&self) -> [u32; 3] {
+        let outputs = self.push.outputs.max(1);
+        [1, outputs.min(MAX_GROUP_COUNT), outputs.div_ceil(MAX_GROUP_COUNT)]
+    }
+}
+
+/// The two reduction pipelines (`shaders/reduce.comp` with `PASS` = 1 and 2)
+#[derive(Clone, Copy, Debug)]
+pub struct ReduceKernels {
+    pub pass1: KernelBinding,
+    pub pass2: KernelBinding,
+}
+
+/// Record a reduction of `input` along `axis` into `output`
+///
+/// `output` must be contiguous with the input's shape minus `axis`. `scratch`
+/// holds the partials and is required when `ReducePlan::is_two_pass`.
+///
+/// # Safety Requirements
+/// - `cmd` must be in the recording state
+/// - The kernels' descriptor sets must not be in use by pending work
+/// - All buffers must stay alive until the command buffer completes
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn reduce(
+    device: &ash::Device,
+    cmd: vk::CommandBuffer,
+    kernels: &ReduceKernels,
+    input: &Tensor,
+    axis: usize,
+    op: ReduceOp,
+    scratch: Option<&BufferRange>,
+    output: &Tensor,
+) -> OpsResult<ReducePlan> {
+    let plan = ReducePlan::new(&input.layout, axis, op)?;
+    if output.layout != plan.output_layout {
+        return Err(OpsError::ShapeMismatch(format!(
+            "reduce output must be contiguous {:?}, got {:?}",
+            plan.output_layout.shape(),
+            output.layout.shape()
+        )));
+    }
+
+    if !plan.is_two_pass() {
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe {
+            kernels.pass1.bind_buffers(device, &input.range, &output.range);
+            kernels.pass1.record(device, cmd, &plan.push, plan.pass1_groups());
+        }
+        return Ok(plan);
+    }
+
+    let needed = plan.scratch_bytes();
+    let available = scratch.map_or(0, |s| s.size);
+    let scratch = scratch
+        .filter(|s| s.size >= needed)
+        .ok_or(OpsError::ScratchTooSmall { needed, available })?;
+
+    // SAFETY: forwarded from the caller's guarantees
+    unsafe {
+        kernels.pass1.bind_buffers(device, &input.range, scratch);
+        kernels.pass1.record(device, cmd, &plan.push, plan.pass1_groups());
+        compute_barrier(device, cmd);
+        kernels.pass2.bind_buffers(device, scratch, &output.range);
+        kernels.pass2.record(device, cmd, &plan.push, plan.pass2_groups());
+    }
+    Ok(plan)
+}
+
+/// Host reference for `reduce`, folding per chunk like the kernels
+///
+/// `data` is the whole backing buffer of `layout`.
+pub fn reduce_host(data: &[f32], layout: &Layout, axis: usize, op: ReduceOp) -> OpsResult<Vec<f32>> {
+    let plan = ReducePlan::new(layout, axis, op)?;
+    if data.len() < layout.span() {
+        return Err(TensorError::BufferTooSmall {
+            needed: layout.span() as u64 * ELEMENT_SIZE,
+            available: data.len() as u64 * ELEMENT_SIZE,
+        }
+        .into());
+    }
+
+    let axis_len = plan.push.axis_len as usize;
+    let axis_stride = plan.push.axis_stride as usize;
+    let per_group = (REDUCE_WORKGROUP_SIZE * REDUCE_ELEMENTS_PER_THREAD) as usize;
+
+    let outputs = (0..plan.outer_layout.numel())
+        .map(|out| {
+            let base = plan.outer_layout.offset_of(out);
+            let partials = (0..plan.push.chunks as usize).map(|chunk| {
+                let start = chunk * per_group;
+                let end = (start + per_group).min(axis_len);
+                (start..end).fold(op.identity(), |acc, i| op.combine(acc, data[base + i * axis_stride]))
+            });
+            let acc = partials.fold(op.identity(), |acc, p| op.combine(acc, p));
+            op.finalize(acc, axis_len)
+        })
+        .collect();
+    Ok(outputs)
+}
+
+/// Tile edge of the tiled transpose kernel
+pub const TRANSPOSE_TILE: u32 = 32;
+
+/// Rows of the tile each workgroup row handles per step (workgroup is 32x8)
+pub const TRANSPOSE_BLOCK_ROWS: u32 = 8;
+
+/// Threads per workgroup of the general permute kernel
+pub const PERMUTE_WORKGROUP_SIZE: u32 = 256;
+
+/// Push constants shared by both permute kernels
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PermutePushConstants {
+    pub rank: u32,
+    pub numel: u32,
+    /// Output dim read with unit input stride (tiled kernel only)
+    pub tile_dim: u32,
+    pub _pad: u32,
+    pub out_shape: [u32; MAX_RANK],
+    /// Input stride of each output dim
+    pub in_strides: [u32; MAX_RANK],
+    pub out_strides: [u32; MAX_RANK],
+}
+
+/// Which permute kernel a plan uses
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PermuteKernel {
+    /// Shared-memory tiles so both reads and writes are coalesced
+    Tiled,
+    /// One thread per output element
+    General,
+}
+
+/// Dispatch plan for materializing a permuted view
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PermutePlan {
+    pub push: PermutePushConstants,
+    pub kernel: PermuteKernel,
+    pub groups: [u32; 3],
+    pub output_layout: Layout,
+}
+
+impl PermutePlan {
+    /// Plan writing `input` permuted by `perm` into a contiguous output
+    ///
+    /// The tiled kernel is used when the input's unit-stride dim does not end
+    /// up innermost in the output, which is when a naive copy would scatter
+    /// either its reads or its writes.
+    pub fn new(input: &Layout, perm: &[usize]) -> OpsResult<Self> {
+        input.check_kernel_rank()?;
+        let view = input.permute(perm)?;
+        let output_layout = Layout::contiguous(view.shape());
+        let rank = view.rank();
+
+        let mut push = PermutePushConstants {
+            rank: rank as u32,
+            numel: view.numel() as u32,
+            ..Default::default()
+        };
+        for i in 0..rank {
+            push.out_shape[i] = view.shape()[i] as u32;
+            push.in_strides[i] = view.strides()[i] as u32;
+            push.out_strides[i] = output_layout.strides()[i] as u32;
+        }
+
+        let tile_dim = (rank >= 2)
+            .then(|| (0..rank - 1).find(|&d| view.strides()[d] == 1 && view.shape()[d] > 1))
+            .flatten();
+        let tiled = tile_dim.and_then(|dim| {
+            let inner = view.shape()[rank - 1];
+            let batch = view.numel() / (inner * view.shape()[dim]).max(1);
+            let groups = [
+                (inner as u32).div_ceil(TRANSPOSE_TILE),
+                (view.shape()[dim] as u32).div_ceil(TRANSPOSE_TILE),
+                batch as u32,
+            ];
+            (groups.iter().all(|&g| g <= MAX_GROUP_COUNT)).then_some((dim, groups))
+        });
+
+        let (kernel, groups) = match tiled {
+            Some((dim, groups)) => {
+                push.tile_dim = dim as u32;
+                (PermuteKernel::Tiled, groups)
+            }
+            None => {
+                let groups = push.numel.div_ceil(PERMUTE_WORKGROUP_SIZE).max(1);
+                (
+                    PermuteKernel::General,
+                    [groups.min(MAX_GROUP_COUNT), groups.div_ceil(MAX_GROUP_COUNT), 1],
+                )
+            }
+        };
+
+        Ok(Self {
+            push,
+            kernel,
+            groups,
+            output_layout,
+        })
+    }
+}
+
+/// Permute pipelines (`shaders/permute.comp` with `TILED` = 1 and 0)
+#[derive(Clone, Copy, Debug)]
+pub struct PermuteKernels {
+    pub tiled: KernelBinding,
+    pub general: KernelBinding,
+}
+
+/// Record a copy of `input` permuted by `perm` into contiguous `output`
+///
+/// `perm[i]` is the input dim that becomes output dim `i`.
+///
+/// # Safety Requirements
+/// - `cmd` must be in the recording state
+/// - The kernels' descriptor sets must not be in use by pending work
+/// - Both buffers must stay alive until the command buffer completes
+pub unsafe fn permute(
+    device: &ash::Device,
+    cmd: vk::CommandBuffer,
+    kernels: &PermuteKernels,
+    input: &Tensor,
+    perm: &[usize],
+    output: &Tensor,
+) -> OpsResult<PermutePlan> {
+    let plan = PermutePlan::new(&input.layout, perm)?;
+    if output.layout != plan.output_layout {
+        return Err(OpsError::ShapeMismatch(format!(
+            "permute output must be contiguous {:?}, got {:?}",
+            plan.output_layout.shape(),
+            output.layout.shape()
+        )));
+    }
+
+    let kernel = match plan.kernel {
+        PermuteKernel::Tiled => &kernels.tiled,
+        PermuteKernel::General => &kernels.general,
+    };
+    // SAFETY: forwarded from the caller's guarantees
+    unsafe {
+        kernel.bind_buffers(device, &input.range, &output.range);
+        kernel.record(device, cmd, &plan.push, plan.groups);
+    }
+    Ok(plan)
+}
+
+/// Record a swap of dims `dim0` and `dim1` into contiguous `output`
+///
+/// # Safety Requirements
+/// - Same as `permute`
+pub unsafe fn transpose(
+    device: &ash::Device,
+    cmd: vk::CommandBuffer,
+    kernels: &PermuteKernels,
+    input: &Tensor,
+    dim0: usize,
+    dim1: usize,
+    output: &Tensor,
+) -> OpsResult<PermutePlan> {
+    let perm = transpose_perm(input.layout.rank(), dim0, dim1)?;
+    // SAFETY: forwarded from the caller's guarantees
+    unsafe { permute(device, cmd, kernels, input, &perm, output) }
+}
+
+/// Permutation swapping `dim0` and `dim1`
+pub fn transpose_perm(rank: usize, dim0: usize, dim1: usize) -> OpsResult<Vec<usize>> {
+    for axis in [dim0, dim1] {
+        if axis >= rank {
+            return Err(TensorError::AxisOutOfRange { axis, rank }.into());
+        }
+    }
+    let mut perm: Vec<usize> = (0..rank).collect();
+    perm.swap(dim0, dim1);
+    Ok(perm)
+}
+
+/// Host reference for `permute`
+pub fn permute_host(data: &[f32], layout: &Layout, perm: &[usize]) -> OpsResult<Vec<f32>> {
+    let view = layout.permute(perm)?;
+    if data.len() < layout.span() {
+        return Err(TensorError::BufferTooSmall {
+            needed: layout.span() as u64 * ELEMENT_SIZE,
+            available: data.len() as u64 * ELEMENT_SIZE,
+        }
+        .into());
+    }
+    Ok((0..view.numel()).map(|i| data[view.offset_of(i)]).collect())
+}
+
+/// Threads per workgroup of the NaN/Inf scan kernel
+pub const CHECK_FINITE_WORKGROUP_SIZE: u32 = 256;
+
+/// Bytes of the flag buffer written by `check_finite`
+pub const CHECK_FINITE_FLAG_BYTES: u64 = 8;
+
+const FLAG_NAN: u32 = 1;
+const FLAG_INF: u32 = 2;
+
+/// Push constants of the NaN/Inf scan kernel
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CheckFinitePushConstants {
+    pub rank: u32,
+    pub numel: u32,
+    pub _pad: [u32; 2],
+    pub shape: [u32; MAX_RANK],
+    pub strides: [u32; MAX_RANK],
+}
+
+/// Result of a NaN/Inf scan, decoded from the flag buffer
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NonFinite {
+    pub has_nan: bool,
+    pub has_inf: bool,
+    /// Lowest logical index holding a non-finite value
+    pub first_index: Option<u32>,
+}
+
+impl NonFinite {
+    /// Decode the two flag words `[flags, first_index]`
+    pub fn from_flags(words: [u32; 2]) -> Self {
+        Self {
+            has_nan: words[0] & FLAG_NAN != 0,
+            has_inf: words[0] & FLAG_INF != 0,
+            first_index: (words[1] != u32::MAX).then_some(words[1]),
+        }
+    }
+
+    /// Decode from flag buffer bytes read back to the host
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let word = |i: usize| {
+            bytes
+                .get(i * 4..i * 4 + 4)
+                .map_or(0, |b| u32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+        };
+        Self::from_flags([word(0), word(1)])
+    }
+
+    pub fn is_finite(&self) -> bool {
+        !self.has_nan && !self.has_inf
+    }
+}
+
+/// Record a scan of `input` for NaN/Inf into an 8-byte `flags` buffer
+///
+/// The flags are reset first, so the buffer can be reused between layers.
+/// Decode the readback with `NonFinite::from_bytes`.
+///
+/// # Safety Requirements
+/// - `cmd` must be in the recording state
+/// - The kernel's descriptor set must not be in use by pending work
+/// - Both buffers must stay alive until the command buffer completes
+pub unsafe fn check_finite(
+    device: &ash::Device,
+    cmd: vk::CommandBuffer,
+    kernel: &KernelBinding,
+    input: &Tensor,
+    flags: &BufferRange,
+) -> OpsResult<CheckFinitePushConstants> {
+    input.layout.check_kernel_rank()?;
+    if flags.size < CHECK_FINITE_FLAG_BYTES {
+        return Err(OpsError::ScratchTooSmall {
+            needed: CHECK_FINITE_FLAG_BYTES,
+            available: flags.size,
+        });
+    }
+
+    let mut push = CheckFinitePushConstants {
+        rank: input.layout.rank() as u32,
+        numel: input.layout.numel() as u32,
+        ..Default::default()
+    };
+    for (i, (&dim, &stride)) in input.layout.shape().iter().zip(input.layout.strides()).enumerate() {
+        push.shape[i] = dim as u32;
+        push.strides[i] = stride as u32;
+    }
+    let groups = push.numel.div_ceil(CHECK_FINITE_WORKGROUP_SIZE).max(1);
+
+    let reset = vk::MemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+    // SAFETY: forwarded from the caller's guarantees
+    unsafe {
+        device.cmd_fill_buffer(cmd, flags.buffer, flags.offset, 4, 0);
+        device.cmd_fill_buffer(cmd, flags.buffer, flags.offset + 4, 4, u32::MAX);
+        device.cmd_pipeline_barrier(
+            cmd,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::DependencyFlags::empty(),
+            &[reset],
+            &[],
+            &[],
+        );
+        kernel.bind_buffers(device, &input.range, flags);
+        kernel.record(
+            device,
+            cmd,
+            &push,
+            [groups.min(MAX_GROUP_COUNT), groups.div_ceil(MAX_GROUP_COUNT), 1],
+        );
+    }
+    Ok(push)
+}
+
+/// Host reference for `check_finite`
+pub fn check_finite_host(data: &[f32], layout: &Layout) -> NonFinite {
+    let mut result = NonFinite::default();
+    for i in 0..layout.numel() {
+        let value = data[layout.offset_of(i)];
+        if value.is_finite() {
+            continue;
+        }
+        result.has_nan |= value.is_nan();
+        result.has_inf |= value.is_infinite();
+        result.first_index.get_or_insert(i as u32);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reduce_host_strided_axis() {
+        // 2x3 stored transposed: logical [[0, 1, 2], [3, 4, 5]]
+        let data = [0.0, 3.0, 1.0, 4.0, 2.0, 5.0];
+        let layout = Layout::with_strides(&[2, 3], &[1, 2]).unwrap();
+        assert_eq!(reduce_host(&data, &layout, 1, ReduceOp::Sum).unwrap(), vec![3.0, 12.0]);
+        assert_eq!(reduce_host(&data, &layout, 0, ReduceOp::Max).unwrap(), vec![3.0, 4.0, 5.0]);
+        assert_eq!(reduce_host(&data, &layout, 1, ReduceOp::Mean).unwrap(), vec![1.0, 4.0]);
+    }
+
+    #[test]
+    fn test_long_axis_needs_two_passes() {
+        let layout = Layout::contiguous(&[3, 5000]);
+        let plan = ReducePlan::new(&layout, 1, ReduceOp::Min).unwrap();
+        assert!(plan.is_two_pass());
+        assert_eq!(plan.push.chunks, 5);
+        assert_eq!(plan.scratch_bytes(), 3 * 5 * 4);
+        assert_eq!(plan.pass1_groups(), [5, 3, 1]);
+
+        let data: Vec<f32> = (0..15000).map(|i| i as f32).collect();
+        assert_eq!(reduce_host(&data, &layout, 1, ReduceOp::Min).unwrap(), vec![0.0, 5000.0, 10000.0]);
+    }
+
+    #[test]
+    fn test_permute_selects_tiled_kernel() {
+        let layout = Layout::contiguous(&[4, 64, 48]);
+        let plan = PermutePlan::new(&layout, &transpose_perm(3, 1, 2).unwrap()).unwrap();
+        assert_eq!(plan.kernel, PermuteKernel::Tiled);
+        assert_eq!(plan.push.tile_dim, 1);
+        assert_eq!(plan.groups, [2, 2, 4]);
+
+        // Keeping the innermost dim in place is already coalesced
+        let plan = PermutePlan::new(&layout, &[1, 0, 2]).unwrap();
+        assert_eq!(plan.kernel, PermuteKernel::General);
+    }
+
+    #[test]
+    fn test_permute_host_matches_view() {
+        let data: Vec<f32> = (0..6).map(|i| i as f32).collect();
+        let layout = Layout::contiguous(&[2, 3]);
+        assert_eq!(
+            permute_host(&data, &layout, &[1, 0]).unwrap(),
+            vec![0.0, 3.0, 1.0, 4.0, 2.0, 5.0]
+        );
+    }
+
+    #[test]
+    fn test_check_finite_host_reports_first_offender() {
+        let layout = Layout::contiguous(&[2, 2]).permute(&[1, 0]).unwrap();
+        let data = [0.0, f32::INFINITY, f32::NAN, 1.0];
+        let result = check_finite_host(&data, &layout);
+        assert!(result.has_nan && result.has_inf);
+        // Logical order of the transpose is [0, NaN, Inf, 1]
+        assert_eq!(result.first_index, Some(1));
+
+        assert!(NonFinite::from_flags([0, u32::MAX]).is_finite());
+        assert_eq!(NonFinite::from_flags([FLAG_INF, 7]).first_index, Some(7));
+    }
+}
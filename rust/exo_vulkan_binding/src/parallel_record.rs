@@ -0,0 +1,246 @@
+//! Multi-threaded command recording
+//!
+//! Recording a per-token graph of a few hundred dispatches can take longer
+//! on the CPU than a small model takes to run on a big GPU, so recording,
+//! not the GPU, bounds tokens per second. `ParallelRecorder` splits the plan
+//! into contiguous regions and records each on its own thread into a
+//! secondary command buffer; the secondaries are then executed in plan order
+//! from one primary, with a compute barrier between regions, so the GPU sees
+//! the same work as a serial recording.
+//!
+//! Command pools need external synchronization, so each region slot records
+//! from its own pool (`CommandPoolCache::for_stream`) and reuses its
+//! secondary buffer from one recording to the next.
+
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use ash::vk;
+use parking_lot::Mutex;
+use thiserror::Error;
+
+use crate::command::{CommandError, CommandPool, CommandPoolCache};
+use crate::graph::{Dispatch, Graph, GraphError, GraphResult};
+
+/// Parallel recording errors
+#[derive(Error, Debug)]
+pub enum ParallelRecordError {
+    #[error(transparent)]
+    Command(#[from] CommandError),
+
+    #[error(transparent)]
+    Graph(#[from] GraphError),
+
+    #[error("Recording worker panicked")]
+    WorkerPanicked,
+}
+
+pub type ParallelRecordResult<T> = Result<T, ParallelRecordError>;
+
+/// Fewest dispatches worth handing to a separate thread
+pub const DEFAULT_MIN_REGION: usize = 32;
+
+/// Records single dispatches; shared by every worker thread
+pub trait RegionRecorder: Sync {
+    /// Record `dispatch` into `cmd`, including any barrier it needs against
+    /// earlier dispatches of the same region
+    fn record(&self, graph: &Graph, dispatch: &Dispatch, cmd: vk::CommandBuffer) -> GraphResult<()>;
+}
+
+/// Split `len` dispatches into at most `workers` contiguous regions of at
+/// least `min_region` dispatches (except when `len` itself is smaller)
+pub fn split_regions(len: usize, workers: usize, min_region: usize) -> Vec<Range<usize>> {
+    if len == 0 {
+        return Vec::new();
+    }
+    let count = workers.min(len / min_region.max(1)).max(1);
+    let (base, extra) = (len / count, len % count);
+    let mut start = 0;
+    (0..count)
+        .map(|i| {
+            let end = start + base + usize::from(i < extra);
+            let region = start..end;
+            start = end;
+            region
+        })
+        .collect()
+}
+
+/// Run `f` for every region, one thread each, and collect results in
+/// region order
+///
+/// The first region runs on the calling thread.
+pub fn map_regions<T: Send>(
+    regions: &[Range<usize>],
+    f: impl Fn(usize, Range<usize>) -> ParallelRecordResult<T> + Sync,
+) -> ParallelRecordResult<Vec<T>> {
+    let f = &f;
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = regions
+            .iter()
+            .cloned()
+            .enumerate()
+            .skip(1)
+            .map(|(i, region)| scope.spawn(move || f(i, region)))
+            .collect();
+
+        let mut results = Vec::with_capacity(regions.len());
+        if let Some(first) = regions.first() {
+            results.push(f(0, first.clone()));
+        }
+        for worker in workers {
+            results.push(worker.join().unwrap_or(Err(ParallelRecordError::WorkerPanicked)));
+        }
+        results.into_iter().collect()
+    })
+}
+
+/// What one recording did
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecordStats {
+    pub dispatches: usize,
+    /// Secondary command buffers executed from the primary
+    pub regions: usize,
+    /// Wall-clock CPU time spent recording
+    pub elapsed: Duration,
+}
+
+/// Records plans across worker threads into secondary command buffers
+pub struct ParallelRecorder {
+    device: ash::Device,
+    pools: CommandPoolCache,
+    queue_family_index: u32,
+    workers: usize,
+    min_region: usize,
+    /// Pool and reusable secondary buffer of each region slot
+    slots: Mutex<Vec<(Arc<CommandPool>, vk::CommandBuffer)>>,
+}
+
+impl ParallelRecorder {
+    /// Create a recorder using up to `workers` threads
+    ///
+    /// # Safety Requirements
+    /// - device must outlive the recorder
+    /// - queue_family_index must be valid for device
+    pub fn new(device: ash::Device, queue_family_index: u32, workers: usize) -> Self {
+        Self {
+            pools: CommandPoolCache::new(device.clone()),
+            device,
+            queue_family_index,
+            workers: workers.max(1),
+            min_region: DEFAULT_MIN_REGION,
+            slots: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Recorder with one worker per available core, at most 4
+    pub fn with_default_workers(device: ash::Device, queue_family_index: u32) -> Self {
+        let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+        Self::new(device, queue_family_index, cores.min(4))
+    }
+
+    /// Smallest region handed to a thread; smaller plans record on fewer threads
+    pub fn set_min_region(&mut self, min_region: usize) {
+        self.min_region = min_region.max(1);
+    }
+
+    /// Pools and secondaries for the first `count` slots, created on first use
+    fn slots(&self, count: usize) -> ParallelRecordResult<Vec<(Arc<CommandPool>, vk::CommandBuffer)>> {
+        let mut slots = self.slots.lock();
+        while slots.len() < count {
+            let pool = self.pools.for_stream(slots.len() as u64, self.queue_family_index)?;
+            let buffer = pool.allocate_secondary_buffers(1)?[0];
+            slots.push((pool, buffer));
+        }
+        Ok(slots[..count].to_vec())
+    }
+
+    /// Record `plan` into `primary`
+    ///
+    /// # Safety Requirements
+    /// - `primary` is a primary command buffer of this device in the recording state
+    /// - no earlier recording made by this recorder is pending execution
+    ///   (secondary buffers are reset and reused)
+    pub unsafe fn record(
+        &self,
+        graph: &Graph,
+        plan: &[Dispatch],
+        recorder: &impl RegionRecorder,
+        primary: vk::CommandBuffer,
+    ) -> ParallelRecordResult<RecordStats> {
+        let started = Instant::now();
+        let regions = split_regions(plan.len(), self.workers, self.min_region);
+        let slots = self.slots(regions.len())?;
+
+        let secondaries = map_regions(&regions, |i, region| {
+            let (pool, cmd) = &slots[i];
+            pool.reset_buffer(*cmd)?;
+            pool.begin_secondary(*cmd)?;
+            for dispatch in &plan[region] {
+                recorder.record(graph, dispatch, *cmd)?;
+            }
+            pool.end_recording(*cmd)?;
+            Ok(*cmd)
+        })?;
+
+        let barrier = vk::MemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+        for (i, secondary) in secondaries.iter().enumerate() {
+            // SAFETY:
+            //   - primary is recording (caller's responsibility)
+            //   - secondaries were recorded above and are executable
+            unsafe {
+                if i > 0 {
+                    self.device.cmd_pipeline_barrier(
+                        primary,
+                        vk::PipelineStageFlags::COMPUTE_SHADER,
+                        vk::PipelineStageFlags::COMPUTE_SHADER,
+                        vk::DependencyFlags::empty(),
+                        &[barrier],
+                        &[],
+                        &[],
+                    );
+                }
+                self.device.cmd_execute_commands(primary, &[*secondary]);
+            }
+        }
+
+        Ok(RecordStats {
+            dispatches: plan.len(),
+            regions: regions.len(),
+            elapsed: started.elapsed(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_regions_covers_plan_in_order() {
+        assert!(split_regions(0, 4, 32).is_empty());
+        assert_eq!(split_regions(10, 4, 32), vec![0..10]);
+        assert_eq!(split_regions(100, 4, 32), vec![0..34, 34..67, 67..100]);
+        assert_eq!(split_regions(400, 4, 32), vec![0..100, 100..200, 200..300, 300..400]);
+        assert_eq!(split_regions(5, 8, 1).len(), 5);
+    }
+
+    #[test]
+    fn test_map_regions_keeps_region_order() {
+        let regions = split_regions(90, 3, 1);
+        let sums = map_regions(&regions, |_, region| Ok(region.sum::<usize>())).unwrap();
+        assert_eq!(sums, vec![435, 1335, 2235]);
+
+        let err = map_regions(&regions, |i, _| {
+            if i == 2 {
+                Err(GraphError::Backend("boom".to_string()).into())
+            } else {
+                Ok(i)
+            }
+        });
+        assert!(matches!(err, Err(ParallelRecordError::Graph(_))));
+    }
+}
@@ -0,0 +1,180 @@
+//! Repetition, presence and frequency penalties
+//!
+//! Applied to a sequence's logits on device before sampling, so the host
+//! never edits logits. Pass 1 counts occurrences of each token in the
+//! sequence's history; pass 2 penalizes every seen token in place, reading
+//! the penalty values from the sampler config ring (see `sampler`), so
+//! changing them mid-generation needs no re-recording.
+//!
+//! Semantics follow the OpenAI API for presence/frequency and the CTRL/HF
+//! convention for the multiplicative repetition penalty:
+//!
+//! ```text
+//! logit = logit > 0 ? logit / repetition : logit * repetition
+//! logit -= count * frequency + presence
+//! ```
+
+use std::collections::HashMap;
+
+use ash::vk;
+
+use crate::memory::BufferRange;
+use crate::ops::{KernelBinding, MAX_GROUP_COUNT, OpsError, OpsResult, compute_barrier};
+use crate::sampler::{SamplerConfigRing, SamplerParams};
+
+/// Threads per workgroup of both penalty passes
+pub const PENALTY_WORKGROUP_SIZE: u32 = 256;
+
+/// Bytes per history token and per count
+const WORD_SIZE: u64 = 4;
+
+/// Push constants shared by both penalty passes
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PenaltyPushConstants {
+    pub vocab: u32,
+    pub history_len: u32,
+    /// Sampler ring slot holding the penalty values
+    pub slot: u32,
+    pub _pad: u32,
+}
+
+/// The two penalty pipelines (`shaders/penalties.comp` with `PASS` = 1 and 2)
+#[derive(Clone, Copy, Debug)]
+pub struct PenaltyKernels {
+    pub count: KernelBinding,
+    pub apply: KernelBinding,
+}
+
+fn groups(threads: u32) -> [u32; 3] {
+    let groups = threads.div_ceil(PENALTY_WORKGROUP_SIZE).max(1);
+    [
+        groups.min(MAX_GROUP_COUNT),
+        groups.div_ceil(MAX_GROUP_COUNT),
+        1,
+    ]
+}
+
+/// Record penalties for one sequence
+///
+/// `logits` holds the sequence's `vocab` logits and is updated in place.
+/// `history` holds its `history_len` previous tokens as u32; tokens outside
+/// the vocabulary are ignored. `counts` is scratch of `vocab` u32s. `slot`
+/// is the value `SamplerConfigRing::advance` returned for this step.
+///
+/// # Safety Requirements
+/// - `cmd` must be in the recording state
+/// - The kernels' descriptor sets must not be in use by pending work
+/// - All buffers must stay alive until the command buffer completes
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn apply_penalties(
+    device: &ash::Device,
+    cmd: vk::CommandBuffer,
+    kernels: &PenaltyKernels,
+    logits: &BufferRange,
+    history: &BufferRange,
+    history_len: u32,
+    counts: &BufferRange,
+    ring: &SamplerConfigRing,
+    slot: u32,
+) -> OpsResult<PenaltyPushConstants> {
+    let vocab = (logits.size / WORD_SIZE) as u32;
+    if history.size < u64::from(history_len) * WORD_SIZE {
+        return Err(OpsError::ShapeMismatch(format!(
+            "history of {} bytes holds fewer than {} tokens",
+            history.size, history_len
+        )));
+    }
+    let needed = u64::from(vocab) * WORD_SIZE;
+    if counts.size < needed {
+        return Err(OpsError::ScratchTooSmall {
+            needed,
+            available: counts.size,
+        });
+    }
+    if slot >= ring.slots() {
+        return Err(OpsError::ShapeMismatch(format!(
+            "sampler slot {} out of {}",
+            slot,
+            ring.slots()
+        )));
+    }
+
+    let push = PenaltyPushConstants {
+        vocab,
+        history_len,
+        slot,
+        _pad: 0,
+    };
+    let cleared = vk::MemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+    // SAFETY: forwarded from the caller's guarantees
+    unsafe {
+        device.cmd_fill_buffer(cmd, counts.buffer, counts.offset, needed, 0);
+        device.cmd_pipeline_barrier(
+            cmd,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::DependencyFlags::empty(),
+            &[cleared],
+            &[],
+            &[],
+        );
+        if history_len > 0 {
+            kernels.count.bind_buffers(device, history, counts);
+            kernels
+                .count
+                .record(device, cmd, &push, groups(history_len));
+        }
+        compute_barrier(device, cmd);
+        kernels.apply.bind_buffers(device, counts, logits);
+        kernels.apply.bind_buffer(device, 2, &ring.binding());
+        kernels.apply.record(device, cmd, &push, groups(vocab));
+    }
+    Ok(push)
+}
+
+/// Host reference for `apply_penalties`
+pub fn apply_penalties_host(logits: &mut [f32], history: &[u32], params: &SamplerParams) {
+    let mut counts: HashMap<u32, u32> = HashMap::new();
+    for &token in history.iter().filter(|&&t| (t as usize) < logits.len()) {
+        *counts.entry(token).or_default() += 1;
+    }
+    for (token, count) in counts {
+        let logit = &mut logits[token as usize];
+        *logit = if *logit > 0.0 {
+            *logit / params.repetition_penalty
+        } else {
+            *logit * params.repetition_penalty
+        };
+        *logit -= count as f32 * params.frequency_penalty + params.presence_penalty;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_penalties_host() {
+        let params = SamplerParams {
+            repetition_penalty: 2.0,
+            presence_penalty: 0.5,
+            frequency_penalty: 0.25,
+            ..SamplerParams::default()
+        };
+        let mut logits = [4.0, -1.0, 3.0, 1.0];
+        apply_penalties_host(&mut logits, &[0, 1, 1, 9], &params);
+        // token 0: 4 / 2 - (0.25 + 0.5); token 1: -1 * 2 - (0.5 + 0.5)
+        assert_eq!(logits, [1.25, -3.0, 3.0, 1.0]);
+    }
+
+    #[test]
+    fn test_neutral_params_leave_logits() {
+        let mut logits = [0.5, -0.5];
+        apply_penalties_host(&mut logits, &[0, 1, 1], &SamplerParams::default());
+        assert_eq!(logits, [0.5, -0.5]);
+        assert_eq!(groups(70_000_000), [65535, 5, 1]);
+    }
+}
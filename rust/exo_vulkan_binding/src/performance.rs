@@ -0,0 +1,299 @@
+//! Sustained performance over sliding windows
+//!
+//! Phones run at full speed for the first few seconds of generation and
+//! then throttle as they heat up, so a single tokens/sec figure flatters
+//! them. `PerformanceTracker` keeps the tokens generated and the GPU time
+//! spent over the last `LONG_WINDOW`, reports throughput and GPU busy
+//! percentage over a short and a long window, and compares the short window
+//! with the best one seen to spot degradation. When throughput falls and
+//! each token also costs more GPU time, the GPU itself got slower (clocks
+//! dropped), which on a phone almost always means thermal throttling; when
+//! throughput falls at the same cost per token, the app is simply issuing
+//! less work.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+
+/// Window of the current-throughput figures
+pub const SHORT_WINDOW: Duration = Duration::from_secs(10);
+
+/// Window of the sustained-throughput figures; older samples are dropped
+pub const LONG_WINDOW: Duration = Duration::from_secs(60);
+
+/// Drop from the best short window that counts as degradation
+pub const DEGRADATION_THRESHOLD: f64 = 0.15;
+
+/// Minimum spacing of best-window updates while recording
+const PEAK_UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Direction of recent performance
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Trend {
+    /// Less than one short window of history
+    Warmup,
+    /// No tokens in the short window
+    Idle,
+    Stable,
+    /// Throughput fell at unchanged GPU cost per token: less work is issued
+    Degrading,
+    /// Throughput fell and each token costs more GPU time: the GPU slowed down
+    ThermalThrottling,
+}
+
+impl Trend {
+    pub fn name(self) -> &'static str {
+        match self {
+            Trend::Warmup => "warmup",
+            Trend::Idle => "idle",
+            Trend::Stable => "stable",
+            Trend::Degrading => "degrading",
+            Trend::ThermalThrottling => "thermal_throttling",
+        }
+    }
+}
+
+/// Throughput and GPU load over one window
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct WindowStats {
+    /// Covered time; shorter than the window until enough history exists
+    pub duration: Duration,
+    pub tokens: u64,
+    pub tokens_per_sec: f64,
+    /// Share of the window the GPU was busy, 0 to 100
+    pub gpu_busy_percent: f64,
+    pub gpu_time_per_token: Option<Duration>,
+}
+
+/// Everything `getPerformanceSummary` reports
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PerformanceSummary {
+    pub short: WindowStats,
+    pub long: WindowStats,
+    /// Best short-window throughput seen since the last reset
+    pub peak_tokens_per_sec: f64,
+    /// How far the short window is below the peak, 0 to 100
+    pub degradation_percent: f64,
+    pub trend: Trend,
+}
+
+impl PerformanceSummary {
+    pub fn to_json(&self) -> String {
+        let window = |w: &WindowStats| {
+            format!(
+                r#"{{"duration_ms":{},"tokens":{},"tokens_per_sec":{:.2},"gpu_busy_percent":{:.1},"gpu_us_per_token":{}}}"#,
+                w.duration.as_millis(),
+                w.tokens,
+                w.tokens_per_sec,
+                w.gpu_busy_percent,
+                w.gpu_time_per_token.map_or_else(|| "null".to_string(), |t| t.as_micros().to_string())
+            )
+        };
+        format!(
+            r#"{{"short":{},"long":{},"peak_tokens_per_sec":{:.2},"degradation_percent":{:.1},"trend":"{}"}}"#,
+            window(&self.short),
+            window(&self.long),
+            self.peak_tokens_per_sec,
+            self.degradation_percent,
+            self.trend.name()
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Sample {
+    at: Instant,
+    tokens: u64,
+    gpu_time: Duration,
+}
+
+/// Sliding-window record of generated tokens and GPU time
+#[derive(Debug, Default)]
+pub struct PerformanceTracker {
+    samples: VecDeque<Sample>,
+    started: Option<Instant>,
+    peak_tokens_per_sec: f64,
+    /// GPU time per token of the fastest short window seen
+    best_gpu_time_per_token: Option<Duration>,
+    last_peak_update: Option<Instant>,
+}
+
+impl PerformanceTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count `tokens` generated at `at`
+    pub fn record_tokens(&mut self, tokens: u64, at: Instant) {
+        self.push(Sample {
+            at,
+            tokens,
+            gpu_time: Duration::ZERO,
+        });
+    }
+
+    /// Count `gpu_time` of work that finished at `at`
+    pub fn record_gpu_time(&mut self, gpu_time: Duration, at: Instant) {
+        self.push(Sample {
+            at,
+            tokens: 0,
+            gpu_time,
+        });
+    }
+
+    fn push(&mut self, sample: Sample) {
+        self.started.get_or_insert(sample.at);
+        self.samples.push_back(sample);
+        while self
+            .samples
+            .front()
+            .is_some_and(|s| sample.at.saturating_duration_since(s.at) > LONG_WINDOW)
+        {
+            self.samples.pop_front();
+        }
+        if self
+            .last_peak_update
+            .is_none_or(|last| sample.at.saturating_duration_since(last) >= PEAK_UPDATE_INTERVAL)
+        {
+            self.update_peak(sample.at);
+        }
+    }
+
+    /// Fold the short window ending at `now` into the best seen, once it is full
+    fn update_peak(&mut self, now: Instant) {
+        self.last_peak_update = Some(now);
+        let short = self.window(SHORT_WINDOW, now);
+        if short.duration < SHORT_WINDOW {
+            return;
+        }
+        self.peak_tokens_per_sec = self.peak_tokens_per_sec.max(short.tokens_per_sec);
+        if let Some(per_token) = short.gpu_time_per_token {
+            self.best_gpu_time_per_token = Some(self.best_gpu_time_per_token.map_or(per_token, |b| b.min(per_token)));
+        }
+    }
+
+    /// Throughput and GPU load over the `length` before `now`
+    pub fn window(&self, length: Duration, now: Instant) -> WindowStats {
+        let Some(started) = self.started else {
+            return WindowStats::default();
+        };
+        let duration = length.min(now.saturating_duration_since(started));
+        let (tokens, gpu_time) = self
+            .samples
+            .iter()
+            .filter(|s| now.saturating_duration_since(s.at) <= duration)
+            .fold((0, Duration::ZERO), |(tokens, gpu), s| (tokens + s.tokens, gpu + s.gpu_time));
+        let seconds = duration.as_secs_f64();
+        WindowStats {
+            duration,
+            tokens,
+            tokens_per_sec: if seconds > 0.0 { tokens as f64 / seconds } else { 0.0 },
+            gpu_busy_percent: if seconds > 0.0 {
+                (gpu_time.as_secs_f64() / seconds * 100.0).min(100.0)
+            } else {
+                0.0
+            },
+            gpu_time_per_token: u32::try_from(tokens).ok().filter(|&t| t > 0).map(|t| gpu_time / t),
+        }
+    }
+
+    /// Current figures and trend
+    pub fn summary(&mut self, now: Instant) -> PerformanceSummary {
+        self.update_peak(now);
+        let short = self.window(SHORT_WINDOW, now);
+        let long = self.window(LONG_WINDOW, now);
+        let degradation = if self.peak_tokens_per_sec > 0.0 {
+            (1.0 - short.tokens_per_sec / self.peak_tokens_per_sec).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let slower_gpu = match (short.gpu_time_per_token, self.best_gpu_time_per_token) {
+            (Some(now), Some(best)) => now.as_secs_f64() > best.as_secs_f64() * (1.0 + DEGRADATION_THRESHOLD),
+            _ => false,
+        };
+        let trend = if short.duration < SHORT_WINDOW || self.peak_tokens_per_sec == 0.0 {
+            Trend::Warmup
+        } else if short.tokens == 0 {
+            Trend::Idle
+        } else if degradation < DEGRADATION_THRESHOLD {
+            Trend::Stable
+        } else if slower_gpu {
+            Trend::ThermalThrottling
+        } else {
+            Trend::Degrading
+        };
+        PerformanceSummary {
+            short,
+            long,
+            peak_tokens_per_sec: self.peak_tokens_per_sec,
+            degradation_percent: degradation * 100.0,
+            trend,
+        }
+    }
+
+    /// Forget all history, e.g. when a new workload starts
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+lazy_static! {
+    static ref TRACKER: Mutex<PerformanceTracker> = Mutex::new(PerformanceTracker::new());
+}
+
+/// The process-wide tracker generation records into
+pub fn global() -> parking_lot::MutexGuard<'static, PerformanceTracker> {
+    TRACKER.lock()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `seconds` of one token per `token_ms`, each costing `gpu_ms` of GPU time
+    fn run(tracker: &mut PerformanceTracker, from: Instant, seconds: u64, token_ms: u64, gpu_ms: u64) -> Instant {
+        let mut at = from;
+        let end = from + Duration::from_secs(seconds);
+        while at < end {
+            at += Duration::from_millis(token_ms);
+            tracker.record_gpu_time(Duration::from_millis(gpu_ms), at);
+            tracker.record_tokens(1, at);
+        }
+        at
+    }
+
+    #[test]
+    fn test_detects_gpu_slowdown_as_throttling() {
+        let mut tracker = PerformanceTracker::new();
+        let start = Instant::now();
+        assert_eq!(tracker.summary(start).trend, Trend::Warmup);
+
+        let at = run(&mut tracker, start, 20, 50, 40);
+        let summary = tracker.summary(at);
+        assert_eq!(summary.trend, Trend::Stable);
+        assert!((summary.short.tokens_per_sec - 20.0).abs() < 0.5);
+        assert!((summary.short.gpu_busy_percent - 80.0).abs() < 2.0);
+
+        // Clocks drop: each token now takes twice the GPU time
+        let at = run(&mut tracker, at, 20, 100, 80);
+        let summary = tracker.summary(at);
+        assert_eq!(summary.trend, Trend::ThermalThrottling);
+        assert!(summary.degradation_percent > 40.0);
+        assert!((summary.peak_tokens_per_sec - 20.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn test_less_work_is_not_throttling() {
+        let mut tracker = PerformanceTracker::new();
+        let start = Instant::now();
+        let at = run(&mut tracker, start, 20, 50, 10);
+        // Same GPU cost per token, but the app asks for tokens half as often
+        let at = run(&mut tracker, at, 20, 100, 10);
+        assert_eq!(tracker.summary(at).trend, Trend::Degrading);
+        assert_eq!(tracker.summary(at + LONG_WINDOW).trend, Trend::Idle);
+        tracker.reset();
+        assert_eq!(tracker.summary(at).short, WindowStats::default());
+    }
+}
@@ -0,0 +1,463 @@
+//! Compute pipelines from SPIR-V
+//!
+//! Loads a SPIR-V module (an embedded kernel, a runtime plugin or raw bytes),
+//! reflects its bindings and push constants with `KernelInterface::reflect`
+//! and builds the shader module, descriptor set layout, pipeline layout and
+//! compute pipeline from them, so no caller describes a layout by hand.
+//!
+//! Each pipeline owns a small descriptor pool and one default set.
+//! `dispatch` checks the buffers and push constants against the reflected
+//! interface, writes the buffers into the set and records the dispatch.
+//! Callers recording several dispatches of one pipeline before a submit
+//! allocate extra sets with `allocate_descriptor_set`.
+
+use std::ffi::CString;
+use std::mem::size_of;
+
+use ash::vk;
+use thiserror::Error;
+
+use crate::kernel_args::{KernelArgsError, KernelInterface};
+use crate::kernel_plugins::{self, PluginKernel};
+use crate::kernels::{self, KernelsError};
+use crate::memory::BufferRange;
+use crate::object_budget::{self, BudgetError, ObjectKind};
+use crate::trace;
+#[cfg(feature = "kernels-core")]
+use crate::ops::KernelBinding;
+
+/// Pipeline errors
+#[derive(Error, Debug)]
+pub enum PipelineError {
+    #[error(transparent)]
+    Kernels(#[from] KernelsError),
+
+    #[error("Reflection failed: {0}")]
+    Reflection(#[from] KernelArgsError),
+
+    #[error("Invalid SPIR-V: {0}")]
+    InvalidSpirv(String),
+
+    #[error("Kernel {kernel} has no compute entry point {entry_point:?}")]
+    MissingEntryPoint { kernel: String, entry_point: String },
+
+    #[error("Kernel {kernel}: bindings {supplied:?} supplied, shader declares {declared:?}")]
+    BindingMismatch {
+        kernel: String,
+        supplied: Vec<u32>,
+        declared: Vec<u32>,
+    },
+
+    #[error("Kernel {kernel}: {supplied} push constant bytes supplied, shader declares {declared}")]
+    PushConstantMismatch { kernel: String, supplied: u32, declared: u32 },
+
+    #[error(transparent)]
+    Budget(#[from] BudgetError),
+
+    #[error("Vulkan error: {0:?}")]
+    VulkanError(vk::Result),
+}
+
+pub type PipelineResult<T> = Result<T, PipelineError>;
+
+/// Descriptor sets each pipeline's pool can hold, the default one included
+pub const DESCRIPTOR_SETS_PER_PIPELINE: u32 = 8;
+
+/// Workgroups covering `threads` invocations per axis
+///
+/// Modules whose local size is not a literal count as one invocation per group.
+pub fn dispatch_groups(local_size: Option<[u32; 3]>, threads: [u32; 3]) -> [u32; 3] {
+    let local = local_size.unwrap_or([1, 1, 1]);
+    [0, 1, 2].map(|axis| threads[axis].div_ceil(local[axis].max(1)).max(1))
+}
+
+/// Check `supplied` bindings cover exactly the `declared` ones
+fn check_bindings(kernel: &str, declared: &[u32], supplied: &[u32]) -> PipelineResult<()> {
+    let mut sorted = supplied.to_vec();
+    sorted.sort_unstable();
+    if sorted != declared {
+        return Err(PipelineError::BindingMismatch {
+            kernel: kernel.to_string(),
+            supplied: sorted,
+            declared: declared.to_vec(),
+        });
+    }
+    Ok(())
+}
+
+/// A compute pipeline with the layouts and descriptors it needs
+pub struct ComputePipeline {
+    device: ash::Device,
+    name: String,
+    interface: KernelInterface,
+    shader_module: vk::ShaderModule,
+    set_layout: vk::DescriptorSetLayout,
+    layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_set: vk::DescriptorSet,
+}
+
+impl ComputePipeline {
+    /// Build a pipeline from SPIR-V words
+    ///
+    /// # Arguments
+    /// * `device` - Device to create on (must outlive the pipeline)
+    /// * `name` - Kernel name, for errors and logs
+    /// * `entry_point` - Compute entry point, usually `main`
+    /// * `cache` - Pipeline cache, or a null handle
+    pub fn new(
+        device: &ash::Device,
+        name: &str,
+        spirv: &[u32],
+        entry_point: &str,
+        cache: vk::PipelineCache,
+    ) -> PipelineResult<Self> {
+        Self::new_specialized(device, name, spirv, entry_point, &[], cache)
+    }
+
+    /// Build a pipeline with specialization constants
+    ///
+    /// `constants[i]` is the 32-bit value of `constant_id = i`; constants
+    /// the module does not declare are ignored by the driver.
+    pub fn new_specialized(
+        device: &ash::Device,
+        name: &str,
+        spirv: &[u32],
+        entry_point: &str,
+        constants: &[u32],
+        cache: vk::PipelineCache,
+    ) -> PipelineResult<Self> {
+        let interface = KernelInterface::reflect(spirv)?;
+        if !interface.entry_points.iter().any(|e| e == entry_point) {
+            return Err(PipelineError::MissingEntryPoint {
+                kernel: name.to_string(),
+                entry_point: entry_point.to_string(),
+            });
+        }
+        let entry = CString::new(entry_point).map_err(|e| PipelineError::InvalidSpirv(e.to_string()))?;
+
+        object_budget::acquire(ObjectKind::Pipeline, 1)?;
+        if let Err(e) = object_budget::acquire(ObjectKind::DescriptorPool, 1) {
+            object_budget::release(ObjectKind::Pipeline, 1);
+            return Err(e.into());
+        }
+        // Handles start null and are filled in as they are created; on an
+        // early return Drop destroys the ones that exist (destroying a null
+        // handle is a no-op) and returns the budget
+        let mut built = Self {
+            device: device.clone(),
+            name: name.to_string(),
+            interface,
+            shader_module: vk::ShaderModule::null(),
+            set_layout: vk::DescriptorSetLayout::null(),
+            layout: vk::PipelineLayout::null(),
+            pipeline: vk::Pipeline::null(),
+            descriptor_pool: vk::DescriptorPool::null(),
+            descriptor_set: vk::DescriptorSet::null(),
+        };
+
+        // SAFETY: device is valid (caller's responsibility) and every create
+        // info below only points at locals that outlive the call
+        unsafe {
+            let module_info = vk::ShaderModuleCreateInfo::default().code(spirv);
+            built.shader_module = device
+                .create_shader_module(&module_info, None)
+                .map_err(PipelineError::VulkanError)?;
+
+            let bindings: Vec<vk::DescriptorSetLayoutBinding> = built
+                .interface
+                .buffer_bindings
+                .iter()
+                .map(|&binding| {
+                    vk::DescriptorSetLayoutBinding::default()
+                        .binding(binding)
+                        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                        .descriptor_count(1)
+                        .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                })
+                .collect();
+            let set_layout_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings);
+            built.set_layout = device
+                .create_descriptor_set_layout(&set_layout_info, None)
+                .map_err(PipelineError::VulkanError)?;
+
+            let push_size = built.interface.push_constant_size();
+            let push_ranges: Vec<vk::PushConstantRange> = (push_size > 0)
+                .then(|| {
+                    vk::PushConstantRange::default()
+                        .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                        .offset(0)
+                        .size(push_size)
+                })
+                .into_iter()
+                .collect();
+            let set_layouts = [built.set_layout];
+            let layout_info = vk::PipelineLayoutCreateInfo::default()
+                .set_layouts(&set_layouts)
+                .push_constant_ranges(&push_ranges);
+            built.layout = device
+                .create_pipeline_layout(&layout_info, None)
+                .map_err(PipelineError::VulkanError)?;
+
+            let map_entries: Vec<vk::SpecializationMapEntry> = (0..constants.len() as u32)
+                .map(|id| {
+                    vk::SpecializationMapEntry::default()
+                        .constant_id(id)
+                        .offset(id * 4)
+                        .size(4)
+                })
+                .collect();
+            let data: Vec<u8> = constants.iter().flat_map(|c| c.to_ne_bytes()).collect();
+            let specialization = vk::SpecializationInfo::default()
+                .map_entries(&map_entries)
+                .data(&data);
+            let mut stage = vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::COMPUTE)
+                .module(built.shader_module)
+                .name(&entry);
+            if !constants.is_empty() {
+                stage = stage.specialization_info(&specialization);
+            }
+            let pipeline_info = vk::ComputePipelineCreateInfo::default()
+                .stage(stage)
+                .layout(built.layout);
+            built.pipeline = device
+                .create_compute_pipelines(cache, &[pipeline_info], None)
+                .map_err(|(_, e)| PipelineError::VulkanError(e))?[0];
+
+            // Pools must hold at least one descriptor, even for kernels without buffers
+            let pool_sizes = [vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count((bindings.len() as u32).max(1) * DESCRIPTOR_SETS_PER_PIPELINE)];
+            let pool_info = vk::DescriptorPoolCreateInfo::default()
+                .max_sets(DESCRIPTOR_SETS_PER_PIPELINE)
+                .pool_sizes(&pool_sizes);
+            built.descriptor_pool = device
+                .create_descriptor_pool(&pool_info, None)
+                .map_err(PipelineError::VulkanError)?;
+        }
+        built.descriptor_set = built.allocate_descriptor_set()?;
+
+        log::debug!(
+            "Created pipeline {} ({} bindings, {} push constant bytes, constants {:?})",
+            name,
+            built.interface.buffer_bindings.len(),
+            built.interface.push_constant_size(),
+            constants
+        );
+        Ok(built)
+    }
+
+    /// Build the pipeline of an embedded kernel
+    pub fn from_embedded(device: &ash::Device, name: &str, cache: vk::PipelineCache) -> PipelineResult<Self> {
+        Self::new(device, name, kernels::spirv(name)?, "main", cache)
+    }
+
+    /// Build the pipeline of an embedded kernel with specialization constants
+    pub fn from_embedded_specialized(
+        device: &ash::Device,
+        name: &str,
+        constants: &[u32],
+        cache: vk::PipelineCache,
+    ) -> PipelineResult<Self> {
+        Self::new_specialized(device, name, kernels::spirv(name)?, "main", constants, cache)
+    }
+
+    /// Build the pipeline of a registered plugin kernel
+    pub fn from_plugin(device: &ash::Device, kernel: &PluginKernel, cache: vk::PipelineCache) -> PipelineResult<Self> {
+        Self::new(device, &kernel.name, &kernel.spirv, &kernel.metadata.entry_point, cache)
+    }
+
+    /// Build a pipeline from SPIR-V bytes, e.g. read from a file
+    pub fn from_bytes(device: &ash::Device, name: &str, bytes: &[u8], cache: vk::PipelineCache) -> PipelineResult<Self> {
+        let spirv = kernel_plugins::spirv_words(name, bytes).map_err(|e| PipelineError::InvalidSpirv(e.to_string()))?;
+        Self::new(device, name, &spirv, "main", cache)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// What the module declares
+    pub fn interface(&self) -> &KernelInterface {
+        &self.interface
+    }
+
+    pub fn pipeline(&self) -> vk::Pipeline {
+        self.pipeline
+    }
+
+    pub fn layout(&self) -> vk::PipelineLayout {
+        self.layout
+    }
+
+    /// Layout of the pipeline's only set, for allocating sets elsewhere
+    /// (e.g. from a `descriptor::DescriptorManager`)
+    pub fn set_layout(&self) -> vk::DescriptorSetLayout {
+        self.set_layout
+    }
+
+    /// The set `dispatch` writes
+    pub fn descriptor_set(&self) -> vk::DescriptorSet {
+        self.descriptor_set
+    }
+
+    /// Workgroups covering `threads` invocations per axis
+    pub fn groups_for(&self, threads: [u32; 3]) -> [u32; 3] {
+        dispatch_groups(self.interface.local_size, threads)
+    }
+
+    /// Allocate another descriptor set from the pipeline's pool
+    ///
+    /// At most `DESCRIPTOR_SETS_PER_PIPELINE` sets exist per pipeline; they
+    /// are freed with it.
+    pub fn allocate_descriptor_set(&self) -> PipelineResult<vk::DescriptorSet> {
+        let set_layouts = [self.set_layout];
+        let info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(self.descriptor_pool)
+            .set_layouts(&set_layouts);
+        // SAFETY: pool and layout were created from self.device
+        let sets = unsafe { self.device.allocate_descriptor_sets(&info) }.map_err(PipelineError::VulkanError)?;
+        Ok(sets[0])
+    }
+
+    /// Bind `buffers` and record a dispatch of `groups` workgroups into the default set
+    ///
+    /// `buffers` pairs each binding the shader declares with its range; `push`
+    /// must be exactly the shader's push constant block (`()` for none).
+    ///
+    /// # Safety Requirements
+    /// - `cmd` must be in the recording state
+    /// - The default set must not be in use by pending work
+    /// - All buffers must stay alive until the command buffer completes
+    pub unsafe fn dispatch<P: Copy>(
+        &self,
+        cmd: vk::CommandBuffer,
+        buffers: &[(u32, &BufferRange)],
+        push: &P,
+        groups: [u32; 3],
+    ) -> PipelineResult<()> {
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe { self.dispatch_with_set(cmd, self.descriptor_set, buffers, push, groups) }
+    }
+
+    /// `dispatch` writing a set from `allocate_descriptor_set` instead of the default one
+    ///
+    /// # Safety Requirements
+    /// - As for `dispatch`, with `set` in place of the default set
+    pub unsafe fn dispatch_with_set<P: Copy>(
+        &self,
+        cmd: vk::CommandBuffer,
+        set: vk::DescriptorSet,
+        buffers: &[(u32, &BufferRange)],
+        push: &P,
+        groups: [u32; 3],
+    ) -> PipelineResult<()> {
+        // SAFETY: P is a repr(C) plain-data push constant struct
+        let bytes = unsafe { std::slice::from_raw_parts(push as *const P as *const u8, size_of::<P>()) };
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe { self.dispatch_bytes(cmd, set, buffers, bytes, groups) }
+    }
+
+    /// `dispatch_with_set` taking the push constant block as raw bytes
+    ///
+    /// For callers that only learn the block's layout at run time, such as
+    /// the remote shim.
+    ///
+    /// # Safety Requirements
+    /// - As for `dispatch_with_set`
+    pub unsafe fn dispatch_bytes(
+        &self,
+        cmd: vk::CommandBuffer,
+        set: vk::DescriptorSet,
+        buffers: &[(u32, &BufferRange)],
+        push: &[u8],
+        groups: [u32; 3],
+    ) -> PipelineResult<()> {
+        let supplied: Vec<u32> = buffers.iter().map(|(binding, _)| *binding).collect();
+        check_bindings(&self.name, &self.interface.buffer_bindings, &supplied)?;
+        let declared = self.interface.push_constant_size();
+        if push.len() as u32 != declared {
+            return Err(PipelineError::PushConstantMismatch {
+                kernel: self.name.clone(),
+                supplied: push.len() as u32,
+                declared,
+            });
+        }
+
+        let infos: Vec<[vk::DescriptorBufferInfo; 1]> = buffers.iter().map(|(_, range)| [range.descriptor_info()]).collect();
+        let writes: Vec<vk::WriteDescriptorSet> = buffers
+            .iter()
+            .zip(&infos)
+            .map(|((binding, _), info)| {
+                vk::WriteDescriptorSet::default()
+                    .dst_set(set)
+                    .dst_binding(*binding)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .buffer_info(info)
+            })
+            .collect();
+        // SAFETY: caller guarantees cmd is recording and set is not in use
+        unsafe {
+            self.device.update_descriptor_sets(&writes, &[]);
+            self.device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::COMPUTE, self.pipeline);
+            self.device
+                .cmd_bind_descriptor_sets(cmd, vk::PipelineBindPoint::COMPUTE, self.layout, 0, &[set], &[]);
+            if !push.is_empty() {
+                self.device
+                    .cmd_push_constants(cmd, self.layout, vk::ShaderStageFlags::COMPUTE, 0, push);
+            }
+            self.device.cmd_dispatch(cmd, groups[0], groups[1], groups[2]);
+        }
+        trace::record_dispatch();
+        Ok(())
+    }
+
+    /// The pipeline as the `ops` record functions take it, using the default set
+    #[cfg(feature = "kernels-core")]
+    pub fn kernel_binding(&self) -> KernelBinding {
+        KernelBinding {
+            pipeline: self.pipeline,
+            layout: self.layout,
+            descriptor_set: self.descriptor_set,
+        }
+    }
+}
+
+impl Drop for ComputePipeline {
+    fn drop(&mut self) {
+        // SAFETY: every handle was created from self.device or is null, and
+        // the owner guarantees no pending work uses the pipeline
+        unsafe {
+            self.device.destroy_descriptor_pool(self.descriptor_pool, None);
+            self.device.destroy_pipeline(self.pipeline, None);
+            self.device.destroy_pipeline_layout(self.layout, None);
+            self.device.destroy_descriptor_set_layout(self.set_layout, None);
+            self.device.destroy_shader_module(self.shader_module, None);
+        }
+        object_budget::release(ObjectKind::DescriptorPool, 1);
+        object_budget::release(ObjectKind::Pipeline, 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dispatch_groups_round_up() {
+        assert_eq!(dispatch_groups(Some([256, 1, 1]), [1000, 1, 1]), [4, 1, 1]);
+        assert_eq!(dispatch_groups(Some([16, 16, 1]), [33, 16, 0]), [3, 1, 1]);
+        assert_eq!(dispatch_groups(None, [5, 2, 1]), [5, 2, 1]);
+    }
+
+    #[test]
+    fn test_bindings_must_match_declared() {
+        assert!(check_bindings("k", &[0, 1, 3], &[3, 0, 1]).is_ok());
+        assert!(matches!(
+            check_bindings("k", &[0, 1], &[0]),
+            Err(PipelineError::BindingMismatch { supplied, .. }) if supplied == vec![0]
+        ));
+    }
+}
@@ -0,0 +1,281 @@
+//! Crash-consistent pipeline cache persistence
+//!
+//! Pipeline cache blobs are opaque driver data, and drivers trust them: a
+//! truncated file (the app was killed mid-write) or a blob from another
+//! driver version (the phone took an OTA update) can crash pipeline
+//! creation at startup. Saving therefore writes a temporary file, syncs it
+//! and renames it over the old one, so the file on disk is always a
+//! complete old or new cache. Each file carries a header naming the device
+//! and driver it came from plus a CRC32 of the blob; loading checks both,
+//! and the header Vulkan puts at the start of the blob itself, and silently
+//! discards a cache that does not match — the cost is recompiling
+//! pipelines once.
+//!
+//! File layout (little-endian): magic `EXPC`, format version, vendor id,
+//! device id, driver version, pipeline cache UUID (16 bytes), blob length
+//! (u64), blob CRC32, blob.
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use ash::vk;
+use thiserror::Error;
+
+use crate::checksum::crc32;
+
+/// Pipeline cache errors
+#[derive(Error, Debug)]
+pub enum PipelineCacheError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Vulkan error: {0:?}")]
+    VulkanError(vk::Result),
+}
+
+pub type PipelineCacheResult<T> = Result<T, PipelineCacheError>;
+
+const MAGIC: [u8; 4] = *b"EXPC";
+const FORMAT_VERSION: u32 = 1;
+const HEADER_LEN: usize = 4 + 4 * 4 + 16 + 8 + 4;
+
+/// Length of the header Vulkan puts at the start of cache data (version one)
+const VK_HEADER_LEN: usize = 32;
+
+/// Device and driver a cache belongs to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CacheIdentity {
+    pub vendor_id: u32,
+    pub device_id: u32,
+    pub driver_version: u32,
+    pub pipeline_cache_uuid: [u8; vk::UUID_SIZE],
+}
+
+impl CacheIdentity {
+    pub fn for_device(props: &vk::PhysicalDeviceProperties) -> Self {
+        Self {
+            vendor_id: props.vendor_id,
+            device_id: props.device_id,
+            driver_version: props.driver_version,
+            pipeline_cache_uuid: props.pipeline_cache_uuid,
+        }
+    }
+
+    fn encode(&self, blob: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_LEN + blob.len());
+        out.extend_from_slice(&MAGIC);
+        for word in [FORMAT_VERSION, self.vendor_id, self.device_id, self.driver_version] {
+            out.extend_from_slice(&word.to_le_bytes());
+        }
+        out.extend_from_slice(&self.pipeline_cache_uuid);
+        out.extend_from_slice(&(blob.len() as u64).to_le_bytes());
+        out.extend_from_slice(&crc32(blob).to_le_bytes());
+        out.extend_from_slice(blob);
+        out
+    }
+
+    /// The blob in `file`, or why it does not belong to this identity
+    fn decode<'a>(&self, file: &'a [u8]) -> Result<&'a [u8], &'static str> {
+        if file.len() < HEADER_LEN || file[..4] != MAGIC {
+            return Err("not a pipeline cache file");
+        }
+        let word = |i: usize| u32::from_le_bytes(file[4 + i * 4..8 + i * 4].try_into().unwrap());
+        if word(0) != FORMAT_VERSION {
+            return Err("unknown format version");
+        }
+        let stored = Self {
+            vendor_id: word(1),
+            device_id: word(2),
+            driver_version: word(3),
+            pipeline_cache_uuid: file[20..36].try_into().unwrap(),
+        };
+        if stored != *self {
+            return Err("written by another device or driver");
+        }
+        let len = u64::from_le_bytes(file[36..44].try_into().unwrap());
+        let crc = u32::from_le_bytes(file[44..48].try_into().unwrap());
+        let blob = &file[HEADER_LEN..];
+        if blob.len() as u64 != len {
+            return Err("truncated");
+        }
+        if crc32(blob) != crc {
+            return Err("checksum mismatch");
+        }
+        self.check_vulkan_header(blob)?;
+        Ok(blob)
+    }
+
+    /// Check the `VkPipelineCacheHeaderVersionOne` at the start of `blob`
+    fn check_vulkan_header(&self, blob: &[u8]) -> Result<(), &'static str> {
+        if blob.len() < VK_HEADER_LEN {
+            return Err("blob shorter than the Vulkan header");
+        }
+        let word = |i: usize| u32::from_le_bytes(blob[i * 4..i * 4 + 4].try_into().unwrap());
+        let header_len = word(0) as usize;
+        if header_len < VK_HEADER_LEN || header_len > blob.len() {
+            return Err("bad Vulkan header length");
+        }
+        if word(1) != vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32 {
+            return Err("unknown Vulkan header version");
+        }
+        if word(2) != self.vendor_id || word(3) != self.device_id || blob[16..32] != self.pipeline_cache_uuid {
+            return Err("Vulkan header names another device");
+        }
+        Ok(())
+    }
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}.tmp", std::process::id()));
+    path.with_file_name(name)
+}
+
+/// Replace the file at `path` with `contents`
+///
+/// Readers, including a restart after a crash at any point, see either the
+/// previous file or the complete new one.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let temp = temp_path(path);
+    let written = (|| {
+        let mut file = File::create(&temp)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&temp, path)
+    })();
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+
+    // Persist the rename itself; not every platform can open directories
+    if let Some(dir) = path.parent()
+        && let Ok(dir) = File::open(dir)
+    {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+/// Atomically replace the cache at `path` with `blob`
+pub fn save(path: &Path, identity: &CacheIdentity, blob: &[u8]) -> PipelineCacheResult<()> {
+    Ok(write_atomic(path, &identity.encode(blob))?)
+}
+
+/// Cache data saved for `identity`, if any
+///
+/// A file that is corrupt or belongs to another device or driver is deleted
+/// and None returned; only I/O errors other than a missing file are errors.
+pub fn load(path: &Path, identity: &CacheIdentity) -> PipelineCacheResult<Option<Vec<u8>>> {
+    let file = match fs::read(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    match identity.decode(&file) {
+        Ok(blob) => Ok(Some(blob.to_vec())),
+        Err(reason) => {
+            log::info!("Discarding pipeline cache {}: {}", path.display(), reason);
+            let _ = fs::remove_file(path);
+            Ok(None)
+        }
+    }
+}
+
+/// Create a pipeline cache seeded from the file at `path` if it is valid
+///
+/// # Safety Requirements
+/// - device must be a valid logical device of the physical device `identity` describes
+pub unsafe fn create(
+    device: &ash::Device,
+    path: &Path,
+    identity: &CacheIdentity,
+) -> PipelineCacheResult<vk::PipelineCache> {
+    let initial = load(path, identity).unwrap_or_else(|e| {
+        log::warn!("Failed to read pipeline cache {}: {}", path.display(), e);
+        None
+    });
+    let info = vk::PipelineCacheCreateInfo::default().initial_data(initial.as_deref().unwrap_or_default());
+    // SAFETY: device is valid (caller's responsibility); initial data was validated above
+    unsafe { device.create_pipeline_cache(&info, None) }.map_err(PipelineCacheError::VulkanError)
+}
+
+/// Write `cache` to `path`
+///
+/// # Safety Requirements
+/// - cache must be a valid pipeline cache of device
+pub unsafe fn persist(
+    device: &ash::Device,
+    cache: vk::PipelineCache,
+    path: &Path,
+    identity: &CacheIdentity,
+) -> PipelineCacheResult<()> {
+    // SAFETY: cache and device are valid (caller's responsibility)
+    let blob = unsafe { device.get_pipeline_cache_data(cache) }.map_err(PipelineCacheError::VulkanError)?;
+    save(path, identity, &blob)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identity() -> CacheIdentity {
+        CacheIdentity {
+            vendor_id: 0x13B5,
+            device_id: 0x9211,
+            driver_version: 42,
+            pipeline_cache_uuid: [7; 16],
+        }
+    }
+
+    /// Blob with a Vulkan header matching `identity()` and some payload
+    fn blob() -> Vec<u8> {
+        let id = identity();
+        let mut blob = Vec::new();
+        for word in [32, 1, id.vendor_id, id.device_id] {
+            blob.extend_from_slice(&u32::to_le_bytes(word));
+        }
+        blob.extend_from_slice(&id.pipeline_cache_uuid);
+        blob.extend_from_slice(b"driver data");
+        blob
+    }
+
+    fn path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("exo_{}_{}.bin", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_round_trip_and_driver_update_discards() {
+        let path = path("pipeline_cache_round_trip");
+        assert_eq!(load(&path, &identity()).unwrap(), None);
+
+        save(&path, &identity(), &blob()).unwrap();
+        assert_eq!(load(&path, &identity()).unwrap(), Some(blob()));
+        assert!(!temp_path(&path).exists());
+
+        let updated = CacheIdentity {
+            driver_version: 43,
+            ..identity()
+        };
+        assert_eq!(load(&path, &updated).unwrap(), None);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_corrupt_files_are_discarded() {
+        let path = path("pipeline_cache_corrupt");
+        let good = identity().encode(&blob());
+
+        let mut flipped = good.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        let truncated = &good[..good.len() - 3];
+        let mut foreign_blob = blob();
+        foreign_blob[8] ^= 1;
+        for file in [&flipped[..], truncated, &identity().encode(&foreign_blob), b"EXPC"] {
+            fs::write(&path, file).unwrap();
+            assert_eq!(load(&path, &identity()).unwrap(), None);
+            assert!(!path.exists());
+        }
+    }
+}
@@ -0,0 +1,288 @@
+//! Warm pipelines for common dispatch shapes
+//!
+//! Kernels specialized on a shape (a row length, a tile count, a prompt
+//! length bucket) need one pipeline per shape, and compiling one during
+//! interactive use stalls the token that needed it. `PipelinePool` keeps the
+//! specialized pipelines of each kernel resident, keyed by their
+//! specialization constants, and evicts the least recently used one once a
+//! kernel holds `capacity` of them. It also counts how often each shape is
+//! requested; `prewarm` builds the most requested shapes that are not
+//! resident, displacing resident ones requested less often, so an app can
+//! call it while idle and find them warm on the next prompt.
+//!
+//! Lengths should be bucketed (`shape_bucket`, or the graph compiler's
+//! `bucketing` policy) before they become constants, so a prompt growing
+//! by one token keeps its pipeline.
+//!
+//! Evicted pipelines may still be referenced by recorded command buffers,
+//! so they are retired rather than destroyed; `collect_retired` drops them
+//! once that work has completed.
+
+use std::collections::HashMap;
+
+use ash::vk;
+
+use crate::pipeline::{ComputePipeline, PipelineResult};
+
+/// Resident pipelines per kernel unless configured otherwise
+pub const DEFAULT_CAPACITY: usize = 4;
+
+/// Distinct shapes whose request counts are kept per kernel
+pub const MAX_OBSERVED_SHAPES: usize = 64;
+
+/// Power-of-two bucket of a length, so nearby lengths share a pipeline
+pub fn shape_bucket(len: u32) -> u32 {
+    len.max(1).checked_next_power_of_two().unwrap_or(len)
+}
+
+struct Resident<T> {
+    value: T,
+    last_used: u64,
+}
+
+/// Resident values and request counts of one kernel's shapes
+struct ShapeCache<T> {
+    resident: HashMap<Vec<u32>, Resident<T>>,
+    observed: HashMap<Vec<u32>, u64>,
+}
+
+impl<T> Default for ShapeCache<T> {
+    fn default() -> Self {
+        Self {
+            resident: HashMap::new(),
+            observed: HashMap::new(),
+        }
+    }
+}
+
+impl<T> ShapeCache<T> {
+    /// Count a request for `shape`, forgetting the rarest shape when full
+    fn observe(&mut self, shape: &[u32]) {
+        if let Some(count) = self.observed.get_mut(shape) {
+            *count += 1;
+            return;
+        }
+        if self.observed.len() >= MAX_OBSERVED_SHAPES
+            && let Some(rarest) = self.observed.iter().min_by_key(|(_, count)| **count).map(|(s, _)| s.clone())
+        {
+            self.observed.remove(&rarest);
+        }
+        self.observed.insert(shape.to_vec(), 1);
+    }
+
+    fn count(&self, shape: &[u32]) -> u64 {
+        self.observed.get(shape).copied().unwrap_or(0)
+    }
+
+    /// Mark `shape` used at `clock`; false if it is not resident
+    fn touch(&mut self, shape: &[u32], clock: u64) -> bool {
+        match self.resident.get_mut(shape) {
+            Some(entry) => {
+                entry.last_used = clock;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Make `value` resident, returning what was evicted to stay within `capacity`
+    fn insert(&mut self, shape: Vec<u32>, value: T, clock: u64, capacity: usize) -> Vec<T> {
+        let mut evicted = Vec::new();
+        while self.resident.len() >= capacity.max(1) {
+            let Some(oldest) = self
+                .resident
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(s, _)| s.clone())
+            else {
+                break;
+            };
+            evicted.extend(self.resident.remove(&oldest).map(|entry| entry.value));
+        }
+        self.resident.insert(shape, Resident { value, last_used: clock });
+        evicted
+    }
+
+    /// The most requested shape that is not resident, and the resident shape
+    /// it should displace when the cache is full
+    ///
+    /// None once every shape requested more often than a resident one is resident.
+    fn next_to_warm(&self, capacity: usize) -> Option<(Vec<u32>, Option<Vec<u32>>)> {
+        let candidate = self
+            .observed
+            .iter()
+            .filter(|(shape, _)| !self.resident.contains_key(*shape))
+            .max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)))
+            .map(|(shape, _)| shape.clone())?;
+        if self.resident.len() < capacity.max(1) {
+            return Some((candidate, None));
+        }
+        let victim = self
+            .resident
+            .iter()
+            .min_by_key(|(shape, entry)| (self.count(shape), entry.last_used))
+            .map(|(shape, _)| shape.clone())?;
+        (self.count(&victim) < self.count(&candidate)).then_some((candidate, Some(victim)))
+    }
+
+    /// Request counts, most requested first
+    fn observed(&self) -> Vec<(Vec<u32>, u64)> {
+        let mut shapes: Vec<_> = self.observed.iter().map(|(s, c)| (s.clone(), *c)).collect();
+        shapes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        shapes
+    }
+}
+
+/// Pool counters
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PipelinePoolStats {
+    /// Requests served by a resident pipeline
+    pub hits: u64,
+    /// Requests that built a pipeline
+    pub misses: u64,
+    /// Pipelines built by `prewarm`
+    pub prewarmed: u64,
+    pub evictions: u64,
+    pub resident: usize,
+    /// Evicted pipelines waiting for `collect_retired`
+    pub retired: usize,
+}
+
+/// Specialized pipelines of embedded kernels, per kernel and shape
+pub struct PipelinePool {
+    device: ash::Device,
+    cache: vk::PipelineCache,
+    capacity: usize,
+    kernels: HashMap<String, ShapeCache<ComputePipeline>>,
+    retired: Vec<ComputePipeline>,
+    clock: u64,
+    stats: PipelinePoolStats,
+}
+
+impl PipelinePool {
+    /// Create an empty pool keeping up to `capacity` pipelines per kernel
+    ///
+    /// `device` must outlive the pool; `cache` may be null.
+    pub fn new(device: &ash::Device, cache: vk::PipelineCache, capacity: usize) -> Self {
+        Self {
+            device: device.clone(),
+            cache,
+            capacity: capacity.max(1),
+            kernels: HashMap::new(),
+            retired: Vec::new(),
+            clock: 0,
+            stats: PipelinePoolStats::default(),
+        }
+    }
+
+    /// The pipeline of `kernel` specialized with `constants`, built if not resident
+    ///
+    /// Building may evict the kernel's least recently used pipeline to the
+    /// retired list.
+    pub fn get(&mut self, kernel: &str, constants: &[u32]) -> PipelineResult<&ComputePipeline> {
+        self.clock += 1;
+        let shapes = self.kernels.entry(kernel.to_string()).or_default();
+        shapes.observe(constants);
+        if shapes.touch(constants, self.clock) {
+            self.stats.hits += 1;
+        } else {
+            let pipeline = ComputePipeline::from_embedded_specialized(&self.device, kernel, constants, self.cache)?;
+            self.stats.misses += 1;
+            let evicted = shapes.insert(constants.to_vec(), pipeline, self.clock, self.capacity);
+            self.stats.evictions += evicted.len() as u64;
+            self.retired.extend(evicted);
+        }
+        Ok(&shapes.resident[constants].value)
+    }
+
+    /// Build up to `limit` of the most requested shapes of `kernel` that are not resident
+    ///
+    /// A full kernel only gives up pipelines requested less often than the
+    /// shape replacing them. Returns the number of pipelines built.
+    pub fn prewarm(&mut self, kernel: &str, limit: usize) -> PipelineResult<usize> {
+        let Some(shapes) = self.kernels.get_mut(kernel) else {
+            return Ok(0);
+        };
+        let mut built = 0;
+        while built < limit {
+            let Some((shape, victim)) = shapes.next_to_warm(self.capacity) else {
+                break;
+            };
+            let pipeline = ComputePipeline::from_embedded_specialized(&self.device, kernel, &shape, self.cache)?;
+            if let Some(entry) = victim.and_then(|victim| shapes.resident.remove(&victim)) {
+                self.stats.evictions += 1;
+                self.retired.push(entry.value);
+            }
+            self.clock += 1;
+            shapes.insert(shape, pipeline, self.clock, self.capacity);
+            self.stats.prewarmed += 1;
+            built += 1;
+        }
+        if built > 0 {
+            log::debug!("Prewarmed {} pipelines of {}", built, kernel);
+        }
+        Ok(built)
+    }
+
+    /// Shapes requested of `kernel` with their counts, most requested first
+    pub fn observed(&self, kernel: &str) -> Vec<(Vec<u32>, u64)> {
+        self.kernels.get(kernel).map(ShapeCache::observed).unwrap_or_default()
+    }
+
+    /// Destroy evicted pipelines
+    ///
+    /// Call once the command buffers recorded before the evictions have completed.
+    pub fn collect_retired(&mut self) {
+        self.retired.clear();
+    }
+
+    pub fn stats(&self) -> PipelinePoolStats {
+        PipelinePoolStats {
+            resident: self.kernels.values().map(|shapes| shapes.resident.len()).sum(),
+            retired: self.retired.len(),
+            ..self.stats
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shape_bucket() {
+        assert_eq!(shape_bucket(0), 1);
+        assert_eq!(shape_bucket(17), 32);
+        assert_eq!(shape_bucket(512), 512);
+        assert_eq!(shape_bucket(u32::MAX), u32::MAX);
+    }
+
+    #[test]
+    fn test_least_recently_used_is_evicted() {
+        let mut cache = ShapeCache::default();
+        assert!(cache.insert(vec![1], 'a', 1, 2).is_empty());
+        assert!(cache.insert(vec![2], 'b', 2, 2).is_empty());
+        assert!(cache.touch(&[1], 3));
+        assert_eq!(cache.insert(vec![3], 'c', 4, 2), vec!['b']);
+        assert!(!cache.touch(&[2], 5));
+        assert!(cache.touch(&[1], 5) && cache.touch(&[3], 5));
+    }
+
+    #[test]
+    fn test_prewarm_displaces_rarer_shapes() {
+        let mut cache = ShapeCache::default();
+        for shape in [[64], [64], [64], [128], [256], [256]] {
+            cache.observe(&shape);
+        }
+        assert_eq!(cache.observed()[0], (vec![64], 3));
+        assert_eq!(cache.next_to_warm(2), Some((vec![64], None)));
+
+        cache.insert(vec![128], 'x', 1, 2);
+        cache.insert(vec![256], 'y', 2, 2);
+        assert_eq!(cache.next_to_warm(2), Some((vec![64], Some(vec![128]))));
+
+        cache.resident.remove(&vec![128]);
+        cache.insert(vec![64], 'z', 3, 2);
+        assert_eq!(cache.next_to_warm(2), None);
+    }
+}
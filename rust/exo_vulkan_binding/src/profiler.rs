@@ -0,0 +1,645 @@
+//! Per-op latency profiling
+//!
+//! Correlates host-side `Instant` stamps with GPU timestamp queries so each
+//! op can be broken down into queue wait, GPU execution and readback time.
+//! When `VK_EXT_calibrated_timestamps` is available the GPU clock is anchored
+//! to the host clock directly; otherwise queue wait is derived from the host
+//! round-trip minus GPU execution time.
+//!
+//! `TimestampPool` brackets many regions of one command buffer, e.g. every
+//! kernel of a layer, and the `Profiler` aggregates their timings into
+//! per-kernel stats that partitioning can use in place of bandwidth
+//! estimates.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use ash::vk;
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use thiserror::Error;
+
+use crate::trace;
+
+/// Profiler-related errors
+#[derive(Error, Debug)]
+pub enum ProfilerError {
+    #[error("Timestamp queries unsupported: {0}")]
+    Unsupported(String),
+
+    #[error("Timestamp results not ready")]
+    NotReady,
+
+    #[error("Timestamp pool full: {0} regions")]
+    PoolFull(u32),
+
+    #[error("Unknown timestamp region {0}")]
+    UnknownRegion(u32),
+
+    #[error("Vulkan error: {0:?}")]
+    VulkanError(vk::Result),
+}
+
+pub type ProfilerResult<T> = Result<T, ProfilerError>;
+
+/// Anchor pairing a GPU timestamp with the host clock
+#[derive(Clone, Copy, Debug)]
+pub struct ClockCalibration {
+    host_anchor: Instant,
+    gpu_anchor_ticks: u64,
+    timestamp_period_ns: f64,
+}
+
+impl ClockCalibration {
+    /// Build a calibration from an already-sampled pair of clocks
+    pub fn from_samples(host_anchor: Instant, gpu_anchor_ticks: u64, timestamp_period_ns: f32) -> Self {
+        Self {
+            host_anchor,
+            gpu_anchor_ticks,
+            timestamp_period_ns: f64::from(timestamp_period_ns),
+        }
+    }
+
+    /// Sample the device clock via `VK_EXT_calibrated_timestamps`
+    ///
+    /// Returns `None` if the extension is not supported by the physical device.
+    ///
+    /// # Safety Requirements
+    /// - device must have been created with the calibrated timestamps extension
+    ///   enabled whenever the physical device reports support for it
+    pub unsafe fn query(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        physical_device: vk::PhysicalDevice,
+        timestamp_period_ns: f32,
+    ) -> Option<Self> {
+        if !supports_calibrated_timestamps(instance, physical_device) {
+            return None;
+        }
+
+        let loader = ash::ext::calibrated_timestamps::Device::new(instance, device);
+        let info = [vk::CalibratedTimestampInfoEXT::default().time_domain(vk::TimeDomainEXT::DEVICE)];
+
+        // Bracket the device sample with host stamps and anchor at the midpoint
+        let before = Instant::now();
+        // SAFETY: the extension is supported and enabled on device (caller's contract)
+        let (timestamps, _max_deviation) = unsafe { loader.get_calibrated_timestamps(&info) }.ok()?;
+        let after = Instant::now();
+
+        let host_anchor = before + after.duration_since(before) / 2;
+        Some(Self::from_samples(host_anchor, *timestamps.first()?, timestamp_period_ns))
+    }
+
+    /// Convert a GPU tick count to the host clock
+    pub fn gpu_to_host(&self, ticks: u64) -> Instant {
+        let delta_ns = ticks_to_ns(ticks.abs_diff(self.gpu_anchor_ticks), self.timestamp_period_ns);
+        let delta = Duration::from_nanos(delta_ns);
+        if ticks >= self.gpu_anchor_ticks {
+            self.host_anchor + delta
+        } else {
+            self.host_anchor.checked_sub(delta).unwrap_or(self.host_anchor)
+        }
+    }
+}
+
+/// Whether the physical device exposes `VK_EXT_calibrated_timestamps`
+pub fn supports_calibrated_timestamps(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> bool {
+    // SAFETY: physical_device was enumerated from this instance
+    let extensions = match unsafe { instance.enumerate_device_extension_properties(physical_device) } {
+        Ok(extensions) => extensions,
+        Err(_) => return false,
+    };
+    extensions.iter().any(|ext| {
+        ext.extension_name_as_c_str()
+            .is_ok_and(|name| name == ash::ext::calibrated_timestamps::NAME)
+    })
+}
+
+fn ticks_to_ns(ticks: u64, period_ns: f64) -> u64 {
+    (ticks as f64 * period_ns) as u64
+}
+
+/// Two-slot timestamp query pool bracketing a single op
+pub struct TimestampQueries {
+    device: ash::Device,
+    pool: vk::QueryPool,
+    timestamp_period_ns: f32,
+}
+
+impl TimestampQueries {
+    /// Create a query pool with begin/end timestamp slots
+    ///
+    /// # Safety Requirements
+    /// - device must be valid for the lifetime of this object
+    pub fn new(device: ash::Device, timestamp_period_ns: f32) -> ProfilerResult<Self> {
+        if timestamp_period_ns <= 0.0 {
+            return Err(ProfilerError::Unsupported(
+                "device reports timestamp_period of 0".to_string(),
+            ));
+        }
+
+        let pool_info = vk::QueryPoolCreateInfo::default()
+            .query_type(vk::QueryType::TIMESTAMP)
+            .query_count(2);
+
+        // SAFETY: device is valid (caller's responsibility)
+        let pool = unsafe { device.create_query_pool(&pool_info, None) }
+            .map_err(ProfilerError::VulkanError)?;
+
+        Ok(Self {
+            device,
+            pool,
+            timestamp_period_ns,
+        })
+    }
+
+    /// Reset the pool and write the begin timestamp
+    ///
+    /// # Safety Requirements
+    /// - cmd_buffer must be in the recording state
+    pub unsafe fn cmd_begin(&self, cmd_buffer: vk::CommandBuffer) {
+        unsafe {
+            // SAFETY:
+            //   - cmd_buffer is recording (caller's contract)
+            //   - pool is valid and holds two queries
+            self.device.cmd_reset_query_pool(cmd_buffer, self.pool, 0, 2);
+            self.device
+                .cmd_write_timestamp(cmd_buffer, vk::PipelineStageFlags::TOP_OF_PIPE, self.pool, 0);
+        }
+    }
+
+    /// Write the end timestamp
+    ///
+    /// # Safety Requirements
+    /// - cmd_buffer must be in the recording state and `cmd_begin` recorded first
+    pub unsafe fn cmd_end(&self, cmd_buffer: vk::CommandBuffer) {
+        unsafe {
+            // SAFETY:
+            //   - cmd_buffer is recording (caller's contract)
+            //   - pool is valid and holds two queries
+            self.device
+                .cmd_write_timestamp(cmd_buffer, vk::PipelineStageFlags::BOTTOM_OF_PIPE, self.pool, 1);
+        }
+    }
+
+    /// Read back `(begin, end)` GPU ticks once the submission has completed
+    pub fn results(&self) -> ProfilerResult<(u64, u64)> {
+        let mut data = [0u64; 2];
+        // SAFETY: pool is valid and holds exactly two queries
+        match unsafe {
+            self.device
+                .get_query_pool_results(self.pool, 0, &mut data, vk::QueryResultFlags::TYPE_64)
+        } {
+            Ok(()) => Ok((data[0], data[1])),
+            Err(vk::Result::NOT_READY) => Err(ProfilerError::NotReady),
+            Err(e) => Err(ProfilerError::VulkanError(e)),
+        }
+    }
+
+    /// Nanoseconds per GPU tick
+    pub fn timestamp_period_ns(&self) -> f32 {
+        self.timestamp_period_ns
+    }
+}
+
+impl Drop for TimestampQueries {
+    fn drop(&mut self) {
+        unsafe {
+            // SAFETY:
+            //   - pool is valid
+            //   - no submissions referencing it are in flight
+            self.device.destroy_query_pool(self.pool, None);
+        }
+    }
+}
+
+/// Handle to one bracketed region of a `TimestampPool`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RegionId(u32);
+
+/// GPU time of one bracketed region
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegionTiming {
+    pub name: String,
+    pub duration_ns: u64,
+}
+
+impl RegionTiming {
+    /// Timing of a region from its begin/end ticks
+    pub fn from_ticks(name: &str, begin: u64, end: u64, timestamp_period_ns: f32) -> Self {
+        Self {
+            name: name.to_string(),
+            duration_ns: ticks_to_ns(end.saturating_sub(begin), f64::from(timestamp_period_ns)),
+        }
+    }
+}
+
+/// Timestamp query pool bracketing up to `capacity` regions of a command buffer
+///
+/// Record `cmd_reset` first, then `cmd_begin`/`cmd_end` around each region;
+/// once the submission completes, `resolve` returns every region's GPU time.
+pub struct TimestampPool {
+    device: ash::Device,
+    pool: vk::QueryPool,
+    capacity: u32,
+    timestamp_period_ns: f32,
+    /// Names of the regions begun since the last reset
+    regions: Mutex<Vec<String>>,
+}
+
+impl TimestampPool {
+    /// Create a pool for `capacity` regions
+    ///
+    /// # Safety Requirements
+    /// - device must be valid for the lifetime of this object
+    pub fn new(device: ash::Device, timestamp_period_ns: f32, capacity: u32) -> ProfilerResult<Self> {
+        if timestamp_period_ns <= 0.0 {
+            return Err(ProfilerError::Unsupported(
+                "device reports timestamp_period of 0".to_string(),
+            ));
+        }
+
+        let pool_info = vk::QueryPoolCreateInfo::default()
+            .query_type(vk::QueryType::TIMESTAMP)
+            .query_count(capacity.max(1) * 2);
+
+        // SAFETY: device is valid (caller's responsibility)
+        let pool = unsafe { device.create_query_pool(&pool_info, None) }
+            .map_err(ProfilerError::VulkanError)?;
+
+        Ok(Self {
+            device,
+            pool,
+            capacity: capacity.max(1),
+            timestamp_period_ns,
+            regions: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Reset every query and forget previously begun regions
+    ///
+    /// # Safety Requirements
+    /// - cmd_buffer must be in the recording state
+    /// - results of the previous submission must already be resolved
+    pub unsafe fn cmd_reset(&self, cmd_buffer: vk::CommandBuffer) {
+        self.regions.lock().clear();
+        unsafe {
+            // SAFETY:
+            //   - cmd_buffer is recording (caller's contract)
+            //   - pool is valid and holds 2 * capacity queries
+            self.device
+                .cmd_reset_query_pool(cmd_buffer, self.pool, 0, self.capacity * 2);
+        }
+    }
+
+    /// Write the begin timestamp of a region named `name`, e.g. a kernel name
+    ///
+    /// # Safety Requirements
+    /// - cmd_buffer must be in the recording state and `cmd_reset` recorded first
+    pub unsafe fn cmd_begin(&self, cmd_buffer: vk::CommandBuffer, name: &str) -> ProfilerResult<RegionId> {
+        let mut regions = self.regions.lock();
+        let index = regions.len() as u32;
+        if index >= self.capacity {
+            return Err(ProfilerError::PoolFull(self.capacity));
+        }
+        regions.push(name.to_string());
+        unsafe {
+            // SAFETY:
+            //   - cmd_buffer is recording (caller's contract)
+            //   - query 2 * index is in range and was reset
+            self.device
+                .cmd_write_timestamp(cmd_buffer, vk::PipelineStageFlags::TOP_OF_PIPE, self.pool, index * 2);
+        }
+        Ok(RegionId(index))
+    }
+
+    /// Write the end timestamp of `region`
+    ///
+    /// # Safety Requirements
+    /// - cmd_buffer must be in the recording state
+    pub unsafe fn cmd_end(&self, cmd_buffer: vk::CommandBuffer, region: RegionId) -> ProfilerResult<()> {
+        if region.0 as usize >= self.regions.lock().len() {
+            return Err(ProfilerError::UnknownRegion(region.0));
+        }
+        unsafe {
+            // SAFETY:
+            //   - cmd_buffer is recording (caller's contract)
+            //   - query 2 * index + 1 is in range and was reset
+            self.device.cmd_write_timestamp(
+                cmd_buffer,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                self.pool,
+                region.0 * 2 + 1,
+            );
+        }
+        Ok(())
+    }
+
+    /// GPU time of every region begun since the last reset, in begin order
+    ///
+    /// Every region must have been ended and the submission completed.
+    pub fn resolve(&self) -> ProfilerResult<Vec<RegionTiming>> {
+        let regions = self.regions.lock();
+        if regions.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut data = vec![0u64; regions.len() * 2];
+        // SAFETY: pool is valid and the queried range was written by the
+        // completed submission
+        match unsafe {
+            self.device
+                .get_query_pool_results(self.pool, 0, &mut data, vk::QueryResultFlags::TYPE_64)
+        } {
+            Ok(()) => Ok(regions
+                .iter()
+                .zip(data.chunks_exact(2))
+                .map(|(name, ticks)| RegionTiming::from_ticks(name, ticks[0], ticks[1], self.timestamp_period_ns))
+                .collect()),
+            Err(vk::Result::NOT_READY) => Err(ProfilerError::NotReady),
+            Err(e) => Err(ProfilerError::VulkanError(e)),
+        }
+    }
+
+    /// Regions the pool can bracket per submission
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+}
+
+impl Drop for TimestampPool {
+    fn drop(&mut self) {
+        unsafe {
+            // SAFETY:
+            //   - pool is valid
+            //   - no submissions referencing it are in flight
+            self.device.destroy_query_pool(self.pool, None);
+        }
+    }
+}
+
+/// Aggregated GPU time of one kernel across its runs
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct KernelStats {
+    pub count: u64,
+    pub total_ns: u64,
+    pub min_ns: u64,
+    pub max_ns: u64,
+}
+
+impl KernelStats {
+    fn add(&mut self, duration_ns: u64) {
+        self.min_ns = if self.count == 0 { duration_ns } else { self.min_ns.min(duration_ns) };
+        self.max_ns = self.max_ns.max(duration_ns);
+        self.count += 1;
+        self.total_ns = self.total_ns.saturating_add(duration_ns);
+    }
+
+    /// Mean GPU time per run
+    pub fn mean(&self) -> Duration {
+        Duration::from_nanos(self.total_ns.checked_div(self.count).unwrap_or(0))
+    }
+}
+
+/// Raw host and GPU stamps captured around one op
+#[derive(Clone, Copy, Debug)]
+pub struct OpStamps {
+    /// Host time just before `vkQueueSubmit`
+    pub submitted: Instant,
+    /// Host time when the fence / idle wait returned
+    pub completed: Instant,
+    /// Host time when the result was available to the caller
+    pub read_back: Instant,
+    /// GPU tick at the start of the op
+    pub gpu_begin: u64,
+    /// GPU tick at the end of the op
+    pub gpu_end: u64,
+}
+
+/// Latency of one op split into its phases
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LatencyBreakdown {
+    pub queue_wait: Duration,
+    pub execution: Duration,
+    pub readback: Duration,
+}
+
+impl LatencyBreakdown {
+    /// Split an op's stamps into phases
+    ///
+    /// With a calibration the GPU begin/end are mapped onto the host clock;
+    /// without one the completion wait is attributed to queue wait.
+    pub fn from_stamps(stamps: &OpStamps, timestamp_period_ns: f32, calibration: Option<&ClockCalibration>) -> Self {
+        let execution = Duration::from_nanos(ticks_to_ns(
+            stamps.gpu_end.saturating_sub(stamps.gpu_begin),
+            f64::from(timestamp_period_ns),
+        ));
+
+        match calibration {
+            Some(calibration) => {
+                let gpu_begin = calibration.gpu_to_host(stamps.gpu_begin);
+                let gpu_end = calibration.gpu_to_host(stamps.gpu_end);
+                Self {
+                    queue_wait: gpu_begin.saturating_duration_since(stamps.submitted),
+                    execution,
+                    readback: stamps.read_back.saturating_duration_since(gpu_end),
+                }
+            }
+            None => {
+                let round_trip = stamps.completed.saturating_duration_since(stamps.submitted);
+                Self {
+                    queue_wait: round_trip.saturating_sub(execution),
+                    execution,
+                    readback: stamps.read_back.saturating_duration_since(stamps.completed),
+                }
+            }
+        }
+    }
+
+    /// Sum of all phases
+    pub fn total(&self) -> Duration {
+        self.queue_wait + self.execution + self.readback
+    }
+}
+
+/// One profiled op
+#[derive(Clone, Debug)]
+pub struct OpProfile {
+    pub name: String,
+    pub breakdown: LatencyBreakdown,
+    /// Trace id current when the op was recorded
+    pub trace_id: Option<String>,
+}
+
+/// Collects per-op latency breakdowns
+#[derive(Default)]
+pub struct Profiler {
+    calibration: Option<ClockCalibration>,
+    records: Mutex<Vec<OpProfile>>,
+    kernels: Mutex<BTreeMap<String, KernelStats>>,
+}
+
+impl Profiler {
+    /// Create a profiler, optionally anchored with a clock calibration
+    pub fn new(calibration: Option<ClockCalibration>) -> Self {
+        Self {
+            calibration,
+            records: Mutex::new(Vec::new()),
+            kernels: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Whether GPU timestamps are mapped to the host clock
+    pub fn is_calibrated(&self) -> bool {
+        self.calibration.is_some()
+    }
+
+    /// Record the stamps of a completed op, attributing its GPU time to the current trace
+    pub fn record(&self, name: &str, stamps: &OpStamps, timestamp_period_ns: f32) -> LatencyBreakdown {
+        let breakdown = LatencyBreakdown::from_stamps(stamps, timestamp_period_ns, self.calibration.as_ref());
+        trace::record_gpu_time(breakdown.execution);
+        self.records.lock().push(OpProfile {
+            name: name.to_string(),
+            breakdown,
+            trace_id: trace::current(),
+        });
+        breakdown
+    }
+
+    /// Fold resolved `TimestampPool` regions into the per-kernel stats,
+    /// attributing their GPU time to the current trace
+    pub fn record_regions(&self, timings: &[RegionTiming]) {
+        let mut kernels = self.kernels.lock();
+        for timing in timings {
+            trace::record_gpu_time(Duration::from_nanos(timing.duration_ns));
+            kernels.entry(timing.name.clone()).or_default().add(timing.duration_ns);
+        }
+    }
+
+    /// Per-kernel stats, by kernel name
+    pub fn kernel_stats(&self) -> BTreeMap<String, KernelStats> {
+        self.kernels.lock().clone()
+    }
+
+    /// Expected GPU time of running each of `kernels` once, e.g. the
+    /// kernels of one layer, for `NodeProfile::layer_time`
+    ///
+    /// `None` if any kernel has not been measured.
+    pub fn estimate(&self, kernels: &[&str]) -> Option<Duration> {
+        let stats = self.kernels.lock();
+        kernels.iter().map(|name| stats.get(*name).map(KernelStats::mean)).sum()
+    }
+
+    /// Per-kernel stats as JSON: `{"<kernel>": {"count", "mean_ns", "min_ns", "max_ns", "total_ns"}}`
+    pub fn kernel_stats_json(&self) -> String {
+        let entries: Vec<String> = self
+            .kernels
+            .lock()
+            .iter()
+            .map(|(name, stats)| {
+                format!(
+                    r#"{:?}:{{"count":{},"mean_ns":{},"min_ns":{},"max_ns":{},"total_ns":{}}}"#,
+                    name,
+                    stats.count,
+                    stats.mean().as_nanos(),
+                    stats.min_ns,
+                    stats.max_ns,
+                    stats.total_ns
+                )
+            })
+            .collect();
+        format!("{{{}}}", entries.join(","))
+    }
+
+    /// All recorded ops, oldest first
+    pub fn records(&self) -> Vec<OpProfile> {
+        self.records.lock().clone()
+    }
+
+    /// Drop all recorded ops and kernel stats
+    pub fn clear(&self) {
+        self.records.lock().clear();
+        self.kernels.lock().clear();
+    }
+
+    /// Human-readable table of per-op latency breakdowns
+    pub fn report(&self) -> String {
+        let records = self.records.lock();
+        let mut out = format!(
+            "{:<24} {:>12} {:>12} {:>12} {:>12}\n",
+            "op", "queue_us", "exec_us", "readback_us", "total_us"
+        );
+        for record in records.iter() {
+            out.push_str(&format!(
+                "{:<24} {:>12} {:>12} {:>12} {:>12}\n",
+                record.name,
+                record.breakdown.queue_wait.as_micros(),
+                record.breakdown.execution.as_micros(),
+                record.breakdown.readback.as_micros(),
+                record.breakdown.total().as_micros()
+            ));
+        }
+        out
+    }
+}
+
+lazy_static! {
+    static ref PROFILER: Profiler = Profiler::default();
+}
+
+/// The process-wide profiler kernel timings are recorded into
+pub fn global() -> &'static Profiler {
+    &PROFILER
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stamps(base: Instant) -> OpStamps {
+        OpStamps {
+            submitted: base,
+            completed: base + Duration::from_micros(500),
+            read_back: base + Duration::from_micros(700),
+            gpu_begin: 1_000,
+            gpu_end: 1_300,
+        }
+    }
+
+    #[test]
+    fn test_uncalibrated_breakdown() {
+        let base = Instant::now();
+        let breakdown = LatencyBreakdown::from_stamps(&stamps(base), 1000.0, None);
+        assert_eq!(breakdown.execution, Duration::from_micros(300));
+        assert_eq!(breakdown.queue_wait, Duration::from_micros(200));
+        assert_eq!(breakdown.readback, Duration::from_micros(200));
+    }
+
+    #[test]
+    fn test_calibrated_breakdown() {
+        let base = Instant::now();
+        // GPU tick 1_000 happened 100us after submit
+        let calibration = ClockCalibration::from_samples(base + Duration::from_micros(100), 1_000, 1000.0);
+        let breakdown = LatencyBreakdown::from_stamps(&stamps(base), 1000.0, Some(&calibration));
+        assert_eq!(breakdown.queue_wait, Duration::from_micros(100));
+        assert_eq!(breakdown.execution, Duration::from_micros(300));
+        assert_eq!(breakdown.readback, Duration::from_micros(300));
+    }
+
+    #[test]
+    fn test_kernel_stats_aggregate_regions() {
+        let profiler = Profiler::new(None);
+        let timings = [
+            RegionTiming::from_ticks("gemm", 100, 400, 10.0),
+            RegionTiming::from_ticks("gemm", 0, 100, 10.0),
+            RegionTiming::from_ticks("softmax", 0, 50, 10.0),
+        ];
+        profiler.record_regions(&timings);
+
+        let stats = profiler.kernel_stats()["gemm"];
+        assert_eq!((stats.count, stats.min_ns, stats.max_ns), (2, 1_000, 3_000));
+        assert_eq!(stats.mean(), Duration::from_nanos(2_000));
+        assert_eq!(profiler.estimate(&["gemm", "softmax"]), Some(Duration::from_nanos(2_500)));
+        assert_eq!(profiler.estimate(&["gemm", "rope"]), None);
+        assert!(profiler.kernel_stats_json().contains(r#""softmax":{"count":1,"mean_ns":500"#));
+    }
+}
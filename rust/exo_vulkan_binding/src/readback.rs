@@ -0,0 +1,312 @@
+//! Overlapped logits readback
+//!
+//! Downloading logits with a copy at the end of the compute submission keeps
+//! the compute queue busy with DMA work and makes the host wait on the whole
+//! submission. `LogitsReadback` moves the copy to the transfer queue instead:
+//! the compute submission that produces the logits signals a semaphore, a
+//! transfer submission waits on it and copies into a persistently mapped
+//! staging buffer, and the compute queue is free to start on the next layer
+//! (or the next sequence in the batch) in the meantime. On devices with a
+//! dedicated DMA engine the download then costs the compute queue nothing.
+
+use std::time::Duration;
+
+use ash::vk;
+use thiserror::Error;
+
+use crate::command::{CommandError, CommandPool, Fence, Queue, Semaphore};
+use crate::memory::{AllocationInfo, BufferRange};
+use crate::tensor::ELEMENT_SIZE;
+
+/// Readback-related errors
+#[derive(Error, Debug)]
+pub enum ReadbackError {
+    #[error("Staging buffer must be host-visible and mapped")]
+    StagingNotMapped,
+
+    #[error("Logits of {logits} bytes do not fit the {staging} byte staging buffer")]
+    TooLarge { logits: u64, staging: u64 },
+
+    #[error("A readback is already in flight")]
+    Busy,
+
+    #[error("No readback in flight")]
+    NotSubmitted,
+
+    #[error("Readback did not finish within {0:?}")]
+    Timeout(Duration),
+
+    #[error(transparent)]
+    Command(#[from] CommandError),
+
+    #[error("Vulkan error: {0:?}")]
+    VulkanError(vk::Result),
+}
+
+pub type ReadbackResult<T> = Result<T, ReadbackError>;
+
+/// Downloads logits on the transfer queue, overlapped with compute
+pub struct LogitsReadback {
+    device: ash::Device,
+    transfer_queue: Queue,
+    compute_family: u32,
+    pool: CommandPool,
+    cmd: vk::CommandBuffer,
+    ready: Semaphore,
+    fence: Fence,
+    staging: AllocationInfo,
+    /// Bytes of the readback in flight, if any
+    in_flight: Option<u64>,
+}
+
+// SAFETY: staging.mapped_ptr points into memory owned by the staging
+// allocation for as long as this struct lives, and is only dereferenced
+// through &mut self
+unsafe impl Send for LogitsReadback {}
+
+impl LogitsReadback {
+    /// Create a readback path on `transfer_queue`
+    ///
+    /// `staging` must be a mapped, host-visible allocation usable as a
+    /// TRANSFER_DST buffer, sized for the largest logits to download.
+    pub fn new(
+        device: ash::Device,
+        transfer_queue: Queue,
+        compute_family: u32,
+        staging: AllocationInfo,
+    ) -> ReadbackResult<Self> {
+        if staging.mapped_ptr.is_none()
+            || !staging
+                .memory_properties
+                .contains(vk::MemoryPropertyFlags::HOST_VISIBLE)
+        {
+            return Err(ReadbackError::StagingNotMapped);
+        }
+        let pool = CommandPool::new(device.clone(), transfer_queue.queue_family_index())?;
+        let cmd = pool.allocate_buffers(1)?[0];
+        Ok(Self {
+            ready: Semaphore::new(device.clone())?,
+            fence: Fence::new(device.clone(), false)?,
+            device,
+            transfer_queue,
+            compute_family,
+            pool,
+            cmd,
+            staging,
+            in_flight: None,
+        })
+    }
+
+    /// Semaphore the compute submission that writes the logits must signal
+    pub fn ready_semaphore(&self) -> vk::Semaphore {
+        self.ready.raw()
+    }
+
+    /// Whether compute and transfer use different queue families
+    fn crosses_families(&self) -> bool {
+        self.compute_family != self.transfer_queue.queue_family_index()
+    }
+
+    fn ownership_barrier(
+        &self,
+        logits: &BufferRange,
+        src_access: vk::AccessFlags,
+        dst_access: vk::AccessFlags,
+    ) -> vk::BufferMemoryBarrier<'static> {
+        vk::BufferMemoryBarrier::default()
+            .src_access_mask(src_access)
+            .dst_access_mask(dst_access)
+            .src_queue_family_index(self.compute_family)
+            .dst_queue_family_index(self.transfer_queue.queue_family_index())
+            .buffer(logits.buffer)
+            .offset(logits.offset)
+            .size(logits.size)
+    }
+
+    /// Record, last in the compute command buffer, the hand-off of `logits`
+    /// to the transfer queue family
+    ///
+    /// A no-op when both queues share a family; the semaphore alone orders
+    /// the copy after the compute writes.
+    ///
+    /// # Safety Requirements
+    /// - compute_cmd must be recording on a queue of the compute family
+    /// - logits must be the range later passed to `submit`
+    pub unsafe fn record_release(&self, compute_cmd: vk::CommandBuffer, logits: &BufferRange) {
+        if !self.crosses_families() {
+            return;
+        }
+        let barrier = self.ownership_barrier(
+            logits,
+            vk::AccessFlags::SHADER_WRITE,
+            vk::AccessFlags::empty(),
+        );
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe {
+            self.device.cmd_pipeline_barrier(
+                compute_cmd,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[barrier],
+                &[],
+            );
+        }
+    }
+
+    /// Queue the download of `logits` behind the compute submission that
+    /// signals `ready_semaphore`
+    ///
+    /// # Safety Requirements
+    /// - the compute submission signalling `ready_semaphore` must already be
+    ///   submitted (or be submitted before this queue can execute)
+    /// - logits must not be rewritten until `wait` returns
+    pub unsafe fn submit(&mut self, logits: &BufferRange) -> ReadbackResult<()> {
+        if self.in_flight.is_some() {
+            return Err(ReadbackError::Busy);
+        }
+        if logits.size > self.staging.size {
+            return Err(ReadbackError::TooLarge {
+                logits: logits.size,
+                staging: self.staging.size,
+            });
+        }
+
+        let region = vk::BufferCopy::default()
+            .src_offset(logits.offset)
+            .dst_offset(self.staging.offset)
+            .size(logits.size);
+        let host_barrier = vk::MemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::HOST_READ);
+
+        self.pool.reset_buffer(self.cmd)?;
+        self.pool
+            .begin_recording(self.cmd, vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
+        // SAFETY: cmd is recording; both buffers are valid (caller / new)
+        unsafe {
+            if self.crosses_families() {
+                // Acquire half of the release recorded on the compute queue
+                let acquire = self.ownership_barrier(
+                    logits,
+                    vk::AccessFlags::empty(),
+                    vk::AccessFlags::TRANSFER_READ,
+                );
+                self.device.cmd_pipeline_barrier(
+                    self.cmd,
+                    vk::PipelineStageFlags::TOP_OF_PIPE,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[acquire],
+                    &[],
+                );
+            }
+            self.device
+                .cmd_copy_buffer(self.cmd, logits.buffer, self.staging.buffer, &[region]);
+            self.device.cmd_pipeline_barrier(
+                self.cmd,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(),
+                &[host_barrier],
+                &[],
+                &[],
+            );
+        }
+        self.pool.end_recording(self.cmd)?;
+
+        self.fence.reset()?;
+        self.transfer_queue.submit(
+            &[self.cmd],
+            Some(self.ready.raw()),
+            None,
+            Some(self.fence.raw()),
+        )?;
+        self.in_flight = Some(logits.size);
+        Ok(())
+    }
+
+    /// Whether the download in flight has landed in the staging buffer
+    pub fn is_ready(&self) -> ReadbackResult<bool> {
+        if self.in_flight.is_none() {
+            return Err(ReadbackError::NotSubmitted);
+        }
+        Ok(self.fence.wait(0)?)
+    }
+
+    /// Wait for the download and copy the logits out as f32
+    pub fn wait(&mut self, timeout: Duration) -> ReadbackResult<Vec<f32>> {
+        let size = self.in_flight.ok_or(ReadbackError::NotSubmitted)?;
+        if !self
+            .fence
+            .wait(timeout.as_nanos().min(u64::MAX as u128) as u64)?
+        {
+            return Err(ReadbackError::Timeout(timeout));
+        }
+        self.in_flight = None;
+
+        if !self
+            .staging
+            .memory_properties
+            .contains(vk::MemoryPropertyFlags::HOST_COHERENT)
+        {
+            let range = vk::MappedMemoryRange::default()
+                .memory(self.staging.device_memory)
+                .offset(self.staging.offset)
+                .size(vk::WHOLE_SIZE);
+            // SAFETY: the staging memory is mapped (checked in new)
+            unsafe { self.device.invalidate_mapped_memory_ranges(&[range]) }
+                .map_err(ReadbackError::VulkanError)?;
+        }
+
+        let ptr = self
+            .staging
+            .mapped_ptr
+            .ok_or(ReadbackError::StagingNotMapped)?;
+        // SAFETY: the mapping covers staging.size >= size bytes, and the
+        // fence guarantees the transfer finished writing them
+        let bytes = unsafe { std::slice::from_raw_parts(ptr, size as usize) };
+        Ok(logits_from_bytes(bytes))
+    }
+}
+
+impl Drop for LogitsReadback {
+    fn drop(&mut self) {
+        if self.in_flight.is_some() {
+            // The semaphore, fence and command buffer must outlive the submission
+            let _ = self.fence.wait(u64::MAX);
+        }
+    }
+}
+
+/// Decode native-endian f32 logits
+pub fn logits_from_bytes(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(ELEMENT_SIZE as usize)
+        .map(|b| f32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_logits_from_bytes() {
+        let logits = [1.5f32, -2.0, 0.25];
+        let bytes: Vec<u8> = logits.iter().flat_map(|v| v.to_ne_bytes()).collect();
+        assert_eq!(logits_from_bytes(&bytes), logits);
+        assert!(logits_from_bytes(&bytes[..3]).is_empty());
+    }
+
+    #[test]
+    fn test_readback_error_display() {
+        let err = ReadbackError::TooLarge {
+            logits: 128,
+            staging: 64,
+        };
+        assert!(err.to_string().contains("128"));
+    }
+}
@@ -0,0 +1,204 @@
+//! Namespaced handle registry
+//!
+//! Handles handed out to clients (gRPC sessions, Android Activities) are
+//! recorded under the client that created them. A client can only read or
+//! free its own handles, and when it disconnects or its Activity is
+//! destroyed, everything it still holds is released in one call. Callers
+//! that predate namespaces use `DEFAULT_CLIENT`.
+
+use std::collections::HashMap;
+
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Namespace of callers that do not identify themselves
+pub const DEFAULT_CLIENT: &str = "default";
+
+/// Registry-related errors
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum RegistryError {
+    #[error("Handle not found: {0}")]
+    NotFound(String),
+
+    /// The handle exists in another client's namespace; which one is not
+    /// disclosed
+    #[error("Handle {0} belongs to another client")]
+    AccessDenied(String),
+
+    #[error("Unknown client: {0}")]
+    UnknownClient(String),
+
+    #[error("Handle already registered: {0}")]
+    Duplicate(String),
+}
+
+pub type RegistryResult<T> = Result<T, RegistryError>;
+
+/// Handles grouped by owning client
+#[derive(Debug)]
+pub struct HandleRegistry<T> {
+    /// client -> handle -> value
+    clients: HashMap<String, HashMap<String, T>>,
+    /// handle -> owning client
+    owners: HashMap<String, String>,
+}
+
+impl<T> Default for HandleRegistry<T> {
+    fn default() -> Self {
+        let mut clients = HashMap::new();
+        clients.insert(DEFAULT_CLIENT.to_string(), HashMap::new());
+        Self {
+            clients,
+            owners: HashMap::new(),
+        }
+    }
+}
+
+impl<T> HandleRegistry<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open a fresh client namespace and return its id
+    pub fn open_client(&mut self) -> String {
+        let client = Uuid::new_v4().to_string();
+        self.clients.insert(client.clone(), HashMap::new());
+        client
+    }
+
+    pub fn has_client(&self, client: &str) -> bool {
+        self.clients.contains_key(client)
+    }
+
+    /// Release every handle of `client` and close its namespace
+    ///
+    /// The default namespace is emptied but stays open.
+    pub fn close_client(&mut self, client: &str) -> RegistryResult<Vec<(String, T)>> {
+        let handles = if client == DEFAULT_CLIENT {
+            self.clients.get_mut(client).map(std::mem::take)
+        } else {
+            self.clients.remove(client)
+        }
+        .ok_or_else(|| RegistryError::UnknownClient(client.to_string()))?;
+
+        for handle in handles.keys() {
+            self.owners.remove(handle);
+        }
+        Ok(handles.into_iter().collect())
+    }
+
+    /// Record `handle` under `client`
+    pub fn insert(&mut self, client: &str, handle: &str, value: T) -> RegistryResult<()> {
+        if self.owners.contains_key(handle) {
+            return Err(RegistryError::Duplicate(handle.to_string()));
+        }
+        let handles = self
+            .clients
+            .get_mut(client)
+            .ok_or_else(|| RegistryError::UnknownClient(client.to_string()))?;
+        handles.insert(handle.to_string(), value);
+        self.owners.insert(handle.to_string(), client.to_string());
+        Ok(())
+    }
+
+    fn check_owner(&self, client: &str, handle: &str) -> RegistryResult<()> {
+        if !self.clients.contains_key(client) {
+            return Err(RegistryError::UnknownClient(client.to_string()));
+        }
+        match self.owners.get(handle) {
+            Some(owner) if owner == client => Ok(()),
+            Some(_) => Err(RegistryError::AccessDenied(handle.to_string())),
+            None => Err(RegistryError::NotFound(handle.to_string())),
+        }
+    }
+
+    /// Look up a handle owned by `client`
+    pub fn get(&self, client: &str, handle: &str) -> RegistryResult<&T> {
+        self.check_owner(client, handle)?;
+        self.clients
+            .get(client)
+            .and_then(|handles| handles.get(handle))
+            .ok_or_else(|| RegistryError::NotFound(handle.to_string()))
+    }
+
+    /// Remove a handle owned by `client`
+    pub fn remove(&mut self, client: &str, handle: &str) -> RegistryResult<T> {
+        self.check_owner(client, handle)?;
+        self.owners.remove(handle);
+        self.clients
+            .get_mut(client)
+            .and_then(|handles| handles.remove(handle))
+            .ok_or_else(|| RegistryError::NotFound(handle.to_string()))
+    }
+
+    /// Remove a handle whatever its owner, for backend-internal cleanup
+    /// such as model eviction
+    pub fn remove_any(&mut self, handle: &str) -> Option<T> {
+        let owner = self.owners.remove(handle)?;
+        self.clients.get_mut(&owner)?.remove(handle)
+    }
+
+    /// Number of handles owned by `client`
+    pub fn client_len(&self, client: &str) -> usize {
+        self.clients.get(client).map_or(0, HashMap::len)
+    }
+
+    /// Every handle as `(client, handle, value)`, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str, &T)> {
+        self.clients.iter().flat_map(|(client, handles)| {
+            handles
+                .iter()
+                .map(move |(handle, value)| (client.as_str(), handle.as_str(), value))
+        })
+    }
+
+    /// Total number of handles
+    pub fn len(&self) -> usize {
+        self.owners.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.owners.is_empty()
+    }
+
+    /// Drop every handle and every client namespace except the default one
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clients_are_isolated() {
+        let mut registry = HandleRegistry::new();
+        let a = registry.open_client();
+        let b = registry.open_client();
+        registry.insert(&a, "h1", 1).unwrap();
+
+        assert_eq!(registry.get(&a, "h1"), Ok(&1));
+        assert_eq!(registry.get(&b, "h1"), Err(RegistryError::AccessDenied("h1".to_string())));
+        assert_eq!(registry.remove(&b, "h1"), Err(RegistryError::AccessDenied("h1".to_string())));
+        assert_eq!(registry.get(DEFAULT_CLIENT, "h2"), Err(RegistryError::NotFound("h2".to_string())));
+        assert!(registry.insert(&b, "h1", 2).is_err());
+    }
+
+    #[test]
+    fn test_close_client_releases_handles() {
+        let mut registry = HandleRegistry::new();
+        let a = registry.open_client();
+        registry.insert(&a, "h1", 1).unwrap();
+        registry.insert(&a, "h2", 2).unwrap();
+        registry.insert(DEFAULT_CLIENT, "h3", 3).unwrap();
+
+        let mut released = registry.close_client(&a).unwrap();
+        released.sort();
+        assert_eq!(released, vec![("h1".to_string(), 1), ("h2".to_string(), 2)]);
+        assert!(!registry.has_client(&a));
+        assert_eq!(registry.len(), 1);
+        assert_eq!(registry.remove_any("h3"), Some(3));
+        assert!(registry.has_client(DEFAULT_CLIENT));
+    }
+}
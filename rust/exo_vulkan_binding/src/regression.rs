@@ -0,0 +1,750 @@
+//! Golden-output regression tests
+//!
+//! A GPU driver update can silently change kernel results, so each fixture
+//! under `fixtures/regression` is a tiny model fragment with the outputs it
+//! must produce: its inputs (shape and values), its nodes as `graph` ops,
+//! and golden values for some of those nodes, computed in float64
+//! independently of this crate. `run_regression_tests` uploads every
+//! fixture to a device, compiles it with `graph::fuse_elementwise`, runs
+//! the plan through `graph::execute` and compares the read-back outputs with
+//! the goldens.
+//!
+//! An output passes when every element is within `tolerance` of its golden
+//! value, relative to `1 + |golden|`, so small values are held to an
+//! absolute and large ones to a relative bound. Fixtures are embedded at
+//! build time; adding one means adding its JSON file and a line to
+//! `FIXTURES`.
+//!
+//! The built-in kernels have no elementwise megakernel, so the device
+//! backend replays fused chains node by node; `scale`, `relu` and
+//! `bias_add` have no kernel at all and are only evaluated on the host.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+
+use ash::vk;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::command::{CommandError, CommandPool, Fence, Queue};
+use crate::device::{DeviceConfig, DeviceError, LogicalDevice};
+use crate::graph::{self, Dispatch, DispatchBackend, ExecuteOptions, Graph, GraphError, GraphResult, NodeId, OpKind};
+use crate::kernel_library::{
+    Activation, DEFAULT_RMS_EPS, Elementwise, KernelRegistry, LibraryError, MatmulDims, matmul_host, rms_norm_host,
+    softmax_host,
+};
+use crate::memory::{AllocationSpec, BufferRange, MemoryAllocator, MemoryError, MemoryUsage};
+use crate::ops::{NonFinite, check_finite_host, compute_barrier};
+use crate::tensor::Layout;
+use crate::transfer::{DataTransfer, TransferError};
+use crate::{VulkanContext, VulkanError};
+
+/// Embedded fixtures, by file name
+pub const FIXTURES: [(&str, &str); 2] = [
+    ("mlp_block.json", include_str!("../fixtures/regression/mlp_block.json")),
+    ("attention_head.json", include_str!("../fixtures/regression/attention_head.json")),
+];
+
+/// Longest one dispatch may take before the device is considered hung
+const DISPATCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Regression test errors
+#[derive(Error, Debug)]
+pub enum RegressionError {
+    #[error("Invalid fixture {file}: {reason}")]
+    InvalidFixture { file: String, reason: String },
+
+    #[error("No built-in kernel for {0}")]
+    Unsupported(&'static str),
+
+    #[error("Output {0} is not materialized by the plan")]
+    NotMaterialized(String),
+
+    #[error("Dispatch did not finish in time")]
+    Timeout,
+
+    #[error(transparent)]
+    Context(#[from] VulkanError),
+
+    #[error(transparent)]
+    Device(#[from] DeviceError),
+
+    #[error(transparent)]
+    Memory(#[from] MemoryError),
+
+    #[error(transparent)]
+    Command(#[from] CommandError),
+
+    #[error(transparent)]
+    Transfer(#[from] TransferError),
+
+    #[error(transparent)]
+    Library(#[from] LibraryError),
+
+    #[error(transparent)]
+    Graph(#[from] GraphError),
+}
+
+pub type RegressionResult<T> = Result<T, RegressionError>;
+
+#[derive(Deserialize)]
+struct FixtureFile {
+    name: String,
+    #[serde(default)]
+    description: String,
+    tolerance: f32,
+    inputs: Vec<InputSpec>,
+    nodes: Vec<NodeSpec>,
+    outputs: BTreeMap<String, Vec<f32>>,
+}
+
+#[derive(Deserialize)]
+struct InputSpec {
+    name: String,
+    shape: [u32; 2],
+    data: Vec<f32>,
+}
+
+#[derive(Deserialize)]
+struct NodeSpec {
+    name: String,
+    op: String,
+    inputs: Vec<String>,
+    /// Factor of a `scale` op
+    #[serde(default)]
+    scale: Option<f32>,
+}
+
+/// A parsed fixture: a graph, its inputs and the values some nodes must take
+#[derive(Clone, Debug)]
+pub struct Fixture {
+    pub name: String,
+    pub description: String,
+    pub tolerance: f32,
+    pub graph: Graph,
+    /// `[rows, cols]` of every node
+    pub shapes: Vec<[u32; 2]>,
+    pub inputs: Vec<(NodeId, Vec<f32>)>,
+    pub golden: Vec<(NodeId, Vec<f32>)>,
+}
+
+fn parse_op(name: &str, scale: Option<f32>) -> Option<OpKind> {
+    Some(match name {
+        "add" => OpKind::Add,
+        "mul" => OpKind::Mul,
+        "scale" => OpKind::Scale(scale?),
+        "bias_add" => OpKind::BiasAdd,
+        "gelu" => OpKind::Gelu,
+        "relu" => OpKind::Relu,
+        "silu" => OpKind::Silu,
+        "matmul" => OpKind::MatMul,
+        "softmax" => OpKind::Softmax,
+        "rms_norm" => OpKind::RmsNorm,
+        _ => return None,
+    })
+}
+
+/// Output shape of `op` over inputs of `shapes`, or why they do not fit
+fn infer_shape(op: OpKind, shapes: &[[u32; 2]]) -> Result<[u32; 2], String> {
+    let arity = match op {
+        OpKind::MatMul | OpKind::RmsNorm | OpKind::BiasAdd => 2..=2,
+        OpKind::Add | OpKind::Mul => 1..=2,
+        _ => 1..=1,
+    };
+    if !arity.contains(&shapes.len()) {
+        return Err(format!("{} takes {:?} inputs, got {}", op.name(), arity, shapes.len()));
+    }
+    let numel = |[rows, cols]: [u32; 2]| rows * cols;
+    let a = shapes[0];
+    let fits = match op {
+        OpKind::MatMul => {
+            let b = shapes[1];
+            return if a[1] == b[0] {
+                Ok([a[0], b[1]])
+            } else {
+                Err(format!("matmul of {:?} and {:?}", a, b))
+            };
+        }
+        OpKind::Add | OpKind::Mul => shapes.get(1).is_none_or(|&b| numel(b) == numel(a)),
+        OpKind::RmsNorm | OpKind::BiasAdd => numel(shapes[1]) == a[1],
+        _ => true,
+    };
+    if fits {
+        Ok(a)
+    } else {
+        Err(format!("{} of {:?} and {:?}", op.name(), a, shapes[1]))
+    }
+}
+
+impl Fixture {
+    /// Parse and check the fixture JSON of `file`
+    pub fn parse(file: &str, json: &str) -> RegressionResult<Self> {
+        let invalid = |reason: String| RegressionError::InvalidFixture {
+            file: file.to_string(),
+            reason,
+        };
+        let spec: FixtureFile = serde_json::from_str(json).map_err(|e| invalid(e.to_string()))?;
+
+        let mut graph = Graph::new();
+        let mut ids: HashMap<String, NodeId> = HashMap::new();
+        let mut shapes = Vec::new();
+        let mut inputs = Vec::new();
+        for input in spec.inputs {
+            if input.data.len() != (input.shape[0] * input.shape[1]) as usize {
+                return Err(invalid(format!(
+                    "input {} has {} values for shape {:?}",
+                    input.name,
+                    input.data.len(),
+                    input.shape
+                )));
+            }
+            let id = graph.input();
+            graph.set_name(id, &input.name)?;
+            if ids.insert(input.name.clone(), id).is_some() {
+                return Err(invalid(format!("duplicate name {}", input.name)));
+            }
+            shapes.push(input.shape);
+            inputs.push((id, input.data));
+        }
+        for node in spec.nodes {
+            let op = parse_op(&node.op, node.scale).ok_or_else(|| invalid(format!("unknown op {:?}", node.op)))?;
+            let operands = node
+                .inputs
+                .iter()
+                .map(|name| ids.get(name).copied().ok_or_else(|| invalid(format!("unknown input {}", name))))
+                .collect::<RegressionResult<Vec<_>>>()?;
+            let operand_shapes: Vec<_> = operands.iter().map(|&i| shapes[i]).collect();
+            let shape = infer_shape(op, &operand_shapes).map_err(|e| invalid(format!("node {}: {}", node.name, e)))?;
+            let id = graph.add(op, &operands)?;
+            graph.set_name(id, &node.name)?;
+            if ids.insert(node.name.clone(), id).is_some() {
+                return Err(invalid(format!("duplicate name {}", node.name)));
+            }
+            shapes.push(shape);
+        }
+        let golden = spec
+            .outputs
+            .into_iter()
+            .map(|(name, values)| {
+                let id = *ids.get(&name).ok_or_else(|| invalid(format!("unknown output {}", name)))?;
+                let [rows, cols] = shapes[id];
+                if values.len() != (rows * cols) as usize {
+                    return Err(invalid(format!("output {} has {} values for shape {:?}", name, values.len(), shapes[id])));
+                }
+                Ok((id, values))
+            })
+            .collect::<RegressionResult<Vec<_>>>()?;
+
+        Ok(Self {
+            name: spec.name,
+            description: spec.description,
+            tolerance: spec.tolerance,
+            graph,
+            shapes,
+            inputs,
+            golden,
+        })
+    }
+
+    fn numel(&self, node: NodeId) -> usize {
+        let [rows, cols] = self.shapes[node];
+        (rows * cols) as usize
+    }
+
+    /// Compare computed `outputs` (indexed by node) with the goldens
+    pub fn check(&self, outputs: &[Option<Vec<f32>>]) -> Vec<OutputCheck> {
+        self.golden
+            .iter()
+            .map(|(node, want)| {
+                let name = self.graph.label(*node);
+                match outputs.get(*node).and_then(Option::as_ref) {
+                    Some(got) => OutputCheck::compare(name, want, got, self.tolerance),
+                    None => OutputCheck::missing(name),
+                }
+            })
+            .collect()
+    }
+
+    /// Run on the host through `graph::execute`, as the device run does
+    pub fn run_host(&self) -> RegressionResult<Vec<OutputCheck>> {
+        let mut backend = HostBackend {
+            fixture: self,
+            values: vec![None; self.shapes.len()],
+        };
+        for (node, data) in &self.inputs {
+            backend.values[*node] = Some(data.clone());
+        }
+        let plan = graph::fuse_elementwise(&self.graph)?;
+        graph::execute(&self.graph, &plan, &mut backend, ExecuteOptions::default())?;
+        Ok(self.check(&backend.values))
+    }
+}
+
+/// All embedded fixtures
+pub fn fixtures() -> RegressionResult<Vec<Fixture>> {
+    FIXTURES.iter().map(|(file, json)| Fixture::parse(file, json)).collect()
+}
+
+/// Result of comparing one output with its golden values
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutputCheck {
+    pub name: String,
+    /// Largest `|got - golden| / (1 + |golden|)`; infinite for non-finite
+    /// or missing values
+    pub max_error: f32,
+    /// Element with the largest error
+    pub worst_index: usize,
+    pub passed: bool,
+}
+
+impl OutputCheck {
+    fn compare(name: String, want: &[f32], got: &[f32], tolerance: f32) -> Self {
+        if want.len() != got.len() {
+            return Self::missing(name);
+        }
+        let (worst_index, max_error) = want
+            .iter()
+            .zip(got)
+            .map(|(w, g)| {
+                let error = (g - w).abs() / (1.0 + w.abs());
+                if error.is_finite() { error } else { f32::INFINITY }
+            })
+            .enumerate()
+            .fold((0, 0.0), |worst, (i, e)| if e > worst.1 { (i, e) } else { worst });
+        Self {
+            name,
+            max_error,
+            worst_index,
+            passed: max_error <= tolerance,
+        }
+    }
+
+    fn missing(name: String) -> Self {
+        Self {
+            name,
+            max_error: f32::INFINITY,
+            worst_index: 0,
+            passed: false,
+        }
+    }
+}
+
+/// Outcome of one fixture
+#[derive(Clone, Debug, PartialEq)]
+pub struct FixtureResult {
+    pub name: String,
+    /// Output comparisons, or why the fixture could not run
+    pub outcome: Result<Vec<OutputCheck>, String>,
+}
+
+impl FixtureResult {
+    pub fn passed(&self) -> bool {
+        self.outcome.as_ref().is_ok_and(|checks| checks.iter().all(|c| c.passed))
+    }
+}
+
+/// Results of one device
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegressionReport {
+    pub device: String,
+    pub driver_version: u32,
+    pub results: Vec<FixtureResult>,
+}
+
+impl RegressionReport {
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(FixtureResult::passed)
+    }
+
+    pub fn to_json(&self) -> String {
+        let results: Vec<Value> = self
+            .results
+            .iter()
+            .map(|r| {
+                let mut entry = json!({"name": r.name, "passed": r.passed()});
+                match &r.outcome {
+                    Ok(checks) => {
+                        entry["outputs"] = checks
+                            .iter()
+                            .map(|c| {
+                                json!({
+                                    "name": c.name,
+                                    "passed": c.passed,
+                                    // JSON has no infinity
+                                    "max_error": c.max_error.is_finite().then_some(c.max_error),
+                                    "worst_index": c.worst_index,
+                                })
+                            })
+                            .collect();
+                    }
+                    Err(reason) => entry["error"] = json!(reason),
+                }
+                entry
+            })
+            .collect();
+        json!({
+            "device": self.device,
+            "driver_version": self.driver_version,
+            "passed": self.passed(),
+            "results": results,
+        })
+        .to_string()
+    }
+}
+
+/// Host value of a non-input node from its operands
+fn eval_host(op: OpKind, operands: &[&[f32]], operand_shapes: &[[u32; 2]]) -> Vec<f32> {
+    let a = operands[0];
+    let [rows, cols] = operand_shapes[0];
+    match op {
+        OpKind::MatMul => matmul_host(
+            a,
+            operands[1],
+            MatmulDims {
+                m: rows,
+                n: operand_shapes[1][1],
+                k: cols,
+            },
+        ),
+        OpKind::Softmax => softmax_host(a, cols as usize),
+        OpKind::RmsNorm => rms_norm_host(a, operands[1], DEFAULT_RMS_EPS),
+        _ => {
+            // Elementwise, with the fused kernel's semantics: a missing second
+            // operand is the first, a shorter one is broadcast
+            let b = operands.get(1).copied().unwrap_or(a);
+            (0..a.len())
+                .map(|i| {
+                    let (x, y) = (a[i], b[i % b.len()]);
+                    match op {
+                        OpKind::Add | OpKind::BiasAdd => x + y,
+                        OpKind::Mul => x * y,
+                        OpKind::Scale(s) => x * s,
+                        OpKind::Gelu => graph::gelu(x),
+                        OpKind::Relu => x.max(0.0),
+                        OpKind::Silu => x / (1.0 + (-x).exp()),
+                        _ => unreachable!("non-elementwise ops handled above"),
+                    }
+                })
+                .collect()
+        }
+    }
+}
+
+/// Executes plans on the host; fused intermediates are not materialized,
+/// as with a megakernel
+struct HostBackend<'a> {
+    fixture: &'a Fixture,
+    values: Vec<Option<Vec<f32>>>,
+}
+
+impl HostBackend<'_> {
+    fn value(&self, node: NodeId) -> GraphResult<&[f32]> {
+        self.values[node]
+            .as_deref()
+            .ok_or_else(|| GraphError::Backend(format!("{} read before it was computed", self.fixture.graph.label(node))))
+    }
+}
+
+impl DispatchBackend for HostBackend<'_> {
+    fn run(&mut self, graph: &Graph, dispatch: &Dispatch) -> GraphResult<()> {
+        let output = dispatch.output();
+        let value = match dispatch {
+            Dispatch::Single(node) => {
+                let inputs = &graph.nodes()[*node].inputs;
+                let operands = inputs.iter().map(|&i| self.value(i)).collect::<GraphResult<Vec<_>>>()?;
+                let shapes: Vec<_> = inputs.iter().map(|&i| self.fixture.shapes[i]).collect();
+                eval_host(graph.nodes()[*node].op, &operands, &shapes)
+            }
+            Dispatch::Fused(kernel) => {
+                let operands = kernel.inputs.iter().map(|&i| self.value(i)).collect::<GraphResult<Vec<_>>>()?;
+                (0..self.fixture.numel(output)).map(|i| kernel.eval(&operands, i)).collect()
+            }
+        };
+        self.values[output] = Some(value);
+        Ok(())
+    }
+
+    fn check_finite(&mut self, _graph: &Graph, node: NodeId) -> GraphResult<NonFinite> {
+        let data = self.value(node)?;
+        Ok(check_finite_host(data, &Layout::contiguous(&[data.len()])))
+    }
+}
+
+/// A device set up for running fixtures
+///
+/// Fields drop in declaration order, so everything created from the device
+/// is declared before it.
+struct Harness {
+    registry: KernelRegistry,
+    allocator: MemoryAllocator,
+    transfer: DataTransfer,
+    fence: Fence,
+    cmd: vk::CommandBuffer,
+    queue: Queue,
+    pool: CommandPool,
+    device: LogicalDevice,
+}
+
+impl Harness {
+    fn new(context: &Arc<VulkanContext>, index: usize) -> RegressionResult<Self> {
+        let device = LogicalDevice::create(context, index, &DeviceConfig::default())?;
+        let pool = device.command_pool()?;
+        let cmd = pool.allocate_buffers(1)?[0];
+        Ok(Self {
+            registry: KernelRegistry::new(device.device(), vk::PipelineCache::null()),
+            allocator: device.memory_allocator(),
+            transfer: device.data_transfer(&pool),
+            fence: Fence::new(device.device().clone(), false)?,
+            cmd,
+            queue: device.compute_queue(),
+            pool,
+            device,
+        })
+    }
+
+    /// Record `nodes` one after another, submit and wait
+    fn submit(&mut self, fixture: &Fixture, buffers: &[BufferRange], nodes: &[NodeId]) -> RegressionResult<()> {
+        self.pool.reset_buffer(self.cmd)?;
+        self.pool
+            .begin_recording(self.cmd, vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
+        let recorded = nodes.iter().try_for_each(|&node| -> RegressionResult<()> {
+            // SAFETY: cmd is recording; the buffers are live allocations of
+            // this device and `submit` waits for the work using them
+            unsafe {
+                self.record_node(fixture, buffers, node)?;
+                compute_barrier(self.device.device(), self.cmd);
+            }
+            Ok(())
+        });
+        self.pool.end_recording(self.cmd)?;
+        recorded?;
+
+        self.fence.reset()?;
+        self.queue.submit(&[self.cmd], None, None, Some(self.fence.raw()))?;
+        let finished = self.fence.wait(DISPATCH_TIMEOUT.as_nanos() as u64)?;
+        self.registry.release_sets()?;
+        if !finished {
+            return Err(RegressionError::Timeout);
+        }
+        Ok(())
+    }
+
+    /// Record `node` with its built-in kernel
+    ///
+    /// # Safety Requirements
+    /// - `cmd` must be in the recording state
+    /// - `buffers` must hold one live buffer per node, sized for its shape
+    unsafe fn record_node(&mut self, fixture: &Fixture, buffers: &[BufferRange], node: NodeId) -> RegressionResult<()> {
+        let graph_node = &fixture.graph.nodes()[node];
+        let inputs = &graph_node.inputs;
+        let [rows, cols] = fixture.shapes[node];
+        let (a, out) = (&buffers[inputs[0]], &buffers[node]);
+        let b = inputs.get(1).map_or(a, |&i| &buffers[i]);
+        let cmd = self.cmd;
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe {
+            match graph_node.op {
+                OpKind::MatMul => {
+                    let dims = MatmulDims {
+                        m: rows,
+                        n: cols,
+                        k: fixture.shapes[inputs[0]][1],
+                    };
+                    self.registry.matmul(cmd, a, b, out, dims)?
+                }
+                OpKind::Softmax => self.registry.softmax(cmd, a, out, rows, cols)?,
+                OpKind::RmsNorm => self.registry.rms_norm(cmd, a, b, out, rows, cols, DEFAULT_RMS_EPS)?,
+                OpKind::Add => self.registry.elementwise(cmd, Elementwise::Add, a, b, out, rows * cols)?,
+                OpKind::Mul => self.registry.elementwise(cmd, Elementwise::Mul, a, b, out, rows * cols)?,
+                OpKind::Gelu => self.registry.activation(cmd, Activation::Gelu, a, out, rows * cols)?,
+                OpKind::Silu => self.registry.activation(cmd, Activation::Silu, a, out, rows * cols)?,
+                op => return Err(RegressionError::Unsupported(op.name())),
+            }
+        }
+        Ok(())
+    }
+
+    fn read(&self, handle: &str, len: usize) -> RegressionResult<Vec<f32>> {
+        let allocation = self.allocator.get_allocation(handle)?;
+        // SAFETY: the allocation is live on this device, no work is pending
+        // and copy_from_device rejects sizes past its end
+        let bytes = unsafe { self.transfer.copy_from_device(allocation, len as u64 * 4) }?;
+        Ok(bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect())
+    }
+
+    fn run(&mut self, fixture: &Fixture) -> RegressionResult<Vec<OutputCheck>> {
+        let memory_type = self.allocator.memory_type_for(MemoryUsage::DeviceLocal)?;
+        let specs: Vec<AllocationSpec> = (0..fixture.shapes.len())
+            .map(|node| AllocationSpec {
+                handle_id: Uuid::new_v4().to_string(),
+                size: fixture.numel(node).max(1) as u64 * 4,
+                memory_type_index: memory_type,
+            })
+            .collect();
+        let handles = self.allocator.allocate_many(&specs, false)?;
+        let checked = self.execute(fixture, &handles);
+        for handle in &handles {
+            if let Err(e) = self.allocator.deallocate(handle) {
+                log::warn!("Failed to free regression buffer: {}", e);
+            }
+        }
+        checked
+    }
+
+    fn execute(&mut self, fixture: &Fixture, handles: &[String]) -> RegressionResult<Vec<OutputCheck>> {
+        for (node, data) in &fixture.inputs {
+            let bytes: Vec<u8> = data.iter().flat_map(|v| v.to_le_bytes()).collect();
+            let allocation = self.allocator.get_allocation(&handles[*node])?;
+            // SAFETY: the allocation is live on this device and no work is pending
+            unsafe { self.transfer.copy_to_device(&bytes, allocation) }?;
+        }
+        let buffers = handles
+            .iter()
+            .map(|handle| Ok(self.allocator.get_allocation(handle)?.range()))
+            .collect::<RegressionResult<Vec<_>>>()?;
+
+        let plan = graph::fuse_elementwise(&fixture.graph)?;
+        let mut backend = DeviceBackend {
+            harness: self,
+            fixture,
+            handles,
+            buffers: &buffers,
+            written: vec![false; fixture.shapes.len()],
+        };
+        graph::execute(&fixture.graph, &plan, &mut backend, ExecuteOptions::default())?;
+        let written = backend.written;
+
+        let mut outputs = vec![None; fixture.shapes.len()];
+        for (node, _) in &fixture.golden {
+            if !written[*node] {
+                return Err(RegressionError::NotMaterialized(fixture.graph.label(*node)));
+            }
+            outputs[*node] = Some(self.read(&handles[*node], fixture.numel(*node))?);
+        }
+        Ok(fixture.check(&outputs))
+    }
+}
+
+/// Executes plans on a `Harness`, one submission per dispatch
+struct DeviceBackend<'a> {
+    harness: &'a mut Harness,
+    fixture: &'a Fixture,
+    handles: &'a [String],
+    buffers: &'a [BufferRange],
+    /// Nodes a dispatch has written
+    written: Vec<bool>,
+}
+
+impl DispatchBackend for DeviceBackend<'_> {
+    fn run(&mut self, _graph: &Graph, dispatch: &Dispatch) -> GraphResult<()> {
+        // No megakernel: a fused chain runs node by node in one submission
+        let nodes = match dispatch {
+            Dispatch::Single(node) => std::slice::from_ref(node),
+            Dispatch::Fused(kernel) => kernel.nodes.as_slice(),
+        };
+        self.harness
+            .submit(self.fixture, self.buffers, nodes)
+            .map_err(|e| GraphError::Backend(e.to_string()))?;
+        for &node in nodes {
+            self.written[node] = true;
+        }
+        Ok(())
+    }
+
+    fn check_finite(&mut self, _graph: &Graph, node: NodeId) -> GraphResult<NonFinite> {
+        let data = self
+            .harness
+            .read(&self.handles[node], self.fixture.numel(node))
+            .map_err(|e| GraphError::Backend(e.to_string()))?;
+        Ok(check_finite_host(&data, &Layout::contiguous(&[data.len()])))
+    }
+}
+
+/// Run every embedded fixture on device `index`
+///
+/// Fixtures that fail to run are reported with their error; the call only
+/// fails if the fixtures do not parse or the device cannot be set up.
+pub fn run_regression_tests(context: &Arc<VulkanContext>, index: usize) -> RegressionResult<RegressionReport> {
+    let properties = *context.get_device_properties(index)?;
+    let fixtures = fixtures()?;
+    let mut harness = Harness::new(context, index)?;
+    let results = fixtures
+        .iter()
+        .map(|fixture| {
+            let outcome = harness.run(fixture).map_err(|e| e.to_string());
+            let result = FixtureResult {
+                name: fixture.name.clone(),
+                outcome,
+            };
+            if result.passed() {
+                log::info!("Regression fixture {} passed", fixture.name);
+            } else {
+                log::warn!("Regression fixture {} failed: {:?}", fixture.name, result.outcome);
+            }
+            result
+        })
+        .collect();
+
+    Ok(RegressionReport {
+        device: properties
+            .device_name_as_c_str()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        driver_version: properties.driver_version,
+        results,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_embedded_fixtures_match_host_execution() {
+        let fixtures = fixtures().unwrap();
+        assert_eq!(fixtures.len(), FIXTURES.len());
+        for fixture in &fixtures {
+            let checks = fixture.run_host().unwrap();
+            assert!(!checks.is_empty());
+            for check in checks {
+                assert!(check.passed, "{}.{}: error {}", fixture.name, check.name, check.max_error);
+            }
+        }
+    }
+
+    #[test]
+    fn test_mismatches_and_bad_fixtures_are_reported() {
+        let mut fixture = fixtures().unwrap().remove(0);
+        fixture.golden[0].1[3] += 0.01;
+        let check = &fixture.run_host().unwrap()[0];
+        assert!(!check.passed);
+        assert_eq!(check.worst_index, 3);
+
+        let report = RegressionReport {
+            device: "Test GPU".to_string(),
+            driver_version: 1,
+            results: vec![FixtureResult {
+                name: fixture.name.clone(),
+                outcome: Ok(vec![OutputCheck::missing("out".to_string())]),
+            }],
+        };
+        let json: Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["passed"], false);
+        assert_eq!(json["results"][0]["outputs"][0]["max_error"], Value::Null);
+
+        let bad = r#"{"name": "bad", "tolerance": 0.001,
+            "inputs": [{"name": "a", "shape": [2, 3], "data": [1, 2, 3, 4, 5, 6]}],
+            "nodes": [{"name": "b", "op": "matmul", "inputs": ["a", "a"]}],
+            "outputs": {}}"#;
+        assert!(matches!(
+            Fixture::parse("bad.json", bad),
+            Err(RegressionError::InvalidFixture { ref reason, .. }) if reason.contains("matmul")
+        ));
+    }
+}
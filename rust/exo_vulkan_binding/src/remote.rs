@@ -0,0 +1,516 @@
+//! Remote kernel execution for development
+//!
+//! Rebuilding and reinstalling an app for every shader tweak makes kernel
+//! work on phones slow. `RemoteServer` runs inside the app and executes
+//! commands from a workstation against the phone's GPU: the desktop client
+//! (`python -m exo.gpu.remote_shim`) reaches it through
+//! `adb forward tcp:7878 tcp:7878` or plain TCP. There is no
+//! authentication, so the server is only compiled with the `remote-debug`
+//! feature, which release builds must leave off, and should be bound to
+//! loopback.
+//!
+//! The protocol is one JSON object per line each way. Requests carry a
+//! `cmd` and an optional `id` that the reply echoes; replies are
+//! `{"id":..,"ok":true,"result":..}` or `{"id":..,"ok":false,"error":".."}`.
+//! Byte payloads are hex strings.
+//!
+//! | `cmd` | Fields | Result |
+//! |-------|--------|--------|
+//! | `enumerate` | | devices |
+//! | `open` | `device` | device info; frees everything of the previous device |
+//! | `alloc` | `size` | `{"handle"}` of device-local memory |
+//! | `free` | `handle` | `null` |
+//! | `write` | `handle`, `data` | `null` |
+//! | `read` | `handle`, `size` (default: all) | `{"data"}` |
+//! | `register` | `name`, `spirv`, `metadata` | `null`; replaces a kernel of that name |
+//! | `dispatch` | `kernel`, `buffers`, `push_constants`, `groups` | `{"elapsed_us"}` |
+//!
+//! Commands other than `enumerate` and `open` use device 0 until another
+//! is opened. `dispatch` binds `buffers` to the kernel's bindings in
+//! ascending order and waits for the GPU; `elapsed_us` is the wall time
+//! from submission to completion. Each connection has its own device and
+//! allocations, released when it closes.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use ash::vk;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::command::{CommandError, CommandPool, Fence, Queue};
+use crate::device::{DeviceConfig, DeviceError, LogicalDevice};
+use crate::kernel_plugins::{self, KernelMetadata, PluginError};
+use crate::memory::{MemoryAllocator, MemoryError, MemoryUsage};
+use crate::pipeline::{ComputePipeline, PipelineError};
+use crate::transfer::{DataTransfer, TransferError};
+use crate::{VulkanContext, VulkanError};
+
+/// Port the desktop client uses by default
+pub const DEFAULT_PORT: u16 = 7878;
+
+/// Longest a dispatch may run before the device is given up on
+pub const DISPATCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Remote shim errors
+#[derive(Error, Debug)]
+pub enum RemoteError {
+    #[error("Remote shim I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Malformed request: {0}")]
+    Malformed(#[from] serde_json::Error),
+
+    #[error("Invalid hex payload: {0}")]
+    InvalidHex(String),
+
+    #[error("Kernel {kernel} declares {declared} buffers, {supplied} supplied")]
+    BufferCount {
+        kernel: String,
+        declared: usize,
+        supplied: usize,
+    },
+
+    #[error("Dispatch of {0} timed out; device closed")]
+    Timeout(String),
+
+    #[error(transparent)]
+    Context(#[from] VulkanError),
+
+    #[error(transparent)]
+    Device(#[from] DeviceError),
+
+    #[error(transparent)]
+    Memory(#[from] MemoryError),
+
+    #[error(transparent)]
+    Transfer(#[from] TransferError),
+
+    #[error(transparent)]
+    Command(#[from] CommandError),
+
+    #[error(transparent)]
+    Pipeline(#[from] PipelineError),
+
+    #[error(transparent)]
+    Plugin(#[from] PluginError),
+}
+
+pub type RemoteResult<T> = Result<T, RemoteError>;
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Value,
+    #[serde(flatten)]
+    command: Command,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+enum Command {
+    Enumerate,
+    Open {
+        device: usize,
+    },
+    Alloc {
+        size: u64,
+    },
+    Free {
+        handle: String,
+    },
+    Write {
+        handle: String,
+        data: String,
+    },
+    Read {
+        handle: String,
+        #[serde(default)]
+        size: Option<u64>,
+    },
+    Register {
+        name: String,
+        spirv: String,
+        metadata: KernelMetadata,
+    },
+    Dispatch {
+        kernel: String,
+        buffers: Vec<String>,
+        #[serde(default)]
+        push_constants: String,
+        groups: [u32; 3],
+    },
+}
+
+/// Accepts desktop connections and runs their commands
+pub struct RemoteServer {
+    addr: SocketAddr,
+    stopping: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl RemoteServer {
+    /// Listen on `addr` (port 0 picks a free one), running commands on `context`'s devices
+    pub fn bind(addr: impl ToSocketAddrs, context: Arc<VulkanContext>) -> RemoteResult<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        if !addr.ip().is_loopback() {
+            log::warn!("Remote shim listening on {}: anyone who can reach it can run kernels", addr);
+        }
+        let stopping = Arc::new(AtomicBool::new(false));
+        let stop_flag = stopping.clone();
+        let handle = thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stop_flag.load(Ordering::Acquire) {
+                    break;
+                }
+                let Ok(stream) = stream else {
+                    continue;
+                };
+                let context = context.clone();
+                thread::spawn(move || {
+                    let peer = stream.peer_addr().map(|a| a.to_string()).unwrap_or_default();
+                    log::info!("Remote shim client {} connected", peer);
+                    if let Err(e) = serve(stream, Session::new(context)) {
+                        log::debug!("Remote shim connection ended: {}", e);
+                    }
+                    log::info!("Remote shim client {} disconnected", peer);
+                });
+            }
+        });
+        Ok(Self {
+            addr,
+            stopping,
+            handle: Some(handle),
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Stop accepting connections; open ones run until the client closes them
+    pub fn shutdown(mut self) {
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            self.stopping.store(true, Ordering::Release);
+            // Wake the accept loop so it sees the flag
+            if TcpStream::connect_timeout(&self.addr, Duration::from_secs(1)).is_ok() {
+                let _ = handle.join();
+            }
+        }
+    }
+}
+
+impl Drop for RemoteServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn serve(stream: TcpStream, mut session: Session) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut reply = session.handle_line(&line).to_string();
+        reply.push('\n');
+        writer.write_all(reply.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// One open device and what a client created on it
+///
+/// Fields drop in declaration order, so everything created from the device
+/// is declared before it.
+struct Gpu {
+    pipelines: HashMap<String, ComputePipeline>,
+    allocator: MemoryAllocator,
+    transfer: DataTransfer,
+    fence: Fence,
+    cmd: vk::CommandBuffer,
+    queue: Queue,
+    pool: CommandPool,
+    device: LogicalDevice,
+}
+
+impl Gpu {
+    fn open(context: &Arc<VulkanContext>, index: usize) -> RemoteResult<Self> {
+        let device = LogicalDevice::create(context, index, &DeviceConfig::default())?;
+        let pool = device.command_pool()?;
+        let cmd = pool.allocate_buffers(1)?[0];
+        Ok(Self {
+            pipelines: HashMap::new(),
+            allocator: device.memory_allocator(),
+            transfer: device.data_transfer(&pool),
+            fence: Fence::new(device.device().clone(), false)?,
+            cmd,
+            queue: device.compute_queue(),
+            pool,
+            device,
+        })
+    }
+
+    fn pipeline(&mut self, kernel: &str) -> RemoteResult<&ComputePipeline> {
+        if !self.pipelines.contains_key(kernel) {
+            let device = self.device.device();
+            let pipeline = match kernel_plugins::get(kernel) {
+                Some(plugin) => ComputePipeline::from_plugin(device, &plugin, vk::PipelineCache::null())?,
+                None => ComputePipeline::from_embedded(device, kernel, vk::PipelineCache::null())?,
+            };
+            self.pipelines.insert(kernel.to_string(), pipeline);
+        }
+        Ok(&self.pipelines[kernel])
+    }
+
+    /// Run `kernel` once and wait for it
+    fn dispatch(&mut self, kernel: &str, buffers: &[String], push: &[u8], groups: [u32; 3]) -> RemoteResult<Duration> {
+        let ranges = buffers
+            .iter()
+            .map(|handle| Ok(self.allocator.get_allocation(handle)?.range()))
+            .collect::<RemoteResult<Vec<_>>>()?;
+        self.pipeline(kernel)?;
+        let pipeline = &self.pipelines[kernel];
+        let declared = &pipeline.interface().buffer_bindings;
+        if declared.len() != ranges.len() {
+            return Err(RemoteError::BufferCount {
+                kernel: kernel.to_string(),
+                declared: declared.len(),
+                supplied: ranges.len(),
+            });
+        }
+        let bound: Vec<_> = declared.iter().copied().zip(&ranges).collect();
+
+        let device = self.device.device();
+        self.pool.reset_buffer(self.cmd)?;
+        self.pool
+            .begin_recording(self.cmd, vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
+        // SAFETY: cmd is recording; the buffers are live allocations of this
+        // device, and the previous dispatch using the default set has completed
+        let recorded = unsafe { pipeline.dispatch_bytes(self.cmd, pipeline.descriptor_set(), &bound, push, groups) };
+        let barrier = vk::MemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::TRANSFER_READ);
+        // SAFETY: cmd is recording
+        unsafe {
+            device.cmd_pipeline_barrier(
+                self.cmd,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[barrier],
+                &[],
+                &[],
+            );
+        }
+        self.pool.end_recording(self.cmd)?;
+        recorded?;
+
+        self.fence.reset()?;
+        let started = Instant::now();
+        self.queue.submit(&[self.cmd], None, None, Some(self.fence.raw()))?;
+        if !self.fence.wait(DISPATCH_TIMEOUT.as_nanos() as u64)? {
+            return Err(RemoteError::Timeout(kernel.to_string()));
+        }
+        Ok(started.elapsed())
+    }
+}
+
+/// State of one client connection
+struct Session {
+    context: Arc<VulkanContext>,
+    gpu: Option<Gpu>,
+}
+
+impl Session {
+    fn new(context: Arc<VulkanContext>) -> Self {
+        Self { context, gpu: None }
+    }
+
+    /// Run one request line and build its reply
+    fn handle_line(&mut self, line: &str) -> Value {
+        let request = match serde_json::from_str::<Request>(line) {
+            Ok(request) => request,
+            Err(e) => return json!({"id": null, "ok": false, "error": RemoteError::from(e).to_string()}),
+        };
+        match self.execute(request.command) {
+            Ok(result) => json!({"id": request.id, "ok": true, "result": result}),
+            Err(e) => {
+                if let RemoteError::Timeout(_) = e {
+                    // The command buffer may still be pending; start over on a fresh device
+                    self.gpu = None;
+                }
+                json!({"id": request.id, "ok": false, "error": e.to_string()})
+            }
+        }
+    }
+
+    /// The open device, opening device 0 if none is
+    fn gpu(&mut self) -> RemoteResult<&mut Gpu> {
+        if self.gpu.is_none() {
+            self.gpu = Some(Gpu::open(&self.context, 0)?);
+        }
+        Ok(self.gpu.as_mut().expect("opened above"))
+    }
+
+    fn execute(&mut self, command: Command) -> RemoteResult<Value> {
+        match command {
+            Command::Enumerate => {
+                let devices: Vec<Value> = self
+                    .context
+                    .enumerate_devices()?
+                    .iter()
+                    .enumerate()
+                    .map(|(index, d)| {
+                        json!({
+                            "index": index,
+                            "device_id": d.device_id,
+                            "name": d.name,
+                            "vendor": d.vendor,
+                            "driver_version": d.driver_version,
+                            "compute_units": d.compute_units,
+                            "subgroup_size": d.subgroup_size,
+                            "max_clock_mhz": d.max_clock_mhz,
+                            "total_memory_bytes": d.total_memory_bytes,
+                        })
+                    })
+                    .collect();
+                Ok(Value::from(devices))
+            }
+            Command::Open { device } => {
+                // Release the old device before creating the new one
+                self.gpu = None;
+                self.gpu = Some(Gpu::open(&self.context, device)?);
+                let properties = self.context.get_device_properties(device)?;
+                Ok(json!({
+                    "device": device,
+                    "name": properties.device_name_as_c_str().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default(),
+                }))
+            }
+            Command::Alloc { size } => {
+                let gpu = self.gpu()?;
+                let memory_type = gpu.allocator.memory_type_for(MemoryUsage::DeviceLocal)?;
+                let handle = gpu.allocator.allocate(size, memory_type, Uuid::new_v4().to_string())?;
+                Ok(json!({"handle": handle}))
+            }
+            Command::Free { handle } => {
+                self.gpu()?.allocator.deallocate(&handle)?;
+                Ok(Value::Null)
+            }
+            Command::Write { handle, data } => {
+                let data = from_hex(&data)?;
+                let gpu = self.gpu()?;
+                let allocation = gpu.allocator.get_allocation(&handle)?;
+                // SAFETY: the allocation is live on this device and no dispatch is pending
+                unsafe { gpu.transfer.copy_to_device(&data, allocation) }?;
+                Ok(Value::Null)
+            }
+            Command::Read { handle, size } => {
+                let gpu = self.gpu()?;
+                let allocation = gpu.allocator.get_allocation(&handle)?;
+                let size = size.unwrap_or(allocation.size);
+                // SAFETY: the allocation is live on this device and
+                // copy_from_device rejects sizes past its end
+                let data = unsafe { gpu.transfer.copy_from_device(allocation, size) }?;
+                Ok(json!({"data": to_hex(&data)}))
+            }
+            Command::Register { name, spirv, metadata } => {
+                let spirv = from_hex(&spirv)?;
+                // Validate before replacing, so a bad upload keeps the old kernel
+                kernel_plugins::validate(&name, &kernel_plugins::spirv_words(&name, &spirv)?, &metadata)?;
+                kernel_plugins::unregister_kernel(&name);
+                kernel_plugins::register_kernel(&name, &spirv, metadata)?;
+                if let Some(gpu) = &mut self.gpu {
+                    gpu.pipelines.remove(&name);
+                }
+                Ok(Value::Null)
+            }
+            Command::Dispatch {
+                kernel,
+                buffers,
+                push_constants,
+                groups,
+            } => {
+                let push = from_hex(&push_constants)?;
+                let elapsed = self.gpu()?.dispatch(&kernel, &buffers, &push, groups)?;
+                Ok(json!({"elapsed_us": elapsed.as_micros() as u64}))
+            }
+        }
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> RemoteResult<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return Err(RemoteError::InvalidHex(format!("odd length {}", hex.len())));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| RemoteError::InvalidHex(format!("bad digits at offset {}", i)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_round_trip() {
+        let bytes = [0x00, 0x7f, 0x80, 0xff];
+        assert_eq!(to_hex(&bytes), "007f80ff");
+        assert_eq!(from_hex("007F80ff").unwrap(), bytes);
+        assert!(from_hex("").unwrap().is_empty());
+        assert!(matches!(from_hex("abc"), Err(RemoteError::InvalidHex(_))));
+        assert!(matches!(from_hex("zz"), Err(RemoteError::InvalidHex(_))));
+        assert!(matches!(from_hex("é0"), Err(RemoteError::InvalidHex(_))));
+    }
+
+    #[test]
+    fn test_parses_requests() {
+        let request: Request = serde_json::from_str(
+            r#"{"id": 7, "cmd": "dispatch", "kernel": "scale", "buffers": ["a", "b"], "groups": [4, 1, 1]}"#,
+        )
+        .unwrap();
+        assert_eq!(request.id, json!(7));
+        assert_eq!(
+            request.command,
+            Command::Dispatch {
+                kernel: "scale".to_string(),
+                buffers: vec!["a".to_string(), "b".to_string()],
+                push_constants: String::new(),
+                groups: [4, 1, 1],
+            }
+        );
+        let request: Request = serde_json::from_str(r#"{"cmd": "read", "handle": "a"}"#).unwrap();
+        assert_eq!(request.id, Value::Null);
+        assert_eq!(
+            request.command,
+            Command::Read {
+                handle: "a".to_string(),
+                size: None
+            }
+        );
+        assert!(serde_json::from_str::<Request>(r#"{"cmd": "format_disk"}"#).is_err());
+    }
+}
@@ -0,0 +1,273 @@
+//! Hot-updatable sampling parameters
+//!
+//! Sampling kernels read temperature, top-k, top-p and the penalties from a
+//! small persistent storage buffer instead of push constants baked into a
+//! recorded graph. The buffer is a ring of per-step slots in host-visible
+//! memory: each step writes the current parameters into the next slot and
+//! passes only the slot index to the kernel. The descriptor for the whole
+//! ring is written once, so changing parameters mid-generation costs a
+//! 32-byte host write rather than a graph rebuild or descriptor update.
+//!
+//! Matching GLSL declaration:
+//!
+//! ```glsl
+//! struct SamplerConfig {
+//!     float temperature; uint top_k; float top_p; float repetition_penalty;
+//!     float presence_penalty; float frequency_penalty; uint seed; uint step;
+//! };
+//! layout(std430, binding = N) readonly buffer Sampler { SamplerConfig slots[]; };
+//! ```
+//!
+//! Slots are `SLOT_STRIDE` bytes apart, so slot `i` is `slots[i * 8]`.
+
+use ash::vk;
+use parking_lot::Mutex;
+use thiserror::Error;
+
+use crate::memory::{AllocationInfo, BufferRange};
+
+/// Bytes between slots; a multiple of every `minStorageBufferOffsetAlignment`
+/// and `nonCoherentAtomSize` the spec allows
+pub const SLOT_STRIDE: u64 = 256;
+
+/// Bytes of one encoded `SamplerConfig`
+pub const CONFIG_SIZE: usize = 32;
+
+/// Sampler-related errors
+#[derive(Error, Debug)]
+pub enum SamplerError {
+    #[error("Invalid sampling parameter: {0}")]
+    InvalidParams(String),
+
+    #[error("Config ring must be host-visible and mapped")]
+    NotMapped,
+
+    #[error("Allocation of {size} bytes holds no {SLOT_STRIDE}-byte slots")]
+    TooSmall { size: u64 },
+
+    #[error("Vulkan error: {0:?}")]
+    VulkanError(vk::Result),
+}
+
+pub type SamplerResult<T> = Result<T, SamplerError>;
+
+/// Sampling parameters for one sequence
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SamplerParams {
+    /// 0 selects greedy decoding
+    pub temperature: f32,
+    /// 0 disables top-k filtering
+    pub top_k: u32,
+    /// 1.0 disables nucleus filtering
+    pub top_p: f32,
+    /// 1.0 disables the repetition penalty
+    pub repetition_penalty: f32,
+    pub presence_penalty: f32,
+    pub frequency_penalty: f32,
+    pub seed: u32,
+}
+
+impl Default for SamplerParams {
+    fn default() -> Self {
+        Self {
+            temperature: 1.0,
+            top_k: 0,
+            top_p: 1.0,
+            repetition_penalty: 1.0,
+            presence_penalty: 0.0,
+            frequency_penalty: 0.0,
+            seed: 0,
+        }
+    }
+}
+
+impl SamplerParams {
+    /// Reject values the kernels cannot interpret
+    pub fn validate(&self) -> SamplerResult<()> {
+        let invalid = |msg: String| Err(SamplerError::InvalidParams(msg));
+        if !self.temperature.is_finite() || self.temperature < 0.0 {
+            return invalid(format!("temperature {}", self.temperature));
+        }
+        if !(self.top_p > 0.0 && self.top_p <= 1.0) {
+            return invalid(format!("top_p {} outside (0, 1]", self.top_p));
+        }
+        if !self.repetition_penalty.is_finite() || self.repetition_penalty <= 0.0 {
+            return invalid(format!("repetition_penalty {}", self.repetition_penalty));
+        }
+        if !self.presence_penalty.is_finite() || !self.frequency_penalty.is_finite() {
+            return invalid("presence/frequency penalties must be finite".to_string());
+        }
+        Ok(())
+    }
+
+    /// std430 bytes of the `SamplerConfig` for decode step `step`
+    pub fn encode(&self, step: u32) -> [u8; CONFIG_SIZE] {
+        let words = [
+            self.temperature.to_bits(),
+            self.top_k,
+            self.top_p.to_bits(),
+            self.repetition_penalty.to_bits(),
+            self.presence_penalty.to_bits(),
+            self.frequency_penalty.to_bits(),
+            self.seed,
+            step,
+        ];
+        let mut bytes = [0u8; CONFIG_SIZE];
+        for (chunk, word) in bytes.chunks_exact_mut(4).zip(words) {
+            chunk.copy_from_slice(&word.to_ne_bytes());
+        }
+        bytes
+    }
+}
+
+#[derive(Debug)]
+struct RingState {
+    params: SamplerParams,
+    /// Steps written so far
+    steps: u64,
+}
+
+/// Persistent ring of sampler config slots
+///
+/// Must have at least as many slots as decode steps in flight, so a slot is
+/// never rewritten while a kernel may still read it.
+pub struct SamplerConfigRing {
+    device: ash::Device,
+    allocation: AllocationInfo,
+    slots: u32,
+    state: Mutex<RingState>,
+}
+
+// SAFETY: the mapped pointer is owned by `allocation` for the ring's
+// lifetime and only written under the state lock
+unsafe impl Send for SamplerConfigRing {}
+unsafe impl Sync for SamplerConfigRing {}
+
+impl SamplerConfigRing {
+    /// Use `allocation` (mapped, host-visible) as the ring
+    pub fn new(
+        device: ash::Device,
+        allocation: AllocationInfo,
+        params: SamplerParams,
+    ) -> SamplerResult<Self> {
+        params.validate()?;
+        if allocation.mapped_ptr.is_none()
+            || !allocation
+                .memory_properties
+                .contains(vk::MemoryPropertyFlags::HOST_VISIBLE)
+        {
+            return Err(SamplerError::NotMapped);
+        }
+        let slots = slot_count(allocation.size);
+        if slots == 0 {
+            return Err(SamplerError::TooSmall {
+                size: allocation.size,
+            });
+        }
+        Ok(Self {
+            device,
+            allocation,
+            slots,
+            state: Mutex::new(RingState { params, steps: 0 }),
+        })
+    }
+
+    pub fn slots(&self) -> u32 {
+        self.slots
+    }
+
+    /// Range to bind once as the sampler storage buffer
+    pub fn binding(&self) -> BufferRange {
+        BufferRange {
+            buffer: self.allocation.buffer,
+            offset: self.allocation.offset,
+            size: u64::from(self.slots) * SLOT_STRIDE,
+        }
+    }
+
+    /// Parameters the next step will use
+    pub fn params(&self) -> SamplerParams {
+        self.state.lock().params
+    }
+
+    /// Replace the parameters from the next step on; safe mid-generation
+    pub fn update(&self, params: SamplerParams) -> SamplerResult<()> {
+        params.validate()?;
+        self.state.lock().params = params;
+        Ok(())
+    }
+
+    /// Write the current parameters into the next slot and return its index,
+    /// to be passed to the sampling kernel
+    pub fn advance(&self) -> SamplerResult<u32> {
+        let mut state = self.state.lock();
+        let slot = (state.steps % u64::from(self.slots)) as u32;
+        let bytes = state.params.encode(state.steps as u32);
+        let offset = u64::from(slot) * SLOT_STRIDE;
+
+        let ptr = self.allocation.mapped_ptr.ok_or(SamplerError::NotMapped)?;
+        // SAFETY: the mapping covers `slots * SLOT_STRIDE` bytes (checked in
+        // new) and the slot is not being read by the device (ring contract)
+        unsafe {
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), ptr.add(offset as usize), CONFIG_SIZE);
+        }
+        if !self
+            .allocation
+            .memory_properties
+            .contains(vk::MemoryPropertyFlags::HOST_COHERENT)
+        {
+            let range = vk::MappedMemoryRange::default()
+                .memory(self.allocation.device_memory)
+                .offset(self.allocation.offset + offset)
+                .size(SLOT_STRIDE);
+            // SAFETY: the memory is mapped and the range lies within it
+            unsafe { self.device.flush_mapped_memory_ranges(&[range]) }
+                .map_err(SamplerError::VulkanError)?;
+        }
+
+        state.steps += 1;
+        Ok(slot)
+    }
+}
+
+/// Slots that fit in `size` bytes
+pub fn slot_count(size: u64) -> u32 {
+    (size / SLOT_STRIDE).min(u64::from(u32::MAX)) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_params_validation() {
+        assert!(SamplerParams::default().validate().is_ok());
+        let bad = SamplerParams {
+            top_p: 0.0,
+            ..SamplerParams::default()
+        };
+        assert!(bad.validate().is_err());
+        let bad = SamplerParams {
+            temperature: f32::NAN,
+            ..SamplerParams::default()
+        };
+        assert!(bad.validate().is_err());
+    }
+
+    #[test]
+    fn test_encode_layout() {
+        let params = SamplerParams {
+            temperature: 0.7,
+            top_k: 40,
+            seed: 9,
+            ..SamplerParams::default()
+        };
+        let bytes = params.encode(5);
+        let word = |i: usize| u32::from_ne_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap());
+        assert_eq!(f32::from_bits(word(0)), 0.7);
+        assert_eq!(word(1), 40);
+        assert_eq!(word(6), 9);
+        assert_eq!(word(7), 5);
+        assert_eq!(slot_count(SLOT_STRIDE * 3 + 10), 3);
+    }
+}
@@ -0,0 +1,170 @@
+//! Session pinning across Activity recreation
+//!
+//! An Android configuration change (rotation, dark mode, window resize)
+//! destroys and recreates the Activity, which closes its client namespace
+//! and with it the KV cache and buffers of an in-flight generation. A
+//! pinned session instead survives its owner going away: closing it only
+//! detaches it, and the new Activity instance resumes it by id (saved in
+//! `onSaveInstanceState`). Detached sessions that nobody resumes within the
+//! keep-alive window expire and are released like a normal close.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+
+/// How long a detached session waits to be resumed by default
+pub const DEFAULT_KEEP_ALIVE: Duration = Duration::from_secs(30);
+
+/// Session errors
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum SessionError {
+    #[error("Session not pinned: {0}")]
+    NotPinned(String),
+
+    #[error("Session {0} is attached; close it before resuming elsewhere")]
+    AlreadyAttached(String),
+}
+
+pub type SessionResult<T> = Result<T, SessionError>;
+
+#[derive(Clone, Copy, Debug)]
+struct PinnedSession {
+    keep_alive: Duration,
+    /// Set while no owner holds the session
+    detached_at: Option<Instant>,
+}
+
+/// Tracks which sessions outlive their owner, and for how long
+#[derive(Debug, Default)]
+pub struct SessionKeeper {
+    sessions: HashMap<String, PinnedSession>,
+}
+
+impl SessionKeeper {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep `id` alive for `keep_alive` after its owner closes it
+    ///
+    /// Pinning an already pinned session updates its keep-alive.
+    pub fn pin(&mut self, id: &str, keep_alive: Duration) {
+        self.sessions
+            .entry(id.to_string())
+            .and_modify(|s| s.keep_alive = keep_alive)
+            .or_insert(PinnedSession {
+                keep_alive,
+                detached_at: None,
+            });
+    }
+
+    /// Stop protecting `id`; a detached session is then due for release
+    ///
+    /// # Returns
+    /// Whether the session was detached, i.e. its resources should be freed now
+    pub fn unpin(&mut self, id: &str) -> bool {
+        self.sessions
+            .remove(id)
+            .is_some_and(|s| s.detached_at.is_some())
+    }
+
+    pub fn is_pinned(&self, id: &str) -> bool {
+        self.sessions.contains_key(id)
+    }
+
+    /// The owner closed `id`
+    ///
+    /// Closing an already detached session ends it: it is unpinned so the
+    /// caller releases it right away.
+    ///
+    /// # Returns
+    /// true if the session was pinned and attached, and is now detached
+    /// instead of closed
+    pub fn detach(&mut self, id: &str, now: Instant) -> bool {
+        match self.sessions.get_mut(id) {
+            Some(session) if session.detached_at.is_none() => {
+                session.detached_at = Some(now);
+                true
+            }
+            Some(_) => {
+                self.sessions.remove(id);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// A new owner takes over a detached session
+    pub fn resume(&mut self, id: &str) -> SessionResult<()> {
+        let session = self
+            .sessions
+            .get_mut(id)
+            .ok_or_else(|| SessionError::NotPinned(id.to_string()))?;
+        if session.detached_at.take().is_none() {
+            return Err(SessionError::AlreadyAttached(id.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Remove and return sessions detached for longer than their keep-alive
+    pub fn expire(&mut self, now: Instant) -> Vec<String> {
+        let expired: Vec<String> = self
+            .sessions
+            .iter()
+            .filter(|(_, s)| {
+                s.detached_at
+                    .is_some_and(|at| now.saturating_duration_since(at) > s.keep_alive)
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &expired {
+            self.sessions.remove(id);
+        }
+        expired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detached_session_resumes_within_keep_alive() {
+        let start = Instant::now();
+        let mut keeper = SessionKeeper::new();
+        assert!(!keeper.detach("unpinned", start));
+
+        keeper.pin("s", Duration::from_secs(5));
+        assert_eq!(keeper.resume("s"), Err(SessionError::AlreadyAttached("s".to_string())));
+        assert!(keeper.detach("s", start));
+        assert!(keeper.expire(start + Duration::from_secs(4)).is_empty());
+        keeper.resume("s").unwrap();
+
+        // Attached sessions never expire
+        assert!(keeper.expire(start + Duration::from_secs(60)).is_empty());
+        assert!(keeper.is_pinned("s"));
+    }
+
+    #[test]
+    fn test_expired_and_unpinned_sessions_are_released() {
+        let start = Instant::now();
+        let mut keeper = SessionKeeper::new();
+        keeper.pin("a", Duration::from_secs(1));
+        keeper.pin("b", Duration::from_secs(1));
+        keeper.detach("a", start);
+        assert_eq!(keeper.expire(start + Duration::from_secs(2)), vec!["a".to_string()]);
+        assert_eq!(keeper.resume("a"), Err(SessionError::NotPinned("a".to_string())));
+
+        assert!(!keeper.unpin("b"));
+        keeper.pin("c", Duration::from_secs(1));
+        keeper.detach("c", start);
+        assert!(keeper.unpin("c"));
+
+        // A second close of a detached session releases it
+        keeper.pin("d", Duration::from_secs(1));
+        assert!(keeper.detach("d", start));
+        assert!(!keeper.detach("d", start));
+        assert!(!keeper.is_pinned("d"));
+    }
+}
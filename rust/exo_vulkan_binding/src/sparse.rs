@@ -0,0 +1,520 @@
+//! Sparse GEMM weight formats
+//!
+//! Pruned checkpoints hold weights that are mostly zero. Two layouts let the
+//! sparse GEMM kernels (`kernel_library::matmul_sparse`) skip that work and
+//! that memory:
+//! - Block sparse: `b[k, n]` is split into `BLOCK`x`BLOCK` blocks and only
+//!   blocks holding a non-zero are stored. A device-side index lists the
+//!   stored k-blocks of each column tile, so whole zero blocks cost nothing.
+//! - 2:4: every group of 4 consecutive `k` in a column keeps 2 values and
+//!   their positions, halving weight memory and multiply-adds.
+//!
+//! `from_dense` converts losslessly and rejects weights that do not fit the
+//! pattern; `prune` forces the pattern by dropping the smallest values,
+//! trading accuracy for speed.
+//!
+//! Sparsified safetensors checkpoints store a sparse weight `<name>` as the
+//! tensors `<name>.values` (F32) and `<name>.index` (U32) laid out as here,
+//! with `__metadata__` key `exo.sparse.<name>` set to `SparseLayout::encode`.
+
+use thiserror::Error;
+
+/// Sparse format errors
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum SparseError {
+    #[error("{what}: {actual} elements, expected {expected}")]
+    LengthMismatch {
+        what: &'static str,
+        expected: usize,
+        actual: usize,
+    },
+
+    #[error("Column {col}, rows {row}..{}: more than 2 of 4 values are non-zero", row + 4)]
+    NotTwoFour { row: usize, col: usize },
+
+    #[error("Invalid sparse index: {0}")]
+    InvalidIndex(String),
+
+    #[error("Invalid sparse layout {0:?}")]
+    InvalidLayout(String),
+}
+
+pub type SparseResult<T> = Result<T, SparseError>;
+
+/// Edge of a block-sparse block; the GEMM tile
+pub const BLOCK: usize = 16;
+
+/// 2:4 groups whose positions share one index word
+pub const GROUPS_PER_WORD: usize = 8;
+
+/// `__metadata__` key prefix marking a sparse tensor in a checkpoint
+pub const METADATA_PREFIX: &str = "exo.sparse.";
+
+/// Sparse layout of a weight
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SparseFormat {
+    Block,
+    TwoFour,
+}
+
+impl SparseFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            SparseFormat::Block => "block16",
+            SparseFormat::TwoFour => "2:4",
+        }
+    }
+
+    /// Embedded kernel that multiplies by weights in this format
+    pub fn kernel(self) -> &'static str {
+        match self {
+            SparseFormat::Block => "gemm_block_sparse",
+            SparseFormat::TwoFour => "gemm_sparse_2_4",
+        }
+    }
+}
+
+/// Format and dense `[k, n]` shape of a sparse weight
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SparseLayout {
+    pub format: SparseFormat,
+    pub k: usize,
+    pub n: usize,
+}
+
+impl SparseLayout {
+    /// Parse a checkpoint metadata value such as `2:4;4096,11008`
+    pub fn parse(value: &str) -> SparseResult<Self> {
+        let invalid = || SparseError::InvalidLayout(value.to_string());
+        let (format, shape) = value.split_once(';').ok_or_else(invalid)?;
+        let format = [SparseFormat::Block, SparseFormat::TwoFour]
+            .into_iter()
+            .find(|f| f.name() == format.trim())
+            .ok_or_else(invalid)?;
+        let (k, n) = shape.split_once(',').ok_or_else(invalid)?;
+        let k = k.trim().parse().map_err(|_| invalid())?;
+        let n = n.trim().parse().map_err(|_| invalid())?;
+        Ok(Self { format, k, n })
+    }
+
+    /// Metadata value `parse` reads back
+    pub fn encode(&self) -> String {
+        format!("{};{},{}", self.format.name(), self.k, self.n)
+    }
+
+    fn col_tiles(&self) -> usize {
+        self.n.div_ceil(BLOCK)
+    }
+
+    fn groups(&self) -> usize {
+        self.k.div_ceil(4)
+    }
+
+    /// f32 values stored for `blocks` stored blocks (2:4 ignores `blocks`)
+    pub fn value_count(&self, blocks: usize) -> usize {
+        match self.format {
+            SparseFormat::Block => blocks * BLOCK * BLOCK,
+            SparseFormat::TwoFour => self.groups() * 2 * self.n,
+        }
+    }
+
+    /// u32 index words for `blocks` stored blocks (2:4 ignores `blocks`)
+    pub fn index_words(&self, blocks: usize) -> usize {
+        match self.format {
+            SparseFormat::Block => self.col_tiles() + 1 + blocks,
+            SparseFormat::TwoFour => self.groups().div_ceil(GROUPS_PER_WORD) * self.n,
+        }
+    }
+}
+
+fn check_len(what: &'static str, expected: usize, actual: usize) -> SparseResult<()> {
+    if actual != expected {
+        return Err(SparseError::LengthMismatch { what, expected, actual });
+    }
+    Ok(())
+}
+
+/// Value at `(row, col)` of a dense `[k, n]` matrix, 0 past its edges
+fn dense_at(dense: &[f32], k: usize, n: usize, row: usize, col: usize) -> f32 {
+    if row < k && col < n { dense[row * n + col] } else { 0.0 }
+}
+
+/// Block-sparse `[k, n]` weight
+///
+/// `index` is `col_start[n_tiles + 1]` followed by the k-block of each stored
+/// block, grouped by column tile; `values` holds the stored blocks in the
+/// same order, each row-major.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockSparseMatrix {
+    layout: SparseLayout,
+    values: Vec<f32>,
+    index: Vec<u32>,
+}
+
+impl BlockSparseMatrix {
+    /// Keep every block that holds a non-zero
+    pub fn from_dense(dense: &[f32], k: usize, n: usize) -> SparseResult<Self> {
+        check_len("dense weight", k * n, dense.len())?;
+        Ok(Self::from_kept(dense, k, n, |block| block.iter().any(|&v| v != 0.0)))
+    }
+
+    /// Keep the `density` fraction of blocks with the largest L2 norm
+    pub fn prune(dense: &[f32], k: usize, n: usize, density: f32) -> SparseResult<Self> {
+        check_len("dense weight", k * n, dense.len())?;
+        let probe = Self::from_kept(dense, k, n, |_| true);
+        let mut norms: Vec<f32> = probe
+            .values
+            .chunks_exact(BLOCK * BLOCK)
+            .map(|block| block.iter().map(|v| v * v).sum())
+            .collect();
+        let keep = ((norms.len() as f32 * density.clamp(0.0, 1.0)).ceil() as usize).min(norms.len());
+        norms.sort_unstable_by(|a, b| b.total_cmp(a));
+        let Some(&threshold) = norms.get(keep.wrapping_sub(1)) else {
+            return Ok(Self::from_kept(dense, k, n, |_| false));
+        };
+        // Ties at the threshold may keep a few more blocks than asked
+        Ok(Self::from_kept(dense, k, n, |block| {
+            block.iter().map(|v| v * v).sum::<f32>() >= threshold && block.iter().any(|&v| v != 0.0)
+        }))
+    }
+
+    fn from_kept(dense: &[f32], k: usize, n: usize, keep: impl Fn(&[f32]) -> bool) -> Self {
+        let layout = SparseLayout {
+            format: SparseFormat::Block,
+            k,
+            n,
+        };
+        let mut col_start = vec![0u32];
+        let mut k_blocks = Vec::new();
+        let mut values = Vec::new();
+        let mut block = vec![0.0; BLOCK * BLOCK];
+        for tile in 0..layout.col_tiles() {
+            for kb in 0..k.div_ceil(BLOCK) {
+                for (i, value) in block.iter_mut().enumerate() {
+                    *value = dense_at(dense, k, n, kb * BLOCK + i / BLOCK, tile * BLOCK + i % BLOCK);
+                }
+                if keep(&block) {
+                    k_blocks.push(kb as u32);
+                    values.extend_from_slice(&block);
+                }
+            }
+            col_start.push(k_blocks.len() as u32);
+        }
+        col_start.extend(k_blocks);
+        Self {
+            layout,
+            values,
+            index: col_start,
+        }
+    }
+
+    /// Validate `values` and `index` read from a checkpoint
+    pub fn from_parts(k: usize, n: usize, values: Vec<f32>, index: Vec<u32>) -> SparseResult<Self> {
+        let layout = SparseLayout {
+            format: SparseFormat::Block,
+            k,
+            n,
+        };
+        let tiles = layout.col_tiles();
+        let col_start = index
+            .get(..=tiles)
+            .ok_or_else(|| SparseError::InvalidIndex(format!("{} words cannot hold {} column starts", index.len(), tiles + 1)))?;
+        if col_start[0] != 0 || col_start.windows(2).any(|w| w[0] > w[1]) {
+            return Err(SparseError::InvalidIndex("column starts must rise from 0".to_string()));
+        }
+        let blocks = col_start[tiles] as usize;
+        check_len("block index", layout.index_words(blocks), index.len())?;
+        check_len("block values", layout.value_count(blocks), values.len())?;
+        if let Some(kb) = index[tiles + 1..].iter().find(|&&kb| kb as usize >= k.div_ceil(BLOCK)) {
+            return Err(SparseError::InvalidIndex(format!("k-block {} past k = {}", kb, k)));
+        }
+        Ok(Self { layout, values, index })
+    }
+
+    pub fn layout(&self) -> SparseLayout {
+        self.layout
+    }
+
+    pub fn values(&self) -> &[f32] {
+        &self.values
+    }
+
+    pub fn index(&self) -> &[u32] {
+        &self.index
+    }
+
+    pub fn stored_blocks(&self) -> usize {
+        self.values.len() / (BLOCK * BLOCK)
+    }
+
+    /// Stored blocks over all blocks
+    pub fn density(&self) -> f32 {
+        let total = self.layout.col_tiles() * self.layout.k.div_ceil(BLOCK);
+        if total == 0 { 0.0 } else { self.stored_blocks() as f32 / total as f32 }
+    }
+
+    /// Stored `(column tile, k-block, values)` in index order
+    pub fn blocks(&self) -> impl Iterator<Item = (usize, usize, &[f32])> + '_ {
+        let tiles = self.layout.col_tiles();
+        let k_blocks = &self.index[tiles + 1..];
+        (0..tiles).flat_map(move |tile| {
+            (self.index[tile] as usize..self.index[tile + 1] as usize).map(move |block| {
+                (tile, k_blocks[block] as usize, &self.values[block * BLOCK * BLOCK..(block + 1) * BLOCK * BLOCK])
+            })
+        })
+    }
+
+    pub fn to_dense(&self) -> Vec<f32> {
+        let SparseLayout { k, n, .. } = self.layout;
+        let mut dense = vec![0.0; k * n];
+        for (tile, kb, block) in self.blocks() {
+            for (i, &value) in block.iter().enumerate() {
+                let (row, col) = (kb * BLOCK + i / BLOCK, tile * BLOCK + i % BLOCK);
+                if row < k && col < n {
+                    dense[row * n + col] = value;
+                }
+            }
+        }
+        dense
+    }
+}
+
+/// 2:4 sparse `[k, n]` weight
+///
+/// `values` is `[k4 / 2, n]` with `k4` = `k` rounded up to 4: rows `2g` and
+/// `2g + 1` hold the two kept values of group `g` (rows `4g..4g + 4`).
+/// `index` packs their positions in the group, 2 bits each with the first
+/// in the low bits, for `GROUPS_PER_WORD` groups per word:
+/// `index[(g / 8) * n + col] >> ((g % 8) * 4)`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TwoFourMatrix {
+    layout: SparseLayout,
+    values: Vec<f32>,
+    index: Vec<u32>,
+}
+
+impl TwoFourMatrix {
+    /// Convert a weight that already has at most 2 non-zeros per group
+    pub fn from_dense(dense: &[f32], k: usize, n: usize) -> SparseResult<Self> {
+        Self::build(dense, k, n, |group, row, col| {
+            let nonzero: Vec<usize> = (0..4).filter(|&i| group[i] != 0.0).collect();
+            if nonzero.len() > 2 {
+                return Err(SparseError::NotTwoFour { row, col });
+            }
+            // Pad with the first zero positions
+            let mut kept: Vec<usize> = nonzero.into_iter().chain((0..4).filter(|&i| group[i] == 0.0)).take(2).collect();
+            kept.sort_unstable();
+            Ok([kept[0], kept[1]])
+        })
+    }
+
+    /// Keep the 2 largest magnitudes of every group
+    pub fn prune(dense: &[f32], k: usize, n: usize) -> SparseResult<Self> {
+        Self::build(dense, k, n, |group, _, _| {
+            let mut order = [0, 1, 2, 3];
+            order.sort_by(|&a, &b| group[b].abs().total_cmp(&group[a].abs()));
+            Ok([order[0].min(order[1]), order[0].max(order[1])])
+        })
+    }
+
+    fn build(
+        dense: &[f32],
+        k: usize,
+        n: usize,
+        positions: impl Fn([f32; 4], usize, usize) -> SparseResult<[usize; 2]>,
+    ) -> SparseResult<Self> {
+        check_len("dense weight", k * n, dense.len())?;
+        let layout = SparseLayout {
+            format: SparseFormat::TwoFour,
+            k,
+            n,
+        };
+        let mut values = vec![0.0; layout.value_count(0)];
+        let mut index = vec![0u32; layout.index_words(0)];
+        for g in 0..layout.groups() {
+            for col in 0..n {
+                let group: [f32; 4] = std::array::from_fn(|i| dense_at(dense, k, n, 4 * g + i, col));
+                let kept = positions(group, 4 * g, col)?;
+                values[2 * g * n + col] = group[kept[0]];
+                values[(2 * g + 1) * n + col] = group[kept[1]];
+                let nibble = (kept[0] | kept[1] << 2) as u32;
+                index[(g / GROUPS_PER_WORD) * n + col] |= nibble << ((g % GROUPS_PER_WORD) * 4);
+            }
+        }
+        Ok(Self { layout, values, index })
+    }
+
+    /// Validate `values` and `index` read from a checkpoint
+    pub fn from_parts(k: usize, n: usize, values: Vec<f32>, index: Vec<u32>) -> SparseResult<Self> {
+        let layout = SparseLayout {
+            format: SparseFormat::TwoFour,
+            k,
+            n,
+        };
+        check_len("2:4 values", layout.value_count(0), values.len())?;
+        check_len("2:4 index", layout.index_words(0), index.len())?;
+        let matrix = Self { layout, values, index };
+        for g in 0..layout.groups() {
+            for col in 0..n {
+                let [first, second] = matrix.positions(g, col);
+                if first >= second {
+                    return Err(SparseError::InvalidIndex(format!(
+                        "group {} of column {}: positions {} and {} not ascending",
+                        g, col, first, second
+                    )));
+                }
+            }
+        }
+        Ok(matrix)
+    }
+
+    /// Positions within group `g` of the values kept in column `col`
+    pub fn positions(&self, g: usize, col: usize) -> [usize; 2] {
+        let word = self.index[(g / GROUPS_PER_WORD) * self.layout.n + col];
+        let nibble = (word >> ((g % GROUPS_PER_WORD) * 4)) as usize;
+        [nibble & 3, (nibble >> 2) & 3]
+    }
+
+    pub fn layout(&self) -> SparseLayout {
+        self.layout
+    }
+
+    pub fn values(&self) -> &[f32] {
+        &self.values
+    }
+
+    pub fn index(&self) -> &[u32] {
+        &self.index
+    }
+
+    pub fn to_dense(&self) -> Vec<f32> {
+        let SparseLayout { k, n, .. } = self.layout;
+        let mut dense = vec![0.0; k * n];
+        for g in 0..self.layout.groups() {
+            for col in 0..n {
+                for (j, position) in self.positions(g, col).into_iter().enumerate() {
+                    let row = 4 * g + position;
+                    if row < k {
+                        dense[row * n + col] = self.values[(2 * g + j) * n + col];
+                    }
+                }
+            }
+        }
+        dense
+    }
+}
+
+/// A sparse weight in either format
+#[derive(Clone, Debug, PartialEq)]
+pub enum SparseMatrix {
+    Block(BlockSparseMatrix),
+    TwoFour(TwoFourMatrix),
+}
+
+impl SparseMatrix {
+    /// Assemble the `.values` and `.index` tensors of a checkpoint weight
+    pub fn from_parts(layout: SparseLayout, values: Vec<f32>, index: Vec<u32>) -> SparseResult<Self> {
+        Ok(match layout.format {
+            SparseFormat::Block => Self::Block(BlockSparseMatrix::from_parts(layout.k, layout.n, values, index)?),
+            SparseFormat::TwoFour => Self::TwoFour(TwoFourMatrix::from_parts(layout.k, layout.n, values, index)?),
+        })
+    }
+
+    pub fn layout(&self) -> SparseLayout {
+        match self {
+            Self::Block(m) => m.layout(),
+            Self::TwoFour(m) => m.layout(),
+        }
+    }
+
+    pub fn values(&self) -> &[f32] {
+        match self {
+            Self::Block(m) => m.values(),
+            Self::TwoFour(m) => m.values(),
+        }
+    }
+
+    pub fn index(&self) -> &[u32] {
+        match self {
+            Self::Block(m) => m.index(),
+            Self::TwoFour(m) => m.index(),
+        }
+    }
+
+    /// Bytes of values and index together
+    pub fn stored_bytes(&self) -> usize {
+        (self.values().len() + self.index().len()) * 4
+    }
+
+    pub fn to_dense(&self) -> Vec<f32> {
+        match self {
+            Self::Block(m) => m.to_dense(),
+            Self::TwoFour(m) => m.to_dense(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `[k, n]` weight whose only non-zero block is the second k-block of tile 0
+    fn one_block_weight(k: usize, n: usize) -> Vec<f32> {
+        let mut dense = vec![0.0; k * n];
+        for row in BLOCK..(2 * BLOCK).min(k) {
+            for col in 0..BLOCK.min(n) {
+                dense[row * n + col] = (row * n + col) as f32;
+            }
+        }
+        dense
+    }
+
+    #[test]
+    fn test_block_sparse_round_trip() {
+        let (k, n) = (40, 20);
+        let dense = one_block_weight(k, n);
+        let sparse = BlockSparseMatrix::from_dense(&dense, k, n).unwrap();
+        assert_eq!(sparse.stored_blocks(), 1);
+        assert_eq!(&sparse.index()[..3], &[0, 1, 1]);
+        assert_eq!(sparse.index()[3], 1);
+        assert!((sparse.density() - 1.0 / 6.0).abs() < 1e-6);
+        assert_eq!(sparse.to_dense(), dense);
+
+        let parts = SparseMatrix::from_parts(sparse.layout(), sparse.values().to_vec(), sparse.index().to_vec());
+        assert_eq!(parts.unwrap(), SparseMatrix::Block(sparse.clone()));
+        let mut bad = sparse.index().to_vec();
+        bad[3] = 9;
+        assert!(BlockSparseMatrix::from_parts(k, n, sparse.values().to_vec(), bad).is_err());
+
+        let pruned = BlockSparseMatrix::prune(&vec![1.0; k * n], k, n, 0.5).unwrap();
+        assert_eq!(pruned.stored_blocks(), 3);
+    }
+
+    #[test]
+    fn test_two_four_round_trip() {
+        // k = 6 leaves a partial second group
+        let (k, n) = (6, 2);
+        let dense = [1.0, 0.0, 0.0, 2.0, 3.0, 0.0, 0.0, 0.0, 0.0, 0.0, 5.0, 4.0];
+        let sparse = TwoFourMatrix::from_dense(&dense, k, n).unwrap();
+        assert_eq!(sparse.positions(0, 0), [0, 2]);
+        assert_eq!(sparse.positions(0, 1), [0, 1]);
+        assert_eq!(sparse.values().len(), 8);
+        assert_eq!(sparse.to_dense(), dense);
+
+        let full = [1.0, -4.0, 2.0, 3.0];
+        assert_eq!(TwoFourMatrix::from_dense(&full, 4, 1), Err(SparseError::NotTwoFour { row: 0, col: 0 }));
+        let pruned = TwoFourMatrix::prune(&full, 4, 1).unwrap();
+        assert_eq!(pruned.to_dense(), vec![0.0, -4.0, 0.0, 3.0]);
+        assert!(TwoFourMatrix::from_parts(4, 1, pruned.values().to_vec(), vec![0b0101]).is_err());
+    }
+
+    #[test]
+    fn test_layout_metadata() {
+        let layout = SparseLayout::parse("2:4; 4096,11008").unwrap();
+        assert_eq!(layout.format, SparseFormat::TwoFour);
+        assert_eq!((layout.k, layout.n), (4096, 11008));
+        assert_eq!(SparseLayout::parse(&layout.encode()).unwrap(), layout);
+        assert!(SparseLayout::parse("csr;4,4").is_err());
+        assert!(SparseLayout::parse("block16;4").is_err());
+    }
+}
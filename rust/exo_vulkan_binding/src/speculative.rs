@@ -0,0 +1,393 @@
+//! Draft/target speculative decoding
+//!
+//! Runs a small draft model ahead of a large target model: the draft proposes
+//! a few tokens, the target scores them in a single forward pass, and the
+//! longest agreeing prefix is accepted. Both models roll their KV caches back
+//! to the accepted length before the next round, so they can live on the same
+//! device or on different devices of the registry.
+//!
+//! Acceptance goes through `LanguageModel::verify`: runtimes keeping logits
+//! on device run `KernelRegistry::speculative_accept` there and read back two
+//! words instead of the round's logits.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use thiserror::Error;
+
+use crate::eval;
+use crate::frame_budget::FramePacer;
+use crate::metrics_history;
+use crate::performance;
+
+/// Speculative decoding errors
+#[derive(Error, Debug)]
+pub enum SpeculativeError {
+    #[error("No runtime registered for model: {0}")]
+    RuntimeNotFound(String),
+
+    #[error("Model forward failed: {0}")]
+    ForwardFailed(String),
+
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+}
+
+pub type SpeculativeResult<T> = Result<T, SpeculativeError>;
+
+/// A decoder-only model with a rollback-capable KV cache
+pub trait LanguageModel: Send {
+    /// Append `tokens` to the KV cache and return logits for each of them
+    fn forward(&mut self, tokens: &[u32]) -> SpeculativeResult<Vec<Vec<f32>>>;
+
+    /// Number of tokens currently held in the KV cache
+    fn kv_len(&self) -> usize;
+
+    /// Drop KV cache entries beyond `len`
+    fn truncate_kv(&mut self, len: usize);
+
+    /// Append `tokens` to the KV cache and return the summed cross-entropy of
+    /// predicting `targets[i]` at the position of `tokens[i]`, in nats
+    ///
+    /// Runtimes keeping logits on device override this to reduce them with
+    /// `KernelRegistry::cross_entropy` instead of reading them back.
+    fn forward_nll(&mut self, tokens: &[u32], targets: &[u32]) -> SpeculativeResult<f64> {
+        let logits = self.forward(tokens)?;
+        eval::sum_nll_host(&logits, targets).map_err(|e| SpeculativeError::InvalidRequest(e.to_string()))
+    }
+
+    /// Append `tokens` to the KV cache and greedily accept `draft` against the
+    /// logits, as `accept_greedy` does
+    ///
+    /// `tokens` is the round input followed by `draft`. Runtimes keeping
+    /// logits on device override this to run
+    /// `KernelRegistry::speculative_accept` instead of reading them back.
+    fn verify(&mut self, tokens: &[u32], draft: &[u32]) -> SpeculativeResult<(usize, Option<u32>)> {
+        let logits = self.forward(tokens)?;
+        Ok(accept_greedy(draft, &logits))
+    }
+
+    /// GPU time of the last `forward` or `verify`, from timestamp queries
+    ///
+    /// Runtimes without timestamps return None and are paced by wall time.
+    fn last_gpu_time(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Run one model step, yielding to the UI first if the frame budget is spent
+///
+/// The step's GPU time is recorded for `performance`.
+fn paced<T>(
+    model: &mut dyn LanguageModel,
+    pacer: &mut Option<FramePacer>,
+    step: impl FnOnce(&mut dyn LanguageModel) -> SpeculativeResult<T>,
+) -> SpeculativeResult<T> {
+    if let Some(pacer) = pacer {
+        pacer.pace();
+    }
+    let started = Instant::now();
+    let output = step(&mut *model)?;
+    let gpu_time = model.last_gpu_time().unwrap_or_else(|| started.elapsed());
+    if let Some(pacer) = pacer {
+        pacer.after_step(gpu_time);
+    }
+    let now = Instant::now();
+    performance::global().record_gpu_time(gpu_time, now);
+    metrics_history::global().record_gpu_time(gpu_time, now);
+    Ok(output)
+}
+
+/// `paced` `forward`
+fn paced_forward(
+    model: &mut dyn LanguageModel,
+    tokens: &[u32],
+    pacer: &mut Option<FramePacer>,
+) -> SpeculativeResult<Vec<Vec<f32>>> {
+    paced(model, pacer, |model| model.forward(tokens))
+}
+
+/// Shared handle to a registered model runtime
+pub type SharedModel = Arc<Mutex<Box<dyn LanguageModel>>>;
+
+lazy_static::lazy_static! {
+    static ref RUNTIMES: Mutex<HashMap<String, SharedModel>> = Mutex::new(HashMap::new());
+}
+
+/// Register the runtime executing a loaded model
+pub fn register_runtime(model_id: &str, model: Box<dyn LanguageModel>) {
+    RUNTIMES
+        .lock()
+        .insert(model_id.to_string(), Arc::new(Mutex::new(model)));
+}
+
+/// Remove a model's runtime
+pub fn unregister_runtime(model_id: &str) -> bool {
+    RUNTIMES.lock().remove(model_id).is_some()
+}
+
+/// Look up a registered runtime
+pub fn runtime(model_id: &str) -> SpeculativeResult<SharedModel> {
+    RUNTIMES
+        .lock()
+        .get(model_id)
+        .cloned()
+        .ok_or_else(|| SpeculativeError::RuntimeNotFound(model_id.to_string()))
+}
+
+/// Index of the largest logit
+pub fn argmax(logits: &[f32]) -> u32 {
+    logits
+        .iter()
+        .enumerate()
+        .fold((0, f32::NEG_INFINITY), |best, (i, &v)| if v > best.1 { (i, v) } else { best })
+        .0 as u32
+}
+
+/// Greedy acceptance of draft tokens against target logits
+///
+/// `target_logits[i]` are the target's logits for the position of `draft[i]`.
+/// Returns the number of accepted draft tokens and the target's token for the
+/// first rejected position (or the bonus token after a fully accepted draft).
+pub fn accept_greedy(draft: &[u32], target_logits: &[Vec<f32>]) -> (usize, Option<u32>) {
+    for (i, &token) in draft.iter().enumerate() {
+        match target_logits.get(i) {
+            Some(logits) => {
+                let expected = argmax(logits);
+                if expected != token {
+                    return (i, Some(expected));
+                }
+            }
+            None => return (i, None),
+        }
+    }
+    (draft.len(), target_logits.get(draft.len()).map(|l| argmax(l)))
+}
+
+/// Outcome of a speculative generation
+#[derive(Clone, Debug, Default)]
+pub struct SpeculativeOutput {
+    pub tokens: Vec<u32>,
+    /// Draft tokens proposed across all rounds
+    pub proposed: usize,
+    /// Draft tokens accepted by the target
+    pub accepted: usize,
+}
+
+impl SpeculativeOutput {
+    /// Fraction of proposed draft tokens the target accepted
+    pub fn acceptance_rate(&self) -> f32 {
+        if self.proposed == 0 {
+            0.0
+        } else {
+            self.accepted as f32 / self.proposed as f32
+        }
+    }
+}
+
+/// Run interleaved draft/target decoding
+///
+/// # Arguments
+/// * `draft` - Small model proposing tokens
+/// * `target` - Large model verifying them
+/// * `prompt` - Prompt tokens (must be non-empty)
+/// * `max_tokens` - Number of tokens to generate
+/// * `draft_len` - Tokens proposed per round
+/// * `stop_token` - Optional end-of-sequence token
+pub fn generate_speculative(
+    draft: &mut dyn LanguageModel,
+    target: &mut dyn LanguageModel,
+    prompt: &[u32],
+    max_tokens: usize,
+    draft_len: usize,
+    stop_token: Option<u32>,
+) -> SpeculativeResult<SpeculativeOutput> {
+    if prompt.is_empty() {
+        return Err(SpeculativeError::InvalidRequest("prompt is empty".to_string()));
+    }
+    if draft_len == 0 {
+        return Err(SpeculativeError::InvalidRequest("draft_len must be > 0".to_string()));
+    }
+
+    let mut output = SpeculativeOutput::default();
+    let mut pacer = FramePacer::from_global(Instant::now());
+
+    // Prefill both caches with all but the last prompt token; the last token
+    // is fed as the first input of every round
+    let (prefix, &last) = (&prompt[..prompt.len() - 1], prompt.last().unwrap_or(&0));
+    draft.truncate_kv(0);
+    target.truncate_kv(0);
+    if !prefix.is_empty() {
+        draft.forward(prefix)?;
+        target.forward(prefix)?;
+    }
+    let mut next_input = last;
+
+    while output.tokens.len() < max_tokens {
+        let base = target.kv_len();
+        let remaining = max_tokens - output.tokens.len();
+        let round_len = draft_len.min(remaining);
+
+        // Draft proposes round_len tokens autoregressively
+        let mut proposal = Vec::with_capacity(round_len);
+        let mut input = next_input;
+        for _ in 0..round_len {
+            let logits = paced_forward(draft, &[input], &mut pacer)?;
+            let token = logits.last().map(|l| argmax(l)).ok_or_else(|| {
+                SpeculativeError::ForwardFailed("draft returned no logits".to_string())
+            })?;
+            proposal.push(token);
+            input = token;
+        }
+
+        // Target verifies the round input plus all proposed tokens in one pass
+        let mut verify_input = Vec::with_capacity(round_len + 1);
+        verify_input.push(next_input);
+        verify_input.extend_from_slice(&proposal);
+        let (accepted, correction) = paced(target, &mut pacer, |target| target.verify(&verify_input, &proposal))?;
+        output.proposed += proposal.len();
+        output.accepted += accepted;
+
+        let mut round_tokens: Vec<u32> = proposal[..accepted].to_vec();
+        round_tokens.extend(correction);
+        round_tokens.truncate(remaining);
+
+        // Roll both caches back to the verified prefix; after a fully accepted
+        // round the draft has not yet seen its own last proposal
+        let kept = base + 1 + accepted;
+        target.truncate_kv(kept);
+        if draft.kv_len() > kept {
+            draft.truncate_kv(kept);
+        } else if draft.kv_len() < kept {
+            let missing_from = draft.kv_len() - base - 1;
+            paced_forward(draft, &proposal[missing_from..accepted], &mut pacer)?;
+        }
+
+        let Some(&last_token) = round_tokens.last() else {
+            break;
+        };
+        let stop_at = stop_token.and_then(|stop| round_tokens.iter().position(|&t| t == stop));
+        let emitted = &round_tokens[..stop_at.map_or(round_tokens.len(), |pos| pos + 1)];
+        output.tokens.extend_from_slice(emitted);
+        let now = Instant::now();
+        performance::global().record_tokens(emitted.len() as u64, now);
+        metrics_history::global().record_tokens(emitted.len() as u64, now);
+        if stop_at.is_some() {
+            break;
+        }
+        next_input = last_token;
+    }
+
+    Ok(output)
+}
+
+/// Run speculative decoding between two registered runtimes
+pub fn generate_speculative_by_id(
+    draft_model_id: &str,
+    target_model_id: &str,
+    prompt: &[u32],
+    max_tokens: usize,
+    draft_len: usize,
+    stop_token: Option<u32>,
+) -> SpeculativeResult<SpeculativeOutput> {
+    if draft_model_id == target_model_id {
+        return Err(SpeculativeError::InvalidRequest(
+            "draft and target must be different models".to_string(),
+        ));
+    }
+    let draft = runtime(draft_model_id)?;
+    let target = runtime(target_model_id)?;
+    // Lock in id order, so concurrent calls with the roles swapped cannot deadlock
+    let (mut draft, mut target) = if draft_model_id < target_model_id {
+        let draft = draft.lock();
+        (draft, target.lock())
+    } else {
+        let target = target.lock();
+        (draft.lock(), target)
+    };
+    generate_speculative(
+        draft.as_mut(),
+        target.as_mut(),
+        prompt,
+        max_tokens,
+        draft_len,
+        stop_token,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Toy model predicting `(last + step) % vocab`
+    struct Counter {
+        step: u32,
+        vocab: usize,
+        kv: Vec<u32>,
+    }
+
+    impl LanguageModel for Counter {
+        fn forward(&mut self, tokens: &[u32]) -> SpeculativeResult<Vec<Vec<f32>>> {
+            self.kv.extend_from_slice(tokens);
+            Ok(tokens
+                .iter()
+                .map(|&t| {
+                    let mut logits = vec![0.0; self.vocab];
+                    logits[(t as usize + self.step as usize) % self.vocab] = 1.0;
+                    logits
+                })
+                .collect())
+        }
+
+        fn kv_len(&self) -> usize {
+            self.kv.len()
+        }
+
+        fn truncate_kv(&mut self, len: usize) {
+            self.kv.truncate(len);
+        }
+    }
+
+    #[test]
+    fn test_matching_draft_is_fully_accepted() {
+        let mut draft = Counter { step: 1, vocab: 64, kv: Vec::new() };
+        let mut target = Counter { step: 1, vocab: 64, kv: Vec::new() };
+        let out = generate_speculative(&mut draft, &mut target, &[1, 2], 8, 4, None).unwrap();
+        assert_eq!(out.tokens, vec![3, 4, 5, 6, 7, 8, 9, 10]);
+        assert_eq!(draft.kv_len(), target.kv_len());
+    }
+
+    #[test]
+    fn test_mismatched_draft_follows_target() {
+        let mut draft = Counter { step: 2, vocab: 64, kv: Vec::new() };
+        let mut target = Counter { step: 1, vocab: 64, kv: Vec::new() };
+        let out = generate_speculative(&mut draft, &mut target, &[1], 5, 3, None).unwrap();
+        assert_eq!(out.tokens, vec![2, 3, 4, 5, 6]);
+        assert_eq!(out.accepted, 0);
+        assert_eq!(target.kv_len(), 5);
+    }
+
+    #[test]
+    fn test_swapped_roles_do_not_deadlock() {
+        for id in ["spec-lock-a", "spec-lock-b"] {
+            register_runtime(id, Box::new(Counter { step: 1, vocab: 64, kv: Vec::new() }));
+        }
+        let workers: Vec<_> = [("spec-lock-a", "spec-lock-b"), ("spec-lock-b", "spec-lock-a")]
+            .into_iter()
+            .map(|(draft, target)| {
+                std::thread::spawn(move || {
+                    for _ in 0..200 {
+                        generate_speculative_by_id(draft, target, &[1, 2], 4, 2, None).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert!(unregister_runtime("spec-lock-a"));
+        assert!(unregister_runtime("spec-lock-b"));
+    }
+}
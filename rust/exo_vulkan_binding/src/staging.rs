@@ -0,0 +1,238 @@
+//! Staging buffer pool for `DataTransfer`
+//!
+//! Uploading a tensor through a fresh staging buffer costs a buffer, an
+//! allocation, a map and their teardown on every copy. The pool keeps
+//! persistently mapped host-visible buffers instead, in power-of-two size
+//! buckets, and hands them out again on the next copy of a similar size.
+//! Each bucket keeps at most `BUFFERS_PER_BUCKET` idle buffers; requests
+//! above `MAX_BUCKET` get a one-off buffer that is destroyed on release.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use ash::vk;
+use parking_lot::Mutex;
+use thiserror::Error;
+
+use crate::memory::{MemoryUsage, find_memory_type};
+
+/// Staging pool errors
+#[derive(Error, Debug)]
+pub enum StagingError {
+    #[error("No host-visible, host-coherent memory type can back a staging buffer")]
+    NoMemoryType,
+
+    #[error("Vulkan error: {0:?}")]
+    VulkanError(vk::Result),
+}
+
+pub type StagingResult<T> = Result<T, StagingError>;
+
+/// Smallest bucket; small copies share it
+pub const MIN_BUCKET: u64 = 64 * 1024;
+
+/// Largest pooled bucket
+pub const MAX_BUCKET: u64 = 256 * 1024 * 1024;
+
+/// Idle buffers kept per bucket
+pub const BUFFERS_PER_BUCKET: usize = 2;
+
+/// Bucket serving a copy of `bytes`
+pub fn bucket_size(bytes: u64) -> u64 {
+    bytes.max(MIN_BUCKET).next_power_of_two()
+}
+
+/// A mapped staging buffer, usable as copy source and destination
+pub struct StagingBuffer {
+    buffer: vk::Buffer,
+    memory: vk::DeviceMemory,
+    size: u64,
+    ptr: *mut u8,
+}
+
+// SAFETY: the mapping belongs to `memory`, which only the holder of the
+// buffer uses
+unsafe impl Send for StagingBuffer {}
+
+impl StagingBuffer {
+    pub fn buffer(&self) -> vk::Buffer {
+        self.buffer
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Mapped bytes of the buffer
+    pub fn mapped(&mut self) -> &mut [u8] {
+        // SAFETY: ptr maps all `size` bytes of memory for as long as self exists
+        unsafe { std::slice::from_raw_parts_mut(self.ptr, self.size as usize) }
+    }
+}
+
+/// Pool counters
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StagingStats {
+    /// Acquires served by an idle buffer
+    pub hits: u64,
+    /// Acquires that created a buffer
+    pub misses: u64,
+    /// Idle buffers and their bytes
+    pub idle_buffers: usize,
+    pub idle_bytes: u64,
+}
+
+#[derive(Default)]
+struct Buckets {
+    idle: BTreeMap<u64, VecDeque<StagingBuffer>>,
+    hits: u64,
+    misses: u64,
+}
+
+/// Persistently mapped staging buffers reused across copies
+pub struct StagingPool {
+    device: ash::Device,
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    buckets: Mutex<Buckets>,
+}
+
+impl StagingPool {
+    /// Create an empty pool for `device`
+    ///
+    /// Buffers use `MemoryUsage::Staging` memory, which is host coherent, so
+    /// the pool never flushes or invalidates.
+    pub fn new(device: ash::Device, memory_properties: vk::PhysicalDeviceMemoryProperties) -> Self {
+        Self {
+            device,
+            memory_properties,
+            buckets: Mutex::new(Buckets::default()),
+        }
+    }
+
+    /// A buffer of at least `bytes`, idle or newly created
+    pub fn acquire(&self, bytes: u64) -> StagingResult<StagingBuffer> {
+        let size = if bytes > MAX_BUCKET { bytes } else { bucket_size(bytes) };
+        {
+            let mut buckets = self.buckets.lock();
+            if let Some(buffer) = buckets.idle.get_mut(&size).and_then(VecDeque::pop_front) {
+                buckets.hits += 1;
+                return Ok(buffer);
+            }
+            buckets.misses += 1;
+        }
+        self.create(size)
+    }
+
+    /// Return a buffer once the copies using it have completed
+    pub fn release(&self, buffer: StagingBuffer) {
+        if buffer.size <= MAX_BUCKET {
+            let mut buckets = self.buckets.lock();
+            let ring = buckets.idle.entry(buffer.size).or_default();
+            if ring.len() < BUFFERS_PER_BUCKET {
+                ring.push_back(buffer);
+                return;
+            }
+        }
+        self.destroy(buffer);
+    }
+
+    /// Destroy every idle buffer
+    pub fn trim(&self) {
+        let idle = std::mem::take(&mut self.buckets.lock().idle);
+        for buffer in idle.into_values().flatten() {
+            self.destroy(buffer);
+        }
+    }
+
+    pub fn stats(&self) -> StagingStats {
+        let buckets = self.buckets.lock();
+        StagingStats {
+            hits: buckets.hits,
+            misses: buckets.misses,
+            idle_buffers: buckets.idle.values().map(VecDeque::len).sum(),
+            idle_bytes: buckets.idle.iter().map(|(size, ring)| size * ring.len() as u64).sum(),
+        }
+    }
+
+    fn create(&self, size: u64) -> StagingResult<StagingBuffer> {
+        let info = vk::BufferCreateInfo::default()
+            .size(size)
+            .usage(vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        // SAFETY: self.device is valid for the pool's lifetime (guaranteed by
+        // the owning `DataTransfer`)
+        let buffer = unsafe { self.device.create_buffer(&info, None) }.map_err(StagingError::VulkanError)?;
+        // SAFETY: buffer was just created on self.device
+        let requirements = unsafe { self.device.get_buffer_memory_requirements(buffer) };
+        let Some(memory_type) = find_memory_type(&self.memory_properties, requirements.memory_type_bits, MemoryUsage::Staging)
+        else {
+            // SAFETY: buffer is unused
+            unsafe { self.device.destroy_buffer(buffer, None) };
+            return Err(StagingError::NoMemoryType);
+        };
+        let alloc = vk::MemoryAllocateInfo::default()
+            .allocation_size(requirements.size)
+            .memory_type_index(memory_type);
+        // SAFETY: buffer and memory are new and owned here; every failure
+        // path releases what was created before it
+        unsafe {
+            let memory = match self.device.allocate_memory(&alloc, None) {
+                Ok(memory) => memory,
+                Err(e) => {
+                    self.device.destroy_buffer(buffer, None);
+                    return Err(StagingError::VulkanError(e));
+                }
+            };
+            let mapped = self
+                .device
+                .bind_buffer_memory(buffer, memory, 0)
+                .and_then(|()| self.device.map_memory(memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty()));
+            match mapped {
+                Ok(ptr) => Ok(StagingBuffer {
+                    buffer,
+                    memory,
+                    size,
+                    ptr: ptr.cast(),
+                }),
+                Err(e) => {
+                    self.device.destroy_buffer(buffer, None);
+                    self.device.free_memory(memory, None);
+                    Err(StagingError::VulkanError(e))
+                }
+            }
+        }
+    }
+
+    fn destroy(&self, buffer: StagingBuffer) {
+        // SAFETY: buffers reach here only once released or idle, so no
+        // pending copy uses them; freeing the memory unmaps it
+        unsafe {
+            self.device.destroy_buffer(buffer.buffer, None);
+            self.device.free_memory(buffer.memory, None);
+        }
+    }
+}
+
+impl Drop for StagingPool {
+    fn drop(&mut self) {
+        self.trim();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_size() {
+        assert_eq!(bucket_size(0), MIN_BUCKET);
+        assert_eq!(bucket_size(MIN_BUCKET), MIN_BUCKET);
+        assert_eq!(bucket_size(MIN_BUCKET + 1), 2 * MIN_BUCKET);
+        assert_eq!(bucket_size(3 * 1024 * 1024), 4 * 1024 * 1024);
+        assert_eq!(bucket_size(MAX_BUCKET), MAX_BUCKET);
+    }
+
+    #[test]
+    fn test_staging_error_display() {
+        assert!(StagingError::NoMemoryType.to_string().contains("host-coherent"));
+    }
+}
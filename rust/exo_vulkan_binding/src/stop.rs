@@ -0,0 +1,206 @@
+//! Stop-sequence detection on device
+//!
+//! After each sampled token is appended to a sequence's history, one small
+//! dispatch compares the tail of the history against every registered stop
+//! sequence and sets a completion flag. The flag buffer is 8 bytes and
+//! usually lives in host-visible memory, so the decode loop checks it after
+//! the step's fence instead of downloading and scanning tokens every step.
+
+use ash::vk;
+
+use crate::memory::BufferRange;
+use crate::ops::{KernelBinding, OpsError, OpsResult};
+
+/// Threads per workgroup of the stop-sequence kernel
+pub const STOP_WORKGROUP_SIZE: u32 = 64;
+
+/// Bytes of the flag buffer written by `check_stop_sequences`
+pub const STOP_FLAG_BYTES: u64 = 8;
+
+/// Push constants of the stop-sequence kernel
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StopPushConstants {
+    pub history_len: u32,
+    pub sequences: u32,
+    pub _pad: [u32; 2],
+}
+
+/// Registered stop sequences, packed for the kernel
+///
+/// Table layout: one `(start, len)` word pair per sequence, followed by the
+/// token words they index. Upload `words()` once; it only changes when the
+/// set of stop sequences does.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StopSequences {
+    sequences: Vec<Vec<u32>>,
+}
+
+impl StopSequences {
+    /// Register `sequences`; empty ones never match and are dropped
+    pub fn new(sequences: impl IntoIterator<Item = Vec<u32>>) -> Self {
+        Self {
+            sequences: sequences.into_iter().filter(|s| !s.is_empty()).collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.sequences.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sequences.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&[u32]> {
+        self.sequences.get(index).map(Vec::as_slice)
+    }
+
+    /// Longest registered sequence; the kernel reads at most this many
+    /// trailing history tokens
+    pub fn max_len(&self) -> usize {
+        self.sequences.iter().map(Vec::len).max().unwrap_or(0)
+    }
+
+    /// Packed table words
+    pub fn words(&self) -> Vec<u32> {
+        let mut words = Vec::with_capacity(self.sequences.len() * 2 + self.sequences.iter().map(Vec::len).sum::<usize>());
+        let mut start = (self.sequences.len() * 2) as u32;
+        for sequence in &self.sequences {
+            words.extend([start, sequence.len() as u32]);
+            start += sequence.len() as u32;
+        }
+        for sequence in &self.sequences {
+            words.extend_from_slice(sequence);
+        }
+        words
+    }
+
+    /// Host reference: index of the first sequence the history ends with
+    pub fn find_match(&self, history: &[u32]) -> Option<usize> {
+        self.sequences.iter().position(|s| history.ends_with(s))
+    }
+}
+
+/// Result of a stop-sequence check, decoded from the flag buffer
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StopStatus {
+    /// Lowest index of a stop sequence the history ends with
+    pub matched: Option<u32>,
+}
+
+impl StopStatus {
+    /// Decode from flag buffer bytes (mapped or read back)
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let word = |i: usize| {
+            bytes
+                .get(i * 4..i * 4 + 4)
+                .map_or(0, |b| u32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+        };
+        Self {
+            matched: (word(0) != 0).then_some(word(1)),
+        }
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.matched.is_some()
+    }
+}
+
+/// Record a check of the last tokens of `history` against the uploaded
+/// stop table
+///
+/// `history` holds the sequence's `history_len` tokens as u32, `table` the
+/// words of `stops`. Decode `flags` with `StopStatus::from_bytes`.
+///
+/// # Safety Requirements
+/// - `cmd` must be in the recording state
+/// - The kernel's descriptor set must not be in use by pending work
+/// - All buffers must stay alive until the command buffer completes
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn check_stop_sequences(
+    device: &ash::Device,
+    cmd: vk::CommandBuffer,
+    kernel: &KernelBinding,
+    stops: &StopSequences,
+    history: &BufferRange,
+    history_len: u32,
+    table: &BufferRange,
+    flags: &BufferRange,
+) -> OpsResult<StopPushConstants> {
+    if flags.size < STOP_FLAG_BYTES {
+        return Err(OpsError::ScratchTooSmall {
+            needed: STOP_FLAG_BYTES,
+            available: flags.size,
+        });
+    }
+    let table_bytes = stops.words().len() as u64 * 4;
+    if table.size < table_bytes {
+        return Err(OpsError::ScratchTooSmall {
+            needed: table_bytes,
+            available: table.size,
+        });
+    }
+    if history.size < u64::from(history_len) * 4 {
+        return Err(OpsError::ShapeMismatch(format!(
+            "history of {} bytes holds fewer than {} tokens",
+            history.size, history_len
+        )));
+    }
+
+    let push = StopPushConstants {
+        history_len,
+        sequences: stops.len() as u32,
+        ..Default::default()
+    };
+    let reset = vk::MemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+    // SAFETY: forwarded from the caller's guarantees
+    unsafe {
+        device.cmd_fill_buffer(cmd, flags.buffer, flags.offset, 4, 0);
+        device.cmd_fill_buffer(cmd, flags.buffer, flags.offset + 4, 4, u32::MAX);
+        device.cmd_pipeline_barrier(
+            cmd,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::DependencyFlags::empty(),
+            &[reset],
+            &[],
+            &[],
+        );
+        if stops.is_empty() {
+            return Ok(push);
+        }
+        kernel.bind_buffers(device, history, flags);
+        kernel.bind_buffer(device, 2, table);
+        kernel.record(device, cmd, &push, [push.sequences.div_ceil(STOP_WORKGROUP_SIZE), 1, 1]);
+    }
+    Ok(push)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_layout() {
+        let stops = StopSequences::new([vec![7, 8], vec![], vec![9]]);
+        assert_eq!(stops.len(), 2);
+        assert_eq!(stops.words(), vec![4, 2, 6, 1, 7, 8, 9]);
+        assert_eq!(stops.max_len(), 2);
+    }
+
+    #[test]
+    fn test_find_match_and_status() {
+        let stops = StopSequences::new([vec![1, 2], vec![2]]);
+        assert_eq!(stops.find_match(&[5, 1, 2]), Some(0));
+        assert_eq!(stops.find_match(&[5, 3, 2]), Some(1));
+        assert_eq!(stops.find_match(&[2, 1]), None);
+
+        let mut bytes = 1u32.to_ne_bytes().to_vec();
+        bytes.extend_from_slice(&1u32.to_ne_bytes());
+        assert_eq!(StopStatus::from_bytes(&bytes).matched, Some(1));
+        assert!(!StopStatus::from_bytes(&[0; 8]).is_stopped());
+    }
+}
@@ -0,0 +1,176 @@
+//! Runtime checks of documented Safety Requirements
+//!
+//! Misusing the memory, transfer and command APIs (a freed allocation
+//! passed to a copy, mapping device-local memory, a command buffer
+//! submitted to a queue of another family) is undefined behaviour that
+//! typically surfaces much later as `ERROR_DEVICE_LOST` on someone's phone.
+//! With the `strict-checks` feature the functions here assert those
+//! requirements at the call site instead, so integration tests fail at the
+//! misuse. Without it every check compiles to nothing.
+//!
+//! Queue family checks need to know where a command buffer came from;
+//! `command::CommandPool` registers the buffers it allocates while the
+//! feature is on.
+
+use std::collections::HashMap;
+
+use ash::vk;
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+
+use crate::memory::AllocationInfo;
+
+/// Whether the checks are compiled in
+pub const ENABLED: bool = cfg!(feature = "strict-checks");
+
+lazy_static! {
+    /// Pool and queue family of each command buffer allocated through `CommandPool`
+    static ref COMMAND_BUFFERS: Mutex<HashMap<vk::CommandBuffer, (vk::CommandPool, u32)>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Assert a Safety Requirement; `requirement` describes it for the failure message
+#[track_caller]
+pub fn require(condition: bool, requirement: impl FnOnce() -> String) {
+    if ENABLED && !condition {
+        let requirement = requirement();
+        log::error!("Safety requirement violated: {}", requirement);
+        assert!(condition, "Safety requirement violated: {}", requirement);
+    }
+}
+
+/// Assert `handle` is not null
+#[track_caller]
+pub fn non_null<H: vk::Handle>(handle: H, what: &str) {
+    require(handle.as_raw() != 0, || format!("{} must not be a null handle", what));
+}
+
+fn overlaps(a_offset: u64, a_size: u64, b_offset: u64, b_size: u64) -> bool {
+    a_offset < b_offset.saturating_add(b_size) && b_offset < a_offset.saturating_add(a_size)
+}
+
+/// Assert the first `size` bytes of `src` and `dst` do not overlap
+#[track_caller]
+pub fn not_aliased(src: &AllocationInfo, dst: &AllocationInfo, size: u64) {
+    require(
+        src.buffer != dst.buffer || !overlaps(src.offset, size, dst.offset, size),
+        || format!("copy of {} bytes from {} overlaps {}", size, src.handle_id, dst.handle_id),
+    );
+}
+
+/// Assert `allocation` has not been freed and its handles are set
+#[track_caller]
+pub fn live_allocation(allocation: &AllocationInfo) {
+    if !ENABLED {
+        return;
+    }
+    require(allocation.is_live(), || {
+        format!("allocation {} used after free or resize", allocation.handle_id)
+    });
+    non_null(allocation.buffer, &allocation.handle_id);
+    non_null(allocation.device_memory, &allocation.handle_id);
+    require(allocation.size <= allocation.capacity, || {
+        format!(
+            "allocation {}: size {} exceeds capacity {}",
+            allocation.handle_id, allocation.size, allocation.capacity
+        )
+    });
+}
+
+/// Assert memory with `properties` can be mapped
+#[track_caller]
+pub fn host_visible(properties: vk::MemoryPropertyFlags, what: &str) {
+    require(properties.contains(vk::MemoryPropertyFlags::HOST_VISIBLE), || {
+        format!("{} is not HOST_VISIBLE ({:?})", what, properties)
+    });
+}
+
+/// Record command buffers allocated from `pool` of `queue_family_index`
+pub fn register_command_buffers(buffers: &[vk::CommandBuffer], pool: vk::CommandPool, queue_family_index: u32) {
+    if ENABLED {
+        let mut registered = COMMAND_BUFFERS.lock();
+        registered.extend(buffers.iter().map(|&buffer| (buffer, (pool, queue_family_index))));
+    }
+}
+
+/// Forget the buffers of a destroyed pool
+pub fn forget_pool(pool: vk::CommandPool) {
+    if ENABLED {
+        COMMAND_BUFFERS.lock().retain(|_, (owner, _)| *owner != pool);
+    }
+}
+
+/// Assert `buffer`, if registered, was allocated from `pool`
+#[track_caller]
+pub fn buffer_from_pool(buffer: vk::CommandBuffer, pool: vk::CommandPool) {
+    if !ENABLED {
+        return;
+    }
+    let owner = COMMAND_BUFFERS.lock().get(&buffer).map(|(owner, _)| *owner);
+    require(owner.is_none_or(|owner| owner == pool), || {
+        format!("command buffer {:?} was allocated from pool {:?}, not {:?}", buffer, owner, pool)
+    });
+}
+
+/// Registered buffers among `buffers` whose family is not `queue_family_index`
+fn family_mismatches(
+    registered: &HashMap<vk::CommandBuffer, (vk::CommandPool, u32)>,
+    buffers: &[vk::CommandBuffer],
+    queue_family_index: u32,
+) -> Vec<(vk::CommandBuffer, u32)> {
+    buffers
+        .iter()
+        .filter_map(|buffer| match registered.get(buffer) {
+            Some(&(_, family)) if family != queue_family_index => Some((*buffer, family)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Assert every registered buffer in `buffers` belongs to `queue_family_index`
+#[track_caller]
+pub fn same_queue_family(buffers: &[vk::CommandBuffer], queue_family_index: u32) {
+    if !ENABLED {
+        return;
+    }
+    for &buffer in buffers {
+        non_null(buffer, "submitted command buffer");
+    }
+    let mismatches = family_mismatches(&COMMAND_BUFFERS.lock(), buffers, queue_family_index);
+    require(mismatches.is_empty(), || {
+        format!(
+            "command buffers from other queue families submitted to family {}: {:?}",
+            queue_family_index, mismatches
+        )
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ash::vk::Handle;
+
+    #[test]
+    fn test_overlap_and_family_predicates() {
+        assert!(overlaps(0, 64, 32, 64));
+        assert!(!overlaps(0, 64, 64, 64));
+        assert!(!overlaps(u64::MAX - 1, 8, 0, 8));
+
+        let pool = vk::CommandPool::from_raw(1);
+        let (compute, transfer, unknown) = (
+            vk::CommandBuffer::from_raw(10),
+            vk::CommandBuffer::from_raw(11),
+            vk::CommandBuffer::from_raw(12),
+        );
+        let registered = HashMap::from([(compute, (pool, 0)), (transfer, (pool, 1))]);
+        assert!(family_mismatches(&registered, &[compute, unknown], 0).is_empty());
+        assert_eq!(family_mismatches(&registered, &[compute, transfer], 0), vec![(transfer, 1)]);
+    }
+
+    #[cfg(feature = "strict-checks")]
+    #[test]
+    #[should_panic(expected = "must not be a null handle")]
+    fn test_null_handle_asserts() {
+        non_null(vk::Buffer::null(), "destination");
+    }
+}
@@ -0,0 +1,210 @@
+//! Tensor views over device buffers
+//!
+//! A `Tensor` is a `BufferRange` plus a strided `Layout`. Strides are in
+//! elements (f32), so a layout can describe transposed, sliced or broadcast
+//! views without moving data. Kernels receive shape and strides through push
+//! constants, which caps the rank at `MAX_RANK`.
+
+use thiserror::Error;
+
+use crate::memory::BufferRange;
+
+/// Highest rank kernels accept
+pub const MAX_RANK: usize = 6;
+
+/// Size of one element in bytes
+pub const ELEMENT_SIZE: u64 = 4;
+
+/// Tensor-related errors
+#[derive(Error, Debug)]
+pub enum TensorError {
+    #[error("Shape has {shape} dims but strides have {strides}")]
+    RankMismatch { shape: usize, strides: usize },
+
+    #[error("Rank {0} exceeds maximum of {MAX_RANK}")]
+    RankTooHigh(usize),
+
+    #[error("Axis {axis} out of range for rank {rank}")]
+    AxisOutOfRange { axis: usize, rank: usize },
+
+    #[error("Invalid permutation: {0:?}")]
+    InvalidPermutation(Vec<usize>),
+
+    #[error("Buffer too small: need {needed} bytes, have {available}")]
+    BufferTooSmall { needed: u64, available: u64 },
+}
+
+pub type TensorResult<T> = Result<T, TensorError>;
+
+/// Shape and element strides of a tensor
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Layout {
+    shape: Vec<usize>,
+    strides: Vec<usize>,
+}
+
+impl Layout {
+    /// Row-major layout for `shape`
+    pub fn contiguous(shape: &[usize]) -> Self {
+        let mut strides = vec![0; shape.len()];
+        let mut stride = 1;
+        for (dim, s) in shape.iter().zip(strides.iter_mut()).rev() {
+            *s = stride;
+            stride *= dim;
+        }
+        Self {
+            shape: shape.to_vec(),
+            strides,
+        }
+    }
+
+    /// Layout with explicit element strides
+    pub fn with_strides(shape: &[usize], strides: &[usize]) -> TensorResult<Self> {
+        if shape.len() != strides.len() {
+            return Err(TensorError::RankMismatch {
+                shape: shape.len(),
+                strides: strides.len(),
+            });
+        }
+        Ok(Self {
+            shape: shape.to_vec(),
+            strides: strides.to_vec(),
+        })
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    pub fn strides(&self) -> &[usize] {
+        &self.strides
+    }
+
+    pub fn rank(&self) -> usize {
+        self.shape.len()
+    }
+
+    /// Number of logical elements
+    pub fn numel(&self) -> usize {
+        self.shape.iter().product()
+    }
+
+    /// Whether the layout is row-major with no gaps
+    pub fn is_contiguous(&self) -> bool {
+        *self == Self::contiguous(&self.shape)
+    }
+
+    /// Number of elements the backing buffer must hold
+    pub fn span(&self) -> usize {
+        if self.shape.contains(&0) {
+            return 0;
+        }
+        1 + self
+            .shape
+            .iter()
+            .zip(&self.strides)
+            .map(|(dim, stride)| (dim - 1) * stride)
+            .sum::<usize>()
+    }
+
+    /// Element offset of the `linear`-th element in row-major logical order
+    pub fn offset_of(&self, mut linear: usize) -> usize {
+        let mut offset = 0;
+        for (dim, stride) in self.shape.iter().zip(&self.strides).rev() {
+            offset += (linear % dim) * stride;
+            linear /= dim;
+        }
+        offset
+    }
+
+    /// Check `axis` is a valid dimension
+    pub fn check_axis(&self, axis: usize) -> TensorResult<()> {
+        if axis >= self.rank() {
+            return Err(TensorError::AxisOutOfRange {
+                axis,
+                rank: self.rank(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Check the rank fits in kernel push constants
+    pub fn check_kernel_rank(&self) -> TensorResult<()> {
+        if self.rank() > MAX_RANK {
+            return Err(TensorError::RankTooHigh(self.rank()));
+        }
+        Ok(())
+    }
+
+    /// View with dimensions reordered; `perm[i]` is the source dim of output dim `i`
+    pub fn permute(&self, perm: &[usize]) -> TensorResult<Self> {
+        let mut seen = vec![false; self.rank()];
+        if perm.len() != self.rank() || perm.iter().any(|&p| p >= self.rank() || std::mem::replace(&mut seen[p], true))
+        {
+            return Err(TensorError::InvalidPermutation(perm.to_vec()));
+        }
+        Ok(Self {
+            shape: perm.iter().map(|&p| self.shape[p]).collect(),
+            strides: perm.iter().map(|&p| self.strides[p]).collect(),
+        })
+    }
+
+    /// Layout with `axis` removed
+    pub fn remove_axis(&self, axis: usize) -> TensorResult<Self> {
+        self.check_axis(axis)?;
+        let mut shape = self.shape.clone();
+        let mut strides = self.strides.clone();
+        shape.remove(axis);
+        strides.remove(axis);
+        Ok(Self { shape, strides })
+    }
+}
+
+/// A strided f32 view into a device buffer
+#[derive(Clone, Debug)]
+pub struct Tensor {
+    pub range: BufferRange,
+    pub layout: Layout,
+}
+
+impl Tensor {
+    /// Create a view, checking the buffer covers the layout
+    pub fn new(range: BufferRange, layout: Layout) -> TensorResult<Self> {
+        let needed = layout.span() as u64 * ELEMENT_SIZE;
+        if needed > range.size {
+            return Err(TensorError::BufferTooSmall {
+                needed,
+                available: range.size,
+            });
+        }
+        Ok(Self { range, layout })
+    }
+
+    /// Contiguous view of `shape`
+    pub fn contiguous(range: BufferRange, shape: &[usize]) -> TensorResult<Self> {
+        Self::new(range, Layout::contiguous(shape))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contiguous_strides_and_offsets() {
+        let layout = Layout::contiguous(&[2, 3, 4]);
+        assert_eq!(layout.strides(), &[12, 4, 1]);
+        assert_eq!(layout.span(), 24);
+        assert_eq!(layout.offset_of(13), 13);
+    }
+
+    #[test]
+    fn test_permute_is_a_view() {
+        let layout = Layout::contiguous(&[2, 3]).permute(&[1, 0]).unwrap();
+        assert_eq!(layout.shape(), &[3, 2]);
+        assert!(!layout.is_contiguous());
+        // Element (1, 0) of the transpose is element (0, 1) of the source
+        assert_eq!(layout.offset_of(2), 1);
+        assert!(Layout::contiguous(&[2, 3]).permute(&[0, 0]).is_err());
+    }
+}
@@ -0,0 +1,146 @@
+//! Backpressure on queue submissions
+//!
+//! Caps the number of in-flight submissions per queue. Each submission takes
+//! a monotonically increasing timeline value; completion advances the
+//! completed value, and `submitted - completed` is the in-flight depth. When
+//! the cap is reached callers either block (with a timeout) or get
+//! `CommandError::WouldBlock` immediately.
+
+use std::time::Duration;
+
+use parking_lot::{Condvar, Mutex};
+
+use crate::command::{CommandError, CommandResult};
+
+/// What to do when the in-flight cap is reached
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BackpressureMode {
+    /// Wait up to `timeout` for a slot, then fail with `WouldBlock`
+    Block { timeout: Duration },
+    /// Fail with `WouldBlock` immediately
+    WouldBlock,
+}
+
+#[derive(Debug, Default)]
+struct TimelineState {
+    /// Last timeline value handed out
+    submitted: u64,
+    /// Highest timeline value known to have completed
+    completed: u64,
+}
+
+/// Limits in-flight submissions on one queue
+#[derive(Debug)]
+pub struct SubmissionLimiter {
+    max_in_flight: u64,
+    mode: BackpressureMode,
+    state: Mutex<TimelineState>,
+    slot_freed: Condvar,
+}
+
+impl SubmissionLimiter {
+    /// Create a limiter allowing `max_in_flight` outstanding submissions
+    pub fn new(max_in_flight: u64, mode: BackpressureMode) -> Self {
+        Self {
+            max_in_flight: max_in_flight.max(1),
+            mode,
+            state: Mutex::new(TimelineState::default()),
+            slot_freed: Condvar::new(),
+        }
+    }
+
+    /// Reserve a slot and return the timeline value for the submission
+    pub fn acquire(&self) -> CommandResult<u64> {
+        let mut state = self.state.lock();
+        while state.submitted - state.completed >= self.max_in_flight {
+            match self.mode {
+                BackpressureMode::WouldBlock => {
+                    return Err(CommandError::WouldBlock(state.submitted - state.completed));
+                }
+                BackpressureMode::Block { timeout } => {
+                    if self.slot_freed.wait_for(&mut state, timeout).timed_out()
+                        && state.submitted - state.completed >= self.max_in_flight
+                    {
+                        return Err(CommandError::WouldBlock(state.submitted - state.completed));
+                    }
+                }
+            }
+        }
+        state.submitted += 1;
+        Ok(state.submitted)
+    }
+
+    /// Give back a slot whose submission never reached the queue
+    ///
+    /// Only the most recent value can be cancelled; older values must complete.
+    pub fn cancel(&self, value: u64) {
+        let mut state = self.state.lock();
+        if value == state.submitted && state.submitted > state.completed {
+            state.submitted -= 1;
+            self.slot_freed.notify_one();
+        }
+    }
+
+    /// Mark every submission up to and including `value` as complete
+    pub fn complete(&self, value: u64) {
+        let mut state = self.state.lock();
+        let value = value.min(state.submitted);
+        if value > state.completed {
+            state.completed = value;
+            self.slot_freed.notify_all();
+        }
+    }
+
+    /// Mark all outstanding submissions complete (e.g. after a queue idle wait)
+    pub fn complete_all(&self) {
+        let submitted = self.state.lock().submitted;
+        self.complete(submitted);
+    }
+
+    /// Number of submissions currently in flight
+    pub fn in_flight(&self) -> u64 {
+        let state = self.state.lock();
+        state.submitted - state.completed
+    }
+
+    /// Highest completed timeline value
+    pub fn completed_value(&self) -> u64 {
+        self.state.lock().completed
+    }
+
+    /// Configured in-flight cap
+    pub fn max_in_flight(&self) -> u64 {
+        self.max_in_flight
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_would_block_at_cap() {
+        let limiter = SubmissionLimiter::new(2, BackpressureMode::WouldBlock);
+        assert_eq!(limiter.acquire().unwrap(), 1);
+        assert_eq!(limiter.acquire().unwrap(), 2);
+        assert!(matches!(limiter.acquire(), Err(CommandError::WouldBlock(2))));
+
+        limiter.complete(1);
+        assert_eq!(limiter.acquire().unwrap(), 3);
+        assert_eq!(limiter.in_flight(), 2);
+    }
+
+    #[test]
+    fn test_block_times_out() {
+        let limiter = SubmissionLimiter::new(
+            1,
+            BackpressureMode::Block {
+                timeout: Duration::from_millis(10),
+            },
+        );
+        limiter.acquire().unwrap();
+        assert!(limiter.acquire().is_err());
+        limiter.complete_all();
+        assert!(limiter.acquire().is_ok());
+    }
+}
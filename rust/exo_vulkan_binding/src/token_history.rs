@@ -0,0 +1,322 @@
+//! Generated token history in device memory
+//!
+//! Each sequence's tokens live in a device buffer of u32s: the prompt is
+//! uploaded once and every step's sampled tokens are appended by a
+//! buffer-to-buffer copy recorded right after the sampler, so decoding never
+//! downloads a token to extend the history. Kernels that need the history
+//! (repetition penalties, stop sequences) bind `DeviceTokenHistory::range`,
+//! and the app reads any span back in one transfer with `export`.
+//!
+//! The decode loop that owns a history registers it under its session id
+//! as a `TokenSource`, which is how the JNI `exportTokens` finds it.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use ash::vk;
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use thiserror::Error;
+
+use crate::memory::{BufferRange, MemoryAllocator, MemoryError};
+use crate::transfer::{DataTransfer, TransferError};
+
+/// Bytes per token
+const TOKEN_SIZE: u64 = 4;
+
+/// Tokens the device buffer grows by
+pub const CHUNK_TOKENS: usize = 4096;
+
+/// Token history errors
+#[derive(Error, Debug)]
+pub enum TokenHistoryError {
+    #[error("No token history for session {0}")]
+    UnknownSession(String),
+
+    #[error("Tokens from {from} are past the end of a {len}-token history")]
+    OutOfRange { from: usize, len: usize },
+
+    #[error(transparent)]
+    Memory(#[from] MemoryError),
+
+    #[error(transparent)]
+    Transfer(#[from] TransferError),
+}
+
+pub type TokenHistoryResult<T> = Result<T, TokenHistoryError>;
+
+/// Tokens `from..from + count` of a `len`-token history, clamped to its end
+fn span(from: usize, count: usize, len: usize) -> TokenHistoryResult<std::ops::Range<usize>> {
+    if from > len {
+        return Err(TokenHistoryError::OutOfRange { from, len });
+    }
+    Ok(from..from.saturating_add(count).min(len))
+}
+
+/// One sequence's tokens in a device buffer
+///
+/// Owns the allocation `<name>.tokens`, of any memory type: it grows with
+/// a copy on the transfer queue.
+#[derive(Debug)]
+pub struct DeviceTokenHistory {
+    buffer: String,
+    capacity: usize,
+    len: usize,
+}
+
+impl DeviceTokenHistory {
+    /// Allocate room for `CHUNK_TOKENS` tokens
+    pub fn new(allocator: &mut MemoryAllocator, name: &str, memory_type_index: u32) -> TokenHistoryResult<Self> {
+        let buffer = allocator.allocate(
+            CHUNK_TOKENS as u64 * TOKEN_SIZE,
+            memory_type_index,
+            format!("{}.tokens", name),
+        )?;
+        Ok(Self {
+            buffer,
+            capacity: CHUNK_TOKENS,
+            len: 0,
+        })
+    }
+
+    /// Tokens in the history
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Tokens the buffer holds without growing
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Grow the buffer to hold `additional` more tokens
+    ///
+    /// Call before recording a step's appends so the buffer never grows
+    /// while submitted work uses it.
+    ///
+    /// # Safety Requirements
+    /// - transfer must use the device of allocator
+    /// - no pending work may use the buffer if it has to grow
+    pub unsafe fn reserve(
+        &mut self,
+        allocator: &mut MemoryAllocator,
+        transfer: &DataTransfer,
+        additional: usize,
+    ) -> TokenHistoryResult<()> {
+        let needed = self.len + additional;
+        if needed > self.capacity {
+            let capacity = needed.next_multiple_of(CHUNK_TOKENS);
+            // SAFETY: forwarded from the caller's guarantees
+            unsafe { allocator.resize_on_device(&self.buffer, capacity as u64 * TOKEN_SIZE, transfer) }?;
+            self.capacity = capacity;
+        }
+        Ok(())
+    }
+
+    /// Upload host tokens, e.g. the prompt, to the end of the history
+    ///
+    /// # Safety Requirements
+    /// - transfer must use the device of allocator
+    /// - no pending work may use the buffer
+    pub unsafe fn append_host(
+        &mut self,
+        allocator: &mut MemoryAllocator,
+        transfer: &DataTransfer,
+        tokens: &[u32],
+    ) -> TokenHistoryResult<()> {
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe { self.reserve(allocator, transfer, tokens.len()) }?;
+        let bytes: Vec<u8> = tokens.iter().flat_map(|t| t.to_le_bytes()).collect();
+        let view = allocator
+            .get_allocation(&self.buffer)?
+            .view(self.len as u64 * TOKEN_SIZE, bytes.len() as u64)?;
+        // SAFETY: transfer and view share a device and nothing uses the buffer
+        unsafe { transfer.copy_to_device(&bytes, &view) }?;
+        self.len += tokens.len();
+        Ok(())
+    }
+
+    /// Record appending the `count` tokens the sampler wrote to `sampled`
+    ///
+    /// The length advances at once; a barrier makes the copied tokens
+    /// visible to compute shaders and transfers recorded after it, such as
+    /// the next step's penalty pass.
+    ///
+    /// # Safety Requirements
+    /// - `cmd` must be in the recording state
+    /// - `reserve` must already have made room for `count` tokens
+    /// - `sampled` must stay alive until the command buffer completes
+    pub unsafe fn record_append(
+        &mut self,
+        device: &ash::Device,
+        cmd: vk::CommandBuffer,
+        allocator: &MemoryAllocator,
+        sampled: &BufferRange,
+        count: u32,
+    ) -> TokenHistoryResult<()> {
+        let size = u64::from(count) * TOKEN_SIZE;
+        let destination = allocator
+            .get_allocation(&self.buffer)?
+            .range()
+            .slice(self.len as u64 * TOKEN_SIZE, size)?;
+        sampled.slice(0, size)?;
+        if count == 0 {
+            return Ok(());
+        }
+        let region = vk::BufferCopy::default()
+            .src_offset(sampled.offset)
+            .dst_offset(destination.offset)
+            .size(size);
+        let written = vk::MemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::TRANSFER_READ);
+        // SAFETY: forwarded from the caller's guarantees; both regions were
+        // bounds-checked above
+        unsafe {
+            device.cmd_copy_buffer(cmd, sampled.buffer, destination.buffer, &[region]);
+            device.cmd_pipeline_barrier(
+                cmd,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[written],
+                &[],
+                &[],
+            );
+        }
+        self.len += count as usize;
+        Ok(())
+    }
+
+    /// Drop tokens past `len`, e.g. rejected speculative tokens
+    pub fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+
+    /// Range of the current tokens, for kernels taking a history binding
+    ///
+    /// The history must not be empty; descriptors cannot bind empty ranges.
+    pub fn range(&self, allocator: &MemoryAllocator) -> TokenHistoryResult<BufferRange> {
+        Ok(allocator
+            .get_allocation(&self.buffer)?
+            .range()
+            .slice(0, self.len as u64 * TOKEN_SIZE)?)
+    }
+
+    /// Read up to `count` tokens starting at `from` in one download
+    ///
+    /// # Safety Requirements
+    /// - transfer must use the device of allocator
+    /// - work appending to the history must have completed
+    pub unsafe fn export(
+        &self,
+        allocator: &MemoryAllocator,
+        transfer: &DataTransfer,
+        from: usize,
+        count: usize,
+    ) -> TokenHistoryResult<Vec<u32>> {
+        let span = span(from, count, self.len)?;
+        if span.is_empty() {
+            return Ok(Vec::new());
+        }
+        let size = span.len() as u64 * TOKEN_SIZE;
+        let view = allocator
+            .get_allocation(&self.buffer)?
+            .view(span.start as u64 * TOKEN_SIZE, size)?;
+        // SAFETY: forwarded from the caller's guarantees
+        let bytes = unsafe { transfer.copy_from_device(&view, size) }?;
+        Ok(bytes
+            .chunks_exact(TOKEN_SIZE as usize)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect())
+    }
+
+    /// Free the allocation
+    pub fn free(self, allocator: &mut MemoryAllocator) -> TokenHistoryResult<()> {
+        allocator.deallocate(&self.buffer)?;
+        Ok(())
+    }
+}
+
+/// A session's token history, readable from outside its decode loop
+///
+/// Implemented by whatever owns the `DeviceTokenHistory` together with the
+/// allocator and transfer needed to read it back.
+pub trait TokenSource: Send + Sync {
+    /// Tokens generated so far, prompt included
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Up to `count` tokens starting at `from`
+    fn export(&self, from: usize, count: usize) -> TokenHistoryResult<Vec<u32>>;
+}
+
+lazy_static! {
+    static ref SOURCES: Mutex<HashMap<String, Arc<dyn TokenSource>>> = Mutex::new(HashMap::new());
+}
+
+/// Make a session's history exportable, replacing any previous one
+pub fn register(session: &str, source: Arc<dyn TokenSource>) {
+    SOURCES.lock().insert(session.to_string(), source);
+}
+
+/// Stop exporting a session's history, e.g. when the session closes
+pub fn unregister(session: &str) -> bool {
+    SOURCES.lock().remove(session).is_some()
+}
+
+/// Up to `count` tokens of a session's history starting at `from`
+pub fn export(session: &str, from: usize, count: usize) -> TokenHistoryResult<Vec<u32>> {
+    // Read outside the lock: the export waits on a GPU download
+    let source = SOURCES
+        .lock()
+        .get(session)
+        .cloned()
+        .ok_or_else(|| TokenHistoryError::UnknownSession(session.to_string()))?;
+    source.export(from, count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_span_clamps_to_history() {
+        assert_eq!(span(2, 3, 10).unwrap(), 2..5);
+        assert_eq!(span(8, 5, 10).unwrap(), 8..10);
+        assert_eq!(span(10, 1, 10).unwrap(), 10..10);
+        assert_eq!(span(4, usize::MAX, 10).unwrap(), 4..10);
+        assert!(matches!(span(11, 1, 10), Err(TokenHistoryError::OutOfRange { from: 11, len: 10 })));
+    }
+
+    /// A history kept on the host, standing in for a decode loop
+    struct HostSource(Vec<u32>);
+
+    impl TokenSource for HostSource {
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+
+        fn export(&self, from: usize, count: usize) -> TokenHistoryResult<Vec<u32>> {
+            Ok(self.0[span(from, count, self.0.len())?].to_vec())
+        }
+    }
+
+    #[test]
+    fn test_export_by_session() {
+        register("token-history-test", Arc::new(HostSource(vec![1, 2, 3, 4])));
+        assert_eq!(export("token-history-test", 1, 2).unwrap(), vec![2, 3]);
+        assert!(unregister("token-history-test"));
+        assert!(matches!(
+            export("token-history-test", 0, 1),
+            Err(TokenHistoryError::UnknownSession(_))
+        ));
+    }
+}
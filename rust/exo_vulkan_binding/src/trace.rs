@@ -0,0 +1,262 @@
+//! Request trace ids
+//!
+//! A server running several inference requests at once needs to know which
+//! request a slow dispatch or a failed submission belonged to. Callers tag
+//! work with a trace id, either per thread for the duration of a request
+//! (`scope`, `set_thread_trace`) or per client namespace, so every call made
+//! on behalf of a session carries it (`set_client_trace`).
+//!
+//! The current id is appended to command buffer debug labels, so GPU
+//! captures show it, and keys the per-trace metrics: dispatches recorded,
+//! profiled GPU time and submission errors. Metrics are kept for the
+//! `MAX_TRACKED_TRACES` most recently active ids.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::time::Duration;
+
+use ash::vk;
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use thiserror::Error;
+
+/// Trace errors
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum TraceError {
+    #[error("Invalid trace id {0:?}")]
+    InvalidId(String),
+}
+
+pub type TraceResult<T> = Result<T, TraceError>;
+
+/// Longest accepted trace id, in bytes (W3C trace context ids are 32 hex digits)
+pub const MAX_TRACE_ID_LEN: usize = 128;
+
+/// Trace ids whose metrics are kept
+pub const MAX_TRACKED_TRACES: usize = 256;
+
+/// Metrics attributed to one trace id
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TraceStats {
+    pub dispatches: u64,
+    pub gpu_time_us: u64,
+    pub errors: u64,
+}
+
+#[derive(Default)]
+struct Tracked {
+    stats: HashMap<String, (TraceStats, u64)>,
+    /// Bumped on every update; the least recently updated id is evicted first
+    clock: u64,
+}
+
+impl Tracked {
+    fn update(&mut self, trace_id: String, apply: impl FnOnce(&mut TraceStats)) {
+        self.clock += 1;
+        let clock = self.clock;
+        if !self.stats.contains_key(&trace_id)
+            && self.stats.len() >= MAX_TRACKED_TRACES
+            && let Some(oldest) = self.stats.iter().min_by_key(|(_, (_, at))| *at).map(|(id, _)| id.clone())
+        {
+            self.stats.remove(&oldest);
+        }
+        let entry = self.stats.entry(trace_id).or_default();
+        apply(&mut entry.0);
+        entry.1 = clock;
+    }
+}
+
+thread_local! {
+    /// Trace id set on this thread
+    static THREAD_TRACE: RefCell<Option<String>> = const { RefCell::new(None) };
+    /// Client namespace the current call acts for
+    static CLIENT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+lazy_static! {
+    static ref CLIENT_TRACES: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
+    static ref TRACKED: Mutex<Tracked> = Mutex::new(Tracked::default());
+}
+
+/// Check a trace id is non-empty, short and printable
+pub fn validate(trace_id: &str) -> TraceResult<()> {
+    if trace_id.is_empty()
+        || trace_id.len() > MAX_TRACE_ID_LEN
+        || !trace_id.chars().all(|c| c.is_ascii_graphic())
+    {
+        return Err(TraceError::InvalidId(trace_id.to_string()));
+    }
+    Ok(())
+}
+
+/// Restores the previous thread trace id when dropped
+pub struct TraceScope {
+    previous: Option<String>,
+}
+
+impl Drop for TraceScope {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        THREAD_TRACE.with(|trace| *trace.borrow_mut() = previous);
+    }
+}
+
+/// Tag this thread's work with `trace_id` until the guard is dropped
+pub fn scope(trace_id: &str) -> TraceResult<TraceScope> {
+    validate(trace_id)?;
+    let previous = THREAD_TRACE.with(|trace| trace.borrow_mut().replace(trace_id.to_string()));
+    Ok(TraceScope { previous })
+}
+
+/// Tag this thread's work with `trace_id` until changed; `None` clears it
+pub fn set_thread_trace(trace_id: Option<&str>) -> TraceResult<()> {
+    if let Some(trace_id) = trace_id {
+        validate(trace_id)?;
+    }
+    THREAD_TRACE.with(|trace| *trace.borrow_mut() = trace_id.map(str::to_string));
+    Ok(())
+}
+
+/// Tag work done for `client` with `trace_id`; `None` clears it
+pub fn set_client_trace(client: &str, trace_id: Option<&str>) -> TraceResult<()> {
+    let mut traces = CLIENT_TRACES.lock();
+    match trace_id {
+        Some(trace_id) => {
+            validate(trace_id)?;
+            traces.insert(client.to_string(), trace_id.to_string());
+        }
+        None => {
+            traces.remove(client);
+        }
+    }
+    Ok(())
+}
+
+/// Mark the current call as acting for `client`, until `leave_client`
+pub fn enter_client(client: &str) {
+    CLIENT.with(|current| *current.borrow_mut() = Some(client.to_string()));
+}
+
+pub fn leave_client() {
+    CLIENT.with(|current| *current.borrow_mut() = None);
+}
+
+/// Trace id of the current work: the thread's, else its client's
+pub fn current() -> Option<String> {
+    THREAD_TRACE.with(|trace| trace.borrow().clone()).or_else(|| {
+        let client = CLIENT.with(|current| current.borrow().clone())?;
+        CLIENT_TRACES.lock().get(&client).cloned()
+    })
+}
+
+/// `name` with the current trace id appended, for debug labels and logs
+pub fn label(name: &str) -> String {
+    match current() {
+        Some(trace_id) => format!("{} [trace={}]", name, trace_id),
+        None => name.to_string(),
+    }
+}
+
+/// Open a debug label region named `label(name)` in `cmd`
+///
+/// # Safety Requirements
+/// - `cmd` must be in the recording state, on the device `debug_utils` was loaded for
+/// - The region must be closed with `end_label` in the same command buffer
+pub unsafe fn begin_label(debug_utils: &ash::ext::debug_utils::Device, cmd: vk::CommandBuffer, name: &str) {
+    // Trace ids are validated printable ASCII; only a NUL in name can fail
+    let name = CString::new(label(name)).unwrap_or_default();
+    let info = vk::DebugUtilsLabelEXT::default().label_name(&name);
+    // SAFETY: forwarded from the caller's guarantees
+    unsafe { debug_utils.cmd_begin_debug_utils_label(cmd, &info) };
+}
+
+/// Close the region opened by `begin_label`
+///
+/// # Safety Requirements
+/// - As for `begin_label`
+pub unsafe fn end_label(debug_utils: &ash::ext::debug_utils::Device, cmd: vk::CommandBuffer) {
+    // SAFETY: forwarded from the caller's guarantees
+    unsafe { debug_utils.cmd_end_debug_utils_label(cmd) };
+}
+
+fn record(apply: impl FnOnce(&mut TraceStats)) {
+    if let Some(trace_id) = current() {
+        TRACKED.lock().update(trace_id, apply);
+    }
+}
+
+/// Count a dispatch recorded for the current trace
+pub fn record_dispatch() {
+    record(|stats| stats.dispatches += 1);
+}
+
+/// Attribute profiled GPU execution time to the current trace
+pub fn record_gpu_time(execution: Duration) {
+    record(|stats| stats.gpu_time_us += execution.as_micros() as u64);
+}
+
+/// Count a failed submission or wait for the current trace
+pub fn record_error() {
+    record(|stats| stats.errors += 1);
+}
+
+/// Metrics of `trace_id`, if it is still tracked
+pub fn stats(trace_id: &str) -> Option<TraceStats> {
+    TRACKED.lock().stats.get(trace_id).map(|(stats, _)| *stats)
+}
+
+/// JSON: `{"trace_id":..,"dispatches":n,"gpu_time_us":n,"errors":n}`
+pub fn stats_json(trace_id: &str, stats: &TraceStats) -> String {
+    format!(
+        r#"{{"trace_id":{},"dispatches":{},"gpu_time_us":{},"errors":{}}}"#,
+        serde_json::Value::from(trace_id),
+        stats.dispatches,
+        stats.gpu_time_us,
+        stats.errors
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_thread_trace_wins_over_client_trace() {
+        set_client_trace("trace-test-client", Some("req-client")).unwrap();
+        enter_client("trace-test-client");
+        assert_eq!(label("decode"), "decode [trace=req-client]");
+        {
+            let _scope = scope("req-42").unwrap();
+            assert_eq!(current().as_deref(), Some("req-42"));
+            record_dispatch();
+            record_gpu_time(Duration::from_micros(1500));
+        }
+        assert_eq!(current().as_deref(), Some("req-client"));
+        leave_client();
+        assert_eq!(current(), None);
+        assert_eq!(
+            stats("req-42"),
+            Some(TraceStats {
+                dispatches: 1,
+                gpu_time_us: 1500,
+                errors: 0
+            })
+        );
+        assert!(matches!(scope("has space"), Err(TraceError::InvalidId(_))));
+    }
+
+    #[test]
+    fn test_least_recent_trace_evicted() {
+        let mut tracked = Tracked::default();
+        for i in 0..MAX_TRACKED_TRACES {
+            tracked.update(format!("t{}", i), |stats| stats.errors += 1);
+        }
+        // Refreshing t0 leaves t1 the least recently updated
+        tracked.update("t0".to_string(), |stats| stats.errors += 1);
+        tracked.update("new".to_string(), |stats| stats.errors += 1);
+        assert_eq!(tracked.stats.len(), MAX_TRACKED_TRACES);
+        assert!(!tracked.stats.contains_key("t1"));
+        assert_eq!(tracked.stats["t0"].0.errors, 2);
+    }
+}
@@ -1,11 +1,22 @@
 //! Vulkan data transfer operations
 //!
 //! Provides host ↔ device and device ↔ device data copying with proper synchronization.
+//!
+//! The blocking copies wait for the queue to go idle. `copy_to_device_async`
+//! instead returns a `TransferHandle` whose fence signals when the upload
+//! completes, so uploads can overlap with compute dispatches.
+
+use std::time::{Duration, Instant};
 
 use ash::vk;
 use thiserror::Error;
 
+use crate::command::Fence;
+use crate::events::{self, GpuEvent, TransferDirection};
+use crate::host_import::ImportedHostMemory;
 use crate::memory::AllocationInfo;
+use crate::staging::{StagingBuffer, StagingPool, StagingStats};
+use crate::strict;
 
 /// Transfer-related errors
 #[derive(Error, Debug)]
@@ -29,10 +40,14 @@ pub enum TransferError {
 pub type TransferResult<T> = Result<T, TransferError>;
 
 /// Manages buffer-to-buffer copy operations
+///
+/// Host copies go through a `StagingPool`, so repeated uploads and downloads
+/// of similar sizes reuse mapped staging buffers.
 pub struct DataTransfer {
     device: ash::Device,
     queue: vk::Queue,
     command_pool: vk::CommandPool,
+    staging: StagingPool,
 }
 
 impl DataTransfer {
@@ -42,22 +57,28 @@ impl DataTransfer {
     /// - device must be valid
     /// - queue must be valid and belong to a compute-capable queue family
     /// - command_pool must be valid and belong to the same queue family
+    /// - memory_properties must be those of the device's physical device
     pub fn new(
         device: ash::Device,
         queue: vk::Queue,
         command_pool: vk::CommandPool,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
     ) -> Self {
+        strict::non_null(queue, "transfer queue");
+        strict::non_null(command_pool, "transfer command pool");
+        let staging = StagingPool::new(device.clone(), *memory_properties);
         DataTransfer {
             device,
             queue,
             command_pool,
+            staging,
         }
     }
 
     /// Copy data from host to device memory
     ///
-    /// Allocates a temporary staging buffer, copies host data to it,
-    /// then records commands to transfer to device memory.
+    /// Copies host data into a pooled staging buffer, then records
+    /// commands to transfer it to device memory.
     ///
     /// # Arguments
     /// * `host_data` - Data to copy
@@ -72,6 +93,7 @@ impl DataTransfer {
         host_data: &[u8],
         device_allocation: &AllocationInfo,
     ) -> TransferResult<()> {
+        strict::live_allocation(device_allocation);
         if host_data.len() as u64 > device_allocation.size {
             return Err(TransferError::InvalidSize(format!(
                 "host data size {} > device allocation size {}",
@@ -84,174 +106,105 @@ impl DataTransfer {
             return Ok(()); // Nothing to copy
         }
 
-        // Create staging buffer for upload
-        // SAFETY:
-        //   - device is valid (from self)
-        //   - sizes are valid
-        let staging_buffer_info = vk::BufferCreateInfo::default()
-            .size(host_data.len() as u64)
-            .usage(vk::BufferUsageFlags::TRANSFER_SRC)
-            .sharing_mode(vk::SharingMode::EXCLUSIVE);
-
-        let staging_buffer = self
-            .device
-            .create_buffer(&staging_buffer_info, None)
-            .map_err(|e| TransferError::VulkanError(e))?;
-
-        // Get memory requirements for staging buffer
-        // SAFETY:
-        //   - staging_buffer is valid (just created)
-        let staging_mem_requirements = self
-            .device
-            .get_buffer_memory_requirements(staging_buffer);
-
-        // Allocate HOST_VISIBLE memory for staging
-        // Note: In real implementation, this would use the allocator
-        // For now, we document the requirement
-        let staging_memory_info = vk::MemoryAllocateInfo::default()
-            .allocation_size(staging_mem_requirements.size)
-            .memory_type_index(0); // Would be found by type filter
-
-        let staging_memory = match self.device.allocate_memory(&staging_memory_info, None) {
-            Ok(mem) => mem,
-            Err(e) => {
-                self.device.destroy_buffer(staging_buffer, None);
-                return Err(TransferError::VulkanError(e));
-            }
-        };
+        crate::diagnostics::note_upload(host_data.len());
+        let started = Instant::now();
 
-        // Bind staging buffer to memory
-        if let Err(e) = self
-            .device
-            .bind_buffer_memory(staging_buffer, staging_memory, 0)
-        {
-            self.device.destroy_buffer(staging_buffer, None);
-            self.device.free_memory(staging_memory, None);
-            return Err(TransferError::VulkanError(e));
-        }
+        let mut staging = self.acquire_staging(host_data.len() as u64)?;
+        staging.mapped()[..host_data.len()].copy_from_slice(host_data);
 
-        // Map staging memory and copy data
-        // SAFETY:
-        //   - staging_memory is valid and allocated
-        //   - host_data is valid
-        match self.device.map_memory(
-            staging_memory,
-            0,
-            vk::WHOLE_SIZE,
-            vk::MemoryMapFlags::empty(),
-        ) {
-            Ok(ptr) => {
-                // Copy host data to staging buffer
-                std::ptr::copy_nonoverlapping(
-                    host_data.as_ptr(),
-                    ptr as *mut u8,
-                    host_data.len(),
-                );
-                self.device.unmap_memory(staging_memory);
-            }
-            Err(e) => {
-                self.device.destroy_buffer(staging_buffer, None);
-                self.device.free_memory(staging_memory, None);
-                return Err(TransferError::VulkanError(e));
-            }
-        }
-
-        // Allocate and record copy command
-        let alloc_info = vk::CommandBufferAllocateInfo::default()
-            .command_pool(self.command_pool)
-            .level(vk::CommandBufferLevel::PRIMARY)
-            .command_buffer_count(1);
-
-        let cmd_buffers = match self.device.allocate_command_buffers(&alloc_info) {
-            Ok(bufs) => bufs,
-            Err(e) => {
-                self.device.destroy_buffer(staging_buffer, None);
-                self.device.free_memory(staging_memory, None);
-                return Err(TransferError::VulkanError(e));
-            }
+        // SAFETY: staging and device_allocation.buffer are valid and the
+        // region lies within both (checked above)
+        let copied = unsafe {
+            self.submit_and_wait(|cmd_buffer| {
+                self.record_upload(cmd_buffer, &staging, device_allocation, host_data.len() as u64)
+            })
         };
+        self.staging.release(staging);
+        copied?;
 
-        let cmd_buffer = cmd_buffers[0];
-
-        // Record copy command
-        // SAFETY:
-        //   - cmd_buffer is valid (just allocated)
-        //   - staging_buffer and device_allocation.buffer are valid
-        let begin_info = vk::CommandBufferBeginInfo::default()
-            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
-
-        if let Err(e) = self.device.begin_command_buffer(cmd_buffer, &begin_info) {
-            self.device.destroy_buffer(staging_buffer, None);
-            self.device.free_memory(staging_memory, None);
-            return Err(TransferError::VulkanError(e));
-        }
-
-        // Record copy region
-        let region = vk::BufferCopy::default()
-            .src_offset(0)
-            .dst_offset(0)
-            .size(host_data.len() as u64);
-
-        self.device.cmd_copy_buffer(
-            cmd_buffer,
-            staging_buffer,
-            device_allocation.buffer,
-            &[region],
-        );
-
-        // Record pipeline barrier for device access
-        let memory_barrier = vk::MemoryBarrier::default()
-            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
-            .dst_access_mask(vk::AccessFlags::SHADER_READ);
+        emit_transfer(TransferDirection::HostToDevice, host_data.len() as u64, started);
+        Ok(())
+    }
 
-        self.device.cmd_pipeline_barrier(
-            cmd_buffer,
-            vk::PipelineStageFlags::TRANSFER,
-            vk::PipelineStageFlags::COMPUTE_SHADER,
-            vk::DependencyFlags::empty(),
-            &[memory_barrier],
-            &[],
-            &[],
-        );
-
-        // End recording
-        if let Err(e) = self.device.end_command_buffer(cmd_buffer) {
-            self.device.destroy_buffer(staging_buffer, None);
-            self.device.free_memory(staging_memory, None);
-            return Err(TransferError::VulkanError(e));
+    /// Start copying data from host to device memory without waiting for it
+    ///
+    /// `host_data` is copied into a pooled staging buffer before this
+    /// returns, so it may be reused at once; the GPU copy runs in the
+    /// background and signals the returned handle's fence. `on_complete`
+    /// runs once the handle observes completion (in `poll`, `wait` or drop).
+    ///
+    /// # Arguments
+    /// * `host_data` - Data to copy
+    /// * `device_allocation` - Destination device allocation
+    /// * `on_complete` - Optional callback receiving the outcome
+    ///
+    /// # Safety Requirements
+    /// - device_allocation must be valid and allocated
+    /// - device_allocation must stay alive, and not be read by other work
+    ///   submitted before it, until the handle completes
+    pub unsafe fn copy_to_device_async(
+        &self,
+        host_data: &[u8],
+        device_allocation: &AllocationInfo,
+        on_complete: Option<TransferCallback>,
+    ) -> TransferResult<TransferHandle<'_>> {
+        strict::live_allocation(device_allocation);
+        if host_data.len() as u64 > device_allocation.size {
+            return Err(TransferError::InvalidSize(format!(
+                "host data size {} > device allocation size {}",
+                host_data.len(),
+                device_allocation.size
+            )));
         }
 
-        // Submit and wait
-        // SAFETY:
-        //   - cmd_buffer is valid and properly recorded
-        //   - queue is valid
-        let cmd_buffers = [cmd_buffer];
-        let submit_info = vk::SubmitInfo::default().command_buffers(&cmd_buffers);
-
-        if let Err(e) = self.device.queue_submit(self.queue, &[submit_info], vk::Fence::null()) {
-            self.device.destroy_buffer(staging_buffer, None);
-            self.device.free_memory(staging_memory, None);
-            return Err(TransferError::VulkanError(e));
+        let fence = Fence::new(self.device.clone(), host_data.is_empty())
+            .map_err(|e| TransferError::SynchronizationFailed(e.to_string()))?;
+        let mut handle = TransferHandle {
+            transfer: self,
+            fence,
+            cmd_buffer: None,
+            staging: None,
+            bytes: host_data.len() as u64,
+            started: Instant::now(),
+            on_complete,
+            done: false,
+        };
+        if host_data.is_empty() {
+            return Ok(handle); // Nothing to copy; the fence starts signaled
         }
 
-        // Wait for completion
-        if let Err(e) = self.device.queue_wait_idle(self.queue) {
-            self.device.destroy_buffer(staging_buffer, None);
-            self.device.free_memory(staging_memory, None);
-            return Err(TransferError::VulkanError(e));
+        crate::diagnostics::note_upload(host_data.len());
+        let mut staging = self.acquire_staging(host_data.len() as u64)?;
+        staging.mapped()[..host_data.len()].copy_from_slice(host_data);
+
+        // SAFETY: staging and device_allocation.buffer are valid and the
+        // region lies within both (checked above)
+        let submitted = unsafe {
+            self.submit(
+                |cmd_buffer| self.record_upload(cmd_buffer, &staging, device_allocation, host_data.len() as u64),
+                handle.fence.raw(),
+            )
+        };
+        match submitted {
+            Ok(cmd_buffer) => {
+                handle.cmd_buffer = Some(cmd_buffer);
+                handle.staging = Some(staging);
+                Ok(handle)
+            }
+            Err(e) => {
+                self.staging.release(staging);
+                // Nothing was submitted; the callback is dropped unrun
+                handle.on_complete = None;
+                handle.done = true;
+                Err(e)
+            }
         }
-
-        // Clean up temporary resources
-        self.device.destroy_buffer(staging_buffer, None);
-        self.device.free_memory(staging_memory, None);
-
-        Ok(())
     }
 
     /// Copy data from device to host memory
     ///
-    /// Records commands to copy from device to temporary staging buffer,
-    /// then maps and copies to host memory.
+    /// Records commands to copy from device to a pooled staging buffer,
+    /// then copies its mapping to host memory.
     ///
     /// # Arguments
     /// * `device_allocation` - Source device allocation
@@ -268,6 +221,7 @@ impl DataTransfer {
         device_allocation: &AllocationInfo,
         size: u64,
     ) -> TransferResult<Vec<u8>> {
+        strict::live_allocation(device_allocation);
         if size > device_allocation.size {
             return Err(TransferError::InvalidSize(format!(
                 "copy size {} > device allocation size {}",
@@ -279,149 +233,52 @@ impl DataTransfer {
             return Ok(Vec::new());
         }
 
-        // Create staging buffer for download
-        // SAFETY:
-        //   - device is valid
-        let staging_buffer_info = vk::BufferCreateInfo::default()
-            .size(size)
-            .usage(vk::BufferUsageFlags::TRANSFER_DST)
-            .sharing_mode(vk::SharingMode::EXCLUSIVE);
-
-        let staging_buffer = self
-            .device
-            .create_buffer(&staging_buffer_info, None)
-            .map_err(|e| TransferError::VulkanError(e))?;
-
-        // Get memory requirements
-        let staging_mem_requirements = self
-            .device
-            .get_buffer_memory_requirements(staging_buffer);
-
-        // Allocate HOST_VISIBLE memory
-        let staging_memory_info = vk::MemoryAllocateInfo::default()
-            .allocation_size(staging_mem_requirements.size)
-            .memory_type_index(0);
-
-        let staging_memory = match self.device.allocate_memory(&staging_memory_info, None) {
-            Ok(mem) => mem,
-            Err(e) => {
-                self.device.destroy_buffer(staging_buffer, None);
-                return Err(TransferError::VulkanError(e));
-            }
-        };
+        let started = Instant::now();
 
-        // Bind staging buffer
-        if let Err(e) = self
-            .device
-            .bind_buffer_memory(staging_buffer, staging_memory, 0)
+        if device_allocation
+            .memory_properties
+            .contains(vk::MemoryPropertyFlags::HOST_VISIBLE)
         {
-            self.device.destroy_buffer(staging_buffer, None);
-            self.device.free_memory(staging_memory, None);
-            return Err(TransferError::VulkanError(e));
+            crate::diagnostics::record(
+                crate::diagnostics::SlowPath::UnmappedHostVisibleRead,
+                &format!("{} bytes from {}", size, device_allocation.handle_id),
+            );
         }
 
-        // Allocate command buffer for copy
-        let alloc_info = vk::CommandBufferAllocateInfo::default()
-            .command_pool(self.command_pool)
-            .level(vk::CommandBufferLevel::PRIMARY)
-            .command_buffer_count(1);
+        let mut staging = self.acquire_staging(size)?;
+
+        // SAFETY: device_allocation.buffer and staging are valid and the
+        // region lies within both (checked above)
+        let copied = unsafe {
+            self.submit_and_wait(|cmd_buffer| {
+                // Make device data available to the copy
+                let memory_barrier = vk::MemoryBarrier::default()
+                    .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::TRANSFER_READ);
+                self.device.cmd_pipeline_barrier(
+                    cmd_buffer,
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::DependencyFlags::empty(),
+                    &[memory_barrier],
+                    &[],
+                    &[],
+                );
 
-        let cmd_buffers = match self.device.allocate_command_buffers(&alloc_info) {
-            Ok(bufs) => bufs,
-            Err(e) => {
-                self.device.destroy_buffer(staging_buffer, None);
-                self.device.free_memory(staging_memory, None);
-                return Err(TransferError::VulkanError(e));
-            }
+                let region = vk::BufferCopy::default()
+                    .src_offset(device_allocation.offset)
+                    .dst_offset(0)
+                    .size(size);
+                self.device
+                    .cmd_copy_buffer(cmd_buffer, device_allocation.buffer, staging.buffer(), &[region]);
+            })
         };
+        let data = copied.map(|()| staging.mapped()[..size as usize].to_vec());
+        self.staging.release(staging);
+        let data = data?;
 
-        let cmd_buffer = cmd_buffers[0];
-
-        // Record copy command
-        let begin_info = vk::CommandBufferBeginInfo::default()
-            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
-
-        if let Err(e) = self.device.begin_command_buffer(cmd_buffer, &begin_info) {
-            self.device.destroy_buffer(staging_buffer, None);
-            self.device.free_memory(staging_memory, None);
-            return Err(TransferError::VulkanError(e));
-        }
-
-        // Record memory barrier to make device data available
-        let memory_barrier = vk::MemoryBarrier::default()
-            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
-            .dst_access_mask(vk::AccessFlags::TRANSFER_READ);
-
-        self.device.cmd_pipeline_barrier(
-            cmd_buffer,
-            vk::PipelineStageFlags::COMPUTE_SHADER,
-            vk::PipelineStageFlags::TRANSFER,
-            vk::DependencyFlags::empty(),
-            &[memory_barrier],
-            &[],
-            &[],
-        );
-
-        // Record copy
-        let region = vk::BufferCopy::default()
-            .src_offset(0)
-            .dst_offset(0)
-            .size(size);
-
-        self.device.cmd_copy_buffer(
-            cmd_buffer,
-            device_allocation.buffer,
-            staging_buffer,
-            &[region],
-        );
-
-        // End recording
-        if let Err(e) = self.device.end_command_buffer(cmd_buffer) {
-            self.device.destroy_buffer(staging_buffer, None);
-            self.device.free_memory(staging_memory, None);
-            return Err(TransferError::VulkanError(e));
-        }
-
-        // Submit and wait
-        let cmd_buffers = [cmd_buffer];
-        let submit_info = vk::SubmitInfo::default().command_buffers(&cmd_buffers);
-
-        if let Err(e) = self.device.queue_submit(self.queue, &[submit_info], vk::Fence::null()) {
-            self.device.destroy_buffer(staging_buffer, None);
-            self.device.free_memory(staging_memory, None);
-            return Err(TransferError::VulkanError(e));
-        }
-
-        if let Err(e) = self.device.queue_wait_idle(self.queue) {
-            self.device.destroy_buffer(staging_buffer, None);
-            self.device.free_memory(staging_memory, None);
-            return Err(TransferError::VulkanError(e));
-        }
-
-        // Map and read data
-        match self.device.map_memory(
-            staging_memory,
-            0,
-            vk::WHOLE_SIZE,
-            vk::MemoryMapFlags::empty(),
-        ) {
-            Ok(ptr) => {
-                let mut data = vec![0u8; size as usize];
-                std::ptr::copy_nonoverlapping(ptr as *const u8, data.as_mut_ptr(), size as usize);
-                self.device.unmap_memory(staging_memory);
-
-                // Clean up
-                self.device.destroy_buffer(staging_buffer, None);
-                self.device.free_memory(staging_memory, None);
-
-                Ok(data)
-            }
-            Err(e) => {
-                self.device.destroy_buffer(staging_buffer, None);
-                self.device.free_memory(staging_memory, None);
-                Err(TransferError::VulkanError(e))
-            }
-        }
+        emit_transfer(TransferDirection::DeviceToHost, size, started);
+        Ok(data)
     }
 
     /// Copy data directly between device buffers
@@ -440,6 +297,9 @@ impl DataTransfer {
         dst: &AllocationInfo,
         size: u64,
     ) -> TransferResult<()> {
+        strict::live_allocation(src);
+        strict::live_allocation(dst);
+        strict::not_aliased(src, dst, size);
         if size > src.size || size > dst.size {
             return Err(TransferError::InvalidSize(
                 "copy size exceeds allocation size".to_string(),
@@ -450,53 +310,282 @@ impl DataTransfer {
             return Ok(());
         }
 
-        // Allocate command buffer
+        let started = Instant::now();
+        let region = vk::BufferCopy::default()
+            .src_offset(src.offset)
+            .dst_offset(dst.offset)
+            .size(size);
+        let record = |cmd_buffer| {
+            // SAFETY: cmd_buffer is recording; both allocations belong to
+            // self.device and hold the region (forwarded from the caller's
+            // guarantees and checked above)
+            unsafe { self.device.cmd_copy_buffer(cmd_buffer, src.buffer, dst.buffer, &[region]) };
+        };
+        // SAFETY: `record` only records the copy above
+        unsafe { self.submit_and_wait(record) }?;
+
+        emit_transfer(TransferDirection::DeviceToDevice, size, started);
+        Ok(())
+    }
+
+    /// Copy host bytes imported as device-visible memory into a device allocation
+    ///
+    /// The GPU reads the source pages directly, so unlike `copy_to_device`
+    /// no staging buffer is filled on the CPU.
+    ///
+    /// # Arguments
+    /// * `src` - Imported host memory
+    /// * `offset` - Byte offset of the data within `src`
+    /// * `dst` - Destination allocation
+    /// * `size` - Bytes to copy
+    ///
+    /// # Safety Requirements
+    /// - src must have been imported on this transfer's device
+    /// - dst must be valid and not in use by other work
+    pub unsafe fn copy_from_imported(
+        &self,
+        src: &ImportedHostMemory,
+        offset: u64,
+        dst: &AllocationInfo,
+        size: u64,
+    ) -> TransferResult<()> {
+        strict::live_allocation(dst);
+        if size > dst.size || offset.checked_add(size).is_none_or(|end| end > src.len()) {
+            return Err(TransferError::InvalidSize(
+                "copy size exceeds imported range or allocation size".to_string(),
+            ));
+        }
+
+        if size == 0 {
+            return Ok(());
+        }
+
+        let started = Instant::now();
         let alloc_info = vk::CommandBufferAllocateInfo::default()
             .command_pool(self.command_pool)
             .level(vk::CommandBufferLevel::PRIMARY)
             .command_buffer_count(1);
+        // SAFETY: command_pool belongs to self.device (guaranteed by `new`)
+        let cmd_buffer = unsafe { self.device.allocate_command_buffers(&alloc_info) }
+            .map_err(TransferError::VulkanError)?[0];
+
+        // SAFETY: cmd_buffer was just allocated; src windows and dst belong to
+        // self.device and the regions lie within both (checked above)
+        let recorded = unsafe {
+            (|| {
+                let begin_info = vk::CommandBufferBeginInfo::default()
+                    .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+                self.device.begin_command_buffer(cmd_buffer, &begin_info)?;
+                // A range can straddle import windows; each window is its own buffer
+                for (buffer, region) in src.copy_regions(offset, size, dst.offset) {
+                    self.device.cmd_copy_buffer(cmd_buffer, buffer, dst.buffer, &[region]);
+                }
+                self.device.end_command_buffer(cmd_buffer)?;
+
+                let cmd_buffers = [cmd_buffer];
+                let submit_info = vk::SubmitInfo::default().command_buffers(&cmd_buffers);
+                self.device.queue_submit(self.queue, &[submit_info], vk::Fence::null())?;
+                self.device.queue_wait_idle(self.queue)
+            })()
+        };
+        // SAFETY: the queue is idle or the buffer was never submitted
+        unsafe { self.device.free_command_buffers(self.command_pool, &[cmd_buffer]) };
+        recorded.map_err(TransferError::VulkanError)?;
 
-        let cmd_buffers = self
-            .device
-            .allocate_command_buffers(&alloc_info)
-            .map_err(|e| TransferError::VulkanError(e))?;
-
-        let cmd_buffer = cmd_buffers[0];
+        emit_transfer(TransferDirection::HostToDevice, size, started);
+        Ok(())
+    }
+    /// Counters of the staging buffer pool
+    pub fn staging_stats(&self) -> StagingStats {
+        self.staging.stats()
+    }
 
-        // Record copy
-        let begin_info = vk::CommandBufferBeginInfo::default()
-            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+    /// Free the idle staging buffers, e.g. under memory pressure
+    pub fn trim_staging(&self) {
+        self.staging.trim();
+    }
 
-        self.device
-            .begin_command_buffer(cmd_buffer, &begin_info)
-            .map_err(|e| TransferError::VulkanError(e))?;
+    fn acquire_staging(&self, bytes: u64) -> TransferResult<StagingBuffer> {
+        self.staging
+            .acquire(bytes)
+            .map_err(|e| TransferError::StagingFailed(e.to_string()))
+    }
 
+    /// Record a staging → device upload of `size` bytes, made visible to compute shaders
+    ///
+    /// # Safety Requirements
+    /// - cmd_buffer must be recording; both buffers must hold the region
+    unsafe fn record_upload(
+        &self,
+        cmd_buffer: vk::CommandBuffer,
+        staging: &StagingBuffer,
+        device_allocation: &AllocationInfo,
+        size: u64,
+    ) {
         let region = vk::BufferCopy::default()
             .src_offset(0)
-            .dst_offset(0)
+            .dst_offset(device_allocation.offset)
             .size(size);
+        let memory_barrier = vk::MemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ);
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe {
+            self.device
+                .cmd_copy_buffer(cmd_buffer, staging.buffer(), device_allocation.buffer, &[region]);
+            self.device.cmd_pipeline_barrier(
+                cmd_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[memory_barrier],
+                &[],
+                &[],
+            );
+        }
+    }
+
+    /// Record one command buffer with `record`, submit it and wait for the queue
+    ///
+    /// # Safety Requirements
+    /// - Everything `record` records must be valid on self.device
+    unsafe fn submit_and_wait(&self, record: impl FnOnce(vk::CommandBuffer)) -> TransferResult<()> {
+        // SAFETY: forwarded from the caller's guarantees
+        let cmd_buffer = unsafe { self.submit(record, vk::Fence::null()) }?;
+        // SAFETY: self.queue belongs to self.device (guaranteed by `new`)
+        let waited = unsafe { self.device.queue_wait_idle(self.queue) };
+        // SAFETY: the queue is idle (or lost, which completes all work)
+        unsafe { self.device.free_command_buffers(self.command_pool, &[cmd_buffer]) };
+        waited.map_err(TransferError::VulkanError)
+    }
+
+    /// Record one command buffer with `record` and submit it, signaling `fence`
+    ///
+    /// Returns the submitted buffer for the caller to free once it completes;
+    /// on error nothing is left to free.
+    ///
+    /// # Safety Requirements
+    /// - Everything `record` records must be valid on self.device
+    /// - fence must be null or unsignaled and belong to self.device
+    unsafe fn submit(&self, record: impl FnOnce(vk::CommandBuffer), fence: vk::Fence) -> TransferResult<vk::CommandBuffer> {
+        let alloc_info = vk::CommandBufferAllocateInfo::default()
+            .command_pool(self.command_pool)
+            .level(vk::CommandBufferLevel::PRIMARY)
+            .command_buffer_count(1);
+        // SAFETY: command_pool belongs to self.device (guaranteed by `new`)
+        let cmd_buffer = unsafe { self.device.allocate_command_buffers(&alloc_info) }
+            .map_err(TransferError::VulkanError)?[0];
+
+        // SAFETY: cmd_buffer was just allocated; the recorded commands are
+        // valid (forwarded from the caller's guarantees)
+        let submitted = unsafe {
+            (|| {
+                let begin_info = vk::CommandBufferBeginInfo::default()
+                    .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+                self.device.begin_command_buffer(cmd_buffer, &begin_info)?;
+                record(cmd_buffer);
+                self.device.end_command_buffer(cmd_buffer)?;
+
+                let cmd_buffers = [cmd_buffer];
+                let submit_info = vk::SubmitInfo::default().command_buffers(&cmd_buffers);
+                self.device.queue_submit(self.queue, &[submit_info], fence)
+            })()
+        };
+        if let Err(e) = submitted {
+            // SAFETY: the buffer was never submitted
+            unsafe { self.device.free_command_buffers(self.command_pool, &[cmd_buffer]) };
+            return Err(TransferError::VulkanError(e));
+        }
+        Ok(cmd_buffer)
+    }
+}
 
-        self.device
-            .cmd_copy_buffer(cmd_buffer, src.buffer, dst.buffer, &[region]);
+/// Callback run with the outcome of an asynchronous transfer
+pub type TransferCallback = Box<dyn FnOnce(&TransferResult<()>) + Send>;
+
+/// An upload started by `DataTransfer::copy_to_device_async`
+///
+/// Holds the fence, command buffer and staging buffer of the copy and
+/// releases them once it observes the fence signaled. Dropping an
+/// unfinished handle waits for the copy.
+pub struct TransferHandle<'a> {
+    transfer: &'a DataTransfer,
+    fence: Fence,
+    cmd_buffer: Option<vk::CommandBuffer>,
+    staging: Option<StagingBuffer>,
+    bytes: u64,
+    started: Instant,
+    on_complete: Option<TransferCallback>,
+    done: bool,
+}
 
-        self.device
-            .end_command_buffer(cmd_buffer)
-            .map_err(|e| TransferError::VulkanError(e))?;
+impl TransferHandle<'_> {
+    /// Bytes being copied
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
 
-        // Submit
-        let cmd_buffers = [cmd_buffer];
-        let submit_info = vk::SubmitInfo::default().command_buffers(&cmd_buffers);
+    /// Whether the copy has completed, without blocking
+    pub fn poll(&mut self) -> TransferResult<bool> {
+        self.wait_ns(0)
+    }
+
+    /// Wait up to `timeout` for the copy; false if it is still running
+    pub fn wait(&mut self, timeout: Duration) -> TransferResult<bool> {
+        self.wait_ns(u64::try_from(timeout.as_nanos()).unwrap_or(u64::MAX))
+    }
+
+    fn wait_ns(&mut self, timeout_ns: u64) -> TransferResult<bool> {
+        if self.done {
+            return Ok(true);
+        }
+        let result = match self.fence.wait(timeout_ns) {
+            Ok(false) => return Ok(false),
+            Ok(true) => Ok(()),
+            Err(e) => Err(TransferError::SynchronizationFailed(e.to_string())),
+        };
+        self.complete(&result);
+        result.map(|()| true)
+    }
 
-        self.device
-            .queue_submit(self.queue, &[submit_info], vk::Fence::null())
-            .map_err(|e| TransferError::VulkanError(e))?;
+    /// Release the copy's resources and run the callback
+    fn complete(&mut self, result: &TransferResult<()>) {
+        self.done = true;
+        let transfer = self.transfer;
+        if let Some(cmd_buffer) = self.cmd_buffer.take() {
+            // SAFETY: the fence signaled (or the device was lost, which
+            // completes all work), so the buffer is no longer pending
+            unsafe { transfer.device.free_command_buffers(transfer.command_pool, &[cmd_buffer]) };
+        }
+        if let Some(staging) = self.staging.take() {
+            transfer.staging.release(staging);
+        }
+        if result.is_ok() && self.bytes > 0 {
+            emit_transfer(TransferDirection::HostToDevice, self.bytes, self.started);
+        }
+        if let Some(callback) = self.on_complete.take() {
+            callback(result);
+        }
+    }
+}
 
-        self.device
-            .queue_wait_idle(self.queue)
-            .map_err(|e| TransferError::VulkanError(e))
+impl Drop for TransferHandle<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = self.wait_ns(u64::MAX) {
+            log::warn!("Waiting for an asynchronous upload failed: {}", e);
+        }
     }
 }
 
+/// Publish a completed transfer on the event bus
+fn emit_transfer(direction: TransferDirection, bytes: u64, started: Instant) {
+    events::emit(GpuEvent::Transfer {
+        direction,
+        bytes,
+        duration: started.elapsed(),
+    });
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
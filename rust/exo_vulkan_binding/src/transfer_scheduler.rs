@@ -0,0 +1,521 @@
+//! Priority scheduling for uploads
+//!
+//! Weight prefetches for upcoming layers share the transfer path with the
+//! activations the current decode step is waiting on. Background uploads are
+//! split into chunks and critical uploads are serviced between chunks, so a
+//! prefetch delays a token-critical copy by at most one chunk.
+//!
+//! With an adaptive `ChunkPolicy`, chunk sizes follow measured throughput:
+//! each chunk is sized to take about `target_chunk_time` at the recent
+//! bandwidth, so fast links load weights in big chunks while slow ones keep
+//! the preemption latency bounded. While the app flags UI responsiveness
+//! (`set_ui_responsive`), the shorter `responsive_chunk_time` applies.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+use crate::memory::AllocationInfo;
+use crate::transfer::{DataTransfer, TransferError, TransferResult};
+
+/// Default background chunk: small enough to finish well within a decode step
+pub const DEFAULT_CHUNK_SIZE: u64 = 1 << 20;
+
+/// Chunks are multiples of this many bytes
+pub const CHUNK_GRANULARITY: u64 = 64 << 10;
+
+/// Weight of the newest sample in the throughput estimate
+const THROUGHPUT_WEIGHT: f64 = 0.25;
+
+static UI_RESPONSIVE: AtomicBool = AtomicBool::new(false);
+
+/// Flag (or clear) that the UI needs the GPU's attention, e.g. during scrolling
+/// or animations; adaptive schedulers switch to short chunks while it is set
+pub fn set_ui_responsive(responsive: bool) {
+    UI_RESPONSIVE.store(responsive, Ordering::Relaxed);
+}
+
+/// Whether `set_ui_responsive` is in effect
+pub fn ui_responsive() -> bool {
+    UI_RESPONSIVE.load(Ordering::Relaxed)
+}
+
+/// How background chunks are sized
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkPolicy {
+    pub min_chunk: u64,
+    pub max_chunk: u64,
+    /// Time one chunk should take at the measured throughput
+    pub target_chunk_time: Duration,
+    /// Target while the UI is flagged responsive
+    pub responsive_chunk_time: Duration,
+}
+
+impl ChunkPolicy {
+    /// Always `chunk_size` bytes
+    pub fn fixed(chunk_size: u64) -> Self {
+        let chunk_size = chunk_size.max(1);
+        Self {
+            min_chunk: chunk_size,
+            max_chunk: chunk_size,
+            target_chunk_time: Duration::ZERO,
+            responsive_chunk_time: Duration::ZERO,
+        }
+    }
+}
+
+impl Default for ChunkPolicy {
+    fn default() -> Self {
+        Self {
+            min_chunk: 256 << 10,
+            max_chunk: 16 << 20,
+            target_chunk_time: Duration::from_millis(4),
+            responsive_chunk_time: Duration::from_millis(1),
+        }
+    }
+}
+
+/// Adapts the chunk size to measured throughput
+#[derive(Clone, Debug)]
+pub struct ChunkSizer {
+    policy: ChunkPolicy,
+    chunk_size: u64,
+    /// Smoothed bytes per second
+    throughput: Option<f64>,
+}
+
+impl ChunkSizer {
+    pub fn new(policy: ChunkPolicy) -> Self {
+        let chunk_size = DEFAULT_CHUNK_SIZE.clamp(policy.min_chunk, policy.max_chunk);
+        Self {
+            policy,
+            chunk_size,
+            throughput: None,
+        }
+    }
+
+    /// Size of the next chunk
+    pub fn chunk_size(&self) -> u64 {
+        self.chunk_size
+    }
+
+    /// Smoothed throughput in bytes per second, once measured
+    pub fn throughput(&self) -> Option<f64> {
+        self.throughput
+    }
+
+    /// Record a chunk of `bytes` that took `elapsed` and resize the next one
+    pub fn record(&mut self, bytes: u64, elapsed: Duration, ui_responsive: bool) {
+        if self.policy.min_chunk == self.policy.max_chunk || elapsed.is_zero() {
+            return;
+        }
+        let sample = bytes as f64 / elapsed.as_secs_f64();
+        let throughput = match self.throughput {
+            Some(estimate) => estimate * (1.0 - THROUGHPUT_WEIGHT) + sample * THROUGHPUT_WEIGHT,
+            None => sample,
+        };
+        self.throughput = Some(throughput);
+
+        let target = if ui_responsive {
+            self.policy.responsive_chunk_time
+        } else {
+            self.policy.target_chunk_time
+        };
+        let ideal = (throughput * target.as_secs_f64()) as u64;
+        let granular = (ideal / CHUNK_GRANULARITY).max(1) * CHUNK_GRANULARITY;
+        self.chunk_size = granular.clamp(self.policy.min_chunk, self.policy.max_chunk);
+    }
+}
+
+/// Snapshot of scheduler state
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TransferMetrics {
+    /// Queued critical transfers
+    pub critical_depth: usize,
+    /// Queued background transfers
+    pub background_depth: usize,
+    /// Bytes not yet uploaded across both queues
+    pub queued_bytes: u64,
+    /// Size of the next background chunk
+    pub chunk_size: u64,
+    /// Smoothed background throughput, bytes per second
+    pub throughput: Option<f64>,
+    pub chunks_uploaded: u64,
+    pub bytes_uploaded: u64,
+}
+
+/// Identifier returned by `TransferScheduler::submit`
+pub type TransferId = u64;
+
+/// Urgency of a transfer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransferPriority {
+    /// Activations the current step is waiting on; never chunked
+    Critical,
+    /// Weight prefetches; chunked and preempted by critical transfers
+    Background,
+}
+
+/// Performs the individual copies; implemented by `DataTransfer`
+pub trait TransferExecutor {
+    /// Upload `data` to the start of `destination`
+    ///
+    /// # Safety Requirements
+    /// - Same as `DataTransfer::copy_to_device`
+    unsafe fn upload(&self, data: &[u8], destination: &AllocationInfo) -> TransferResult<()>;
+}
+
+impl TransferExecutor for DataTransfer {
+    unsafe fn upload(&self, data: &[u8], destination: &AllocationInfo) -> TransferResult<()> {
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe { self.copy_to_device(data, destination) }
+    }
+}
+
+/// A queued upload and how far it has got
+struct PendingUpload {
+    id: TransferId,
+    priority: TransferPriority,
+    data: Arc<[u8]>,
+    destination: AllocationInfo,
+    /// Bytes already uploaded
+    done: u64,
+}
+
+// SAFETY: the destination's mapped pointer is cleared on submit; uploads
+// only use the buffer handle and offset, which are plain Vulkan handles
+unsafe impl Send for PendingUpload {}
+
+/// Final result of one transfer
+#[derive(Debug)]
+pub struct TransferOutcome {
+    pub id: TransferId,
+    pub priority: TransferPriority,
+    pub result: TransferResult<()>,
+}
+
+/// What one scheduling step did
+#[derive(Debug)]
+pub enum Step {
+    /// Nothing was queued
+    Idle,
+    /// One chunk of a background transfer was uploaded
+    Progress(TransferId),
+    /// A transfer completed or failed
+    Finished(TransferOutcome),
+}
+
+#[derive(Default)]
+struct Queues {
+    critical: VecDeque<PendingUpload>,
+    background: VecDeque<PendingUpload>,
+    next_id: TransferId,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Counters {
+    chunks: u64,
+    bytes: u64,
+}
+
+/// Two-level upload scheduler in front of a `TransferExecutor`
+pub struct TransferScheduler<E> {
+    executor: E,
+    sizer: Mutex<ChunkSizer>,
+    counters: Mutex<Counters>,
+    queues: Mutex<Queues>,
+}
+
+impl<E: TransferExecutor> TransferScheduler<E> {
+    /// Create a scheduler that splits background uploads into `chunk_size` bytes
+    pub fn new(executor: E, chunk_size: u64) -> Self {
+        Self::with_policy(executor, ChunkPolicy::fixed(chunk_size))
+    }
+
+    /// Create a scheduler that sizes background chunks from measured throughput
+    pub fn adaptive(executor: E) -> Self {
+        Self::with_policy(executor, ChunkPolicy::default())
+    }
+
+    pub fn with_policy(executor: E, policy: ChunkPolicy) -> Self {
+        Self {
+            executor,
+            sizer: Mutex::new(ChunkSizer::new(policy)),
+            counters: Mutex::new(Counters::default()),
+            queues: Mutex::new(Queues::default()),
+        }
+    }
+
+    pub fn executor(&self) -> &E {
+        &self.executor
+    }
+
+    /// Queue an upload of `data` into `destination`
+    pub fn submit(
+        &self,
+        priority: TransferPriority,
+        data: impl Into<Arc<[u8]>>,
+        destination: &AllocationInfo,
+    ) -> TransferResult<TransferId> {
+        let data = data.into();
+        if data.len() as u64 > destination.size {
+            return Err(TransferError::InvalidSize(format!(
+                "upload of {} bytes into {} byte allocation {}",
+                data.len(),
+                destination.size,
+                destination.handle_id
+            )));
+        }
+
+        let mut queues = self.queues.lock();
+        queues.next_id += 1;
+        let id = queues.next_id;
+        let upload = PendingUpload {
+            id,
+            priority,
+            data,
+            destination: AllocationInfo {
+                mapped_ptr: None,
+                ..destination.clone()
+            },
+            done: 0,
+        };
+        match priority {
+            TransferPriority::Critical => queues.critical.push_back(upload),
+            TransferPriority::Background => queues.background.push_back(upload),
+        }
+        Ok(id)
+    }
+
+    /// Drop a queued transfer; a background transfer may already be partly uploaded
+    pub fn cancel(&self, id: TransferId) -> bool {
+        let mut queues = self.queues.lock();
+        let before = queues.critical.len() + queues.background.len();
+        queues.critical.retain(|u| u.id != id);
+        queues.background.retain(|u| u.id != id);
+        queues.critical.len() + queues.background.len() != before
+    }
+
+    /// Number of queued `(critical, background)` transfers
+    pub fn pending(&self) -> (usize, usize) {
+        let queues = self.queues.lock();
+        (queues.critical.len(), queues.background.len())
+    }
+
+    /// Queue depths, chunk size and throughput
+    pub fn metrics(&self) -> TransferMetrics {
+        let (critical_depth, background_depth, queued_bytes) = {
+            let queues = self.queues.lock();
+            let queued_bytes = queues
+                .critical
+                .iter()
+                .chain(&queues.background)
+                .map(|u| u.data.len() as u64 - u.done)
+                .sum();
+            (queues.critical.len(), queues.background.len(), queued_bytes)
+        };
+        let sizer = self.sizer.lock();
+        let counters = *self.counters.lock();
+        TransferMetrics {
+            critical_depth,
+            background_depth,
+            queued_bytes,
+            chunk_size: sizer.chunk_size(),
+            throughput: sizer.throughput(),
+            chunks_uploaded: counters.chunks,
+            bytes_uploaded: counters.bytes,
+        }
+    }
+
+    /// Upload one critical transfer, or else one background chunk
+    ///
+    /// The queue lock is not held during the copy, so critical transfers
+    /// submitted meanwhile are picked up by the very next step.
+    ///
+    /// # Safety Requirements
+    /// - Same as `TransferExecutor::upload` for every queued destination
+    pub unsafe fn step(&self) -> Step {
+        let upload = {
+            let mut queues = self.queues.lock();
+            match queues.critical.pop_front() {
+                Some(upload) => upload,
+                None => match queues.background.pop_front() {
+                    Some(upload) => upload,
+                    None => return Step::Idle,
+                },
+            }
+        };
+        // SAFETY: forwarded from the caller's guarantees
+        unsafe { self.advance(upload) }
+    }
+
+    /// Run every queued critical transfer, leaving background work queued
+    ///
+    /// # Safety Requirements
+    /// - Same as `step`
+    pub unsafe fn flush_critical(&self) -> Vec<TransferOutcome> {
+        let mut outcomes = Vec::new();
+        loop {
+            let Some(upload) = self.queues.lock().critical.pop_front() else {
+                return outcomes;
+            };
+            // SAFETY: forwarded from the caller's guarantees
+            if let Step::Finished(outcome) = unsafe { self.advance(upload) } {
+                outcomes.push(outcome);
+            }
+        }
+    }
+
+    /// Step until both queues are empty
+    ///
+    /// # Safety Requirements
+    /// - Same as `step`
+    pub unsafe fn run_until_idle(&self) -> Vec<TransferOutcome> {
+        let mut outcomes = Vec::new();
+        loop {
+            // SAFETY: forwarded from the caller's guarantees
+            match unsafe { self.step() } {
+                Step::Idle => return outcomes,
+                Step::Progress(_) => {}
+                Step::Finished(outcome) => outcomes.push(outcome),
+            }
+        }
+    }
+
+    /// Upload the next piece of `upload` and requeue whatever is left
+    unsafe fn advance(&self, mut upload: PendingUpload) -> Step {
+        let total = upload.data.len() as u64;
+        let len = match upload.priority {
+            TransferPriority::Critical => total,
+            TransferPriority::Background => self.sizer.lock().chunk_size().min(total - upload.done),
+        };
+        let start = upload.done as usize;
+        let chunk = &upload.data[start..start + len as usize];
+
+        let started = Instant::now();
+        let result = upload
+            .destination
+            .view(upload.done, len)
+            .map_err(|e| TransferError::InvalidSize(e.to_string()))
+            // SAFETY: forwarded from the caller's guarantees; the view lies
+            // within the destination
+            .and_then(|view| unsafe { self.executor.upload(chunk, &view) });
+        if result.is_ok() {
+            if upload.priority == TransferPriority::Background {
+                self.sizer.lock().record(len, started.elapsed(), ui_responsive());
+            }
+            let mut counters = self.counters.lock();
+            counters.chunks += 1;
+            counters.bytes += len;
+        }
+
+        upload.done += len;
+        if result.is_ok() && upload.done < total {
+            let id = upload.id;
+            // Resume before any background work queued behind it
+            self.queues.lock().background.push_front(upload);
+            return Step::Progress(id);
+        }
+        Step::Finished(TransferOutcome {
+            id: upload.id,
+            priority: upload.priority,
+            result,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Liveness;
+    use ash::vk;
+
+    /// Records `(first byte, len)` of every upload
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<(u8, usize)>>);
+
+    impl TransferExecutor for Recorder {
+        unsafe fn upload(&self, data: &[u8], _destination: &AllocationInfo) -> TransferResult<()> {
+            self.0.lock().push((data[0], data.len()));
+            Ok(())
+        }
+    }
+
+    fn allocation(size: u64) -> AllocationInfo {
+        AllocationInfo {
+            handle_id: "test".to_string(),
+            size,
+            capacity: size,
+            device_memory: vk::DeviceMemory::null(),
+            buffer: vk::Buffer::null(),
+            offset: 0,
+            mapped_ptr: None,
+            memory_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            generation: 1,
+            liveness: Arc::new(Liveness::new(1)),
+        }
+    }
+
+    #[test]
+    fn test_critical_preempts_background_chunks() {
+        let scheduler = TransferScheduler::new(Recorder::default(), 4);
+        let weights: Vec<u8> = (0..10).collect();
+        let prefetch = scheduler
+            .submit(TransferPriority::Background, weights, &allocation(10))
+            .unwrap();
+
+        // SAFETY: the recorder performs no Vulkan calls
+        unsafe {
+            assert!(matches!(scheduler.step(), Step::Progress(id) if id == prefetch));
+            scheduler
+                .submit(TransferPriority::Critical, vec![100u8; 6], &allocation(6))
+                .unwrap();
+            let outcomes = scheduler.run_until_idle();
+            assert_eq!(outcomes.len(), 2);
+            assert_eq!(outcomes[0].priority, TransferPriority::Critical);
+            assert_eq!(outcomes[1].id, prefetch);
+        }
+        assert_eq!(*scheduler.executor().0.lock(), vec![(0, 4), (100, 6), (4, 4), (8, 2)]);
+        let metrics = scheduler.metrics();
+        assert_eq!((metrics.chunks_uploaded, metrics.bytes_uploaded, metrics.queued_bytes), (4, 16, 0));
+    }
+
+    #[test]
+    fn test_chunk_size_follows_throughput() {
+        let mut sizer = ChunkSizer::new(ChunkPolicy::default());
+        assert_eq!(sizer.chunk_size(), DEFAULT_CHUNK_SIZE);
+
+        // ~1.17 GB/s: 4 ms worth, rounded down to the granularity
+        sizer.record(1 << 20, Duration::from_micros(900), false);
+        assert_eq!(sizer.chunk_size(), 71 * CHUNK_GRANULARITY);
+
+        // Responsive UI: 1 ms worth
+        sizer.record(71 * CHUNK_GRANULARITY, Duration::from_millis(4), true);
+        assert_eq!(sizer.chunk_size(), 17 * CHUNK_GRANULARITY);
+
+        // Slow link: clamped to the minimum
+        let mut slow = ChunkSizer::new(ChunkPolicy::default());
+        slow.record(1 << 20, Duration::from_secs(1), false);
+        assert_eq!(slow.chunk_size(), ChunkPolicy::default().min_chunk);
+
+        let mut fixed = ChunkSizer::new(ChunkPolicy::fixed(4096));
+        fixed.record(1 << 20, Duration::from_micros(10), false);
+        assert_eq!((fixed.chunk_size(), fixed.throughput()), (4096, None));
+    }
+
+    #[test]
+    fn test_submit_rejects_oversized_upload() {
+        let scheduler = TransferScheduler::new(Recorder::default(), DEFAULT_CHUNK_SIZE);
+        assert!(scheduler
+            .submit(TransferPriority::Critical, vec![0u8; 8], &allocation(4))
+            .is_err());
+        let id = scheduler
+            .submit(TransferPriority::Background, vec![0u8; 4], &allocation(4))
+            .unwrap();
+        assert!(scheduler.cancel(id));
+        assert_eq!(scheduler.pending(), (0, 0));
+    }
+}
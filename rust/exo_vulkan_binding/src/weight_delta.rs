@@ -0,0 +1,288 @@
+//! In-place weight updates from fine-tuning deltas
+//!
+//! Personalization updates (a LoRA merged on a server, a few steps of
+//! on-device fine-tuning elsewhere) arrive as an f32 delta per weight
+//! tensor, far smaller to download than the model. `WeightTable` names the
+//! resident weight tensors; applying a delta to one runs
+//! `w += scale * delta` over its allocation with the `axpy` kernels, so
+//! the model is updated without being reloaded. int8 weights are
+//! dequantized, updated and requantized in the same pass with their
+//! existing per-row params, so a delta cannot widen a row's range: see
+//! `KernelRegistry::axpy_int8`.
+//!
+//! A negative `scale` reverts a delta applied with the positive one, up to
+//! rounding and, for int8 weights, saturation.
+
+use std::collections::BTreeMap;
+
+use ash::vk;
+use thiserror::Error;
+
+use crate::calibration::QuantParams;
+use crate::kernel_library::{KernelRegistry, LibraryError, axpy_host, axpy_int8_host};
+use crate::memory::BufferRange;
+use crate::tensor::ELEMENT_SIZE;
+
+/// Weight delta errors
+#[derive(Error, Debug)]
+pub enum DeltaError {
+    #[error("Weight tensor not found: {0}")]
+    NotFound(String),
+
+    #[error("Delta for {name} has {actual} elements, expected {expected}")]
+    LengthMismatch { name: String, expected: usize, actual: usize },
+
+    #[error("Invalid weight tensor {name}: {reason}")]
+    InvalidTensor { name: String, reason: String },
+
+    #[error("Delta scale must be finite, got {0}")]
+    InvalidScale(f32),
+
+    #[error(transparent)]
+    Library(#[from] LibraryError),
+}
+
+pub type DeltaResult<T> = Result<T, DeltaError>;
+
+/// How a weight tensor's elements are stored
+#[derive(Clone, Debug, PartialEq)]
+pub enum WeightEncoding {
+    F32,
+    /// int8 packed four to a word, with one set of params per row
+    Int8PerRow(Vec<QuantParams>),
+}
+
+/// A resident weight tensor deltas can be applied to
+#[derive(Clone, Debug, PartialEq)]
+pub struct WeightTensor {
+    /// Allocation holding the elements
+    pub handle: String,
+    pub rows: usize,
+    pub cols: usize,
+    pub encoding: WeightEncoding,
+}
+
+impl WeightTensor {
+    pub fn len(&self) -> usize {
+        self.rows * self.cols
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Bytes the elements occupy on device
+    pub fn bytes(&self) -> u64 {
+        match self.encoding {
+            WeightEncoding::F32 => self.len() as u64 * ELEMENT_SIZE,
+            WeightEncoding::Int8PerRow(_) => (self.len() as u64).div_ceil(4) * 4,
+        }
+    }
+
+    /// Check the tensor is consistent with its encoding
+    fn validate(&self, name: &str) -> DeltaResult<()> {
+        let invalid = |reason: String| DeltaError::InvalidTensor {
+            name: name.to_string(),
+            reason,
+        };
+        if self.rows.checked_mul(self.cols).is_none_or(|len| u32::try_from(len).is_err()) {
+            return Err(invalid(format!("{}x{} exceeds u32 elements", self.rows, self.cols)));
+        }
+        if let WeightEncoding::Int8PerRow(params) = &self.encoding {
+            if params.len() != self.rows {
+                return Err(invalid(format!("{} rows but {} quantization params", self.rows, params.len())));
+            }
+            if let Some(p) = params.iter().find(|p| !(p.scale.is_finite() && p.scale > 0.0)) {
+                return Err(invalid(format!("quantization scale {} is not positive", p.scale)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check a delta of `len` elements at `scale` can be applied
+    pub fn check_delta(&self, name: &str, len: usize, scale: f32) -> DeltaResult<()> {
+        if !scale.is_finite() {
+            return Err(DeltaError::InvalidScale(scale));
+        }
+        if len != self.len() {
+            return Err(DeltaError::LengthMismatch {
+                name: name.to_string(),
+                expected: self.len(),
+                actual: len,
+            });
+        }
+        Ok(())
+    }
+
+    /// Record `w += scale * delta` over the tensor
+    ///
+    /// `params` must hold the tensor's params as `activation_codec::params_words`
+    /// for int8 weights and is ignored for f32 ones.
+    ///
+    /// # Safety Requirements
+    /// - As for `KernelRegistry::elementwise`
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn record_apply(
+        &self,
+        kernels: &mut KernelRegistry,
+        cmd: vk::CommandBuffer,
+        weights: &BufferRange,
+        params: Option<&BufferRange>,
+        delta: &BufferRange,
+        scale: f32,
+    ) -> DeltaResult<()> {
+        if !scale.is_finite() {
+            return Err(DeltaError::InvalidScale(scale));
+        }
+        // Lengths were checked to fit u32 on registration
+        match &self.encoding {
+            // SAFETY: forwarded from the caller's guarantees
+            WeightEncoding::F32 => unsafe { kernels.axpy(cmd, weights, delta, self.len() as u32, scale) }?,
+            WeightEncoding::Int8PerRow(_) => {
+                let params = params.ok_or_else(|| DeltaError::InvalidTensor {
+                    name: self.handle.clone(),
+                    reason: "int8 weights need their params buffer".to_string(),
+                })?;
+                // SAFETY: forwarded from the caller's guarantees
+                unsafe {
+                    kernels.axpy_int8(cmd, weights, params, delta, self.rows as u32, self.cols as u32, scale)
+                }?
+            }
+        }
+        Ok(())
+    }
+
+    /// Host reference for `record_apply` over the tensor's bytes as stored on device
+    pub fn apply_host(&self, bytes: &mut [u8], delta: &[f32], scale: f32) {
+        match &self.encoding {
+            WeightEncoding::F32 => {
+                let mut w: Vec<f32> = bytes
+                    .chunks_exact(4)
+                    .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                    .collect();
+                axpy_host(&mut w, delta, scale);
+                for (b, w) in bytes.chunks_exact_mut(4).zip(w) {
+                    b.copy_from_slice(&w.to_le_bytes());
+                }
+            }
+            WeightEncoding::Int8PerRow(params) => {
+                let len = self.len().min(bytes.len());
+                let mut w: Vec<i8> = bytes[..len].iter().map(|&b| b as i8).collect();
+                axpy_int8_host(&mut w, params, delta, self.cols, scale);
+                for (b, w) in bytes.iter_mut().zip(w) {
+                    *b = w as u8;
+                }
+            }
+        }
+    }
+}
+
+/// Weight tensors by name, and the deltas applied to each
+#[derive(Debug, Default)]
+pub struct WeightTable {
+    tensors: BTreeMap<String, WeightTensor>,
+    applied: BTreeMap<String, u64>,
+}
+
+impl WeightTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Name `tensor`, replacing any tensor of that name
+    pub fn register(&mut self, name: &str, tensor: WeightTensor) -> DeltaResult<()> {
+        tensor.validate(name)?;
+        self.tensors.insert(name.to_string(), tensor);
+        self.applied.remove(name);
+        Ok(())
+    }
+
+    pub fn unregister(&mut self, name: &str) -> Option<WeightTensor> {
+        self.applied.remove(name);
+        self.tensors.remove(name)
+    }
+
+    /// Forget every tensor stored in `handle`, e.g. once it is freed
+    pub fn unregister_handle(&mut self, handle: &str) {
+        let names: Vec<String> = self
+            .tensors
+            .iter()
+            .filter(|(_, tensor)| tensor.handle == handle)
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in names {
+            self.unregister(&name);
+        }
+    }
+
+    pub fn get(&self, name: &str) -> DeltaResult<&WeightTensor> {
+        self.tensors.get(name).ok_or_else(|| DeltaError::NotFound(name.to_string()))
+    }
+
+    /// Count a delta applied to `name`
+    pub fn record_applied(&mut self, name: &str) {
+        if self.tensors.contains_key(name) {
+            *self.applied.entry(name.to_string()).or_insert(0) += 1;
+        }
+    }
+
+    /// Deltas applied to `name` since it was registered
+    pub fn applied(&self, name: &str) -> u64 {
+        self.applied.get(name).copied().unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calibration::QuantScheme;
+
+    #[test]
+    fn test_apply_and_revert_f32() {
+        let tensor = WeightTensor {
+            handle: "w".to_string(),
+            rows: 1,
+            cols: 3,
+            encoding: WeightEncoding::F32,
+        };
+        let mut bytes: Vec<u8> = [1.0f32, -2.0, 0.5].iter().flat_map(|v| v.to_le_bytes()).collect();
+        let original = bytes.clone();
+        let delta = [0.5, 1.0, -0.25];
+        tensor.apply_host(&mut bytes, &delta, 2.0);
+        let updated: Vec<f32> = bytes.chunks_exact(4).map(|b| f32::from_le_bytes(b.try_into().unwrap())).collect();
+        assert_eq!(updated, vec![2.0, 0.0, 0.0]);
+        tensor.apply_host(&mut bytes, &delta, -2.0);
+        assert_eq!(bytes, original);
+
+        assert!(matches!(tensor.check_delta("w", 2, 1.0), Err(DeltaError::LengthMismatch { .. })));
+        assert!(matches!(tensor.check_delta("w", 3, f32::NAN), Err(DeltaError::InvalidScale(_))));
+    }
+
+    #[test]
+    fn test_int8_requantizes_per_row() {
+        let params = vec![
+            QuantParams::from_range(-1.27, 1.27, QuantScheme::Symmetric),
+            QuantParams::from_range(-12.7, 12.7, QuantScheme::Symmetric),
+        ];
+        let tensor = WeightTensor {
+            handle: "w".to_string(),
+            rows: 2,
+            cols: 2,
+            encoding: WeightEncoding::Int8PerRow(params.clone()),
+        };
+        // Row 0 steps by 0.01, row 1 by 0.1
+        let mut bytes = vec![10i8 as u8, 120, 10, (-5i8) as u8];
+        tensor.apply_host(&mut bytes, &[0.05, 0.1, 0.07, 1.0], 1.0);
+        assert_eq!(bytes.iter().map(|&b| b as i8).collect::<Vec<_>>(), vec![15, 127, 11, 5]);
+
+        let mut table = WeightTable::new();
+        let mut bad = tensor.clone();
+        bad.encoding = WeightEncoding::Int8PerRow(params[..1].to_vec());
+        assert!(matches!(table.register("w", bad), Err(DeltaError::InvalidTensor { .. })));
+        table.register("w", tensor).unwrap();
+        table.record_applied("w");
+        assert_eq!(table.applied("w"), 1);
+        table.unregister_handle("w");
+        assert!(matches!(table.get("w"), Err(DeltaError::NotFound(_))));
+    }
+}
@@ -0,0 +1,150 @@
+"""Desktop client for the on-device remote kernel shim.
+
+Talks to the development server an Android debug build starts with
+``VulkanGpu.startRemoteShim(7878)`` (native ``remote-debug`` feature), so
+kernels can be iterated on from a workstation against the phone's GPU::
+
+    adb forward tcp:7878 tcp:7878
+    python -m exo.gpu.remote_shim enumerate
+    python -m exo.gpu.remote_shim run scale.spv --metadata scale.json \\
+        --input x.bin --output-size 4096 --groups 16 1 1 --out y.bin
+
+The protocol is one JSON object per line; see ``remote.rs`` in
+``exo_vulkan_binding`` for the commands.
+"""
+
+import argparse
+import json
+import socket
+import sys
+from pathlib import Path
+from typing import Any, Optional
+
+DEFAULT_PORT = 7878
+
+
+class RemoteShimError(RuntimeError):
+    """A command the device rejected."""
+
+
+class RemoteShim:
+    """One connection to the on-device shim; allocations live as long as it."""
+
+    def __init__(self, host: str = "127.0.0.1", port: int = DEFAULT_PORT, timeout: float = 30.0):
+        self._sock = socket.create_connection((host, port), timeout=timeout)
+        self._reader = self._sock.makefile("r", encoding="utf-8")
+        self._next_id = 0
+
+    def close(self) -> None:
+        self._reader.close()
+        self._sock.close()
+
+    def __enter__(self) -> "RemoteShim":
+        return self
+
+    def __exit__(self, *exc: object) -> None:
+        self.close()
+
+    def call(self, cmd: str, **fields: Any) -> Any:
+        """Send one command and return its result, raising RemoteShimError on failure."""
+        self._next_id += 1
+        request = {"id": self._next_id, "cmd": cmd, **fields}
+        self._sock.sendall((json.dumps(request) + "\n").encode("utf-8"))
+        line = self._reader.readline()
+        if not line:
+            raise ConnectionError("Remote shim closed the connection")
+        reply = json.loads(line)
+        if not reply.get("ok"):
+            raise RemoteShimError(reply.get("error", "unknown error"))
+        return reply.get("result")
+
+    def enumerate(self) -> list:
+        return self.call("enumerate")
+
+    def open(self, device: int) -> dict:
+        return self.call("open", device=device)
+
+    def alloc(self, size: int) -> str:
+        return self.call("alloc", size=size)["handle"]
+
+    def free(self, handle: str) -> None:
+        self.call("free", handle=handle)
+
+    def write(self, handle: str, data: bytes) -> None:
+        self.call("write", handle=handle, data=data.hex())
+
+    def read(self, handle: str, size: Optional[int] = None) -> bytes:
+        return bytes.fromhex(self.call("read", handle=handle, size=size)["data"])
+
+    def register(self, name: str, spirv: bytes, metadata: dict) -> None:
+        self.call("register", name=name, spirv=spirv.hex(), metadata=metadata)
+
+    def dispatch(self, kernel: str, buffers: list[str], groups: tuple[int, int, int], push_constants: bytes = b"") -> int:
+        """Run a kernel once and return the microseconds from submission to completion."""
+        result = self.call(
+            "dispatch", kernel=kernel, buffers=buffers, groups=list(groups), push_constants=push_constants.hex()
+        )
+        return result["elapsed_us"]
+
+
+def _run(shim: RemoteShim, args: argparse.Namespace) -> None:
+    spirv_path = Path(args.spirv)
+    kernel = args.name or spirv_path.stem
+    metadata = json.loads(Path(args.metadata).read_text()) if args.metadata else {"bindings": []}
+    shim.register(kernel, spirv_path.read_bytes(), metadata)
+
+    # Inputs bind first and the output last, as with VulkanGpu.dispatchKernel
+    handles = []
+    for path in args.input:
+        data = Path(path).read_bytes()
+        handle = shim.alloc(len(data))
+        shim.write(handle, data)
+        handles.append(handle)
+    output = shim.alloc(args.output_size)
+    handles.append(output)
+
+    push = bytes.fromhex(args.push) if args.push else b""
+    timings = [shim.dispatch(kernel, handles, tuple(args.groups), push) for _ in range(max(args.repeat, 1))]
+    print(f"{kernel}: best {min(timings)} us, median {sorted(timings)[len(timings) // 2]} us over {len(timings)} runs")
+
+    result = shim.read(output)
+    if args.out:
+        Path(args.out).write_bytes(result)
+    else:
+        print(result[:64].hex())
+
+
+def main(argv: Optional[list[str]] = None) -> int:
+    parser = argparse.ArgumentParser(prog="exo.gpu.remote_shim", description=__doc__.splitlines()[0])
+    parser.add_argument("--host", default="127.0.0.1")
+    parser.add_argument("--port", type=int, default=DEFAULT_PORT)
+    parser.add_argument("--device", type=int, default=0, help="Device index to run on")
+    commands = parser.add_subparsers(dest="command", required=True)
+    commands.add_parser("enumerate", help="List the device's GPUs")
+    run = commands.add_parser("run", help="Register a SPIR-V kernel, run it and read back its output")
+    run.add_argument("spirv", help="Compiled SPIR-V module")
+    run.add_argument("--name", help="Kernel name (default: file stem)")
+    run.add_argument("--metadata", help='Kernel metadata JSON, e.g. {"bindings": [0, 1]}')
+    run.add_argument("--input", action="append", default=[], help="Input buffer file; repeat in binding order")
+    run.add_argument("--output-size", type=int, required=True, help="Output buffer bytes")
+    run.add_argument("--groups", type=int, nargs=3, default=[1, 1, 1], metavar=("X", "Y", "Z"))
+    run.add_argument("--push", help="Push constant block as hex")
+    run.add_argument("--repeat", type=int, default=1, help="Dispatches to time")
+    run.add_argument("--out", help="Write the output buffer here instead of printing its start")
+    args = parser.parse_args(argv)
+
+    try:
+        with RemoteShim(args.host, args.port) as shim:
+            if args.command == "enumerate":
+                print(json.dumps(shim.enumerate(), indent=2))
+            else:
+                shim.open(args.device)
+                _run(shim, args)
+    except (OSError, RemoteShimError) as e:
+        print(f"remote_shim: {e}", file=sys.stderr)
+        return 1
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(main())